@@ -0,0 +1,49 @@
+//! Compares parsing files with loading them from a cache.
+//!
+//! Run with `cargo bench --features cache`.
+use asefile::{cache, AsepriteFile};
+use std::time::{Duration, Instant};
+
+const FILES: &[&str] = &[
+    "big",
+    "layers_and_tags",
+    "linked_cels",
+    "tilemap",
+    "palette",
+];
+const ITERATIONS: u32 = 200;
+
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    for name in FILES {
+        let path = format!("./tests/data/{}.aseprite", name);
+        let source = std::fs::read(&path).unwrap();
+        let fingerprint = cache::fingerprint(&source);
+        let mut data = Vec::new();
+        AsepriteFile::read(&source[..])
+            .unwrap()
+            .to_cache(fingerprint, &mut data)
+            .unwrap();
+
+        let parse = time(|| {
+            AsepriteFile::read(&source[..]).unwrap();
+        });
+        let from_cache = time(|| {
+            AsepriteFile::from_cache(&data[..], fingerprint).unwrap();
+        });
+        println!(
+            "{:<16} parse {:>10.1?}  cache {:>10.1?}  speedup {:.1}x",
+            name,
+            parse,
+            from_cache,
+            parse.as_secs_f64() / from_cache.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,24 @@
+//
+// Plays an Aseprite file as an animation in the terminal.
+//
+// Usage: cargo run --example preview -- [FILE] [MAX_WIDTH]
+//
+use std::{env, io, path::Path};
+
+use asefile::{ansi::AnsiOptions, AsepriteFile};
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let file = args
+        .next()
+        .unwrap_or_else(|| "examples/basic/input.aseprite".to_owned());
+    let max_width = args
+        .next()
+        .map_or(80, |w| w.parse().expect("invalid width"));
+    let ase = AsepriteFile::read_file(Path::new(&file)).unwrap();
+    let options = AnsiOptions::new().max_width(max_width);
+    let stdout = io::stdout();
+    loop {
+        asefile::ansi::play(&ase, &options, stdout.lock()).unwrap();
+    }
+}
@@ -0,0 +1,56 @@
+/* C interface of asefile, built with the `capi` feature. See the
+ * documentation of the `asefile::capi` module for details. */
+#ifndef ASEFILE_H
+#define ASEFILE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct AseFile AseFile;
+
+typedef struct AseTag {
+    const char *name;
+    uint32_t from_frame;
+    uint32_t to_frame;
+    /* 0 for forward, 1 for reverse and 2 for ping-pong playback. The
+     * ping-pong reverse direction of newer Aseprite versions is not
+     * supported, files that use it fail to open. */
+    uint32_t direction;
+    /* How often the animation plays, 0 means forever. */
+    uint32_t repeat;
+} AseTag;
+
+typedef struct AseSlice {
+    const char *name;
+    int32_t x;
+    int32_t y;
+    uint32_t width;
+    uint32_t height;
+} AseSlice;
+
+AseFile *asefile_open(const char *path);
+AseFile *asefile_read(const uint8_t *data, size_t len);
+void asefile_free(AseFile *file);
+const char *asefile_last_error(void);
+
+uint32_t asefile_width(const AseFile *file);
+uint32_t asefile_height(const AseFile *file);
+uint32_t asefile_num_frames(const AseFile *file);
+uint32_t asefile_frame_duration(const AseFile *file, uint32_t frame);
+int asefile_frame_rgba(const AseFile *file, uint32_t frame, uint8_t *out, size_t len);
+
+uint32_t asefile_num_tags(const AseFile *file);
+int asefile_tag(const AseFile *file, uint32_t tag_id, AseTag *out);
+
+uint32_t asefile_num_slices(const AseFile *file);
+int asefile_slice(const AseFile *file, uint32_t slice_id, uint32_t frame, AseSlice *out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
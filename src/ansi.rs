@@ -0,0 +1,196 @@
+/*!
+Preview frames in a terminal.
+
+[Frame::render_ansi](crate::Frame::render_ansi) draws a frame with the upper
+half block character (`▀`), using 24-bit ANSI colors for the foreground (the
+upper pixel) and the background (the lower pixel). Each character cell thus
+shows two pixels on top of each other. Most terminal emulators support these
+colors.
+
+```
+# use asefile::AsepriteFile;
+# use asefile::ansi::AnsiOptions;
+# use std::path::Path;
+# let path = Path::new("./tests/data/basic-16x16.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let art = ase.frame(0).render_ansi(&AnsiOptions::new().max_width(40));
+// print!("{}", art);
+```
+
+[play] shows all frames of a sprite as an animation.
+*/
+use crate::image::{Rgba, RgbaImage};
+use crate::AsepriteFile;
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    thread,
+    time::Duration,
+};
+
+const UPPER_HALF_BLOCK: char = '▀';
+const RESET: &str = "\x1b[0m";
+const DEFAULT_BACKGROUND: &str = "\x1b[49m";
+const CLEAR_SCREEN: &str = "\x1b[2J";
+const CURSOR_HOME: &str = "\x1b[H";
+
+// Colors and size (in output pixels) of the squares of the checkerboard.
+const CHECKER_LIGHT: [u8; 3] = [204, 204, 204];
+const CHECKER_DARK: [u8; 3] = [153, 153, 153];
+const CHECKER_SIZE: u32 = 2;
+
+/// What to show behind transparent pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transparency {
+    /// A light and dark gray checkerboard, like in Aseprite.
+    Checkerboard,
+    /// A single RGB color.
+    Solid([u8; 3]),
+}
+
+/// Options for [Frame::render_ansi](crate::Frame::render_ansi) and [play].
+///
+/// ```
+/// # use asefile::ansi::{AnsiOptions, Transparency};
+/// let options = AnsiOptions::new()
+///     .max_width(80)
+///     .transparency(Transparency::Solid([0, 0, 0]));
+/// ```
+#[derive(Debug, Clone)]
+pub struct AnsiOptions {
+    max_width: Option<u32>,
+    transparency: Transparency,
+}
+
+impl Default for AnsiOptions {
+    fn default() -> Self {
+        Self {
+            max_width: None,
+            transparency: Transparency::Checkerboard,
+        }
+    }
+}
+
+impl AnsiOptions {
+    /// Default options: One character per pixel horizontally and a
+    /// checkerboard behind transparent pixels.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum width of the output in characters. Wider frames are scaled
+    /// down (using nearest-neighbor sampling), keeping their aspect ratio.
+    /// Values less than 1 are treated as 1.
+    pub fn max_width(mut self, columns: u32) -> Self {
+        self.max_width = Some(columns.max(1));
+        self
+    }
+
+    /// What to show behind transparent and semi-transparent pixels.
+    pub fn transparency(mut self, transparency: Transparency) -> Self {
+        self.transparency = transparency;
+        self
+    }
+}
+
+// Renders an image: one line per two rows of pixels, each terminated by a
+// reset of the colors and a newline.
+pub(crate) fn render(image: &RgbaImage, options: &AnsiOptions) -> String {
+    let image = match options.max_width {
+        Some(max_width) if image.width() > max_width => scale_down(image, max_width),
+        _ => image.clone(),
+    };
+    let (width, height) = image.dimensions();
+    let mut out = String::new();
+    for y in (0..height).step_by(2) {
+        let mut current = (None, None);
+        for x in 0..width {
+            let top = fill(*image.get_pixel(x, y), x, y, options.transparency);
+            let bottom = if y + 1 < height {
+                Some(fill(
+                    *image.get_pixel(x, y + 1),
+                    x,
+                    y + 1,
+                    options.transparency,
+                ))
+            } else {
+                None
+            };
+            if current.0 != Some(top) {
+                write!(out, "\x1b[38;2;{};{};{}m", top[0], top[1], top[2]).unwrap();
+            }
+            if current.1 != Some(bottom) {
+                match bottom {
+                    Some(c) => write!(out, "\x1b[48;2;{};{};{}m", c[0], c[1], c[2]).unwrap(),
+                    // The last row of an image with an odd height.
+                    None => out.push_str(DEFAULT_BACKGROUND),
+                }
+            }
+            current = (Some(top), Some(bottom));
+            out.push(UPPER_HALF_BLOCK);
+        }
+        out.push_str(RESET);
+        out.push('\n');
+    }
+    out
+}
+
+fn scale_down(image: &RgbaImage, width: u32) -> RgbaImage {
+    let (src_width, src_height) = image.dimensions();
+    let height = ((src_height as u64 * width as u64 + src_width as u64 / 2) / src_width as u64)
+        .max(1) as u32;
+    RgbaImage::from_fn(width, height, |x, y| {
+        let src_x = (x as u64 * src_width as u64 / width as u64) as u32;
+        let src_y = ((y as u64 * src_height as u64 / height as u64) as u32).min(src_height - 1);
+        *image.get_pixel(src_x, src_y)
+    })
+}
+
+// The color of a pixel composited onto the background.
+fn fill(pixel: Rgba<u8>, x: u32, y: u32, transparency: Transparency) -> [u8; 3] {
+    let [r, g, b, a] = pixel.0;
+    if a == 255 {
+        return [r, g, b];
+    }
+    let background = match transparency {
+        Transparency::Solid(color) => color,
+        Transparency::Checkerboard if ((x / CHECKER_SIZE) ^ (y / CHECKER_SIZE)) & 1 == 0 => {
+            CHECKER_LIGHT
+        }
+        Transparency::Checkerboard => CHECKER_DARK,
+    };
+    let mix =
+        |fg: u8, bg: u8| ((fg as u32 * a as u32 + bg as u32 * (255 - a as u32) + 127) / 255) as u8;
+    [
+        mix(r, background[0]),
+        mix(g, background[1]),
+        mix(b, background[2]),
+    ]
+}
+
+/// Play all frames of the sprite once as an animation in the terminal.
+///
+/// Clears the screen, then draws each frame at the top left corner and
+/// waits for the frame's duration before drawing the next one. All frames
+/// are rendered before playback starts, so the timing is not disturbed by
+/// compositing.
+///
+/// # Errors
+///
+/// Returns any error of the underlying writer.
+pub fn play<W: Write>(file: &AsepriteFile, options: &AnsiOptions, mut w: W) -> io::Result<()> {
+    let frames: Vec<(String, u32)> = (0..file.num_frames())
+        .map(|index| {
+            let frame = file.frame(index);
+            (frame.render_ansi(options), frame.duration())
+        })
+        .collect();
+    w.write_all(CLEAR_SCREEN.as_bytes())?;
+    for (art, duration) in frames {
+        w.write_all(CURSOR_HOME.as_bytes())?;
+        w.write_all(art.as_bytes())?;
+        w.flush()?;
+        thread::sleep(Duration::from_millis(duration as u64));
+    }
+    Ok(())
+}
@@ -0,0 +1,339 @@
+/*!
+Random, structurally valid sprites for fuzzing and property tests.
+
+Requires the `arbitrary` feature. [ArbitrarySprite] implements
+[arbitrary::Arbitrary] and is a deliberately simple description of a sprite:
+a small canvas, a handful of layers and frames, cels with random pixels, and
+optional tags, slices, and palette. All values are kept within bounds that the
+[AsepriteFileBuilder] accepts, so [ArbitrarySprite::build] cannot fail.
+
+Because the representation is made of plain vectors and numbers, fuzzers can
+shrink failing inputs effectively.
+
+```
+# use arbitrary::{Arbitrary, Unstructured};
+# use asefile::arbitrary::ArbitrarySprite;
+let bytes = [7u8; 256];
+let mut u = Unstructured::new(&bytes);
+let sprite = ArbitrarySprite::arbitrary(&mut u).unwrap();
+let ase = sprite.build();
+assert_eq!(ase.num_frames() as usize, sprite.frames.len());
+```
+*/
+use crate::image::RgbaImage;
+use crate::{
+    AnimationDirection, AsepriteFile, AsepriteFileBuilder, BlendMode, Slice, SliceKey, SliceOrigin,
+    SliceSize,
+};
+use ::arbitrary::{Arbitrary, Result, Unstructured};
+
+/// Largest canvas width and height.
+pub const MAX_CANVAS_SIZE: u16 = 64;
+/// Largest number of layers.
+pub const MAX_LAYERS: usize = 4;
+/// Largest number of frames.
+pub const MAX_FRAMES: usize = 4;
+/// Largest number of tags and of slices.
+pub const MAX_TAGS: usize = 3;
+/// Largest number of palette colors.
+pub const MAX_COLORS: usize = 16;
+
+const BLEND_MODES: [BlendMode; 19] = [
+    BlendMode::Normal,
+    BlendMode::Multiply,
+    BlendMode::Screen,
+    BlendMode::Overlay,
+    BlendMode::Darken,
+    BlendMode::Lighten,
+    BlendMode::ColorDodge,
+    BlendMode::ColorBurn,
+    BlendMode::HardLight,
+    BlendMode::SoftLight,
+    BlendMode::Difference,
+    BlendMode::Exclusion,
+    BlendMode::Hue,
+    BlendMode::Saturation,
+    BlendMode::Color,
+    BlendMode::Luminosity,
+    BlendMode::Addition,
+    BlendMode::Subtract,
+    BlendMode::Divide,
+];
+
+const DIRECTIONS: [AnimationDirection; 3] = [
+    AnimationDirection::Forward,
+    AnimationDirection::Reverse,
+    AnimationDirection::PingPong,
+];
+
+/// A random RGBA sprite. See the [module documentation](self).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArbitrarySprite {
+    /// Canvas width, in `1..=MAX_CANVAS_SIZE`.
+    pub width: u16,
+    /// Canvas height, in `1..=MAX_CANVAS_SIZE`.
+    pub height: u16,
+    /// Between 0 and [MAX_LAYERS] layers, bottom to top.
+    pub layers: Vec<ArbitraryLayer>,
+    /// Between 1 and [MAX_FRAMES] frames.
+    pub frames: Vec<ArbitraryFrame>,
+    /// Tags with valid frame ranges.
+    pub tags: Vec<ArbitraryTag>,
+    /// Slices with a single key on frame 0.
+    pub slices: Vec<ArbitrarySlice>,
+    /// Optional palette colors.
+    pub palette: Option<Vec<[u8; 4]>>,
+}
+
+/// A layer of an [ArbitrarySprite].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArbitraryLayer {
+    /// Layer name.
+    pub name: String,
+    /// Blend mode.
+    pub blend_mode: BlendMode,
+    /// Layer opacity.
+    pub opacity: u8,
+    /// Whether the layer is visible.
+    pub visible: bool,
+}
+
+/// A frame of an [ArbitrarySprite].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArbitraryFrame {
+    /// Frame duration in milliseconds.
+    pub duration: u16,
+    /// One entry per layer.
+    pub cels: Vec<Option<ArbitraryCel>>,
+}
+
+/// A cel of an [ArbitrarySprite]. The image may extend beyond the canvas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArbitraryCel {
+    /// Horizontal position of the top left corner.
+    pub x: i16,
+    /// Vertical position of the top left corner.
+    pub y: i16,
+    /// Image width, in `1..=MAX_CANVAS_SIZE`.
+    pub width: u16,
+    /// Image height, in `1..=MAX_CANVAS_SIZE`.
+    pub height: u16,
+    /// `width * height` pixels in row-major order.
+    pub pixels: Vec<[u8; 4]>,
+}
+
+/// A tag of an [ArbitrarySprite].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArbitraryTag {
+    /// Tag name.
+    pub name: String,
+    /// First frame of the tag.
+    pub from_frame: u16,
+    /// Last frame of the tag. Not smaller than `from_frame`.
+    pub to_frame: u16,
+    /// Animation direction.
+    pub direction: AnimationDirection,
+}
+
+/// A slice of an [ArbitrarySprite].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArbitrarySlice {
+    /// Slice name.
+    pub name: String,
+    /// Horizontal position.
+    pub x: i32,
+    /// Vertical position.
+    pub y: i32,
+    /// Width of the slice.
+    pub width: u32,
+    /// Height of the slice.
+    pub height: u32,
+}
+
+impl ArbitrarySprite {
+    /// Create the sprite described by `self`.
+    pub fn build(&self) -> AsepriteFile {
+        let mut builder = AsepriteFileBuilder::new(self.width, self.height);
+        for layer in &self.layers {
+            let id = builder.add_layer(&layer.name);
+            builder.set_layer_blend_mode(id, layer.blend_mode);
+            builder.set_layer_opacity(id, layer.opacity);
+            builder.set_layer_visible(id, layer.visible);
+        }
+        for frame in &self.frames {
+            let frame_id = builder.add_frame(frame.duration);
+            for (layer_id, cel) in frame.cels.iter().enumerate() {
+                if let Some(cel) = cel {
+                    builder.add_cel(frame_id, layer_id as u32, (cel.x, cel.y), &cel.image());
+                }
+            }
+        }
+        for tag in &self.tags {
+            builder.add_tag(&tag.name, tag.from_frame, tag.to_frame, tag.direction);
+        }
+        for slice in &self.slices {
+            builder.add_slice(slice.to_slice());
+        }
+        if let Some(colors) = &self.palette {
+            builder.set_palette(colors);
+        }
+        builder
+            .build()
+            .expect("ArbitrarySprite is always a valid sprite")
+    }
+
+    /// The sprite described by `self` as the contents of an Aseprite file.
+    /// Useful as a starting point for fuzzing the parser.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.build()
+            .write_to(&mut data)
+            .expect("ArbitrarySprite fits into an Aseprite file");
+        data
+    }
+}
+
+impl ArbitraryCel {
+    /// The cel's pixels as an image.
+    pub fn image(&self) -> RgbaImage {
+        let raw = self.pixels.iter().flatten().copied().collect();
+        RgbaImage::from_raw(self.width as u32, self.height as u32, raw)
+            .expect("pixel count matches cel size")
+    }
+}
+
+impl ArbitrarySlice {
+    fn to_slice(&self) -> Slice {
+        Slice {
+            name: self.name.clone(),
+            keys: vec![SliceKey {
+                from_frame: 0,
+                origin: SliceOrigin {
+                    x: self.x,
+                    y: self.y,
+                },
+                size: SliceSize {
+                    width: self.width,
+                    height: self.height,
+                },
+                slice9: None,
+                pivot: None,
+            }],
+            user_data: None,
+        }
+    }
+}
+
+// Short printable names keep generated sprites readable when debugging.
+fn name(u: &mut Unstructured<'_>) -> Result<String> {
+    let len = u.int_in_range(0..=8)?;
+    (0..len)
+        .map(|_| Ok(char::from(u.int_in_range(b'a'..=b'z')?)))
+        .collect()
+}
+
+fn canvas_size(u: &mut Unstructured<'_>) -> Result<u16> {
+    u.int_in_range(1..=MAX_CANVAS_SIZE)
+}
+
+fn cel(u: &mut Unstructured<'_>, width: u16, height: u16) -> Result<ArbitraryCel> {
+    let w = width as i16;
+    let h = height as i16;
+    let x = u.int_in_range(-w..=w)?;
+    let y = u.int_in_range(-h..=h)?;
+    let width = canvas_size(u)?;
+    let height = canvas_size(u)?;
+    let pixels = (0..width as usize * height as usize)
+        .map(|_| u.arbitrary())
+        .collect::<Result<_>>()?;
+    Ok(ArbitraryCel {
+        x,
+        y,
+        width,
+        height,
+        pixels,
+    })
+}
+
+impl<'a> Arbitrary<'a> for ArbitrarySprite {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let width = canvas_size(u)?;
+        let height = canvas_size(u)?;
+
+        let num_layers = u.int_in_range(0..=MAX_LAYERS)?;
+        let layers = (0..num_layers)
+            .map(|_| {
+                Ok(ArbitraryLayer {
+                    name: name(u)?,
+                    blend_mode: *u.choose(&BLEND_MODES)?,
+                    opacity: u.arbitrary()?,
+                    visible: u.arbitrary()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let num_frames = u.int_in_range(1..=MAX_FRAMES)?;
+        let frames = (0..num_frames)
+            .map(|_| {
+                let duration = u.arbitrary()?;
+                let cels = (0..num_layers)
+                    .map(|_| {
+                        if u.arbitrary()? {
+                            cel(u, width, height).map(Some)
+                        } else {
+                            Ok(None)
+                        }
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(ArbitraryFrame { duration, cels })
+            })
+            .collect::<Result<_>>()?;
+
+        let last_frame = num_frames as u16 - 1;
+        let num_tags = u.int_in_range(0..=MAX_TAGS)?;
+        let tags = (0..num_tags)
+            .map(|_| {
+                let from_frame = u.int_in_range(0..=last_frame)?;
+                Ok(ArbitraryTag {
+                    name: name(u)?,
+                    from_frame,
+                    to_frame: u.int_in_range(from_frame..=last_frame)?,
+                    direction: *u.choose(&DIRECTIONS)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let num_slices = u.int_in_range(0..=MAX_TAGS)?;
+        let slices = (0..num_slices)
+            .map(|_| {
+                Ok(ArbitrarySlice {
+                    name: name(u)?,
+                    x: u.int_in_range(0..=width as i32 - 1)?,
+                    y: u.int_in_range(0..=height as i32 - 1)?,
+                    width: u.int_in_range(1..=width as u32)?,
+                    height: u.int_in_range(1..=height as u32)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let palette = if u.arbitrary()? {
+            let num_colors = u.int_in_range(1..=MAX_COLORS)?;
+            let colors = (0..num_colors)
+                .map(|_| u.arbitrary())
+                .collect::<Result<_>>()?;
+            Some(colors)
+        } else {
+            None
+        };
+
+        Ok(ArbitrarySprite {
+            width,
+            height,
+            layers,
+            frames,
+            tags,
+            slices,
+            palette,
+        })
+    }
+}
@@ -0,0 +1,593 @@
+/*!
+Pack the frames of one or more sprites tightly into a texture atlas.
+
+[pack] renders every frame, optionally trims its transparent border, and
+places the images with the MaxRects bin packing algorithm. The result is an
+atlas image together with an [AtlasFrame] for each frame that records where
+it ended up, how it was trimmed, how long it is shown, and where its slices
+are in the atlas.
+
+```
+# use asefile::AsepriteFile;
+# use asefile::atlas::{self, AtlasOptions};
+# use std::path::Path;
+# let player = AsepriteFile::read_file(Path::new("./tests/data/layers_and_tags.aseprite")).unwrap();
+# let coin = AsepriteFile::read_file(Path::new("./tests/data/basic-16x16.aseprite")).unwrap();
+let options = AtlasOptions::new().trim(true).padding(1);
+let atlas = atlas::pack(&[("player", &player), ("coin", &coin)], &options).unwrap();
+for frame in atlas.frames() {
+    let (x, y, width, height) = frame.rect();
+    println!("{} {}: {}x{} at {},{}", frame.sprite(), frame.frame(), width, height, x, y);
+}
+// atlas.image().save("atlas.png").unwrap();
+```
+
+Use [spritesheet](crate::spritesheet) instead if the frames should be laid
+out in a regular grid.
+*/
+use crate::image::RgbaImage;
+use crate::{AsepriteFile, AsepriteParseError, Result};
+use std::collections::HashMap;
+
+/// Options for [pack].
+///
+/// ```
+/// # use asefile::atlas::AtlasOptions;
+/// let options = AtlasOptions::new()
+///     .trim(true)
+///     .merge_duplicates(true)
+///     .max_size(1024, 1024);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AtlasOptions {
+    trim: bool,
+    padding: u32,
+    merge_duplicates: bool,
+    power_of_two: bool,
+    max_size: (u32, u32),
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        Self {
+            trim: false,
+            padding: 0,
+            merge_duplicates: false,
+            power_of_two: false,
+            max_size: (8192, 8192),
+        }
+    }
+}
+
+impl AtlasOptions {
+    /// Default options: Frames are packed untrimmed and without padding into
+    /// an atlas of at most 8192x8192 pixels.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove the fully transparent border of each frame before packing it.
+    /// See [AtlasFrame::trim_offset]. Fully transparent frames are trimmed to
+    /// a single pixel.
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Transparent pixels between neighboring frames. Defaults to 0.
+    pub fn padding(mut self, pixels: u32) -> Self {
+        self.padding = pixels;
+        self
+    }
+
+    /// Pack identical images only once. Their frames then share the same
+    /// [rect](AtlasFrame::rect), and all but the first are marked as
+    /// [aliases](AtlasFrame::alias_of). Held frames of an animation take no
+    /// extra space this way. Defaults to `false`.
+    pub fn merge_duplicates(mut self, merge: bool) -> Self {
+        self.merge_duplicates = merge;
+        self
+    }
+
+    /// Round the width and height of the atlas up to a power of two.
+    /// Defaults to `false`.
+    pub fn power_of_two(mut self, power_of_two: bool) -> Self {
+        self.power_of_two = power_of_two;
+        self
+    }
+
+    /// The largest allowed atlas size. Defaults to 8192x8192.
+    pub fn max_size(mut self, width: u32, height: u32) -> Self {
+        self.max_size = (width, height);
+        self
+    }
+}
+
+/// A texture atlas created by [pack].
+#[derive(Debug, Clone)]
+pub struct Atlas {
+    pub(crate) image: RgbaImage,
+    frames: Vec<AtlasFrame>,
+}
+
+impl Atlas {
+    /// The atlas image.
+    #[cfg(feature = "image")]
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// Take ownership of the atlas image.
+    #[cfg(feature = "image")]
+    pub fn into_image(self) -> RgbaImage {
+        self.image
+    }
+
+    /// Like [into_image](Atlas::into_image) but returns a
+    /// [raw_image::RgbaImage](crate::raw_image::RgbaImage). This is
+    /// available without the `image` feature.
+    pub fn into_raw_image(self) -> crate::raw_image::RgbaImage {
+        crate::raw_image::from_image(self.image)
+    }
+
+    /// All packed frames, sprite by sprite in the order they were passed to
+    /// [pack], and in frame order within each sprite.
+    pub fn frames(&self) -> &[AtlasFrame] {
+        &self.frames
+    }
+}
+
+/// The location and metadata of a frame in an [Atlas].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtlasFrame {
+    sprite: String,
+    frame: u32,
+    rect: (u32, u32, u32, u32),
+    trim_offset: (u32, u32),
+    source_size: (u32, u32),
+    duration: u32,
+    alias_of: Option<usize>,
+    slices: Vec<AtlasSlice>,
+}
+
+impl AtlasFrame {
+    /// The name of the sprite as passed to [pack].
+    pub fn sprite(&self) -> &str {
+        &self.sprite
+    }
+
+    /// The frame number in the sprite.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// The area of the atlas that contains the (trimmed) image: x, y, width,
+    /// and height.
+    pub fn rect(&self) -> (u32, u32, u32, u32) {
+        self.rect
+    }
+
+    /// Position of the trimmed image in the untrimmed frame. Always `(0, 0)`
+    /// if trimming is off.
+    pub fn trim_offset(&self) -> (u32, u32) {
+        self.trim_offset
+    }
+
+    /// Size of the untrimmed frame, which is the canvas size of the sprite.
+    pub fn source_size(&self) -> (u32, u32) {
+        self.source_size
+    }
+
+    /// Whether anything was removed by trimming.
+    pub fn is_trimmed(&self) -> bool {
+        let (_, _, width, height) = self.rect;
+        (width, height) != self.source_size
+    }
+
+    /// Frame duration in milliseconds.
+    pub fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    /// If the frame's image is identical to an earlier frame and was merged
+    /// with it, the index of that frame in [Atlas::frames]. Always `None` if
+    /// [merging duplicates](AtlasOptions::merge_duplicates) is off.
+    pub fn alias_of(&self) -> Option<usize> {
+        self.alias_of
+    }
+
+    /// The slices of the sprite that exist in this frame, moved into atlas
+    /// coordinates.
+    pub fn slices(&self) -> &[AtlasSlice] {
+        &self.slices
+    }
+}
+
+/// A [Slice](crate::Slice) of a frame in an [Atlas], with all positions in
+/// atlas coordinates. Slices may extend beyond the frame's
+/// [rect](AtlasFrame::rect), e.g., into the area removed by trimming.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtlasSlice {
+    name: String,
+    bounds: (i32, i32, u32, u32),
+    center: Option<(i32, i32, u32, u32)>,
+    pivot: Option<(i32, i32)>,
+}
+
+impl AtlasSlice {
+    /// The name of the slice.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The area of the slice: x, y, width, and height.
+    pub fn bounds(&self) -> (i32, i32, u32, u32) {
+        self.bounds
+    }
+
+    /// The center area of a 9-slice: x, y, width, and height.
+    pub fn center(&self) -> Option<(i32, i32, u32, u32)> {
+        self.center
+    }
+
+    /// The pivot point.
+    pub fn pivot(&self) -> Option<(i32, i32)> {
+        self.pivot
+    }
+}
+
+/// Pack all frames of the given sprites into one atlas. Each sprite is
+/// given a name, which is stored in the [AtlasFrame]s.
+///
+/// # Errors
+///
+/// Returns [AsepriteParseError::InvalidInput] if the frames do not fit into
+/// the [maximum size](AtlasOptions::max_size).
+pub fn pack(sprites: &[(&str, &AsepriteFile)], options: &AtlasOptions) -> Result<Atlas> {
+    let mut frames = Vec::new();
+    // Images to pack, and for each frame the index of its image.
+    let mut images: Vec<RgbaImage> = Vec::new();
+    let mut image_of_frame = Vec::new();
+    // For each image, the first frame that uses it.
+    let mut first_frame_of_image = Vec::new();
+    let mut duplicates: HashMap<RgbaImage, usize> = HashMap::new();
+    let mut file_of_frame = Vec::new();
+    for &(name, file) in sprites {
+        let (width, height) = file.size();
+        for frame in 0..file.num_frames() {
+            let mut image = file.frame(frame).composite();
+            let mut trim_offset = (0, 0);
+            if options.trim {
+                let (x, y, w, h) = opaque_bounds(&image);
+                image = crate::image::imageops::crop_imm(&image, x, y, w, h).to_image();
+                trim_offset = (x, y);
+            }
+            let (w, h) = image.dimensions();
+            let index = if options.merge_duplicates {
+                *duplicates.entry(image).or_insert_with_key(|image| {
+                    images.push(image.clone());
+                    images.len() - 1
+                })
+            } else {
+                images.push(image);
+                images.len() - 1
+            };
+            if index == first_frame_of_image.len() {
+                first_frame_of_image.push(frames.len());
+            }
+            let alias_of = Some(first_frame_of_image[index]).filter(|&first| first != frames.len());
+            image_of_frame.push(index);
+            frames.push(AtlasFrame {
+                sprite: name.to_owned(),
+                frame,
+                rect: (0, 0, w, h),
+                trim_offset,
+                source_size: (width as u32, height as u32),
+                duration: file.frame(frame).duration(),
+                alias_of,
+                slices: Vec::new(),
+            });
+            file_of_frame.push(file);
+        }
+    }
+
+    let sizes: Vec<(u32, u32)> = images.iter().map(|image| image.dimensions()).collect();
+    let Placement {
+        positions,
+        size: (width, height),
+    } = place(&sizes, options)?;
+    let mut atlas = RgbaImage::new(width, height);
+    for (image, &(x, y)) in images.iter().zip(&positions) {
+        crate::image::imageops::replace(&mut atlas, image, x, y);
+    }
+    for ((frame, &index), file) in frames.iter_mut().zip(&image_of_frame).zip(file_of_frame) {
+        let (x, y) = positions[index];
+        frame.rect.0 = x;
+        frame.rect.1 = y;
+        frame.slices = frame_slices(file, frame.frame, (x, y), frame.trim_offset);
+    }
+    Ok(Atlas {
+        image: atlas,
+        frames,
+    })
+}
+
+// The slices that exist in a frame, moved from sprite coordinates to an image
+// that contains the frame at (x, y), trimmed by `trim_offset`. Slices that are
+// hidden in the frame have a size of 0 and are left out.
+pub(crate) fn frame_slices(
+    file: &AsepriteFile,
+    frame: u32,
+    (x, y): (u32, u32),
+    trim_offset: (u32, u32),
+) -> Vec<AtlasSlice> {
+    let dx = x as i32 - trim_offset.0 as i32;
+    let dy = y as i32 - trim_offset.1 as i32;
+    file.slices()
+        .iter()
+        .filter_map(|slice| {
+            let (x, y, width, height) = slice.bounds_at(frame)?;
+            let key = slice.key_for_frame(frame)?;
+            let (left, top) = (x + dx, y + dy);
+            Some(AtlasSlice {
+                name: slice.name.clone(),
+                bounds: (left, top, width, height),
+                center: key.slice9.map(|center| {
+                    (
+                        left + center.center_x,
+                        top + center.center_y,
+                        center.center_width,
+                        center.center_height,
+                    )
+                }),
+                pivot: key.pivot_position().map(|(x, y)| (x + dx, y + dy)),
+            })
+        })
+        .collect()
+}
+
+// The smallest rectangle that contains all pixels that are not fully
+// transparent, at least 1x1.
+pub(crate) fn opaque_bounds(image: &RgbaImage) -> (u32, u32, u32, u32) {
+    let (mut min_x, mut min_y) = (u32::MAX, u32::MAX);
+    let (mut max_x, mut max_y) = (0, 0);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel.0[3] != 0 {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+    if min_x == u32::MAX {
+        return (0, 0, 1, 1);
+    }
+    (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1)
+}
+
+struct Placement {
+    positions: Vec<(u32, u32)>,
+    size: (u32, u32),
+}
+
+// Finds a small atlas size for which all rectangles fit.
+fn place(sizes: &[(u32, u32)], options: &AtlasOptions) -> Result<Placement> {
+    let padding = options.padding;
+    // Padding is added to the right and bottom of each rectangle. The bin is
+    // just as much larger, so that the last row and column need none.
+    let padded: Vec<(u32, u32)> = sizes
+        .iter()
+        .map(|&(w, h)| (w + padding, h + padding))
+        .collect();
+    // Larger rectangles first, which gives much tighter results.
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| {
+        let (w, h) = padded[i];
+        std::cmp::Reverse((w.max(h), w * h))
+    });
+
+    // Start with a square of the total area and grow it until everything
+    // fits.
+    let area: u64 = padded.iter().map(|&(w, h)| w as u64 * h as u64).sum();
+    let side = (area as f64).sqrt().ceil() as u32;
+    let mut width = side.max(sizes.iter().map(|&(w, _)| w).max().unwrap_or(1));
+    let mut height = side.max(sizes.iter().map(|&(_, h)| h).max().unwrap_or(1));
+    let round = |size: u32| {
+        if options.power_of_two {
+            size.next_power_of_two()
+        } else {
+            size
+        }
+    };
+    let grow = |size: u32| {
+        if options.power_of_two {
+            round(size) * 2
+        } else {
+            size + (size / 16).max(1)
+        }
+    };
+    let (max_width, max_height) = options.max_size;
+    loop {
+        let (bin_width, bin_height) = (round(width), round(height));
+        if bin_width > max_width || bin_height > max_height {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Frames do not fit into an atlas of {}x{}",
+                max_width, max_height
+            )));
+        }
+        if let Some(positions) =
+            try_place(&padded, &order, bin_width + padding, bin_height + padding)
+        {
+            let size = if options.power_of_two {
+                (bin_width, bin_height)
+            } else {
+                // The used area, which may be smaller than the bin.
+                positions
+                    .iter()
+                    .zip(sizes)
+                    .fold((1, 1), |(w, h), (&(x, y), &(size_x, size_y))| {
+                        (w.max(x + size_x), h.max(y + size_y))
+                    })
+            };
+            return Ok(Placement { positions, size });
+        }
+        // Grow the shorter side, unless it can't grow any further.
+        let can_grow_width = round(grow(width)) <= max_width;
+        let can_grow_height = round(grow(height)) <= max_height;
+        if (width <= height && can_grow_width) || !can_grow_height {
+            width = grow(width);
+        } else {
+            height = grow(height);
+        }
+    }
+}
+
+// Positions of all rectangles in a bin of the given size, if they fit.
+fn try_place(
+    sizes: &[(u32, u32)],
+    order: &[usize],
+    width: u32,
+    height: u32,
+) -> Option<Vec<(u32, u32)>> {
+    let mut bin = MaxRects::new(width, height);
+    let mut positions = vec![(0, 0); sizes.len()];
+    for &i in order {
+        let (w, h) = sizes[i];
+        positions[i] = bin.insert(w, h)?;
+    }
+    Some(positions)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    fn right(&self) -> u32 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> u32 {
+        self.y + self.height
+    }
+
+    fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+
+    fn contains(&self, other: &Rect) -> bool {
+        self.x <= other.x
+            && self.y <= other.y
+            && other.right() <= self.right()
+            && other.bottom() <= self.bottom()
+    }
+}
+
+// MaxRects bin packer with the "best short side fit" heuristic: Keeps a list
+// of maximal free rectangles, which may overlap, and puts each new rectangle
+// where it leaves the least space along its shorter side.
+struct MaxRects {
+    free: Vec<Rect>,
+}
+
+impl MaxRects {
+    fn new(width: u32, height: u32) -> Self {
+        MaxRects {
+            free: vec![Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            }],
+        }
+    }
+
+    fn insert(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let mut best: Option<((u32, u32), Rect)> = None;
+        for free in &self.free {
+            if free.width < width || free.height < height {
+                continue;
+            }
+            let leftover_x = free.width - width;
+            let leftover_y = free.height - height;
+            let score = (leftover_x.min(leftover_y), leftover_x.max(leftover_y));
+            if best.map_or(true, |(best_score, _)| score < best_score) {
+                let rect = Rect {
+                    x: free.x,
+                    y: free.y,
+                    width,
+                    height,
+                };
+                best = Some((score, rect));
+            }
+        }
+        let (_, used) = best?;
+        self.split(&used);
+        self.prune();
+        Some((used.x, used.y))
+    }
+
+    // Replaces every free rectangle that overlaps `used` with the up to four
+    // maximal rectangles around it.
+    fn split(&mut self, used: &Rect) {
+        let mut new_free = Vec::new();
+        self.free.retain(|free| {
+            if !free.intersects(used) {
+                return true;
+            }
+            if used.x > free.x {
+                new_free.push(Rect {
+                    width: used.x - free.x,
+                    ..*free
+                });
+            }
+            if used.right() < free.right() {
+                new_free.push(Rect {
+                    x: used.right(),
+                    width: free.right() - used.right(),
+                    ..*free
+                });
+            }
+            if used.y > free.y {
+                new_free.push(Rect {
+                    height: used.y - free.y,
+                    ..*free
+                });
+            }
+            if used.bottom() < free.bottom() {
+                new_free.push(Rect {
+                    y: used.bottom(),
+                    height: free.bottom() - used.bottom(),
+                    ..*free
+                });
+            }
+            false
+        });
+        self.free.extend(new_free);
+    }
+
+    // Removes free rectangles that lie within another one.
+    fn prune(&mut self) {
+        let mut i = 0;
+        while i < self.free.len() {
+            let rect = self.free[i];
+            let redundant = self
+                .free
+                .iter()
+                .enumerate()
+                .any(|(j, other)| j != i && other.contains(&rect) && (other != &rect || j < i));
+            if redundant {
+                self.free.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,276 @@
+/*!
+Load sprites as [Bevy](https://bevyengine.org) assets.
+
+Requires the `bevy` feature. [AsepritePlugin] registers the [Aseprite] asset
+together with an [AsepriteLoader] for `.aseprite` and `.ase` files. It needs
+the asset and image plugins, which are part of Bevy's `DefaultPlugins`:
+
+```no_run
+# use bevy_app::App;
+# use bevy_asset::{AssetPlugin, AssetServer, Handle};
+# use bevy_image::{ImagePlugin, TextureAtlasPlugin};
+use asefile::bevy::{Aseprite, AsepritePlugin};
+
+let mut app = App::new();
+app.add_plugins((
+    AssetPlugin::default(),
+    ImagePlugin::default(),
+    TextureAtlasPlugin,
+    AsepritePlugin,
+));
+let server = app.world().resource::<AssetServer>();
+let player: Handle<Aseprite> = server.load("player.aseprite");
+```
+
+Loading a sprite adds the following labeled assets:
+
+- `Atlas`: An [Image] with all frames, arranged in a grid.
+- `AtlasLayout`: A [TextureAtlasLayout] for the atlas. Its texture index
+  is the frame number.
+- `Frame0`, `Frame1`, ...: An [Image] of each frame.
+
+They can be loaded on their own, e.g. `player.aseprite#Atlas`. The
+[Aseprite] asset holds their handles as well as a [SpriteAnimation] for every
+tag.
+
+Images use nearest-neighbor sampling by default, so pixel art stays sharp.
+Change this with the [AsepriteLoaderSettings] in the `.meta` file of the
+sprite.
+*/
+use crate::exporters::playback_frames;
+use crate::image::RgbaImage;
+use crate::spritesheet::{self, SheetLayout, SpriteSheetOptions};
+use crate::{AsepriteFile, AsepriteParseError};
+use bevy_app::{App, Plugin};
+use bevy_asset::io::Reader;
+use bevy_asset::{Asset, AssetApp, AssetLoader, Handle, LoadContext, RenderAssetUsages};
+use bevy_image::{Image, ImageSampler, TextureAtlasLayout};
+use bevy_math::{URect, UVec2};
+use bevy_reflect::TypePath;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use wgpu_types::{Extent3d, TextureDimension, TextureFormat};
+
+/// Registers the [Aseprite] asset and the [AsepriteLoader].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsepritePlugin;
+
+impl Plugin for AsepritePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Aseprite>()
+            .init_asset_loader::<AsepriteLoader>();
+    }
+}
+
+/// A sprite loaded by the [AsepriteLoader].
+#[derive(Asset, TypePath, Debug)]
+pub struct Aseprite {
+    #[dependency]
+    atlas: Handle<Image>,
+    #[dependency]
+    atlas_layout: Handle<TextureAtlasLayout>,
+    #[dependency]
+    frames: Vec<Handle<Image>>,
+    animations: Vec<SpriteAnimation>,
+}
+
+impl Aseprite {
+    /// The image with all frames.
+    pub fn atlas(&self) -> &Handle<Image> {
+        &self.atlas
+    }
+
+    /// The position of each frame in the [atlas](Aseprite::atlas), indexed by
+    /// frame number.
+    pub fn atlas_layout(&self) -> &Handle<TextureAtlasLayout> {
+        &self.atlas_layout
+    }
+
+    /// The image of each frame.
+    pub fn frames(&self) -> &[Handle<Image>] {
+        &self.frames
+    }
+
+    /// The animations of all tags, in the order of the tags.
+    pub fn animations(&self) -> &[SpriteAnimation] {
+        &self.animations
+    }
+
+    /// The animation of the tag with the given name.
+    pub fn animation(&self, name: &str) -> Option<&SpriteAnimation> {
+        self.animations
+            .iter()
+            .find(|animation| animation.name == name)
+    }
+}
+
+/// The frames shown while playing a tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpriteAnimation {
+    name: String,
+    frames: Vec<usize>,
+    durations: Vec<Duration>,
+    looping: bool,
+}
+
+impl SpriteAnimation {
+    /// The name of the tag.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The frames to show, as indices into the
+    /// [atlas layout](Aseprite::atlas_layout). Already in playback order,
+    /// so reverse and ping-pong tags need no special handling. Tags with a
+    /// [repeat](crate::Tag::repeat) count contain every repetition.
+    pub fn frames(&self) -> &[usize] {
+        &self.frames
+    }
+
+    /// How long each of the [frames](SpriteAnimation::frames) is shown.
+    pub fn durations(&self) -> &[Duration] {
+        &self.durations
+    }
+
+    /// Whether the animation starts over after the last frame. Otherwise it
+    /// stops there.
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// The time it takes to show every frame once.
+    pub fn duration(&self) -> Duration {
+        self.durations.iter().sum()
+    }
+
+    /// The atlas index to show after the animation played for `elapsed`.
+    pub fn frame_at(&self, elapsed: Duration) -> usize {
+        let total = self.duration();
+        let mut time = if self.looping && !total.is_zero() {
+            Duration::from_nanos((elapsed.as_nanos() % total.as_nanos()) as u64)
+        } else {
+            elapsed
+        };
+        for (frame, duration) in self.frames.iter().zip(&self.durations) {
+            if time < *duration {
+                return *frame;
+            }
+            time -= *duration;
+        }
+        *self.frames.last().unwrap()
+    }
+}
+
+/// Loads [Aseprite] assets from `.aseprite` and `.ase` files.
+#[derive(Debug, Clone, Copy, Default, TypePath)]
+pub struct AsepriteLoader;
+
+/// Settings of the [AsepriteLoader].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AsepriteLoaderSettings {
+    /// The sampler of the atlas and frame images. Defaults to
+    /// [ImageSampler::nearest].
+    pub sampler: ImageSampler,
+    /// Where the images are used.
+    pub asset_usage: RenderAssetUsages,
+}
+
+impl Default for AsepriteLoaderSettings {
+    fn default() -> Self {
+        Self {
+            sampler: ImageSampler::nearest(),
+            asset_usage: RenderAssetUsages::default(),
+        }
+    }
+}
+
+impl AssetLoader for AsepriteLoader {
+    type Asset = Aseprite;
+    type Settings = AsepriteLoaderSettings;
+    type Error = AsepriteParseError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        settings: &AsepriteLoaderSettings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Aseprite, AsepriteParseError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+        let file = AsepriteFile::read(&data[..])?;
+        sprite(&file, settings, load_context)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["aseprite", "ase"]
+    }
+}
+
+fn sprite(
+    file: &AsepriteFile,
+    settings: &AsepriteLoaderSettings,
+    load_context: &mut LoadContext<'_>,
+) -> Result<Aseprite, AsepriteParseError> {
+    let columns = (file.num_frames() as f64).sqrt().ceil() as u32;
+    let options = SpriteSheetOptions::new().layout(SheetLayout::Grid { columns });
+    let sheet = spritesheet::build(file, &options)?;
+    let (width, height) = sheet.image().dimensions();
+    let mut layout = TextureAtlasLayout::new_empty(UVec2::new(width, height));
+    for frame in sheet.frames() {
+        layout.add_texture(URect::new(
+            frame.x(),
+            frame.y(),
+            frame.x() + frame.width(),
+            frame.y() + frame.height(),
+        ));
+    }
+    let atlas = load_context.add_labeled_asset("Atlas", image(sheet.into_image(), settings));
+    let atlas_layout = load_context.add_labeled_asset("AtlasLayout", layout);
+    let frames = (0..file.num_frames())
+        .map(|frame| {
+            let image = image(file.frame(frame).image(), settings);
+            load_context.add_labeled_asset(format!("Frame{}", frame), image)
+        })
+        .collect();
+    Ok(Aseprite {
+        atlas,
+        atlas_layout,
+        frames,
+        animations: animations(file),
+    })
+}
+
+pub(crate) fn animations(file: &AsepriteFile) -> Vec<SpriteAnimation> {
+    (0..file.num_tags())
+        .map(|tag_id| {
+            let tag = file.tag(tag_id);
+            let frames = playback_frames(tag);
+            SpriteAnimation {
+                name: tag.name().to_owned(),
+                durations: frames
+                    .iter()
+                    .map(|&frame| Duration::from_millis(file.frame(frame).duration().into()))
+                    .collect(),
+                frames: frames.into_iter().map(|frame| frame as usize).collect(),
+                looping: tag.repeat() == 0,
+            }
+        })
+        .collect()
+}
+
+fn image(image: RgbaImage, settings: &AsepriteLoaderSettings) -> Image {
+    let size = Extent3d {
+        width: image.width(),
+        height: image.height(),
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image::new(
+        size,
+        TextureDimension::D2,
+        image.into_raw(),
+        TextureFormat::Rgba8UnormSrgb,
+        settings.asset_usage,
+    );
+    image.sampler = settings.sampler.clone();
+    image
+}
@@ -0,0 +1,91 @@
+/*!
+Helpers for reading binary data that follows the conventions of the Aseprite
+file format.
+
+Useful for decoding custom payloads, e.g., binary data that an extension
+stored in [UserData](crate::UserData):
+
+```
+# use asefile::binary::Reader;
+let data: &[u8] = &[0x34, 0x12, 0x02, 0x00, b'h', b'i'];
+let mut reader = Reader::new(data);
+assert_eq!(reader.word().unwrap(), 0x1234);
+assert_eq!(reader.string().unwrap(), "hi");
+```
+*/
+use crate::io::Read;
+use crate::{reader::AseReader, Result};
+use alloc::string::String;
+
+/// Reads values in the same encoding as Aseprite files. All numbers are
+/// little-endian.
+///
+/// Works with any `std::io::Read`, including `&[u8]`. Without the `std`
+/// feature, it reads from byte slices.
+///
+/// # Errors
+///
+/// Reading past the end of the input returns an
+/// [AsepriteParseError::IoError](crate::AsepriteParseError::IoError) of kind
+/// [std::io::ErrorKind::UnexpectedEof]. Strings that are not valid UTF-8
+/// return an
+/// [AsepriteParseError::InvalidInput](crate::AsepriteParseError::InvalidInput).
+pub struct Reader<R: Read> {
+    inner: AseReader<R>,
+}
+
+impl<R: Read> Reader<R> {
+    /// Create a reader that reads from `input`.
+    pub fn new(input: R) -> Self {
+        Self {
+            inner: AseReader::with(input),
+        }
+    }
+
+    /// Return the underlying input.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner()
+    }
+
+    /// Read a `BYTE` (unsigned 8-bit integer).
+    pub fn byte(&mut self) -> Result<u8> {
+        self.inner.byte()
+    }
+
+    /// Read a `WORD` (unsigned 16-bit integer).
+    pub fn word(&mut self) -> Result<u16> {
+        self.inner.word()
+    }
+
+    /// Read a `SHORT` (signed 16-bit integer).
+    pub fn short(&mut self) -> Result<i16> {
+        self.inner.short()
+    }
+
+    /// Read a `DWORD` (unsigned 32-bit integer).
+    pub fn dword(&mut self) -> Result<u32> {
+        self.inner.dword()
+    }
+
+    /// Read a `LONG` (signed 32-bit integer).
+    pub fn long(&mut self) -> Result<i32> {
+        self.inner.long()
+    }
+
+    /// Read a `FIXED` value, a signed 32-bit 16.16 fixed point number. The
+    /// conversion to `f64` is exact.
+    pub fn fixed(&mut self) -> Result<f64> {
+        self.inner.fixed()
+    }
+
+    /// Read a `STRING`: a `WORD` with the number of bytes, followed by that
+    /// many bytes of UTF-8. The string is not terminated by a zero byte.
+    pub fn string(&mut self) -> Result<String> {
+        self.inner.string()
+    }
+
+    /// Fill `buffer` with the next bytes of the input.
+    pub fn read_exact(&mut self, buffer: &mut [u8]) -> Result<()> {
+        self.inner.read_exact(buffer)
+    }
+}
@@ -1,6 +1,26 @@
-use std::usize;
-
-use image::Rgba;
+/*!
+Aseprite's blend modes, for compositing images yourself with the same results
+as Aseprite.
+
+[blend] combines two pixels, and [blend_image] draws a whole image onto
+another one, e.g., the [image of a cel](crate::Cel::image):
+
+```
+# use asefile::{AsepriteFile, blend};
+# use image::RgbaImage;
+# let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/basic-16x16.aseprite")).unwrap();
+let mut canvas = RgbaImage::new(ase.width() as u32, ase.height() as u32);
+let frame = ase.frame(0);
+for layer in ase.layers() {
+    let cel = frame.layer(layer.id());
+    blend::blend_image(layer.blend_mode(), &mut canvas, &cel.image(), 0, 0, 255);
+}
+```
+*/
+use crate::image::Rgba;
+#[cfg(feature = "image")]
+use crate::image::RgbaImage;
+use crate::BlendMode;
 
 // Rust port of Aseprite's blend functions:
 // https://github.com/aseprite/aseprite/blob/master/src/doc/blend_funcs.cpp
@@ -10,8 +30,74 @@ use image::Rgba;
 //  - PDF Blend Modes addendum: https://www.adobe.com/content/dam/acom/en/devnet/pdf/pdf_reference_archive/blend_modes.pdf
 //  - Pixman source: https://github.com/servo/pixman/blob/master/pixman/pixman-combine-float.c
 
+/// A color with 8 bits per channel, as in the images of this crate.
 pub type Color8 = Rgba<u8>;
 
+pub(crate) type BlendFn = fn(Color8, Color8, u8) -> Color8;
+
+/// Blend `src` onto `backdrop` with the blend mode `mode`, like Aseprite
+/// draws the pixels of a cel onto the layers below. The alpha of `src` is
+/// scaled by `opacity`, e.g., the opacity of the cel.
+///
+/// ```
+/// # use asefile::{blend, BlendMode};
+/// # use image::Rgba;
+/// let backdrop = Rgba([200, 100, 50, 255]);
+/// let src = Rgba([128, 128, 128, 255]);
+/// assert_eq!(blend::blend(BlendMode::Multiply, backdrop, src, 255), Rgba([100, 50, 25, 255]));
+/// ```
+#[cfg(feature = "image")]
+pub fn blend(mode: BlendMode, backdrop: Color8, src: Color8, opacity: u8) -> Color8 {
+    blend_fn(mode)(backdrop, src, opacity)
+}
+
+/// Blend all pixels of `src` onto `backdrop` with the blend mode `mode`,
+/// see [blend]. The top left corner of `src` is placed at `(x, y)`, and
+/// pixels that end up outside of `backdrop` are skipped.
+#[cfg(feature = "image")]
+pub fn blend_image(
+    mode: BlendMode,
+    backdrop: &mut RgbaImage,
+    src: &RgbaImage,
+    x: i32,
+    y: i32,
+    opacity: u8,
+) {
+    let blend_fn = blend_fn(mode);
+    let (width, height) = (backdrop.width() as i64, backdrop.height() as i64);
+    for (src_x, src_y, pixel) in src.enumerate_pixels() {
+        let (target_x, target_y) = (x as i64 + src_x as i64, y as i64 + src_y as i64);
+        if (0..width).contains(&target_x) && (0..height).contains(&target_y) {
+            let target = backdrop.get_pixel_mut(target_x as u32, target_y as u32);
+            *target = blend_fn(*target, *pixel, opacity);
+        }
+    }
+}
+
+pub(crate) fn blend_fn(mode: BlendMode) -> BlendFn {
+    match mode {
+        BlendMode::Normal => normal,
+        BlendMode::Multiply => multiply,
+        BlendMode::Screen => screen,
+        BlendMode::Overlay => overlay,
+        BlendMode::Darken => darken,
+        BlendMode::Lighten => lighten,
+        BlendMode::ColorDodge => color_dodge,
+        BlendMode::ColorBurn => color_burn,
+        BlendMode::HardLight => hard_light,
+        BlendMode::SoftLight => soft_light,
+        BlendMode::Difference => difference,
+        BlendMode::Exclusion => exclusion,
+        BlendMode::Hue => hsl_hue,
+        BlendMode::Saturation => hsl_saturation,
+        BlendMode::Color => hsl_color,
+        BlendMode::Luminosity => hsl_luminosity,
+        BlendMode::Addition => addition,
+        BlendMode::Subtract => subtract,
+        BlendMode::Divide => divide,
+    }
+}
+
 #[allow(dead_code)]
 pub(crate) fn merge(backdrop: Color8, src: Color8, opacity: u8) -> Color8 {
     let [back_r, back_g, back_b, back_a] = backdrop.0;
@@ -19,7 +105,6 @@ pub(crate) fn merge(backdrop: Color8, src: Color8, opacity: u8) -> Color8 {
     let res_r;
     let res_g;
     let res_b;
-    let res_a;
 
     if back_a == 0 {
         res_r = src_r;
@@ -34,7 +119,7 @@ pub(crate) fn merge(backdrop: Color8, src: Color8, opacity: u8) -> Color8 {
         res_g = blend8(back_g, src_g, opacity);
         res_b = blend8(back_b, src_b, opacity);
     }
-    res_a = blend8(back_a, src_a, opacity);
+    let res_a = blend8(back_a, src_a, opacity);
     if res_a == 0 {
         Rgba([0, 0, 0, 0])
     } else {
@@ -279,6 +364,22 @@ fn soft_light_baseline(backdrop: Color8, src: Color8, opacity: u8) -> Color8 {
     normal(backdrop, src, opacity)
 }
 
+#[cfg(feature = "std")]
+fn sqrt(x: f64) -> f64 {
+    x.sqrt()
+}
+
+// Without std there is no square root for floats. Newton's method converges
+// quickly for the inputs of blend_soft_light, which are between 0.25 and 1.
+#[cfg(not(feature = "std"))]
+fn sqrt(x: f64) -> f64 {
+    let mut root = x;
+    for _ in 0..8 {
+        root = 0.5 * (root + x / root);
+    }
+    root
+}
+
 fn blend_soft_light(b: i32, s: i32) -> i32 {
     // The original uses double, but since inputs & output are only 8 bits using
     // f32 should actually be enough.
@@ -288,7 +389,7 @@ fn blend_soft_light(b: i32, s: i32) -> i32 {
     let d = if b <= 0.25 {
         ((16.0 * b - 12.0) * b + 4.0) * b
     } else {
-        b.sqrt()
+        sqrt(b)
     };
 
     let r = if s <= 0.5 {
@@ -331,7 +432,7 @@ fn difference_baseline(backdrop: Color8, src: Color8, opacity: u8) -> Color8 {
 }
 
 fn blend_difference(b: i32, s: i32) -> u8 {
-    (b - s).abs() as u8
+    (b - s).unsigned_abs() as u8
 }
 
 // --- exclusion ---------------------------------------------------------------
@@ -684,13 +785,11 @@ fn test_set_saturation() {
                     // println!("set_saturation({:.3}, {:.3}, {:.3}, {:.3}) => ({:.3}, {:.3}, {:.3}) => sat: {:.5} (input sat: {:.5})",
                     // r, g, b, sat, r1, g1, b1, sat1, sat0);
 
-                    if !(r == g && g == b) {
-                        if (sat1 - sat).abs() > 0.00001 {
-                            panic!(
+                    if !(r == g && g == b) && (sat1 - sat).abs() > 0.00001 {
+                        panic!(
                                 "set_saturation({:.3}, {:.3}, {:.3}, {:.3}) => ({:.3}, {:.3}, {:.3}) => sat: {:.5} (input sat: {:.5})",
                                 r, g, b, sat, r1, g1, b1, sat1, sat0
                             );
-                        }
                     }
                 }
             }
@@ -801,7 +900,25 @@ fn test_normal() {
     assert_eq!(Rgba([118, 162, 135, 255]), res);
 }
 
-fn mul_un8(a: i32, b: i32) -> u8 {
+#[cfg(feature = "image")]
+#[test]
+fn test_blend_image() {
+    let back = Rgba([0, 205, 249, 255]);
+    let front = Rgba([237, 118, 20, 255]);
+    assert_eq!(
+        blend(BlendMode::Screen, back, front, 200),
+        screen(back, front, 200)
+    );
+
+    let mut backdrop = RgbaImage::from_pixel(3, 2, back);
+    let src = RgbaImage::from_pixel(2, 2, front);
+    blend_image(BlendMode::Normal, &mut backdrop, &src, 2, -1, 128);
+    let blended = normal(back, front, 128);
+    let pixels: Vec<Color8> = backdrop.pixels().copied().collect();
+    assert_eq!(pixels, vec![back, back, blended, back, back, back]);
+}
+
+pub(crate) fn mul_un8(a: i32, b: i32) -> u8 {
     let t = a * b + 0x80;
     let r = ((t >> 8) + t) >> 8;
     r as u8
@@ -0,0 +1,362 @@
+#[cfg(feature = "image")]
+use crate::image::RgbaImage;
+use crate::{
+    cel::{CelContent, CelData, CelsData, ImageContent, ImageSize, PixelRange, RawCel},
+    color_profile::ColorProfile,
+    external_file::ExternalFilesById,
+    file::Grid,
+    layer::{LayerData, LayerType, LayersData},
+    palette::ColorPalette,
+    slice::SliceNames,
+    tileset::TilesetsById,
+    AnimationDirection, AsepriteFile, AsepriteParseError, BlendMode, LayerFlags, PixelFormat,
+    Result, Slice, Tag,
+};
+
+/// Constructs an [AsepriteFile] from scratch.
+///
+/// Frames, layers, and cels are added one by one. Layers are stacked in the
+/// order in which they were added (the first layer is at the bottom). Indices
+/// passed to the builder are only checked when calling [build], which returns
+/// an error if they are out of range.
+///
+/// Sprites are RGBA by default. Use [set_pixel_format] for grayscale or
+/// indexed sprites, whose cels are added with [add_cel_pixels].
+///
+/// ```
+/// # use asefile::{AnimationDirection, AsepriteFileBuilder};
+/// # use image::RgbaImage;
+/// let mut builder = AsepriteFileBuilder::new(16, 16);
+/// let layer = builder.add_layer("Background");
+/// let frame = builder.add_frame(100);
+/// builder.add_cel(frame, layer, (0, 0), &RgbaImage::new(16, 16));
+/// builder.add_tag("Idle", 0, 0, AnimationDirection::Forward);
+/// let ase = builder.build().unwrap();
+/// assert_eq!(ase.num_frames(), 1);
+/// ```
+///
+/// An indexed sprite:
+///
+/// ```
+/// # use asefile::{AsepriteFileBuilder, PixelFormat};
+/// let mut builder = AsepriteFileBuilder::new(2, 1);
+/// builder.set_pixel_format(PixelFormat::Indexed {
+///     transparent_color_index: 0,
+/// });
+/// builder.set_palette(&[[0, 0, 0, 0], [255, 0, 0, 255]]);
+/// let layer = builder.add_layer("Layer");
+/// let frame = builder.add_frame(100);
+/// builder.add_cel_pixels(frame, layer, (0, 0), (2, 1), &[1, 0]);
+/// let ase = builder.build().unwrap();
+/// assert_eq!(ase.frame(0).image().get_pixel(0, 0).0, [255, 0, 0, 255]);
+/// ```
+///
+/// [build]: AsepriteFileBuilder::build
+/// [set_pixel_format]: AsepriteFileBuilder::set_pixel_format
+/// [add_cel_pixels]: AsepriteFileBuilder::add_cel_pixels
+#[derive(Debug)]
+pub struct AsepriteFileBuilder {
+    width: u16,
+    height: u16,
+    pixel_format: PixelFormat,
+    palette: Option<ColorPalette>,
+    layers: Vec<LayerData>,
+    frame_times: Vec<u16>,
+    cels: Vec<PendingCel>,
+    tags: Vec<Tag>,
+    slices: Vec<Slice>,
+}
+
+// A cel whose frame and layer have not been checked yet.
+#[derive(Debug)]
+struct PendingCel {
+    frame: u32,
+    layer: u32,
+    origin: (i16, i16),
+    size: (u32, u32),
+    pixels: Vec<u8>,
+    // Added with `add_cel`; otherwise the pixels are in the sprite's format.
+    rgba: bool,
+}
+
+impl AsepriteFileBuilder {
+    /// Start a new sprite with the given canvas size and no frames or layers.
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            pixel_format: PixelFormat::Rgba,
+            palette: None,
+            layers: Vec::new(),
+            frame_times: Vec::new(),
+            cels: Vec::new(),
+            tags: Vec::new(),
+            slices: Vec::new(),
+        }
+    }
+
+    /// Set the color depth of the sprite. Defaults to [PixelFormat::Rgba].
+    pub fn set_pixel_format(&mut self, pixel_format: PixelFormat) {
+        self.pixel_format = pixel_format;
+    }
+
+    /// Add a visible image layer on top of all existing layers. Returns the
+    /// id of the new layer.
+    pub fn add_layer(&mut self, name: &str) -> u32 {
+        self.layers.push(LayerData {
+            flags: LayerFlags::VISIBLE | LayerFlags::EDITABLE,
+            name: name.to_owned(),
+            blend_mode: BlendMode::Normal,
+            opacity: 255,
+            layer_type: LayerType::Image,
+            user_data: None,
+            child_level: 0,
+            uuid: None,
+        });
+        self.layers.len() as u32 - 1
+    }
+
+    /// Set the blend mode of a layer. Does nothing if the layer does not exist.
+    pub fn set_layer_blend_mode(&mut self, layer: u32, blend_mode: BlendMode) {
+        if let Some(layer) = self.layers.get_mut(layer as usize) {
+            layer.blend_mode = blend_mode;
+        }
+    }
+
+    /// Set the opacity of a layer. Does nothing if the layer does not exist.
+    pub fn set_layer_opacity(&mut self, layer: u32, opacity: u8) {
+        if let Some(layer) = self.layers.get_mut(layer as usize) {
+            layer.opacity = opacity;
+        }
+    }
+
+    /// Show or hide a layer. Does nothing if the layer does not exist.
+    pub fn set_layer_visible(&mut self, layer: u32, visible: bool) {
+        if let Some(layer) = self.layers.get_mut(layer as usize) {
+            layer.flags.set(LayerFlags::VISIBLE, visible);
+        }
+    }
+
+    /// Append a frame with the given duration in milliseconds. Returns the
+    /// index of the new frame.
+    pub fn add_frame(&mut self, duration_ms: u16) -> u32 {
+        self.frame_times.push(duration_ms);
+        self.frame_times.len() as u32 - 1
+    }
+
+    /// Place an image on the given frame and layer. `origin` is the position
+    /// of the image's top left corner on the canvas and may lie outside the
+    /// canvas.
+    ///
+    /// Only RGBA sprites can have RGBA cels. See
+    /// [add_cel_pixels](AsepriteFileBuilder::add_cel_pixels) for other pixel
+    /// formats.
+    #[cfg(feature = "image")]
+    pub fn add_cel(&mut self, frame: u32, layer: u32, origin: (i16, i16), image: &RgbaImage) {
+        self.cels.push(PendingCel {
+            frame,
+            layer,
+            origin,
+            size: image.dimensions(),
+            pixels: image.as_raw().clone(),
+            rgba: true,
+        });
+    }
+
+    /// Like [add_cel](AsepriteFileBuilder::add_cel), but with pixels in the
+    /// sprite's [PixelFormat], row by row, as in Aseprite files: four bytes
+    /// (red, green, blue, alpha) per RGBA pixel, two bytes (value, alpha) per
+    /// grayscale pixel, and the palette index of indexed pixels.
+    pub fn add_cel_pixels(
+        &mut self,
+        frame: u32,
+        layer: u32,
+        origin: (i16, i16),
+        size: (u32, u32),
+        pixels: &[u8],
+    ) {
+        self.cels.push(PendingCel {
+            frame,
+            layer,
+            origin,
+            size,
+            pixels: pixels.to_vec(),
+            rgba: false,
+        });
+    }
+
+    /// Add a tag spanning the frames `from_frame..=to_frame`.
+    pub fn add_tag(
+        &mut self,
+        name: &str,
+        from_frame: u16,
+        to_frame: u16,
+        animation_direction: AnimationDirection,
+    ) {
+        self.tags.push(Tag::new(
+            name.to_owned(),
+            from_frame,
+            to_frame,
+            animation_direction,
+            0,
+        ));
+    }
+
+    /// Add a slice.
+    pub fn add_slice(&mut self, slice: Slice) {
+        self.slices.push(slice);
+    }
+
+    /// Set the palette. Color `i` gets palette index `i`.
+    pub fn set_palette(&mut self, colors: &[[u8; 4]]) {
+        self.palette = Some(ColorPalette::from_colors(colors));
+    }
+
+    /// Create the sprite.
+    ///
+    /// # Errors
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the canvas is empty, the
+    /// sprite has no frames, or a cel, tag, or slice refers to a frame or
+    /// layer that does not exist. Also fails if a cel image is empty, wider or
+    /// higher than 65535 pixels, or its pixels do not match its size and the
+    /// pixel format, and if an indexed pixel refers to a missing palette
+    /// entry.
+    pub fn build(self) -> Result<AsepriteFile> {
+        if self.width == 0 || self.height == 0 {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Invalid canvas size: {}x{}",
+                self.width, self.height
+            )));
+        }
+        if self.frame_times.is_empty() || self.frame_times.len() > u16::MAX as usize {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Invalid number of frames: {}",
+                self.frame_times.len()
+            )));
+        }
+        let num_frames = self.frame_times.len() as u16;
+        let num_layers = self.layers.len();
+
+        let pixel_format = self.pixel_format;
+        let mut framedata = CelsData::new(num_frames as u32, pixel_format);
+        for pending in self.cels {
+            let PendingCel {
+                frame,
+                layer,
+                origin,
+                size: (width, height),
+                pixels,
+                rgba,
+            } = pending;
+            if width == 0 || height == 0 || width > u16::MAX as u32 || height > u16::MAX as u32 {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Invalid cel image size: {}x{}",
+                    width, height
+                )));
+            }
+            if rgba && pixel_format != PixelFormat::Rgba {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Cannot add an RGBA image to a sprite with pixel format {:?}",
+                    pixel_format
+                )));
+            }
+            let expected_len = width as usize * height as usize * pixel_format.bytes_per_pixel();
+            if pixels.len() != expected_len {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Cel of size {}x{} needs {} bytes of pixels, got {}",
+                    width,
+                    height,
+                    expected_len,
+                    pixels.len()
+                )));
+            }
+            if layer as usize >= num_layers {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Cel references a missing layer: {}",
+                    layer
+                )));
+            }
+            if frame >= num_frames as u32 {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Cel references a missing frame: {}",
+                    frame
+                )));
+            }
+            let frame = frame as u16;
+            let range = framedata
+                .frame_pixels_mut(frame)
+                .extend_from_bytes(&pixels)?;
+            let cel = RawCel {
+                data: CelData {
+                    layer_index: layer as u16,
+                    x: origin.0,
+                    y: origin.1,
+                    opacity: 255,
+                    z_index: 0,
+                },
+                content: CelContent::Raw(ImageContent {
+                    size: ImageSize {
+                        width: width as u16,
+                        height: height as u16,
+                    },
+                    pixels: PixelRange { frame, range },
+                }),
+                user_data: None,
+                extra: None,
+            };
+            framedata.add_cel(frame, cel)?;
+        }
+        for tag in &self.tags {
+            if tag.from_frame() > tag.to_frame() || tag.to_frame() >= num_frames as u32 {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Invalid frame range for tag {:?}: {}..={}",
+                    tag.name(),
+                    tag.from_frame(),
+                    tag.to_frame()
+                )));
+            }
+        }
+        for slice in &self.slices {
+            if let Some(key) = slice
+                .keys
+                .iter()
+                .find(|k| k.from_frame >= num_frames as u32)
+            {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Slice {:?} has a key for missing frame {}",
+                    slice.name, key.from_frame
+                )));
+            }
+        }
+
+        let layers = LayersData::from_vec(self.layers)?;
+        let tilesets = TilesetsById::new();
+        framedata.validate(&layers, self.palette.as_ref())?;
+
+        Ok(AsepriteFile {
+            width: self.width,
+            height: self.height,
+            num_frames,
+            pixel_format,
+            palette: self.palette,
+            layers,
+            color_profile: Some(ColorProfile::srgb()),
+            frame_times: self.frame_times,
+            tags: self.tags,
+            framedata,
+            external_files: ExternalFilesById::new(),
+            tilesets,
+            sprite_user_data: None,
+            slice_names: SliceNames::new(&self.slices),
+            slices: self.slices,
+            warnings: Vec::new(),
+            trailing_data: Vec::new(),
+            pixel_ratio: (1, 1),
+            grid: Grid::default(),
+            raw_chunks: Vec::new(),
+            metadata_only: false,
+            frame_cache: Default::default(),
+        })
+    }
+}
@@ -0,0 +1,969 @@
+/*!
+Cache decoded files for fast loading.
+
+Requires the `cache` feature. Most of the time spent parsing a file goes into
+decompressing its cels. Asset pipelines that load the same files again and
+again can store the decoded file with [AsepriteFile::to_cache] and load it
+from there with [AsepriteFile::from_cache], which skips decompression and
+most validation. [read_file_cached] does both, including the bookkeeping.
+
+```
+# use asefile::{cache, AsepriteFile};
+# let path = "./tests/data/layers_and_tags.aseprite";
+let source = std::fs::read(path).unwrap();
+let fingerprint = cache::fingerprint(&source);
+let ase = AsepriteFile::read(&source[..]).unwrap();
+let mut data = Vec::new();
+ase.to_cache(fingerprint, &mut data).unwrap();
+
+let cached = AsepriteFile::from_cache(&data[..], fingerprint).unwrap();
+assert_eq!(cached.num_layers(), ase.num_layers());
+```
+
+A cache starts with a header containing the version of this crate and the
+fingerprint of the source file. Caches written by other versions of this
+crate or for other source data are rejected, so the format can change between
+releases without further notice. Pixels are stored uncompressed, so caches
+are usually larger than the source file.
+*/
+use crate::{
+    cel::{CelContent, CelData, CelsData, ImageContent, ImageSize, PixelRange, RawCel},
+    color_profile::{ColorProfile, ColorProfileType},
+    external_file::{ExternalFile, ExternalFileId, ExternalFilesById},
+    file::{Grid, RawChunk},
+    hash::Fnv1a,
+    layer::{self, LayerData, LayerFlags, LayerType, LayersData},
+    palette::{ColorPalette, ColorPaletteEntry, MAX_COLORS},
+    parse,
+    pixel::Pixels,
+    reader::AseReader,
+    slice::{Slice, Slice9, SliceKey, SliceNames, SliceOrigin, SlicePivot, SliceSize},
+    tags::{self, Tag},
+    tile::{Tile, TileId, Tiles},
+    tilemap::{TileBitmaskHeader, Tilemap},
+    tileset::{ExternalTilesetReference, TileSize, Tileset, TilesetId, TilesetsById},
+    user_data::{self, UserData},
+    writer, AsepriteFile, AsepriteParseError, ParseWarning, PixelFormat, Result,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fs::{self, File},
+    hash::Hasher,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8; 8] = b"ASECACHE";
+// Increment when the layout changes, in addition to the crate version check.
+const FORMAT_VERSION: u32 = 1;
+const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// See the same constant in reader.rs.
+const MAX_PREALLOCATION: usize = 1 << 24;
+
+/// A fingerprint of the contents of a source file. It is stable across
+/// processes, platforms and versions of this crate.
+pub fn fingerprint(source: &[u8]) -> u64 {
+    let mut hasher = Fnv1a::new();
+    hasher.write(source);
+    hasher.finish()
+}
+
+/// Load the file at `path`, using the cache at `cache_path` if it is up to
+/// date. Otherwise the file is parsed and the cache is (re)written.
+///
+/// The source file is always read to compute its [fingerprint], but it is
+/// only parsed if the cache is missing, stale, or invalid.
+///
+/// # Errors
+///
+/// Fails like [AsepriteFile::read_file] if the file has to be parsed, and
+/// with [AsepriteParseError::IoError] if the cache cannot be written.
+pub fn read_file_cached(path: &Path, cache_path: &Path) -> Result<AsepriteFile> {
+    let source = fs::read(path)?;
+    let fingerprint = fingerprint(&source);
+    if let Ok(cache) = File::open(cache_path) {
+        if let Ok(file) = AsepriteFile::from_cache(BufReader::new(cache), fingerprint) {
+            return Ok(file);
+        }
+    }
+    let file = AsepriteFile::read(&source[..])?;
+    let mut w = BufWriter::new(File::create(cache_path)?);
+    file.to_cache(fingerprint, &mut w)?;
+    w.flush()?;
+    Ok(file)
+}
+
+/// # Caching
+///
+/// See the [cache](crate::cache) module. Requires the `cache` feature.
+impl AsepriteFile {
+    /// Write the decoded file to a cache. `source_fingerprint` identifies the
+    /// data the file was loaded from and is usually the [fingerprint] of the
+    /// source file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [io::ErrorKind::InvalidInput] if the file was
+    /// loaded with [read_metadata](AsepriteFile::read_metadata) and has no
+    /// pixels. Also returns any error of the underlying writer.
+    pub fn to_cache<W: Write>(&self, source_fingerprint: u64, w: W) -> io::Result<()> {
+        if self.metadata_only {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot cache a file loaded without its pixels",
+            ));
+        }
+        let mut w = CacheWriter(w);
+        w.0.write_all(MAGIC)?;
+        w.dword(FORMAT_VERSION)?;
+        w.string(CRATE_VERSION)?;
+        w.0.write_u64::<LittleEndian>(source_fingerprint)?;
+        write_file(self, &mut w)
+    }
+
+    /// Load a file from a cache written by [to_cache](AsepriteFile::to_cache).
+    ///
+    /// # Errors
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the cache was written by
+    /// a different version of this crate, for a different
+    /// `source_fingerprint`, or is corrupt, and
+    /// [AsepriteParseError::IoError] if it cannot be read.
+    pub fn from_cache<R: Read>(r: R, source_fingerprint: u64) -> Result<AsepriteFile> {
+        let mut r = CacheReader(r);
+        let mut magic = [0; 8];
+        r.0.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(AsepriteParseError::InvalidInput(
+                "Not an asefile cache".into(),
+            ));
+        }
+        let format_version = r.dword()?;
+        let crate_version = r.string()?;
+        if format_version != FORMAT_VERSION || crate_version != CRATE_VERSION {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Cache was written by asefile {} (format {}), expected {} (format {})",
+                crate_version, format_version, CRATE_VERSION, FORMAT_VERSION
+            )));
+        }
+        let fingerprint = r.0.read_u64::<LittleEndian>()?;
+        if fingerprint != source_fingerprint {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Stale cache: fingerprint {:016x}, expected {:016x}",
+                fingerprint, source_fingerprint
+            )));
+        }
+        read_file(&mut r)
+    }
+}
+
+struct CacheWriter<W>(W);
+
+impl<W: Write> CacheWriter<W> {
+    fn byte(&mut self, value: u8) -> io::Result<()> {
+        self.0.write_u8(value)
+    }
+
+    fn word(&mut self, value: u16) -> io::Result<()> {
+        self.0.write_u16::<LittleEndian>(value)
+    }
+
+    fn short(&mut self, value: i16) -> io::Result<()> {
+        self.0.write_i16::<LittleEndian>(value)
+    }
+
+    fn dword(&mut self, value: u32) -> io::Result<()> {
+        self.0.write_u32::<LittleEndian>(value)
+    }
+
+    fn long(&mut self, value: i32) -> io::Result<()> {
+        self.0.write_i32::<LittleEndian>(value)
+    }
+
+    fn len(&mut self, value: usize) -> io::Result<()> {
+        let value = u32::try_from(value)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too large for a cache"))?;
+        self.dword(value)
+    }
+
+    fn bytes(&mut self, value: &[u8]) -> io::Result<()> {
+        self.len(value.len())?;
+        self.0.write_all(value)
+    }
+
+    fn string(&mut self, value: &str) -> io::Result<()> {
+        self.bytes(value.as_bytes())
+    }
+
+    fn option<T>(
+        &mut self,
+        value: Option<&T>,
+        write: impl FnOnce(&mut Self, &T) -> io::Result<()>,
+    ) -> io::Result<()> {
+        match value {
+            Some(value) => {
+                self.byte(1)?;
+                write(self, value)
+            }
+            None => self.byte(0),
+        }
+    }
+
+    fn user_data(&mut self, value: Option<&UserData>) -> io::Result<()> {
+        self.option(value, |w, user_data| {
+            w.option(user_data.text.as_ref(), |w, text| w.string(text))?;
+            w.option(user_data.color.as_ref(), |w, color| w.0.write_all(&color.0))?;
+            // Extensions by name, each with its properties as in files.
+            w.len(user_data.properties.len())?;
+            for (extension, properties) in &user_data.properties {
+                w.string(extension)?;
+                w.bytes(&writer::encode_properties(properties)?)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+struct CacheReader<R>(R);
+
+impl<R: Read> CacheReader<R> {
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.0.read_u8()?)
+    }
+
+    fn word(&mut self) -> Result<u16> {
+        Ok(self.0.read_u16::<LittleEndian>()?)
+    }
+
+    fn short(&mut self) -> Result<i16> {
+        Ok(self.0.read_i16::<LittleEndian>()?)
+    }
+
+    fn dword(&mut self) -> Result<u32> {
+        Ok(self.0.read_u32::<LittleEndian>()?)
+    }
+
+    fn long(&mut self) -> Result<i32> {
+        Ok(self.0.read_i32::<LittleEndian>()?)
+    }
+
+    fn bool(&mut self) -> Result<bool> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(AsepriteParseError::InvalidInput(format!(
+                "Invalid boolean in cache: {}",
+                value
+            ))),
+        }
+    }
+
+    // A length or count. Only used to size buffers up to MAX_PREALLOCATION,
+    // so a corrupt cache cannot trigger huge allocations.
+    fn len(&mut self) -> Result<usize> {
+        self.dword().map(|len| len as usize)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        let len = self.len()?;
+        let mut bytes = Vec::with_capacity(len.min(MAX_PREALLOCATION));
+        (&mut self.0).take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.bytes()?)?)
+    }
+
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T>) -> Result<Option<T>> {
+        if self.bool()? {
+            read(self).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn vec<T>(&mut self, mut read: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let len = self.len()?;
+        let mut result = Vec::with_capacity(len.min(MAX_PREALLOCATION / 64));
+        for _ in 0..len {
+            result.push(read(self)?);
+        }
+        Ok(result)
+    }
+
+    fn user_data(&mut self) -> Result<Option<UserData>> {
+        self.option(|r| {
+            let text = r.option(|r| r.string())?;
+            let color = r.option(|r| {
+                let mut color = [0; 4];
+                r.0.read_exact(&mut color)?;
+                Ok(crate::image::Rgba(color))
+            })?;
+            let num_extensions = r.len()?;
+            let mut properties = BTreeMap::new();
+            for _ in 0..num_extensions {
+                let extension = r.string()?;
+                let data = r.bytes()?;
+                let mut reader = AseReader::new(&data);
+                properties.insert(extension, user_data::parse_properties(&mut reader, 0)?);
+            }
+            Ok(UserData {
+                text,
+                color,
+                properties,
+            })
+        })
+    }
+
+    fn pixels(&mut self, pixel_format: PixelFormat) -> Result<Pixels> {
+        let mut pixels = Pixels::new(pixel_format);
+        pixels.extend_from_bytes(&self.bytes()?)?;
+        Ok(pixels)
+    }
+}
+
+fn invalid(message: &str) -> AsepriteParseError {
+    AsepriteParseError::InvalidInput(format!("Corrupt cache: {}", message))
+}
+
+// The body of the cache: all fields of the file, in declaration order, with
+// the same encodings as in Aseprite files where possible.
+
+fn write_file<W: Write>(file: &AsepriteFile, w: &mut CacheWriter<W>) -> io::Result<()> {
+    w.word(file.width)?;
+    w.word(file.height)?;
+    w.word(file.num_frames)?;
+    let (color_depth, transparent_color_index) = match file.pixel_format {
+        PixelFormat::Rgba => (32, 0),
+        PixelFormat::Grayscale => (16, 0),
+        PixelFormat::Indexed {
+            transparent_color_index,
+        } => (8, transparent_color_index),
+    };
+    w.word(color_depth)?;
+    w.byte(transparent_color_index)?;
+
+    w.option(file.palette.as_ref(), |w, palette| {
+        w.len(palette.num_colors() as usize)?;
+        for (_, entry) in palette {
+            w.dword(entry.id)?;
+            w.0.write_all(&entry.rgba8)?;
+            w.option(entry.name.as_ref(), |w, name| w.string(name))?;
+        }
+        Ok(())
+    })?;
+
+    w.len(file.layers.layers.len())?;
+    for layer in &file.layers.layers {
+        w.dword(layer.flags.bits())?;
+        w.string(&layer.name)?;
+        w.word(layer.blend_mode as u16)?;
+        w.byte(layer.opacity)?;
+        match layer.layer_type {
+            LayerType::Image => w.word(0)?,
+            LayerType::Group => w.word(1)?,
+            LayerType::Tilemap(TilesetId(id)) => {
+                w.word(2)?;
+                w.dword(id)?;
+            }
+        }
+        w.user_data(layer.user_data.as_ref())?;
+        w.word(layer.child_level)?;
+        w.option(layer.uuid.as_ref(), |w, uuid| w.0.write_all(uuid))?;
+    }
+
+    for duration in &file.frame_times {
+        w.word(*duration)?;
+    }
+
+    w.len(file.tags.len())?;
+    for tag in &file.tags {
+        w.string(tag.name())?;
+        w.word(tag.from_frame() as u16)?;
+        w.word(tag.to_frame() as u16)?;
+        w.byte(tag.animation_direction() as u8)?;
+        w.word(tag.repeat())?;
+        w.user_data(tag.user_data())?;
+    }
+
+    for frame in 0..file.num_frames {
+        write_frame(&file.framedata, frame, w)?;
+    }
+
+    let mut external_files: Vec<&ExternalFile> = file.external_files.map().values().collect();
+    external_files.sort_by_key(|external_file| *external_file.id().value());
+    w.len(external_files.len())?;
+    for external_file in external_files {
+        w.dword(*external_file.id().value())?;
+        w.string(external_file.name())?;
+        w.byte(external_file.file_type)?;
+    }
+
+    let mut tilesets: Vec<&Tileset> = file.tilesets.map().values().collect();
+    tilesets.sort_by_key(|tileset| tileset.id.0);
+    w.len(tilesets.len())?;
+    for tileset in tilesets {
+        w.dword(tileset.id.0)?;
+        w.byte(tileset.empty_tile_is_id_zero as u8)?;
+        w.dword(tileset.tile_count)?;
+        w.word(tileset.tile_size.width)?;
+        w.word(tileset.tile_size.height)?;
+        w.short(tileset.base_index)?;
+        w.string(&tileset.name)?;
+        w.option(tileset.external_file.as_ref(), |w, reference| {
+            w.dword(*reference.external_file_id.value())?;
+            w.dword(reference.tileset_id.0)
+        })?;
+        w.option(tileset.pixels.as_ref(), |w, pixels| {
+            w.bytes(&pixels.all().to_bytes())
+        })?;
+        w.user_data(tileset.user_data.as_ref())?;
+        w.len(tileset.tile_user_data.len())?;
+        for user_data in &tileset.tile_user_data {
+            w.user_data(user_data.as_ref())?;
+        }
+    }
+
+    w.user_data(file.sprite_user_data.as_ref())?;
+
+    w.len(file.slices.len())?;
+    for slice in &file.slices {
+        w.string(&slice.name)?;
+        w.len(slice.keys.len())?;
+        for key in &slice.keys {
+            w.dword(key.from_frame)?;
+            w.long(key.origin.x)?;
+            w.long(key.origin.y)?;
+            w.dword(key.size.width)?;
+            w.dword(key.size.height)?;
+            w.option(key.slice9.as_ref(), |w, slice9| {
+                w.long(slice9.center_x)?;
+                w.long(slice9.center_y)?;
+                w.dword(slice9.center_width)?;
+                w.dword(slice9.center_height)
+            })?;
+            w.option(key.pivot.as_ref(), |w, pivot| {
+                w.long(pivot.x)?;
+                w.long(pivot.y)
+            })?;
+        }
+        w.user_data(slice.user_data.as_ref())?;
+    }
+
+    w.len(file.warnings.len())?;
+    for warning in &file.warnings {
+        match *warning {
+            ParseWarning::TrailingData { offset, len } => {
+                w.byte(0)?;
+                w.0.write_u64::<LittleEndian>(offset)?;
+                w.0.write_u64::<LittleEndian>(len)?;
+            }
+            ParseWarning::FileSizeMismatch { declared, actual } => {
+                w.byte(1)?;
+                w.dword(declared)?;
+                w.0.write_u64::<LittleEndian>(actual)?;
+            }
+            ParseWarning::UnknownChunk { frame, chunk_type } => {
+                w.byte(2)?;
+                w.word(frame)?;
+                w.word(chunk_type)?;
+            }
+            ParseWarning::UnknownLayerFlags { layer, flags } => {
+                w.byte(3)?;
+                w.dword(layer)?;
+                w.word(flags)?;
+            }
+            ParseWarning::UnusedUserData { frame } => {
+                w.byte(4)?;
+                w.word(frame)?;
+            }
+            ParseWarning::MissingLayer { frame, layer } => {
+                w.byte(5)?;
+                w.word(frame)?;
+                w.word(layer)?;
+            }
+            ParseWarning::Truncated {
+                frames,
+                declared_frames,
+            } => {
+                w.byte(6)?;
+                w.word(frames)?;
+                w.word(declared_frames)?;
+            }
+            ParseWarning::TileIdOutOfRange {
+                frame,
+                layer,
+                tile_id,
+            } => {
+                w.byte(7)?;
+                w.word(frame)?;
+                w.word(layer)?;
+                w.dword(tile_id)?;
+            }
+        }
+    }
+    w.bytes(&file.trailing_data)?;
+
+    w.byte(file.pixel_ratio.0)?;
+    w.byte(file.pixel_ratio.1)?;
+    let grid = &file.grid;
+    w.short(grid.x)?;
+    w.short(grid.y)?;
+    w.word(grid.width)?;
+    w.word(grid.height)?;
+    w.option(file.color_profile.as_ref(), |w, color_profile| {
+        w.byte(match color_profile.profile_type {
+            ColorProfileType::None => 0,
+            ColorProfileType::Srgb => 1,
+            ColorProfileType::Icc => 2,
+        })?;
+        w.option(color_profile.fixed_gamma.as_ref(), |w, gamma| {
+            w.0.write_f64::<LittleEndian>(*gamma)
+        })?;
+        w.option(color_profile.icc_profile.as_ref(), |w, icc| w.bytes(icc))
+    })?;
+    w.len(file.raw_chunks.len())?;
+    for chunk in &file.raw_chunks {
+        w.word(chunk.frame)?;
+        w.dword(chunk.index)?;
+        w.word(chunk.chunk_type)?;
+        w.bytes(&chunk.data)?;
+    }
+    Ok(())
+}
+
+// The pixels of the frame's image cels followed by the cels. Pixels in the
+// frame's buffer that no cel refers to, e.g., those of cels whose layer does
+// not exist, are left out.
+fn write_frame<W: Write>(
+    framedata: &CelsData,
+    frame: u16,
+    w: &mut CacheWriter<W>,
+) -> io::Result<()> {
+    let cels: Vec<(u32, &RawCel)> = framedata.frame_cels(frame).collect();
+    let mut bytes = Vec::new();
+    for (_, cel) in &cels {
+        if let CelContent::Raw(ImageContent { pixels, .. }) = &cel.content {
+            bytes.extend_from_slice(&framedata.pixels(pixels).to_bytes());
+        }
+    }
+    w.bytes(&bytes)?;
+    w.len(cels.len())?;
+    let mut start = 0;
+    for (_, cel) in cels {
+        w.word(cel.data.layer_index)?;
+        w.short(cel.data.x)?;
+        w.short(cel.data.y)?;
+        w.byte(cel.data.opacity)?;
+        w.short(cel.data.z_index)?;
+        w.user_data(cel.user_data.as_ref())?;
+        w.option(cel.extra.as_ref(), |w, extra| w.bytes(extra))?;
+        match &cel.content {
+            CelContent::Raw(ImageContent { size, pixels }) => {
+                w.word(0)?;
+                w.word(size.width)?;
+                w.word(size.height)?;
+                w.len(start)?;
+                w.len(pixels.range.len())?;
+                start += pixels.range.len();
+            }
+            CelContent::Linked(frame) => {
+                w.word(1)?;
+                w.word(*frame)?;
+            }
+            CelContent::Tilemap(tilemap) => {
+                w.word(3)?;
+                w.word(tilemap.width)?;
+                w.word(tilemap.height)?;
+                w.word(tilemap.bits_per_tile)?;
+                let header = &tilemap.bitmask_header;
+                w.dword(header.tile_id)?;
+                w.dword(header.x_flip)?;
+                w.dword(header.y_flip)?;
+                w.dword(header.rotate_90cw)?;
+                for tile in tilemap.tiles.iter() {
+                    w.dword(tile.id.0)?;
+                    let flags = tile.flip_x as u8
+                        | (tile.flip_y as u8) << 1
+                        | (tile.rotate_90cw as u8) << 2;
+                    w.byte(flags)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_file<R: Read>(r: &mut CacheReader<R>) -> Result<AsepriteFile> {
+    let width = r.word()?;
+    let height = r.word()?;
+    let num_frames = r.word()?;
+    let color_depth = r.word()?;
+    let transparent_color_index = r.byte()?;
+    let pixel_format = parse::parse_pixel_format(color_depth, transparent_color_index)?;
+    if width == 0 || height == 0 || num_frames == 0 {
+        return Err(invalid("empty sprite"));
+    }
+
+    let palette = r.option(|r| {
+        let entries = r.vec(|r| {
+            let id = r.dword()?;
+            if id >= MAX_COLORS {
+                return Err(invalid("palette index"));
+            }
+            let mut rgba8 = [0; 4];
+            r.0.read_exact(&mut rgba8)?;
+            let name = r.option(|r| r.string())?;
+            Ok((id, ColorPaletteEntry { id, rgba8, name }))
+        })?;
+        Ok(ColorPalette {
+            entries: entries.into_iter().collect(),
+        })
+    })?;
+
+    let layers = r.vec(|r| {
+        let flags = LayerFlags::from_bits_truncate(r.dword()?);
+        let name = r.string()?;
+        let blend_mode = layer::parse_blend_mode(r.word()?)?;
+        let opacity = r.byte()?;
+        let layer_type = r.word()?;
+        let layer_type = match layer_type {
+            2 => LayerType::Tilemap(TilesetId(r.dword()?)),
+            0 => LayerType::Image,
+            1 => LayerType::Group,
+            _ => return Err(invalid("layer type")),
+        };
+        let user_data = r.user_data()?;
+        let child_level = r.word()?;
+        let uuid = r.option(|r| {
+            let mut uuid = [0; 16];
+            r.0.read_exact(&mut uuid)?;
+            Ok(uuid)
+        })?;
+        Ok(LayerData {
+            flags,
+            name,
+            blend_mode,
+            opacity,
+            layer_type,
+            user_data,
+            child_level,
+            uuid,
+        })
+    })?;
+    let layers = LayersData::from_vec(layers)?;
+
+    let frame_times = (0..num_frames)
+        .map(|_| r.word())
+        .collect::<Result<Vec<_>>>()?;
+
+    let tags = r.vec(|r| {
+        let name = r.string()?;
+        let from_frame = r.word()?;
+        let to_frame = r.word()?;
+        let direction = tags::parse_animation_direction(r.byte()?)?;
+        let repeat = r.word()?;
+        if from_frame > to_frame || to_frame >= num_frames {
+            return Err(invalid("tag range"));
+        }
+        let mut tag = Tag::new(name, from_frame, to_frame, direction, repeat);
+        tag.user_data = r.user_data()?;
+        Ok(tag)
+    })?;
+
+    let mut framedata = CelsData::new(num_frames as u32, pixel_format);
+    for frame in 0..num_frames {
+        read_frame(r, &mut framedata, frame, num_frames, pixel_format)?;
+    }
+
+    let mut external_files = ExternalFilesById::new();
+    for external_file in r.vec(|r| {
+        let id = ExternalFileId::new(r.dword()?);
+        let name = r.string()?;
+        Ok(ExternalFile::new(id, name, r.byte()?))
+    })? {
+        external_files.add(external_file);
+    }
+
+    let mut tilesets = TilesetsById::new();
+    for tileset in r.vec(|r| {
+        let id = TilesetId(r.dword()?);
+        let empty_tile_is_id_zero = r.bool()?;
+        let tile_count = r.dword()?;
+        let tile_size = TileSize {
+            width: r.word()?,
+            height: r.word()?,
+        };
+        let base_index = r.short()?;
+        let name = r.string()?;
+        let external_file = r.option(|r| {
+            Ok(ExternalTilesetReference {
+                external_file_id: ExternalFileId::new(r.dword()?),
+                tileset_id: TilesetId(r.dword()?),
+            })
+        })?;
+        let pixels = r.option(|r| r.pixels(pixel_format))?;
+        let expected = tile_count as u64 * tile_size.pixels_per_tile() as u64;
+        if pixels.as_ref().is_some_and(|p| p.len() as u64 != expected) {
+            return Err(invalid("tileset size"));
+        }
+        let user_data = r.user_data()?;
+        let tile_user_data = r.vec(|r| r.user_data())?;
+        if tile_user_data.len() > tile_count as usize {
+            return Err(invalid("tile user data"));
+        }
+        Ok(Tileset {
+            id,
+            empty_tile_is_id_zero,
+            tile_count,
+            tile_size,
+            base_index,
+            name,
+            external_file,
+            pixels,
+            user_data,
+            tile_user_data,
+        })
+    })? {
+        tilesets.add(tileset);
+    }
+
+    let sprite_user_data = r.user_data()?;
+
+    let slices = r.vec(|r| {
+        let name = r.string()?;
+        let keys = r.vec(|r| {
+            Ok(SliceKey {
+                from_frame: r.dword()?,
+                origin: SliceOrigin {
+                    x: r.long()?,
+                    y: r.long()?,
+                },
+                size: SliceSize {
+                    width: r.dword()?,
+                    height: r.dword()?,
+                },
+                slice9: r.option(|r| {
+                    Ok(Slice9 {
+                        center_x: r.long()?,
+                        center_y: r.long()?,
+                        center_width: r.dword()?,
+                        center_height: r.dword()?,
+                    })
+                })?,
+                pivot: r.option(|r| {
+                    Ok(SlicePivot {
+                        x: r.long()?,
+                        y: r.long()?,
+                    })
+                })?,
+            })
+        })?;
+        let user_data = r.user_data()?;
+        Ok(Slice {
+            name,
+            keys,
+            user_data,
+        })
+    })?;
+
+    let warnings = r.vec(|r| match r.byte()? {
+        0 => Ok(ParseWarning::TrailingData {
+            offset: r.0.read_u64::<LittleEndian>()?,
+            len: r.0.read_u64::<LittleEndian>()?,
+        }),
+        1 => Ok(ParseWarning::FileSizeMismatch {
+            declared: r.dword()?,
+            actual: r.0.read_u64::<LittleEndian>()?,
+        }),
+        2 => Ok(ParseWarning::UnknownChunk {
+            frame: r.word()?,
+            chunk_type: r.word()?,
+        }),
+        3 => Ok(ParseWarning::UnknownLayerFlags {
+            layer: r.dword()?,
+            flags: r.word()?,
+        }),
+        4 => Ok(ParseWarning::UnusedUserData { frame: r.word()? }),
+        5 => Ok(ParseWarning::MissingLayer {
+            frame: r.word()?,
+            layer: r.word()?,
+        }),
+        6 => Ok(ParseWarning::Truncated {
+            frames: r.word()?,
+            declared_frames: r.word()?,
+        }),
+        7 => Ok(ParseWarning::TileIdOutOfRange {
+            frame: r.word()?,
+            layer: r.word()?,
+            tile_id: r.dword()?,
+        }),
+        _ => Err(invalid("warning")),
+    })?;
+    let trailing_data = r.bytes()?;
+
+    let pixel_ratio = (r.byte()?, r.byte()?);
+    if pixel_ratio.0 == 0 || pixel_ratio.1 == 0 {
+        return Err(invalid("pixel ratio"));
+    }
+    let grid = Grid {
+        x: r.short()?,
+        y: r.short()?,
+        width: r.word()?,
+        height: r.word()?,
+    };
+    let color_profile = r.option(|r| {
+        let profile_type = match r.byte()? {
+            0 => ColorProfileType::None,
+            1 => ColorProfileType::Srgb,
+            2 => ColorProfileType::Icc,
+            _ => return Err(invalid("color profile type")),
+        };
+        Ok(ColorProfile {
+            profile_type,
+            fixed_gamma: r.option(|r| Ok(r.0.read_f64::<LittleEndian>()?))?,
+            icc_profile: r.option(|r| r.bytes())?,
+        })
+    })?;
+    let raw_chunks = r.vec(|r| {
+        let frame = r.word()?;
+        if frame >= num_frames {
+            return Err(invalid("chunk frame"));
+        }
+        Ok(RawChunk {
+            frame,
+            index: r.dword()?,
+            chunk_type: r.word()?,
+            data: r.bytes()?,
+        })
+    })?;
+
+    // The same checks as after parsing, which make sure that the ids stored
+    // in layers and cels are valid.
+    tilesets.validate(&pixel_format, &palette)?;
+    layers.validate(&tilesets)?;
+    framedata.validate(&layers, palette.as_ref())?;
+
+    Ok(AsepriteFile {
+        width,
+        height,
+        num_frames,
+        pixel_format,
+        palette,
+        layers,
+        color_profile,
+        frame_times,
+        tags,
+        framedata,
+        external_files,
+        tilesets,
+        sprite_user_data,
+        slice_names: SliceNames::new(&slices),
+        slices,
+        warnings,
+        trailing_data,
+        pixel_ratio,
+        grid,
+        raw_chunks,
+        metadata_only: false,
+        frame_cache: Default::default(),
+    })
+}
+
+fn read_frame<R: Read>(
+    r: &mut CacheReader<R>,
+    framedata: &mut CelsData,
+    frame: u16,
+    num_frames: u16,
+    pixel_format: PixelFormat,
+) -> Result<()> {
+    *framedata.frame_pixels_mut(frame) = r.pixels(pixel_format)?;
+    let num_pixels = framedata.frame_pixels_mut(frame).len();
+    let num_cels = r.len()?;
+    for _ in 0..num_cels {
+        let data = CelData {
+            layer_index: r.word()?,
+            x: r.short()?,
+            y: r.short()?,
+            opacity: r.byte()?,
+            z_index: r.short()?,
+        };
+        let user_data = r.user_data()?;
+        let extra = r.option(|r| r.bytes())?;
+        let content = match r.word()? {
+            0 => {
+                let size = ImageSize {
+                    width: r.word()?,
+                    height: r.word()?,
+                };
+                let start = r.len()?;
+                let len = r.len()?;
+                if len != size.pixel_count() || start.saturating_add(len) > num_pixels {
+                    return Err(invalid("cel pixels"));
+                }
+                CelContent::Raw(ImageContent {
+                    size,
+                    pixels: PixelRange {
+                        frame,
+                        range: start..start + len,
+                    },
+                })
+            }
+            1 => {
+                let source = r.word()?;
+                if source >= num_frames {
+                    return Err(invalid("linked cel"));
+                }
+                CelContent::Linked(source)
+            }
+            3 => {
+                let width = r.word()?;
+                let height = r.word()?;
+                let bits_per_tile = r.word()?;
+                let bitmask_header = TileBitmaskHeader {
+                    tile_id: r.dword()?,
+                    x_flip: r.dword()?,
+                    y_flip: r.dword()?,
+                    rotate_90cw: r.dword()?,
+                };
+                let num_tiles = width as usize * height as usize;
+                let mut tiles = Vec::with_capacity(num_tiles.min(MAX_PREALLOCATION / 8));
+                for _ in 0..num_tiles {
+                    let id = TileId(r.dword()?);
+                    let flags = r.byte()?;
+                    tiles.push(Tile {
+                        id,
+                        flip_x: flags & 1 != 0,
+                        flip_y: flags & 2 != 0,
+                        rotate_90cw: flags & 4 != 0,
+                    });
+                }
+                CelContent::Tilemap(Tilemap {
+                    width,
+                    height,
+                    tiles: Tiles(tiles),
+                    bits_per_tile,
+                    bitmask_header,
+                })
+            }
+            _ => return Err(invalid("cel type")),
+        };
+        framedata.add_cel(
+            frame,
+            RawCel {
+                data,
+                content,
+                user_data,
+                extra,
+            },
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,350 @@
+/*!
+A C interface for engines and tools that are not written in Rust.
+
+Requires the `capi` feature. Build a shared library with
+
+```text
+cargo rustc --release --features capi --crate-type cdylib
+```
+
+and include `include/asefile.h`. A sprite is opened with [asefile_open] or
+[asefile_read], which return an opaque handle that must be released with
+[asefile_free]:
+
+```c
+AseFile *file = asefile_open("player.aseprite");
+if (!file) {
+    fprintf(stderr, "%s\n", asefile_last_error());
+    return 1;
+}
+uint32_t width = asefile_width(file);
+uint32_t height = asefile_height(file);
+uint8_t *pixels = malloc(width * height * 4);
+for (uint32_t frame = 0; frame < asefile_num_frames(file); frame++) {
+    asefile_frame_rgba(file, frame, pixels, width * height * 4);
+    /* ... */
+}
+free(pixels);
+asefile_free(file);
+```
+
+Functions that can fail return a null pointer or `-1`. Accessors that return
+a number return 0 if the handle is null. The message of the last error on the
+calling thread is available from [asefile_last_error].
+Strings returned by the library stay valid until the file is freed.
+*/
+use crate::{AnimationDirection, AsepriteFile};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+/// An opened sprite.
+pub struct AseFile {
+    file: AsepriteFile,
+    // Names as C strings, so they can be borrowed by the caller.
+    tag_names: Vec<CString>,
+    slice_names: Vec<CString>,
+}
+
+/// A tag, see [Tag](crate::Tag).
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AseTag {
+    /// The name of the tag, valid until the file is freed.
+    pub name: *const c_char,
+    /// First frame included in the tag.
+    pub from_frame: u32,
+    /// Last frame included in the tag.
+    pub to_frame: u32,
+    /// 0 for forward, 1 for reverse and 2 for ping-pong playback. The
+    /// ping-pong reverse direction of newer Aseprite versions is not
+    /// supported, files that use it fail to open.
+    pub direction: u32,
+    /// How often the animation plays, 0 means forever.
+    pub repeat: u32,
+}
+
+/// The bounds of a slice in one frame, see [Slice](crate::Slice).
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AseSlice {
+    /// The name of the slice, valid until the file is freed.
+    pub name: *const c_char,
+    /// Left edge of the slice.
+    pub x: i32,
+    /// Top edge of the slice.
+    pub y: i32,
+    /// Width of the slice.
+    pub width: u32,
+    /// Height of the slice.
+    pub height: u32,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).unwrap();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+// Borrows the file behind a handle, or sets the last error if it is null.
+unsafe fn handle<'a>(file: *const AseFile) -> Option<&'a AseFile> {
+    if file.is_null() {
+        set_last_error("File is null".to_owned());
+    }
+    file.as_ref()
+}
+
+fn open(result: crate::Result<AsepriteFile>) -> *mut AseFile {
+    match result {
+        Ok(file) => {
+            let c_string = |name: &str| CString::new(name.replace('\0', "")).unwrap();
+            let tag_names = (0..file.num_tags())
+                .map(|tag_id| c_string(file.tag(tag_id).name()))
+                .collect();
+            let slice_names = file
+                .slices()
+                .iter()
+                .map(|slice| c_string(&slice.name))
+                .collect();
+            Box::into_raw(Box::new(AseFile {
+                file,
+                tag_names,
+                slice_names,
+            }))
+        }
+        Err(err) => {
+            set_last_error(err.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Open the sprite at the path. Returns null on failure.
+///
+/// # Safety
+///
+/// `path` must be a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn asefile_open(path: *const c_char) -> *mut AseFile {
+    if path.is_null() {
+        set_last_error("Path is null".to_owned());
+        return ptr::null_mut();
+    }
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path,
+        Err(_) => {
+            set_last_error("Path is not valid UTF-8".to_owned());
+            return ptr::null_mut();
+        }
+    };
+    open(AsepriteFile::read_file(path.as_ref()))
+}
+
+/// Parse a sprite from `len` bytes of memory. Returns null on failure. The
+/// data is not used after the function returns.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn asefile_read(data: *const u8, len: usize) -> *mut AseFile {
+    if data.is_null() {
+        set_last_error("Data is null".to_owned());
+        return ptr::null_mut();
+    }
+    open(AsepriteFile::read(std::slice::from_raw_parts(data, len)))
+}
+
+/// Release a file. Does nothing if `file` is null.
+///
+/// # Safety
+///
+/// `file` must be null or a handle that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn asefile_free(file: *mut AseFile) {
+    if !file.is_null() {
+        drop(Box::from_raw(file));
+    }
+}
+
+/// A message describing the last error on this thread, or null. Valid until
+/// the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn asefile_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Width of the sprite in pixels, or 0 if `file` is null.
+///
+/// # Safety
+///
+/// `file` must be null or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn asefile_width(file: *const AseFile) -> u32 {
+    handle(file).map_or(0, |handle| handle.file.width() as u32)
+}
+
+/// Height of the sprite in pixels, or 0 if `file` is null.
+///
+/// # Safety
+///
+/// `file` must be null or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn asefile_height(file: *const AseFile) -> u32 {
+    handle(file).map_or(0, |handle| handle.file.height() as u32)
+}
+
+/// Number of frames of the sprite, or 0 if `file` is null.
+///
+/// # Safety
+///
+/// `file` must be null or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn asefile_num_frames(file: *const AseFile) -> u32 {
+    handle(file).map_or(0, |handle| handle.file.num_frames())
+}
+
+/// Duration of the frame in milliseconds, or 0 if `file` is null or the frame
+/// does not exist.
+///
+/// # Safety
+///
+/// `file` must be null or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn asefile_frame_duration(file: *const AseFile, frame: u32) -> u32 {
+    match handle(file) {
+        Some(handle) if frame < handle.file.num_frames() => handle.file.frame(frame).duration(),
+        _ => 0,
+    }
+}
+
+/// Write the composited frame into `out` as RGBA data, 4 bytes per pixel in
+/// rows from top to bottom. `len` must be at least `width * height * 4`.
+/// Returns 0 on success and -1 if `file` is null, the frame does not exist or
+/// the buffer is too small.
+///
+/// # Safety
+///
+/// `file` must be null or a valid handle and `out` must point to `len`
+/// writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn asefile_frame_rgba(
+    file: *const AseFile,
+    frame: u32,
+    out: *mut u8,
+    len: usize,
+) -> c_int {
+    let file = match handle(file) {
+        Some(handle) => &handle.file,
+        None => return -1,
+    };
+    if frame >= file.num_frames() {
+        set_last_error(format!("Frame does not exist: {}", frame));
+        return -1;
+    }
+    let needed = file.width() * file.height() * 4;
+    if out.is_null() || len < needed {
+        set_last_error(format!("Buffer needs at least {} bytes", needed));
+        return -1;
+    }
+    let image = file.frame(frame).raw_image();
+    std::slice::from_raw_parts_mut(out, needed).copy_from_slice(image.as_raw());
+    0
+}
+
+/// Number of tags of the sprite, or 0 if `file` is null.
+///
+/// # Safety
+///
+/// `file` must be null or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn asefile_num_tags(file: *const AseFile) -> u32 {
+    handle(file).map_or(0, |handle| handle.file.num_tags())
+}
+
+/// Write the tag into `out`. Returns 0 on success and -1 if `file` is null or
+/// the tag does not exist.
+///
+/// # Safety
+///
+/// `file` must be null or a valid handle and `out` must point to a writable
+/// `AseTag`.
+#[no_mangle]
+pub unsafe extern "C" fn asefile_tag(file: *const AseFile, tag_id: u32, out: *mut AseTag) -> c_int {
+    let handle = match handle(file) {
+        Some(handle) => handle,
+        None => return -1,
+    };
+    if tag_id >= handle.file.num_tags() || out.is_null() {
+        set_last_error(format!("Tag does not exist: {}", tag_id));
+        return -1;
+    }
+    let tag = handle.file.tag(tag_id);
+    *out = AseTag {
+        name: handle.tag_names[tag_id as usize].as_ptr(),
+        from_frame: tag.from_frame(),
+        to_frame: tag.to_frame(),
+        direction: match tag.animation_direction() {
+            AnimationDirection::Forward => 0,
+            AnimationDirection::Reverse => 1,
+            AnimationDirection::PingPong => 2,
+        },
+        repeat: tag.repeat() as u32,
+    };
+    0
+}
+
+/// Number of slices of the sprite, or 0 if `file` is null.
+///
+/// # Safety
+///
+/// `file` must be null or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn asefile_num_slices(file: *const AseFile) -> u32 {
+    handle(file).map_or(0, |handle| handle.file.slices().len() as u32)
+}
+
+/// Write the bounds of the slice in the given frame into `out`. Returns 0 on
+/// success and -1 if `file` is null, the slice does not exist or it has no
+/// bounds in the frame.
+///
+/// # Safety
+///
+/// `file` must be null or a valid handle and `out` must point to a writable
+/// `AseSlice`.
+#[no_mangle]
+pub unsafe extern "C" fn asefile_slice(
+    file: *const AseFile,
+    slice_id: u32,
+    frame: u32,
+    out: *mut AseSlice,
+) -> c_int {
+    let handle = match handle(file) {
+        Some(handle) => handle,
+        None => return -1,
+    };
+    let slices = handle.file.slices();
+    if slice_id as usize >= slices.len() || out.is_null() {
+        set_last_error(format!("Slice does not exist: {}", slice_id));
+        return -1;
+    }
+    let key = match slices[slice_id as usize].key_for_frame(frame) {
+        Some(key) => key,
+        None => {
+            set_last_error(format!("Slice has no bounds in frame {}", frame));
+            return -1;
+        }
+    };
+    *out = AseSlice {
+        name: handle.slice_names[slice_id as usize].as_ptr(),
+        x: key.origin.x,
+        y: key.origin.y,
+        width: key.size.width,
+        height: key.size.height,
+    };
+    0
+}
@@ -1,15 +1,25 @@
+use crate::hash::Fnv1a;
 use crate::layer::{LayerData, LayerType};
-use crate::pixel::Pixels;
+use crate::limits::Budget;
+use crate::pixel::{FramePixels, PixelsRef};
+#[cfg(feature = "std")]
+use crate::pixel::{Pixels, SharedBytes};
 use crate::reader::AseReader;
 use crate::tilemap::Tilemap;
+use crate::tileset::TilesetsById;
 use crate::user_data::UserData;
 use crate::{
-    layer::LayersData, AsepriteFile, AsepriteParseError, ColorPalette, PixelFormat, Result,
+    layer::LayersData, raw_image, AsepriteFile, AsepriteParseError, ColorPalette, ParseWarning,
+    PixelFormat, Result,
 };
+use alloc::{format, vec, vec::Vec};
 
-use image::RgbaImage;
-use std::fmt;
-use std::io::Read;
+#[cfg(feature = "image")]
+use crate::image::RgbaImage;
+use crate::io::Read;
+use core::fmt;
+use core::hash::Hasher;
+use core::ops::Range;
 
 /// A reference to a single Cel. This contains the image data at a specific
 /// layer and frame. In the timeline view these are the dots.
@@ -24,11 +34,30 @@ pub struct Cel<'a> {
 impl<'a> Cel<'a> {
     /// This cel as an image. Result has the same dimensions as the [AsepriteFile].
     /// If the cel is empty, all image pixels will be transparent.
+    #[cfg(feature = "image")]
     pub fn image(&self) -> RgbaImage {
         self.file
             .layer_image(self.frame as u16, self.layer as usize)
     }
 
+    /// Like [image](Cel::image) but with premultiplied alpha, see
+    /// [ImageOptions::premultiply_alpha](crate::ImageOptions::premultiply_alpha).
+    #[cfg(feature = "image")]
+    pub fn image_premultiplied(&self) -> RgbaImage {
+        let mut image = self.image();
+        crate::file::premultiply_alpha(&mut image);
+        image
+    }
+
+    /// Like [image](Cel::image) but returns a [raw_image::RgbaImage]. This
+    /// is available without the `image` feature.
+    pub fn raw_image(&self) -> raw_image::RgbaImage {
+        raw_image::from_image(
+            self.file
+                .layer_image(self.frame as u16, self.layer as usize),
+        )
+    }
+
     /// Returns `true` if the cel contains no data.
     pub fn is_empty(&self) -> bool {
         self.file
@@ -37,19 +66,54 @@ impl<'a> Cel<'a> {
                 frame: self.frame as u16,
                 layer: self.layer as u16,
             })
-            .is_some()
+            .is_none()
     }
 
     /// Returns the cel's user data, if any is present.
     pub fn user_data(&self) -> Option<&UserData> {
         self.user_data
     }
+
+    /// Moves the cel this many layers up (if positive) or down (if negative)
+    /// when compositing the frame, without changing the order of the layers.
+    /// `0` for empty cels and files written before Aseprite 1.3.
+    pub fn z_index(&self) -> i16 {
+        self.file
+            .framedata
+            .cel(CelId {
+                frame: self.frame as u16,
+                layer: self.layer as u16,
+            })
+            .map_or(0, |cel| cel.data.z_index)
+    }
+
+    /// A hash of the cel's position, opacity, and pixel (or tile) data.
+    /// Returns `None` if the cel is empty.
+    ///
+    /// A linked cel has the same hash as the cel it links to. Comparing hashes
+    /// is a cheap way to find out whether a cel changed between two versions
+    /// of a file. The hash does not change between runs of the program.
+    pub fn content_hash(&self) -> Option<u64> {
+        let cel_id = CelId {
+            frame: self.frame as u16,
+            layer: self.layer as u16,
+        };
+        self.file.framedata.resolved_cel(cel_id).map(|raw| {
+            let mut hasher = Fnv1a::new();
+            raw.write_hash(&self.file.framedata, &mut hasher);
+            hasher.finish()
+        })
+    }
 }
 
 /// Organizes all Cels into a 2d array.
 pub(crate) struct CelsData {
     // Mapping: frame_id -> layer_id -> Option<RawCel>
     data: Vec<Vec<Option<RawCel>>>,
+    // The decoded pixels of all image cels in a frame share one buffer, so a
+    // file needs a few large allocations instead of one per cel. Cels refer to
+    // their pixels with a `PixelRange`.
+    pixels: Vec<FramePixels>,
     num_frames: u32,
 }
 #[derive(Debug, Clone, Copy)]
@@ -84,11 +148,134 @@ impl fmt::Debug for CelsData {
 }
 
 impl CelsData {
-    pub fn new(num_frames: u32) -> Self {
+    pub fn new(num_frames: u32, pixel_format: PixelFormat) -> Self {
+        Self::with_frame_pixels(num_frames, || FramePixels::new(pixel_format))
+    }
+
+    // Like `new`, but the pixels of each frame are decoded on first use.
+    // With `shared` bytes, the images refer to the data in that buffer
+    // instead of copies.
+    #[cfg(feature = "std")]
+    pub fn new_lazy(
+        num_frames: u32,
+        pixel_format: PixelFormat,
+        shared: Option<SharedBytes>,
+    ) -> Self {
+        Self::with_frame_pixels(num_frames, || {
+            FramePixels::new_lazy(pixel_format, shared.clone())
+        })
+    }
+
+    // Like `new`, but the pixels of all images are discarded.
+    #[cfg(feature = "std")]
+    pub fn new_skipped(num_frames: u32, pixel_format: PixelFormat) -> Self {
+        Self::with_frame_pixels(num_frames, || FramePixels::new_skipped(pixel_format))
+    }
+
+    fn with_frame_pixels<F: FnMut() -> FramePixels>(num_frames: u32, new_pixels: F) -> Self {
         let mut data = Vec::with_capacity(num_frames as usize);
         // Initialize with one layer (outer Vec) and zero RawCel (inner Vec).
         data.resize_with(num_frames as usize, || vec![None]);
-        CelsData { data, num_frames }
+        let mut pixels = Vec::with_capacity(num_frames as usize);
+        pixels.resize_with(num_frames as usize, new_pixels);
+        CelsData {
+            data,
+            pixels,
+            num_frames,
+        }
+    }
+
+    // The buffer that the pixels of the cels in `frame` need to be added to
+    // while parsing.
+    pub fn frame_buffer_mut(&mut self, frame: u16) -> &mut FramePixels {
+        &mut self.pixels[frame as usize]
+    }
+
+    // The decoded pixels of `frame`, for modification.
+    #[cfg(feature = "std")]
+    pub fn frame_pixels_mut(&mut self, frame: u16) -> &mut Pixels {
+        self.pixels[frame as usize].get_mut()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn frame_pixels(&self, frame: u16) -> &Pixels {
+        self.pixels[frame as usize].get()
+    }
+
+    pub fn pixels(&self, range: &PixelRange) -> PixelsRef<'_> {
+        self.pixels[range.frame as usize]
+            .get()
+            .get(range.range.clone())
+    }
+
+    // Decodes the pixels of all frames that were not decoded yet.
+    #[cfg(feature = "std")]
+    pub fn decode_all(&self) -> Result<()> {
+        for pixels in &self.pixels {
+            pixels.try_get()?;
+        }
+        Ok(())
+    }
+
+    // Decodes all frames on the rayon thread pool. Fails with the error of
+    // the first frame that could not be decoded.
+    #[cfg(feature = "rayon")]
+    pub fn decode_parallel(&mut self) -> Result<()> {
+        use rayon::prelude::*;
+
+        // Events of the worker threads go to the subscriber of this thread.
+        #[cfg(feature = "tracing")]
+        let dispatch = tracing::dispatcher::get_default(Clone::clone);
+        let decoded: Vec<_> = self
+            .pixels
+            .par_iter()
+            .map(|pixels| {
+                #[cfg(feature = "tracing")]
+                let _guard = tracing::dispatcher::set_default(&dispatch);
+                pixels.decode_encoded()
+            })
+            .collect();
+        for (pixels, decoded) in self.pixels.iter_mut().zip(decoded) {
+            if let Some(decoded) = decoded {
+                *pixels = FramePixels::Decoded(decoded?);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn set_palette(&mut self, palette: Option<&ColorPalette>) {
+        for pixels in &mut self.pixels {
+            pixels.set_palette(palette);
+        }
+    }
+
+    // Drops all frames after the first `num_frames`.
+    pub fn truncate(&mut self, num_frames: u32) {
+        self.data.truncate(num_frames as usize);
+        self.pixels.truncate(num_frames as usize);
+        self.num_frames = self.num_frames.min(num_frames);
+    }
+
+    // Removes the cels of layers that do not exist, i.e. whose index is at
+    // least `num_layers`. Returns their ids.
+    pub fn remove_cels_without_layer(&mut self, num_layers: usize) -> Vec<CelId> {
+        let mut removed = Vec::new();
+        for (frame, by_layer) in self.data.iter_mut().enumerate() {
+            if by_layer.len() <= num_layers {
+                continue;
+            }
+            for (layer, cel) in by_layer.iter().enumerate().skip(num_layers) {
+                if cel.is_some() {
+                    removed.push(CelId {
+                        frame: frame as u16,
+                        layer: layer as u16,
+                    });
+                }
+            }
+            by_layer.truncate(num_layers);
+        }
+        removed
     }
 
     fn check_valid_frame_id(&self, frame_id: u16) -> Result<()> {
@@ -128,11 +315,24 @@ impl CelsData {
             .filter_map(|(layer_id, cel)| cel.as_ref().map(|c| (layer_id as u32, c)))
     }
 
+    // The cels of a frame in the order in which they are drawn. Each cel's
+    // z-index moves it that many layers up or down. Like in Aseprite, a cel
+    // that ends up at the same position as another one is drawn after it if
+    // its z-index is higher.
+    pub fn frame_cels_in_z_order(&self, frame_id: u16) -> Vec<(u32, &RawCel)> {
+        let mut cels: Vec<(u32, &RawCel)> = self.frame_cels(frame_id).collect();
+        cels.sort_by_key(|(layer_id, cel)| {
+            let z_index = cel.data.z_index as i32;
+            (*layer_id as i32 + z_index, z_index)
+        });
+        cels
+    }
+
     // Frame ID must be valid. If Layer ID is out of bounds always returns an
     // empty Vec.
     pub fn cel(&self, cel_id: CelId) -> Option<&RawCel> {
         let CelId { frame, layer } = cel_id;
-        let layers = &self.data[frame as usize];
+        let layers = self.data.get(frame as usize)?;
         if (layer as usize) >= layers.len() {
             None
         } else {
@@ -140,6 +340,211 @@ impl CelsData {
         }
     }
 
+    // Like `cel` but follows linked cels to the cel that holds the data.
+    pub fn resolved_cel(&self, cel_id: CelId) -> Option<&RawCel> {
+        let cel = self.cel(cel_id)?;
+        if let CelContent::Linked(frame) = cel.content {
+            self.cel(CelId {
+                frame,
+                layer: cel_id.layer,
+            })
+        } else {
+            Some(cel)
+        }
+    }
+
+    // Frees the frame's pixel buffer once the last cel of the frame is gone.
+    #[cfg(feature = "image")]
+    pub fn remove_cel(&mut self, cel_id: CelId) -> Option<RawCel> {
+        let layers = &mut self.data[cel_id.frame as usize];
+        let cel = layers.get_mut(cel_id.layer as usize).and_then(Option::take);
+        if layers.iter().all(Option::is_none) {
+            self.pixels[cel_id.frame as usize].clear();
+        }
+        cel
+    }
+
+    // For each frame, the cels that are no longer needed once that frame and
+    // all frames before it have been rendered. Linked cels always point to an
+    // earlier frame, so a cel that other cels link to is kept alive until the
+    // last frame that links to it.
+    #[cfg(feature = "image")]
+    pub fn release_schedule(&self) -> Vec<Vec<CelId>> {
+        let mut last_use: Vec<Vec<u16>> = self
+            .data
+            .iter()
+            .enumerate()
+            .map(|(frame, layers)| vec![frame as u16; layers.len()])
+            .collect();
+        for (frame, layers) in self.data.iter().enumerate() {
+            for (layer, cel) in layers.iter().enumerate() {
+                if let Some(CelContent::Linked(source)) = cel.as_ref().map(|c| &c.content) {
+                    if let Some(entry) = last_use
+                        .get_mut(*source as usize)
+                        .and_then(|layers| layers.get_mut(layer))
+                    {
+                        *entry = (*entry).max(frame as u16);
+                    }
+                }
+            }
+        }
+
+        let mut schedule = vec![Vec::new(); self.data.len()];
+        for (frame, layers) in self.data.iter().enumerate() {
+            for (layer, cel) in layers.iter().enumerate() {
+                if cel.is_some() {
+                    schedule[last_use[frame][layer] as usize].push(CelId {
+                        frame: frame as u16,
+                        layer: layer as u16,
+                    });
+                }
+            }
+        }
+        schedule
+    }
+
+    // Replaces the content of an image cel with RGBA pixels, creating the cel
+    // if necessary. A linked cel becomes independent and cels that linked to
+    // this one keep the old image. The old pixels are removed from the frame's
+    // buffer.
+    #[cfg(feature = "std")]
+    pub fn set_image(
+        &mut self,
+        cel_id: CelId,
+        origin: (i16, i16),
+        size: ImageSize,
+        rgba: &[u8],
+    ) -> Result<()> {
+        self.unlink_dependents(cel_id)?;
+        // A linked cel was drawn with the opacity of its source.
+        let opacity = self
+            .resolved_cel(cel_id)
+            .map(|cel| cel.data.opacity)
+            .unwrap_or(255);
+        let z_index = self.cel(cel_id).map_or(0, |cel| cel.data.z_index);
+        let range = self
+            .frame_pixels_mut(cel_id.frame)
+            .extend_from_bytes(rgba)?;
+        let content = CelContent::Raw(ImageContent {
+            size,
+            pixels: PixelRange {
+                frame: cel_id.frame,
+                range,
+            },
+        });
+        let data = CelData {
+            layer_index: cel_id.layer,
+            x: origin.0,
+            y: origin.1,
+            opacity,
+            z_index,
+        };
+        match self.cel_mut(&cel_id) {
+            Some(cel) => {
+                cel.data = data;
+                cel.content = content;
+                // The extra data describes the old image's bounds.
+                cel.extra = None;
+                self.compact_pixels(cel_id.frame)
+            }
+            None => self.add_cel(
+                cel_id.frame,
+                RawCel {
+                    data,
+                    content,
+                    user_data: None,
+                    extra: None,
+                },
+            ),
+        }
+    }
+
+    // Copies the pixels of the frame's image cels into a new buffer, which
+    // frees the pixels that no cel refers to anymore.
+    #[cfg(feature = "std")]
+    fn compact_pixels(&mut self, frame: u16) -> Result<()> {
+        let buffer = self.pixels[frame as usize].get_mut();
+        let mut compacted = buffer.get(0..0).to_pixels();
+        for cel in self.data[frame as usize].iter_mut().flatten() {
+            if let CelContent::Raw(ImageContent { pixels, .. }) = &mut cel.content {
+                let copy = buffer.get(pixels.range.clone()).to_pixels();
+                pixels.range = compacted.append(copy)?;
+            }
+        }
+        *buffer = compacted;
+        Ok(())
+    }
+
+    // Gives the first cel that links to `cel_id` a copy of its image and lets
+    // the other linked cels point to that copy instead.
+    #[cfg(feature = "std")]
+    fn unlink_dependents(&mut self, cel_id: CelId) -> Result<()> {
+        let layer = cel_id.layer;
+        let dependents: Vec<u16> = (0..self.data.len() as u16)
+            .filter(|frame| {
+                let cel = self.cel(CelId {
+                    frame: *frame,
+                    layer,
+                });
+                matches!(cel.map(|c| &c.content), Some(CelContent::Linked(f)) if *f == cel_id.frame)
+            })
+            .collect();
+        let (new_source, others) = match dependents.split_first() {
+            Some((first, others)) => (*first, others),
+            None => return Ok(()),
+        };
+        let (x, y, opacity, size, range, extra) = match self.cel(cel_id) {
+            Some(RawCel {
+                data,
+                content: CelContent::Raw(ImageContent { size, pixels }),
+                extra,
+                ..
+            }) => (
+                data.x,
+                data.y,
+                data.opacity,
+                *size,
+                pixels.clone(),
+                extra.clone(),
+            ),
+            _ => {
+                return Err(AsepriteParseError::InternalError(format!(
+                    "Cannot unlink cels from {}",
+                    cel_id
+                )))
+            }
+        };
+        let copy = self.pixels(&range).to_pixels();
+        let range = self.frame_pixels_mut(new_source).append(copy)?;
+        let new_source_id = CelId {
+            frame: new_source,
+            layer,
+        };
+        let cel = self
+            .cel_mut(&new_source_id)
+            .expect("Dependent cel disappeared");
+        cel.data.x = x;
+        cel.data.y = y;
+        cel.data.opacity = opacity;
+        cel.extra = extra;
+        cel.content = CelContent::Raw(ImageContent {
+            size,
+            pixels: PixelRange {
+                frame: new_source,
+                range,
+            },
+        });
+        for frame in others {
+            if let Some(cel) = self.cel_mut(&CelId {
+                frame: *frame,
+                layer,
+            }) {
+                cel.content = CelContent::Linked(new_source);
+            }
+        }
+        Ok(())
+    }
+
     pub fn cel_mut(&mut self, cel_id: &CelId) -> Option<&mut RawCel> {
         let frame = cel_id.frame;
         let layer = cel_id.layer;
@@ -161,18 +566,23 @@ impl CelsData {
         let by_layer = &self.data[frame as usize];
         if let Some(ref cel) = by_layer[layer_index] {
             match &cel.content {
-                CelContent::Raw(image_content) => match &image_content.pixels {
-                    Pixels::Rgba(_) => {}
-                    Pixels::Grayscale(_) => {}
-                    Pixels::Indexed(indexed_pixels) => {
-                        let palette = palette.ok_or_else(|| {
-                            AsepriteParseError::InvalidInput(
-                                "No palette present for indexed pixel data".into(),
-                            )
-                        })?;
-                        palette.validate_indexed_pixels(indexed_pixels)?;
+                CelContent::Raw(image_content) => {
+                    let frame_pixels = &self.pixels[frame as usize];
+                    if frame_pixels.is_indexed() && palette.is_none() {
+                        return Err(AsepriteParseError::InvalidInput(
+                            "No palette present for indexed pixel data".into(),
+                        ));
                     }
-                },
+                    // Lazily loaded pixels are validated when they are decoded.
+                    if let (Some(pixels), Some(palette)) = (frame_pixels.get_if_decoded(), palette)
+                    {
+                        if let PixelsRef::Indexed(indexed_pixels) =
+                            pixels.get(image_content.pixels.range.clone())
+                        {
+                            palette.validate_indexed_pixels(indexed_pixels)?;
+                        }
+                    }
+                }
                 CelContent::Linked(other_frame) => {
                     match self.cel(CelId{ frame: *other_frame, layer: layer_index as u16 }) {
                         Some(other_cel) => {
@@ -193,9 +603,9 @@ impl CelsData {
                 }
                 CelContent::Tilemap(_) => {
                     // Verify that a Tilemap cel belongs to a Tilemap layer.
-                    if let LayerType::Tilemap(_) = layer.layer_type {
-                        // Tilemap Layer, ok
-                    } else {
+                    // Missing tilesets are reported by LayersData::validate
+                    // and missing tiles by tile_ids_out_of_range.
+                    if !matches!(layer.layer_type, LayerType::Tilemap(_)) {
                         return Err(AsepriteParseError::InvalidInput(format!(
                             "Invalid cel. Tilemap Cel (f:{},l:{}) outside of tilemap layer.",
                             frame, layer_index
@@ -208,15 +618,59 @@ impl CelsData {
     }
 
     pub fn validate(&self, layers_data: &LayersData, palette: Option<&ColorPalette>) -> Result<()> {
+        let num_layers = layers_data.layers.len();
         for frame in 0..self.num_frames {
             let by_layer = &self.data[frame as usize];
             for layer_index in 0..by_layer.len() {
+                if by_layer[layer_index].is_none() {
+                    continue;
+                }
+                if layer_index >= num_layers {
+                    return Err(AsepriteParseError::InvalidInput(format!(
+                        "Invalid cel. Cel (f:{},l:{}) references a missing layer. Number of layers: {}",
+                        frame, layer_index, num_layers
+                    )));
+                }
                 let layer = &layers_data[layer_index as u32];
                 self.validate_cel(frame, layer_index, layer, palette)?;
             }
         }
         Ok(())
     }
+
+    // Warns about tilemap cels that use tiles beyond the end of their
+    // tileset. Must be called after validate.
+    pub(crate) fn tile_ids_out_of_range(
+        &self,
+        layers_data: &LayersData,
+        tilesets: &TilesetsById,
+    ) -> Vec<ParseWarning> {
+        let mut warnings = Vec::new();
+        for (frame, by_layer) in self.data.iter().enumerate() {
+            for (layer_index, cel) in by_layer.iter().enumerate() {
+                let tilemap = match cel.as_ref().map(|cel| &cel.content) {
+                    Some(CelContent::Tilemap(tilemap)) => tilemap,
+                    _ => continue,
+                };
+                let tileset = match layers_data[layer_index as u32].layer_type {
+                    LayerType::Tilemap(tileset_id) => tilesets.get(&tileset_id),
+                    _ => None,
+                };
+                let tile_count = match tileset {
+                    Some(tileset) => *tileset.tile_count(),
+                    None => continue,
+                };
+                if let Some(tile) = tilemap.tiles.iter().find(|tile| tile.id.0 >= tile_count) {
+                    warnings.push(ParseWarning::TileIdOutOfRange {
+                        frame: frame as u16,
+                        layer: layer_index as u16,
+                        tile_id: tile.id.0,
+                    });
+                }
+            }
+        }
+        warnings
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -233,6 +687,9 @@ impl ImageSize {
     pub(crate) fn pixel_count(&self) -> usize {
         self.width as usize * self.height as usize
     }
+    pub(crate) fn byte_count(&self, bytes_per_pixel: usize) -> u64 {
+        self.pixel_count() as u64 * bytes_per_pixel as u64
+    }
 }
 
 // CelData holds fields which are common to all cel types.
@@ -242,6 +699,7 @@ pub(crate) struct CelData {
     pub x: i16,
     pub y: i16,
     pub opacity: u8,
+    pub z_index: i16,
 }
 impl CelData {
     fn parse<R: Read>(reader: &mut AseReader<R>) -> Result<Self> {
@@ -254,13 +712,22 @@ impl CelData {
             x,
             y,
             opacity,
+            // Follows the cel type.
+            z_index: 0,
         })
     }
 }
 
+// Location of a cel's pixels in `CelsData`.
+#[derive(Debug, Clone)]
+pub(crate) struct PixelRange {
+    pub frame: u16,
+    pub range: Range<usize>,
+}
+
 pub(crate) struct ImageContent {
     pub size: ImageSize,
-    pub pixels: Pixels,
+    pub pixels: PixelRange,
 }
 
 // CelContent holds data specific to each type of cel.
@@ -271,16 +738,18 @@ pub(crate) enum CelContent {
     Tilemap(Tilemap),
 }
 impl CelContent {
-    fn parse<R: Read>(
-        mut reader: AseReader<R>,
-        pixel_format: PixelFormat,
+    fn parse(
+        mut reader: AseReader<&[u8]>,
+        frame: u16,
+        pixels: &mut FramePixels,
         cel_type: u16,
+        budget: &mut Budget,
     ) -> Result<Self> {
         match cel_type {
-            0 => parse_raw_cel(reader, pixel_format).map(CelContent::Raw),
+            0 => parse_raw_cel(reader, frame, pixels, budget).map(CelContent::Raw),
             1 => reader.word().map(CelContent::Linked),
-            2 => parse_compressed_cel(reader, pixel_format).map(CelContent::Raw),
-            3 => Tilemap::parse_chunk(reader).map(CelContent::Tilemap),
+            2 => parse_compressed_cel(reader, frame, pixels, budget).map(CelContent::Raw),
+            3 => Tilemap::parse_chunk(reader, budget).map(CelContent::Tilemap),
             _ => Err(AsepriteParseError::InvalidInput(format!(
                 "Invalid/Unsupported Cel type: {}",
                 cel_type
@@ -291,7 +760,7 @@ impl CelContent {
 
 impl fmt::Debug for ImageContent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "<{} bytes>", self.pixels.byte_count())
+        write!(f, "<{} pixels>", self.pixels.range.len())
     }
 }
 
@@ -300,41 +769,104 @@ pub(crate) struct RawCel {
     pub data: CelData,
     pub content: CelContent,
     pub user_data: Option<UserData>,
+    // The contents of the cel's Cel Extra chunk, which this crate does not
+    // interpret but writes back unchanged.
+    pub extra: Option<Vec<u8>>,
 }
 
-fn parse_raw_cel<R: Read>(
-    mut reader: AseReader<R>,
-    pixel_format: PixelFormat,
+impl RawCel {
+    // Hashes everything that affects how the cel is rendered. Linked cels only
+    // hash the link target's frame, so resolve the link first to compare
+    // content. User data is not included. `framedata` must be the CelsData
+    // that holds this cel.
+    pub(crate) fn write_hash<H: Hasher>(&self, framedata: &CelsData, state: &mut H) {
+        let CelData { x, y, opacity, .. } = self.data;
+        state.write(&x.to_le_bytes());
+        state.write(&y.to_le_bytes());
+        state.write(&[opacity]);
+        match &self.content {
+            CelContent::Raw(ImageContent { size, pixels }) => {
+                state.write(&[0]);
+                state.write(&size.width.to_le_bytes());
+                state.write(&size.height.to_le_bytes());
+                framedata.pixels(pixels).write_hash(state);
+            }
+            CelContent::Linked(frame) => {
+                state.write(&[1]);
+                state.write(&frame.to_le_bytes());
+            }
+            CelContent::Tilemap(tilemap) => {
+                state.write(&[2]);
+                state.write(&tilemap.width.to_le_bytes());
+                state.write(&tilemap.height.to_le_bytes());
+                for tile in tilemap.tiles.iter() {
+                    let flags = tile.flip_x as u8
+                        | (tile.flip_y as u8) << 1
+                        | (tile.rotate_90cw as u8) << 2;
+                    state.write(&tile.id.0.to_le_bytes());
+                    state.write(&[flags]);
+                }
+            }
+        }
+    }
+}
+
+fn parse_raw_cel(
+    mut reader: AseReader<&[u8]>,
+    frame: u16,
+    pixels: &mut FramePixels,
+    budget: &mut Budget,
 ) -> Result<ImageContent> {
     let size = ImageSize::parse(&mut reader)?;
-    Pixels::from_raw(reader, pixel_format, size.pixel_count())
-        .map(|pixels| ImageContent { size, pixels })
+    budget.reserve("Cel image", size.byte_count(pixels.bytes_per_pixel()))?;
+    let range = pixels.read_raw(reader, size.pixel_count())?;
+    Ok(ImageContent {
+        size,
+        pixels: PixelRange { frame, range },
+    })
 }
 
-fn parse_compressed_cel<R: Read>(
-    mut reader: AseReader<R>,
-    pixel_format: PixelFormat,
+fn parse_compressed_cel(
+    mut reader: AseReader<&[u8]>,
+    frame: u16,
+    pixels: &mut FramePixels,
+    budget: &mut Budget,
 ) -> Result<ImageContent> {
     let size = ImageSize::parse(&mut reader)?;
-    Pixels::from_compressed(reader, pixel_format, size.pixel_count())
-        .map(|pixels| ImageContent { size, pixels })
+    budget.reserve("Cel image", size.byte_count(pixels.bytes_per_pixel()))?;
+    let range = pixels.read_compressed(reader, size.pixel_count())?;
+    Ok(ImageContent {
+        size,
+        pixels: PixelRange { frame, range },
+    })
 }
 
-pub(crate) fn parse_chunk(data: &[u8], pixel_format: PixelFormat) -> Result<RawCel> {
-    let mut reader = AseReader::new(&data);
-    let data = CelData::parse(&mut reader)?;
+// Image data is appended to `pixels`, the pixel buffer of `frame`.
+pub(crate) fn parse_chunk(
+    data: &[u8],
+    frame: u16,
+    pixels: &mut FramePixels,
+    budget: &mut Budget,
+) -> Result<RawCel> {
+    let mut reader = AseReader::new(data);
+    let mut data = CelData::parse(&mut reader)?;
     let cel_type = reader.word()?;
-    reader.skip_reserved(7)?;
+    data.z_index = reader.short()?;
+    reader.skip_reserved(5)?;
+
+    trace_span!(TRACE, "parse_cel", layer = data.layer_index, cel_type);
 
-    let content = CelContent::parse(reader, pixel_format, cel_type)?;
+    let content = CelContent::parse(reader, frame, pixels, cel_type, budget)?;
     Ok(RawCel {
         data,
         content,
         user_data: None,
+        extra: None,
     })
 }
 
 // For debugging
+#[cfg(feature = "std")]
 #[allow(dead_code)]
 fn dump_bytes(data: &[u8]) {
     let mut column = 0;
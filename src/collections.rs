@@ -0,0 +1,11 @@
+// Maps keyed by ids. Without the `std` feature there are no hash maps, so
+// ordered maps take their place.
+#[cfg(feature = "std")]
+pub(crate) type Map<K, V> = std::collections::HashMap<K, V>;
+#[cfg(not(feature = "std"))]
+pub(crate) type Map<K, V> = alloc::collections::BTreeMap<K, V>;
+
+#[cfg(feature = "std")]
+pub(crate) type IntMap<K, V> = nohash::IntMap<K, V>;
+#[cfg(not(feature = "std"))]
+pub(crate) type IntMap<K, V> = alloc::collections::BTreeMap<K, V>;
@@ -0,0 +1,244 @@
+// Conversion of composited images from the sprite's color profile to sRGB, see
+// `ImageOptions::convert_to_srgb`.
+//
+// Only matrix/TRC ICC profiles are interpreted: RGB profiles with a tone
+// curve and a colorant per channel, which is how RGB working spaces like
+// Display P3 or Adobe RGB are described. Profiles built from lookup tables are
+// left unconverted.
+use crate::color_profile::{ColorProfile, ColorProfileType};
+use crate::image::RgbaImage;
+use std::convert::TryInto;
+
+type Matrix = [[f32; 3]; 3];
+
+// The sRGB colorants adapted to the D50 white of the ICC profile connection
+// space, as in the sRGB profile published by the ICC.
+const SRGB_TO_XYZ_D50: Matrix = [
+    [0.436_074_7, 0.385_064_9, 0.143_080_4],
+    [0.222_504_5, 0.716_878_6, 0.060_616_9],
+    [0.013_932_2, 0.097_104_5, 0.714_173_3],
+];
+
+pub(crate) struct ColorTransform {
+    // The linear value of each 8 bit value, per channel.
+    decode: [[f32; 256]; 3],
+    // From linear values in the source space to linear sRGB.
+    matrix: Matrix,
+}
+
+impl ColorTransform {
+    // The transform from `profile` to sRGB. `None` if colors are already in
+    // sRGB, the profile says that they should not be managed, or the profile
+    // is not supported.
+    pub(crate) fn to_srgb(profile: &ColorProfile) -> Option<Self> {
+        let (curves, to_xyz) = match profile.profile_type() {
+            ColorProfileType::None => return None,
+            ColorProfileType::Srgb => {
+                let gamma = profile.fixed_gamma()? as f32;
+                let curve = Curve::Parametric([gamma, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+                ([curve.clone(), curve.clone(), curve], SRGB_TO_XYZ_D50)
+            }
+            ColorProfileType::Icc => parse_icc(profile.icc_profile()?)?,
+        };
+        let matrix = multiply(&invert(&SRGB_TO_XYZ_D50)?, &to_xyz);
+        let mut decode = [[0.0; 256]; 3];
+        for (table, curve) in decode.iter_mut().zip(&curves) {
+            for (value, linear) in table.iter_mut().enumerate() {
+                *linear = curve.eval(value as f32 / 255.0);
+            }
+        }
+        Some(Self { decode, matrix })
+    }
+
+    // Converts the color of all pixels that are not fully transparent. Alpha
+    // stays unchanged.
+    pub(crate) fn apply(&self, image: &mut RgbaImage) {
+        for pixel in image.pixels_mut() {
+            if pixel.0[3] == 0 {
+                continue;
+            }
+            let linear = [
+                self.decode[0][pixel.0[0] as usize],
+                self.decode[1][pixel.0[1] as usize],
+                self.decode[2][pixel.0[2] as usize],
+            ];
+            for (channel, row) in self.matrix.iter().enumerate() {
+                let value = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+                pixel.0[channel] = encode_srgb(value);
+            }
+        }
+    }
+}
+
+fn encode_srgb(linear: f32) -> u8 {
+    let linear = linear.clamp(0.0, 1.0);
+    let encoded = if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0 + 0.5) as u8
+}
+
+#[derive(Clone)]
+enum Curve {
+    // The parameters `[g, a, b, c, d, e, f]` of
+    // `y = (a * x + b)^g + e` for `x >= d` and `y = c * x + f` otherwise,
+    // which covers all parametric curves of ICC profiles.
+    Parametric([f32; 7]),
+    // Values for equally spaced inputs, linearly interpolated in between.
+    Table(Vec<f32>),
+}
+
+impl Curve {
+    fn eval(&self, x: f32) -> f32 {
+        match self {
+            Curve::Parametric([g, a, b, c, d, e, f]) => {
+                if x >= *d {
+                    (a * x + b).max(0.0).powf(*g) + e
+                } else {
+                    c * x + f
+                }
+            }
+            Curve::Table(table) => {
+                let position = x * (table.len() - 1) as f32;
+                let index = (position as usize).min(table.len() - 2);
+                let fraction = position - index as f32;
+                table[index] + (table[index + 1] - table[index]) * fraction
+            }
+        }
+    }
+}
+
+// The tone curves of the red, green and blue channels and the matrix from
+// their linear values to the XYZ profile connection space.
+fn parse_icc(data: &[u8]) -> Option<([Curve; 3], Matrix)> {
+    if data.get(16..20)? != b"RGB " || data.get(20..24)? != b"XYZ " {
+        return None;
+    }
+    let tag = |signature: &[u8; 4]| {
+        // Each entry of the tag table has 12 bytes.
+        let count = (be_u32(data, 128)? as usize).min(data.len() / 12);
+        (0..count).find_map(|index| {
+            let entry = 132 + index * 12;
+            if data.get(entry..entry + 4)? != signature {
+                return None;
+            }
+            let offset = be_u32(data, entry + 4)? as usize;
+            let size = be_u32(data, entry + 8)? as usize;
+            data.get(offset..offset.checked_add(size)?)
+        })
+    };
+    let mut to_xyz = [[0.0; 3]; 3];
+    for (column, signature) in [b"rXYZ", b"gXYZ", b"bXYZ"].iter().enumerate() {
+        let xyz = tag(signature)?;
+        if xyz.get(0..4)? != b"XYZ " {
+            return None;
+        }
+        for (row, values) in to_xyz.iter_mut().enumerate() {
+            values[column] = be_fixed(xyz, 8 + row * 4)?;
+        }
+    }
+    let red = parse_curve(tag(b"rTRC")?)?;
+    let green = parse_curve(tag(b"gTRC")?)?;
+    let blue = parse_curve(tag(b"bTRC")?)?;
+    Some(([red, green, blue], to_xyz))
+}
+
+fn parse_curve(data: &[u8]) -> Option<Curve> {
+    match data.get(0..4)? {
+        b"curv" => {
+            let count = be_u32(data, 8)? as usize;
+            let entry = |index: usize| be_u16(data, 12 + index * 2);
+            match count {
+                0 => Some(Curve::Parametric([1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0])),
+                1 => {
+                    let gamma = entry(0)? as f32 / 256.0;
+                    Some(Curve::Parametric([gamma, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]))
+                }
+                _ => (0..count)
+                    .map(|index| entry(index).map(|value| value as f32 / 65535.0))
+                    .collect::<Option<_>>()
+                    .map(Curve::Table),
+            }
+        }
+        b"para" => {
+            let param = |index: usize| be_fixed(data, 12 + index * 4);
+            let g = param(0)?;
+            let params = match be_u16(data, 8)? {
+                0 => [g, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                1 => {
+                    let (a, b) = (param(1)?, param(2)?);
+                    [g, a, b, 0.0, -b / a, 0.0, 0.0]
+                }
+                2 => {
+                    let (a, b, c) = (param(1)?, param(2)?, param(3)?);
+                    [g, a, b, 0.0, -b / a, c, c]
+                }
+                3 => [g, param(1)?, param(2)?, param(3)?, param(4)?, 0.0, 0.0],
+                4 => [
+                    g,
+                    param(1)?,
+                    param(2)?,
+                    param(3)?,
+                    param(4)?,
+                    param(5)?,
+                    param(6)?,
+                ],
+                _ => return None,
+            };
+            Some(Curve::Parametric(params))
+        }
+        _ => None,
+    }
+}
+
+// ICC profiles are big-endian.
+fn be_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+// An `s15Fixed16Number`.
+fn be_fixed(data: &[u8], offset: usize) -> Option<f32> {
+    be_u32(data, offset).map(|value| value as i32 as f32 / 65536.0)
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 3]; 3];
+    for (row, values) in result.iter_mut().enumerate() {
+        for (column, value) in values.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[row][k] * b[k][column]).sum();
+        }
+    }
+    result
+}
+
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let cofactor = |row: usize, column: usize| {
+        let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+        let (c0, c1) = ((column + 1) % 3, (column + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let determinant: f32 = (0..3)
+        .map(|column| m[0][column] * cofactor(0, column))
+        .sum();
+    if determinant.abs() < 1e-6 {
+        return None;
+    }
+    let mut result = [[0.0; 3]; 3];
+    for (row, values) in result.iter_mut().enumerate() {
+        for (column, value) in values.iter_mut().enumerate() {
+            // The inverse is the transposed matrix of cofactors.
+            *value = cofactor(column, row) / determinant;
+        }
+    }
+    Some(result)
+}
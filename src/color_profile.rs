@@ -1,44 +1,87 @@
 use crate::{reader::AseReader, AsepriteParseError, Result};
+use alloc::{format, vec::Vec};
 
-#[derive(Debug)]
+/// The color profile of a sprite, see [AsepriteFile::color_profile].
+///
+/// Aseprite uses the profile to convert the sprite's colors for display. The
+/// pixels returned by this crate are only converted with
+/// [ImageOptions::convert_to_srgb](crate::ImageOptions::convert_to_srgb).
+///
+/// [AsepriteFile::color_profile]: crate::AsepriteFile::color_profile
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ColorProfile {
-    pub profile_type: ColorProfileType,
-    pub fixed_gamma: Option<f64>,
-    // pub icc_profile: Option<Vec<u8>>,
+    pub(crate) profile_type: ColorProfileType,
+    pub(crate) fixed_gamma: Option<f64>,
+    pub(crate) icc_profile: Option<Vec<u8>>,
 }
 
-#[derive(Debug, PartialEq)]
+/// The kind of a [ColorProfile].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ColorProfileType {
+    /// No color profile. Colors are used as they are.
     None,
+    /// The sRGB color space. This is the profile of new sprites in Aseprite.
     Srgb,
-    ICC,
+    /// An embedded ICC profile, see [ColorProfile::icc_profile].
+    Icc,
+}
+
+impl ColorProfile {
+    // The profile of sprites created by this crate.
+    #[cfg(feature = "std")]
+    pub(crate) fn srgb() -> Self {
+        Self {
+            profile_type: ColorProfileType::Srgb,
+            fixed_gamma: None,
+            icc_profile: None,
+        }
+    }
+
+    /// The kind of profile.
+    pub fn profile_type(&self) -> ColorProfileType {
+        self.profile_type
+    }
+
+    /// The special gamma of the profile, if one is set. A gamma of 1.0 means
+    /// linear colors.
+    pub fn fixed_gamma(&self) -> Option<f64> {
+        self.fixed_gamma
+    }
+
+    /// The raw bytes of the embedded ICC profile. Only present for profiles
+    /// of type [ColorProfileType::Icc].
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.icc_profile.as_deref()
+    }
 }
 
 pub(crate) fn parse_chunk(data: &[u8]) -> Result<ColorProfile> {
     let mut reader = AseReader::new(data);
     let profile_type = reader.word()?;
     let flags = reader.word()?;
-    let _fixed_gamma = reader.dword()?;
+    let fixed_gamma = reader.fixed()?;
     reader.skip_reserved(8)?;
 
     let profile_type = parse_color_profile_type(profile_type)?;
     let fixed_gamma = if flags & 1 != 0 {
-        return Err(AsepriteParseError::UnsupportedFeature(
-            "Custom gamma is currently not supported.".to_owned(),
-        ));
+        Some(fixed_gamma)
     } else {
         None
     };
 
-    if profile_type == ColorProfileType::ICC {
-        return Err(AsepriteParseError::UnsupportedFeature(
-            "Embedded ICC color profiles are currently not supported".to_owned(),
-        ));
-    }
+    let icc_profile = if profile_type == ColorProfileType::Icc {
+        let len = reader.dword()?;
+        Some(reader.take_bytes(len as usize)?)
+    } else {
+        None
+    };
 
     Ok(ColorProfile {
         profile_type,
         fixed_gamma,
+        icc_profile,
     })
 }
 
@@ -46,7 +89,7 @@ fn parse_color_profile_type(id: u16) -> Result<ColorProfileType> {
     match id {
         0x0000 => Ok(ColorProfileType::None),
         0x0001 => Ok(ColorProfileType::Srgb),
-        0x0002 => Ok(ColorProfileType::ICC),
+        0x0002 => Ok(ColorProfileType::Icc),
         _ => Err(AsepriteParseError::UnsupportedFeature(format!(
             "Unknown color profile type: {}",
             id
@@ -0,0 +1,122 @@
+#[cfg(feature = "image")]
+use crate::collections::IntMap;
+use crate::image::Rgba;
+#[cfg(feature = "image")]
+use crate::ImageOptions;
+use alloc::sync::Arc;
+use core::fmt;
+
+/// Options for [Frame::image_with_composite_options], which change how
+/// layers are drawn without modifying the file, e.g., to preview team colors
+/// or to flash a layer when it takes damage.
+///
+/// ```
+/// # use asefile::{AsepriteFile, CompositeOptions};
+/// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/layers_and_tags.aseprite")).unwrap();
+/// let cape = ase.layer_by_name("Layer 1").unwrap().id();
+/// let options = CompositeOptions::new()
+///     .layer_tint(cape, [255, 64, 64])
+///     .layer_opacity(cape, 128);
+/// let image = ase.frame(0).image_with_composite_options(&options);
+/// ```
+///
+/// [Frame::image_with_composite_options]: crate::Frame::image_with_composite_options
+#[cfg(feature = "image")]
+#[derive(Debug, Clone, Default)]
+pub struct CompositeOptions {
+    pub(crate) image_options: ImageOptions,
+    layers: IntMap<u32, LayerStyle>,
+}
+
+#[cfg(feature = "image")]
+impl CompositeOptions {
+    /// Default options: Layers are drawn as in [Frame::image](crate::Frame::image).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Which layers to composite and how to post-process the image, see
+    /// [ImageOptions]. Defaults to [ImageOptions::new].
+    pub fn image_options(mut self, options: ImageOptions) -> Self {
+        self.image_options = options;
+        self
+    }
+
+    /// Draw the layer `layer_id` with the given opacity, which is combined
+    /// with the opacity of its cels. An opacity of 0 hides the layer.
+    pub fn layer_opacity(mut self, layer_id: u32, opacity: u8) -> Self {
+        self.layers.entry(layer_id).or_default().opacity = opacity;
+        self
+    }
+
+    /// Multiply the colors of the layer `layer_id` with `tint` before they
+    /// are blended, like a multiply layer that only affects this layer.
+    /// White leaves the colors unchanged.
+    pub fn layer_tint(mut self, layer_id: u32, tint: [u8; 3]) -> Self {
+        self.layers.entry(layer_id).or_default().tint = Some(tint);
+        self
+    }
+
+    /// Blend the layer `layer_id` with `blend_fn` instead of its
+    /// [blend mode](crate::Layer::blend_mode), e.g., to emulate a blend mode
+    /// of a game engine that Aseprite does not have.
+    ///
+    /// The function is called for each pixel of the layer's cels with the
+    /// pixel below (the backdrop), the pixel of the cel, and the opacity of
+    /// the cel, and returns the blended pixel.
+    ///
+    /// ```
+    /// # use asefile::CompositeOptions;
+    /// // Additive blending that keeps the alpha of the backdrop.
+    /// let options = CompositeOptions::new().layer_blend_fn(1, |mut backdrop, src, opacity| {
+    ///     for channel in 0..3 {
+    ///         let add = src[channel] as u32 * src[3] as u32 * opacity as u32 / (255 * 255);
+    ///         backdrop[channel] = backdrop[channel].saturating_add(add as u8);
+    ///     }
+    ///     backdrop
+    /// });
+    /// ```
+    pub fn layer_blend_fn<F>(mut self, layer_id: u32, blend_fn: F) -> Self
+    where
+        F: Fn(Rgba<u8>, Rgba<u8>, u8) -> Rgba<u8> + Send + Sync + 'static,
+    {
+        self.layers.entry(layer_id).or_default().blend_fn = Some(Arc::new(blend_fn));
+        self
+    }
+
+    pub(crate) fn layer_style(&self, layer_id: u32) -> LayerStyle {
+        self.layers.get(&layer_id).cloned().unwrap_or_default()
+    }
+}
+
+pub(crate) type CustomBlendFn = dyn Fn(Rgba<u8>, Rgba<u8>, u8) -> Rgba<u8> + Send + Sync;
+
+// How the cels of a layer are drawn in addition to their layer's settings.
+#[derive(Clone)]
+pub(crate) struct LayerStyle {
+    // Scales the opacity of the cels.
+    pub(crate) opacity: u8,
+    pub(crate) tint: Option<[u8; 3]>,
+    // Replaces the blend mode of the layer.
+    pub(crate) blend_fn: Option<Arc<CustomBlendFn>>,
+}
+
+impl Default for LayerStyle {
+    fn default() -> Self {
+        Self {
+            opacity: 255,
+            tint: None,
+            blend_fn: None,
+        }
+    }
+}
+
+impl fmt::Debug for LayerStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LayerStyle")
+            .field("opacity", &self.opacity)
+            .field("tint", &self.tint)
+            .field("blend_fn", &self.blend_fn.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,487 @@
+//! Compare two versions of an Aseprite file.
+//!
+//! [diff()] describes changes the way an artist would: the canvas was resized,
+//! frames were added, a layer's pixels changed in frames 2 and 3, a tag now
+//! covers a different range, and so on.
+//!
+//! ```
+//! # use asefile::AsepriteFile;
+//! # use std::path::Path;
+//! # let path = Path::new("./tests/data/layers_and_tags.aseprite");
+//! let old = AsepriteFile::read_file(&path).unwrap();
+//! let new = AsepriteFile::read_file(&path).unwrap();
+//! let changes = asefile::diff::diff(&old, &new);
+//! assert!(changes.is_empty());
+//! println!("{}", changes);
+//! ```
+use std::fmt;
+
+use crate::{AnimationDirection, AsepriteFile, BlendMode, ColorPalette};
+
+/// The value of a property in the old and in the new file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Change<T> {
+    /// Value in the old file.
+    pub old: T,
+    /// Value in the new file.
+    pub new: T,
+}
+
+impl<T: PartialEq> Change<T> {
+    fn between(old: T, new: T) -> Option<Self> {
+        if old == new {
+            None
+        } else {
+            Some(Change { old, new })
+        }
+    }
+}
+
+/// Describes whether an item only exists in one of the files or in both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChangeKind {
+    /// The item only exists in the new file.
+    Added,
+    /// The item only exists in the old file.
+    Removed,
+    /// The item exists in both files but some of its properties differ.
+    Modified,
+}
+
+/// The differences between two files. See [diff()].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileDiff {
+    /// Canvas width and height, if changed.
+    pub size: Option<Change<(u32, u32)>>,
+    /// Number of frames, if changed. Frames are compared by index, so added or
+    /// removed frames are always at the end.
+    pub num_frames: Option<Change<u32>>,
+    /// Duration changes of frames that exist in both files.
+    pub frame_durations: Vec<FrameDurationChange>,
+    /// Added, removed, and modified layers.
+    pub layers: Vec<LayerDiff>,
+    /// Added, removed, and modified tags.
+    pub tags: Vec<TagDiff>,
+    /// Added, removed, and modified slices.
+    pub slices: Vec<SliceDiff>,
+    /// Palette changes, if any.
+    pub palette: Option<PaletteDiff>,
+}
+
+/// A frame whose duration changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameDurationChange {
+    /// Frame index.
+    pub frame: u32,
+    /// Duration in milliseconds.
+    pub duration: Change<u32>,
+}
+
+/// Changes to a single layer.
+///
+/// Layers are matched by their [UUID](crate::Layer::uuid) if both files have
+/// one, then by their path (see [crate::Layer::path]) and, failing that, by
+/// name. This means a renamed layer without UUID shows up as one removed and
+/// one added layer, while a layer moved into a different group is reported as
+/// modified with `old_path` set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LayerDiff {
+    /// Path of the layer in the new file (or in the old file if it was removed).
+    pub path: String,
+    /// Whether the layer was added, removed, or modified.
+    pub kind: ChangeKind,
+    /// Path in the old file if the layer was matched by UUID or name but its
+    /// path changed.
+    pub old_path: Option<String>,
+    /// Blend mode, if changed.
+    pub blend_mode: Option<Change<BlendMode>>,
+    /// Layer opacity, if changed.
+    pub opacity: Option<Change<u8>>,
+    /// Visibility (see [crate::Layer::is_visible]), if changed.
+    pub visible: Option<Change<bool>>,
+    /// Frames that exist in both files in which the layer's cel changed.
+    /// Compares cels using [crate::Cel::content_hash].
+    pub changed_frames: Vec<u32>,
+}
+
+/// Changes to a single tag. Tags are matched by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TagDiff {
+    /// Tag name.
+    pub name: String,
+    /// Whether the tag was added, removed, or modified.
+    pub kind: ChangeKind,
+    /// First and last frame of the tag, if changed.
+    pub frames: Option<Change<(u32, u32)>>,
+    /// Animation direction, if changed.
+    pub direction: Option<Change<AnimationDirection>>,
+    /// [Repeat](crate::Tag::repeat) count, if changed.
+    pub repeat: Option<Change<u16>>,
+}
+
+/// Changes to a single slice. Slices are matched by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SliceDiff {
+    /// Slice name.
+    pub name: String,
+    /// Whether the slice was added, removed, or modified.
+    pub kind: ChangeKind,
+    /// True if any of the slice's keys (position, size, 9-slice, or pivot)
+    /// changed.
+    pub keys_changed: bool,
+}
+
+/// Changes to the color palette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PaletteDiff {
+    /// Number of colors, if changed. A missing palette counts as zero colors.
+    pub num_colors: Option<Change<u32>>,
+    /// Indices of entries whose color or name differ, including entries that
+    /// only exist in one of the palettes.
+    pub changed_colors: Vec<u32>,
+}
+
+impl FileDiff {
+    /// Returns `true` if no differences were found.
+    pub fn is_empty(&self) -> bool {
+        *self == FileDiff::default()
+    }
+}
+
+/// Compare two files. `a` is considered the old version and `b` the new one.
+pub fn diff(a: &AsepriteFile, b: &AsepriteFile) -> FileDiff {
+    let size = Change::between(
+        (a.width() as u32, a.height() as u32),
+        (b.width() as u32, b.height() as u32),
+    );
+    let num_frames = Change::between(a.num_frames(), b.num_frames());
+    let common_frames = a.num_frames().min(b.num_frames());
+    let frame_durations = (0..common_frames)
+        .filter_map(|frame| {
+            Change::between(a.frame(frame).duration(), b.frame(frame).duration())
+                .map(|duration| FrameDurationChange { frame, duration })
+        })
+        .collect();
+
+    FileDiff {
+        size,
+        num_frames,
+        frame_durations,
+        layers: diff_layers(a, b, common_frames),
+        tags: diff_tags(a, b),
+        slices: diff_slices(a, b),
+        palette: diff_palettes(a.palette(), b.palette()),
+    }
+}
+
+// Pairs up items of two lists. Each matcher is tried in turn on all items that
+// are still unpaired, so earlier matchers take precedence. Within a matcher,
+// the first unpaired item of `b` wins. Returns the partner in `b` for each
+// item in `a` and the items of `b` that were not paired.
+pub(crate) fn match_items(
+    len_a: usize,
+    len_b: usize,
+    matchers: &[&dyn Fn(usize, usize) -> bool],
+) -> (Vec<Option<usize>>, Vec<usize>) {
+    let mut partners = vec![None; len_a];
+    let mut used = vec![false; len_b];
+    for matcher in matchers {
+        for (ia, partner) in partners.iter_mut().enumerate() {
+            if partner.is_some() {
+                continue;
+            }
+            if let Some(ib) = (0..len_b).find(|&ib| !used[ib] && matcher(ia, ib)) {
+                used[ib] = true;
+                *partner = Some(ib);
+            }
+        }
+    }
+    let unpaired = (0..len_b).filter(|&ib| !used[ib]).collect();
+    (partners, unpaired)
+}
+
+fn diff_layers(a: &AsepriteFile, b: &AsepriteFile, common_frames: u32) -> Vec<LayerDiff> {
+    let paths_a: Vec<String> = a.layers().map(|l| l.path()).collect();
+    let paths_b: Vec<String> = b.layers().map(|l| l.path()).collect();
+    let same_uuid = |ia: usize, ib: usize| {
+        let uuid = a.layer(ia as u32).uuid();
+        uuid.is_some() && uuid == b.layer(ib as u32).uuid()
+    };
+    let same_path = |ia: usize, ib: usize| paths_a[ia] == paths_b[ib];
+    let same_name = |ia: usize, ib: usize| a.layer(ia as u32).name() == b.layer(ib as u32).name();
+    let (partners, added) = match_items(
+        paths_a.len(),
+        paths_b.len(),
+        &[&same_uuid, &same_path, &same_name],
+    );
+
+    let mut result = Vec::new();
+    for (ia, partner) in partners.into_iter().enumerate() {
+        let layer_a = a.layer(ia as u32);
+        let ib = match partner {
+            Some(ib) => ib,
+            None => {
+                result.push(LayerDiff::new(paths_a[ia].clone(), ChangeKind::Removed));
+                continue;
+            }
+        };
+        let layer_b = b.layer(ib as u32);
+        let old_path = if paths_a[ia] != paths_b[ib] {
+            Some(paths_a[ia].clone())
+        } else {
+            None
+        };
+        let changed_frames = (0..common_frames)
+            .filter(|&frame| {
+                layer_a.frame(frame).content_hash() != layer_b.frame(frame).content_hash()
+            })
+            .collect();
+        let layer_diff = LayerDiff {
+            path: paths_b[ib].clone(),
+            kind: ChangeKind::Modified,
+            old_path,
+            blend_mode: Change::between(layer_a.blend_mode(), layer_b.blend_mode()),
+            opacity: Change::between(layer_a.opacity(), layer_b.opacity()),
+            visible: Change::between(layer_a.is_visible(), layer_b.is_visible()),
+            changed_frames,
+        };
+        if layer_diff.has_changes() {
+            result.push(layer_diff);
+        }
+    }
+    for ib in added {
+        result.push(LayerDiff::new(paths_b[ib].clone(), ChangeKind::Added));
+    }
+    result
+}
+
+impl LayerDiff {
+    fn new(path: String, kind: ChangeKind) -> Self {
+        LayerDiff {
+            path,
+            kind,
+            old_path: None,
+            blend_mode: None,
+            opacity: None,
+            visible: None,
+            changed_frames: Vec::new(),
+        }
+    }
+
+    fn has_changes(&self) -> bool {
+        self.old_path.is_some()
+            || self.blend_mode.is_some()
+            || self.opacity.is_some()
+            || self.visible.is_some()
+            || !self.changed_frames.is_empty()
+    }
+}
+
+fn diff_tags(a: &AsepriteFile, b: &AsepriteFile) -> Vec<TagDiff> {
+    let same_name = |ia: usize, ib: usize| a.tags[ia].name() == b.tags[ib].name();
+    let (partners, added) = match_items(a.tags.len(), b.tags.len(), &[&same_name]);
+    let tag_diff = |name: &str, kind| TagDiff {
+        name: name.to_owned(),
+        kind,
+        frames: None,
+        direction: None,
+        repeat: None,
+    };
+
+    let mut result = Vec::new();
+    for (ia, partner) in partners.into_iter().enumerate() {
+        let tag_a = &a.tags[ia];
+        match partner {
+            None => result.push(tag_diff(tag_a.name(), ChangeKind::Removed)),
+            Some(ib) => {
+                let tag_b = &b.tags[ib];
+                let frames = Change::between(
+                    (tag_a.from_frame(), tag_a.to_frame()),
+                    (tag_b.from_frame(), tag_b.to_frame()),
+                );
+                let direction =
+                    Change::between(tag_a.animation_direction(), tag_b.animation_direction());
+                let repeat = Change::between(tag_a.repeat(), tag_b.repeat());
+                if frames.is_some() || direction.is_some() || repeat.is_some() {
+                    result.push(TagDiff {
+                        frames,
+                        direction,
+                        repeat,
+                        ..tag_diff(tag_b.name(), ChangeKind::Modified)
+                    });
+                }
+            }
+        }
+    }
+    for ib in added {
+        result.push(tag_diff(b.tags[ib].name(), ChangeKind::Added));
+    }
+    result
+}
+
+fn diff_slices(a: &AsepriteFile, b: &AsepriteFile) -> Vec<SliceDiff> {
+    let (slices_a, slices_b) = (a.slices(), b.slices());
+    let same_name = |ia: usize, ib: usize| slices_a[ia].name == slices_b[ib].name;
+    let (partners, added) = match_items(slices_a.len(), slices_b.len(), &[&same_name]);
+    let slice_diff = |name: &str, kind, keys_changed| SliceDiff {
+        name: name.to_owned(),
+        kind,
+        keys_changed,
+    };
+
+    let mut result = Vec::new();
+    for (ia, partner) in partners.into_iter().enumerate() {
+        let slice_a = &slices_a[ia];
+        match partner {
+            None => result.push(slice_diff(&slice_a.name, ChangeKind::Removed, false)),
+            Some(ib) => {
+                if slice_a.keys != slices_b[ib].keys {
+                    result.push(slice_diff(&slice_a.name, ChangeKind::Modified, true));
+                }
+            }
+        }
+    }
+    for ib in added {
+        result.push(slice_diff(&slices_b[ib].name, ChangeKind::Added, false));
+    }
+    result
+}
+
+fn diff_palettes(a: Option<&ColorPalette>, b: Option<&ColorPalette>) -> Option<PaletteDiff> {
+    let num_colors = |p: Option<&ColorPalette>| p.map(|p| p.num_colors()).unwrap_or(0);
+    let entry = |p: Option<&ColorPalette>, index: u32| {
+        p.and_then(|p| p.color(index))
+            .map(|c| (c.raw_rgba8(), c.name().map(str::to_owned)))
+    };
+    // Palette indices are usually contiguous, but don't rely on it.
+    let max_index = |p: Option<&ColorPalette>| {
+        p.and_then(|p| p.entries.keys().max().copied())
+            .map(|i| i + 1)
+            .unwrap_or(0)
+    };
+    let changed_colors: Vec<u32> = (0..max_index(a).max(max_index(b)))
+        .filter(|&index| entry(a, index) != entry(b, index))
+        .collect();
+    let num_colors = Change::between(num_colors(a), num_colors(b));
+
+    if num_colors.is_none() && changed_colors.is_empty() {
+        None
+    } else {
+        Some(PaletteDiff {
+            num_colors,
+            changed_colors,
+        })
+    }
+}
+
+fn join<T: fmt::Display>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for FileDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no changes");
+        }
+        if let Some(Change { old, new }) = self.size {
+            writeln!(
+                f,
+                "canvas resized: {}x{} -> {}x{}",
+                old.0, old.1, new.0, new.1
+            )?;
+        }
+        if let Some(Change { old, new }) = self.num_frames {
+            writeln!(f, "frame count: {} -> {}", old, new)?;
+        }
+        for FrameDurationChange { frame, duration } in &self.frame_durations {
+            writeln!(
+                f,
+                "frame {} duration: {}ms -> {}ms",
+                frame, duration.old, duration.new
+            )?;
+        }
+        for layer in &self.layers {
+            write!(f, "layer {:?}", layer.path)?;
+            match layer.kind {
+                ChangeKind::Added => writeln!(f, " added")?,
+                ChangeKind::Removed => writeln!(f, " removed")?,
+                ChangeKind::Modified => {
+                    let mut changes = Vec::new();
+                    if let Some(old_path) = &layer.old_path {
+                        changes.push(format!("moved from {:?}", old_path));
+                    }
+                    if let Some(Change { old, new }) = layer.blend_mode {
+                        changes.push(format!("blend mode {:?} -> {:?}", old, new));
+                    }
+                    if let Some(Change { old, new }) = layer.opacity {
+                        changes.push(format!("opacity {} -> {}", old, new));
+                    }
+                    if let Some(Change { new, .. }) = layer.visible {
+                        changes.push(if new { "shown" } else { "hidden" }.to_owned());
+                    }
+                    if !layer.changed_frames.is_empty() {
+                        changes.push(format!(
+                            "pixels changed in frames {}",
+                            join(&layer.changed_frames)
+                        ));
+                    }
+                    writeln!(f, ": {}", changes.join("; "))?;
+                }
+            }
+        }
+        for tag in &self.tags {
+            write!(f, "tag {:?}", tag.name)?;
+            match tag.kind {
+                ChangeKind::Added => writeln!(f, " added")?,
+                ChangeKind::Removed => writeln!(f, " removed")?,
+                ChangeKind::Modified => {
+                    let mut changes = Vec::new();
+                    if let Some(Change { old, new }) = tag.frames {
+                        changes.push(format!("frames {}-{} -> {}-{}", old.0, old.1, new.0, new.1));
+                    }
+                    if let Some(Change { old, new }) = tag.direction {
+                        changes.push(format!("direction {:?} -> {:?}", old, new));
+                    }
+                    if let Some(Change { old, new }) = tag.repeat {
+                        changes.push(format!("repeat {} -> {}", old, new));
+                    }
+                    writeln!(f, ": {}", changes.join("; "))?;
+                }
+            }
+        }
+        for slice in &self.slices {
+            let what = match slice.kind {
+                ChangeKind::Added => "added",
+                ChangeKind::Removed => "removed",
+                ChangeKind::Modified => "keys changed",
+            };
+            writeln!(f, "slice {:?} {}", slice.name, what)?;
+        }
+        if let Some(palette) = &self.palette {
+            write!(f, "palette:")?;
+            if let Some(Change { old, new }) = palette.num_colors {
+                write!(f, " {} -> {} colors", old, new)?;
+                if !palette.changed_colors.is_empty() {
+                    write!(f, ";")?;
+                }
+            }
+            if !palette.changed_colors.is_empty() {
+                write!(f, " changed colors {}", join(&palette.changed_colors))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+#[cfg(feature = "image")]
+use crate::image::RgbaImage;
+use crate::{
+    cel::{CelId, ImageSize},
+    layer::LayerType,
+    pixel::Pixels,
+    raw_image, AsepriteFile, AsepriteParseError, PaletteRemap, PixelFormat, Result, Tag,
+};
+use std::convert::TryFrom;
+
+/// # Editing
+///
+/// Layers, tags, and frames are referred to by their ids, i.e., the same
+/// values that are passed to [AsepriteFile::layer], [AsepriteFile::tag], and
+/// [AsepriteFile::frame]. An edit never changes any ids. Handles such as
+/// [Layer](crate::Layer) or [Frame](crate::Frame) borrow the file, so they
+/// have to be obtained again after an edit.
+///
+/// Edited files can be saved with [AsepriteFile::write_file]. Everything that
+/// was not edited is written back as it was loaded.
+impl AsepriteFile {
+    /// Replace the image of the cel at the given frame and layer, or create
+    /// the cel if it is empty. `origin` is the position of the image's top
+    /// left corner on the canvas and may lie outside the canvas.
+    ///
+    /// If the cel is linked to another cel, the link is broken and only this
+    /// cel changes. Likewise, cels that are linked to this cel keep showing
+    /// the old image. The cel's opacity and user data are kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the frame or layer does
+    /// not exist, the layer is not an image layer, the image is empty or
+    /// larger than 65535 pixels in either direction, or `origin` is outside
+    /// the range of cel positions (`-32768..=32767`). Only files with an
+    /// RGBA pixel format can be edited; other files result in
+    /// [AsepriteParseError::UnsupportedFeature].
+    #[cfg(feature = "image")]
+    pub fn set_cel_image(
+        &mut self,
+        frame: u32,
+        layer: u32,
+        image: &RgbaImage,
+        origin: (i32, i32),
+    ) -> Result<()> {
+        self.set_cel_rgba(frame, layer, image.dimensions(), image.as_raw(), origin)
+    }
+
+    /// Like [set_cel_image](AsepriteFile::set_cel_image) but takes a
+    /// [raw_image::RgbaImage]. This is available without the `image` feature.
+    ///
+    /// # Errors
+    ///
+    /// See [set_cel_image](AsepriteFile::set_cel_image).
+    pub fn set_cel_raw_image(
+        &mut self,
+        frame: u32,
+        layer: u32,
+        image: &raw_image::RgbaImage,
+        origin: (i32, i32),
+    ) -> Result<()> {
+        self.set_cel_rgba(frame, layer, image.dimensions(), image.as_raw(), origin)
+    }
+
+    fn set_cel_rgba(
+        &mut self,
+        frame: u32,
+        layer: u32,
+        (width, height): (u32, u32),
+        pixels: &[u8],
+        origin: (i32, i32),
+    ) -> Result<()> {
+        self.check_frame(frame)?;
+        self.check_layer(layer)?;
+        if self.pixel_format != PixelFormat::Rgba {
+            return Err(AsepriteParseError::UnsupportedFeature(format!(
+                "Cannot store RGBA images in a file with pixel format {:?}",
+                self.pixel_format
+            )));
+        }
+        if self.layers[layer].layer_type != LayerType::Image {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Layer {} is not an image layer",
+                layer
+            )));
+        }
+        let size = match (u16::try_from(width), u16::try_from(height)) {
+            (Ok(width), Ok(height)) if width > 0 && height > 0 => ImageSize { width, height },
+            _ => {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Invalid cel image size: {}x{}",
+                    width, height
+                )))
+            }
+        };
+        let origin = match (i16::try_from(origin.0), i16::try_from(origin.1)) {
+            (Ok(x), Ok(y)) => (x, y),
+            _ => {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Cel position out of range: {:?}",
+                    origin
+                )))
+            }
+        };
+        let cel_id = CelId {
+            frame: frame as u16,
+            layer: layer as u16,
+        };
+        self.frame_cache.clear();
+        self.framedata.set_image(cel_id, origin, size, pixels)
+    }
+
+    /// Change the name of a layer.
+    ///
+    /// # Errors
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the layer does not exist.
+    pub fn rename_layer(&mut self, layer: u32, name: &str) -> Result<()> {
+        self.check_layer(layer)?;
+        self.layers.layers[layer as usize].name = name.to_owned();
+        Ok(())
+    }
+
+    /// Change the name of a tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the tag does not exist.
+    pub fn rename_tag(&mut self, tag: u32, name: &str) -> Result<()> {
+        self.tag_mut(tag)?.set_name(name.to_owned());
+        Ok(())
+    }
+
+    /// Change the frames of a tag. Both `from_frame` and `to_frame` are
+    /// included in the tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the tag or either frame
+    /// does not exist, or if `from_frame` is greater than `to_frame`.
+    pub fn set_tag_range(&mut self, tag: u32, from_frame: u32, to_frame: u32) -> Result<()> {
+        self.check_frame(from_frame)?;
+        self.check_frame(to_frame)?;
+        if from_frame > to_frame {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Invalid tag range: {}..={}",
+                from_frame, to_frame
+            )));
+        }
+        let tag = self.tag_mut(tag)?;
+        tag.set_range(from_frame as u16, to_frame as u16);
+        Ok(())
+    }
+
+    /// Change a color of the palette. `color` is in RGBA order. The entry's
+    /// name is kept.
+    ///
+    /// In indexed files this recolors all pixels that use the entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the file has no palette
+    /// or the palette has no entry with the given index.
+    pub fn set_palette_color(&mut self, index: u32, color: [u8; 4]) -> Result<()> {
+        let entry = self
+            .palette
+            .as_mut()
+            .and_then(|palette| palette.entries.get_mut(&index))
+            .ok_or_else(|| {
+                AsepriteParseError::InvalidInput(format!("Palette entry does not exist: {}", index))
+            })?;
+        entry.rgba8 = color;
+        self.frame_cache.clear();
+        Ok(())
+    }
+
+    /// Change the palette indices of all pixels of an indexed file with a
+    /// table from [ColorPalette::remap_to](crate::ColorPalette::remap_to).
+    /// This covers all cels and the tiles of all tilesets. Pixels with the
+    /// [transparent color index](AsepriteFile::transparent_color_index) stay
+    /// transparent, and pixels whose index has no new index are kept. Pixels
+    /// that are remapped to the transparent color index become transparent,
+    /// so the target palette should have its transparent color at the same
+    /// index.
+    ///
+    /// The palette itself does not change. To move the sprite to the target
+    /// palette, set the colors of the target with
+    /// [set_palette_color](AsepriteFile::set_palette_color).
+    ///
+    /// # Errors
+    ///
+    /// Returns [AsepriteParseError::UnsupportedFeature] if the file does not
+    /// use [PixelFormat::Indexed], or an error if the pixels of a frame could
+    /// not be decoded.
+    pub fn apply_palette_remap(&mut self, remap: &PaletteRemap) -> Result<()> {
+        let transparent_index = self.transparent_color_index().ok_or_else(|| {
+            AsepriteParseError::UnsupportedFeature(format!(
+                "Cannot remap the palette of a file with pixel format {:?}",
+                self.pixel_format
+            ))
+        })?;
+        self.framedata.decode_all()?;
+        for frame in 0..self.num_frames() {
+            if let Pixels::Indexed(indices) = self.framedata.frame_pixels_mut(frame as u16) {
+                remap.apply(indices, transparent_index);
+            }
+        }
+        for tileset in self.tilesets.map_mut().values_mut() {
+            if let Some(Pixels::Indexed(indices)) = &mut tileset.pixels {
+                remap.apply(indices, transparent_index);
+            }
+        }
+        self.frame_cache.clear();
+        Ok(())
+    }
+
+    /// Change the duration of a frame, in milliseconds.
+    ///
+    /// # Errors
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the frame does not exist.
+    pub fn set_frame_duration(&mut self, frame: u32, duration_ms: u16) -> Result<()> {
+        self.check_frame(frame)?;
+        self.frame_times[frame as usize] = duration_ms;
+        Ok(())
+    }
+
+    fn tag_mut(&mut self, tag: u32) -> Result<&mut Tag> {
+        self.tags
+            .get_mut(tag as usize)
+            .ok_or_else(|| AsepriteParseError::InvalidInput(format!("Tag does not exist: {}", tag)))
+    }
+
+    pub(crate) fn check_frame(&self, frame: u32) -> Result<()> {
+        if frame >= self.num_frames() {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Frame does not exist: {}",
+                frame
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_layer(&self, layer: u32) -> Result<()> {
+        if layer >= self.num_layers() {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Layer does not exist: {}",
+                layer
+            )));
+        }
+        Ok(())
+    }
+}
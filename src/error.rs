@@ -1,7 +1,23 @@
-use std::{error::Error, fmt, io, string::FromUtf8Error};
+use alloc::{
+    format,
+    string::{FromUtf8Error, String},
+};
+use core::{error::Error, fmt};
+#[cfg(feature = "std")]
+use std::io;
 
 /// An error occured while reading the Aseprite file.
+///
+/// Errors in the frames of a file say where they occurred, e.g., "Invalid
+/// Aseprite input: Invalid/Unsupported Cel type: 7 (in Cel chunk of frame 2 at
+/// offset 1234)". The offset is the number of bytes from the start of the
+/// input to the frame or chunk.
+///
+/// New variants may be added in minor releases, and the
+/// [IoError](AsepriteParseError::IoError) variant only exists with the `std`
+/// feature.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum AsepriteParseError {
     /// The input data was malformed. String contains detailed message.
     InvalidInput(String),
@@ -10,11 +26,39 @@ pub enum AsepriteParseError {
     UnsupportedFeature(String),
     /// An internal error occurred.
     InternalError(String),
+    /// The file exceeds one of the resource limits of its
+    /// [ReadOptions](crate::ReadOptions), e.g.,
+    /// [max_canvas_size](crate::ReadOptions::max_canvas_size). String
+    /// contains detailed message.
+    LimitExceeded(String),
     /// An IO error occured. Also includes errors where the input was shorter
-    /// than expected.
+    /// than expected. Without the `std` feature, such errors are reported as
+    /// [InvalidInput](AsepriteParseError::InvalidInput) instead.
+    #[cfg(feature = "std")]
     IoError(io::Error),
 }
 
+impl AsepriteParseError {
+    // Adds where in the file the error occurred to its message, e.g., "Cel
+    // chunk of frame 2 at offset 1234".
+    pub(crate) fn with_context(self, context: &str) -> Self {
+        let add = |msg: String| format!("{} (in {})", msg, context);
+        match self {
+            AsepriteParseError::InvalidInput(msg) => AsepriteParseError::InvalidInput(add(msg)),
+            AsepriteParseError::UnsupportedFeature(msg) => {
+                AsepriteParseError::UnsupportedFeature(add(msg))
+            }
+            AsepriteParseError::InternalError(msg) => AsepriteParseError::InternalError(add(msg)),
+            AsepriteParseError::LimitExceeded(msg) => AsepriteParseError::LimitExceeded(add(msg)),
+            #[cfg(feature = "std")]
+            AsepriteParseError::IoError(err) => {
+                AsepriteParseError::IoError(io::Error::new(err.kind(), add(err.to_string())))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 impl From<io::Error> for AsepriteParseError {
     fn from(err: io::Error) -> Self {
         AsepriteParseError::IoError(err)
@@ -37,6 +81,10 @@ impl fmt::Display for AsepriteParseError {
             AsepriteParseError::InternalError(msg) => {
                 write!(f, "Internal error: {}", msg)
             }
+            AsepriteParseError::LimitExceeded(msg) => {
+                write!(f, "Resource limit exceeded: {}", msg)
+            }
+            #[cfg(feature = "std")]
             AsepriteParseError::IoError(err) => write!(f, "I/O error: {}", err),
         }
     }
@@ -45,8 +93,134 @@ impl fmt::Display for AsepriteParseError {
 impl Error for AsepriteParseError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
+            #[cfg(feature = "std")]
             AsepriteParseError::IoError(err) => Some(err),
             _ => None,
         }
     }
 }
+
+/// A problem with a file that was loaded anyway. See
+/// [AsepriteFile::warnings](crate::AsepriteFile::warnings).
+///
+/// These are anomalies that Aseprite itself tolerates or that a newer version
+/// of Aseprite may produce. Loading with
+/// [ReadOptions::strict](crate::ReadOptions::strict) turns them into errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// There is data after the end of the last frame.
+    TrailingData {
+        /// Offset of the first byte after the last frame.
+        offset: u64,
+        /// Number of bytes after the last frame. See
+        /// [AsepriteFile::trailing_data](crate::AsepriteFile::trailing_data).
+        len: u64,
+    },
+    /// The file size in the header does not match the end of the last frame.
+    FileSizeMismatch {
+        /// The file size in the header.
+        declared: u32,
+        /// The offset at which the last frame ends.
+        actual: u64,
+    },
+    /// A chunk of a type that this version of `asefile` does not know, e.g.,
+    /// from a newer version of Aseprite. It is ignored.
+    UnknownChunk {
+        /// The frame that contains the chunk.
+        frame: u16,
+        /// The type code of the chunk.
+        chunk_type: u16,
+    },
+    /// A layer has flags that this version of `asefile` does not know. They
+    /// are ignored.
+    UnknownLayerFlags {
+        /// The index of the layer.
+        layer: u32,
+        /// The unknown bits of the layer's flags.
+        flags: u16,
+    },
+    /// A user data chunk does not belong to anything, e.g., because it is the
+    /// first chunk of the file or there are more user data chunks after a
+    /// tileset than it has tiles. It is ignored.
+    UnusedUserData {
+        /// The frame that contains the chunk.
+        frame: u16,
+    },
+    /// Only the first frames of the file could be read, e.g., because it was
+    /// cut off. Only reported by
+    /// [AsepriteFile::read_partial](crate::AsepriteFile::read_partial).
+    Truncated {
+        /// The number of frames that were read.
+        frames: u16,
+        /// The number of frames in the header.
+        declared_frames: u16,
+    },
+    /// A cel belongs to a layer that does not exist. The cel is dropped.
+    MissingLayer {
+        /// The frame of the cel.
+        frame: u16,
+        /// The layer index of the cel.
+        layer: u16,
+    },
+    /// A tilemap cel uses a tile id beyond the end of its tileset, which
+    /// Aseprite writes after tiles were deleted from the tileset. Such tiles
+    /// are drawn as tile 0, the empty tile.
+    TileIdOutOfRange {
+        /// The frame of the cel.
+        frame: u16,
+        /// The layer index of the cel.
+        layer: u16,
+        /// The first tile id of the cel that is out of range.
+        tile_id: u32,
+    },
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseWarning::TrailingData { offset, len } => {
+                write!(f, "{} bytes of trailing data at offset {}", len, offset)
+            }
+            ParseWarning::FileSizeMismatch { declared, actual } => write!(
+                f,
+                "File size in header is {} bytes, but the last frame ends at offset {}",
+                declared, actual
+            ),
+            ParseWarning::UnknownChunk { frame, chunk_type } => {
+                write!(
+                    f,
+                    "Unknown chunk type 0x{:x} in frame {}",
+                    chunk_type, frame
+                )
+            }
+            ParseWarning::UnknownLayerFlags { layer, flags } => {
+                write!(f, "Unknown flags 0x{:x} of layer {}", flags, layer)
+            }
+            ParseWarning::UnusedUserData { frame } => {
+                write!(f, "User data chunk in frame {} belongs to nothing", frame)
+            }
+            ParseWarning::Truncated {
+                frames,
+                declared_frames,
+            } => write!(
+                f,
+                "File is truncated: only {} of {} frames could be read",
+                frames, declared_frames
+            ),
+            ParseWarning::MissingLayer { frame, layer } => write!(
+                f,
+                "Cel in frame {} belongs to missing layer {}",
+                frame, layer
+            ),
+            ParseWarning::TileIdOutOfRange {
+                frame,
+                layer,
+                tile_id,
+            } => write!(
+                f,
+                "Tilemap cel in frame {} of layer {} uses missing tile {}",
+                frame, layer, tile_id
+            ),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+/*!
+Export animations as animated PNG (APNG) images.
+
+Requires the `apng` feature. [write()] encodes every frame of the sprite, or
+the frames of a single tag, together with their durations. Frames are stored
+losslessly with their full alpha channel, so unlike GIF there is no limit on
+the number of colors.
+
+```
+# use asefile::AsepriteFile;
+# use asefile::exporters::apng::{self, ApngOptions};
+# use std::path::Path;
+# let path = Path::new("./tests/data/layers_and_tags.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let mut data = Vec::new();
+apng::write(&ase, &mut data, &ApngOptions::new().tag(0)).unwrap();
+// std::fs::write("walk.png", data).unwrap();
+```
+
+The first exported frame is also the default image of the file, which is
+what viewers without APNG support display.
+
+When exporting a tag, its frames are written in playback order, so
+[Reverse](crate::AnimationDirection::Reverse) and
+[PingPong](crate::AnimationDirection::PingPong) tags play correctly in any
+viewer. A tag without a [repeat](crate::Tag::repeat) count loops forever.
+Otherwise all repetitions are written out and the animation plays once.
+Exporting the whole sprite always loops.
+*/
+use super::playback_frames;
+use crate::image::RgbaImage;
+use crate::png::{self, push_chunk, COLOR_TYPE_RGBA};
+use crate::AsepriteFile;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+/// Options for [write()].
+///
+/// ```
+/// # use asefile::exporters::apng::ApngOptions;
+/// let options = ApngOptions::new().tag(2);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ApngOptions {
+    tag: Option<u32>,
+}
+
+impl ApngOptions {
+    /// Default options: Export all frames.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only export the frames of the tag with the given id.
+    pub fn tag(mut self, tag_id: u32) -> Self {
+        self.tag = Some(tag_id);
+        self
+    }
+}
+
+// Frame durations are given as a fraction of a second.
+const DELAY_DENOMINATOR: u16 = 1000;
+// Leave the canvas as it is after a frame; the next one replaces it entirely.
+const DISPOSE_OP_NONE: u8 = 0;
+const BLEND_OP_SOURCE: u8 = 0;
+
+/// Write an animated PNG image.
+///
+/// # Errors
+///
+/// Fails with [io::ErrorKind::InvalidInput] if the tag does not exist. Also
+/// returns any error of the underlying writer.
+pub fn write<W: Write>(file: &AsepriteFile, mut w: W, options: &ApngOptions) -> io::Result<()> {
+    let (frames, num_plays) = match options.tag {
+        Some(tag_id) if tag_id >= file.num_tags() => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Tag does not exist: {}", tag_id),
+            ))
+        }
+        Some(tag_id) => {
+            let tag = file.tag(tag_id);
+            let num_plays: u32 = if tag.repeat() == 0 { 0 } else { 1 };
+            (playback_frames(tag), num_plays)
+        }
+        None => ((0..file.num_frames()).collect(), 0),
+    };
+
+    let (width, height) = file.size();
+    let mut data = png::SIGNATURE.to_vec();
+    let header = png::header(width as u32, height as u32, COLOR_TYPE_RGBA);
+    push_chunk(&mut data, b"IHDR", &header);
+    let mut animation = (frames.len() as u32).to_be_bytes().to_vec();
+    animation.extend_from_slice(&num_plays.to_be_bytes());
+    push_chunk(&mut data, b"acTL", &animation);
+
+    // Ping-pong animations show most frames twice; encode each one once.
+    let mut encoded: Vec<Option<Vec<u8>>> = vec![None; file.num_frames() as usize];
+    // Frame control and frame data chunks share one sequence.
+    let mut sequence_number: u32 = 0;
+    for (index, frame) in frames.into_iter().enumerate() {
+        let compressed = encoded[frame as usize]
+            .get_or_insert_with(|| encode_image(&file.frame(frame).composite()));
+        let delay = u16::try_from(file.frame(frame).duration()).unwrap_or(u16::MAX);
+        let mut control = sequence_number.to_be_bytes().to_vec();
+        control.extend_from_slice(&(width as u32).to_be_bytes());
+        control.extend_from_slice(&(height as u32).to_be_bytes());
+        // Frame position, always at the top left corner.
+        control.extend_from_slice(&[0; 8]);
+        control.extend_from_slice(&delay.to_be_bytes());
+        control.extend_from_slice(&DELAY_DENOMINATOR.to_be_bytes());
+        control.extend_from_slice(&[DISPOSE_OP_NONE, BLEND_OP_SOURCE]);
+        push_chunk(&mut data, b"fcTL", &control);
+        sequence_number += 1;
+
+        if index == 0 {
+            push_chunk(&mut data, b"IDAT", compressed);
+        } else {
+            let mut frame_data = Vec::with_capacity(compressed.len() + 4);
+            frame_data.extend_from_slice(&sequence_number.to_be_bytes());
+            frame_data.extend_from_slice(compressed);
+            push_chunk(&mut data, b"fdAT", &frame_data);
+            sequence_number += 1;
+        }
+    }
+    push_chunk(&mut data, b"IEND", &[]);
+    w.write_all(&data)
+}
+
+fn encode_image(image: &RgbaImage) -> Vec<u8> {
+    png::compress(&png::filter_rows(image))
+}
+
+// Decoder for the subset of the format written by this module, used to test
+// the encoder.
+#[cfg(all(test, feature = "image"))]
+pub(crate) mod decode {
+    use super::*;
+    use crate::png::decode::u32_at;
+    use crate::png::{predict, BIT_DEPTH, BYTES_PER_PIXEL};
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    pub(crate) struct Animation {
+        pub width: u32,
+        pub height: u32,
+        pub num_plays: u32,
+        // Frame images and their durations in milliseconds.
+        pub frames: Vec<(RgbaImage, u32)>,
+    }
+
+    fn u16_at(data: &[u8], position: usize) -> u16 {
+        u16::from_be_bytes([data[position], data[position + 1]])
+    }
+
+    pub(crate) fn animation(data: &[u8]) -> Animation {
+        let chunks = png::decode::chunks(data);
+        let (chunk_type, header) = chunks[0];
+        assert_eq!(chunk_type, b"IHDR");
+        assert_eq!(header[8..], [BIT_DEPTH, COLOR_TYPE_RGBA, 0, 0, 0]);
+        let (chunk_type, animation) = chunks[1];
+        assert_eq!(chunk_type, b"acTL");
+        let mut result = Animation {
+            width: u32_at(header, 0),
+            height: u32_at(header, 4),
+            num_plays: u32_at(animation, 4),
+            frames: Vec::new(),
+        };
+        assert_eq!(chunks.last().unwrap().0, b"IEND");
+
+        let mut sequence_number = 0;
+        let mut index = 2;
+        while chunks[index].0 == b"fcTL" {
+            let control = chunks[index].1;
+            assert_eq!(u32_at(control, 0), sequence_number);
+            assert_eq!(u32_at(control, 4), result.width);
+            assert_eq!(u32_at(control, 8), result.height);
+            assert_eq!(control[12..20], [0; 8]);
+            assert_eq!(u16_at(control, 22), DELAY_DENOMINATOR);
+            assert_eq!(control[24..], [DISPOSE_OP_NONE, BLEND_OP_SOURCE]);
+            let duration = u16_at(control, 20) as u32;
+            sequence_number += 1;
+
+            let (chunk_type, payload) = chunks[index + 1];
+            let compressed = if result.frames.is_empty() {
+                assert_eq!(chunk_type, b"IDAT");
+                payload
+            } else {
+                assert_eq!(chunk_type, b"fdAT");
+                assert_eq!(u32_at(payload, 0), sequence_number);
+                sequence_number += 1;
+                &payload[4..]
+            };
+            let image = image(compressed, result.width, result.height);
+            result.frames.push((image, duration));
+            index += 2;
+        }
+        assert_eq!(index, chunks.len() - 1);
+        assert_eq!(result.frames.len() as u32, u32_at(animation, 0));
+        result
+    }
+
+    fn image(compressed: &[u8], width: u32, height: u32) -> RgbaImage {
+        let mut filtered = Vec::new();
+        ZlibDecoder::new(compressed)
+            .read_to_end(&mut filtered)
+            .unwrap();
+        let stride = width as usize * BYTES_PER_PIXEL;
+        assert_eq!(filtered.len(), (stride + 1) * height as usize);
+        let mut raw: Vec<u8> = Vec::with_capacity(stride * height as usize);
+        let zeros = vec![0; stride];
+        for (y, row) in filtered.chunks(stride + 1).enumerate() {
+            let start = y * stride;
+            for i in 0..stride {
+                let (previous, current) = raw.split_at(start);
+                let above = if y == 0 {
+                    &zeros[..]
+                } else {
+                    &previous[start - stride..]
+                };
+                let predicted = predict(row[0], current, above, i);
+                raw.push(row[i + 1].wrapping_add(predicted));
+            }
+        }
+        RgbaImage::from_raw(width, height, raw).unwrap()
+    }
+}
@@ -0,0 +1,61 @@
+/*!
+Export frames as Windows cursors (`.cur`).
+
+Requires the `cur` feature. A cursor is an icon (see `exporters::ico`) with a
+hotspot: the pixel that is the actual position of the mouse pointer. [write()]
+takes the hotspot from the [pivot](crate::SliceKey::pivot) of a slice, so it
+can be edited in Aseprite along with the image.
+
+```
+# use asefile::AsepriteFile;
+# use asefile::exporters::cur;
+# use std::path::Path;
+# let path = Path::new("./tests/data/slice.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let mut data = Vec::new();
+cur::write(&ase, 0, 32, Some("Slice 1"), &mut data).unwrap();
+// std::fs::write("pointer.cur", data).unwrap();
+```
+*/
+use super::ico::{render, write_entries, Entry};
+use crate::png;
+use crate::AsepriteFile;
+use std::io::{self, Write};
+
+const CURSOR_TYPE: u16 = 2;
+
+/// Write a cursor showing the given frame, scaled to `size` pixels as in
+/// `exporters::ico::write`.
+///
+/// The hotspot is the pivot of the slice named `hotspot_slice` at this frame,
+/// scaled along with the image. It is moved to the nearest edge if it lies
+/// outside the canvas. The top left corner is used if no slice name is
+/// given, there is no such slice, or the slice is hidden or has no pivot at
+/// this frame.
+///
+/// # Errors
+///
+/// Fails with [io::ErrorKind::InvalidInput] if the frame does not exist or
+/// `size` is not between 1 and 256. Also returns any error of the underlying
+/// writer.
+pub fn write<W: Write>(
+    file: &AsepriteFile,
+    frame: u32,
+    size: u32,
+    hotspot_slice: Option<&str>,
+    w: W,
+) -> io::Result<()> {
+    let (image, placement) = render(file, frame, size)?;
+    let hotspot = hotspot_slice
+        .and_then(|name| file.slice(name))
+        .and_then(|slice| slice.key_for_frame(frame))
+        .filter(|key| key.size.width != 0 && key.size.height != 0)
+        .and_then(|key| key.pivot_position())
+        .map_or((0, 0), |(x, y)| placement.icon_pixel(x, y));
+    let entry = Entry {
+        size,
+        fields: hotspot,
+        png: png::encode(&image),
+    };
+    write_entries(w, CURSOR_TYPE, &[entry])
+}
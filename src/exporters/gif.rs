@@ -0,0 +1,319 @@
+/*!
+Export animations as animated GIF images.
+
+Requires the `gif` feature. [write()] encodes every frame of the sprite, or
+the frames of a single tag, together with their durations.
+
+All frames share one palette of at most 256 colors, one of which is reserved
+for transparency. Sprites that use fewer colors are stored exactly, which
+includes every indexed sprite with a transparent color. Otherwise the palette
+is computed from all exported frames with the NeuQuant algorithm and each
+pixel is mapped to its closest palette color. GIF has no partial
+transparency: Pixels with an alpha value below 128 become fully transparent,
+all others fully opaque.
+
+```
+# use asefile::AsepriteFile;
+# use asefile::exporters::gif::{self, GifOptions};
+# use std::path::Path;
+# let path = Path::new("./tests/data/layers_and_tags.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let mut data = Vec::new();
+gif::write(&ase, &mut data, &GifOptions::new().tag(0)).unwrap();
+// std::fs::write("walk.gif", data).unwrap();
+```
+
+GIF stores durations in hundredths of a second, so frame durations are
+rounded to the nearest 10 milliseconds. Durations below 20 milliseconds are
+raised to 20 milliseconds because most viewers play shorter delays much
+slower than intended.
+
+When exporting a tag, its frames are written in playback order, so
+[Reverse](crate::AnimationDirection::Reverse) and
+[PingPong](crate::AnimationDirection::PingPong) tags play correctly in any
+viewer. A tag without a [repeat](crate::Tag::repeat) count loops forever.
+Otherwise all repetitions are written out and the animation plays once.
+Exporting the whole sprite always loops.
+*/
+use super::playback_frames;
+use crate::image::RgbaImage;
+use crate::AsepriteFile;
+use color_quant::NeuQuant;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Options for [write()].
+///
+/// ```
+/// # use asefile::exporters::gif::GifOptions;
+/// let options = GifOptions::new().tag(2);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct GifOptions {
+    tag: Option<u32>,
+}
+
+impl GifOptions {
+    /// Default options: Export all frames.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only export the frames of the tag with the given id.
+    pub fn tag(mut self, tag_id: u32) -> Self {
+        self.tag = Some(tag_id);
+        self
+    }
+}
+
+// Pixels with a lower alpha value are transparent.
+const ALPHA_THRESHOLD: u8 = 128;
+// Palette index of transparent pixels.
+const TRANSPARENT_INDEX: u8 = 0;
+const MAX_COLORS: usize = 256;
+// Between 1 (best) and 30 (fastest). 10 is the recommended trade-off.
+const SAMPLE_FACTOR: i32 = 10;
+// In hundredths of a second.
+const MIN_DELAY: u32 = 2;
+const MAX_CODE_SIZE: u8 = 12;
+
+// Block introducers and labels.
+const EXTENSION: u8 = 0x21;
+const GRAPHIC_CONTROL_LABEL: u8 = 0xF9;
+const APPLICATION_LABEL: u8 = 0xFF;
+const IMAGE_SEPARATOR: u8 = 0x2C;
+const TRAILER: u8 = 0x3B;
+// Graphic control flags: Clear the canvas after each frame, so that
+// transparent pixels don't show the previous frame.
+const DISPOSE_TO_BACKGROUND: u8 = 2 << 2;
+const TRANSPARENCY_FLAG: u8 = 0x01;
+const GLOBAL_COLOR_TABLE_FLAG: u8 = 0x80;
+
+/// Write an animated GIF image.
+///
+/// # Errors
+///
+/// Fails with [io::ErrorKind::InvalidInput] if the tag does not exist. Also
+/// returns any error of the underlying writer.
+pub fn write<W: Write>(file: &AsepriteFile, mut w: W, options: &GifOptions) -> io::Result<()> {
+    let (frames, loops) = match options.tag {
+        Some(tag_id) if tag_id >= file.num_tags() => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Tag does not exist: {}", tag_id),
+            ))
+        }
+        Some(tag_id) => {
+            let tag = file.tag(tag_id);
+            (playback_frames(tag), tag.repeat() == 0)
+        }
+        None => ((0..file.num_frames()).collect(), true),
+    };
+
+    // Ping-pong animations show most frames twice; render each one once.
+    let mut images: Vec<Option<RgbaImage>> = vec![None; file.num_frames() as usize];
+    for &frame in &frames {
+        images[frame as usize].get_or_insert_with(|| file.frame(frame).composite());
+    }
+    let mut palette = Palette::new(images.iter().flatten());
+    let bits = palette.bits();
+
+    let (width, height) = file.size();
+    let mut data = b"GIF89a".to_vec();
+    push_u16(&mut data, width as u16);
+    push_u16(&mut data, height as u16);
+    // Color resolution and size of the global color table, followed by the
+    // background color and the pixel aspect ratio.
+    data.push(GLOBAL_COLOR_TABLE_FLAG | ((bits - 1) << 4) | (bits - 1));
+    data.push(TRANSPARENT_INDEX);
+    data.push(0);
+    for index in 0..1 << bits {
+        let color = palette.colors.get(index).copied().unwrap_or([0, 0, 0]);
+        data.extend_from_slice(&color);
+    }
+    if loops {
+        data.extend_from_slice(&[EXTENSION, APPLICATION_LABEL, 11]);
+        data.extend_from_slice(b"NETSCAPE2.0");
+        // Sub-block 1 holds the loop count, 0 loops forever.
+        data.extend_from_slice(&[3, 1, 0, 0, 0]);
+    }
+
+    let min_code_size = bits.max(2);
+    let mut encoded: Vec<Option<Vec<u8>>> = vec![None; file.num_frames() as usize];
+    for frame in frames {
+        let image = images[frame as usize].as_ref().unwrap();
+        let lzw = encoded[frame as usize].get_or_insert_with(|| {
+            let indices: Vec<u8> = image.pixels().map(|px| palette.index(px.0)).collect();
+            lzw_encode(&indices, min_code_size)
+        });
+        let delay = ((file.frame(frame).duration() + 5) / 10).clamp(MIN_DELAY, u16::MAX as u32);
+        data.extend_from_slice(&[EXTENSION, GRAPHIC_CONTROL_LABEL, 4]);
+        data.push(DISPOSE_TO_BACKGROUND | TRANSPARENCY_FLAG);
+        push_u16(&mut data, delay as u16);
+        data.extend_from_slice(&[TRANSPARENT_INDEX, 0]);
+
+        // Every frame covers the whole canvas and uses the global palette.
+        data.push(IMAGE_SEPARATOR);
+        push_u16(&mut data, 0);
+        push_u16(&mut data, 0);
+        push_u16(&mut data, width as u16);
+        push_u16(&mut data, height as u16);
+        data.push(0);
+        data.push(min_code_size);
+        for block in lzw.chunks(255) {
+            data.push(block.len() as u8);
+            data.extend_from_slice(block);
+        }
+        data.push(0);
+    }
+    data.push(TRAILER);
+    w.write_all(&data)
+}
+
+fn push_u16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+struct Palette {
+    // Entry 0 is the transparent color.
+    colors: Vec<[u8; 3]>,
+    indices: HashMap<[u8; 3], u8>,
+    // Only set if the frames contain too many colors.
+    quantizer: Option<NeuQuant>,
+}
+
+impl Palette {
+    fn new<'a>(images: impl Iterator<Item = &'a RgbaImage> + Clone) -> Self {
+        let mut palette = Palette {
+            colors: vec![[0, 0, 0]],
+            indices: HashMap::new(),
+            quantizer: None,
+        };
+        let opaque = images
+            .flat_map(|image| image.pixels())
+            .filter(|px| px.0[3] >= ALPHA_THRESHOLD);
+        for px in opaque.clone() {
+            let [r, g, b, _] = px.0;
+            if palette.indices.contains_key(&[r, g, b]) {
+                continue;
+            }
+            if palette.colors.len() == MAX_COLORS {
+                return Self::quantized(opaque);
+            }
+            palette
+                .indices
+                .insert([r, g, b], palette.colors.len() as u8);
+            palette.colors.push([r, g, b]);
+        }
+        palette
+    }
+
+    fn quantized<'a>(opaque: impl Iterator<Item = &'a crate::image::Rgba<u8>>) -> Self {
+        let mut samples = Vec::new();
+        for px in opaque {
+            samples.extend_from_slice(&[px.0[0], px.0[1], px.0[2], 255]);
+        }
+        let quantizer = NeuQuant::new(SAMPLE_FACTOR, MAX_COLORS - 1, &samples);
+        let mut colors = vec![[0, 0, 0]];
+        for color in quantizer.color_map_rgb().chunks(3) {
+            colors.push([color[0], color[1], color[2]]);
+        }
+        Palette {
+            colors,
+            indices: HashMap::new(),
+            quantizer: Some(quantizer),
+        }
+    }
+
+    // Number of bits per index, at least 1.
+    fn bits(&self) -> u8 {
+        let mut bits = 1;
+        while 1 << bits < self.colors.len() {
+            bits += 1;
+        }
+        bits
+    }
+
+    fn index(&mut self, [r, g, b, a]: [u8; 4]) -> u8 {
+        if a < ALPHA_THRESHOLD {
+            return TRANSPARENT_INDEX;
+        }
+        let quantizer = &self.quantizer;
+        *self.indices.entry([r, g, b]).or_insert_with(|| {
+            // Exact palettes contain every opaque color.
+            let quantizer = quantizer.as_ref().unwrap();
+            quantizer.index_of(&[r, g, b, 255]) as u8 + 1
+        })
+    }
+}
+
+// Variable-length LZW as used by GIF: codes are packed starting at the least
+// significant bit, the table is reset when it runs out of 12-bit codes.
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear_code = 1_u16 << min_code_size;
+    let end_code = clear_code + 1;
+    let mut out = BitWriter::default();
+    let mut code_size = min_code_size + 1;
+    // The most recently assigned code.
+    let mut last_code = end_code;
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    out.write(clear_code, code_size);
+
+    let mut indices = indices.iter();
+    let mut current = match indices.next() {
+        Some(&index) => index as u16,
+        None => {
+            out.write(end_code, code_size);
+            return out.finish();
+        }
+    };
+    for &index in indices {
+        if let Some(&code) = table.get(&(current, index)) {
+            current = code;
+            continue;
+        }
+        out.write(current, code_size);
+        last_code += 1;
+        if last_code == 1 << code_size {
+            code_size += 1;
+        }
+        if last_code == (1 << MAX_CODE_SIZE) - 1 {
+            out.write(clear_code, code_size);
+            table.clear();
+            code_size = min_code_size + 1;
+            last_code = end_code;
+        } else {
+            table.insert((current, index), last_code);
+        }
+        current = index as u16;
+    }
+    out.write(current, code_size);
+    out.write(end_code, code_size);
+    out.finish()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    buffer: u32,
+    num_bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.num_bits;
+        self.num_bits += size;
+        while self.num_bits >= 8 {
+            self.data.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.num_bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.num_bits > 0 {
+            self.data.push(self.buffer as u8);
+        }
+        self.data
+    }
+}
@@ -0,0 +1,257 @@
+/*!
+Export animations as Godot 4 `SpriteFrames` resources.
+
+Requires the `godot` feature. [write_sprite_frames] writes a text resource
+(`.tres`) that can be used with an `AnimatedSprite2D` node. The resource does
+not contain any pixels; it refers to the image of an [Atlas] created by
+[atlas::pack], and each frame is a region of that image.
+
+```
+# use asefile::AsepriteFile;
+# use asefile::atlas::{self, AtlasOptions};
+# use asefile::exporters::godot;
+# use std::path::Path;
+# let path = Path::new("./tests/data/basic-16x16.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let atlas = atlas::pack(&[("player", &ase)], &AtlasOptions::new().trim(true)).unwrap();
+// atlas.image().save("player.png").unwrap();
+let mut tres = Vec::new();
+godot::write_sprite_frames(&atlas, "player", &ase, "res://player.png", &mut tres).unwrap();
+// std::fs::write("player.tres", tres).unwrap();
+```
+
+[export_sprite_frames] does both at once and saves the resource next to its
+atlas image.
+
+Every tag becomes one animation with the same name. If the sprite has no tags,
+a single animation called `default` contains all frames. Trimmed frames get a
+margin, so Godot draws them at their original size and position.
+
+Godot describes the timing of an animation by a speed in frames per second
+and a relative duration for each frame. The speed is chosen such that all
+frame durations of the animation are represented exactly.
+
+Godot can neither play animations backwards nor repeat them a fixed number of
+times, so the frames of such tags are written out in playback order:
+[Reverse](crate::AnimationDirection::Reverse) tags list their frames in
+reverse, [PingPong](crate::AnimationDirection::PingPong) tags list the
+frames going forward and then back, and tags with a [repeat](crate::Tag::repeat)
+count are unrolled into a non-looping animation. Tags without a repeat count
+loop.
+*/
+use super::playback_frames;
+use crate::atlas::{self, Atlas, AtlasFrame, AtlasOptions};
+use crate::png;
+use crate::AsepriteFile;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Write a Godot 4 `SpriteFrames` resource in text format (`.tres`) for the
+/// sprite `file`, which was packed into `atlas` under the name `sprite`.
+///
+/// `image_path` is the path of the atlas image in the Godot project, e.g.,
+/// `res://sprites/player.png`, or relative to the resource. The atlas may
+/// contain other sprites as well.
+///
+/// # Errors
+///
+/// Fails with [io::ErrorKind::InvalidInput] if a frame of the sprite is
+/// missing from `atlas`. Also returns any error of the underlying writer.
+pub fn write_sprite_frames<W: Write>(
+    atlas: &Atlas,
+    sprite: &str,
+    file: &AsepriteFile,
+    image_path: &str,
+    mut w: W,
+) -> io::Result<()> {
+    let animations = animations(file);
+
+    let mut used_frames: Vec<u32> = animations
+        .iter()
+        .flat_map(|a| a.frames.iter().copied())
+        .collect();
+    used_frames.sort_unstable();
+    used_frames.dedup();
+    let regions = used_frames
+        .iter()
+        .map(|&frame| {
+            atlas
+                .frames()
+                .iter()
+                .find(|region| region.sprite() == sprite && region.frame() == frame)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Frame {} of sprite {} is missing", frame, sprite),
+                    )
+                })
+        })
+        .collect::<io::Result<Vec<&AtlasFrame>>>()?;
+
+    writeln!(
+        w,
+        "[gd_resource type=\"SpriteFrames\" load_steps={} format=3]",
+        used_frames.len() + 2
+    )?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "[ext_resource type=\"Texture2D\" path={} id=\"1\"]",
+        quote(image_path)
+    )?;
+    for (frame, region) in used_frames.iter().zip(regions) {
+        let (x, y, width, height) = region.rect();
+        writeln!(w)?;
+        writeln!(
+            w,
+            "[sub_resource type=\"AtlasTexture\" id=\"AtlasTexture_{}\"]",
+            frame
+        )?;
+        writeln!(w, "atlas = ExtResource(\"1\")")?;
+        writeln!(w, "region = Rect2({}, {}, {}, {})", x, y, width, height)?;
+        if region.is_trimmed() {
+            // The position of the region in the frame, and how much larger
+            // the frame is.
+            let (left, top) = region.trim_offset();
+            let (source_width, source_height) = region.source_size();
+            writeln!(
+                w,
+                "margin = Rect2({}, {}, {}, {})",
+                left,
+                top,
+                source_width - width,
+                source_height - height
+            )?;
+        }
+    }
+    writeln!(w)?;
+    writeln!(w, "[resource]")?;
+    write!(w, "animations = [")?;
+    for (index, animation) in animations.iter().enumerate() {
+        if index > 0 {
+            write!(w, ", ")?;
+        }
+        let durations: Vec<u32> = animation
+            .frames
+            .iter()
+            .map(|f| file.frame(*f).duration())
+            .collect();
+        let unit = durations.iter().copied().fold(0, gcd).max(1);
+        writeln!(w, "{{")?;
+        write!(w, "\"frames\": [")?;
+        for (index, (frame, duration)) in animation.frames.iter().zip(&durations).enumerate() {
+            if index > 0 {
+                write!(w, ", ")?;
+            }
+            writeln!(w, "{{")?;
+            writeln!(w, "\"duration\": {:?},", *duration as f64 / unit as f64)?;
+            writeln!(w, "\"texture\": SubResource(\"AtlasTexture_{}\")", frame)?;
+            write!(w, "}}")?;
+        }
+        writeln!(w, "],")?;
+        writeln!(w, "\"loop\": {},", animation.looping)?;
+        writeln!(w, "\"name\": &{},", quote(&animation.name))?;
+        writeln!(w, "\"speed\": {:?}", 1000.0 / unit as f64)?;
+        write!(w, "}}")?;
+    }
+    writeln!(w, "]")?;
+    Ok(())
+}
+
+/// Pack the frames of `file` into an atlas with the given `options`, and
+/// save a `SpriteFrames` resource to `path` and the atlas image next to it,
+/// with the same name and the extension `png`.
+///
+/// The resource refers to the image by its file name, which Godot resolves
+/// relative to the resource, so both files can be moved together within the
+/// project.
+///
+/// ```no_run
+/// # use asefile::AsepriteFile;
+/// # use asefile::atlas::AtlasOptions;
+/// # use asefile::exporters::godot;
+/// # use std::path::Path;
+/// # let ase = AsepriteFile::read_file(Path::new("player.aseprite")).unwrap();
+/// // Writes player.tres and player.png.
+/// let path = Path::new("godot/sprites/player.tres");
+/// godot::export_sprite_frames(&ase, path, &AtlasOptions::new().trim(true)).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Fails with [io::ErrorKind::InvalidInput] if `path` has no file name or is
+/// itself a `.png` file, or if the frames do not fit into the
+/// [maximum size](AtlasOptions::max_size) of the atlas. Also returns any
+/// error of writing the files.
+pub fn export_sprite_frames(
+    file: &AsepriteFile,
+    path: &Path,
+    options: &AtlasOptions,
+) -> io::Result<()> {
+    let image_path = path.with_extension("png");
+    let image_name = match image_path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if image_path != path => name.to_owned(),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid resource path: {}", path.display()),
+            ))
+        }
+    };
+    let atlas = atlas::pack(&[("sprite", file)], options)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    std::fs::write(&image_path, png::encode(&atlas.image))?;
+    let mut tres = Vec::new();
+    write_sprite_frames(&atlas, "sprite", file, &image_name, &mut tres)?;
+    std::fs::write(path, tres)
+}
+
+struct Animation {
+    name: String,
+    frames: Vec<u32>,
+    looping: bool,
+}
+
+fn animations(file: &AsepriteFile) -> Vec<Animation> {
+    if file.num_tags() == 0 {
+        return vec![Animation {
+            name: "default".to_owned(),
+            frames: (0..file.num_frames()).collect(),
+            looping: true,
+        }];
+    }
+    (0..file.num_tags())
+        .map(|id| {
+            let tag = file.tag(id);
+            Animation {
+                name: tag.name().to_owned(),
+                frames: playback_frames(tag),
+                looping: tag.repeat() == 0,
+            }
+        })
+        .collect()
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// A string literal in Godot's text resource format.
+fn quote(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
@@ -0,0 +1,167 @@
+/*!
+Export frames as Windows icons (`.ico`).
+
+Requires the `ico` feature. [write()] stores one or more frames, each scaled
+to a square size, in a single icon file. Applications pick the size that fits
+best, so an icon usually contains the same picture at several sizes. The
+`exporters::cur` module writes cursors, which use the same format.
+
+```
+# use asefile::AsepriteFile;
+# use asefile::exporters::ico;
+# use std::path::Path;
+# let path = Path::new("./tests/data/basic-16x16.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let mut data = Vec::new();
+ico::write(&ase, &[(0, 16), (0, 32), (0, 48)], &mut data).unwrap();
+// std::fs::write("favicon.ico", data).unwrap();
+```
+
+Frames are scaled with nearest-neighbor sampling, which keeps pixel art crisp
+when the size is a multiple of the sprite size. Sprites that are not square
+are centered and padded with transparent pixels. The images are stored in PNG
+format, which is supported since Windows Vista.
+*/
+use crate::image::{Rgba, RgbaImage};
+use crate::png;
+use crate::AsepriteFile;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+const ICON_TYPE: u16 = 1;
+const MAX_SIZE: u32 = 256;
+
+/// Write an icon containing the given frames. Each frame is paired with its
+/// size in pixels, which must be between 1 and 256. The same frame may be
+/// used for several sizes.
+///
+/// # Errors
+///
+/// Fails with [io::ErrorKind::InvalidInput] if `frames` is empty, a frame
+/// does not exist, or a size is out of range. Also returns any error of the
+/// underlying writer.
+pub fn write<W: Write>(file: &AsepriteFile, frames: &[(u32, u32)], w: W) -> io::Result<()> {
+    if frames.is_empty() {
+        return Err(invalid_input("No frames to export".to_owned()));
+    }
+    let entries = frames
+        .iter()
+        .map(|&(frame, size)| {
+            let (image, _) = render(file, frame, size)?;
+            Ok(Entry {
+                size,
+                // One color plane with 32 bits per pixel.
+                fields: (1, 32),
+                png: png::encode(&image),
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    write_entries(w, ICON_TYPE, &entries)
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// An image of the icon or cursor file.
+pub(crate) struct Entry {
+    pub size: u32,
+    // Color planes and bits per pixel for icons, the hotspot for cursors.
+    pub fields: (u16, u16),
+    pub png: Vec<u8>,
+}
+
+pub(crate) fn write_entries<W: Write>(
+    mut w: W,
+    image_type: u16,
+    entries: &[Entry],
+) -> io::Result<()> {
+    let count = u16::try_from(entries.len())
+        .map_err(|_| invalid_input(format!("Too many images: {}", entries.len())))?;
+    w.write_all(&0_u16.to_le_bytes())?;
+    w.write_all(&image_type.to_le_bytes())?;
+    w.write_all(&count.to_le_bytes())?;
+    let mut offset = 6 + 16 * entries.len() as u32;
+    for entry in entries {
+        // A size of 256 pixels is stored as 0.
+        let size = (entry.size % 256) as u8;
+        w.write_all(&[size, size, 0, 0])?;
+        w.write_all(&entry.fields.0.to_le_bytes())?;
+        w.write_all(&entry.fields.1.to_le_bytes())?;
+        w.write_all(&(entry.png.len() as u32).to_le_bytes())?;
+        w.write_all(&offset.to_le_bytes())?;
+        offset += entry.png.len() as u32;
+    }
+    for entry in entries {
+        w.write_all(&entry.png)?;
+    }
+    Ok(())
+}
+
+// Renders a frame scaled to fit into a square and centered on it.
+pub(crate) fn render(
+    file: &AsepriteFile,
+    frame: u32,
+    size: u32,
+) -> io::Result<(RgbaImage, Placement)> {
+    if frame >= file.num_frames() {
+        return Err(invalid_input(format!("Frame does not exist: {}", frame)));
+    }
+    if size == 0 || size > MAX_SIZE {
+        return Err(invalid_input(format!("Invalid icon size: {}", size)));
+    }
+    let (width, height) = file.size();
+    let placement = Placement::new((width as u32, height as u32), size);
+    let source = file.frame(frame).composite();
+    let image = RgbaImage::from_fn(size, size, |x, y| match placement.source_pixel(x, y) {
+        Some((x, y)) => *source.get_pixel(x, y),
+        None => Rgba([0, 0, 0, 0]),
+    });
+    Ok((image, placement))
+}
+
+// Where the canvas ends up in the icon.
+pub(crate) struct Placement {
+    canvas: (u32, u32),
+    scaled: (u32, u32),
+    offset: (u32, u32),
+}
+
+impl Placement {
+    fn new(canvas: (u32, u32), size: u32) -> Self {
+        let longest = canvas.0.max(canvas.1);
+        let fit = |length: u32| ((length * size + longest / 2) / longest).max(1);
+        let scaled = (fit(canvas.0), fit(canvas.1));
+        Self {
+            canvas,
+            scaled,
+            offset: ((size - scaled.0) / 2, (size - scaled.1) / 2),
+        }
+    }
+
+    // The canvas pixel shown at the given pixel of the icon, if any.
+    fn source_pixel(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        let (x, y) = (x.wrapping_sub(self.offset.0), y.wrapping_sub(self.offset.1));
+        if x >= self.scaled.0 || y >= self.scaled.1 {
+            return None;
+        }
+        Some((
+            x * self.canvas.0 / self.scaled.0,
+            y * self.canvas.1 / self.scaled.1,
+        ))
+    }
+
+    // The pixel of the icon that shows the given position on the canvas.
+    // Positions outside the canvas are moved to its nearest edge.
+    #[cfg(feature = "cur")]
+    pub(crate) fn icon_pixel(&self, x: i32, y: i32) -> (u16, u16) {
+        let map = |v: i32, canvas: u32, scaled: u32, offset: u32| {
+            let v = v.clamp(0, canvas as i32 - 1) as u32;
+            (offset + v * scaled / canvas) as u16
+        };
+        (
+            map(x, self.canvas.0, self.scaled.0, self.offset.0),
+            map(y, self.canvas.1, self.scaled.1, self.offset.1),
+        )
+    }
+}
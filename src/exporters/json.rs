@@ -0,0 +1,511 @@
+/*!
+Describe sprite sheets and atlases in the JSON format of `aseprite --data`.
+
+Requires the `json` feature. The output has the same structure as the data
+file written by the Aseprite CLI, so importers that already read Aseprite's
+JSON work unchanged. [write_sheet] describes a [SpriteSheet] of one sprite,
+[write_atlas] an [Atlas] of one or more sprites.
+
+```
+# use asefile::AsepriteFile;
+# use asefile::exporters::json::{self, JsonFormat, JsonOptions};
+# use asefile::spritesheet::{self, SpriteSheetOptions};
+# use std::path::Path;
+# let path = Path::new("./tests/data/layers_and_tags.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let sheet = spritesheet::build(&ase, &SpriteSheetOptions::new()).unwrap();
+// sheet.image().save("player.png").unwrap();
+let options = JsonOptions::new()
+    .format(JsonFormat::Array)
+    .image("player.png");
+let mut data = Vec::new();
+json::write_sheet(&sheet, "player", &ase, &mut data, &options).unwrap();
+// std::fs::write("player.json", data).unwrap();
+```
+
+Like Aseprite, the output contains these sections:
+
+- `frames`: The position of every frame in the image, its trimming, and its
+  duration. Keyed by file name in the [Hash](JsonFormat::Hash) format, a list
+  with a `filename` member in the [Array](JsonFormat::Array) format.
+- `meta.frameTags`: Every tag whose frames are all included, with `from` and
+  `to` referring to entries of `frames`.
+- `meta.layers`: The visible layers, which are the ones rendered into the
+  frames.
+- `meta.slices`: All slices with their keys.
+
+In addition to Aseprite's output, frames that contain slices have a `slices`
+member that lists them with `name`, `bounds`, `center`, and `pivot` moved
+into image coordinates, so that 9-slice and hitbox data can be used directly
+with the packed image. Unlike `meta.slices`, `center` and `pivot` are not
+relative to the bounds.
+
+User data of tags, layers, and slices is written as `color` and `data`.
+*/
+use super::json_value::Value;
+use crate::atlas::{self, Atlas, AtlasSlice};
+use crate::frame_export::{first_tag_containing, FileNamePattern};
+use crate::layer::LayerType;
+use crate::spritesheet::SpriteSheet;
+use crate::{AnimationDirection, AsepriteFile, BlendMode, UserData};
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+/// Layout of the `frames` section.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// An object with the file name of each frame as key. Aseprite's
+    /// `--format json-hash`.
+    #[default]
+    Hash,
+    /// A list of frames, each with a `filename` member. Aseprite's
+    /// `--format json-array`.
+    Array,
+}
+
+/// Options for [write_sheet] and [write_atlas].
+///
+/// ```
+/// # use asefile::exporters::json::{JsonFormat, JsonOptions};
+/// let options = JsonOptions::new()
+///     .format(JsonFormat::Array)
+///     .filename_format("{title}/{tag}_{tagframe}");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct JsonOptions {
+    format: JsonFormat,
+    image: Option<String>,
+    filename_format: Option<String>,
+}
+
+impl JsonOptions {
+    /// Default options: [JsonFormat::Hash] with Aseprite's default file names
+    /// and no image path.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Layout of the `frames` section.
+    pub fn format(mut self, format: JsonFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Path of the image, written as `meta.image`. Engines usually resolve it
+    /// relative to the JSON file.
+    pub fn image(mut self, path: &str) -> Self {
+        self.image = Some(path.to_owned());
+        self
+    }
+
+    /// Pattern for the name of each frame, with the placeholders of
+    /// [AsepriteFile::export_frames]. `{title}` is the name of the sprite.
+    /// Defaults to `"{title} {frame}.aseprite"`, or `"{title}.aseprite"` for
+    /// sprites with a single frame, like Aseprite.
+    pub fn filename_format(mut self, pattern: &str) -> Self {
+        self.filename_format = Some(pattern.to_owned());
+        self
+    }
+}
+
+/// Write the JSON description of a sprite sheet of `file`. `title` is used
+/// for the file names of the frames.
+///
+/// # Errors
+///
+/// Fails with [io::ErrorKind::InvalidInput] if the file name format is
+/// invalid or, in the [Hash](JsonFormat::Hash) format, produces the same name
+/// for two frames. Also returns any error of the underlying writer.
+pub fn write_sheet<W: Write>(
+    sheet: &SpriteSheet,
+    title: &str,
+    file: &AsepriteFile,
+    mut w: W,
+    options: &JsonOptions,
+) -> io::Result<()> {
+    let regions: Vec<Region> = sheet
+        .frames()
+        .iter()
+        .map(|frame| Region {
+            sprite: 0,
+            frame: frame.frame(),
+            rect: (frame.x(), frame.y(), frame.width(), frame.height()),
+            trim_offset: frame.trim_offset(),
+            trimmed: frame.is_trimmed(),
+            alias_of: frame.alias_of(),
+            slices: atlas::frame_slices(
+                file,
+                frame.frame(),
+                (frame.x(), frame.y()),
+                frame.trim_offset(),
+            ),
+        })
+        .collect();
+    let document = document(
+        &[(title, file)],
+        &regions,
+        sheet.image.dimensions(),
+        options,
+    )?;
+    document.write(&mut w)
+}
+
+/// Write the JSON description of an atlas. `sprites` must contain every
+/// sprite packed into the atlas, under the same name.
+///
+/// # Errors
+///
+/// Fails with [io::ErrorKind::InvalidInput] if a sprite of the atlas is
+/// missing from `sprites`, if the file name format is invalid or, in the
+/// [Hash](JsonFormat::Hash) format, produces the same name for two frames.
+/// Also returns any error of the underlying writer.
+pub fn write_atlas<W: Write>(
+    atlas: &Atlas,
+    sprites: &[(&str, &AsepriteFile)],
+    mut w: W,
+    options: &JsonOptions,
+) -> io::Result<()> {
+    let regions = atlas_regions(atlas, sprites)?;
+    let document = document(sprites, &regions, atlas.image.dimensions(), options)?;
+    document.write(&mut w)
+}
+
+// A frame in the image.
+pub(crate) struct Region {
+    // Index into the sprites.
+    pub(crate) sprite: usize,
+    pub(crate) frame: u32,
+    pub(crate) rect: (u32, u32, u32, u32),
+    pub(crate) trim_offset: (u32, u32),
+    pub(crate) trimmed: bool,
+    // Index of the region with the same image, if duplicates were merged.
+    pub(crate) alias_of: Option<usize>,
+    // The slices of the frame in image coordinates.
+    pub(crate) slices: Vec<AtlasSlice>,
+}
+
+pub(crate) fn atlas_regions(
+    atlas: &Atlas,
+    sprites: &[(&str, &AsepriteFile)],
+) -> io::Result<Vec<Region>> {
+    let mut regions = Vec::with_capacity(atlas.frames().len());
+    for frame in atlas.frames() {
+        let sprite = sprites
+            .iter()
+            .position(|&(name, _)| name == frame.sprite())
+            .ok_or_else(|| invalid_input(format!("Sprite is missing: {}", frame.sprite())))?;
+        regions.push(Region {
+            sprite,
+            frame: frame.frame(),
+            rect: frame.rect(),
+            trim_offset: frame.trim_offset(),
+            trimmed: frame.is_trimmed(),
+            alias_of: frame.alias_of(),
+            slices: frame.slices().to_vec(),
+        });
+    }
+    Ok(regions)
+}
+
+// The name of each region. Without a custom pattern, `single` is used for
+// sprites with one frame, `multiple` for all others.
+pub(crate) fn frame_names(
+    sprites: &[(&str, &AsepriteFile)],
+    regions: &[Region],
+    filename_format: Option<&str>,
+    (single, multiple): (&str, &str),
+) -> io::Result<Vec<String>> {
+    let custom_pattern = match filename_format {
+        Some(pattern) => Some(FileNamePattern::parse(pattern)?),
+        None => None,
+    };
+    let default_patterns = (
+        FileNamePattern::parse(single)?,
+        FileNamePattern::parse(multiple)?,
+    );
+    regions
+        .iter()
+        .map(|region| {
+            let (title, file) = sprites[region.sprite];
+            let pattern = match &custom_pattern {
+                Some(pattern) => pattern,
+                None if file.num_frames() == 1 => &default_patterns.0,
+                None => &default_patterns.1,
+            };
+            let tag = first_tag_containing(file, region.frame);
+            pattern.render(file, region.frame, tag, Some(title))
+        })
+        .collect()
+}
+
+pub(crate) fn check_unique(names: &[String]) -> io::Result<()> {
+    let mut unique = HashSet::new();
+    for name in names {
+        if !unique.insert(name.as_str()) {
+            return Err(invalid_input(format!(
+                "File name format produces {} more than once",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+// The `frames` section in the layout shared by Aseprite and TexturePacker,
+// without durations.
+pub(crate) fn frames(
+    sprites: &[(&str, &AsepriteFile)],
+    regions: &[Region],
+    names: Vec<String>,
+    format: JsonFormat,
+    extend: impl Fn(Value, &Region) -> Value,
+) -> io::Result<Value> {
+    let entries = names.into_iter().zip(regions).map(|(name, region)| {
+        let (x, y, w, h) = region.rect;
+        let (offset_x, offset_y) = region.trim_offset;
+        let (source_width, source_height) = sprites[region.sprite].1.size();
+        let entry = Value::object();
+        let entry = match format {
+            JsonFormat::Hash => entry,
+            JsonFormat::Array => entry.with("filename", name.as_str()),
+        };
+        let entry = entry
+            .with("frame", Value::rect(x.into(), y.into(), w, h))
+            .with("rotated", false)
+            .with("trimmed", region.trimmed)
+            .with(
+                "spriteSourceSize",
+                Value::rect(offset_x.into(), offset_y.into(), w, h),
+            )
+            .with(
+                "sourceSize",
+                Value::size(source_width as u32, source_height as u32),
+            );
+        (name, extend(entry, region))
+    });
+    Ok(match format {
+        JsonFormat::Hash => {
+            let entries: Vec<(String, Value)> = entries.collect();
+            let names: Vec<String> = entries.iter().map(|(name, _)| name.clone()).collect();
+            check_unique(&names)?;
+            Value::Object(entries)
+        }
+        JsonFormat::Array => Value::Array(entries.map(|(_, entry)| entry).collect()),
+    })
+}
+
+fn document(
+    sprites: &[(&str, &AsepriteFile)],
+    regions: &[Region],
+    size: (u32, u32),
+    options: &JsonOptions,
+) -> io::Result<Value> {
+    let names = frame_names(
+        sprites,
+        regions,
+        options.filename_format.as_deref(),
+        ("{title}.aseprite", "{title} {frame}.aseprite"),
+    )?;
+    let frames = frames(sprites, regions, names, options.format, |entry, region| {
+        let file = sprites[region.sprite].1;
+        let entry = entry.with("duration", file.frame(region.frame).duration());
+        if region.slices.is_empty() {
+            return entry;
+        }
+        entry.with(
+            "slices",
+            region.slices.iter().map(slice).collect::<Vec<_>>(),
+        )
+    })?;
+
+    let mut meta = Value::object()
+        .with("app", env!("CARGO_PKG_HOMEPAGE"))
+        .with("version", env!("CARGO_PKG_VERSION"));
+    if let Some(image) = &options.image {
+        meta = meta.with("image", image.as_str());
+    }
+    let meta = meta
+        .with("format", "RGBA8888")
+        .with("size", Value::size(size.0, size.1))
+        .with("scale", "1")
+        .with("frameTags", frame_tags(sprites, regions))
+        .with("layers", layers(sprites))
+        .with("slices", slices(sprites));
+
+    Ok(Value::object().with("frames", frames).with("meta", meta))
+}
+
+fn frame_tags(sprites: &[(&str, &AsepriteFile)], regions: &[Region]) -> Vec<Value> {
+    let index_of = |sprite: usize, frame: u32| {
+        regions
+            .iter()
+            .position(|region| region.sprite == sprite && region.frame == frame)
+    };
+    let mut tags = Vec::new();
+    for (sprite, &(_, file)) in sprites.iter().enumerate() {
+        for tag_id in 0..file.num_tags() {
+            let tag = file.tag(tag_id);
+            let from = index_of(sprite, tag.from_frame());
+            // Only tags whose frames are stored consecutively can be described
+            // by a range.
+            let complete = from.is_some_and(|from| {
+                (tag.from_frame()..=tag.to_frame())
+                    .enumerate()
+                    .all(|(offset, frame)| index_of(sprite, frame) == Some(from + offset))
+            });
+            let from = match from {
+                Some(from) if complete => from as u32,
+                _ => continue,
+            };
+            let direction = match tag.animation_direction() {
+                AnimationDirection::Forward => "forward",
+                AnimationDirection::Reverse => "reverse",
+                AnimationDirection::PingPong => "pingpong",
+            };
+            let mut value = Value::object()
+                .with("name", tag.name())
+                .with("from", from)
+                .with("to", from + tag.to_frame() - tag.from_frame())
+                .with("direction", direction);
+            if tag.repeat() > 0 {
+                value = value.with("repeat", tag.repeat().to_string());
+            }
+            tags.push(with_user_data(value, tag.user_data()));
+        }
+    }
+    tags
+}
+
+fn layers(sprites: &[(&str, &AsepriteFile)]) -> Vec<Value> {
+    // Sprites in an atlas usually share their layer structure, so identical
+    // layers are only listed once.
+    let mut layers = Vec::new();
+    for &(_, file) in sprites {
+        for layer in file.layers().filter(|layer| layer.is_visible()) {
+            let mut value = Value::object().with("name", layer.name());
+            if let Some(parent) = layer.parent() {
+                value = value.with("group", parent.name());
+            }
+            if layer.layer_type() != LayerType::Group {
+                value = value
+                    .with("opacity", layer.opacity() as u32)
+                    .with("blendMode", blend_mode_name(layer.blend_mode()));
+            }
+            let value = with_user_data(value, layer.user_data());
+            if !layers.contains(&value) {
+                layers.push(value);
+            }
+        }
+    }
+    layers
+}
+
+fn slices(sprites: &[(&str, &AsepriteFile)]) -> Vec<Value> {
+    let mut slices = Vec::new();
+    for &(_, file) in sprites {
+        for slice in file.slices() {
+            let keys: Vec<Value> = slice
+                .keys
+                .iter()
+                .map(|key| {
+                    let mut value = Value::object().with("frame", key.from_frame).with(
+                        "bounds",
+                        Value::rect(
+                            key.origin.x.into(),
+                            key.origin.y.into(),
+                            key.size.width,
+                            key.size.height,
+                        ),
+                    );
+                    if let Some(center) = key.slice9 {
+                        value = value.with(
+                            "center",
+                            Value::rect(
+                                center.center_x.into(),
+                                center.center_y.into(),
+                                center.center_width,
+                                center.center_height,
+                            ),
+                        );
+                    }
+                    if let Some(pivot) = key.pivot {
+                        value = value.with(
+                            "pivot",
+                            Value::object()
+                                .with("x", i64::from(pivot.x))
+                                .with("y", i64::from(pivot.y)),
+                        );
+                    }
+                    value
+                })
+                .collect();
+            let value = with_user_data(
+                Value::object().with("name", slice.name.as_str()),
+                slice.user_data.as_ref(),
+            );
+            slices.push(value.with("keys", keys));
+        }
+    }
+    slices
+}
+
+// A slice of a frame in image coordinates.
+fn slice(slice: &AtlasSlice) -> Value {
+    let (x, y, w, h) = slice.bounds();
+    let mut value = Value::object()
+        .with("name", slice.name())
+        .with("bounds", Value::rect(x.into(), y.into(), w, h));
+    if let Some((x, y, w, h)) = slice.center() {
+        value = value.with("center", Value::rect(x.into(), y.into(), w, h));
+    }
+    if let Some((x, y)) = slice.pivot() {
+        value = value.with(
+            "pivot",
+            Value::object()
+                .with("x", i64::from(x))
+                .with("y", i64::from(y)),
+        );
+    }
+    value
+}
+
+fn with_user_data(mut value: Value, user_data: Option<&UserData>) -> Value {
+    if let Some(user_data) = user_data {
+        if let Some(color) = user_data.color {
+            let [r, g, b, a] = color.0;
+            value = value.with("color", format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a));
+        }
+        if let Some(text) = &user_data.text {
+            value = value.with("data", text.as_str());
+        }
+    }
+    value
+}
+
+fn blend_mode_name(mode: BlendMode) -> &'static str {
+    match mode {
+        BlendMode::Normal => "normal",
+        BlendMode::Multiply => "multiply",
+        BlendMode::Screen => "screen",
+        BlendMode::Overlay => "overlay",
+        BlendMode::Darken => "darken",
+        BlendMode::Lighten => "lighten",
+        BlendMode::ColorDodge => "color_dodge",
+        BlendMode::ColorBurn => "color_burn",
+        BlendMode::HardLight => "hard_light",
+        BlendMode::SoftLight => "soft_light",
+        BlendMode::Difference => "difference",
+        BlendMode::Exclusion => "exclusion",
+        BlendMode::Hue => "hue",
+        BlendMode::Saturation => "saturation",
+        BlendMode::Color => "color",
+        BlendMode::Luminosity => "luminosity",
+        BlendMode::Addition => "addition",
+        BlendMode::Subtract => "subtract",
+        BlendMode::Divide => "divide",
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
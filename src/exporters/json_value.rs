@@ -0,0 +1,164 @@
+// A minimal JSON document model for the exporters that write JSON. Objects
+// keep their keys in insertion order so the output is stable and matches the
+// layout other tools produce.
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub(crate) fn object() -> Self {
+        Value::Object(Vec::new())
+    }
+
+    // Append a member to an object. Does nothing for other values.
+    pub(crate) fn with(mut self, key: &str, value: impl Into<Value>) -> Self {
+        if let Value::Object(members) = &mut self {
+            members.push((key.to_owned(), value.into()));
+        }
+        self
+    }
+
+    pub(crate) fn rect(x: i64, y: i64, w: u32, h: u32) -> Self {
+        Value::object()
+            .with("x", x)
+            .with("y", y)
+            .with("w", w)
+            .with("h", h)
+    }
+
+    pub(crate) fn size(w: u32, h: u32) -> Self {
+        Value::object().with("w", w).with("h", h)
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Value::Array(_) | Value::Object(_))
+    }
+
+    // Objects and arrays that only contain scalars are written on a single
+    // line, everything else is indented by two spaces per level.
+    pub(crate) fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_indented(w, 0)?;
+        writeln!(w)
+    }
+
+    fn write_indented<W: Write>(&self, w: &mut W, level: usize) -> io::Result<()> {
+        match self {
+            Value::Bool(value) => write!(w, "{}", value),
+            Value::Number(value) => write!(w, "{}", value),
+            Value::String(value) => write_string(w, value),
+            Value::Array(items) => {
+                if items.is_empty() {
+                    return write!(w, "[]");
+                }
+                if items.iter().all(Value::is_scalar) {
+                    write!(w, "[ ")?;
+                    for (index, item) in items.iter().enumerate() {
+                        if index > 0 {
+                            write!(w, ", ")?;
+                        }
+                        item.write_indented(w, level)?;
+                    }
+                    return write!(w, " ]");
+                }
+                writeln!(w, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    indent(w, level + 1)?;
+                    item.write_indented(w, level + 1)?;
+                    writeln!(w, "{}", if index + 1 < items.len() { "," } else { "" })?;
+                }
+                indent(w, level)?;
+                write!(w, "]")
+            }
+            Value::Object(members) => {
+                if members.is_empty() {
+                    return write!(w, "{{}}");
+                }
+                if members.iter().all(|(_, value)| value.is_scalar()) {
+                    write!(w, "{{ ")?;
+                    for (index, (key, value)) in members.iter().enumerate() {
+                        if index > 0 {
+                            write!(w, ", ")?;
+                        }
+                        write_string(w, key)?;
+                        write!(w, ": ")?;
+                        value.write_indented(w, level)?;
+                    }
+                    return write!(w, " }}");
+                }
+                writeln!(w, "{{")?;
+                for (index, (key, value)) in members.iter().enumerate() {
+                    indent(w, level + 1)?;
+                    write_string(w, key)?;
+                    write!(w, ": ")?;
+                    value.write_indented(w, level + 1)?;
+                    writeln!(w, "{}", if index + 1 < members.len() { "," } else { "" })?;
+                }
+                indent(w, level)?;
+                write!(w, "}}")
+            }
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Self {
+        Value::Number(value.into())
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(items: Vec<Value>) -> Self {
+        Value::Array(items)
+    }
+}
+
+fn indent<W: Write>(w: &mut W, level: usize) -> io::Result<()> {
+    write!(w, "{:1$}", "", level * 2)
+}
+
+fn write_string<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    write!(w, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(w, "\\\"")?,
+            '\\' => write!(w, "\\\\")?,
+            '\n' => write!(w, "\\n")?,
+            '\r' => write!(w, "\\r")?,
+            '\t' => write!(w, "\\t")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => write!(w, "{}", c)?,
+        }
+    }
+    write!(w, "\"")
+}
@@ -0,0 +1,202 @@
+/*!
+Describe atlases in the text `.atlas` format of libGDX.
+
+Requires the `libgdx` feature. [write()] describes an [Atlas] as a single
+page that `TextureAtlas` can load:
+
+```
+# use asefile::AsepriteFile;
+# use asefile::atlas::{self, AtlasOptions};
+# use asefile::exporters::libgdx::{self, LibgdxOptions};
+# use std::path::Path;
+# let player = AsepriteFile::read_file(Path::new("./tests/data/layers_and_tags.aseprite")).unwrap();
+let sprites = [("player", &player)];
+let atlas = atlas::pack(&sprites, &AtlasOptions::new().trim(true).padding(1)).unwrap();
+// atlas.image().save("player.png").unwrap();
+let mut data = Vec::new();
+libgdx::write(&atlas, &sprites, "player.png", &mut data, &LibgdxOptions::new()).unwrap();
+// std::fs::write("player.atlas", data).unwrap();
+```
+
+Every frame of a tag becomes a region named `{tag}_{index}`, where `index`
+counts the frames of the tag from 0, e.g. `walk_0`, `walk_1`, and so on.
+Frames that are part of several tags are listed once for each tag, frames
+without a tag are named `{sprite}_{frame}` after the sprite name and frame
+number. Trimmed frames keep their original size and offset, so libGDX draws
+them at the right position.
+*/
+use crate::atlas::{Atlas, AtlasFrame};
+use crate::AsepriteFile;
+use std::io::{self, Write};
+
+/// How libGDX samples the atlas texture.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextureFilter {
+    /// Use the nearest pixel, which keeps pixel art sharp.
+    #[default]
+    Nearest,
+    /// Interpolate between neighboring pixels.
+    Linear,
+}
+
+/// Options for [write()].
+///
+/// ```
+/// # use asefile::exporters::libgdx::{LibgdxOptions, TextureFilter};
+/// let options = LibgdxOptions::new()
+///     .filter(TextureFilter::Linear)
+///     .prefix_sprite(true);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LibgdxOptions {
+    filter: TextureFilter,
+    prefix_sprite: bool,
+}
+
+impl LibgdxOptions {
+    /// Default options: [TextureFilter::Nearest] and region names without the
+    /// sprite name.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The filter for minification and magnification.
+    pub fn filter(mut self, filter: TextureFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Prefix the names of tagged regions with the sprite name and a `/`,
+    /// e.g. `player/walk_0`. Useful if several sprites in the atlas use the
+    /// same tag names.
+    pub fn prefix_sprite(mut self, prefix: bool) -> Self {
+        self.prefix_sprite = prefix;
+        self
+    }
+}
+
+/// Write the description of an atlas. `sprites` must contain every sprite
+/// packed into the atlas, under the same name. `image` is the path of the
+/// atlas image relative to the `.atlas` file.
+///
+/// # Errors
+///
+/// Fails with [io::ErrorKind::InvalidInput] if a sprite of the atlas is
+/// missing from `sprites`. Also returns any error of the underlying writer.
+pub fn write<W: Write>(
+    atlas: &Atlas,
+    sprites: &[(&str, &AsepriteFile)],
+    image: &str,
+    mut w: W,
+    options: &LibgdxOptions,
+) -> io::Result<()> {
+    if let Some(frame) = atlas
+        .frames()
+        .iter()
+        .find(|frame| sprites.iter().all(|&(name, _)| name != frame.sprite()))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Sprite is missing: {}", frame.sprite()),
+        ));
+    }
+
+    let filter = match options.filter {
+        TextureFilter::Nearest => "Nearest",
+        TextureFilter::Linear => "Linear",
+    };
+    let (width, height) = atlas.image.dimensions();
+    writeln!(w)?;
+    writeln!(w, "{}", image)?;
+    writeln!(w, "size: {}, {}", width, height)?;
+    writeln!(w, "format: RGBA8888")?;
+    writeln!(w, "filter: {},{}", filter, filter)?;
+    writeln!(w, "repeat: none")?;
+
+    for &(sprite, file) in sprites {
+        let frames: Vec<_> = atlas
+            .frames()
+            .iter()
+            .filter(|frame| frame.sprite() == sprite)
+            .collect();
+        let frame = |frame_id: u32| frames.iter().find(|frame| frame.frame() == frame_id);
+        let prefix = if options.prefix_sprite {
+            format!("{}/", sprite)
+        } else {
+            String::new()
+        };
+        for tag_id in 0..file.num_tags() {
+            let tag = file.tag(tag_id);
+            for (index, frame_id) in (tag.from_frame()..=tag.to_frame()).enumerate() {
+                if let Some(frame) = frame(frame_id) {
+                    let name = format!("{}{}_{}", prefix, tag.name(), index);
+                    write_region(&mut w, &name, frame)?;
+                }
+            }
+        }
+        for frame in &frames {
+            let tagged = (0..file.num_tags()).any(|tag_id| {
+                let tag = file.tag(tag_id);
+                tag.from_frame() <= frame.frame() && frame.frame() <= tag.to_frame()
+            });
+            if !tagged {
+                let name = format!("{}_{}", sprite, frame.frame());
+                write_region(&mut w, &name, frame)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_region<W: Write>(w: &mut W, name: &str, frame: &AtlasFrame) -> io::Result<()> {
+    let (x, y, width, height) = frame.rect();
+    let (offset_x, offset_y) = frame.trim_offset();
+    let (source_width, source_height) = frame.source_size();
+    writeln!(w, "{}", name)?;
+    writeln!(w, "  rotate: false")?;
+    writeln!(w, "  xy: {}, {}", x, y)?;
+    writeln!(w, "  size: {}, {}", width, height)?;
+    writeln!(w, "  orig: {}, {}", source_width, source_height)?;
+    // libGDX measures the offset from the bottom left corner.
+    writeln!(
+        w,
+        "  offset: {}, {}",
+        offset_x,
+        source_height - offset_y - height
+    )?;
+    writeln!(w, "  index: -1")
+}
+
+// Reads back the format written by this module, used to test the writer.
+#[cfg(all(test, feature = "image"))]
+pub(crate) mod decode {
+    use std::collections::HashMap;
+
+    // The page header and each region's values, keyed by field name.
+    pub(crate) type AtlasFile = (Vec<String>, Vec<(String, HashMap<String, Vec<i64>>)>);
+
+    pub(crate) fn atlas(text: &str) -> AtlasFile {
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some(""));
+        let header: Vec<String> = lines.by_ref().take(5).map(str::to_owned).collect();
+        let mut regions: Vec<(String, HashMap<String, Vec<i64>>)> = Vec::new();
+        for line in lines {
+            match line.strip_prefix("  ") {
+                Some(field) => {
+                    let (key, value) = field.split_once(": ").unwrap();
+                    let numbers = value
+                        .split(", ")
+                        .filter_map(|number| number.parse().ok())
+                        .collect();
+                    regions
+                        .last_mut()
+                        .unwrap()
+                        .1
+                        .insert(key.to_owned(), numbers);
+                }
+                None => regions.push((line.to_owned(), HashMap::new())),
+            }
+        }
+        (header, regions)
+    }
+}
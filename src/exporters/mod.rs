@@ -0,0 +1,86 @@
+/*!
+Export sprites to formats used by other tools and game engines.
+
+Each exporter lives in its own module and requires the cargo feature of the
+same name:
+
+- `apng`: Animated PNG images with full alpha.
+- `cur`: Windows cursors, with the hotspot taken from a slice. Also enables
+  `ico`.
+- `gif`: Animated GIF images, with the palette reduced to 256 colors if
+  necessary.
+- `godot`: [SpriteFrames](https://docs.godotengine.org/en/stable/classes/class_spriteframes.html)
+  resources for Godot 4.
+- `ico`: Windows icons containing one or more sizes.
+- `json`: Sprite sheet and atlas metadata in the JSON format of the Aseprite
+  CLI.
+- `libgdx`: Atlas descriptions in the text `.atlas` format of libGDX.
+- `texturepacker`: Atlas metadata in the JSON and plist formats of
+  TexturePacker. Also enables `json`.
+- `webp`: Animated, lossless WebP images.
+*/
+
+#[cfg(feature = "apng")]
+pub mod apng;
+#[cfg(feature = "cur")]
+pub mod cur;
+#[cfg(feature = "gif")]
+pub mod gif;
+#[cfg(feature = "godot")]
+pub mod godot;
+#[cfg(feature = "ico")]
+pub mod ico;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "json")]
+mod json_value;
+#[cfg(feature = "libgdx")]
+pub mod libgdx;
+#[cfg(feature = "texturepacker")]
+pub mod texturepacker;
+#[cfg(feature = "webp")]
+pub mod webp;
+
+#[cfg(any(
+    feature = "apng",
+    feature = "bevy",
+    feature = "gif",
+    feature = "godot",
+    feature = "macroquad",
+    feature = "webp"
+))]
+use crate::{AnimationDirection, Tag};
+
+// All frames shown while playing the tag. Looping tags only contain one
+// iteration. Subsequent ping-pong passes don't repeat the frame at which they
+// turn around.
+#[cfg(any(
+    feature = "apng",
+    feature = "bevy",
+    feature = "gif",
+    feature = "godot",
+    feature = "macroquad",
+    feature = "webp"
+))]
+pub(crate) fn playback_frames(tag: &Tag) -> Vec<u32> {
+    let forward: Vec<u32> = (tag.from_frame()..=tag.to_frame()).collect();
+    let backward: Vec<u32> = forward.iter().rev().copied().collect();
+    let repeat = tag.repeat();
+    match tag.animation_direction() {
+        AnimationDirection::Forward => forward.repeat(repeat.max(1) as usize),
+        AnimationDirection::Reverse => backward.repeat(repeat.max(1) as usize),
+        AnimationDirection::PingPong => {
+            let passes = if repeat == 0 { 2 } else { repeat };
+            let mut frames = forward.clone();
+            for pass in 1..passes {
+                let next = if pass % 2 == 1 { &backward } else { &forward };
+                frames.extend_from_slice(&next[1..]);
+            }
+            if repeat == 0 && frames.len() > 1 {
+                // The first frame follows again when the animation loops.
+                frames.pop();
+            }
+            frames
+        }
+    }
+}
@@ -0,0 +1,341 @@
+/*!
+Describe atlases in the data formats of TexturePacker.
+
+Requires the `texturepacker` feature, which also enables `json`. [write()]
+describes an [Atlas] in one of the formats most engines can import:
+
+- [JsonHash](TexturePackerFormat::JsonHash) and
+  [JsonArray](TexturePackerFormat::JsonArray): TexturePacker's generic JSON
+  formats, read by Phaser, PixiJS, and many others.
+- [Plist](TexturePackerFormat::Plist): The property list format of Cocos2d
+  (format version 3), also read by SpriteKit and Cocos Creator. Frames
+  merged by [merge_duplicates](crate::atlas::AtlasOptions::merge_duplicates)
+  are listed as `aliases` of the first identical frame.
+
+```
+# use asefile::AsepriteFile;
+# use asefile::atlas::{self, AtlasOptions};
+# use asefile::exporters::texturepacker::{self, TexturePackerFormat, TexturePackerOptions};
+# use std::path::Path;
+# let player = AsepriteFile::read_file(Path::new("./tests/data/layers_and_tags.aseprite")).unwrap();
+let sprites = [("player", &player)];
+let atlas = atlas::pack(&sprites, &AtlasOptions::new().trim(true)).unwrap();
+// atlas.image().save("atlas.png").unwrap();
+let options = TexturePackerOptions::new()
+    .format(TexturePackerFormat::Plist)
+    .image("atlas.png");
+let mut data = Vec::new();
+texturepacker::write(&atlas, &sprites, &mut data, &options).unwrap();
+// std::fs::write("atlas.plist", data).unwrap();
+```
+
+These formats only describe where each frame is stored. Use the
+[json exporter](super::json) to include tags, layers, and slices.
+*/
+use super::json::{self, check_unique, frame_names, JsonFormat, Region};
+use super::json_value::Value;
+use crate::atlas::Atlas;
+use crate::AsepriteFile;
+use std::io::{self, Write};
+
+/// The data format to write.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TexturePackerFormat {
+    /// JSON with an object that has the name of each frame as key.
+    #[default]
+    JsonHash,
+    /// JSON with a list of frames, each with a `filename` member.
+    JsonArray,
+    /// A Cocos2d property list.
+    Plist,
+}
+
+/// Options for [write()].
+///
+/// ```
+/// # use asefile::exporters::texturepacker::{TexturePackerFormat, TexturePackerOptions};
+/// let options = TexturePackerOptions::new()
+///     .format(TexturePackerFormat::JsonArray)
+///     .filename_format("{title}/{tag}_{tagframe}.png");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TexturePackerOptions {
+    format: TexturePackerFormat,
+    image: Option<String>,
+    filename_format: Option<String>,
+}
+
+impl TexturePackerOptions {
+    /// Default options: [TexturePackerFormat::JsonHash] with the default
+    /// frame names and no image path.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The data format to write.
+    pub fn format(mut self, format: TexturePackerFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Path of the atlas image, relative to the data file.
+    pub fn image(mut self, path: &str) -> Self {
+        self.image = Some(path.to_owned());
+        self
+    }
+
+    /// Pattern for the name of each frame, with the placeholders of
+    /// [AsepriteFile::export_frames]. `{title}` is the name of the sprite.
+    /// Defaults to `"{title}_{frame}.png"`, or `"{title}.png"` for sprites
+    /// with a single frame.
+    pub fn filename_format(mut self, pattern: &str) -> Self {
+        self.filename_format = Some(pattern.to_owned());
+        self
+    }
+}
+
+/// Write the description of an atlas. `sprites` must contain every sprite
+/// packed into the atlas, under the same name.
+///
+/// # Errors
+///
+/// Fails with [io::ErrorKind::InvalidInput] if a sprite of the atlas is
+/// missing from `sprites`, if the file name format is invalid or, except for
+/// [JsonArray](TexturePackerFormat::JsonArray), produces the same name for
+/// two frames. Also returns any error of the underlying writer.
+pub fn write<W: Write>(
+    atlas: &Atlas,
+    sprites: &[(&str, &AsepriteFile)],
+    mut w: W,
+    options: &TexturePackerOptions,
+) -> io::Result<()> {
+    let regions = json::atlas_regions(atlas, sprites)?;
+    let names = frame_names(
+        sprites,
+        &regions,
+        options.filename_format.as_deref(),
+        ("{title}.png", "{title}_{frame}.png"),
+    )?;
+    let size = atlas.image.dimensions();
+    let format = match options.format {
+        TexturePackerFormat::JsonHash => JsonFormat::Hash,
+        TexturePackerFormat::JsonArray => JsonFormat::Array,
+        TexturePackerFormat::Plist => {
+            return write_plist(sprites, &regions, &names, size, options, w)
+        }
+    };
+
+    let frames = json::frames(sprites, &regions, names, format, |entry, _| entry)?;
+    let mut meta = Value::object()
+        .with("app", env!("CARGO_PKG_HOMEPAGE"))
+        .with("version", env!("CARGO_PKG_VERSION"));
+    if let Some(image) = &options.image {
+        meta = meta.with("image", image.as_str());
+    }
+    let meta = meta
+        .with("format", "RGBA8888")
+        .with("size", Value::size(size.0, size.1))
+        .with("scale", "1");
+    Value::object()
+        .with("frames", frames)
+        .with("meta", meta)
+        .write(&mut w)
+}
+
+fn write_plist<W: Write>(
+    sprites: &[(&str, &AsepriteFile)],
+    regions: &[Region],
+    names: &[String],
+    size: (u32, u32),
+    options: &TexturePackerOptions,
+    mut w: W,
+) -> io::Result<()> {
+    check_unique(names)?;
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">"#
+    )?;
+    writeln!(w, r#"<plist version="1.0">"#)?;
+    writeln!(w, "  <dict>")?;
+    writeln!(w, "    <key>frames</key>")?;
+    writeln!(w, "    <dict>")?;
+    for (index, (name, region)) in names.iter().zip(regions).enumerate() {
+        let (x, y, width, height) = region.rect;
+        let (offset_x, offset_y) = region.trim_offset;
+        let (source_width, source_height) = sprites[region.sprite].1.size();
+        let (source_width, source_height) = (source_width as i64, source_height as i64);
+        // Offset of the center of the trimmed frame from the center of the
+        // source frame, with the y axis pointing up.
+        let center_x = 2 * offset_x as i64 + width as i64 - source_width;
+        let center_y = source_height - (2 * offset_y as i64 + height as i64);
+        writeln!(w, "      <key>{}</key>", escape(name))?;
+        writeln!(w, "      <dict>")?;
+        let aliases: Vec<&String> = names
+            .iter()
+            .zip(regions)
+            .filter(|(_, other)| other.alias_of == Some(index))
+            .map(|(name, _)| name)
+            .collect();
+        writeln!(w, "        <key>aliases</key>")?;
+        if aliases.is_empty() {
+            writeln!(w, "        <array/>")?;
+        } else {
+            writeln!(w, "        <array>")?;
+            for alias in aliases {
+                writeln!(w, "          <string>{}</string>", escape(alias))?;
+            }
+            writeln!(w, "        </array>")?;
+        }
+        writeln!(w, "        <key>spriteOffset</key>")?;
+        writeln!(
+            w,
+            "        <string>{{{},{}}}</string>",
+            half(center_x),
+            half(center_y)
+        )?;
+        writeln!(w, "        <key>spriteSize</key>")?;
+        writeln!(w, "        <string>{{{},{}}}</string>", width, height)?;
+        writeln!(w, "        <key>spriteSourceSize</key>")?;
+        writeln!(
+            w,
+            "        <string>{{{},{}}}</string>",
+            source_width, source_height
+        )?;
+        writeln!(w, "        <key>textureRect</key>")?;
+        writeln!(
+            w,
+            "        <string>{{{{{},{}}},{{{},{}}}}}</string>",
+            x, y, width, height
+        )?;
+        writeln!(w, "        <key>textureRotated</key>")?;
+        writeln!(w, "        <false/>")?;
+        writeln!(w, "      </dict>")?;
+    }
+    writeln!(w, "    </dict>")?;
+    writeln!(w, "    <key>metadata</key>")?;
+    writeln!(w, "    <dict>")?;
+    writeln!(w, "      <key>format</key>")?;
+    writeln!(w, "      <integer>3</integer>")?;
+    writeln!(w, "      <key>pixelFormat</key>")?;
+    writeln!(w, "      <string>RGBA8888</string>")?;
+    writeln!(w, "      <key>premultiplyAlpha</key>")?;
+    writeln!(w, "      <false/>")?;
+    if let Some(image) = &options.image {
+        writeln!(w, "      <key>realTextureFileName</key>")?;
+        writeln!(w, "      <string>{}</string>", escape(image))?;
+    }
+    writeln!(w, "      <key>size</key>")?;
+    writeln!(w, "      <string>{{{},{}}}</string>", size.0, size.1)?;
+    if let Some(image) = &options.image {
+        writeln!(w, "      <key>textureFileName</key>")?;
+        writeln!(w, "      <string>{}</string>", escape(image))?;
+    }
+    writeln!(w, "    </dict>")?;
+    writeln!(w, "  </dict>")?;
+    writeln!(w, "</plist>")
+}
+
+// Formats half of the given value, e.g. `1.5` for 3.
+fn half(twice: i64) -> String {
+    if twice % 2 == 0 {
+        (twice / 2).to_string()
+    } else {
+        format!("{:.1}", twice as f64 / 2.0)
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Reads back the subset of the plist format written by this module, used to
+// test the writer.
+#[cfg(all(test, feature = "image"))]
+pub(crate) mod decode {
+    pub(crate) struct Frame {
+        pub(crate) name: String,
+        pub(crate) rect: [i64; 4],
+        pub(crate) offset: (f64, f64),
+        pub(crate) source_size: (i64, i64),
+        pub(crate) aliases: Vec<String>,
+    }
+
+    // The frames and the metadata as key-value pairs.
+    pub(crate) fn plist(text: &str) -> (Vec<Frame>, Vec<(String, String)>) {
+        let lines: Vec<&str> = text.lines().map(str::trim).collect();
+        let value = |line: &str| -> String {
+            let (start, end) = match line.rfind("</") {
+                Some(end) => (line.find('>').unwrap() + 1, end),
+                None => (0, line.len()),
+            };
+            line[start..end]
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&amp;", "&")
+        };
+        let numbers = |line: &str| -> Vec<f64> {
+            value(line)
+                .split(['{', '}', ','])
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse().unwrap())
+                .collect()
+        };
+        let frames_start = lines
+            .iter()
+            .position(|l| *l == "<key>frames</key>")
+            .unwrap()
+            + 2;
+        let meta_start = lines
+            .iter()
+            .position(|l| *l == "<key>metadata</key>")
+            .unwrap();
+        let mut frames = Vec::new();
+        let mut index = frames_start;
+        while index < meta_start - 1 {
+            let name = value(lines[index]);
+            let end = index + lines[index..].iter().position(|l| *l == "</dict>").unwrap();
+            let members = &lines[index + 2..end];
+            let get = |key: &str| {
+                let at = members
+                    .iter()
+                    .position(|l| *l == format!("<key>{}</key>", key))
+                    .unwrap();
+                &members[at + 1..]
+            };
+            let aliases = match get("aliases")[0] {
+                "<array/>" => Vec::new(),
+                _ => get("aliases")[1..]
+                    .iter()
+                    .take_while(|l| **l != "</array>")
+                    .map(|l| value(l))
+                    .collect(),
+            };
+            let rect = numbers(get("textureRect")[0]);
+            let offset = numbers(get("spriteOffset")[0]);
+            let source = numbers(get("spriteSourceSize")[0]);
+            frames.push(Frame {
+                name,
+                rect: [
+                    rect[0] as i64,
+                    rect[1] as i64,
+                    rect[2] as i64,
+                    rect[3] as i64,
+                ],
+                offset: (offset[0], offset[1]),
+                source_size: (source[0] as i64, source[1] as i64),
+                aliases,
+            });
+            index = end + 1;
+        }
+        let mut meta = Vec::new();
+        let mut index = meta_start + 2;
+        while lines[index].starts_with("<key>") {
+            meta.push((value(lines[index]), value(lines[index + 1])));
+            index += 2;
+        }
+        (frames, meta)
+    }
+}
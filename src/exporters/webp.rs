@@ -0,0 +1,506 @@
+/*!
+Export animations as animated WebP images.
+
+Requires the `webp` feature. [write()] encodes every frame of the sprite, or
+the frames of a single tag, together with their durations. Frames are stored
+losslessly, including their alpha channel. The encoder is implemented in this
+crate and does not support lossy compression.
+
+```
+# use asefile::AsepriteFile;
+# use asefile::exporters::webp::{self, WebpOptions};
+# use std::path::Path;
+# let path = Path::new("./tests/data/layers_and_tags.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let mut data = Vec::new();
+webp::write(&ase, &mut data, &WebpOptions::new().tag(0)).unwrap();
+// std::fs::write("walk.webp", data).unwrap();
+```
+
+When exporting a tag, its frames are written in playback order, so
+[Reverse](crate::AnimationDirection::Reverse) and
+[PingPong](crate::AnimationDirection::PingPong) tags play correctly in any
+viewer. A tag without a [repeat](crate::Tag::repeat) count loops forever.
+Otherwise all repetitions are written out and the animation plays once.
+Exporting the whole sprite always loops.
+*/
+use super::playback_frames;
+use crate::image::RgbaImage;
+use crate::AsepriteFile;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+/// Options for [write()].
+///
+/// ```
+/// # use asefile::exporters::webp::WebpOptions;
+/// let options = WebpOptions::new().tag(2);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WebpOptions {
+    tag: Option<u32>,
+}
+
+impl WebpOptions {
+    /// Default options: Export all frames.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only export the frames of the tag with the given id.
+    pub fn tag(mut self, tag_id: u32) -> Self {
+        self.tag = Some(tag_id);
+        self
+    }
+}
+
+// Limits of the lossless bitstream and the container.
+const MAX_IMAGE_SIZE: usize = 1 << 14;
+const MAX_DURATION: u32 = (1 << 24) - 1;
+
+// VP8X flags.
+const ALPHA_FLAG: u8 = 0x10;
+const ANIMATION_FLAG: u8 = 0x02;
+// ANMF flag: Overwrite the canvas instead of alpha-blending onto it.
+const NO_BLEND_FLAG: u8 = 0x02;
+
+/// Write an animated WebP image.
+///
+/// # Errors
+///
+/// Fails with [io::ErrorKind::InvalidInput] if the tag does not exist or if
+/// the canvas is wider or higher than 16384 pixels (the maximum size of a
+/// lossless WebP image). Also returns any error of the underlying writer.
+pub fn write<W: Write>(file: &AsepriteFile, mut w: W, options: &WebpOptions) -> io::Result<()> {
+    let (width, height) = file.size();
+    if width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE {
+        return Err(invalid_input(format!(
+            "Canvas too large for WebP: {}x{}",
+            width, height
+        )));
+    }
+    let (frames, loop_count) = match options.tag {
+        Some(tag_id) if tag_id >= file.num_tags() => {
+            return Err(invalid_input(format!("Tag does not exist: {}", tag_id)))
+        }
+        Some(tag_id) => {
+            let tag = file.tag(tag_id);
+            let loop_count: u16 = if tag.repeat() == 0 { 0 } else { 1 };
+            (playback_frames(tag), loop_count)
+        }
+        None => ((0..file.num_frames()).collect(), 0),
+    };
+
+    let mut body = Vec::new();
+    let mut header = vec![ALPHA_FLAG | ANIMATION_FLAG, 0, 0, 0];
+    push_u24(&mut header, width as u32 - 1);
+    push_u24(&mut header, height as u32 - 1);
+    push_chunk(&mut body, b"VP8X", &header);
+    // Transparent background color, followed by the loop count.
+    let mut animation = vec![0; 4];
+    animation.extend_from_slice(&loop_count.to_le_bytes());
+    push_chunk(&mut body, b"ANIM", &animation);
+
+    // Ping-pong animations show most frames twice; encode each one once.
+    let mut encoded: Vec<Option<Vec<u8>>> = vec![None; file.num_frames() as usize];
+    for frame in frames {
+        let bitstream = encoded[frame as usize]
+            .get_or_insert_with(|| encode_image(&file.frame(frame).composite()));
+        let mut data = Vec::with_capacity(bitstream.len() + 25);
+        // Frame position, always at the top left corner.
+        push_u24(&mut data, 0);
+        push_u24(&mut data, 0);
+        push_u24(&mut data, width as u32 - 1);
+        push_u24(&mut data, height as u32 - 1);
+        push_u24(&mut data, file.frame(frame).duration().min(MAX_DURATION));
+        data.push(NO_BLEND_FLAG);
+        push_chunk(&mut data, b"VP8L", bitstream);
+        push_chunk(&mut body, b"ANMF", &data);
+    }
+
+    let riff_size = u32::try_from(body.len() + 4)
+        .map_err(|_| invalid_input("Animation too large for WebP".to_owned()))?;
+    w.write_all(b"RIFF")?;
+    w.write_all(&riff_size.to_le_bytes())?;
+    w.write_all(b"WEBP")?;
+    w.write_all(&body)
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn push_u24(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_le_bytes()[..3]);
+}
+
+fn push_chunk(data: &mut Vec<u8>, fourcc: &[u8; 4], payload: &[u8]) {
+    data.extend_from_slice(fourcc);
+    data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    data.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        data.push(0);
+    }
+}
+
+// --- Lossless (VP8L) bitstream ---
+//
+// Only a small part of the format is used: no transforms, no color cache, and
+// a single set of prefix codes for the whole image. Pixels are either stored
+// as literals or copied from the pixel to the left or the pixel above, which
+// works well for pixel art with its large areas of flat color.
+
+const SIGNATURE: u32 = 0x2f;
+const NUM_LITERALS: usize = 256;
+const NUM_LENGTH_CODES: usize = 24;
+const NUM_DISTANCE_CODES: usize = 40;
+const MIN_COPY_LENGTH: usize = 3;
+const MAX_COPY_LENGTH: usize = 4096;
+const MAX_CODE_LENGTH: usize = 15;
+const MAX_CODE_LENGTH_CODE_LENGTH: usize = 7;
+const NUM_CODE_LENGTH_CODES: usize = 19;
+const CODE_LENGTH_ORDER: [usize; NUM_CODE_LENGTH_CODES] = [
+    17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+// Distance codes for the neighbors (0, 1) and (1, 0) in the format's table
+// of nearby pixels.
+const DISTANCE_CODE_ABOVE: u32 = 1;
+const DISTANCE_CODE_LEFT: u32 = 2;
+
+enum Token {
+    Literal([u8; 4]),
+    Copy { length: u32, distance_code: u32 },
+}
+
+fn encode_image(image: &RgbaImage) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let pixels: Vec<[u8; 4]> = image.pixels().map(|p| p.0).collect();
+    let tokens = find_copies(&pixels, width as usize);
+
+    let mut green = vec![0_u32; NUM_LITERALS + NUM_LENGTH_CODES];
+    let mut red = vec![0_u32; NUM_LITERALS];
+    let mut blue = vec![0_u32; NUM_LITERALS];
+    let mut alpha = vec![0_u32; NUM_LITERALS];
+    let mut distance = vec![0_u32; NUM_DISTANCE_CODES];
+    for token in &tokens {
+        match token {
+            Token::Literal([r, g, b, a]) => {
+                green[*g as usize] += 1;
+                red[*r as usize] += 1;
+                blue[*b as usize] += 1;
+                alpha[*a as usize] += 1;
+            }
+            Token::Copy {
+                length,
+                distance_code,
+            } => {
+                green[NUM_LITERALS + prefix_encode(*length).0 as usize] += 1;
+                distance[prefix_encode(*distance_code).0 as usize] += 1;
+            }
+        }
+    }
+
+    let mut w = BitWriter::default();
+    w.write(8, SIGNATURE);
+    w.write(14, width - 1);
+    w.write(14, height - 1);
+    let uses_alpha = pixels.iter().any(|p| p[3] != 255);
+    w.write(1, uses_alpha as u32);
+    w.write(3, 0); // version
+    w.write(1, 0); // no transforms
+    w.write(1, 0); // no color cache
+    w.write(1, 0); // a single prefix code group
+
+    let green = PrefixCode::write(&mut w, &green);
+    let red = PrefixCode::write(&mut w, &red);
+    let blue = PrefixCode::write(&mut w, &blue);
+    let alpha = PrefixCode::write(&mut w, &alpha);
+    let distance = PrefixCode::write(&mut w, &distance);
+    for token in &tokens {
+        match token {
+            Token::Literal([r, g, b, a]) => {
+                green.write_symbol(&mut w, *g as usize);
+                red.write_symbol(&mut w, *r as usize);
+                blue.write_symbol(&mut w, *b as usize);
+                alpha.write_symbol(&mut w, *a as usize);
+            }
+            Token::Copy {
+                length,
+                distance_code,
+            } => {
+                let (prefix, extra_bits, extra) = prefix_encode(*length);
+                green.write_symbol(&mut w, NUM_LITERALS + prefix as usize);
+                w.write(extra_bits, extra);
+                let (prefix, extra_bits, extra) = prefix_encode(*distance_code);
+                distance.write_symbol(&mut w, prefix as usize);
+                w.write(extra_bits, extra);
+            }
+        }
+    }
+    w.finish()
+}
+
+// Greedily replaces runs of pixels that equal the pixel to their left or the
+// pixel above by copies.
+fn find_copies(pixels: &[[u8; 4]], width: usize) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < pixels.len() {
+        let mut best = (0, 0);
+        for &(offset, distance_code) in &[(1, DISTANCE_CODE_LEFT), (width, DISTANCE_CODE_ABOVE)] {
+            if i < offset {
+                continue;
+            }
+            let length = pixels[i..]
+                .iter()
+                .zip(&pixels[i - offset..])
+                .take(MAX_COPY_LENGTH)
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best.0 {
+                best = (length, distance_code);
+            }
+        }
+        if best.0 >= MIN_COPY_LENGTH {
+            tokens.push(Token::Copy {
+                length: best.0 as u32,
+                distance_code: best.1,
+            });
+            i += best.0;
+        } else {
+            tokens.push(Token::Literal(pixels[i]));
+            i += 1;
+        }
+    }
+    tokens
+}
+
+// Splits a copy length or distance code (at least 1) into a prefix symbol,
+// the number of extra bits, and their value.
+fn prefix_encode(value: u32) -> (u32, u8, u32) {
+    let value = value - 1;
+    if value < 4 {
+        return (value, 0, 0);
+    }
+    let highest_bit = 31 - value.leading_zeros();
+    let second_highest_bit = (value >> (highest_bit - 1)) & 1;
+    let extra_bits = highest_bit - 1;
+    let extra = value & ((1 << extra_bits) - 1);
+    (
+        2 * highest_bit + second_highest_bit,
+        extra_bits as u8,
+        extra,
+    )
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    num_bits: u32,
+}
+
+impl BitWriter {
+    // Writes the lowest `num_bits` bits of `value`, least significant first.
+    fn write(&mut self, num_bits: u8, value: u32) {
+        self.buffer |= (value as u64) << self.num_bits;
+        self.num_bits += num_bits as u32;
+        while self.num_bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.num_bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.num_bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// A canonical prefix code. Codes are stored bit-reversed, so they can be
+// written with `BitWriter::write`.
+struct PrefixCode {
+    lengths: Vec<u8>,
+    codes: Vec<u16>,
+}
+
+impl PrefixCode {
+    // Chooses a code for symbols with the given frequencies and writes its
+    // description.
+    fn write(w: &mut BitWriter, counts: &[u32]) -> Self {
+        let used: Vec<usize> = (0..counts.len()).filter(|s| counts[*s] > 0).collect();
+        if used.len() <= 2 && used.iter().all(|s| *s < NUM_LITERALS) {
+            // "Simple" code of one or two 8-bit symbols.
+            let symbols = if used.is_empty() { vec![0] } else { used };
+            w.write(1, 1);
+            w.write(1, symbols.len() as u32 - 1);
+            if symbols[0] < 2 {
+                w.write(1, 0);
+                w.write(1, symbols[0] as u32);
+            } else {
+                w.write(1, 1);
+                w.write(8, symbols[0] as u32);
+            }
+            let mut lengths = vec![0; counts.len()];
+            if let Some(second) = symbols.get(1) {
+                w.write(8, *second as u32);
+                lengths[symbols[0]] = 1;
+                lengths[*second] = 1;
+            }
+            return Self::from_lengths(lengths);
+        }
+
+        w.write(1, 0);
+        let lengths = code_lengths(counts, MAX_CODE_LENGTH);
+        write_code_lengths(w, &lengths);
+        Self::from_lengths(lengths)
+    }
+
+    // A code with a single symbol uses zero bits for it.
+    fn from_lengths(mut lengths: Vec<u8>) -> Self {
+        if lengths.iter().filter(|l| **l > 0).count() == 1 {
+            lengths.iter_mut().for_each(|l| *l = 0);
+        }
+        let mut num_codes = [0_u16; MAX_CODE_LENGTH + 1];
+        for length in &lengths {
+            num_codes[*length as usize] += 1;
+        }
+        num_codes[0] = 0;
+        let mut next_code = [0_u16; MAX_CODE_LENGTH + 1];
+        let mut code = 0;
+        for length in 1..=MAX_CODE_LENGTH {
+            code = (code + num_codes[length - 1]) << 1;
+            next_code[length] = code;
+        }
+        let codes = lengths
+            .iter()
+            .map(|length| {
+                let length = *length as usize;
+                if length == 0 {
+                    return 0;
+                }
+                let code = next_code[length];
+                next_code[length] += 1;
+                code.reverse_bits() >> (16 - length)
+            })
+            .collect();
+        Self { lengths, codes }
+    }
+
+    fn write_symbol(&self, w: &mut BitWriter, symbol: usize) {
+        w.write(self.lengths[symbol], self.codes[symbol] as u32);
+    }
+}
+
+// Writes the code lengths of a "normal" prefix code, which are themselves
+// run-length and prefix coded.
+fn write_code_lengths(w: &mut BitWriter, lengths: &[u8]) {
+    // (symbol, number of extra bits, extra bits)
+    let mut tokens: Vec<(usize, u8, u32)> = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let length = lengths[i];
+        let run = lengths[i..].iter().take_while(|l| **l == length).count();
+        if length == 0 {
+            let mut remaining = run;
+            while remaining >= 11 {
+                let n = remaining.min(138);
+                tokens.push((18, 7, n as u32 - 11));
+                remaining -= n;
+            }
+            if remaining >= 3 {
+                tokens.push((17, 3, remaining as u32 - 3));
+                remaining = 0;
+            }
+            tokens.extend(std::iter::repeat((0, 0, 0)).take(remaining));
+        } else {
+            tokens.push((length as usize, 0, 0));
+            let mut remaining = run - 1;
+            while remaining >= 3 {
+                let n = remaining.min(6);
+                tokens.push((16, 2, n as u32 - 3));
+                remaining -= n;
+            }
+            tokens.extend(std::iter::repeat((length as usize, 0, 0)).take(remaining));
+        }
+        i += run;
+    }
+
+    let mut counts = [0_u32; NUM_CODE_LENGTH_CODES];
+    for (symbol, _, _) in &tokens {
+        counts[*symbol] += 1;
+    }
+    let code_length_lengths = code_lengths(&counts, MAX_CODE_LENGTH_CODE_LENGTH);
+    let num_written = CODE_LENGTH_ORDER
+        .iter()
+        .rposition(|s| code_length_lengths[*s] > 0)
+        .map_or(0, |i| i + 1)
+        .max(4);
+    w.write(4, num_written as u32 - 4);
+    for symbol in &CODE_LENGTH_ORDER[..num_written] {
+        w.write(3, code_length_lengths[*symbol] as u32);
+    }
+    w.write(1, 0); // the lengths of all symbols follow
+    let code = PrefixCode::from_lengths(code_length_lengths);
+    for (symbol, extra_bits, extra) in tokens {
+        code.write_symbol(w, symbol);
+        w.write(extra_bits, extra);
+    }
+}
+
+// Huffman code lengths for the given symbol frequencies, limited to
+// `max_length` bits. Symbols with a frequency of zero get length zero.
+fn code_lengths(counts: &[u32], max_length: usize) -> Vec<u8> {
+    let symbols: Vec<usize> = (0..counts.len()).filter(|s| counts[*s] > 0).collect();
+    let mut lengths = vec![0_u8; counts.len()];
+    if symbols.len() == 1 {
+        lengths[symbols[0]] = 1;
+        return lengths;
+    }
+    // If the tree gets too deep, rare symbols are made more frequent until
+    // it fits.
+    let mut min_count = 1_u64;
+    loop {
+        let mut weights: Vec<u64> = symbols
+            .iter()
+            .map(|s| (counts[*s] as u64).max(min_count))
+            .collect();
+        let mut parents = vec![usize::MAX; symbols.len()];
+        let mut heap: std::collections::BinaryHeap<_> = weights
+            .iter()
+            .enumerate()
+            .map(|(node, weight)| std::cmp::Reverse((*weight, node)))
+            .collect();
+        while heap.len() > 1 {
+            let std::cmp::Reverse((weight_a, a)) = heap.pop().unwrap();
+            let std::cmp::Reverse((weight_b, b)) = heap.pop().unwrap();
+            let node = weights.len();
+            weights.push(weight_a + weight_b);
+            parents.push(usize::MAX);
+            parents[a] = node;
+            parents[b] = node;
+            heap.push(std::cmp::Reverse((weight_a + weight_b, node)));
+        }
+        let depths: Vec<usize> = (0..symbols.len())
+            .map(|leaf| {
+                let mut depth = 0;
+                let mut node = leaf;
+                while parents[node] != usize::MAX {
+                    node = parents[node];
+                    depth += 1;
+                }
+                depth
+            })
+            .collect();
+        if depths.iter().all(|d| *d <= max_length) {
+            for (symbol, depth) in symbols.iter().zip(depths) {
+                lengths[*symbol] = depth as u8;
+            }
+            return lengths;
+        }
+        min_count *= 2;
+    }
+}
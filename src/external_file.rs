@@ -1,10 +1,13 @@
+use crate::collections::Map;
 use crate::reader::AseReader;
 use crate::Result;
+use alloc::{string::String, vec::Vec};
 use core::str;
-use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::{fmt, sync::Arc};
 
 /// Unique identifier of a reference to an [ExternalFile].
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct ExternalFileId(u32);
 
 impl ExternalFileId {
@@ -24,11 +27,35 @@ impl ExternalFileId {
 pub struct ExternalFile {
     id: ExternalFileId,
     name: String,
+    // Stored as in the file, so unknown types are written back unchanged.
+    pub(crate) file_type: u8,
+}
+
+/// What an [ExternalFile] refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExternalFileType {
+    /// A palette file.
+    Palette,
+    /// A file containing tilesets, see
+    /// [Tileset::external_file](crate::Tileset::external_file).
+    Tileset,
+    /// The name of an extension that defines user data properties. The
+    /// property maps of the extension use this entry's id.
+    PropertiesExtension,
+    /// The name of an extension that manages the tiles of tilemaps. A sprite
+    /// has at most one.
+    TileManagementExtension,
+    /// A type added in a newer version of Aseprite.
+    Unknown(u8),
 }
 
 impl ExternalFile {
-    pub(crate) fn new(id: ExternalFileId, name: String) -> Self {
-        Self { id, name }
+    pub(crate) fn new(id: ExternalFileId, name: String, file_type: u8) -> Self {
+        Self {
+            id,
+            name,
+            file_type,
+        }
     }
 
     /// Returns a reference to the external file's id.
@@ -36,22 +63,37 @@ impl ExternalFile {
         &self.id
     }
 
-    /// Returns a reference to the external file's name.
+    /// Returns a reference to the external file's name. For extensions, this
+    /// is the extension's id instead of a file name.
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    /// What the external file contains.
+    pub fn file_type(&self) -> ExternalFileType {
+        match self.file_type {
+            0 => ExternalFileType::Palette,
+            1 => ExternalFileType::Tileset,
+            2 => ExternalFileType::PropertiesExtension,
+            3 => ExternalFileType::TileManagementExtension,
+            other => ExternalFileType::Unknown(other),
+        }
+    }
+
     pub(crate) fn parse_chunk(data: &[u8]) -> Result<Vec<Self>> {
         let mut reader = AseReader::new(data);
         let entry_ct = reader.dword()?;
         reader.skip_reserved(8)?;
 
-        let mut results = Vec::with_capacity(entry_ct as usize);
+        // Every entry takes up more than one byte, so this bounds the
+        // capacity even if the entry count is corrupt.
+        let mut results = Vec::with_capacity((entry_ct as usize).min(data.len()));
         for _ in 0..entry_ct {
             let id = ExternalFileId::new(reader.dword()?);
-            reader.skip_reserved(8)?;
+            let file_type = reader.byte()?;
+            reader.skip_reserved(7)?;
             let name = reader.string()?;
-            results.push(Self::new(id, name))
+            results.push(Self::new(id, name, file_type))
         }
 
         Ok(results)
@@ -60,11 +102,11 @@ impl ExternalFile {
 
 /// A map of [ExternalFileId] values to [ExternalFile] instances.
 #[derive(Debug)]
-pub struct ExternalFilesById(HashMap<ExternalFileId, ExternalFile>);
+pub struct ExternalFilesById(Map<ExternalFileId, ExternalFile>);
 
 impl ExternalFilesById {
     pub(crate) fn new() -> Self {
-        Self(HashMap::new())
+        Self(Map::new())
     }
 
     pub(crate) fn add(&mut self, external_file: ExternalFile) {
@@ -72,7 +114,7 @@ impl ExternalFilesById {
     }
 
     /// Returns a reference to the underlying HashMap value.
-    pub fn map(&self) -> &HashMap<ExternalFileId, ExternalFile> {
+    pub fn map(&self) -> &Map<ExternalFileId, ExternalFile> {
         &self.0
     }
 
@@ -81,3 +123,19 @@ impl ExternalFilesById {
         self.0.get(id)
     }
 }
+
+// Supplies the contents of external files, see
+// `ReadOptions::external_resolver`.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub(crate) struct ExternalResolver(pub(crate) Arc<ResolveFn>);
+
+#[cfg(feature = "std")]
+type ResolveFn = dyn Fn(&ExternalFile) -> Option<Vec<u8>> + Send + Sync;
+
+#[cfg(feature = "std")]
+impl fmt::Debug for ExternalResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ExternalResolver")
+    }
+}
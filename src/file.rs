@@ -1,23 +1,39 @@
-use std::{
-    fs::File,
-    io::{BufReader, Read},
-    path::Path,
-};
+#[cfg(feature = "std")]
+use crate::color_management::ColorTransform;
+#[cfg(feature = "std")]
+use crate::external_file::ExternalResolver;
+#[cfg(feature = "std")]
+use crate::frame_cache::{CacheKey, FrameCache};
+#[cfg(feature = "std")]
+use crate::pixel::SharedBytes;
+#[cfg(feature = "std")]
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use crate::io::Read;
+use alloc::{borrow::Cow, vec::Vec};
 
+use crate::image::{Rgba, RgbaImage};
+#[cfg(feature = "std")]
+use crate::{
+    ansi::{self, AnsiOptions},
+    histogram::{Histogram, HistogramBuilder},
+};
 use crate::{
     blend::{self, Color8},
     cel::{CelData, CelId, CelsData, ImageContent, ImageSize},
+    color_profile::ColorProfile,
+    composite::{CustomBlendFn, LayerStyle},
     external_file::{ExternalFile, ExternalFileId, ExternalFilesById},
     layer::{Layer, LayerType, LayersData},
-    slice::Slice,
+    limits::Limits,
+    slice::{Slice, SliceNames},
     tile::TileId,
     tilemap::Tilemap,
-    tileset::{TileSize, Tileset, TilesetImageError, TilesetsById},
+    tileset::{Tileset, TilesetsById},
     user_data::UserData,
 };
 use crate::{cel::Cel, *};
 use cel::{CelContent, RawCel};
-use image::{Rgba, RgbaImage};
 
 /// A parsed Aseprite file.
 #[derive(Debug)]
@@ -28,7 +44,7 @@ pub struct AsepriteFile {
     pub(crate) pixel_format: PixelFormat,
     pub(crate) palette: Option<ColorPalette>,
     pub(crate) layers: LayersData,
-    // pub(crate) color_profile: Option<ColorProfile>,
+    pub(crate) color_profile: Option<ColorProfile>,
     pub(crate) frame_times: Vec<u16>,
     pub(crate) tags: Vec<Tag>,
     pub(crate) framedata: CelsData, // Vec<Vec<cel::RawCel>>,
@@ -36,13 +52,354 @@ pub struct AsepriteFile {
     pub(crate) tilesets: TilesetsById,
     pub(crate) sprite_user_data: Option<UserData>,
     pub(crate) slices: Vec<Slice>,
+    pub(crate) slice_names: SliceNames,
+    pub(crate) warnings: Vec<ParseWarning>,
+    pub(crate) trailing_data: Vec<u8>,
+    pub(crate) pixel_ratio: (u8, u8),
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) grid: Grid,
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) raw_chunks: Vec<RawChunk>,
+    // See `AsepriteFile::read_metadata`.
+    #[cfg(feature = "std")]
+    pub(crate) metadata_only: bool,
+    #[cfg(feature = "std")]
+    pub(crate) frame_cache: FrameCache,
+}
+
+/// The grid that the sprite was drawn on in Aseprite, see
+/// [AsepriteFile::grid].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Grid {
+    pub(crate) x: i16,
+    pub(crate) y: i16,
+    pub(crate) width: u16,
+    pub(crate) height: u16,
+}
+
+impl Grid {
+    /// Horizontal position of the grid's origin in pixels.
+    pub fn x(&self) -> i16 {
+        self.x
+    }
+
+    /// Vertical position of the grid's origin in pixels.
+    pub fn y(&self) -> i16 {
+        self.y
+    }
+
+    /// Width of a grid cell in pixels. `0` if the file has no grid.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Height of a grid cell in pixels. `0` if the file has no grid.
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+}
+
+impl Default for Grid {
+    // The grid of new sprites in Aseprite.
+    fn default() -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: 16,
+            height: 16,
+        }
+    }
+}
+
+/// A chunk that this crate does not interpret, e.g., of a type added in a
+/// newer version of Aseprite. See [Frame::raw_chunks].
+///
+/// Raw chunks are kept so that they can be written back unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawChunk {
+    pub(crate) frame: u16,
+    // Position among the chunks of the frame, so that the chunk is written
+    // back in the same place.
+    pub(crate) index: u32,
+    pub(crate) chunk_type: u16,
+    pub(crate) data: Vec<u8>,
+}
+
+impl RawChunk {
+    /// The type code of the chunk, e.g., `0x2016` for a mask chunk. See the
+    /// [file format specification](https://github.com/aseprite/aseprite/blob/master/docs/ase-file-specs.md).
+    pub fn chunk_type(&self) -> u16 {
+        self.chunk_type
+    }
+
+    /// The data of the chunk, without the size and type at its start.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Options for loading files. See [AsepriteFile::read_with_options].
+///
+/// ```
+/// # use asefile::ReadOptions;
+/// let options = ReadOptions::new().strict(true);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    pub(crate) strict: bool,
+    // See `AsepriteFile::read_partial`.
+    pub(crate) partial: bool,
+    #[cfg(feature = "std")]
+    pub(crate) lazy: bool,
+    // See `AsepriteFile::read_metadata`.
+    #[cfg(feature = "std")]
+    pub(crate) metadata_only: bool,
+    // The buffer being parsed by `AsepriteFile::read_shared`.
+    #[cfg(feature = "std")]
+    pub(crate) shared: Option<SharedBytes>,
+    pub(crate) limits: Limits,
+    #[cfg(feature = "std")]
+    pub(crate) external_resolver: Option<ExternalResolver>,
+}
+
+impl ReadOptions {
+    /// Default options: Accept files with minor problems and report them as
+    /// [warnings](AsepriteFile::warnings).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject files that would load with [warnings](AsepriteFile::warnings),
+    /// e.g., files with data after the last frame or with chunks of unknown
+    /// types. Defaults to `false`.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Keep the pixel data of cels compressed until it is needed. The cels of
+    /// a frame are decoded the first time an image that uses one of them is
+    /// requested, e.g., with [Frame::image]. This makes loading faster and
+    /// uses less memory if only a few frames are needed. Defaults to `false`.
+    ///
+    /// Invalid pixel data is only detected when it is decoded. The affected
+    /// cels are drawn transparent and a warning is logged; use
+    /// [AsepriteFile::decode_all] to check for such errors. Tilesets are not
+    /// affected by this option.
+    ///
+    /// Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn lazy(mut self, lazy: bool) -> Self {
+        self.lazy = lazy;
+        self
+    }
+
+    /// Load the tiles of tilesets that are stored in an external file.
+    /// Tilesets in Aseprite can link to a tileset in another sprite instead
+    /// of including their tiles. For each such tileset, `resolver` is called
+    /// with the [ExternalFile] and returns the contents of that sprite, or
+    /// `None` if it is not available. Without a resolver, or if it returns
+    /// `None`, files with external tilesets fail to load with
+    /// [AsepriteParseError::UnsupportedFeature].
+    ///
+    /// The external sprite is read with the same limits, but its own
+    /// external files are not resolved. Its tileset must have the same tile
+    /// size and pixel format as the one linking to it. Returning data that
+    /// cannot be loaded fails with the error of reading the external file.
+    ///
+    /// ```
+    /// # use asefile::{AsepriteFile, ReadOptions};
+    /// # use std::path::Path;
+    /// let options = ReadOptions::new().external_resolver(|external| {
+    ///     std::fs::read(Path::new("./tests/data").join(external.name())).ok()
+    /// });
+    /// let path = Path::new("./tests/data/tileset.aseprite");
+    /// let ase = AsepriteFile::read_file_with_options(path, &options);
+    /// # ase.unwrap();
+    /// ```
+    ///
+    /// Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn external_resolver<F>(mut self, resolver: F) -> Self
+    where
+        F: Fn(&ExternalFile) -> Option<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.external_resolver = Some(ExternalResolver(Arc::new(resolver)));
+        self
+    }
+
+    /// Reject files whose canvas is wider than `width` or higher than
+    /// `height` pixels. Each [frame image](Frame::image) takes four bytes per
+    /// pixel of the canvas. Unlimited by default.
+    ///
+    /// Like all limits, this fails with [AsepriteParseError::LimitExceeded]
+    /// before any memory is allocated for the data in question. Set limits
+    /// when loading files from untrusted sources:
+    ///
+    /// ```
+    /// # use asefile::{AsepriteFile, AsepriteParseError, ReadOptions};
+    /// let options = ReadOptions::new()
+    ///     .max_canvas_size(8, 8)
+    ///     .max_frames(100)
+    ///     .max_image_bytes(1 << 20)
+    ///     .max_total_bytes(16 << 20);
+    /// let data = std::fs::read("./tests/data/basic-16x16.aseprite").unwrap();
+    /// let result = AsepriteFile::read_with_options(&data[..], &options);
+    /// assert!(matches!(result, Err(AsepriteParseError::LimitExceeded(_))));
+    /// ```
+    pub fn max_canvas_size(mut self, width: u16, height: u16) -> Self {
+        self.limits.canvas_size = Some((width, height));
+        self
+    }
+
+    /// Reject files with more than `num_frames` frames. Unlimited by
+    /// default.
+    pub fn max_frames(mut self, num_frames: u16) -> Self {
+        self.limits.frames = Some(num_frames);
+        self
+    }
+
+    /// Reject files with a cel image, tilemap or tileset that takes more than
+    /// `bytes` bytes when decompressed. Unlimited by default.
+    ///
+    /// The size is computed from the dimensions in the file, so a small file
+    /// with highly compressed data cannot get around the limit.
+    pub fn max_image_bytes(mut self, bytes: u64) -> Self {
+        self.limits.image_bytes = Some(bytes);
+        self
+    }
+
+    /// Reject files whose cel images, tilemaps and tilesets take more than
+    /// `bytes` bytes in total when decompressed. Unlimited by default.
+    ///
+    /// Linked cels are not counted. With [lazy](ReadOptions::lazy) decoding,
+    /// the limit applies to all cels, even if only some are decoded.
+    pub fn max_total_bytes(mut self, bytes: u64) -> Self {
+        self.limits.total_bytes = Some(bytes);
+        self
+    }
+}
+
+/// Options for compositing frame images. See [Frame::image_with_options].
+///
+/// ```
+/// # use asefile::ImageOptions;
+/// let options = ImageOptions::new().include_hidden_layers(true);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ImageOptions {
+    include_hidden_layers: bool,
+    include_reference_layers: bool,
+    #[cfg(feature = "std")]
+    convert_to_srgb: bool,
+    apply_pixel_ratio: bool,
+    background: Option<Background>,
+    premultiply_alpha: bool,
+}
+
+// What is drawn behind the layers, see `ImageOptions::background_color`.
+#[derive(Debug, Clone, Copy)]
+enum Background {
+    Color([u8; 3]),
+    PaletteIndex(u8),
+}
+
+impl ImageOptions {
+    /// Default options: Composite all visible layers except reference layers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also composite layers that are hidden in Aseprite, i.e., layers whose
+    /// eye icon (or that of a parent group) is deactivated. Defaults to
+    /// `false`.
+    pub fn include_hidden_layers(mut self, include: bool) -> Self {
+        self.include_hidden_layers = include;
+        self
+    }
+
+    /// Also composite [reference layers](crate::Layer::is_reference), which
+    /// Aseprite shows while drawing but leaves out when exporting. Defaults
+    /// to `false`.
+    pub fn include_reference_layers(mut self, include: bool) -> Self {
+        self.include_reference_layers = include;
+        self
+    }
+
+    /// Convert the composited image from the sprite's
+    /// [color profile](AsepriteFile::color_profile) to sRGB, so that sprites
+    /// drawn with a fixed gamma or an embedded ICC profile look the same as
+    /// in Aseprite when displayed as sRGB. Defaults to `false`, which returns
+    /// the colors stored in the file.
+    ///
+    /// Only ICC profiles that describe RGB colors with a tone curve and a
+    /// colorant per channel are supported, which includes the common RGB
+    /// working spaces. Images of sprites with other profiles are not
+    /// converted.
+    #[cfg(feature = "std")]
+    pub fn convert_to_srgb(mut self, convert: bool) -> Self {
+        self.convert_to_srgb = convert;
+        self
+    }
+
+    /// Stretch the composited image according to the sprite's
+    /// [pixel ratio](AsepriteFile::pixel_ratio), so that sprites with
+    /// non-square pixels look like in Aseprite. For example, the image of a
+    /// 16x16 sprite with a pixel ratio of 2:1 is 32x16 pixels. Defaults to
+    /// `false`, which returns one image pixel per sprite pixel.
+    pub fn apply_pixel_ratio(mut self, apply: bool) -> Self {
+        self.apply_pixel_ratio = apply;
+        self
+    }
+
+    /// Composite the layers over an opaque background of the given color
+    /// instead of a transparent canvas, so that the image is ready to be
+    /// displayed without alpha. Defaults to no background.
+    pub fn background_color(mut self, rgb: [u8; 3]) -> Self {
+        self.background = Some(Background::Color(rgb));
+        self
+    }
+
+    /// Like [background_color](ImageOptions::background_color) but uses the
+    /// color of the palette entry `index`, e.g., the background color that an
+    /// indexed sprite was drawn for. The color is used as opaque. If the
+    /// sprite has no such entry, there is no background.
+    pub fn background_index(mut self, index: u8) -> Self {
+        self.background = Some(Background::PaletteIndex(index));
+        self
+    }
+
+    /// Return the image with premultiplied alpha, i.e., with each color
+    /// channel multiplied by the pixel's alpha, as most GPU pipelines expect.
+    /// This is done last, after all other options. Defaults to `false`, which
+    /// returns straight alpha like Aseprite.
+    pub fn premultiply_alpha(mut self, premultiply: bool) -> Self {
+        self.premultiply_alpha = premultiply;
+        self
+    }
 }
 
 /// A reference to a single frame.
 #[derive(Debug)]
 pub struct Frame<'a> {
-    file: &'a AsepriteFile,
-    index: u32,
+    pub(crate) file: &'a AsepriteFile,
+    pub(crate) index: u32,
+}
+
+// Like layers, frames can only be serialized.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Frame<'_> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> core::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut frame = serializer.serialize_struct("Frame", 2)?;
+        frame.serialize_field("index", &self.index)?;
+        frame.serialize_field("duration", &self.duration())?;
+        frame.end()
+    }
 }
 
 /// Pixel format of the source Aseprite file.
@@ -81,17 +438,229 @@ impl PixelFormat {
 
 impl AsepriteFile {
     /// Load Aseprite file. Loads full file into memory.
+    ///
+    /// # Errors
+    ///
+    /// Files that cannot describe a sprite are rejected with
+    /// [AsepriteParseError::InvalidInput]. This includes a canvas with zero
+    /// width or height, zero frames, and cels that refer to missing palette
+    /// entries. Cels that refer to missing layers are dropped with a
+    /// [ParseWarning::MissingLayer] [warning](AsepriteFile::warnings).
+    /// Tilemap cels that refer to missing tiles are kept with a
+    /// [ParseWarning::TileIdOutOfRange] warning, and those tiles are drawn as
+    /// tile 0, the empty tile.
+    ///
+    /// Files that are merely empty load successfully: a sprite may have no
+    /// layers, no cels, no tags, or (if it contains no indexed pixels) no
+    /// palette. Frames of such sprites render as fully transparent images.
+    #[cfg(feature = "std")]
     pub fn read_file(path: &Path) -> Result<Self> {
-        let file = File::open(&path)?;
+        Self::read_file_with_options(path, &ReadOptions::default())
+    }
+
+    /// Like [read_file](AsepriteFile::read_file) but with custom
+    /// [ReadOptions].
+    #[cfg(feature = "std")]
+    pub fn read_file_with_options(path: &Path, options: &ReadOptions) -> Result<Self> {
+        let file = File::open(path)?;
         let reader = BufReader::new(file);
-        parse::read_aseprite(reader)
+        parse::read_aseprite(reader, options)
     }
 
-    /// Load Aseprite file from any input that implements `std::io::Read`.
+    /// Load Aseprite file from any input that implements `std::io::Read`, or
+    /// from a byte slice without the `std` feature.
     ///
-    /// You can use this to read from an in-memory file.
+    /// You can use this to read from an in-memory file. See
+    /// [AsepriteFile::read_file] for which inputs are rejected.
     pub fn read<R: Read>(input: R) -> Result<AsepriteFile> {
-        parse::read_aseprite(input)
+        Self::read_with_options(input, &ReadOptions::default())
+    }
+
+    /// Like [read](AsepriteFile::read) but with custom [ReadOptions].
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors of [read_file](AsepriteFile::read_file),
+    /// [strict](ReadOptions::strict) loading fails with
+    /// [AsepriteParseError::InvalidInput] if there would be any
+    /// [warnings](AsepriteFile::warnings).
+    pub fn read_with_options<R: Read>(input: R, options: &ReadOptions) -> Result<AsepriteFile> {
+        parse::read_aseprite(input, options)
+    }
+
+    /// Load everything but the pixels of cels: the header, layers, frame
+    /// durations, tags, slices, palette, tilesets and user data, as well as
+    /// the position and size of each cel. This is much faster than
+    /// [read](AsepriteFile::read) for tools that only need timing or slice
+    /// information. All [images](Frame::image) of the file are transparent.
+    ///
+    /// Such a file cannot be [written](AsepriteFile::write_to) or
+    /// [cached](AsepriteFile::to_cache), since that would store the
+    /// transparent images in place of the actual pixels.
+    ///
+    /// Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn read_metadata<R: Read>(input: R) -> Result<AsepriteFile> {
+        let options = ReadOptions {
+            metadata_only: true,
+            ..ReadOptions::default()
+        };
+        Self::read_with_options(input, &options)
+    }
+
+    /// Load as many frames as possible from a file that is cut off or
+    /// corrupted, e.g., by an interrupted save or download.
+    ///
+    /// If a frame after the first one cannot be read, the file ends with the
+    /// frame before it. The [warnings](AsepriteFile::warnings) then include
+    /// [ParseWarning::Truncated], and [num_frames](AsepriteFile::num_frames)
+    /// counts only the frames that were read. Tags are shortened to these
+    /// frames.
+    ///
+    /// ```
+    /// # use asefile::{AsepriteFile, ParseWarning};
+    /// let data = std::fs::read("./tests/data/layers_and_tags.aseprite").unwrap();
+    /// let cut_off = &data[..data.len() - 100];
+    /// assert!(AsepriteFile::read(cut_off).is_err());
+    /// let ase = AsepriteFile::read_partial(cut_off).unwrap();
+    /// assert!(ase.num_frames() < 4);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Same as [read](AsepriteFile::read) if the header or the first frame
+    /// cannot be read.
+    pub fn read_partial<R: Read>(input: R) -> Result<AsepriteFile> {
+        let options = ReadOptions {
+            partial: true,
+            ..ReadOptions::default()
+        };
+        Self::read_with_options(input, &options)
+    }
+
+    /// Load a file from a buffer that it can keep a reference to, e.g., a
+    /// memory-mapped file. The file is loaded with [lazy](ReadOptions::lazy)
+    /// decoding and refers to the compressed pixel data of its cels in `data`
+    /// instead of a copy, so memory is only allocated for the cels whose
+    /// pixels are needed.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # use std::{fs::File, sync::Arc};
+    /// # let file = File::open("./tests/data/layers_and_tags.aseprite").unwrap();
+    /// // The file must not be modified while it is mapped.
+    /// let map = unsafe { memmap2::Mmap::map(&file) }.unwrap();
+    /// let ase = AsepriteFile::read_shared(Arc::new(map)).unwrap();
+    /// let image = ase.frame(0).image();
+    /// ```
+    ///
+    /// Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn read_shared<T>(data: Arc<T>) -> Result<AsepriteFile>
+    where
+        T: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        let shared = SharedBytes(data);
+        let options = ReadOptions {
+            lazy: true,
+            shared: Some(shared.clone()),
+            ..ReadOptions::default()
+        };
+        parse::read_aseprite(shared.get(), &options)
+    }
+
+    /// Load a file from the start of `input`, which may contain more data
+    /// after the file, e.g., further files. Returns the file and the number
+    /// of bytes it occupied.
+    ///
+    /// Unlike [read](AsepriteFile::read), this stops reading exactly at the
+    /// end of the last frame, so the next file can be read from the same
+    /// input. The file size in the header is not used to find the end of the
+    /// file, but a mismatch is reported in the [warnings](AsepriteFile::warnings).
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let one = std::fs::read("./tests/data/basic-16x16.aseprite").unwrap();
+    /// # let two = std::fs::read("./tests/data/big.aseprite").unwrap();
+    /// let bundle = [one, two].concat();
+    /// let mut input = &bundle[..];
+    /// let mut files = Vec::new();
+    /// while !input.is_empty() {
+    ///     let (file, _len) = AsepriteFile::read_next(&mut input).unwrap();
+    ///     files.push(file);
+    /// }
+    /// assert_eq!(files.len(), 2);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Same as [read_file](AsepriteFile::read_file).
+    pub fn read_next<R: Read>(input: R) -> Result<(AsepriteFile, u64)> {
+        parse::read_next(input)
+    }
+
+    /// Problems with the file that did not prevent loading it. Always empty
+    /// for files loaded with [strict](ReadOptions::strict) options.
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    /// Decode the pixel data of all cels that were not needed yet. Only has an
+    /// effect for files loaded with [lazy](ReadOptions::lazy) decoding.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [AsepriteParseError::InvalidInput] if the pixel data of a
+    /// cel is invalid, like [read](AsepriteFile::read) would have without
+    /// lazy decoding.
+    ///
+    /// Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn decode_all(&self) -> Result<()> {
+        self.framedata.decode_all()
+    }
+
+    /// Keep the images of the `capacity` most recently composited frames.
+    /// [Frame::image] then returns a copy of a kept image instead of
+    /// blending the layers of the frame again, which helps when an animation
+    /// is played back by compositing the same frames over and over. The
+    /// cache holds `capacity` images of the size of the sprite.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # use std::path::Path;
+    /// # let path = Path::new("./tests/data/layers_and_tags.aseprite");
+    /// let mut ase = AsepriteFile::read_file(&path).unwrap();
+    /// ase.set_frame_cache_capacity(ase.num_frames() as usize);
+    /// for _ in 0..3 {
+    ///     for frame in 0..ase.num_frames() {
+    ///         let image = ase.frame(frame).image();
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// Defaults to 0, which disables the cache. Reducing the capacity drops
+    /// the least recently used images, and edits of the file drop all of them.
+    ///
+    /// Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn set_frame_cache_capacity(&mut self, capacity: usize) {
+        self.frame_cache.set_capacity(capacity);
+    }
+
+    /// The number of frame images kept, see
+    /// [set_frame_cache_capacity](AsepriteFile::set_frame_cache_capacity).
+    ///
+    /// Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn frame_cache_capacity(&self) -> usize {
+        self.frame_cache.capacity()
+    }
+
+    /// The data after the end of the last frame. Usually empty. See
+    /// [ParseWarning::TrailingData].
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing_data
     }
 
     /// Width in pixels.
@@ -125,6 +694,15 @@ impl AsepriteFile {
         self.pixel_format
     }
 
+    /// The palette index that stands for transparent pixels in indexed
+    /// sprites, or `None` if the sprite is not indexed. Frame and cel images
+    /// draw pixels with this index as fully transparent, except in the
+    /// background layer. Shorthand for
+    /// [PixelFormat::transparent_color_index].
+    pub fn transparent_color_index(&self) -> Option<u8> {
+        self.pixel_format.transparent_color_index()
+    }
+
     /// The color palette in the image.
     ///
     /// For indexed color images, this includes all colors used by individual
@@ -139,10 +717,10 @@ impl AsepriteFile {
     /// # Panics
     ///
     /// Panics if the ID is not valid. ID must be less than number of layers.
-    pub fn layer(&self, id: u32) -> Layer {
+    pub fn layer(&self, id: u32) -> Layer<'_> {
         assert!(id < self.num_layers());
         Layer {
-            file: &self,
+            file: self,
             layer_id: id,
         }
     }
@@ -151,7 +729,7 @@ impl AsepriteFile {
     ///
     /// If multiple layers with the same name exist returns the layer with
     /// the lower ID.
-    pub fn layer_by_name(&self, name: &str) -> Option<Layer> {
+    pub fn layer_by_name(&self, name: &str) -> Option<Layer<'_>> {
         for layer_id in 0..self.num_layers() {
             let l = self.layer(layer_id);
             if l.name() == name {
@@ -161,20 +739,104 @@ impl AsepriteFile {
         None
     }
 
+    /// Access a layer by its [path](Layer::path), e.g., `"UI/Buttons/Hover"`
+    /// for the layer "Hover" in the group "Buttons" in the top level group
+    /// "UI". Layer names that contain `/` are matched as well.
+    ///
+    /// If multiple layers have the path, returns the layer with the lower ID.
+    pub fn layer_by_path(&self, path: &str) -> Option<Layer<'_>> {
+        self.find_layer_path(None, path).map(|id| self.layer(id))
+    }
+
+    // Walks down the groups whose names `path` starts with.
+    fn find_layer_path(&self, parent: Option<u32>, path: &str) -> Option<u32> {
+        self.layers.children_of(parent).find_map(|id| {
+            let name = self.layers[id].name.as_str();
+            let rest = path.strip_prefix(name)?;
+            if rest.is_empty() {
+                Some(id)
+            } else {
+                self.find_layer_path(Some(id), rest.strip_prefix('/')?)
+            }
+        })
+    }
+
+    /// All layers for which `predicate` returns `true`, in the order of
+    /// their IDs.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/layers_and_tags.aseprite")).unwrap();
+    /// let hidden: Vec<_> = ase.layers_matching(|layer| !layer.is_visible()).collect();
+    /// ```
+    pub fn layers_matching<F>(&self, mut predicate: F) -> impl Iterator<Item = Layer<'_>>
+    where
+        F: FnMut(&Layer<'_>) -> bool,
+    {
+        self.layers().filter(move |layer| predicate(layer))
+    }
+
     /// An iterator over all layers.
-    pub fn layers(&self) -> LayersIter {
+    pub fn layers(&self) -> LayersIter<'_> {
         LayersIter {
             file: self,
             next: 0,
         }
     }
 
+    /// The layers at the top level, i.e., outside of any group, from bottom
+    /// to top. Use [Layer::children] to walk the groups among them.
+    pub fn root_layers(&self) -> impl Iterator<Item = Layer<'_>> {
+        self.layers.children_of(None).map(move |layer_id| Layer {
+            file: self,
+            layer_id,
+        })
+    }
+
+    #[cfg(feature = "image")]
+    /// Render every visible top-level layer of every frame to its own image,
+    /// like Aseprite's `--split-layers` export. Returns the name of the
+    /// layer, the frame index, and the image, ordered by frame and then from
+    /// the bottom layer to the top layer.
+    ///
+    /// Groups are rendered with everything inside of them, see
+    /// [Frame::group_image]. Image layers are rendered like
+    /// [Cel::image](crate::Cel::image). Hidden layers and reference layers
+    /// are skipped.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/layers_and_tags.aseprite")).unwrap();
+    /// for (layer, frame, image) in ase.split_layers() {
+    ///     // image.save(format!("{}-{}.png", layer, frame)).unwrap();
+    ///     println!("{} {}: {}x{}", layer, frame, image.width(), image.height());
+    /// }
+    /// ```
+    pub fn split_layers(&self) -> Vec<(&str, u32, RgbaImage)> {
+        let layers: Vec<Layer<'_>> = self
+            .root_layers()
+            .filter(|layer| layer.is_visible() && !layer.is_reference())
+            .collect();
+        let mut images = Vec::with_capacity(layers.len() * self.num_frames as usize);
+        for frame in 0..self.num_frames {
+            for layer in &layers {
+                let image = if layer.layer_type() == LayerType::Group {
+                    self.group_image(frame, layer.id())
+                } else {
+                    self.layer_image(frame, layer.id() as usize)
+                };
+                images.push((self.layers[layer.id()].name.as_str(), frame as u32, image));
+            }
+        }
+        images
+    }
+
     /// A reference to a single frame.
     ///
     /// # Panics
     ///
     /// Panics if `index` is not less than `num_frames`.
-    pub fn frame(&self, index: u32) -> Frame {
+    pub fn frame(&self, index: u32) -> Frame<'_> {
         assert!(index < self.num_frames as u32);
         Frame { file: self, index }
     }
@@ -208,12 +870,7 @@ impl AsepriteFile {
     /// If multiple tags with the same name exist, returns the one with the
     /// lower ID.
     pub fn tag_by_name(&self, name: &str) -> Option<&Tag> {
-        for tag in &self.tags {
-            if tag.name() == name {
-                return Some(tag);
-            }
-        }
-        None
+        self.tags.iter().find(|tag| tag.name() == name)
     }
 
     /// Access the file's [Tileset]s.
@@ -221,26 +878,27 @@ impl AsepriteFile {
         &self.tilesets
     }
 
+    #[cfg(feature = "image")]
     /// Construct the image of each tile in the [Tileset].
     /// The image has width equal to the tile width and height equal to (tile_height * tile_count).
     pub fn tileset_image(
         &self,
         tileset_id: &TilesetId,
-    ) -> std::result::Result<RgbaImage, TilesetImageError> {
+    ) -> core::result::Result<RgbaImage, TilesetImageError> {
         let tileset = self
             .tilesets
             .get(tileset_id)
-            .ok_or_else(|| TilesetImageError::MissingTilesetId(*tileset_id))?;
+            .ok_or(TilesetImageError::MissingTilesetId(*tileset_id))?;
         let pixels = tileset
             .pixels
             .as_ref()
-            .ok_or_else(|| TilesetImageError::NoPixelsInTileset(*tileset_id))?;
+            .ok_or(TilesetImageError::NoPixelsInTileset(*tileset_id))?;
         let resolver_data = pixel::IndexResolverData {
             palette: self.palette.as_ref(),
             transparent_color_index: self.pixel_format.transparent_color_index(),
             layer_is_background: false,
         };
-        let image_pixels = pixels.clone_as_image_rgba(resolver_data);
+        let image_pixels = pixels.all().clone_as_image_rgba(resolver_data);
 
         Ok(tileset.write_to_image(image_pixels.as_ref()))
     }
@@ -255,34 +913,203 @@ impl AsepriteFile {
         &self.slices
     }
 
-    // pub fn color_profile(&self) -> Option<&ColorProfile> {
-    //     self.color_profile.as_ref()
-    // }
+    /// Lookup slice by name.
+    ///
+    /// If multiple slices with the same name exist, returns the one with the
+    /// lower index in [slices](AsepriteFile::slices).
+    pub fn slice(&self, name: &str) -> Option<&Slice> {
+        self.slice_names.find(&self.slices, name)
+    }
+
+    /// All slices whose name starts with `prefix`, e.g., all slices named
+    /// `"icon/..."`. The slices are sorted by name. Slices with the same name
+    /// keep their order.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/slice.aseprite")).unwrap();
+    /// for slice in ase.slices_named("Slice") {
+    ///     println!("{}", slice.name);
+    /// }
+    /// ```
+    pub fn slices_named<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a Slice> + 'a {
+        self.slice_names.with_prefix(&self.slices, prefix)
+    }
+
+    /// All slices whose rectangle at the given frame contains the point
+    /// `(x, y)` in sprite coordinates, in the order of
+    /// [slices](AsepriteFile::slices). Slices that do not exist yet or are
+    /// hidden at that frame are left out.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/slice.aseprite")).unwrap();
+    /// for slice in ase.slices_at(0, 7, 7) {
+    ///     println!("(7, 7) is in {}", slice.name);
+    /// }
+    /// ```
+    pub fn slices_at(&self, frame: u32, x: i32, y: i32) -> impl Iterator<Item = &Slice> {
+        self.slices.iter().filter(move |slice| {
+            slice
+                .bounds_at(frame)
+                .is_some_and(|(left, top, width, height)| {
+                    let (dx, dy) = (x as i64 - left as i64, y as i64 - top as i64);
+                    dx >= 0 && dy >= 0 && dx < width as i64 && dy < height as i64
+                })
+        })
+    }
+
+    /// The width and height of a sprite pixel, e.g., `(2, 1)` for pixels that
+    /// are twice as wide as high. Most sprites have square pixels, i.e., a
+    /// ratio of `(1, 1)`. Images are only stretched accordingly with
+    /// [ImageOptions::apply_pixel_ratio].
+    pub fn pixel_ratio(&self) -> (u8, u8) {
+        self.pixel_ratio
+    }
+
+    /// The grid settings of the sprite. Sprites created by this crate have a
+    /// 16x16 grid at the origin, like new sprites in Aseprite.
+    pub fn grid(&self) -> Grid {
+        self.grid
+    }
+
+    /// The color profile of the sprite, or `None` if the file has no color
+    /// profile chunk. Files written before Aseprite 1.2.25 have none.
+    pub fn color_profile(&self) -> Option<&ColorProfile> {
+        self.color_profile.as_ref()
+    }
+
+    #[cfg(feature = "image")]
+    /// Renders all frames and returns them in order. Consumes the file so that
+    /// the decoded cel data of each frame can be freed as soon as it has been
+    /// rendered. This keeps peak memory close to the size of the remaining
+    /// cels plus the images produced so far.
+    ///
+    /// Use [into_frame_images_iter](AsepriteFile::into_frame_images_iter) to
+    /// process one frame at a time.
+    pub fn into_frame_images(self, options: ImageOptions) -> Vec<RgbaImage> {
+        self.into_frame_images_iter(options).collect()
+    }
+
+    #[cfg(feature = "image")]
+    /// Like [into_frame_images](AsepriteFile::into_frame_images) but renders
+    /// frames lazily. If each image is dropped before requesting the next one,
+    /// peak memory is roughly one frame image plus the cels of the frames that
+    /// have not been rendered yet.
+    pub fn into_frame_images_iter(self, options: ImageOptions) -> IntoFrameImages {
+        let release_schedule = self.framedata.release_schedule();
+        IntoFrameImages {
+            file: self,
+            options,
+            release_schedule,
+            next: 0,
+        }
+    }
 
     /// Construct the image belonging to the specific animation frame. Combines
     /// layers according to their blend mode. Skips invisible layers (i.e.,
-    /// layers with a deactivated eye icon).
+    /// layers with a deactivated eye icon) unless the options say otherwise.
     ///
     /// Can fail if the `frame` does not exist, an unsupported feature is
     /// used, or the file is malformed.
-    fn frame_image(&self, frame: u16) -> RgbaImage {
+    fn frame_image(&self, frame: u16, options: &ImageOptions) -> RgbaImage {
+        #[cfg(feature = "std")]
+        {
+            let key = CacheKey {
+                frame,
+                include_hidden_layers: options.include_hidden_layers,
+                include_reference_layers: options.include_reference_layers,
+                convert_to_srgb: options.convert_to_srgb,
+                apply_pixel_ratio: options.apply_pixel_ratio,
+                background: self.background_color(options),
+                premultiply_alpha: options.premultiply_alpha,
+            };
+            self.frame_cache
+                .get_or_insert(key, || self.composite_frame(frame, options))
+        }
+        #[cfg(not(feature = "std"))]
+        self.composite_frame(frame, options)
+    }
+
+    fn composite_frame(&self, frame: u16, options: &ImageOptions) -> RgbaImage {
+        self.composite_layers(frame, options, |_| Some(LayerStyle::default()))
+    }
+
+    // Composites the layers for which `style` returns how to draw them. Layers
+    // for which it returns `None` are skipped, in addition to the layers
+    // skipped according to `options`.
+    fn composite_layers<F>(&self, frame: u16, options: &ImageOptions, style: F) -> RgbaImage
+    where
+        F: Fn(&Layer<'_>) -> Option<LayerStyle>,
+    {
+        trace_span!(DEBUG, "composite_frame", frame);
         let mut image = RgbaImage::new(self.width as u32, self.height as u32);
 
-        for (layer_id, cel) in self.framedata.frame_cels(frame) {
-            // TODO: Ensure this is always done in layer order (pre-sort Cels?)
-            if !self.layer(layer_id).is_visible() {
+        for (layer_id, cel) in self.framedata.frame_cels_in_z_order(frame) {
+            let layer = self.layer(layer_id);
+            if !options.include_hidden_layers && !layer.is_visible() {
                 continue;
             }
-            self.write_cel(&mut image, cel);
+            if !options.include_reference_layers && layer.is_reference() {
+                continue;
+            }
+            if let Some(style) = style(&layer) {
+                self.write_cel(&mut image, cel, style);
+            }
+        }
+
+        if let Some(background) = self.background_color(options) {
+            for pixel in image.pixels_mut() {
+                *pixel = blend::normal(Rgba(background), *pixel, 255);
+            }
         }
 
+        #[cfg(feature = "std")]
+        {
+            let transform = self
+                .color_profile
+                .as_ref()
+                .filter(|_| options.convert_to_srgb)
+                .and_then(ColorTransform::to_srgb);
+            if let Some(transform) = transform {
+                transform.apply(&mut image);
+            }
+        }
+        if options.apply_pixel_ratio && self.pixel_ratio != (1, 1) {
+            let (pixel_width, pixel_height) =
+                (self.pixel_ratio.0 as u32, self.pixel_ratio.1 as u32);
+            let (width, height) = image.dimensions();
+            image = RgbaImage::from_fn(width * pixel_width, height * pixel_height, |x, y| {
+                *image.get_pixel(x / pixel_width, y / pixel_height)
+            });
+        }
+        if options.premultiply_alpha {
+            premultiply_alpha(&mut image);
+        }
         image
     }
 
-    fn write_cel(&self, image: &mut RgbaImage, cel: &RawCel) {
+    // The opaque color behind the layers, if any.
+    fn background_color(&self, options: &ImageOptions) -> Option<[u8; 4]> {
+        let [r, g, b] = match options.background? {
+            Background::Color(rgb) => rgb,
+            Background::PaletteIndex(index) => {
+                let [r, g, b, _] = self.palette.as_ref()?.color(index as u32)?.raw_rgba8();
+                [r, g, b]
+            }
+        };
+        Some([r, g, b, 255])
+    }
+
+    // Draws the cel with its layer's blend mode, changed by `style`.
+    fn write_cel(&self, image: &mut RgbaImage, cel: &RawCel, style: LayerStyle) {
         let RawCel { data, content, .. } = cel;
         let layer = self.layer(data.layer_index as u32);
-        let blend_mode = layer.blend_mode();
+        let blender = match &style.blend_fn {
+            Some(blend_fn) => Blender::Custom(blend_fn.as_ref()),
+            None => Blender::mode(layer.blend_mode()),
+        };
+        let opacity = blend::mul_un8(data.opacity as i32, style.opacity as i32);
         let resolver_data = pixel::IndexResolverData {
             palette: self.palette.as_ref(),
             transparent_color_index: self.pixel_format.transparent_color_index(),
@@ -291,9 +1118,13 @@ impl AsepriteFile {
         match &content {
             CelContent::Raw(image_content) => {
                 let ImageContent { size, pixels } = image_content;
-                let image_pixels = pixels.clone_as_image_rgba(resolver_data);
+                let image_pixels = self
+                    .framedata
+                    .pixels(pixels)
+                    .clone_as_image_rgba(resolver_data);
+                let image_pixels = tint_pixels(image_pixels, style.tint);
 
-                write_raw_cel_to_image(image, data, size, image_pixels.as_ref(), &blend_mode);
+                write_raw_cel_to_image(image, data, size, image_pixels.as_ref(), &blender, opacity);
             }
             CelContent::Tilemap(tilemap_data) => {
                 let layer_type = layer.layer_type();
@@ -312,7 +1143,8 @@ impl AsepriteFile {
                     .pixels
                     .as_ref()
                     .expect("Expected Tileset data to contain pixels. Should have been caught by TilesetsById::validate()");
-                let rgba_pixels = tileset_pixels.clone_as_image_rgba(resolver_data);
+                let rgba_pixels = tileset_pixels.all().clone_as_image_rgba(resolver_data);
+                let rgba_pixels = tint_pixels(rgba_pixels, style.tint);
 
                 write_tilemap_cel_to_image(
                     image,
@@ -320,7 +1152,8 @@ impl AsepriteFile {
                     tilemap_data,
                     tileset,
                     rgba_pixels.as_ref(),
-                    &blend_mode,
+                    &blender,
+                    opacity,
                 );
             }
             CelContent::Linked(frame) => {
@@ -334,24 +1167,120 @@ impl AsepriteFile {
                         );
                     } else {
                         // Recurse once with the source non-Linked cel
-                        self.write_cel(image, cel);
+                        self.write_cel(image, cel, style);
                     }
                 }
             }
         }
     }
 
+    // Fast path for color histograms of indexed files: If the frame consists
+    // of a single cel that is drawn without blending, counts the palette
+    // indices instead of compositing. Returns `false` if that is not possible.
+    #[cfg(feature = "std")]
+    fn add_indexed_colors(&self, frame: u16, builder: &mut HistogramBuilder) -> bool {
+        let (palette, transparent_color_index) = match (&self.palette, self.pixel_format) {
+            (
+                Some(palette),
+                PixelFormat::Indexed {
+                    transparent_color_index,
+                },
+            ) => (palette, transparent_color_index),
+            _ => return false,
+        };
+        let mut visible = self.framedata.frame_cels(frame).filter(|(layer_id, _)| {
+            let layer = self.layer(*layer_id);
+            layer.is_visible() && !layer.is_reference()
+        });
+        let (layer_id, cel) = match (visible.next(), visible.next()) {
+            (Some(cel), None) => cel,
+            (None, _) => return true,
+            _ => return false,
+        };
+        let layer = &self.layers[layer_id];
+        if layer.blend_mode != BlendMode::Normal || layer.opacity != 255 {
+            return false;
+        }
+        let cel = self
+            .framedata
+            .resolved_cel(CelId {
+                frame,
+                layer: layer_id as u16,
+            })
+            .unwrap_or(cel);
+        let (size, indices) = match &cel.content {
+            CelContent::Raw(ImageContent { size, pixels }) => match self.framedata.pixels(pixels) {
+                pixel::PixelsRef::Indexed(indices) => (size, indices),
+                _ => return false,
+            },
+            _ => return false,
+        };
+        if cel.data.opacity != 255 {
+            return false;
+        }
+
+        let mut counts = [0_u64; 256];
+        let (x0, y0) = (cel.data.x as i32, cel.data.y as i32);
+        for y in 0..size.height as i32 {
+            let canvas_y = y0 + y;
+            if canvas_y < 0 || canvas_y >= self.height as i32 {
+                continue;
+            }
+            for x in 0..size.width as i32 {
+                let canvas_x = x0 + x;
+                if canvas_x >= 0 && canvas_x < self.width as i32 {
+                    let idx = (y * size.width as i32 + x) as usize;
+                    counts[indices[idx] as usize] += 1;
+                }
+            }
+        }
+        let layer_is_background = layer.is_background();
+        for (index, count) in counts.iter().enumerate() {
+            let color = pixel::Indexed::new(index as u8).as_rgba(
+                palette,
+                transparent_color_index,
+                layer_is_background,
+            );
+            if let Some(color) = color {
+                builder.add(color, *count);
+            }
+        }
+        true
+    }
+
     pub(crate) fn layer_image(&self, frame: u16, layer_id: usize) -> RgbaImage {
+        trace_span!(DEBUG, "composite_layer", frame, layer = layer_id);
         let mut image = RgbaImage::new(self.width as u32, self.height as u32);
         if let Some(cel) = self.framedata.cel(CelId {
             frame,
             layer: layer_id as u16,
         }) {
-            self.write_cel(&mut image, cel);
+            self.write_cel(&mut image, cel, LayerStyle::default());
         }
         image
     }
 
+    // Composites the layers inside the group `group_id` like a frame image
+    // that only contains them. Layers that are hidden within the group are
+    // skipped, but the visibility of the group itself is ignored.
+    #[cfg(feature = "image")]
+    fn group_image(&self, frame: u16, group_id: u32) -> RgbaImage {
+        let options = ImageOptions::new().include_hidden_layers(true);
+        self.composite_layers(frame, &options, |layer| {
+            let mut next = Some(layer.id());
+            while let Some(layer_id) = next {
+                if layer_id == group_id {
+                    return Some(LayerStyle::default());
+                }
+                if !self.layers[layer_id].flags.contains(LayerFlags::VISIBLE) {
+                    return None;
+                }
+                next = self.layer(layer_id).parent().map(|parent| parent.id());
+            }
+            None
+        })
+    }
+
     // fn frame_cels(&self, frame: u16, layer: u16) -> Vec<&RawCel> {
     //     self.framedata[frame as usize]
     //         .iter()
@@ -381,17 +1310,155 @@ impl<'a> Iterator for LayersIter<'a> {
     }
 }
 
+#[cfg(feature = "image")]
+/// An iterator that renders the frames of a file it owns. See
+/// [AsepriteFile::into_frame_images_iter].
+#[derive(Debug)]
+pub struct IntoFrameImages {
+    file: AsepriteFile,
+    options: ImageOptions,
+    release_schedule: Vec<Vec<CelId>>,
+    next: u32,
+}
+
+#[cfg(feature = "image")]
+impl Iterator for IntoFrameImages {
+    type Item = RgbaImage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.file.num_frames() {
+            return None;
+        }
+        let frame = self.next as u16;
+        let image = self.file.frame_image(frame, &self.options);
+        for cel_id in self.release_schedule[frame as usize].drain(..) {
+            self.file.framedata.remove_cel(cel_id);
+        }
+        self.next += 1;
+        Some(image)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.file.num_frames() - self.next) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(feature = "image")]
+impl ExactSizeIterator for IntoFrameImages {}
+
 impl<'a> Frame<'a> {
+    #[cfg(feature = "image")]
     /// Construct the image belonging to the specific animation frame. Combines
     /// layers according to their blend mode. Skips invisible layers (i.e.,
-    /// layers with a deactivated eye icon).
+    /// layers with a deactivated eye icon) and
+    /// [reference layers](crate::Layer::is_reference).
     ///
     pub fn image(&self) -> RgbaImage {
-        self.file.frame_image(self.index as u16)
+        self.composite()
+    }
+
+    #[cfg(feature = "image")]
+    /// Like [image](Frame::image) but with custom [ImageOptions].
+    pub fn image_with_options(&self, options: &ImageOptions) -> RgbaImage {
+        self.file.frame_image(self.index as u16, options)
+    }
+
+    /// Like [image](Frame::image) but returns a [raw_image::RgbaImage]. This
+    /// is available without the `image` feature.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/basic-16x16.aseprite")).unwrap();
+    /// let image = ase.frame(0).raw_image();
+    /// assert_eq!(image.dimensions(), (ase.width() as u32, ase.height() as u32));
+    /// ```
+    pub fn raw_image(&self) -> raw_image::RgbaImage {
+        raw_image::from_image(self.composite())
+    }
+
+    /// Like [raw_image](Frame::raw_image) but with custom [ImageOptions].
+    pub fn raw_image_with_options(&self, options: &ImageOptions) -> raw_image::RgbaImage {
+        raw_image::from_image(self.file.frame_image(self.index as u16, options))
+    }
+
+    // The image of `image`, which is also needed without the `image` feature.
+    pub(crate) fn composite(&self) -> RgbaImage {
+        self.file
+            .frame_image(self.index as u16, &ImageOptions::default())
+    }
+
+    #[cfg(feature = "image")]
+    /// Like [image](Frame::image) but only composites the layers selected by
+    /// `filter`. Hidden layers and reference layers are still skipped, see
+    /// [image_with_layers_and_options](Frame::image_with_layers_and_options).
+    ///
+    /// ```
+    /// # use asefile::{AsepriteFile, LayerFilter};
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/layers_and_tags.aseprite")).unwrap();
+    /// let body = ase.frame(0).image_with_layers(&LayerFilter::name("Layer 1"));
+    /// ```
+    pub fn image_with_layers(&self, filter: &LayerFilter<'_>) -> RgbaImage {
+        self.image_with_layers_and_options(filter, &ImageOptions::default())
+    }
+
+    #[cfg(feature = "image")]
+    /// Like [image_with_layers](Frame::image_with_layers) but with custom
+    /// [ImageOptions]. Unlike the images of [image](Frame::image), these
+    /// images are not cached.
+    pub fn image_with_layers_and_options(
+        &self,
+        filter: &LayerFilter<'_>,
+        options: &ImageOptions,
+    ) -> RgbaImage {
+        self.file
+            .composite_layers(self.index as u16, options, |layer| {
+                filter.selects(layer).then(LayerStyle::default)
+            })
+    }
+
+    #[cfg(feature = "image")]
+    /// Like [image](Frame::image) but draws layers as changed by the
+    /// [CompositeOptions], e.g., with a different opacity or a tint. Unlike
+    /// the images of [image](Frame::image), these images are not cached.
+    pub fn image_with_composite_options(&self, options: &CompositeOptions) -> RgbaImage {
+        self.file
+            .composite_layers(self.index as u16, &options.image_options, |layer| {
+                Some(options.layer_style(layer.id()))
+            })
+    }
+
+    #[cfg(feature = "image")]
+    /// Composite the layers inside the group `layer_id` into one image,
+    /// e.g., to export a piece of equipment that is drawn in its own group.
+    /// The image is the same as [Frame::image] with only this group visible:
+    /// Hidden layers and reference layers inside the group are skipped.
+    ///
+    /// The visibility of the group itself is ignored, so hidden groups can
+    /// be rendered as well.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/layers_and_tags.aseprite")).unwrap();
+    /// let group = ase.layer_by_name("Group 1").unwrap();
+    /// let image = ase.frame(0).group_image(group.id());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `layer_id` is not the id of a group layer.
+    pub fn group_image(&self, layer_id: u32) -> RgbaImage {
+        assert!(
+            layer_id < self.file.num_layers()
+                && self.file.layers[layer_id].layer_type == LayerType::Group,
+            "Layer {} is not a group",
+            layer_id
+        );
+        self.file.group_image(self.index as u16, layer_id)
     }
 
     /// Get cel corresponding to the given layer in this frame.
-    pub fn layer(&self, layer_id: u32) -> Cel {
+    pub fn layer(&self, layer_id: u32) -> Cel<'_> {
         assert!(layer_id < self.file.num_layers());
         let raw_cel = self.file.framedata.cel(CelId {
             frame: self.index as u16,
@@ -410,40 +1477,123 @@ impl<'a> Frame<'a> {
     pub fn duration(&self) -> u32 {
         self.file.frame_times[self.index as usize] as u32
     }
+
+    /// The chunks of this frame that this crate does not interpret, in the
+    /// order in which they appear in the file. These are chunks of unknown
+    /// types, which are also reported as [ParseWarning::UnknownChunk], the
+    /// deprecated mask and path chunks, and cel extra chunks without a cel.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/basic-16x16.aseprite")).unwrap();
+    /// for chunk in ase.frame(0).raw_chunks() {
+    ///     println!("chunk 0x{:04x}: {} bytes", chunk.chunk_type(), chunk.data().len());
+    /// }
+    /// ```
+    pub fn raw_chunks(&self) -> impl Iterator<Item = &'a RawChunk> {
+        let frame = self.index as u16;
+        self.file
+            .raw_chunks
+            .iter()
+            .filter(move |chunk| chunk.frame == frame)
+    }
+
+    /// Count the colors of the frame's [image](Frame::image). Each color
+    /// channel is quantized to `bits_per_channel` bits, so there are at most
+    /// `2^(3 * bits_per_channel)` buckets. Fully transparent pixels are
+    /// ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits_per_channel` is not between 1 and 8.
+    #[cfg(feature = "std")]
+    pub fn color_histogram(&self, bits_per_channel: u8) -> Histogram {
+        let mut builder = HistogramBuilder::new(bits_per_channel);
+        let frame = self.index as u16;
+        if !self.file.add_indexed_colors(frame, &mut builder) {
+            for pixel in self.composite().pixels() {
+                builder.add(*pixel, 1);
+            }
+        }
+        builder.build()
+    }
+
+    /// The `n` most common colors of the frame together with the fraction of
+    /// non-transparent pixels covered by each. Similar colors are grouped
+    /// together, see [color_histogram](Frame::color_histogram). Uses 4 bits
+    /// per channel.
+    #[cfg(feature = "std")]
+    pub fn dominant_colors(&self, n: usize) -> Vec<([u8; 4], f32)> {
+        self.color_histogram(4).dominant_colors(n)
+    }
+
+    /// Render the frame's [image](Frame::image) as colored text for a
+    /// terminal, with two pixels per character. See the [ansi] module.
+    #[cfg(feature = "std")]
+    pub fn render_ansi(&self, options: &AnsiOptions) -> String {
+        ansi::render(&self.composite(), options)
+    }
 }
 
-type BlendFn = Box<dyn Fn(Color8, Color8, u8) -> Color8>;
-
-fn blend_mode_to_blend_fn(mode: BlendMode) -> BlendFn {
-    // TODO: Make these statically allocated
-    match mode {
-        BlendMode::Normal => Box::new(blend::normal),
-        BlendMode::Multiply => Box::new(blend::multiply),
-        BlendMode::Screen => Box::new(blend::screen),
-        BlendMode::Overlay => Box::new(blend::overlay),
-        BlendMode::Darken => Box::new(blend::darken),
-        BlendMode::Lighten => Box::new(blend::lighten),
-        BlendMode::ColorDodge => Box::new(blend::color_dodge),
-        BlendMode::ColorBurn => Box::new(blend::color_burn),
-        BlendMode::HardLight => Box::new(blend::hard_light),
-        BlendMode::SoftLight => Box::new(blend::soft_light),
-        BlendMode::Difference => Box::new(blend::difference),
-        BlendMode::Exclusion => Box::new(blend::exclusion),
-        BlendMode::Hue => Box::new(blend::hsl_hue),
-        BlendMode::Saturation => Box::new(blend::hsl_saturation),
-        BlendMode::Color => Box::new(blend::hsl_color),
-        BlendMode::Luminosity => Box::new(blend::hsl_luminosity),
-        BlendMode::Addition => Box::new(blend::addition),
-        BlendMode::Subtract => Box::new(blend::subtract),
-        BlendMode::Divide => Box::new(blend::divide),
+pub(crate) fn premultiply_alpha(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let alpha = pixel.0[3] as i32;
+        for channel in &mut pixel.0[..3] {
+            *channel = blend::mul_un8(*channel as i32, alpha);
+        }
     }
 }
 
-fn tile_slice<'a, T>(pixels: &'a [T], tile_size: &TileSize, tile_id: &TileId) -> &'a [T] {
-    let pixels_per_tile = tile_size.pixels_per_tile() as usize;
-    let start = pixels_per_tile * (tile_id.0 as usize);
-    let end = start + pixels_per_tile;
-    &pixels[start..end]
+// Multiplies the colors with `tint`, if there is one.
+fn tint_pixels(pixels: Cow<'_, [Rgba<u8>]>, tint: Option<[u8; 3]>) -> Cow<'_, [Rgba<u8>]> {
+    let tint = match tint {
+        Some(tint) => tint,
+        None => return pixels,
+    };
+    let tinted = pixels
+        .iter()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            let multiply = |value: u8, tint: u8| blend::mul_un8(value as i32, tint as i32);
+            Rgba([
+                multiply(r, tint[0]),
+                multiply(g, tint[1]),
+                multiply(b, tint[2]),
+                a,
+            ])
+        })
+        .collect();
+    Cow::Owned(tinted)
+}
+
+// Blends cels with one of Aseprite's blend modes, or with a custom function
+// from the CompositeOptions.
+enum Blender<'a> {
+    // The blend mode is only needed for the SIMD implementations.
+    #[cfg_attr(not(feature = "simd"), allow(dead_code))]
+    Mode(BlendMode, blend::BlendFn),
+    Custom(&'a CustomBlendFn),
+}
+
+impl Blender<'_> {
+    fn mode(blend_mode: BlendMode) -> Self {
+        Blender::Mode(blend_mode, blend::blend_fn(blend_mode))
+    }
+
+    fn blend(&self, backdrop: Color8, src: Color8, opacity: u8) -> Color8 {
+        match self {
+            Blender::Mode(_, blend_fn) => blend_fn(backdrop, src, opacity),
+            Blender::Custom(blend_fn) => blend_fn(backdrop, src, opacity),
+        }
+    }
+}
+
+// Missing tiles are drawn as tile 0. Returns `None` if the tileset has no
+// tiles at all.
+fn tile_slice<'a, T>(pixels: &'a [T], tileset: &Tileset, tile_id: &TileId) -> Option<&'a [T]> {
+    let pixels_per_tile = tileset.tile_size().pixels_per_tile() as usize;
+    let start = pixels_per_tile * (tileset.drawn_tile_id(tile_id.0) as usize);
+    pixels.get(start..start + pixels_per_tile)
 }
 
 fn write_tilemap_cel_to_image(
@@ -452,29 +1602,32 @@ fn write_tilemap_cel_to_image(
     tilemap_data: &Tilemap,
     tileset: &Tileset,
     pixels: &[Rgba<u8>],
-    blend_mode: &BlendMode,
+    blender: &Blender<'_>,
+    opacity: u8,
 ) {
-    let CelData { x, y, opacity, .. } = cel_data;
-    let cel_x = *x as i32;
-    let cel_y = *y as i32;
+    let CelData { x, y, .. } = cel_data;
+    // Tilemaps can be much larger than the canvas, so pixel positions are
+    // computed as i64 to rule out overflows.
+    let cel_x = *x as i64;
+    let cel_y = *y as i64;
     // tilemap dimensions
-    let tilemap_width = tilemap_data.width as i32;
-    let tilemap_height = tilemap_data.height as i32;
+    let tilemap_width = tilemap_data.width as i64;
+    let tilemap_height = tilemap_data.height as i64;
     let tiles = &tilemap_data.tiles;
     // tile dimensions
     let tile_size = tileset.tile_size();
-    let tile_width = *tile_size.width() as i32;
-    let tile_height = *tile_size.height() as i32;
-    // pixels
-    let blend_fn = blend_mode_to_blend_fn(*blend_mode);
-
+    let tile_width = *tile_size.width() as i64;
+    let tile_height = *tile_size.height() as i64;
     for tile_y in 0..tilemap_height {
         for tile_x in 0..tilemap_width {
             // TODO: support tile transform flags
             let tilemap_tile_idx = (tile_x + (tile_y * tilemap_width)) as usize;
             let tile = &tiles[tilemap_tile_idx];
             let tile_id = &tile.id;
-            let tile_pixels = tile_slice(&pixels, tile_size, tile_id);
+            let tile_pixels = match tile_slice(pixels, tileset, tile_id) {
+                Some(tile_pixels) => tile_pixels,
+                None => continue,
+            };
             for pixel_y in 0..tile_height {
                 for pixel_x in 0..tile_width {
                     let pixel_idx = ((pixel_y * tile_width) + pixel_x) as usize;
@@ -482,13 +1635,13 @@ fn write_tilemap_cel_to_image(
                     let image_x = (tile_x * tile_width) + pixel_x + cel_x;
                     let image_y = (tile_y * tile_height) + pixel_y + cel_y;
                     // Skip pixels off of the canvas.
-                    let x_in_bounds = (0..(image.width() as i32)).contains(&image_x);
-                    let y_in_bounds = (0..(image.height() as i32)).contains(&image_y);
+                    let x_in_bounds = (0..(image.width() as i64)).contains(&image_x);
+                    let y_in_bounds = (0..(image.height() as i64)).contains(&image_y);
                     if x_in_bounds && y_in_bounds {
                         let image_x = image_x as u32;
                         let image_y = image_y as u32;
                         let src = *image.get_pixel(image_x, image_y);
-                        let new = blend_fn(src, image_pixel, *opacity);
+                        let new = blender.blend(src, image_pixel, opacity);
                         image.put_pixel(image_x, image_y, new);
                     }
                 }
@@ -502,30 +1655,49 @@ fn write_raw_cel_to_image(
     cel_data: &CelData,
     image_size: &ImageSize,
     pixels: &[Rgba<u8>],
-    blend_mode: &BlendMode,
+    blender: &Blender<'_>,
+    opacity: u8,
 ) {
     let ImageSize { width, height } = image_size;
-    let CelData { x, y, opacity, .. } = cel_data;
-    let blend_fn = blend_mode_to_blend_fn(*blend_mode);
+    let CelData { x, y, .. } = cel_data;
     let x0 = *x as i32;
     let y0 = *y as i32;
     let x_end = x0 + (*width as i32);
     let y_end = y0 + (*height as i32);
     let (img_width, img_height) = image.dimensions();
+    // The columns of the cel that are on the canvas.
+    let x_start = x0.max(0);
+    let x_stop = x_end.min(img_width as i32);
+    if x_start >= x_stop {
+        return;
+    }
+    let row_len = (x_stop - x_start) as usize;
+    let bytes: &mut [u8] = image;
 
-    for y in y0..y_end {
-        if y < 0 || y >= img_height as i32 {
-            continue;
-        }
-        for x in x0..x_end {
-            if x < 0 || x >= img_width as i32 {
-                continue;
-            }
-            let idx = (y - y0) as usize * *width as usize + (x - x0) as usize;
-            let image_pixel = pixels[idx];
-            let src = *image.get_pixel(x as u32, y as u32);
-            let new = blend_fn(src, image_pixel, *opacity);
-            image.put_pixel(x as u32, y as u32, new);
-        }
+    for y in y0.max(0)..y_end.min(img_height as i32) {
+        let start = (y - y0) as usize * *width as usize + (x_start - x0) as usize;
+        let offset = (y as usize * img_width as usize + x_start as usize) * 4;
+        blend_row(
+            blender,
+            &mut bytes[offset..offset + row_len * 4],
+            &pixels[start..start + row_len],
+            opacity,
+        );
+    }
+}
+
+// Blends `src` onto `backdrop`, which holds the RGBA bytes of as many pixels.
+#[cfg_attr(not(feature = "simd"), allow(unused_variables))]
+fn blend_row(blender: &Blender<'_>, backdrop: &mut [u8], src: &[Rgba<u8>], opacity: u8) {
+    #[cfg(feature = "simd")]
+    let done = match blender {
+        Blender::Mode(blend_mode, _) => crate::simd::blend_row(*blend_mode, backdrop, src, opacity),
+        Blender::Custom(_) => 0,
+    };
+    #[cfg(not(feature = "simd"))]
+    let done = 0;
+    for (back, src) in backdrop[done * 4..].chunks_exact_mut(4).zip(&src[done..]) {
+        let new = blender.blend(Rgba([back[0], back[1], back[2], back[3]]), *src, opacity);
+        back.copy_from_slice(&new.0);
     }
 }
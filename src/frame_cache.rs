@@ -0,0 +1,99 @@
+use crate::image::RgbaImage;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+
+// The most recently composited frame images of a file, see
+// `AsepriteFile::set_frame_cache_capacity`. Entries are kept in the order of
+// their last use and looked up linearly, which is fast enough for the few
+// dozen frames of a typical animation.
+#[derive(Default)]
+pub(crate) struct FrameCache {
+    capacity: usize,
+    entries: Mutex<VecDeque<(CacheKey, RgbaImage)>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CacheKey {
+    pub frame: u16,
+    pub include_hidden_layers: bool,
+    pub include_reference_layers: bool,
+    pub convert_to_srgb: bool,
+    pub apply_pixel_ratio: bool,
+    pub background: Option<[u8; 4]>,
+    pub premultiply_alpha: bool,
+}
+
+impl FrameCache {
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // The number of cached images.
+    pub(crate) fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    // Drops the least recently used images that no longer fit.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let entries = self
+            .entries
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+        entries.shrink_to_fit();
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    // The cached image for `key`, or the result of `composite`, which is then
+    // cached. The lock is not held while compositing, so other threads can
+    // use the cache in the meantime.
+    pub(crate) fn get_or_insert<F>(&self, key: CacheKey, composite: F) -> RgbaImage
+    where
+        F: FnOnce() -> RgbaImage,
+    {
+        if self.capacity == 0 {
+            return composite();
+        }
+        {
+            let mut entries = self.lock();
+            if let Some(position) = entries.iter().position(|(k, _)| *k == key) {
+                let entry = entries.remove(position).expect("Entry exists");
+                let image = entry.1.clone();
+                entries.push_back(entry);
+                return image;
+            }
+        }
+        let image = composite();
+        let mut entries = self.lock();
+        if !entries.iter().any(|(k, _)| *k == key) {
+            if entries.len() == self.capacity {
+                entries.pop_front();
+            }
+            entries.push_back((key, image.clone()));
+        }
+        image
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<(CacheKey, RgbaImage)>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for FrameCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.len())
+            .finish()
+    }
+}
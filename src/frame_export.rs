@@ -0,0 +1,289 @@
+use crate::{png, AsepriteFile, Tag};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Options for [AsepriteFile::export_frames_with_options].
+///
+/// ```
+/// # use asefile::FrameExportOptions;
+/// let options = FrameExportOptions::new().title("player").tag(0);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FrameExportOptions {
+    title: Option<String>,
+    tag: Option<u32>,
+    indexed: bool,
+}
+
+impl FrameExportOptions {
+    /// Default options: Export all frames, without a title.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value of the `{title}` placeholder. Aseprite uses the file name
+    /// without its extension.
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_owned());
+        self
+    }
+
+    /// Only export the frames of the tag with the given id.
+    pub fn tag(mut self, tag_id: u32) -> Self {
+        self.tag = Some(tag_id);
+        self
+    }
+
+    /// Write 8-bit paletted PNGs with the sprite's palette, see
+    /// [Frame::write_indexed_png](crate::Frame::write_indexed_png). Only
+    /// works for indexed sprites.
+    pub fn indexed(mut self, indexed: bool) -> Self {
+        self.indexed = indexed;
+        self
+    }
+}
+
+/// # Exporting frames
+impl AsepriteFile {
+    /// Save each frame as a PNG file in `dir`, which is created if necessary.
+    /// The file names are generated from the `pattern`, which may contain
+    /// the following placeholders of the Aseprite CLI's `--filename-format`:
+    ///
+    /// - `{title}`: The title set with [FrameExportOptions::title].
+    /// - `{tag}`: The name of the exported tag. When exporting all frames,
+    ///   the name of the first tag that contains the frame, or nothing.
+    /// - `{frame}`: The frame number, starting at 0.
+    /// - `{tagframe}`: The frame number relative to the first frame of the
+    ///   tag in `{tag}`.
+    /// - `{duration}`: The frame duration in milliseconds.
+    ///
+    /// As in Aseprite, digits after `frame` or `tagframe` set the first
+    /// number and the minimum width: `{frame01}` numbers the frames `01`,
+    /// `02`, and so on. If the pattern contains no frame number but several
+    /// frames are exported, `{frame1}` is inserted before the file extension.
+    /// The pattern may contain `/` to write into subdirectories.
+    ///
+    /// Returns the paths of the written files, in frame order.
+    ///
+    /// ```no_run
+    /// # use asefile::AsepriteFile;
+    /// # use std::path::Path;
+    /// # let path = Path::new("./tests/data/layers_and_tags.aseprite");
+    /// let ase = AsepriteFile::read_file(&path).unwrap();
+    /// let paths = ase
+    ///     .export_frames(Path::new("frames"), "{tag}_{frame01}.png")
+    ///     .unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails with [io::ErrorKind::InvalidInput] if the pattern contains an
+    /// unknown placeholder, uses `{title}` without a title, or produces the
+    /// same file name for two frames, or if the tag does not exist. With
+    /// [FrameExportOptions::indexed], also fails like
+    /// [Frame::write_indexed_png](crate::Frame::write_indexed_png). Also
+    /// returns any error of creating directories or writing files.
+    pub fn export_frames(&self, dir: &Path, pattern: &str) -> io::Result<Vec<PathBuf>> {
+        self.export_frames_with_options(dir, pattern, &FrameExportOptions::new())
+    }
+
+    /// Like [export_frames](AsepriteFile::export_frames) but with custom
+    /// [FrameExportOptions].
+    pub fn export_frames_with_options(
+        &self,
+        dir: &Path,
+        pattern: &str,
+        options: &FrameExportOptions,
+    ) -> io::Result<Vec<PathBuf>> {
+        let frames: Vec<u32> = match options.tag {
+            Some(tag_id) if tag_id >= self.num_tags() => {
+                return Err(invalid_input(format!("Tag does not exist: {}", tag_id)))
+            }
+            Some(tag_id) => {
+                let tag = self.tag(tag_id);
+                (tag.from_frame()..=tag.to_frame()).collect()
+            }
+            None => (0..self.num_frames()).collect(),
+        };
+        let mut pattern = FileNamePattern::parse(pattern)?;
+        if !pattern.has_frame_number() && frames.len() > 1 {
+            pattern.add_frame_number();
+        }
+
+        let mut paths = Vec::with_capacity(frames.len());
+        let mut unique = HashSet::new();
+        for &frame in &frames {
+            let tag = match options.tag {
+                Some(tag_id) => Some(self.tag(tag_id)),
+                None => first_tag_containing(self, frame),
+            };
+            let name = pattern.render(self, frame, tag, options.title.as_deref())?;
+            if !unique.insert(name.clone()) {
+                return Err(invalid_input(format!(
+                    "File name pattern produces {} more than once",
+                    name
+                )));
+            }
+            paths.push(dir.join(name));
+        }
+
+        for (&frame, path) in frames.iter().zip(&paths) {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            if options.indexed {
+                self.frame(frame).save_indexed_png(path)?;
+            } else {
+                std::fs::write(path, png::encode(&self.frame(frame).composite()))?;
+            }
+        }
+        Ok(paths)
+    }
+}
+
+// The first tag whose range includes the frame.
+pub(crate) fn first_tag_containing(file: &AsepriteFile, frame: u32) -> Option<&Tag> {
+    (0..file.num_tags())
+        .map(|tag_id| file.tag(tag_id))
+        .find(|tag| tag.from_frame() <= frame && frame <= tag.to_frame())
+}
+
+// A file name with placeholders, see AsepriteFile::export_frames.
+#[derive(Debug)]
+pub(crate) struct FileNamePattern {
+    parts: Vec<Part>,
+}
+
+impl FileNamePattern {
+    pub(crate) fn parse(pattern: &str) -> io::Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = pattern;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Literal(rest[..open].to_owned()));
+            }
+            let close = rest[open..].find('}').ok_or_else(|| {
+                invalid_input(format!(
+                    "Unclosed placeholder in file name pattern: {}",
+                    pattern
+                ))
+            })?;
+            let placeholder = &rest[open + 1..open + close];
+            parts.push(parse_placeholder(placeholder).ok_or_else(|| {
+                invalid_input(format!(
+                    "Unknown placeholder {{{}}} in file name pattern",
+                    placeholder
+                ))
+            })?);
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_owned()));
+        }
+        Ok(Self { parts })
+    }
+
+    pub(crate) fn has_frame_number(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Frame { .. } | Part::TagFrame { .. }))
+    }
+
+    // Inserts `{frame1}` before the file extension, like Aseprite does.
+    pub(crate) fn add_frame_number(&mut self) {
+        let parts = &mut self.parts;
+        let extension = match parts.last() {
+            Some(Part::Literal(text)) => text
+                .rfind('.')
+                .filter(|&dot| !text[dot..].contains('/'))
+                .map(|dot| (text.clone(), dot)),
+            _ => None,
+        };
+        let number = Part::Frame { start: 1, width: 1 };
+        match extension {
+            Some((text, dot)) => {
+                parts.pop();
+                parts.push(Part::Literal(text[..dot].to_owned()));
+                parts.push(number);
+                parts.push(Part::Literal(text[dot..].to_owned()));
+            }
+            None => parts.push(number),
+        }
+    }
+
+    pub(crate) fn render(
+        &self,
+        file: &AsepriteFile,
+        frame: u32,
+        tag: Option<&Tag>,
+        title: Option<&str>,
+    ) -> io::Result<String> {
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => name.push_str(text),
+                Part::Title => match title {
+                    Some(title) => name.push_str(title),
+                    None => {
+                        return Err(invalid_input(
+                            "File name pattern uses {title}, but no title was set".to_owned(),
+                        ))
+                    }
+                },
+                Part::Tag => name.push_str(tag.map_or("", |tag| tag.name())),
+                Part::Frame { start, width } => {
+                    name.push_str(&format!("{:0width$}", start + frame, width = width))
+                }
+                Part::TagFrame { start, width } => {
+                    let first = tag.map_or(0, |tag| tag.from_frame());
+                    let number = start + frame - first;
+                    name.push_str(&format!("{:0width$}", number, width = width))
+                }
+                Part::Duration => name.push_str(&file.frame(frame).duration().to_string()),
+            }
+        }
+        Ok(name)
+    }
+}
+
+#[derive(Debug)]
+enum Part {
+    Literal(String),
+    Title,
+    Tag,
+    Frame { start: u32, width: usize },
+    TagFrame { start: u32, width: usize },
+    Duration,
+}
+
+fn parse_placeholder(placeholder: &str) -> Option<Part> {
+    // Digits after a frame number placeholder, e.g. "01" in "{frame01}".
+    let number = |digits: &str| -> Option<(u32, usize)> {
+        if digits.is_empty() {
+            return Some((0, 1));
+        }
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Some((digits.parse().ok()?, digits.len()))
+    };
+    match placeholder {
+        "title" => Some(Part::Title),
+        "tag" => Some(Part::Tag),
+        "duration" => Some(Part::Duration),
+        _ => {
+            if let Some(digits) = placeholder.strip_prefix("tagframe") {
+                number(digits).map(|(start, width)| Part::TagFrame { start, width })
+            } else if let Some(digits) = placeholder.strip_prefix("frame") {
+                number(digits).map(|(start, width)| Part::Frame { start, width })
+            } else {
+                None
+            }
+        }
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
@@ -0,0 +1,66 @@
+/*!
+Load sprites from asynchronous readers.
+
+Requires the `futures` feature. [AsepriteFile::read_async] accepts any
+[AsyncRead] of the `futures-io` crate, so an asset server can load a sprite
+without blocking a thread while it waits for the data:
+
+```
+# use asefile::AsepriteFile;
+# async fn load() -> asefile::Result<AsepriteFile> {
+# let data = include_bytes!("../tests/data/basic-16x16.aseprite");
+# let reader = &data[..];
+let ase = AsepriteFile::read_async(reader).await?;
+# Ok(ase)
+# }
+```
+
+For Tokio readers, convert them with `compat()` from
+[tokio-util](https://docs.rs/tokio-util/latest/tokio_util/compat/index.html).
+
+The input is read into memory asynchronously and then parsed in one go, like
+[AsepriteFile::read]. Parsing does not wait for anything, but it takes a
+while for large files. Move it to a blocking thread pool if that matters.
+*/
+use crate::{AsepriteFile, ReadOptions, Result};
+use futures_io::AsyncRead;
+use std::{
+    future::poll_fn,
+    io::{self, ErrorKind},
+    pin::Pin,
+};
+
+impl AsepriteFile {
+    /// Load Aseprite file from any input that implements [AsyncRead]. See the
+    /// [futures](crate::futures) module.
+    ///
+    /// Requires the `futures` feature.
+    pub async fn read_async<R: AsyncRead + Unpin>(input: R) -> Result<AsepriteFile> {
+        Self::read_async_with_options(input, &ReadOptions::default()).await
+    }
+
+    /// Like [read_async](AsepriteFile::read_async) but with custom
+    /// [ReadOptions].
+    ///
+    /// Requires the `futures` feature.
+    pub async fn read_async_with_options<R: AsyncRead + Unpin>(
+        mut input: R,
+        options: &ReadOptions,
+    ) -> Result<AsepriteFile> {
+        let data = read_to_end(&mut input).await?;
+        Self::read_with_options(&data[..], options)
+    }
+}
+
+async fn read_to_end<R: AsyncRead + Unpin>(input: &mut R) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut chunk = [0; 8192];
+    loop {
+        match poll_fn(|cx| Pin::new(&mut *input).poll_read(cx, &mut chunk)).await {
+            Ok(0) => return Ok(data),
+            Ok(len) => data.extend_from_slice(&chunk[..len]),
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+/*!
+Use sprites in [ggez](https://ggez.rs) games.
+
+Requires the `ggez` feature. [image()] uploads a frame image to the GPU,
+keeping the RGBA byte order of the [RgbaImage] and its sRGB colors. ggez
+sets the filter when drawing rather than per image, so draw pixel art with
+the nearest-neighbor [sampler]:
+
+```no_run
+# use asefile::AsepriteFile;
+# use ggez::graphics::{Canvas, Color, DrawParam};
+# use ggez::{Context, GameResult};
+# fn draw(ctx: &mut Context, ase: &AsepriteFile) -> GameResult {
+let image = asefile::ggez::image(ctx, &ase.frame(0).image());
+let mut canvas = Canvas::from_frame(ctx, Color::BLACK);
+canvas.set_sampler(asefile::ggez::sampler());
+canvas.draw(&image, DrawParam::new().dest([10.0, 10.0]));
+canvas.finish(ctx)
+# }
+```
+
+To draw from an [Atlas], upload it once with [atlas_image] and select each
+frame with [source_rect]:
+
+```no_run
+# use asefile::atlas::Atlas;
+# use ggez::graphics::{Canvas, DrawParam, Image};
+# fn draw(canvas: &mut Canvas, atlas: &Atlas, image: &Image) {
+let frame = &atlas.frames()[0];
+let (x, y) = frame.trim_offset();
+let param = DrawParam::new()
+    .src(asefile::ggez::source_rect(atlas, frame))
+    .dest([10.0 + x as f32, 10.0 + y as f32]);
+canvas.draw(image, param);
+# }
+```
+*/
+use crate::atlas::{Atlas, AtlasFrame};
+use crate::image::RgbaImage;
+use ::ggez::context::Has;
+use ::ggez::graphics::{GraphicsContext, Image, ImageFormat, Rect, Sampler};
+
+/// Upload an image. The returned [Image] uses the
+/// [Rgba8UnormSrgb](ImageFormat::Rgba8UnormSrgb) format, which matches the
+/// pixel data of an [RgbaImage].
+pub fn image(gfx: &impl Has<GraphicsContext>, image: &RgbaImage) -> Image {
+    let (width, height) = image.dimensions();
+    Image::from_pixels(
+        gfx,
+        image.as_raw(),
+        ImageFormat::Rgba8UnormSrgb,
+        width,
+        height,
+    )
+}
+
+/// Upload the image of an atlas, see [image()].
+pub fn atlas_image(gfx: &impl Has<GraphicsContext>, atlas: &Atlas) -> Image {
+    image(gfx, atlas.image())
+}
+
+/// The part of the atlas image that contains the frame, for
+/// [DrawParam::src](::ggez::graphics::DrawParam::src). ggez measures it in
+/// fractions of the image size.
+pub fn source_rect(atlas: &Atlas, frame: &AtlasFrame) -> Rect {
+    let (width, height) = atlas.image().dimensions();
+    let (x, y, w, h) = frame.rect();
+    Rect::fraction(
+        x as f32,
+        y as f32,
+        w as f32,
+        h as f32,
+        &Rect::new(0.0, 0.0, width as f32, height as f32),
+    )
+}
+
+/// Nearest-neighbor sampling without repeating the image, which keeps pixel
+/// art sharp and doesn't sample neighboring atlas frames.
+pub fn sampler() -> Sampler {
+    Sampler::nearest_clamp()
+}
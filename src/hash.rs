@@ -0,0 +1,48 @@
+use core::hash::Hasher;
+
+// 64-bit FNV-1a. Unlike `DefaultHasher` its output does not change between
+// runs or Rust releases, which makes it suitable for hashes that users might
+// store or compare across processes.
+// See: http://www.isthe.com/chongo/tech/comp/fnv/index.html
+pub(crate) struct Fnv1a(u64);
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+impl Fnv1a {
+    pub(crate) fn new() -> Self {
+        Fnv1a(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+#[test]
+fn test_fnv1a() {
+    // Reference values from the FNV test suite.
+    let hash = |bytes: &[u8]| {
+        let mut h = Fnv1a::new();
+        h.write(bytes);
+        h.finish()
+    };
+    assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+}
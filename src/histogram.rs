@@ -0,0 +1,117 @@
+use crate::image::Rgba;
+use nohash::IntMap;
+
+/// Color statistics of an image. See [Frame::color_histogram].
+///
+/// Colors are grouped into buckets by quantizing each of the red, green, and
+/// blue channels to a number of bits. Fully transparent pixels are not
+/// counted.
+///
+/// [Frame::color_histogram]: crate::Frame::color_histogram
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    bits_per_channel: u8,
+    buckets: Vec<HistogramBucket>,
+    total: u64,
+}
+
+/// A group of similar colors in a [Histogram].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistogramBucket {
+    /// The average color (including alpha) of all pixels in this bucket,
+    /// rounded to the nearest integer.
+    pub color: [u8; 4],
+    /// Number of pixels in this bucket.
+    pub count: u64,
+}
+
+impl Histogram {
+    /// Number of bits each color channel was quantized to.
+    pub fn bits_per_channel(&self) -> u8 {
+        self.bits_per_channel
+    }
+
+    /// All non-empty buckets, sorted by descending pixel count. Buckets with
+    /// the same count are ordered by their quantized color, so the order is
+    /// deterministic.
+    pub fn buckets(&self) -> &[HistogramBucket] {
+        &self.buckets
+    }
+
+    /// Number of pixels that were counted, i.e., pixels that are not fully
+    /// transparent.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The colors of the `n` largest buckets together with the fraction of
+    /// counted pixels that fall into each bucket.
+    pub fn dominant_colors(&self, n: usize) -> Vec<([u8; 4], f32)> {
+        self.buckets
+            .iter()
+            .take(n)
+            .map(|b| (b.color, (b.count as f64 / self.total as f64) as f32))
+            .collect()
+    }
+}
+
+// Sums of all channels and the pixel count of one bucket.
+#[derive(Default)]
+struct Sums([u64; 5]);
+
+pub(crate) struct HistogramBuilder {
+    bits_per_channel: u8,
+    sums: IntMap<u32, Sums>,
+}
+
+impl HistogramBuilder {
+    pub(crate) fn new(bits_per_channel: u8) -> Self {
+        assert!(
+            (1..=8).contains(&bits_per_channel),
+            "bits_per_channel must be between 1 and 8, got {}",
+            bits_per_channel
+        );
+        Self {
+            bits_per_channel,
+            sums: IntMap::default(),
+        }
+    }
+
+    // Counts `color` as if it occurred `count` times.
+    pub(crate) fn add(&mut self, color: Rgba<u8>, count: u64) {
+        let [r, g, b, a] = color.0;
+        if a == 0 || count == 0 {
+            return;
+        }
+        let shift = 8 - self.bits_per_channel;
+        let bits = self.bits_per_channel;
+        let key = ((r >> shift) as u32) << (2 * bits)
+            | ((g >> shift) as u32) << bits
+            | (b >> shift) as u32;
+        let sums = &mut self.sums.entry(key).or_default().0;
+        for (sum, channel) in sums.iter_mut().zip(&color.0) {
+            *sum += *channel as u64 * count;
+        }
+        sums[4] += count;
+    }
+
+    pub(crate) fn build(self) -> Histogram {
+        let mut keyed: Vec<(u32, HistogramBucket)> = self
+            .sums
+            .into_iter()
+            .map(|(key, Sums(sums))| {
+                let count = sums[4];
+                let mean = |sum: u64| ((sum + count / 2) / count) as u8;
+                let color = [mean(sums[0]), mean(sums[1]), mean(sums[2]), mean(sums[3])];
+                (key, HistogramBucket { color, count })
+            })
+            .collect();
+        keyed.sort_by(|(key_a, a), (key_b, b)| b.count.cmp(&a.count).then(key_a.cmp(key_b)));
+        let total = keyed.iter().map(|(_, b)| b.count).sum();
+        Histogram {
+            bits_per_channel: self.bits_per_channel,
+            buckets: keyed.into_iter().map(|(_, b)| b).collect(),
+            total,
+        }
+    }
+}
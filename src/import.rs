@@ -0,0 +1,206 @@
+/*!
+Create sprites from a sequence of images.
+
+[from_images] turns rendered frames into a sprite with a single layer and one
+frame per image. [from_directory] does the same for a directory of numbered
+PNG files, such as `walk_1.png`, `walk_2.png`, ..., `walk_10.png`.
+
+```
+# use asefile::import::{self, ImportOptions};
+# use image::{Rgba, RgbaImage};
+let frames = vec![
+    (RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255])), 100),
+    (RgbaImage::from_pixel(8, 8, Rgba([0, 0, 255, 255])), 200),
+];
+let options = ImportOptions::new().tag("Blink");
+let ase = import::from_images_with_options(frames, &options).unwrap();
+assert_eq!(ase.num_frames(), 2);
+assert_eq!(ase.frame(1).duration(), 200);
+assert_eq!(ase.tag(0).name(), "Blink");
+```
+*/
+use crate::{AnimationDirection, AsepriteFile, AsepriteFileBuilder, AsepriteParseError, Result};
+use image::RgbaImage;
+use std::{convert::TryFrom, fs, path::Path};
+
+/// Duration of frames loaded by [from_directory], in milliseconds, unless
+/// [ImportOptions::duration] is set.
+pub const DEFAULT_FRAME_DURATION: u32 = 100;
+
+/// Options for [from_images_with_options] and [from_directory].
+///
+/// ```
+/// # use asefile::import::ImportOptions;
+/// let options = ImportOptions::new().canvas_size(64, 64).duration(80);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    canvas_size: Option<(u16, u16)>,
+    duration: Option<u32>,
+    tag: Option<String>,
+}
+
+impl ImportOptions {
+    /// Default options: The canvas has the size of the images, which must
+    /// all be the same. Frames keep their own durations, and no tag is
+    /// created.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a fixed canvas size. Images of a different size are centered on
+    /// the canvas; parts of larger images that lie outside the canvas are
+    /// kept in the cel but not visible.
+    pub fn canvas_size(mut self, width: u16, height: u16) -> Self {
+        self.canvas_size = Some((width, height));
+        self
+    }
+
+    /// Give all frames the same duration in milliseconds.
+    pub fn duration(mut self, duration_ms: u32) -> Self {
+        self.duration = Some(duration_ms);
+        self
+    }
+
+    /// Add a tag with the given name that spans all frames.
+    pub fn tag(mut self, name: &str) -> Self {
+        self.tag = Some(name.to_owned());
+        self
+    }
+}
+
+/// Create a sprite with one layer and one frame per image. Each image is
+/// paired with the frame duration in milliseconds.
+///
+/// # Errors
+///
+/// Returns [AsepriteParseError::InvalidInput] if there are no images, the
+/// images differ in size, an image is larger than 65535 pixels in either
+/// direction, or a duration is longer than 65535 milliseconds.
+pub fn from_images<I>(images: I) -> Result<AsepriteFile>
+where
+    I: IntoIterator<Item = (RgbaImage, u32)>,
+{
+    from_images_with_options(images, &ImportOptions::default())
+}
+
+/// Like [from_images] but with [ImportOptions]. If a canvas size is set,
+/// the images may differ in size.
+///
+/// # Errors
+///
+/// See [from_images].
+pub fn from_images_with_options<I>(images: I, options: &ImportOptions) -> Result<AsepriteFile>
+where
+    I: IntoIterator<Item = (RgbaImage, u32)>,
+{
+    let mut images = images.into_iter().peekable();
+    let (width, height) = match (options.canvas_size, images.peek()) {
+        (Some(size), _) => size,
+        (None, Some((image, _))) => {
+            let (width, height) = image.dimensions();
+            match (u16::try_from(width), u16::try_from(height)) {
+                (Ok(width), Ok(height)) => (width, height),
+                _ => {
+                    return Err(AsepriteParseError::InvalidInput(format!(
+                        "Image too large: {}x{}",
+                        width, height
+                    )))
+                }
+            }
+        }
+        (None, None) => {
+            return Err(AsepriteParseError::InvalidInput(
+                "No images to import".into(),
+            ))
+        }
+    };
+
+    let mut builder = AsepriteFileBuilder::new(width, height);
+    let layer = builder.add_layer("Layer 1");
+    let mut last_frame = 0;
+    for (index, (image, duration)) in images.enumerate() {
+        let duration = options.duration.unwrap_or(duration);
+        let duration = u16::try_from(duration).map_err(|_| {
+            AsepriteParseError::InvalidInput(format!(
+                "Duration of frame {} too long: {}ms",
+                index, duration
+            ))
+        })?;
+        let (image_width, image_height) = image.dimensions();
+        if options.canvas_size.is_none()
+            && (image_width, image_height) != (width as u32, height as u32)
+        {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Image {} has size {}x{}, expected {}x{}",
+                index, image_width, image_height, width, height
+            )));
+        }
+        let x = (width as i64 - image_width as i64) / 2;
+        let y = (height as i64 - image_height as i64) / 2;
+        let origin = match (i16::try_from(x), i16::try_from(y)) {
+            (Ok(x), Ok(y)) => (x, y),
+            _ => {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Image {} too large: {}x{}",
+                    index, image_width, image_height
+                )))
+            }
+        };
+        last_frame = builder.add_frame(duration);
+        builder.add_cel(last_frame, layer, origin, &image);
+    }
+    if let Some(name) = &options.tag {
+        builder.add_tag(name, 0, last_frame as u16, AnimationDirection::Forward);
+    }
+    builder.build()
+}
+
+/// Create a sprite from all PNG files in a directory, one frame per file.
+/// Files are sorted by name, except that a number at the end of the name is
+/// compared by its value, so `frame_2.png` comes before `frame_10.png`.
+/// Other files are ignored.
+///
+/// Frames are [DEFAULT_FRAME_DURATION] milliseconds long unless the options
+/// set a duration.
+///
+/// # Errors
+///
+/// Returns [AsepriteParseError::IoError] if the directory or a file cannot be
+/// read, and [AsepriteParseError::InvalidInput] if a file is not a valid PNG
+/// image. Otherwise fails like [from_images].
+pub fn from_directory<P: AsRef<Path>>(dir: P, options: &ImportOptions) -> Result<AsepriteFile> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_png = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+        if is_png && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort_by_cached_key(|path| {
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+        let number: Option<u64> = stem[prefix.len()..].parse().ok();
+        (prefix.to_owned(), number, path.clone())
+    });
+
+    let mut images = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let image = image::open(path).map_err(|err| match err {
+            image::ImageError::IoError(err) => AsepriteParseError::IoError(err),
+            err => AsepriteParseError::InvalidInput(format!(
+                "Could not decode {}: {}",
+                path.display(),
+                err
+            )),
+        })?;
+        images.push((image.to_rgba8(), DEFAULT_FRAME_DURATION));
+    }
+    from_images_with_options(images, options)
+}
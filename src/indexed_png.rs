@@ -0,0 +1,131 @@
+use crate::png::{self, push_chunk};
+use crate::{Frame, PixelFormat};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+
+const COLOR_TYPE_INDEXED: u8 = 3;
+// Filtering rarely helps palette indices, the PNG specification recommends
+// not to filter them.
+const FILTER_NONE: u8 = 0;
+
+/// # Indexed PNG export
+impl<'a> Frame<'a> {
+    /// Write the frame's [image](Frame::image) as an 8-bit paletted PNG
+    /// with the sprite's [palette](crate::AsepriteFile::palette). Unlike the
+    /// RGBA image, the PNG keeps the palette entries in their order, so
+    /// tools that work with palette indices see the same colors as Aseprite.
+    ///
+    /// Fully transparent pixels use the transparent color index of the
+    /// sprite. All other pixels use the first palette entry with their
+    /// color.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # use std::path::Path;
+    /// # let path = Path::new("./tests/data/indexed.aseprite");
+    /// let ase = AsepriteFile::read_file(&path).unwrap();
+    /// let mut data = Vec::new();
+    /// ase.frame(0).write_indexed_png(&mut data).unwrap();
+    /// // std::fs::write("frame.png", data).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails with [io::ErrorKind::InvalidInput] if the sprite does not use
+    /// [PixelFormat::Indexed], or if blending layers produced a color that
+    /// is not in the palette, e.g. because of layer opacity. Also returns any
+    /// error of the underlying writer.
+    pub fn write_indexed_png<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let transparent_index = match self.file.pixel_format() {
+            PixelFormat::Indexed {
+                transparent_color_index,
+            } => transparent_color_index,
+            _ => return Err(invalid_input("Sprite is not indexed".to_owned())),
+        };
+        let palette = self
+            .file
+            .palette()
+            .ok_or_else(|| invalid_input("Sprite has no palette".to_owned()))?;
+        // Indices are 8 bits wide, later entries are unreachable anyway. The
+        // palette always contains the transparent index, even if the sprite's
+        // palette is shorter.
+        let num_colors = palette.num_colors().max(transparent_index as u32 + 1);
+        let colors: Vec<[u8; 4]> = (0..num_colors.min(256))
+            .map(|index| {
+                palette
+                    .color(index)
+                    .map_or([0, 0, 0, 255], |entry| entry.raw_rgba8())
+            })
+            .collect();
+        // The transparent index stands for transparent pixels only.
+        let mut indices = HashMap::new();
+        for (index, color) in colors.iter().enumerate().rev() {
+            if index != transparent_index as usize {
+                indices.insert(*color, index as u8);
+            }
+        }
+
+        let image = self.composite();
+        let (width, height) = image.dimensions();
+        let mut filtered = Vec::with_capacity((width as usize + 1) * height as usize);
+        for y in 0..height {
+            filtered.push(FILTER_NONE);
+            for x in 0..width {
+                let pixel = image.get_pixel(x, y).0;
+                let index = if pixel[3] == 0 {
+                    transparent_index
+                } else {
+                    *indices.get(&pixel).ok_or_else(|| {
+                        invalid_input(format!(
+                            "Color at ({}, {}) of frame {} is not in the palette",
+                            x, y, self.index
+                        ))
+                    })?
+                };
+                filtered.push(index);
+            }
+        }
+
+        let mut plte = Vec::with_capacity(colors.len() * 3);
+        let mut trns = Vec::with_capacity(colors.len());
+        for (index, color) in colors.iter().enumerate() {
+            plte.extend_from_slice(&color[..3]);
+            trns.push(if index == transparent_index as usize {
+                0
+            } else {
+                color[3]
+            });
+        }
+        // Entries without an alpha value are opaque.
+        while trns.last() == Some(&255) {
+            trns.pop();
+        }
+
+        let mut data = png::SIGNATURE.to_vec();
+        push_chunk(
+            &mut data,
+            b"IHDR",
+            &png::header(width, height, COLOR_TYPE_INDEXED),
+        );
+        push_chunk(&mut data, b"PLTE", &plte);
+        if !trns.is_empty() {
+            push_chunk(&mut data, b"tRNS", &trns);
+        }
+        push_chunk(&mut data, b"IDAT", &png::compress(&filtered));
+        push_chunk(&mut data, b"IEND", &[]);
+        w.write_all(&data)
+    }
+
+    /// Save the frame as an 8-bit paletted PNG file, see
+    /// [write_indexed_png](Frame::write_indexed_png).
+    pub fn save_indexed_png(&self, path: &Path) -> io::Result<()> {
+        let mut data = Vec::new();
+        self.write_indexed_png(&mut data)?;
+        std::fs::write(path, data)
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
@@ -0,0 +1,80 @@
+// Input for builds without the `std` feature: the subset of `std::io::Read`
+// that the parser uses, implemented for byte slices. The trait is not exported,
+// so that enabling `std` does not replace a public type; without `std`, sprites
+// are read from byte slices.
+use crate::{AsepriteParseError, Result};
+use alloc::{borrow::ToOwned, vec::Vec};
+
+/// A source of bytes, like `std::io::Read`.
+pub trait Read {
+    /// Read up to `buf.len()` bytes and return their number. Returns 0 at the
+    /// end of the input.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Fill the whole buffer. Fails if the input ends too early.
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.read(buf)? {
+                0 => return Err(unexpected_end()),
+                len => buf = &mut buf[len..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Append all remaining bytes to `buf` and return their number.
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let start = buf.len();
+        let mut chunk = [0; 4096];
+        loop {
+            match self.read(&mut chunk)? {
+                0 => return Ok(buf.len() - start),
+                len => buf.extend_from_slice(&chunk[..len]),
+            }
+        }
+    }
+
+    /// An adapter that reads at most `limit` bytes.
+    fn take(self, limit: u64) -> Take<Self>
+    where
+        Self: Sized,
+    {
+        Take { inner: self, limit }
+    }
+}
+
+impl Read for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = buf.len().min(self.len());
+        let (head, tail) = self.split_at(len);
+        buf[..len].copy_from_slice(head);
+        *self = tail;
+        Ok(len)
+    }
+}
+
+impl<R: Read + ?Sized> Read for &mut R {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read(buf)
+    }
+}
+
+/// Reads at most a given number of bytes, see [Read::take].
+#[derive(Debug)]
+pub struct Take<R> {
+    inner: R,
+    limit: u64,
+}
+
+impl<R: Read> Read for Take<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let max = (buf.len() as u64).min(self.limit) as usize;
+        let len = self.inner.read(&mut buf[..max])?;
+        self.limit -= len as u64;
+        Ok(len)
+    }
+}
+
+pub(crate) fn unexpected_end() -> AsepriteParseError {
+    AsepriteParseError::InvalidInput("Unexpected end of input".to_owned())
+}
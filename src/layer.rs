@@ -1,3 +1,4 @@
+use crate::io::Read;
 use crate::{
     cel::{Cel, CelId},
     reader::AseReader,
@@ -5,11 +6,13 @@ use crate::{
     user_data::UserData,
     AsepriteFile, AsepriteParseError, Result,
 };
+use alloc::{borrow::ToOwned, format, string::String, vec::Vec};
 use bitflags::bitflags;
-use std::{io::Read, ops::Index};
+use core::ops::Index;
 
 /// Types of layer.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LayerType {
     /// A regular image layer. This is the normal layer type.
     Image,
@@ -26,6 +29,11 @@ bitflags! {
     ///
     /// For checking whether a layer is visible prefer to use [Layer::is_visible]
     /// as that also takes into account any parent layer's visibility.
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
     pub struct LayerFlags: u32 {
         /// Layer is visible (eye icon is enabled).
         const VISIBLE = 0x0001;
@@ -95,13 +103,49 @@ impl<'a> Layer<'a> {
     ///
     /// Does not indicate the blend order of layers (i.e., which layers are
     /// above or below).
-    pub fn parent(&self) -> Option<Layer> {
+    pub fn parent(&self) -> Option<Layer<'_>> {
         self.file.layers.parents[self.layer_id as usize].map(|id| Layer {
             file: self.file,
             layer_id: id,
         })
     }
 
+    /// The layers directly inside this group, from bottom to top. Empty for
+    /// layers that are not groups. Layers inside of the children are not
+    /// included, but can be reached through the children's children.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/layers_and_tags.aseprite")).unwrap();
+    /// fn print_tree(layer: asefile::Layer, depth: usize) {
+    ///     println!("{}{}", "  ".repeat(depth), layer.name());
+    ///     for child in layer.children() {
+    ///         print_tree(child, depth + 1);
+    ///     }
+    /// }
+    /// for layer in ase.root_layers() {
+    ///     print_tree(layer, 0);
+    /// }
+    /// ```
+    pub fn children(&self) -> impl Iterator<Item = Layer<'a>> + 'a {
+        let file = self.file;
+        file.layers
+            .children_of(Some(self.layer_id))
+            .map(move |layer_id| Layer { file, layer_id })
+    }
+
+    /// The names of all parent groups and this layer, separated by `/`, e.g.,
+    /// `"Body/Arm"`. Layers at the top level have a path equal to their name.
+    ///
+    /// Layer names may contain `/` themselves, so the path is not always
+    /// unambiguous.
+    pub fn path(&self) -> String {
+        match self.parent() {
+            Some(parent) => format!("{}/{}", parent.path(), self.name()),
+            None => self.name().to_owned(),
+        }
+    }
+
     /// Returns if this layer is visible. This requires that this layer and all
     /// of its parent layers are visible.
     pub fn is_visible(&self) -> bool {
@@ -110,8 +154,18 @@ impl<'a> Layer<'a> {
         layer_is_visible && parent_is_visible
     }
 
+    /// Returns if this is a reference layer, i.e., an image that was imported
+    /// as a guide for drawing. Reference layers are regular image layers with
+    /// the [LayerFlags::REFERENCE] flag. They are not part of frame images
+    /// unless
+    /// [ImageOptions::include_reference_layers](crate::ImageOptions::include_reference_layers)
+    /// is set.
+    pub fn is_reference(&self) -> bool {
+        self.data().flags.contains(LayerFlags::REFERENCE)
+    }
+
     /// Get a reference to the Cel for this frame in the layer.
-    pub fn frame(&self, frame_id: u32) -> Cel {
+    pub fn frame(&self, frame_id: u32) -> Cel<'_> {
         assert!(frame_id < self.file.num_frames());
         let raw_cel = self.file.framedata.cel(CelId {
             frame: frame_id as u16,
@@ -130,6 +184,39 @@ impl<'a> Layer<'a> {
     pub fn user_data(&self) -> Option<&UserData> {
         self.data().user_data.as_ref()
     }
+
+    /// The layer's universally unique identifier. Only present if the file
+    /// was saved with the "Layers have UUID" option of newer versions of
+    /// Aseprite. Unlike [id](Layer::id) and [path](Layer::path), it does not
+    /// change when layers are added, moved, or renamed.
+    pub fn uuid(&self) -> Option<[u8; 16]> {
+        self.data().uuid
+    }
+}
+
+// Layers only refer to their file, so they can be serialized but not
+// deserialized.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Layer<'_> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> core::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut layer = serializer.serialize_struct("Layer", 11)?;
+        layer.serialize_field("id", &self.id())?;
+        layer.serialize_field("name", self.name())?;
+        layer.serialize_field("parent", &self.parent().map(|parent| parent.id()))?;
+        layer.serialize_field("flags", &self.flags())?;
+        layer.serialize_field("visible", &self.is_visible())?;
+        layer.serialize_field("reference", &self.is_reference())?;
+        layer.serialize_field("blend_mode", &self.blend_mode())?;
+        layer.serialize_field("opacity", &self.opacity())?;
+        layer.serialize_field("layer_type", &self.layer_type())?;
+        layer.serialize_field("user_data", &self.user_data())?;
+        layer.serialize_field("uuid", &self.uuid())?;
+        layer.end()
+    }
 }
 
 #[derive(Debug)]
@@ -140,7 +227,8 @@ pub struct LayerData {
     pub(crate) opacity: u8,
     pub(crate) layer_type: LayerType,
     pub(crate) user_data: Option<UserData>,
-    child_level: u16,
+    pub(crate) child_level: u16,
+    pub(crate) uuid: Option<[u8; 16]>,
 }
 
 impl LayerData {
@@ -171,6 +259,15 @@ impl LayersData {
         }
         Ok(())
     }
+    // The ids of the layers directly inside `parent`, or of the top level
+    // layers for `None`.
+    pub(crate) fn children_of(&self, parent: Option<u32>) -> impl Iterator<Item = u32> + '_ {
+        self.parents
+            .iter()
+            .enumerate()
+            .filter(move |(_, p)| **p == parent)
+            .map(|(id, _)| id as u32)
+    }
     pub(crate) fn from_vec(layers: Vec<LayerData>) -> Result<Self> {
         // TODO: Validate some properties
         let parents = compute_parents(&layers);
@@ -194,6 +291,7 @@ impl Index<u32> for LayersData {
 /// New Layer Blending Method (#1096)". This is the default as of Aseprite 1.2.25.
 #[allow(missing_docs)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BlendMode {
     Normal,
     Multiply,
@@ -216,8 +314,8 @@ pub enum BlendMode {
     Divide,
 }
 
-pub(crate) fn parse_chunk(data: &[u8]) -> Result<LayerData> {
-    let mut reader = AseReader::new(&data);
+pub(crate) fn parse_chunk(data: &[u8], has_uuid: bool) -> Result<LayerData> {
+    let mut reader = AseReader::new(data);
 
     let flags = reader.word()?;
     let layer_type = reader.word()?;
@@ -230,6 +328,13 @@ pub(crate) fn parse_chunk(data: &[u8]) -> Result<LayerData> {
     let _reserved2 = reader.word()?;
     let name = reader.string()?;
     let layer_type = parse_layer_type(layer_type, &mut reader)?;
+    let uuid = if has_uuid {
+        let mut uuid = [0; 16];
+        reader.read_exact(&mut uuid)?;
+        Some(uuid)
+    } else {
+        None
+    };
 
     let flags = LayerFlags::from_bits_truncate(flags as u32);
 
@@ -248,10 +353,20 @@ pub(crate) fn parse_chunk(data: &[u8]) -> Result<LayerData> {
         layer_type,
         child_level,
         user_data: None,
+        uuid,
     })
 }
 
-fn parse_layer_type<R: Read>(id: u16, reader: &mut AseReader<R>) -> Result<LayerType> {
+// The bits of a layer chunk's flags that have no meaning in `LayerFlags`.
+pub(crate) fn unknown_flags(data: &[u8]) -> u16 {
+    let flags = match data {
+        [low, high, ..] => u16::from_le_bytes([*low, *high]),
+        _ => 0,
+    };
+    flags & !(LayerFlags::all().bits() as u16)
+}
+
+pub(crate) fn parse_layer_type<R: Read>(id: u16, reader: &mut AseReader<R>) -> Result<LayerType> {
     match id {
         0 => Ok(LayerType::Image),
         1 => Ok(LayerType::Group),
@@ -263,7 +378,7 @@ fn parse_layer_type<R: Read>(id: u16, reader: &mut AseReader<R>) -> Result<Layer
     }
 }
 
-fn parse_blend_mode(id: u16) -> Result<BlendMode> {
+pub(crate) fn parse_blend_mode(id: u16) -> Result<BlendMode> {
     match id {
         0 => Ok(BlendMode::Normal),
         1 => Ok(BlendMode::Multiply),
@@ -0,0 +1,95 @@
+use crate::Layer;
+use alloc::{borrow::ToOwned, boxed::Box};
+use core::fmt;
+
+/// Selects the layers that are composited by
+/// [Frame::image_with_layers](crate::Frame::image_with_layers).
+///
+/// A filter that selects a group also selects all layers inside of it, so
+/// `LayerFilter::name("Body")` renders everything in the group "Body".
+///
+/// ```
+/// # use asefile::{AsepriteFile, LayerFilter};
+/// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/layers_and_tags.aseprite")).unwrap();
+/// // Only the layers in the group "Group 1".
+/// let group = ase.frame(0).image_with_layers(&LayerFilter::path("Group 1"));
+/// // Everything except the layers named "guide" or "notes".
+/// let filter = LayerFilter::excluding(LayerFilter::name("guide").or(LayerFilter::name("notes")));
+/// let image = ase.frame(0).image_with_layers(&filter);
+/// ```
+pub struct LayerFilter<'f> {
+    selects: Box<dyn Fn(&Layer<'_>) -> bool + 'f>,
+}
+
+impl<'f> LayerFilter<'f> {
+    /// Select the layers named `name`.
+    pub fn name(name: &str) -> Self {
+        let name = name.to_owned();
+        Self::matching(move |layer| layer.name() == name)
+    }
+
+    /// Select the layer with the given [id](Layer::id).
+    pub fn index(layer_id: u32) -> Self {
+        Self::matching(move |layer| layer.id() == layer_id)
+    }
+
+    /// Select the layer with the given [path](Layer::path), e.g.,
+    /// `"Body/Arm"`.
+    pub fn path(path: &str) -> Self {
+        let path = path.to_owned();
+        Self::matching(move |layer| layer.path() == path)
+    }
+
+    /// Select the layers for which `predicate` returns `true`.
+    pub fn predicate<F>(predicate: F) -> Self
+    where
+        F: Fn(&Layer<'_>) -> bool + 'f,
+    {
+        Self::matching(predicate)
+    }
+
+    /// Select all layers that `filter` does not select.
+    pub fn excluding(filter: LayerFilter<'f>) -> Self {
+        Self {
+            selects: Box::new(move |layer| !filter.selects(layer)),
+        }
+    }
+
+    /// Select the layers that are selected by this filter or by `other`.
+    pub fn or(self, other: LayerFilter<'f>) -> Self {
+        Self {
+            selects: Box::new(move |layer| self.selects(layer) || other.selects(layer)),
+        }
+    }
+
+    /// Returns if the filter selects `layer`.
+    pub fn selects(&self, layer: &Layer<'_>) -> bool {
+        (self.selects)(layer)
+    }
+
+    // A filter that selects the layers that match, or whose groups match.
+    fn matching<F>(matches: F) -> Self
+    where
+        F: Fn(&Layer<'_>) -> bool + 'f,
+    {
+        Self {
+            selects: Box::new(move |layer| {
+                let file = layer.file;
+                let mut next = Some(layer.id());
+                while let Some(layer_id) = next {
+                    if matches(&Layer { file, layer_id }) {
+                        return true;
+                    }
+                    next = Layer { file, layer_id }.parent().map(|parent| parent.id());
+                }
+                false
+            }),
+        }
+    }
+}
+
+impl fmt::Debug for LayerFilter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LayerFilter")
+    }
+}
@@ -1,3 +1,4 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(clippy::all)]
 #![warn(missing_docs)]
 /*!
@@ -45,6 +46,8 @@ println!("Layers: {}", ase.num_layers());
 Aseprite files consist of multiple layers. Usually you just want the final
 image. You can do this by using [Frame::image]. This will return an
 `image::RgbaImage` from the [image](https://docs.rs/image) library.
+[Frame::raw_image] returns a plain pixel buffer instead and is also available
+without the default `image` feature.
 
 ```
 # use asefile::AsepriteFile;
@@ -57,7 +60,11 @@ let output_path = output_dir.join("example.png");
 image.save(&output_path).unwrap();
 ```
 
-This blends together all visible layers the same way Aseprite would.
+This blends together all visible layers the same way Aseprite would. To save
+every frame at once, use [AsepriteFile::export_frames] or combine them into a
+single image with the [spritesheet] module. Indexed sprites can keep their
+palette with [Frame::write_indexed_png], and the [pixel] module gives access
+to the palette indices or grayscale values themselves.
 
 ## Layers
 
@@ -92,41 +99,239 @@ let cel2 = ase.frame(0).layer(0);
 let image = cel1.image();
 ```
 
+## Save file
+
+Use [AsepriteFile::write_file] or [AsepriteFile::write_to] to save a sprite
+in the Aseprite format, e.g., after editing it or creating it with
+[AsepriteFileBuilder].
+
+```
+# use asefile::AsepriteFile;
+# use std::path::Path;
+# let path = Path::new("./tests/data/basic-16x16.aseprite");
+# let mut ase = AsepriteFile::read_file(&path).unwrap();
+ase.rename_layer(0, "Background").unwrap();
+let mut data = Vec::new();
+ase.write_to(&mut data).unwrap();
+```
+
+# Optional Features
+
+- `apng`: Export animations as animated PNG images. See the
+  `asefile::exporters::apng` module.
+- `arbitrary`: Random, structurally valid sprites for fuzzing. See the
+  `asefile::arbitrary` module. Enables `image`.
+- `bevy`: Load sprites as Bevy assets, with a texture atlas and an animation
+  for every tag. See the `asefile::bevy` module. Enables `image`.
+- `cache`: Store decoded files in a binary cache that loads much faster than
+  parsing. See the `asefile::cache` module.
+- `capi`: A C interface for opening sprites and reading their frames, tags
+  and slices. See the `asefile::capi` module.
+- `cur`: Export frames as Windows cursors. See the `asefile::exporters::cur`
+  module. Enables `ico`.
+- `futures`: Load sprites from an asynchronous reader with
+  [AsepriteFile::read_async]. See the `asefile::futures` module.
+- `ggez`: Convert frames and atlases to ggez images. See the `asefile::ggez`
+  module. Enables `image`.
+- `gif`: Export animations as animated GIF images. See the
+  `asefile::exporters::gif` module.
+- `godot`: Export animations as Godot 4 `SpriteFrames` resources. See the
+  `asefile::exporters::godot` module.
+- `ico`: Export frames as Windows icons. See the `asefile::exporters::ico`
+  module.
+- `image` (enabled by default): Return images as `image::RgbaImage`, and
+  everything that takes or returns such images, e.g., the
+  `asefile::import` and `asefile::blend` modules. The `raw_image` accessors,
+  such as [Frame::raw_image], return the plain pixel buffers of the
+  [raw_image] module with or without this feature. Enables `std`.
+- `json`: Describe sprite sheets and atlases in the JSON format of
+  `aseprite --data`. See the `asefile::exporters::json` module.
+- `libgdx`: Describe atlases in the `.atlas` format of libGDX. See the
+  `asefile::exporters::libgdx` module.
+- `macroquad`: Convert frames to macroquad textures and tags to animations.
+  See the `asefile::macroquad` module. Enables `image`.
+- `rayon`: Decode the cels of different frames on multiple threads when
+  reading a file without [lazy](ReadOptions::lazy) decoding.
+- `sdl2`: Convert frames to SDL2 surfaces and textures. See the
+  `asefile::sdl2` module. Requires the SDL2 library. Enables `image`.
+- `serde`: Implements `Serialize` and `Deserialize` for the [diff] report
+  types and for sprite metadata such as [Slice], [Tag], [UserData] and
+  [ColorPaletteEntry]. [Layer] and [Frame] implement `Serialize` only.
+- `simd`: Composite layers with the normal, multiply and screen blend modes
+  four pixels at a time with SSE2 instructions on x86 and x86_64. The images
+  are the same as without it.
+- `std` (enabled by default): Reading from files and any `std::io::Read`, and
+  everything that writes or exports files. Without it the crate is `no_std`
+  and only needs `alloc`: sprites are parsed from byte slices, with the same
+  frames, layers, tags, slices and tilesets as with `std`. Most other
+  features enable `std`.
+- `texturepacker`: Describe atlases in the JSON and plist formats of
+  TexturePacker. See the `asefile::exporters::texturepacker` module. Enables
+  `json`.
+- `tracing`: Emits [tracing](https://docs.rs/tracing) spans and events while
+  parsing and compositing: one span per frame, cel, palette, tags and slice
+  chunk, decompression sizes, and one span per composited frame or layer
+  image. Everything is logged at `DEBUG` or `TRACE` level. Without this
+  feature the instrumentation is compiled out entirely.
+- `wasm`: A JavaScript class for inspecting sprites in the browser, using
+  wasm-bindgen. See the `asefile::wasm` module. The crate builds for
+  `wasm32-unknown-unknown` without this feature as well.
+- `webp`: Export animations as animated, lossless WebP images. See the
+  `asefile::exporters::webp` module.
+- `wgpu`: Upload frames, layers and atlases to wgpu textures. See the
+  `asefile::wgpu` module. Enables `image`.
+
 */
 
-pub(crate) mod blend;
+#[macro_use]
+mod trace;
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod ansi;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+#[cfg(feature = "std")]
+pub mod atlas;
+#[cfg(feature = "bevy")]
+pub mod bevy;
+pub mod binary;
+#[cfg(feature = "image")]
+pub mod blend;
+#[cfg(not(feature = "image"))]
+mod blend;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "cache")]
+pub mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
 pub(crate) mod cel;
+mod collections;
+#[cfg(feature = "std")]
+mod color_management;
 pub(crate) mod color_profile;
+mod composite;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+mod edit;
 pub(crate) mod error;
+#[cfg(feature = "std")]
+pub mod exporters;
 pub(crate) mod external_file;
 pub(crate) mod file;
+#[cfg(feature = "std")]
+mod frame_cache;
+#[cfg(feature = "std")]
+mod frame_export;
+#[cfg(feature = "futures")]
+pub mod futures;
+#[cfg(feature = "ggez")]
+pub mod ggez;
+mod hash;
+#[cfg(feature = "std")]
+mod histogram;
+#[cfg(feature = "image")]
+pub mod import;
+#[cfg(feature = "std")]
+mod indexed_png;
+#[cfg(not(feature = "std"))]
+mod io;
 pub(crate) mod layer;
+mod layer_filter;
+mod limits;
+#[cfg(feature = "macroquad")]
+pub mod macroquad;
+mod native;
 pub(crate) mod palette;
+#[cfg(feature = "std")]
+mod palette_files;
+#[cfg(feature = "std")]
+mod palette_remap;
 pub(crate) mod parse;
-mod pixel;
+pub mod pixel;
+#[cfg(feature = "std")]
+mod png;
+#[cfg(feature = "image")]
+mod quantize;
+pub mod raw_image;
 mod reader;
+#[cfg(feature = "std")]
+pub mod reload;
+#[cfg(feature = "sdl2")]
+pub mod sdl2;
+#[cfg(feature = "simd")]
+mod simd;
 pub(crate) mod slice;
+#[cfg(feature = "image")]
+mod slice_image;
+#[cfg(feature = "std")]
+pub mod spritesheet;
 pub(crate) mod tags;
-#[cfg(test)]
+#[cfg(all(test, feature = "image"))]
+#[allow(clippy::get_first, clippy::if_same_then_else, clippy::needless_borrow)]
 mod tests;
 mod tile;
+#[cfg(feature = "std")]
+mod tile_dedup;
 mod tilemap;
 mod tileset;
 pub(crate) mod user_data;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "wgpu")]
+pub mod wgpu;
+#[cfg(feature = "std")]
+mod writer;
+
+// Images are composited as `image` crate types unless the feature is
+// disabled. Public items that use these types are only available with the
+// feature; the raw accessors convert them to `raw_image` types.
+#[cfg(feature = "image")]
+use ::image;
+#[cfg(not(feature = "image"))]
+use raw_image as image;
+// Input is read with `std::io` unless the feature is disabled.
+#[cfg(feature = "std")]
+use std::io;
 
 /// A specialized `Result` type for Aseprite parsing functions.
-pub type Result<T> = std::result::Result<T, AsepriteParseError>;
+pub type Result<T> = core::result::Result<T, AsepriteParseError>;
 
+#[cfg(feature = "std")]
+pub use builder::AsepriteFileBuilder;
 pub use cel::Cel;
-// pub use color_profile::ColorProfile;
-pub use error::AsepriteParseError;
-pub use external_file::{ExternalFile, ExternalFileId, ExternalFilesById};
-pub use file::{AsepriteFile, Frame, LayersIter, PixelFormat};
+pub use color_profile::{ColorProfile, ColorProfileType};
+#[cfg(feature = "image")]
+pub use composite::CompositeOptions;
+pub use error::{AsepriteParseError, ParseWarning};
+pub use external_file::{ExternalFile, ExternalFileId, ExternalFileType, ExternalFilesById};
+#[cfg(feature = "image")]
+pub use file::IntoFrameImages;
+pub use file::{
+    AsepriteFile, Frame, Grid, ImageOptions, LayersIter, PixelFormat, RawChunk, ReadOptions,
+};
+#[cfg(feature = "std")]
+pub use frame_export::FrameExportOptions;
+#[cfg(feature = "std")]
+pub use histogram::{Histogram, HistogramBucket};
 pub use layer::{BlendMode, Layer, LayerFlags};
-pub use palette::{ColorPalette, ColorPaletteEntry};
+pub use layer_filter::LayerFilter;
+pub use palette::{ColorDistance, ColorPalette, ColorPaletteEntry, ColorPaletteIter};
+#[cfg(feature = "std")]
+pub use palette_remap::{PaletteOrder, PaletteRemap, RemapMatch};
+#[cfg(feature = "image")]
+pub use quantize::QuantizeOptions;
 pub use slice::{Slice, Slice9, SliceKey, SliceOrigin, SlicePivot, SliceSize};
+#[cfg(feature = "image")]
+pub use slice_image::{NineSliceFill, NineSliceOptions};
 pub use tags::{AnimationDirection, Tag};
+pub use tile::{Tile, TileGrid};
+#[cfg(feature = "std")]
+pub use tile_dedup::TileDedupOptions;
 pub use tileset::{
     ExternalTilesetReference, TileSize, Tileset, TilesetId, TilesetImageError, TilesetsById,
 };
-pub use user_data::UserData;
+pub use user_data::{FromPropertyValue, Properties, PropertyError, PropertyValue, UserData};
@@ -0,0 +1,71 @@
+use crate::{AsepriteParseError, Result};
+use alloc::format;
+
+// Resource limits for loading untrusted files, see `ReadOptions::max_canvas_size`
+// and the following methods. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Limits {
+    pub canvas_size: Option<(u16, u16)>,
+    pub frames: Option<u16>,
+    pub image_bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+}
+
+// Checks the sizes declared in a file against the limits while it is parsed,
+// before anything is allocated or decompressed.
+pub(crate) struct Budget {
+    limits: Limits,
+    used: u64,
+}
+
+impl Budget {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self { limits, used: 0 }
+    }
+
+    pub(crate) fn check_canvas(&self, width: u16, height: u16) -> Result<()> {
+        match self.limits.canvas_size {
+            Some((max_width, max_height)) if width > max_width || height > max_height => {
+                Err(AsepriteParseError::LimitExceeded(format!(
+                    "Canvas size {}x{} exceeds the limit of {}x{}",
+                    width, height, max_width, max_height
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_frames(&self, num_frames: u16) -> Result<()> {
+        match self.limits.frames {
+            Some(max) if num_frames > max => Err(AsepriteParseError::LimitExceeded(format!(
+                "{} frames exceed the limit of {}",
+                num_frames, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    // Accounts for an image (`what`, e.g. "Cel image") that decodes to
+    // `bytes` bytes.
+    pub(crate) fn reserve(&mut self, what: &str, bytes: u64) -> Result<()> {
+        if let Some(max) = self.limits.image_bytes {
+            if bytes > max {
+                return Err(AsepriteParseError::LimitExceeded(format!(
+                    "{} of {} bytes exceeds the limit of {} bytes",
+                    what, bytes, max
+                )));
+            }
+        }
+        let used = self.used.saturating_add(bytes);
+        if let Some(max) = self.limits.total_bytes {
+            if used > max {
+                return Err(AsepriteParseError::LimitExceeded(format!(
+                    "Decoded images of {} bytes exceed the limit of {} bytes",
+                    used, max
+                )));
+            }
+        }
+        self.used = used;
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+/*!
+Use sprites in [macroquad](https://macroquad.rs) games.
+
+Requires the `macroquad` feature. [texture] uploads a frame image as a
+[Texture2D] with nearest-neighbor filtering, so pixel art stays sharp:
+
+```no_run
+# use asefile::AsepriteFile;
+# use std::path::Path;
+# let path = Path::new("./tests/data/layers_and_tags.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let texture = asefile::macroquad::texture(&ase.frame(0).image());
+```
+
+For animations, [AnimationSheet] places the frames of each tag in a row of
+one image, which is the layout macroquad's [AnimatedSprite] expects:
+
+```no_run
+# use asefile::AsepriteFile;
+# use asefile::macroquad::AnimationSheet;
+# use std::path::Path;
+# let path = Path::new("./tests/data/layers_and_tags.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let sheet = AnimationSheet::new(&ase);
+let texture = sheet.texture();
+let mut sprite = sheet.animated_sprite(true);
+// In the game loop:
+sprite.update();
+let source = sprite.frame().source_rect;
+```
+
+Creating textures requires the graphics context of macroquad, i.e. it only
+works after the window was opened by `macroquad::main`.
+*/
+use crate::exporters::playback_frames;
+use crate::image::RgbaImage;
+use crate::AsepriteFile;
+use ::macroquad::experimental::animation::{AnimatedSprite, Animation};
+use ::macroquad::texture::{FilterMode, Image, Texture2D};
+use std::convert::TryFrom;
+
+/// Convert an image to a macroquad [Image].
+///
+/// # Panics
+///
+/// Panics if the image is wider or taller than 65535 pixels.
+pub fn image(image: &RgbaImage) -> Image {
+    let (width, height) = image.dimensions();
+    Image {
+        bytes: image.as_raw().clone(),
+        width: u16::try_from(width).expect("Image is too wide for macroquad"),
+        height: u16::try_from(height).expect("Image is too tall for macroquad"),
+    }
+}
+
+/// Upload an image as a [Texture2D] with [FilterMode::Nearest].
+///
+/// # Panics
+///
+/// Panics if the image is wider or taller than 65535 pixels, or if called
+/// outside of macroquad's graphics context.
+pub fn texture(image: &RgbaImage) -> Texture2D {
+    let texture = Texture2D::from_image(&self::image(image));
+    texture.set_filter(FilterMode::Nearest);
+    texture
+}
+
+/// The tags of a sprite as animations for macroquad's [AnimatedSprite].
+///
+/// Each tag has a row in the [image](AnimationSheet::image), with its
+/// frames in playback order. Reverse and ping-pong tags therefore play
+/// correctly, and tags with a [repeat](crate::Tag::repeat) count contain
+/// every repetition. macroquad shows all frames of an animation for the same
+/// time, so the frame rate of each animation is based on the average frame
+/// duration of its tag.
+#[derive(Debug, Clone)]
+pub struct AnimationSheet {
+    image: RgbaImage,
+    frame_size: (u32, u32),
+    animations: Vec<Animation>,
+}
+
+impl AnimationSheet {
+    /// Lay out the animations of all tags. The sheet is empty if the sprite
+    /// has no tags.
+    pub fn new(file: &AsepriteFile) -> Self {
+        let (width, height) = file.size();
+        let (width, height) = (width as u32, height as u32);
+        let rows: Vec<Vec<u32>> = (0..file.num_tags())
+            .map(|tag_id| playback_frames(file.tag(tag_id)))
+            .collect();
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0) as u32;
+        let mut image = RgbaImage::new(columns * width, rows.len() as u32 * height);
+        let mut animations = Vec::with_capacity(rows.len());
+        for (row, frames) in rows.iter().enumerate() {
+            for (column, &frame) in frames.iter().enumerate() {
+                crate::image::imageops::replace(
+                    &mut image,
+                    &file.frame(frame).image(),
+                    column as u32 * width,
+                    row as u32 * height,
+                );
+            }
+            let total: u32 = frames
+                .iter()
+                .map(|&frame| file.frame(frame).duration())
+                .sum();
+            let fps = (frames.len() as f64 * 1000.0 / total.max(1) as f64).round() as u32;
+            animations.push(Animation {
+                name: file.tag(row as u32).name().to_owned(),
+                row: row as u32,
+                frames: frames.len() as u32,
+                fps: fps.max(1),
+            });
+        }
+        Self {
+            image,
+            frame_size: (width, height),
+            animations,
+        }
+    }
+
+    /// The image with a row of frames for each tag.
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// Upload the [image](AnimationSheet::image), see [texture].
+    pub fn texture(&self) -> Texture2D {
+        texture(&self.image)
+    }
+
+    /// Width and height of a single frame.
+    pub fn frame_size(&self) -> (u32, u32) {
+        self.frame_size
+    }
+
+    /// The animation of each tag, in the order of the tags.
+    pub fn animations(&self) -> &[Animation] {
+        &self.animations
+    }
+
+    /// An [AnimatedSprite] that plays the animations on the
+    /// [texture](AnimationSheet::texture). It starts with the first tag.
+    /// Note that macroquad panics when updating a sprite without animations,
+    /// i.e. if the sprite has no tags.
+    pub fn animated_sprite(&self, playing: bool) -> AnimatedSprite {
+        let (width, height) = self.frame_size;
+        AnimatedSprite::new(width, height, &self.animations, playing)
+    }
+}
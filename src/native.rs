@@ -0,0 +1,203 @@
+use crate::cel::{CelContent, CelId, ImageContent, RawCel};
+use crate::layer::LayerType;
+use crate::pixel::{Grayscale, Image, Indexed, PixelsRef};
+use crate::{AsepriteFile, Cel, Frame, PixelFormat};
+
+/// # Images in the sprite's color depth
+impl<'a> Frame<'a> {
+    /// The frame's palette indices, for sprites that use
+    /// [PixelFormat::Indexed]. Returns `None` for other sprites.
+    ///
+    /// Each pixel has the index of the topmost cel of a visible layer that is
+    /// not the [transparent color index](PixelFormat::transparent_color_index)
+    /// there, or the transparent color index if there is none. Cels are
+    /// ordered by layer and [z-index](Cel::z_index). Like Aseprite's export
+    /// of indexed images, this leaves out reference layers and ignores the
+    /// opacity and blend mode of layers and cels. Use [image](Frame::image) to blend them.
+    pub fn indexed_image(&self) -> Option<Image<Indexed>> {
+        let transparent = match self.file.pixel_format {
+            PixelFormat::Indexed {
+                transparent_color_index,
+            } => Indexed::new(transparent_color_index),
+            _ => return None,
+        };
+        let file = self.file;
+        let mut image = Image::from_pixel(file.width as u32, file.height as u32, transparent);
+        for (layer_id, cel) in file.framedata.frame_cels_in_z_order(self.index as u16) {
+            let layer = file.layer(layer_id);
+            if !layer.is_visible() || layer.is_reference() {
+                continue;
+            }
+            // Background layers have no transparent pixels.
+            let is_background = file.layers[layer_id].is_background();
+            file.for_each_cel_pixel(cel, |x, y, pixels, index| {
+                if let PixelsRef::Indexed(indices) = pixels {
+                    let pixel = Indexed::new(indices[index]);
+                    if is_background || pixel != transparent {
+                        image.put_pixel(x, y, pixel);
+                    }
+                }
+            });
+        }
+        Some(image)
+    }
+
+    /// The frame's [image](Frame::image) as grayscale pixels, for sprites
+    /// that use [PixelFormat::Grayscale]. Returns `None` for other sprites.
+    ///
+    /// The layers are blended like for the RGBA image, whose color channels
+    /// all have the same value for grayscale sprites.
+    pub fn grayscale_image(&self) -> Option<Image<Grayscale>> {
+        if self.file.pixel_format != PixelFormat::Grayscale {
+            return None;
+        }
+        let rgba = self.composite();
+        let (width, height) = rgba.dimensions();
+        let mut image = Image::from_pixel(width, height, Grayscale::default());
+        for (x, y, pixel) in rgba.enumerate_pixels() {
+            image.put_pixel(x, y, Grayscale::new(pixel[0], pixel[3]));
+        }
+        Some(image)
+    }
+}
+
+/// # Images in the sprite's color depth
+impl<'a> Cel<'a> {
+    /// The cel's palette indices, for sprites that use
+    /// [PixelFormat::Indexed]. Returns `None` for other sprites.
+    ///
+    /// Like [image](Cel::image), the result has the size of the sprite.
+    /// Pixels outside of the cel have the
+    /// [transparent color index](PixelFormat::transparent_color_index). The
+    /// opacity of the cel and its layer is ignored.
+    pub fn indexed_image(&self) -> Option<Image<Indexed>> {
+        let transparent = match self.file.pixel_format {
+            PixelFormat::Indexed {
+                transparent_color_index,
+            } => Indexed::new(transparent_color_index),
+            _ => return None,
+        };
+        Some(
+            self.native_image(transparent, |pixels, index| match pixels {
+                PixelsRef::Indexed(indices) => Some(Indexed::new(indices[index])),
+                _ => None,
+            }),
+        )
+    }
+
+    /// The cel's pixels, for sprites that use [PixelFormat::Grayscale].
+    /// Returns `None` for other sprites.
+    ///
+    /// Like [image](Cel::image), the result has the size of the sprite.
+    /// Pixels outside of the cel are transparent. The opacity of the cel and
+    /// its layer is ignored.
+    pub fn grayscale_image(&self) -> Option<Image<Grayscale>> {
+        if self.file.pixel_format != PixelFormat::Grayscale {
+            return None;
+        }
+        Some(
+            self.native_image(Grayscale::default(), |pixels, index| match pixels {
+                PixelsRef::Grayscale(grayscale) => Some(grayscale[index]),
+                _ => None,
+            }),
+        )
+    }
+
+    fn native_image<P, F>(&self, empty: P, get: F) -> Image<P>
+    where
+        P: Copy,
+        F: Fn(PixelsRef<'_>, usize) -> Option<P>,
+    {
+        let file = self.file;
+        let mut image = Image::from_pixel(file.width as u32, file.height as u32, empty);
+        let cel_id = CelId {
+            frame: self.frame as u16,
+            layer: self.layer as u16,
+        };
+        if let Some(cel) = file.framedata.cel(cel_id) {
+            file.for_each_cel_pixel(cel, |x, y, pixels, index| {
+                if let Some(pixel) = get(pixels, index) {
+                    image.put_pixel(x, y, pixel);
+                }
+            });
+        }
+        image
+    }
+}
+
+impl AsepriteFile {
+    // Calls `f(x, y, pixels, index)` for each pixel of the cel that is on the
+    // canvas, where `pixels[index]` is the pixel of the cel at canvas position
+    // (x, y). Tilemap cels pass the pixels of their tileset.
+    fn for_each_cel_pixel<F>(&self, cel: &RawCel, mut f: F)
+    where
+        F: FnMut(u32, u32, PixelsRef<'_>, usize),
+    {
+        let cel = match &cel.content {
+            CelContent::Linked(frame) => {
+                let source = self.framedata.cel(CelId {
+                    frame: *frame,
+                    layer: cel.data.layer_index,
+                });
+                // Links to linked cels are rejected by CelsData::validate.
+                match source.filter(|c| !matches!(c.content, CelContent::Linked(_))) {
+                    Some(source) => source,
+                    None => return,
+                }
+            }
+            _ => cel,
+        };
+        let (canvas_width, canvas_height) = (self.width as i64, self.height as i64);
+        let (cel_x, cel_y) = (cel.data.x as i64, cel.data.y as i64);
+        let mut put = |x: i64, y: i64, pixels: PixelsRef<'_>, index: usize| {
+            if (0..canvas_width).contains(&x) && (0..canvas_height).contains(&y) {
+                f(x as u32, y as u32, pixels, index);
+            }
+        };
+        match &cel.content {
+            CelContent::Raw(ImageContent { size, pixels }) => {
+                let pixels = self.framedata.pixels(pixels);
+                let (width, height) = (size.width as i64, size.height as i64);
+                for y in 0..height {
+                    for x in 0..width {
+                        put(cel_x + x, cel_y + y, pixels, (y * width + x) as usize);
+                    }
+                }
+            }
+            CelContent::Tilemap(tilemap) => {
+                let layer = self.layer(cel.data.layer_index as u32);
+                let tileset = match layer.layer_type() {
+                    LayerType::Tilemap(tileset_id) => self.tilesets().get(&tileset_id),
+                    _ => None,
+                };
+                let (tileset, pixels) = match tileset.and_then(|t| Some((t, t.pixels.as_ref()?))) {
+                    Some(found) => found,
+                    None => return,
+                };
+                let pixels = pixels.all();
+                let tile_width = *tileset.tile_size().width() as i64;
+                let tile_height = *tileset.tile_size().height() as i64;
+                let pixels_per_tile = (tile_width * tile_height) as usize;
+                for tile_y in 0..tilemap.height as i64 {
+                    for tile_x in 0..tilemap.width as i64 {
+                        // TODO: support tile transform flags
+                        let tile =
+                            &tilemap.tiles[(tile_y * tilemap.width as i64 + tile_x) as usize];
+                        let start = tileset.drawn_tile_id(tile.id.0) as usize * pixels_per_tile;
+                        for y in 0..tile_height {
+                            for x in 0..tile_width {
+                                put(
+                                    cel_x + tile_x * tile_width + x,
+                                    cel_y + tile_y * tile_height + y,
+                                    pixels,
+                                    start + (y * tile_width + x) as usize,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            CelContent::Linked(_) => {}
+        }
+    }
+}
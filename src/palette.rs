@@ -1,19 +1,26 @@
-use crate::{pixel, reader::AseReader, AsepriteParseError, Result};
-use nohash::IntMap;
+use crate::collections::IntMap;
+use crate::{reader::AseReader, AsepriteParseError, Result};
+use alloc::{format, string::String, vec, vec::Vec};
+
+// Palette indices from a file must be below this. Aseprite never writes
+// larger palettes, and it keeps dense copies of the palette, such as
+// `ColorPalette::to_rgba_vec` and the written palette chunk, small.
+pub(crate) const MAX_COLORS: u32 = 0x10000;
 
 /// The color palette embedded in the file.
 #[derive(Debug)]
 pub struct ColorPalette {
     //entries: Vec<ColorPaletteEntry>,
-    entries: IntMap<u32, ColorPaletteEntry>,
+    pub(crate) entries: IntMap<u32, ColorPaletteEntry>,
 }
 
 /// A single entry in a [ColorPalette].
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ColorPaletteEntry {
-    id: u32,
-    rgba8: [u8; 4],
-    name: Option<String>,
+    pub(crate) id: u32,
+    pub(crate) rgba8: [u8; 4],
+    pub(crate) name: Option<String>,
 }
 
 impl ColorPalette {
@@ -31,13 +38,98 @@ impl ColorPalette {
         self.entries.get(&index)
     }
 
-    pub(crate) fn validate_indexed_pixels(&self, indexed_pixels: &[pixel::Indexed]) -> Result<()> {
-        for pixel in indexed_pixels {
-            let color = self.color(pixel.value().into());
+    /// All entries with their index, in index order.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/palette.aseprite")).unwrap();
+    /// let palette = ase.palette().unwrap();
+    /// for (index, entry) in palette.iter() {
+    ///     println!("{}: {:?}", index, entry.raw_rgba8());
+    /// }
+    /// ```
+    pub fn iter(&self) -> ColorPaletteIter<'_> {
+        let mut entries: Vec<(u32, &ColorPaletteEntry)> = self
+            .entries
+            .iter()
+            .map(|(index, entry)| (*index, entry))
+            .collect();
+        entries.sort_unstable_by_key(|(index, _)| *index);
+        ColorPaletteIter {
+            entries: entries.into_iter(),
+        }
+    }
+
+    /// The colors of all indices from 0 to the last entry, in RGBA order, for
+    /// building palette textures and lookup tables. Indices without an entry
+    /// are fully transparent black (`[0, 0, 0, 0]`).
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/palette.aseprite")).unwrap();
+    /// let palette = ase.palette().unwrap();
+    /// let colors = palette.to_rgba_vec();
+    /// assert_eq!(colors[0], palette.color(0).unwrap().raw_rgba8());
+    /// ```
+    pub fn to_rgba_vec(&self) -> Vec<[u8; 4]> {
+        let size = self.entries.keys().max().map_or(0, |&max| max as usize + 1);
+        let mut colors = vec![[0; 4]; size];
+        for (index, entry) in &self.entries {
+            colors[*index as usize] = entry.rgba8;
+        }
+        colors
+    }
+
+    /// The index of the entry that is closest to `rgba` by the
+    /// [ColorDistance::Perceptual] metric, see [ColorPalette::nearest_by].
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/palette.aseprite")).unwrap();
+    /// let palette = ase.palette().unwrap();
+    /// let index = palette.nearest([250, 10, 10, 255]).unwrap();
+    /// println!("closest to red: {:?}", palette.color(index).unwrap().raw_rgba8());
+    /// ```
+    pub fn nearest(&self, rgba: [u8; 4]) -> Option<u32> {
+        self.nearest_by(rgba, ColorDistance::Perceptual)
+    }
+
+    /// The index of the entry that is closest to `rgba` by the given metric.
+    /// Alpha counts like a color channel. If several entries are equally
+    /// close, the one with the lowest index wins.
+    ///
+    /// Returns `None` if the palette is empty.
+    pub fn nearest_by(&self, rgba: [u8; 4], distance: ColorDistance) -> Option<u32> {
+        self.iter()
+            .min_by_key(|(_, entry)| distance.between(rgba, entry.rgba8))
+            .map(|(index, _)| index)
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn from_colors(colors: &[[u8; 4]]) -> Self {
+        let entries = colors
+            .iter()
+            .enumerate()
+            .map(|(id, rgba8)| {
+                let id = id as u32;
+                let entry = ColorPaletteEntry {
+                    id,
+                    rgba8: *rgba8,
+                    name: None,
+                };
+                (id, entry)
+            })
+            .collect();
+        Self { entries }
+    }
+
+    pub(crate) fn validate_indexed_pixels(&self, indexed_pixels: &[u8]) -> Result<()> {
+        for index in indexed_pixels {
+            let color = self.color(*index as u32);
             color.ok_or_else(|| {
                 AsepriteParseError::InvalidInput(format!(
                     "Index out of range: {} (max: {})",
-                    pixel.value(),
+                    index,
                     self.num_colors()
                 ))
             })?;
@@ -46,6 +138,74 @@ impl ColorPalette {
     }
 }
 
+/// How [ColorPalette::nearest_by] measures the distance between two colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDistance {
+    /// The Euclidean distance of the RGBA components.
+    Rgb,
+    /// A weighted RGB distance that approximates how different the colors
+    /// look, using the "redmean" weights. Greens are told apart more
+    /// strongly than blues.
+    Perceptual,
+}
+
+impl ColorDistance {
+    // Some monotonic function of the distance. Only the order matters.
+    pub(crate) fn between(self, a: [u8; 4], b: [u8; 4]) -> u32 {
+        let diff = |channel: usize| {
+            let d = a[channel] as i32 - b[channel] as i32;
+            (d * d) as u32
+        };
+        match self {
+            ColorDistance::Rgb => diff(0) + diff(1) + diff(2) + diff(3),
+            ColorDistance::Perceptual => {
+                let red_mean = (a[0] as u32 + b[0] as u32) / 2;
+                // The weights are 2 + r/256, 4 and 3 - r/256, in units of 1/256.
+                (512 + red_mean) * diff(0)
+                    + 1024 * diff(1)
+                    + (767 - red_mean) * diff(2)
+                    + 768 * diff(3)
+            }
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a ColorPalette {
+    type Item = (u32, &'a ColorPaletteEntry);
+    type IntoIter = ColorPaletteIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of a palette in index order. See
+/// [ColorPalette::iter].
+#[derive(Debug)]
+pub struct ColorPaletteIter<'a> {
+    entries: alloc::vec::IntoIter<(u32, &'a ColorPaletteEntry)>,
+}
+
+impl<'a> Iterator for ColorPaletteIter<'a> {
+    type Item = (u32, &'a ColorPaletteEntry);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for ColorPaletteIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries.next_back()
+    }
+}
+
+impl ExactSizeIterator for ColorPaletteIter<'_> {}
+
 impl ColorPaletteEntry {
     /// The id of this entry is the same as its index in the palette.
     pub fn id(&self) -> u32 {
@@ -92,7 +252,9 @@ pub(crate) fn parse_chunk(data: &[u8]) -> Result<ColorPalette> {
     let last_color_index = reader.dword()?;
     reader.skip_reserved(8)?;
 
-    if last_color_index < first_color_index {
+    trace_span!(DEBUG, "parse_palette", first_color_index, last_color_index);
+
+    if last_color_index < first_color_index || last_color_index >= MAX_COLORS {
         return Err(AsepriteParseError::InvalidInput(format!(
             "Bad palette color indices: first={} last={}",
             first_color_index, last_color_index,
@@ -128,3 +290,53 @@ pub(crate) fn parse_chunk(data: &[u8]) -> Result<ColorPalette> {
 
     Ok(ColorPalette { entries })
 }
+
+// Applies an old palette chunk (0x0004, or 0x0011 with `six_bit` color
+// components) to `palette`. Each packet of the chunk sets a run of colors and
+// leaves the others unchanged. The colors are opaque.
+pub(crate) fn apply_old_chunk(
+    data: &[u8],
+    six_bit: bool,
+    palette: &mut Option<ColorPalette>,
+) -> Result<()> {
+    let mut reader = AseReader::new(data);
+    let num_packets = reader.word()?;
+    let mut index = 0_u32;
+    for _ in 0..num_packets {
+        index += reader.byte()? as u32;
+        let num_colors = match reader.byte()? {
+            0 => 256,
+            n => n as u32,
+        };
+        if index + num_colors > MAX_COLORS {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Bad old palette color index: {}",
+                index + num_colors - 1
+            )));
+        }
+        for _ in 0..num_colors {
+            let mut rgb = [reader.byte()?, reader.byte()?, reader.byte()?];
+            if six_bit {
+                // Like Aseprite, which maps 63 to 255.
+                for component in &mut rgb {
+                    *component = (*component << 2) | (*component >> 4);
+                }
+            }
+            let entries = &mut palette
+                .get_or_insert_with(|| ColorPalette {
+                    entries: IntMap::default(),
+                })
+                .entries;
+            entries.insert(
+                index,
+                ColorPaletteEntry {
+                    id: index,
+                    rgba8: [rgb[0], rgb[1], rgb[2], 255],
+                    name: None,
+                },
+            );
+            index += 1;
+        }
+    }
+    Ok(())
+}
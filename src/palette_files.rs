@@ -0,0 +1,239 @@
+use crate::{AsepriteParseError, ColorPalette, Result};
+use std::io::{self, Read, Write};
+
+// Adobe Color Tables always have 256 colors, optionally followed by the
+// number of colors that are used and the transparent index.
+const ACT_COLORS: usize = 256;
+const ACT_NO_TRANSPARENT_INDEX: u16 = 0xFFFF;
+
+/// # Palette files
+impl ColorPalette {
+    /// Write the palette as a GIMP palette (`.gpl`), which most art tools
+    /// can import. Entries keep their names and their order, and gaps
+    /// between entries are filled with opaque black, like in palettes that
+    /// were resized in Aseprite.
+    ///
+    /// If any entry is not fully opaque, the file has an alpha column, as in
+    /// palettes saved by Aseprite. Tools that only know the original format
+    /// ignore it.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # use std::path::Path;
+    /// # let path = Path::new("./tests/data/palette.aseprite");
+    /// let ase = AsepriteFile::read_file(&path).unwrap();
+    /// let mut data = Vec::new();
+    /// ase.palette().unwrap().write_gpl(&mut data).unwrap();
+    /// assert!(data.starts_with(b"GIMP Palette\n"));
+    /// // std::fs::write("palette.gpl", data).unwrap();
+    /// ```
+    pub fn write_gpl<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let colors = self.dense_entries();
+        let has_alpha = colors.iter().any(|(rgba, _)| rgba[3] != 255);
+        writeln!(w, "GIMP Palette")?;
+        if has_alpha {
+            writeln!(w, "Channels: RGBA")?;
+        }
+        writeln!(w, "#")?;
+        for (rgba, name) in colors {
+            let name = name.unwrap_or("Untitled").replace(['\r', '\n'], " ");
+            write!(w, "{:3} {:3} {:3}", rgba[0], rgba[1], rgba[2])?;
+            if has_alpha {
+                write!(w, " {:3}", rgba[3])?;
+            }
+            writeln!(w, "\t{}", name)?;
+        }
+        Ok(())
+    }
+
+    /// Write the palette as an Adobe Color Table (`.act`). The colors are
+    /// written without alpha, but the first fully transparent entry becomes
+    /// the table's transparent index.
+    ///
+    /// Fails with [io::ErrorKind::InvalidInput] if the palette has more than
+    /// 256 entries, the most that a color table can hold.
+    pub fn write_act<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let colors = self.dense_entries();
+        if colors.len() > ACT_COLORS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Color tables hold at most {} colors, the palette has {}",
+                    ACT_COLORS,
+                    colors.len()
+                ),
+            ));
+        }
+        let mut data = Vec::with_capacity(ACT_COLORS * 3 + 4);
+        for (rgba, _) in &colors {
+            data.extend_from_slice(&rgba[..3]);
+        }
+        data.resize(ACT_COLORS * 3, 0);
+        let transparent_index = colors
+            .iter()
+            .position(|(rgba, _)| rgba[3] == 0)
+            .map_or(ACT_NO_TRANSPARENT_INDEX, |index| index as u16);
+        data.extend_from_slice(&(colors.len() as u16).to_be_bytes());
+        data.extend_from_slice(&transparent_index.to_be_bytes());
+        w.write_all(&data)
+    }
+
+    /// Read an Adobe Color Table (`.act`). The colors are opaque, except for
+    /// the transparent index if the table has one.
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the input is not a color
+    /// table.
+    pub fn read_act<R: Read>(mut input: R) -> Result<ColorPalette> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        let (num_colors, transparent_index) = match data.len() {
+            768 => (ACT_COLORS, None),
+            772 => {
+                let num_colors = u16::from_be_bytes([data[768], data[769]]) as usize;
+                let transparent_index = u16::from_be_bytes([data[770], data[771]]);
+                (
+                    num_colors.min(ACT_COLORS),
+                    Some(transparent_index).filter(|&index| index != ACT_NO_TRANSPARENT_INDEX),
+                )
+            }
+            len => {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Color tables have 768 or 772 bytes, not {}",
+                    len
+                )))
+            }
+        };
+        let colors: Vec<[u8; 4]> = data[..num_colors * 3]
+            .chunks(3)
+            .enumerate()
+            .map(|(index, rgb)| {
+                let alpha = if transparent_index == Some(index as u16) {
+                    0
+                } else {
+                    255
+                };
+                [rgb[0], rgb[1], rgb[2], alpha]
+            })
+            .collect();
+        Ok(ColorPalette::from_colors(&colors))
+    }
+
+    /// Write the palette as a JASC palette (`.pal`), the format of Paint
+    /// Shop Pro that many pixel art tools use. The format has no alpha
+    /// channel, so all colors are written as opaque.
+    pub fn write_jasc_pal<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let colors = self.dense_entries();
+        write!(w, "JASC-PAL\r\n0100\r\n{}\r\n", colors.len())?;
+        for (rgba, _) in colors {
+            write!(w, "{} {} {}\r\n", rgba[0], rgba[1], rgba[2])?;
+        }
+        Ok(())
+    }
+
+    /// Read a JASC palette (`.pal`). The colors are opaque.
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the input is not a JASC
+    /// palette.
+    pub fn read_jasc_pal<R: Read>(mut input: R) -> Result<ColorPalette> {
+        let mut text = String::new();
+        input.read_to_string(&mut text)?;
+        let invalid = |message: &str| {
+            AsepriteParseError::InvalidInput(format!("Invalid JASC palette: {}", message))
+        };
+        let mut lines = text.lines().map(str::trim);
+        if lines.next() != Some("JASC-PAL") || lines.next() != Some("0100") {
+            return Err(invalid("missing header"));
+        }
+        let num_colors: usize = lines
+            .next()
+            .and_then(|line| line.parse().ok())
+            .ok_or_else(|| invalid("missing number of colors"))?;
+        let colors = lines
+            .filter(|line| !line.is_empty())
+            .take(num_colors)
+            .map(|line| {
+                let mut rgb = [0; 3];
+                let mut components = line.split_whitespace();
+                for component in &mut rgb {
+                    *component = components
+                        .next()
+                        .and_then(|value| value.parse().ok())
+                        .ok_or_else(|| invalid(&format!("bad color {:?}", line)))?;
+                }
+                Ok([rgb[0], rgb[1], rgb[2], 255])
+            })
+            .collect::<Result<Vec<[u8; 4]>>>()?;
+        if colors.len() != num_colors {
+            return Err(invalid(&format!(
+                "expected {} colors, found {}",
+                num_colors,
+                colors.len()
+            )));
+        }
+        Ok(ColorPalette::from_colors(&colors))
+    }
+
+    /// Write the palette as a list of hex colors (`.hex`), one `rrggbb` line
+    /// per entry, as used by Lospec. Entries that are not fully opaque are
+    /// written as `rrggbbaa`.
+    pub fn write_hex<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for (rgba, _) in self.dense_entries() {
+            write!(w, "{:02x}{:02x}{:02x}", rgba[0], rgba[1], rgba[2])?;
+            if rgba[3] != 255 {
+                write!(w, "{:02x}", rgba[3])?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    /// Read a list of hex colors (`.hex`) with one `rrggbb` or `rrggbbaa`
+    /// color per line. Colors may start with `#`, and empty lines are
+    /// skipped.
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if a line is not a color.
+    pub fn read_hex<R: Read>(mut input: R) -> Result<ColorPalette> {
+        let mut text = String::new();
+        input.read_to_string(&mut text)?;
+        let colors = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let digits = line.strip_prefix('#').unwrap_or(line);
+                let component = |index: usize| {
+                    digits
+                        .get(index * 2..index * 2 + 2)
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                };
+                let color = match digits.len() {
+                    6 => component(0)
+                        .zip(component(1))
+                        .zip(component(2))
+                        .map(|((r, g), b)| [r, g, b, 255]),
+                    8 => component(0)
+                        .zip(component(1))
+                        .zip(component(2))
+                        .zip(component(3))
+                        .map(|(((r, g), b), a)| [r, g, b, a]),
+                    _ => None,
+                };
+                color.ok_or_else(|| {
+                    AsepriteParseError::InvalidInput(format!("Invalid hex color: {:?}", line))
+                })
+            })
+            .collect::<Result<Vec<[u8; 4]>>>()?;
+        Ok(ColorPalette::from_colors(&colors))
+    }
+
+    // The color and name of every index up to the last entry.
+    fn dense_entries(&self) -> Vec<([u8; 4], Option<&str>)> {
+        let size = self.entries.keys().max().map_or(0, |&max| max + 1);
+        (0..size)
+            .map(|index| match self.color(index) {
+                Some(entry) => (entry.raw_rgba8(), entry.name()),
+                None => ([0, 0, 0, 255], None),
+            })
+            .collect()
+    }
+}
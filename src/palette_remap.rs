@@ -0,0 +1,256 @@
+use crate::{
+    palette::{ColorDistance, ColorPaletteEntry},
+    pixel::{Indexed, Pixels},
+    AsepriteFile, AsepriteParseError, ColorPalette, Result,
+};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+/// How [ColorPalette::remap_to] finds the new index of a color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemapMatch {
+    /// Only an entry with exactly the same RGBA color matches. Colors that
+    /// the target palette does not have are not remapped.
+    Exact,
+    /// The closest entry by the given metric, so that every color is
+    /// remapped.
+    Nearest(ColorDistance),
+}
+
+/// The order of [AsepriteFile::sorted_palette].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteOrder {
+    /// Around the color wheel, starting at red. Grays come first, from dark
+    /// to light.
+    Hue,
+    /// From dark to light, by the luminance of sRGB (Rec. 709).
+    Luminance,
+    /// From the most to the least used color, counting the pixels of all
+    /// cel images and tiles. Linked cels share their image, so they count
+    /// only once. Only available for indexed files.
+    Usage,
+}
+
+/// A table that maps the indices of one palette to the indices of another,
+/// see [ColorPalette::remap_to].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaletteRemap {
+    // The new index of each of the 256 indices that indexed pixels can have.
+    table: Vec<Option<u8>>,
+    complete: bool,
+}
+
+impl PaletteRemap {
+    /// The new index of `index`, or `None` if the source palette has no such
+    /// entry or its color was not found in the target palette.
+    pub fn get(&self, index: u8) -> Option<u8> {
+        self.table[index as usize]
+    }
+
+    /// Whether every entry of the source palette that indexed pixels can
+    /// refer to has a new index.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The pixel with its new index. Pixels without a new index are kept.
+    pub fn map(&self, pixel: Indexed) -> Indexed {
+        Indexed::new(self.get(pixel.value()).unwrap_or_else(|| pixel.value()))
+    }
+
+    // Remaps pixel data in place, except for pixels with the transparent
+    // index, which stay transparent.
+    pub(crate) fn apply(&self, indices: &mut [u8], transparent_index: u8) {
+        for index in indices {
+            if *index != transparent_index {
+                *index = self.map(Indexed::new(*index)).value();
+            }
+        }
+    }
+}
+
+/// # Remapping
+impl ColorPalette {
+    /// The table that maps each index of this palette to an index of the
+    /// `target` palette with the same or the closest color. Apply it to a
+    /// sprite with [AsepriteFile::apply_palette_remap] to move the sprite to
+    /// a shared palette.
+    ///
+    /// Only the first 256 entries of either palette take part, as indexed
+    /// pixels cannot refer to others. If several target entries match
+    /// equally well, the one with the lowest index is used.
+    ///
+    /// ```
+    /// # use asefile::{AsepriteFile, ColorDistance, RemapMatch};
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/indexed.aseprite")).unwrap();
+    /// # let shared = AsepriteFile::read_file(std::path::Path::new("./tests/data/palette.aseprite")).unwrap();
+    /// let palette = ase.palette().unwrap();
+    /// let remap = palette.remap_to(
+    ///     shared.palette().unwrap(),
+    ///     RemapMatch::Nearest(ColorDistance::Perceptual),
+    /// );
+    /// assert!(remap.is_complete());
+    /// ```
+    ///
+    /// [AsepriteFile::apply_palette_remap]: crate::AsepriteFile::apply_palette_remap
+    pub fn remap_to(&self, target: &ColorPalette, matching: RemapMatch) -> PaletteRemap {
+        let candidates: Vec<(u8, [u8; 4])> = addressable_colors(target).collect();
+        let mut table = vec![None; 256];
+        let mut complete = true;
+        for (index, rgba) in addressable_colors(self) {
+            let new_index = match matching {
+                RemapMatch::Exact => candidates
+                    .iter()
+                    .find(|(_, candidate)| *candidate == rgba)
+                    .map(|(index, _)| *index),
+                RemapMatch::Nearest(distance) => candidates
+                    .iter()
+                    .min_by_key(|(_, candidate)| distance.between(rgba, *candidate))
+                    .map(|(index, _)| *index),
+            };
+            complete &= new_index.is_some();
+            table[index as usize] = new_index;
+        }
+        PaletteRemap { table, complete }
+    }
+}
+
+fn addressable_colors(palette: &ColorPalette) -> impl Iterator<Item = (u8, [u8; 4])> + '_ {
+    palette
+        .iter()
+        .filter(|(index, _)| *index <= u8::MAX as u32)
+        .map(|(index, entry)| (index as u8, entry.raw_rgba8()))
+}
+
+/// # Palette sorting
+impl AsepriteFile {
+    /// A copy of the palette with its entries sorted, and the table that
+    /// moves pixels to the new indices, see
+    /// [apply_palette_remap](AsepriteFile::apply_palette_remap). Entries keep
+    /// their names, and colors that sort equally keep their order. The
+    /// [transparent color index](AsepriteFile::transparent_color_index) of
+    /// indexed files is not moved, and no other color moves to it. In sparse
+    /// palettes, this can leave a gap before the transparent entry.
+    ///
+    /// ```
+    /// # use asefile::{AsepriteFile, PaletteOrder};
+    /// # let mut ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/indexed.aseprite")).unwrap();
+    /// let (palette, remap) = ase.sorted_palette(PaletteOrder::Luminance).unwrap();
+    /// ase.apply_palette_remap(&remap).unwrap();
+    /// for (index, entry) in &palette {
+    ///     ase.set_palette_color(index, entry.raw_rgba8()).unwrap();
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the file has no
+    /// palette, and [AsepriteParseError::UnsupportedFeature] for
+    /// [PaletteOrder::Usage] in files that are not indexed.
+    pub fn sorted_palette(&self, order: PaletteOrder) -> Result<(ColorPalette, PaletteRemap)> {
+        let palette = self.palette.as_ref().ok_or_else(|| {
+            AsepriteParseError::InvalidInput("The file has no palette".to_owned())
+        })?;
+        let transparent_index = self.transparent_color_index().map(u32::from);
+        let mut entries: Vec<(u32, &ColorPaletteEntry)> = palette
+            .iter()
+            .filter(|(index, _)| Some(*index) != transparent_index)
+            .collect();
+        match order {
+            PaletteOrder::Hue => entries.sort_by(|(_, a), (_, b)| compare_hue(a.rgba8, b.rgba8)),
+            PaletteOrder::Luminance => entries.sort_by_key(|(_, entry)| luminance(entry.rgba8)),
+            PaletteOrder::Usage => {
+                let usage = self.index_usage()?;
+                entries.sort_by_key(|(index, _)| {
+                    std::cmp::Reverse(usage.get(*index as usize).copied().unwrap_or(0))
+                });
+            }
+        }
+        let new_indices = (0..).filter(|index| Some(*index) != transparent_index);
+        let mut entries: Vec<(u32, (u32, &ColorPaletteEntry))> = new_indices.zip(entries).collect();
+        if let Some(index) = transparent_index {
+            if let Some(entry) = palette.color(index) {
+                entries.push((index, (index, entry)));
+            }
+        }
+
+        let mut table = vec![None; 256];
+        let mut sorted = ColorPalette {
+            entries: Default::default(),
+        };
+        for (new_index, (old_index, entry)) in entries {
+            if let (Ok(old), Ok(new)) = (u8::try_from(old_index), u8::try_from(new_index)) {
+                table[old as usize] = Some(new);
+            }
+            sorted.entries.insert(
+                new_index,
+                ColorPaletteEntry {
+                    id: new_index,
+                    rgba8: entry.rgba8,
+                    name: entry.name.clone(),
+                },
+            );
+        }
+        let complete = palette
+            .iter()
+            .all(|(index, _)| index > u8::MAX as u32 || table[index as usize].is_some());
+        Ok((sorted, PaletteRemap { table, complete }))
+    }
+
+    // The number of pixels with each palette index.
+    fn index_usage(&self) -> Result<Vec<u64>> {
+        if self.transparent_color_index().is_none() {
+            return Err(AsepriteParseError::UnsupportedFeature(format!(
+                "Cannot count palette indices in a file with pixel format {:?}",
+                self.pixel_format
+            )));
+        }
+        let mut usage = vec![0; 256];
+        let mut count = |indices: &[u8]| {
+            for index in indices {
+                usage[*index as usize] += 1;
+            }
+        };
+        self.framedata.decode_all()?;
+        for frame in 0..self.num_frames() {
+            if let Pixels::Indexed(indices) = self.framedata.frame_pixels(frame as u16) {
+                count(indices);
+            }
+        }
+        for tileset in self.tilesets.map().values() {
+            if let Some(Pixels::Indexed(indices)) = &tileset.pixels {
+                count(indices);
+            }
+        }
+        Ok(usage)
+    }
+}
+
+// Rec. 709 weights, scaled to integers.
+fn luminance(rgba: [u8; 4]) -> u32 {
+    2126 * rgba[0] as u32 + 7152 * rgba[1] as u32 + 722 * rgba[2] as u32
+}
+
+// Grays first, then by hue, then by luminance.
+fn compare_hue(a: [u8; 4], b: [u8; 4]) -> Ordering {
+    let hue = |rgba: [u8; 4]| {
+        let [r, g, b] = [rgba[0] as f32, rgba[1] as f32, rgba[2] as f32];
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+        if delta == 0.0 {
+            return None;
+        }
+        let sector = if max == r {
+            (g - b) / delta
+        } else if max == g {
+            (b - r) / delta + 2.0
+        } else {
+            (r - g) / delta + 4.0
+        };
+        Some(sector.rem_euclid(6.0))
+    };
+    hue(a)
+        .partial_cmp(&hue(b))
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| luminance(a).cmp(&luminance(b)))
+}
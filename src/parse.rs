@@ -1,21 +1,30 @@
 use crate::cel::CelId;
+#[cfg(feature = "std")]
+use crate::external_file::ExternalResolver;
 use crate::external_file::{ExternalFile, ExternalFilesById};
+use crate::file::{Grid, RawChunk};
+use crate::io::Read;
 use crate::layer::{LayerData, LayersData};
-use crate::reader::AseReader;
-use crate::slice::Slice;
-use crate::tileset::{Tileset, TilesetsById};
+use crate::limits::Budget;
+use crate::reader::{AseReader, CountingReader};
+use crate::slice::{Slice, SliceNames};
+use crate::tileset::{Tileset, TilesetId, TilesetsById};
 use crate::user_data::UserData;
-use crate::{error::AsepriteParseError, AsepriteFile, PixelFormat};
-use log::debug;
-use std::io::Read;
+use crate::{error::AsepriteParseError, AsepriteFile, ParseWarning, PixelFormat, ReadOptions};
+use alloc::{borrow::ToOwned, format, string::ToString, vec, vec::Vec};
+use log::{debug, warn};
 
 use crate::Result;
 use crate::{cel, color_profile, layer, palette, slice, tags, user_data, Tag};
 
 struct ParseInfo {
     palette: Option<palette::ColorPalette>,
+    // Whether `palette` comes from a palette chunk rather than old palette
+    // chunks.
+    has_palette_chunk: bool,
     color_profile: Option<color_profile::ColorProfile>,
     layers: Vec<LayerData>,
+    layers_have_uuid: bool,
     framedata: cel::CelsData, // Vec<Vec<cel::RawCel>>,
     frame_times: Vec<u16>,
     tags: Option<Vec<Tag>>,
@@ -24,15 +33,24 @@ struct ParseInfo {
     sprite_user_data: Option<UserData>,
     user_data_context: Option<UserDataContext>,
     slices: Vec<Slice>,
+    raw_chunks: Vec<RawChunk>,
+    warnings: Vec<ParseWarning>,
 }
 
 impl ParseInfo {
-    fn new(num_frames: u16, default_frame_time: u16) -> Self {
+    fn new(
+        num_frames: u16,
+        default_frame_time: u16,
+        pixel_format: PixelFormat,
+        layers_have_uuid: bool,
+    ) -> Self {
         Self {
             palette: None,
+            has_palette_chunk: false,
             color_profile: None,
             layers: Vec::new(),
-            framedata: cel::CelsData::new(num_frames as u32),
+            layers_have_uuid,
+            framedata: cel::CelsData::new(num_frames as u32, pixel_format),
             frame_times: vec![default_frame_time; num_frames as usize],
             tags: None,
             external_files: ExternalFilesById::new(),
@@ -40,6 +58,8 @@ impl ParseInfo {
             sprite_user_data: None,
             user_data_context: None,
             slices: Vec::new(),
+            raw_chunks: Vec::new(),
+            warnings: Vec::new(),
         }
     }
     fn add_cel(&mut self, frame_id: u16, cel: cel::RawCel) -> Result<()> {
@@ -81,13 +101,15 @@ impl ParseInfo {
         self.user_data_context = Some(UserDataContext::TagIndex(tag_index + 1));
         Ok(())
     }
-    fn add_user_data(&mut self, user_data: UserData) -> Result<()> {
-        let user_data_context = self.user_data_context.ok_or_else(|| {
-            AsepriteParseError::InvalidInput(
-                "Found dangling user data chunk. Expected a previous chunk to attach user data"
-                    .into(),
-            )
-        })?;
+    fn add_user_data(&mut self, frame: u16, user_data: UserData) -> Result<()> {
+        let user_data_context = match self.user_data_context {
+            Some(context) => context,
+            None => {
+                // There is no previous chunk to attach the user data to.
+                self.warnings.push(ParseWarning::UnusedUserData { frame });
+                return Ok(());
+            }
+        };
         match user_data_context {
             UserDataContext::CelId(cel_id) => {
                 let cel = self.framedata.cel_mut(&cel_id).ok_or_else(|| {
@@ -122,17 +144,170 @@ impl ParseInfo {
                 })?;
                 slice.user_data = Some(user_data);
             }
+            UserDataContext::Tileset(tileset_id) => {
+                self.set_tileset_user_data(frame, user_data, tileset_id, None)?;
+            }
+            UserDataContext::TileIndex(tileset_id, tile_index) => {
+                self.set_tileset_user_data(frame, user_data, tileset_id, Some(tile_index))?;
+            }
         }
         Ok(())
     }
+    fn add_tileset(&mut self, tileset: Tileset) {
+        self.user_data_context = Some(UserDataContext::Tileset(*tileset.id()));
+        self.tilesets.add(tileset);
+    }
+    fn set_tileset_user_data(
+        &mut self,
+        frame: u16,
+        user_data: UserData,
+        tileset_id: TilesetId,
+        tile_index: Option<u32>,
+    ) -> Result<()> {
+        let tileset = self.tilesets.get_mut(&tileset_id).ok_or_else(|| {
+            AsepriteParseError::InternalError(format!(
+                "Invalid tileset id stored in chunk context: {}",
+                tileset_id
+            ))
+        })?;
+        let next_tile = match tile_index {
+            None => {
+                tileset.user_data = Some(user_data);
+                0
+            }
+            Some(tile_index) => {
+                if tile_index >= tileset.tile_count {
+                    // More user data chunks than tiles.
+                    self.warnings.push(ParseWarning::UnusedUserData { frame });
+                    return Ok(());
+                }
+                let tile_user_data = &mut tileset.tile_user_data;
+                tile_user_data.resize_with(tile_index as usize, || None);
+                tile_user_data.push(Some(user_data));
+                tile_index + 1
+            }
+        };
+        self.user_data_context = Some(UserDataContext::TileIndex(tileset_id, next_tile));
+        Ok(())
+    }
+    // Drops everything after the first `num_frames` frames, including the
+    // partial data of the frame that failed to parse.
+    fn truncate(&mut self, num_frames: u16) {
+        self.framedata.truncate(num_frames as u32);
+        self.frame_times.truncate(num_frames as usize);
+        if let Some(tags) = &mut self.tags {
+            tags.retain(|tag| tag.from_frame() < num_frames as u32);
+            for tag in tags {
+                tag.clamp_to(num_frames);
+            }
+        }
+        self.raw_chunks.retain(|chunk| chunk.frame < num_frames);
+        // The user data of the next chunk may belong to something that was
+        // dropped.
+        self.user_data_context = None;
+    }
+    // A Cel Extra chunk belongs to the cel before it. Without one it is kept
+    // like other unsupported chunks.
+    fn add_cel_extra(&mut self, frame_id: u16, index: u32, data: Vec<u8>) {
+        if let Some(UserDataContext::CelId(cel_id)) = self.user_data_context {
+            if let Some(cel) = self.framedata.cel_mut(&cel_id) {
+                cel.extra = Some(data);
+                return;
+            }
+        }
+        debug!("Found Cel Extra chunk without a cel");
+        self.add_raw_chunk(frame_id, index, ChunkType::CelExtra, data);
+    }
+    fn add_unknown_chunk(&mut self, frame: u16, index: u32, chunk_type: u16, data: Vec<u8>) {
+        self.warnings
+            .push(ParseWarning::UnknownChunk { frame, chunk_type });
+        self.add_raw_chunk(frame, index, ChunkType::Unknown(chunk_type), data);
+    }
+    fn add_raw_chunk(&mut self, frame: u16, index: u32, chunk_type: ChunkType, data: Vec<u8>) {
+        self.raw_chunks.push(RawChunk {
+            frame,
+            index,
+            chunk_type: chunk_type.code(),
+            data,
+        });
+    }
     fn add_slice(&mut self, slice: Slice) {
         let context_idx = self.slices.len();
         self.slices.push(slice);
         self.user_data_context = Some(UserDataContext::SliceIndex(context_idx as u32));
     }
+    // Copies the tiles of tilesets that link to another file from that file,
+    // see `ReadOptions::external_resolver`.
+    #[cfg(feature = "std")]
+    fn resolve_external_tilesets(
+        &mut self,
+        resolver: &ExternalResolver,
+        pixel_format: PixelFormat,
+        options: &ReadOptions,
+    ) -> Result<()> {
+        let external_options = ReadOptions {
+            limits: options.limits,
+            ..ReadOptions::default()
+        };
+        for tileset in self.tilesets.map_mut().values_mut() {
+            let reference = match &tileset.external_file {
+                Some(reference) if tileset.pixels.is_none() => reference,
+                _ => continue,
+            };
+            let external_file = self
+                .external_files
+                .get(&reference.external_file_id)
+                .ok_or_else(|| {
+                    AsepriteParseError::InvalidInput(format!(
+                        "Tileset {} links to missing external file {}",
+                        tileset.id.0,
+                        reference.external_file_id.value()
+                    ))
+                })?;
+            let data = match (resolver.0)(external_file) {
+                Some(data) => data,
+                None => continue,
+            };
+            let context = format!("external file {}", external_file.name());
+            let mut external = AsepriteFile::read_with_options(&data[..], &external_options)
+                .map_err(|err| err.with_context(&context))?;
+            let source = external
+                .tilesets
+                .get_mut(&reference.tileset_id)
+                .ok_or_else(|| {
+                    AsepriteParseError::InvalidInput(format!(
+                        "Tileset {} not found in {}",
+                        reference.tileset_id.0, context
+                    ))
+                })?;
+            let source_size = (source.tile_size.width, source.tile_size.height);
+            if source_size != (tileset.tile_size.width, tileset.tile_size.height)
+                || external.pixel_format.bytes_per_pixel() != pixel_format.bytes_per_pixel()
+            {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Tileset {} in {} does not match tileset {}: tile size {}x{}, {:?}",
+                    reference.tileset_id.0,
+                    context,
+                    tileset.id.0,
+                    source_size.0,
+                    source_size.1,
+                    external.pixel_format
+                )));
+            }
+            tileset.tile_count = source.tile_count;
+            tileset.pixels = source.pixels.take();
+        }
+        Ok(())
+    }
     // Validate moves the ParseInfo data into an intermediate ValidatedParseInfo struct,
     // which is then used to create the AsepriteFile.
-    fn validate(self, pixel_format: &PixelFormat) -> Result<ValidatedParseInfo> {
+    fn validate(mut self, pixel_format: &PixelFormat) -> Result<ValidatedParseInfo> {
+        for cel_id in self.framedata.remove_cels_without_layer(self.layers.len()) {
+            self.warnings.push(ParseWarning::MissingLayer {
+                frame: cel_id.frame,
+                layer: cel_id.layer,
+            });
+        }
         let layers = LayersData::from_vec(self.layers)?;
 
         let tilesets = self.tilesets;
@@ -142,6 +317,8 @@ impl ParseInfo {
 
         let framedata = self.framedata;
         framedata.validate(&layers, palette.as_ref())?;
+        let mut warnings = self.warnings;
+        warnings.extend(framedata.tile_ids_out_of_range(&layers, &tilesets));
 
         Ok(ValidatedParseInfo {
             layers,
@@ -149,10 +326,13 @@ impl ParseInfo {
             framedata,
             external_files: self.external_files,
             palette,
+            color_profile: self.color_profile,
             tags: self.tags.unwrap_or_default(),
             frame_times: self.frame_times,
             sprite_user_data: self.sprite_user_data,
             slices: self.slices,
+            raw_chunks: self.raw_chunks,
+            warnings,
         })
     }
 }
@@ -163,17 +343,62 @@ struct ValidatedParseInfo {
     framedata: cel::CelsData,
     external_files: ExternalFilesById,
     palette: Option<palette::ColorPalette>,
+    color_profile: Option<color_profile::ColorProfile>,
     tags: Vec<Tag>,
     frame_times: Vec<u16>,
     sprite_user_data: Option<UserData>,
     slices: Vec<Slice>,
+    raw_chunks: Vec<RawChunk>,
+    warnings: Vec<ParseWarning>,
 }
 
 // file format docs: https://github.com/aseprite/aseprite/blob/master/docs/ase-file-specs.md
 // v1.3 spec diff doc: https://gist.github.com/dacap/35f3b54fbcd021d099e0166a4f295bab
-pub fn read_aseprite<R: Read>(input: R) -> Result<AsepriteFile> {
+pub fn read_aseprite<R: Read>(input: R, options: &ReadOptions) -> Result<AsepriteFile> {
+    let mut input = CountingReader::new(input);
+    let (mut file, declared_size) = parse_file(&mut input, options)?;
+    let end_of_frames = input.count();
+    // The rest of a truncated file is not trailing data.
+    if !is_truncated(&file.warnings) {
+        input.read_to_end(&mut file.trailing_data)?;
+    }
+    check_end_of_file(
+        declared_size,
+        end_of_frames,
+        &file.trailing_data,
+        options,
+        &mut file.warnings,
+    )?;
+    Ok(file)
+}
+
+// Like `read_aseprite` but stops at the end of the last frame. Also returns the
+// number of bytes read.
+pub fn read_next<R: Read>(input: R) -> Result<(AsepriteFile, u64)> {
+    let mut input = CountingReader::new(input);
+    let options = ReadOptions::new();
+    let (mut file, declared_size) = parse_file(&mut input, &options)?;
+    let end_of_frames = input.count();
+    check_end_of_file(
+        declared_size,
+        end_of_frames,
+        &[],
+        &options,
+        &mut file.warnings,
+    )?;
+    Ok((file, end_of_frames))
+}
+
+// Reads the header and all frames. Returns the file, whose warnings do not
+// include those of `check_end_of_file` yet, and the file size declared in the
+// header.
+#[cfg_attr(not(feature = "std"), allow(unused_variables))]
+fn parse_file<R: Read>(
+    input: &mut CountingReader<R>,
+    options: &ReadOptions,
+) -> Result<(AsepriteFile, u32)> {
     let mut reader = AseReader::with(input);
-    let _size = reader.dword()?;
+    let size = reader.dword()?;
     let magic_number = reader.word()?;
     if magic_number != 0xA5E0 {
         return Err(AsepriteParseError::InvalidInput(format!(
@@ -186,7 +411,7 @@ pub fn read_aseprite<R: Read>(input: R) -> Result<AsepriteFile> {
     let width = reader.word()?;
     let height = reader.word()?;
     let color_depth = reader.word()?;
-    let _flags = reader.dword()?;
+    let flags = reader.dword()?;
     let default_frame_time = reader.word()?;
     let _placeholder1 = reader.dword()?;
     let _placeholder2 = reader.dword()?;
@@ -196,25 +421,101 @@ pub fn read_aseprite<R: Read>(input: R) -> Result<AsepriteFile> {
     let _num_colors = reader.word()?;
     let pixel_width = reader.byte()?;
     let pixel_height = reader.byte()?;
-    let _grid_x = reader.short()?;
-    let _grid_y = reader.short()?;
-    let _grid_width = reader.word()?;
-    let _grid_height = reader.word()?;
+    let grid = Grid {
+        x: reader.short()?,
+        y: reader.short()?,
+        width: reader.word()?,
+        height: reader.word()?,
+    };
     reader.skip_reserved(84)?;
 
-    if !(pixel_width == 1 && pixel_height == 1) {
-        return Err(AsepriteParseError::UnsupportedFeature(
-            "Only pixel width:height ratio of 1:1 supported".to_owned(),
+    if width == 0 || height == 0 {
+        return Err(AsepriteParseError::InvalidInput(format!(
+            "Invalid canvas size: {}x{}",
+            width, height
+        )));
+    }
+    if num_frames == 0 {
+        return Err(AsepriteParseError::InvalidInput(
+            "File contains no frames".to_owned(),
         ));
     }
+    let mut budget = Budget::new(options.limits);
+    budget.check_canvas(width, height)?;
+    budget.check_frames(num_frames)?;
+
+    // Zero in files written before the pixel ratio was introduced.
+    let pixel_ratio = if pixel_width == 0 || pixel_height == 0 {
+        (1, 1)
+    } else {
+        (pixel_width, pixel_height)
+    };
 
-    let mut parse_info = ParseInfo::new(num_frames, default_frame_time);
+    trace_span!(
+        DEBUG,
+        "read_aseprite",
+        width,
+        height,
+        num_frames,
+        color_depth
+    );
 
     let pixel_format = parse_pixel_format(color_depth, transparent_color_index)?;
 
-    for frame_id in 0..num_frames {
+    let layers_have_uuid = flags & HEADER_FLAG_LAYER_UUID != 0;
+    let mut parse_info = ParseInfo::new(
+        num_frames,
+        default_frame_time,
+        pixel_format,
+        layers_have_uuid,
+    );
+    #[cfg(feature = "std")]
+    if options.metadata_only {
+        parse_info.framedata = cel::CelsData::new_skipped(num_frames as u32, pixel_format);
+    } else if options.lazy || cfg!(feature = "rayon") {
+        // With rayon, the frames are decoded in parallel after parsing.
+        parse_info.framedata =
+            cel::CelsData::new_lazy(num_frames as u32, pixel_format, options.shared.clone());
+    }
+
+    let declared_frames = num_frames;
+    let mut num_frames = num_frames;
+    for frame_id in 0..declared_frames {
         // println!("--- Frame {} -------", frame_id);
-        parse_frame(&mut reader, frame_id, pixel_format, &mut parse_info)?;
+        let result = parse_frame(
+            &mut reader,
+            frame_id,
+            pixel_format,
+            &mut parse_info,
+            &mut budget,
+        );
+        match result {
+            Ok(()) => {}
+            // Keep the frames before the one that failed.
+            Err(err) if options.partial && frame_id > 0 => {
+                warn!("Failed to parse frame {}: {}", frame_id, err);
+                parse_info.truncate(frame_id);
+                parse_info.warnings.push(ParseWarning::Truncated {
+                    frames: frame_id,
+                    declared_frames,
+                });
+                num_frames = frame_id;
+                break;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    #[cfg(feature = "std")]
+    if let Some(resolver) = &options.external_resolver {
+        parse_info.resolve_external_tilesets(resolver, pixel_format, options)?;
+    }
+    #[cfg(feature = "std")]
+    parse_info
+        .framedata
+        .set_palette(parse_info.palette.as_ref());
+    #[cfg(feature = "rayon")]
+    if !options.lazy && !options.metadata_only {
+        parse_info.framedata.decode_parallel()?;
     }
 
     let ValidatedParseInfo {
@@ -223,118 +524,268 @@ pub fn read_aseprite<R: Read>(input: R) -> Result<AsepriteFile> {
         framedata,
         external_files,
         palette,
+        color_profile,
         tags,
         frame_times,
         sprite_user_data,
         slices,
+        raw_chunks,
+        warnings,
     } = parse_info.validate(&pixel_format)?;
 
-    Ok(AsepriteFile {
+    let file = AsepriteFile {
         width,
         height,
         num_frames,
         pixel_format,
         palette,
         layers,
+        color_profile,
         frame_times,
         tags,
         framedata,
         external_files,
         tilesets,
         sprite_user_data,
+        slice_names: SliceNames::new(&slices),
         slices,
-    })
+        warnings,
+        trailing_data: Vec::new(),
+        pixel_ratio,
+        grid,
+        raw_chunks,
+        #[cfg(feature = "std")]
+        metadata_only: options.metadata_only,
+        #[cfg(feature = "std")]
+        frame_cache: Default::default(),
+    };
+    Ok((file, size))
+}
+
+// Compares the end of the last frame with the file size in the header and with
+// the end of the input, and adds the results to the `warnings` found while
+// parsing. Strict loading fails if there are any.
+fn check_end_of_file(
+    declared_size: u32,
+    end_of_frames: u64,
+    trailing_data: &[u8],
+    options: &ReadOptions,
+    warnings: &mut Vec<ParseWarning>,
+) -> Result<()> {
+    if !trailing_data.is_empty() {
+        warnings.push(ParseWarning::TrailingData {
+            offset: end_of_frames,
+            len: trailing_data.len() as u64,
+        });
+    }
+    // A truncated file ends where parsing failed, not at the declared size.
+    if declared_size as u64 != end_of_frames && !is_truncated(warnings) {
+        warnings.push(ParseWarning::FileSizeMismatch {
+            declared: declared_size,
+            actual: end_of_frames,
+        });
+    }
+    if options.strict {
+        if let Some(warning) = warnings.first() {
+            return Err(AsepriteParseError::InvalidInput(warning.to_string()));
+        }
+    }
+    for warning in warnings.iter() {
+        warn!("{}", warning);
+    }
+    Ok(())
+}
+
+fn is_truncated(warnings: &[ParseWarning]) -> bool {
+    warnings
+        .iter()
+        .any(|warning| matches!(warning, ParseWarning::Truncated { .. }))
 }
 
 fn parse_frame<R: Read>(
-    reader: &mut AseReader<R>,
+    reader: &mut AseReader<&mut CountingReader<R>>,
     frame_id: u16,
     pixel_format: PixelFormat,
     parse_info: &mut ParseInfo,
+    budget: &mut Budget,
 ) -> Result<()> {
-    let num_bytes = reader.dword()?;
-    let magic_number = reader.word()?;
-    if magic_number != 0xF1FA {
-        return Err(AsepriteParseError::InvalidInput(format!(
-            "Invalid magic number for frame: {:x} != {:x}",
-            magic_number, 0xF1FA
-        )));
+    let frame_offset = reader.position();
+    let FrameHeader {
+        num_bytes,
+        num_chunks,
+        duration,
+    } = FrameHeader::read(reader).map_err(|err| {
+        err.with_context(&format!(
+            "header of frame {} at offset {}",
+            frame_id, frame_offset
+        ))
+    })?;
+
+    // Frames without a duration last as long as the deprecated speed in the
+    // file header, which frame_times is initialized with.
+    if duration != 0 {
+        parse_info.frame_times[frame_id as usize] = duration;
     }
-    let old_num_chunks = reader.word()?;
-    let frame_duration_ms = reader.word()?;
-    let _placeholder = reader.word()?;
-    let new_num_chunks = reader.dword()?;
 
-    parse_info.frame_times[frame_id as usize] = frame_duration_ms;
+    let bytes_available = num_bytes as i64 - FRAME_HEADER_SIZE;
 
-    let num_chunks = if new_num_chunks == 0 {
-        old_num_chunks as u32
-    } else {
-        new_num_chunks
-    };
+    trace_span!(
+        DEBUG,
+        "parse_frame",
+        frame = frame_id,
+        num_chunks,
+        num_bytes
+    );
+
+    let chunks = Chunk::read_all(num_chunks, bytes_available, reader, frame_id)?;
+
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let chunk_type = chunk.chunk_type.clone();
+        let offset = chunk.offset - CHUNK_HEADER_SIZE as u64;
+        let index = index as u32;
+        parse_chunk(chunk, frame_id, index, pixel_format, parse_info, budget).map_err(|err| {
+            err.with_context(&format!(
+                "{:?} chunk of frame {} at offset {}",
+                chunk_type, frame_id, offset
+            ))
+        })?;
+    }
+    // The buffer grew while decoding cels; release the unused capacity.
+    parse_info
+        .framedata
+        .frame_buffer_mut(frame_id)
+        .shrink_to_fit();
 
-    let bytes_available = num_bytes as i64 - FRAME_HEADER_SIZE;
+    Ok(())
+}
 
-    let chunks = Chunk::read_all(num_chunks, bytes_available, reader)?;
+struct FrameHeader {
+    num_bytes: u32,
+    num_chunks: u32,
+    duration: u16,
+}
 
-    for chunk in chunks {
-        let Chunk { chunk_type, data } = chunk;
-        match chunk_type {
-            ChunkType::ColorProfile => {
-                let profile = color_profile::parse_chunk(&data)?;
-                parse_info.color_profile = Some(profile);
-            }
-            ChunkType::Palette => {
-                let palette = palette::parse_chunk(&data)?;
-                parse_info.palette = Some(palette);
-            }
-            ChunkType::Layer => {
-                let layer_data = layer::parse_chunk(&data)?;
-                parse_info.add_layer(layer_data);
-            }
-            ChunkType::Cel => {
-                let cel = cel::parse_chunk(&data, pixel_format)?;
-                parse_info.add_cel(frame_id, cel)?;
-            }
-            ChunkType::ExternalFiles => {
-                let files = ExternalFile::parse_chunk(&data)?;
-                parse_info.add_external_files(files);
-            }
-            ChunkType::Tags => {
-                let tags = tags::parse_chunk(&data)?;
-                if frame_id == 0 {
-                    parse_info.add_tags(tags);
-                } else {
-                    debug!("Ignoring tags outside of frame 0");
-                }
-            }
-            ChunkType::Slice => {
-                let slice = slice::parse_chunk(&data)?;
-                parse_info.add_slice(slice);
-                //println!("Slice: {:#?}", slice);
-            }
-            ChunkType::UserData => {
-                let user_data = user_data::parse_userdata_chunk(&data)?;
-                parse_info.add_user_data(user_data)?;
-                //println!("Userdata: {:#?}", ud);
-            }
-            ChunkType::OldPalette04 | ChunkType::OldPalette11 => {
-                // An old palette chunk precedes the sprite UserData chunk.
-                // Update the chunk context to reflect the OldPalette chunk.
-                parse_info.user_data_context = Some(UserDataContext::OldPalette);
+impl FrameHeader {
+    fn read<R: Read>(reader: &mut AseReader<R>) -> Result<Self> {
+        let num_bytes = reader.dword()?;
+        let magic_number = reader.word()?;
+        if magic_number != 0xF1FA {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Invalid magic number for frame: {:x} != {:x}",
+                magic_number, 0xF1FA
+            )));
+        }
+        let old_num_chunks = reader.word()?;
+        let duration = reader.word()?;
+        let _placeholder = reader.word()?;
+        let new_num_chunks = reader.dword()?;
+        let num_chunks = if new_num_chunks == 0 {
+            old_num_chunks as u32
+        } else {
+            new_num_chunks
+        };
+        Ok(Self {
+            num_bytes,
+            num_chunks,
+            duration,
+        })
+    }
+}
 
-                // parse_info.sprite_user_data = &data.user_data;
+// `index` is the position of the chunk in its frame.
+fn parse_chunk(
+    chunk: Chunk,
+    frame_id: u16,
+    index: u32,
+    pixel_format: PixelFormat,
+    parse_info: &mut ParseInfo,
+    budget: &mut Budget,
+) -> Result<()> {
+    #[cfg_attr(not(feature = "std"), allow(unused_variables))]
+    let Chunk {
+        chunk_type,
+        offset,
+        data,
+    } = chunk;
+    match chunk_type {
+        ChunkType::ColorProfile => {
+            let profile = color_profile::parse_chunk(&data)?;
+            parse_info.color_profile = Some(profile);
+        }
+        ChunkType::Palette => {
+            let palette = palette::parse_chunk(&data)?;
+            parse_info.palette = Some(palette);
+            parse_info.has_palette_chunk = true;
+        }
+        ChunkType::Layer => {
+            let layer_data = layer::parse_chunk(&data, parse_info.layers_have_uuid)?;
+            let unknown_flags = layer::unknown_flags(&data);
+            if unknown_flags != 0 {
+                parse_info.warnings.push(ParseWarning::UnknownLayerFlags {
+                    layer: parse_info.layers.len() as u32,
+                    flags: unknown_flags,
+                });
             }
-            ChunkType::Tileset => {
-                let tileset = Tileset::parse_chunk(&data, pixel_format)?;
-                parse_info.tilesets.add(tileset);
+            parse_info.add_layer(layer_data);
+        }
+        ChunkType::Cel => {
+            let pixels = parse_info.framedata.frame_buffer_mut(frame_id);
+            #[cfg(feature = "std")]
+            pixels.set_chunk(offset as usize..offset as usize + data.len());
+            let cel = cel::parse_chunk(&data, frame_id, pixels, budget)?;
+            parse_info.add_cel(frame_id, cel)?;
+        }
+        ChunkType::ExternalFiles => {
+            let files = ExternalFile::parse_chunk(&data)?;
+            parse_info.add_external_files(files);
+        }
+        ChunkType::Tags => {
+            let tags = tags::parse_chunk(&data)?;
+            if frame_id == 0 {
+                parse_info.add_tags(tags);
+            } else {
+                debug!("Ignoring tags outside of frame 0");
             }
-            ChunkType::CelExtra | ChunkType::Mask | ChunkType::Path => {
-                debug!("Ignoring unsupported chunk type: {:?}", chunk_type);
+        }
+        ChunkType::Slice => {
+            let slice = slice::parse_chunk(&data)?;
+            parse_info.add_slice(slice);
+            //println!("Slice: {:#?}", slice);
+        }
+        ChunkType::UserData => {
+            let user_data = user_data::parse_userdata_chunk(&data, &parse_info.external_files)?;
+            parse_info.add_user_data(frame_id, user_data)?;
+            //println!("Userdata: {:#?}", ud);
+        }
+        ChunkType::OldPalette04 | ChunkType::OldPalette11 => {
+            // Files written before Aseprite 1.1 only have old palette chunks.
+            // Newer files also have a palette chunk, which takes precedence.
+            if !parse_info.has_palette_chunk {
+                let six_bit = chunk_type == ChunkType::OldPalette11;
+                palette::apply_old_chunk(&data, six_bit, &mut parse_info.palette)?;
             }
+            // An old palette chunk precedes the sprite UserData chunk.
+            // Update the chunk context to reflect the OldPalette chunk.
+            parse_info.user_data_context = Some(UserDataContext::OldPalette);
+        }
+        ChunkType::Tileset => {
+            let tileset = Tileset::parse_chunk(&data, pixel_format, budget)?;
+            parse_info.add_tileset(tileset);
+        }
+        ChunkType::CelExtra => {
+            parse_info.add_cel_extra(frame_id, index, data);
+        }
+        ChunkType::Mask | ChunkType::Path => {
+            trace_event!(TRACE, ?chunk_type, "ignoring chunk");
+            debug!("Ignoring unsupported chunk type: {:?}", chunk_type);
+            parse_info.add_raw_chunk(frame_id, index, chunk_type, data);
+        }
+        ChunkType::Unknown(code) => {
+            trace_event!(TRACE, chunk_type = code, "ignoring unknown chunk");
+            debug!("Ignoring unknown chunk type: 0x{:x}", code);
+            parse_info.add_unknown_chunk(frame_id, index, code, data);
         }
     }
-
     Ok(())
 }
 
@@ -345,6 +796,10 @@ enum UserDataContext {
     OldPalette,
     TagIndex(u16),
     SliceIndex(u32),
+    // The user data chunk directly after a tileset chunk belongs to the
+    // tileset, the following ones to its tiles.
+    Tileset(TilesetId),
+    TileIndex(TilesetId, u32),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -363,63 +818,112 @@ enum ChunkType {
     Slice,
     ExternalFiles,
     Tileset,
+    // A chunk type that this crate does not know, with its code.
+    Unknown(u16),
 }
 
-fn parse_chunk_type(chunk_type: u16) -> Result<ChunkType> {
+impl ChunkType {
+    fn code(&self) -> u16 {
+        match self {
+            ChunkType::OldPalette04 => 0x0004,
+            ChunkType::OldPalette11 => 0x0011,
+            ChunkType::Layer => 0x2004,
+            ChunkType::Cel => 0x2005,
+            ChunkType::CelExtra => 0x2006,
+            ChunkType::ColorProfile => 0x2007,
+            ChunkType::ExternalFiles => 0x2008,
+            ChunkType::Mask => 0x2016,
+            ChunkType::Path => 0x2017,
+            ChunkType::Tags => 0x2018,
+            ChunkType::Palette => 0x2019,
+            ChunkType::UserData => 0x2020,
+            ChunkType::Slice => 0x2022,
+            ChunkType::Tileset => 0x2023,
+            ChunkType::Unknown(code) => *code,
+        }
+    }
+}
+
+fn parse_chunk_type(chunk_type: u16) -> ChunkType {
     match chunk_type {
-        0x0004 => Ok(ChunkType::OldPalette04),
-        0x0011 => Ok(ChunkType::OldPalette11),
-        0x2004 => Ok(ChunkType::Layer),
-        0x2005 => Ok(ChunkType::Cel),
-        0x2006 => Ok(ChunkType::CelExtra),
-        0x2007 => Ok(ChunkType::ColorProfile),
-        0x2008 => Ok(ChunkType::ExternalFiles),
-        0x2016 => Ok(ChunkType::Mask),
-        0x2017 => Ok(ChunkType::Path),
-        0x2018 => Ok(ChunkType::Tags),
-        0x2019 => Ok(ChunkType::Palette),
-        0x2020 => Ok(ChunkType::UserData),
-        0x2022 => Ok(ChunkType::Slice),
-        0x2023 => Ok(ChunkType::Tileset),
-        _ => Err(AsepriteParseError::UnsupportedFeature(format!(
-            "Invalid or unsupported chunk type: 0x{:x}",
-            chunk_type
-        ))),
+        0x0004 => ChunkType::OldPalette04,
+        0x0011 => ChunkType::OldPalette11,
+        0x2004 => ChunkType::Layer,
+        0x2005 => ChunkType::Cel,
+        0x2006 => ChunkType::CelExtra,
+        0x2007 => ChunkType::ColorProfile,
+        0x2008 => ChunkType::ExternalFiles,
+        0x2016 => ChunkType::Mask,
+        0x2017 => ChunkType::Path,
+        0x2018 => ChunkType::Tags,
+        0x2019 => ChunkType::Palette,
+        0x2020 => ChunkType::UserData,
+        0x2022 => ChunkType::Slice,
+        0x2023 => ChunkType::Tileset,
+        code => ChunkType::Unknown(code),
     }
 }
 
 const CHUNK_HEADER_SIZE: usize = 6;
 const FRAME_HEADER_SIZE: i64 = 16;
+// Header flag: layer chunks end with the layer's UUID.
+const HEADER_FLAG_LAYER_UUID: u32 = 4;
 
 struct Chunk {
     chunk_type: ChunkType,
+    // Position of the data in the file.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    offset: u64,
     data: Vec<u8>,
 }
 
 impl Chunk {
-    fn read<R: Read>(bytes_available: &mut i64, reader: &mut AseReader<R>) -> Result<Self> {
+    fn read<R: Read>(
+        bytes_available: &mut i64,
+        reader: &mut AseReader<&mut CountingReader<R>>,
+    ) -> Result<Self> {
         let chunk_size = reader.dword()?;
         let chunk_type_code = reader.word()?;
-        let chunk_type = parse_chunk_type(chunk_type_code)?;
+        let chunk_type = parse_chunk_type(chunk_type_code);
 
         check_chunk_bytes(chunk_size, *bytes_available)?;
 
         let chunk_data_bytes = chunk_size as usize - CHUNK_HEADER_SIZE;
+        let offset = reader.position();
         let mut data = vec![0_u8; chunk_data_bytes];
         reader.read_exact(&mut data)?;
         *bytes_available -= chunk_size as i64;
-        Ok(Chunk { chunk_type, data })
+        Ok(Chunk {
+            chunk_type,
+            offset,
+            data,
+        })
     }
     fn read_all<R: Read>(
         count: u32,
         mut bytes_available: i64,
-        reader: &mut AseReader<R>,
+        reader: &mut AseReader<&mut CountingReader<R>>,
+        frame_id: u16,
     ) -> Result<Vec<Self>> {
         let mut chunks: Vec<Chunk> = Vec::new();
-        for _idx in 0..count {
-            let chunk = Self::read(&mut bytes_available, reader)?;
+        for idx in 0..count {
+            let offset = reader.position();
+            let chunk = Self::read(&mut bytes_available, reader).map_err(|err| {
+                err.with_context(&format!(
+                    "chunk {} of frame {} at offset {}",
+                    idx, frame_id, offset
+                ))
+            })?;
             chunks.push(chunk);
         }
+        // Skip any unused bytes at the end of the frame, so the next frame (or
+        // file) starts at the right offset.
+        if bytes_available > 0 {
+            let offset = reader.position();
+            reader.skip(bytes_available as u64).map_err(|err| {
+                err.with_context(&format!("end of frame {} at offset {}", frame_id, offset))
+            })?;
+        }
         Ok(chunks)
     }
 }
@@ -440,7 +944,10 @@ fn check_chunk_bytes(chunk_size: u32, bytes_available: i64) -> Result<()> {
     Ok(())
 }
 
-fn parse_pixel_format(color_depth: u16, transparent_color_index: u8) -> Result<PixelFormat> {
+pub(crate) fn parse_pixel_format(
+    color_depth: u16,
+    transparent_color_index: u8,
+) -> Result<PixelFormat> {
     match color_depth {
         8 => Ok(PixelFormat::Indexed {
             transparent_color_index,
@@ -1,7 +1,29 @@
-use image::{Pixel, Rgba};
+/*!
+Pixels in the color depth of a sprite.
 
+[Frame::image](crate::Frame::image) and [Cel::image](crate::Cel::image)
+convert all pixels to RGBA. Indexed and grayscale sprites can also be read in
+their own color depth, which keeps the palette indices and needs a quarter
+(or half) of the memory. See [Frame::indexed_image](crate::Frame::indexed_image)
+and [Cel::indexed_image](crate::Cel::indexed_image):
+
+```
+# use asefile::AsepriteFile;
+# use std::path::Path;
+# let path = Path::new("./tests/data/indexed.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let image = ase.frame(0).indexed_image().unwrap();
+let index = image.get_pixel(0, 0).value();
+```
+*/
+use crate::image::{Pixel, Rgba};
+
+use crate::io::Read;
 use crate::{reader::AseReader, AsepriteParseError, ColorPalette, PixelFormat, Result};
-use std::{borrow::Cow, io::Read};
+use alloc::{borrow::Cow, format, string::ToString, vec, vec::Vec};
+use core::{hash::Hasher, ops::Range};
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
 
 // From Aseprite file spec:
 // PIXEL: One pixel, depending on the image pixel format:
@@ -9,27 +31,27 @@ use std::{borrow::Cow, io::Read};
 // Indexed: BYTE, Each pixel uses 1 byte (the index).
 // RGBA: BYTE[4], each pixel have 4 bytes in this order Red, Green, Blue, Alpha.
 
-fn read_rgba(chunk: &[u8]) -> Result<Rgba<u8>> {
-    let mut reader = AseReader::new(chunk);
-    let red = reader.byte()?;
-    let green = reader.byte()?;
-    let blue = reader.byte()?;
-    let alpha = reader.byte()?;
-    Ok(Rgba::from_channels(red, green, blue, alpha))
-}
-
-#[derive(Debug, Clone, Copy)]
-pub(crate) struct Grayscale {
+/// A pixel of a grayscale sprite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Grayscale {
     value: u8,
     alpha: u8,
 }
 
 impl Grayscale {
-    fn new(chunk: &[u8]) -> Result<Self> {
-        let mut reader = AseReader::new(chunk);
-        let value = reader.byte()?;
-        let alpha = reader.byte()?;
-        Ok(Self { value, alpha })
+    /// A pixel with the given brightness and alpha value.
+    pub fn new(value: u8, alpha: u8) -> Self {
+        Grayscale { value, alpha }
+    }
+
+    /// The brightness, from black (0) to white (255).
+    pub fn value(&self) -> u8 {
+        self.value
+    }
+
+    /// The alpha value, from transparent (0) to opaque (255).
+    pub fn alpha(&self) -> u8 {
+        self.alpha
     }
 
     pub(crate) fn into_rgba(self) -> Rgba<u8> {
@@ -38,11 +60,19 @@ impl Grayscale {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub(crate) struct Indexed(u8);
+/// A pixel of an indexed sprite: an index into the
+/// [palette](crate::AsepriteFile::palette).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Indexed(u8);
 
 impl Indexed {
-    pub(crate) fn value(&self) -> u8 {
+    /// A pixel with the given palette index.
+    pub fn new(value: u8) -> Self {
+        Indexed(value)
+    }
+
+    /// The palette index.
+    pub fn value(&self) -> u8 {
         self.0
     }
 
@@ -64,87 +94,281 @@ impl Indexed {
     }
 }
 
-fn output_size(pixel_format: PixelFormat, expected_pixel_count: usize) -> usize {
-    pixel_format.bytes_per_pixel() * expected_pixel_count
+/// An image in the color depth of a sprite, see the [module](self)
+/// documentation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Image<P> {
+    width: u32,
+    height: u32,
+    pixels: Vec<P>,
 }
 
+impl<P: Copy> Image<P> {
+    pub(crate) fn from_pixel(width: u32, height: u32, pixel: P) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![pixel; width as usize * height as usize],
+        }
+    }
+
+    /// Width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Width and height of the image in pixels.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// All pixels, in rows from top to bottom.
+    pub fn pixels(&self) -> &[P] {
+        &self.pixels
+    }
+
+    /// All pixels, in rows from top to bottom.
+    pub fn into_pixels(self) -> Vec<P> {
+        self.pixels
+    }
+
+    /// The pixel at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside of the image.
+    pub fn get_pixel(&self, x: u32, y: u32) -> P {
+        self.pixels[self.offset(x, y)]
+    }
+
+    pub(crate) fn put_pixel(&mut self, x: u32, y: u32, pixel: P) {
+        let offset = self.offset(x, y);
+        self.pixels[offset] = pixel;
+    }
+
+    fn offset(&self, x: u32, y: u32) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "Pixel ({}, {}) is outside of the {}x{} image",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+fn output_size(bytes_per_pixel: usize, expected_pixel_count: usize) -> Result<usize> {
+    bytes_per_pixel
+        .checked_mul(expected_pixel_count)
+        .ok_or_else(|| {
+            AsepriteParseError::InvalidInput(format!(
+                "Image data too large: {} pixels",
+                expected_pixel_count
+            ))
+        })
+}
+
+// Decoded pixels of one or more images. Cels store their pixels in one
+// `Pixels` buffer per frame (see `CelsData`) and refer to them by range;
+// tilesets own a buffer for all of their tiles. Indexed pixels are stored as
+// plain palette indices so they can be handed out as byte slices.
 #[derive(Debug)]
 pub(crate) enum Pixels {
     Rgba(Vec<Rgba<u8>>),
     Grayscale(Vec<Grayscale>),
-    Indexed(Vec<Indexed>),
+    Indexed(Vec<u8>),
+}
+
+// A borrowed range of `Pixels`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum PixelsRef<'a> {
+    Rgba(&'a [Rgba<u8>]),
+    Grayscale(&'a [Grayscale]),
+    Indexed(&'a [u8]),
 }
 
 impl Pixels {
-    fn from_bytes(bytes: Vec<u8>, pixel_format: PixelFormat) -> Result<Self> {
+    // An empty buffer. Does not allocate.
+    pub(crate) fn new(pixel_format: PixelFormat) -> Self {
         match pixel_format {
-            PixelFormat::Indexed { .. } => {
-                let pixels = bytes.iter().map(|byte| Indexed(*byte)).collect();
-                Ok(Self::Indexed(pixels))
+            PixelFormat::Rgba => Pixels::Rgba(Vec::new()),
+            PixelFormat::Grayscale => Pixels::Grayscale(Vec::new()),
+            PixelFormat::Indexed { .. } => Pixels::Indexed(Vec::new()),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Pixels::Rgba(v) => v.len(),
+            Pixels::Grayscale(v) => v.len(),
+            Pixels::Indexed(v) => v.len(),
+        }
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Pixels::Rgba(_) => 4,
+            Pixels::Grayscale(_) => 2,
+            Pixels::Indexed(_) => 1,
+        }
+    }
+
+    // Decodes `bytes` and appends the pixels to the buffer. Returns the range
+    // of the new pixels.
+    pub(crate) fn extend_from_bytes(&mut self, bytes: &[u8]) -> Result<Range<usize>> {
+        let start = self.len();
+        match self {
+            Pixels::Indexed(pixels) => {
+                pixels.extend_from_slice(bytes);
             }
-            PixelFormat::Grayscale => {
+            Pixels::Grayscale(pixels) => {
                 if bytes.len() % 2 != 0 {
                     return Err(AsepriteParseError::InvalidInput(
                         "Incorrect length of bytes for Grayscale image data".to_string(),
                     ));
                 }
-                let pixels: Result<Vec<_>> = bytes.chunks_exact(2).map(Grayscale::new).collect();
-                pixels.map(Self::Grayscale)
+                pixels.extend(bytes.chunks_exact(2).map(|chunk| Grayscale {
+                    value: chunk[0],
+                    alpha: chunk[1],
+                }));
             }
-            PixelFormat::Rgba => {
+            Pixels::Rgba(pixels) => {
                 if bytes.len() % 4 != 0 {
                     return Err(AsepriteParseError::InvalidInput(
                         "Incorrect length of bytes for RGBA image data".to_string(),
                     ));
                 }
-                let pixels: Result<Vec<_>> = bytes.chunks_exact(4).map(read_rgba).collect();
-                pixels.map(Self::Rgba)
+                pixels.extend(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|chunk| Rgba::from_channels(chunk[0], chunk[1], chunk[2], chunk[3])),
+                );
             }
         }
+        Ok(start..self.len())
     }
 
-    pub(crate) fn from_raw<T: Read>(
+    pub(crate) fn read_raw<T: Read>(
+        &mut self,
         reader: AseReader<T>,
-        pixel_format: PixelFormat,
         expected_pixel_count: usize,
-    ) -> Result<Self> {
-        let expected_output_size = output_size(pixel_format, expected_pixel_count);
-        reader
-            .take_bytes(expected_output_size)
-            .and_then(|bytes| Self::from_bytes(bytes, pixel_format))
+    ) -> Result<Range<usize>> {
+        let expected_output_size = output_size(self.bytes_per_pixel(), expected_pixel_count)?;
+        let bytes = reader.take_bytes(expected_output_size)?;
+        self.extend_from_bytes(&bytes)
     }
 
-    pub(crate) fn from_compressed<T: Read>(
+    pub(crate) fn read_compressed<T: Read>(
+        &mut self,
         reader: AseReader<T>,
-        pixel_format: PixelFormat,
         expected_pixel_count: usize,
-    ) -> Result<Self> {
-        let expected_output_size = output_size(pixel_format, expected_pixel_count);
-        reader
-            .unzip(expected_output_size)
-            .and_then(|bytes| Self::from_bytes(bytes, pixel_format))
+    ) -> Result<Range<usize>> {
+        let expected_output_size = output_size(self.bytes_per_pixel(), expected_pixel_count)?;
+        let bytes = reader.unzip(expected_output_size)?;
+        self.extend_from_bytes(&bytes)
+    }
+
+    // Appends pixels of the same format. Returns the range of the new pixels.
+    #[cfg(feature = "std")]
+    pub(crate) fn append(&mut self, other: Pixels) -> Result<Range<usize>> {
+        let start = self.len();
+        match (&mut *self, other) {
+            (Pixels::Rgba(v), Pixels::Rgba(other)) => v.extend(other),
+            (Pixels::Grayscale(v), Pixels::Grayscale(other)) => v.extend(other),
+            (Pixels::Indexed(v), Pixels::Indexed(other)) => v.extend(other),
+            _ => {
+                return Err(AsepriteParseError::InternalError(
+                    "Mismatched pixel formats".into(),
+                ))
+            }
+        }
+        Ok(start..self.len())
     }
 
-    pub(crate) fn byte_count(&self) -> usize {
+    pub(crate) fn shrink_to_fit(&mut self) {
         match self {
-            Pixels::Rgba(v) => v.len() * 4,
-            Pixels::Grayscale(v) => v.len() * 2,
-            Pixels::Indexed(v) => v.len(),
+            Pixels::Rgba(v) => v.shrink_to_fit(),
+            Pixels::Grayscale(v) => v.shrink_to_fit(),
+            Pixels::Indexed(v) => v.shrink_to_fit(),
+        }
+    }
+
+    // Panics if the range is out of bounds.
+    pub(crate) fn get(&self, range: Range<usize>) -> PixelsRef<'_> {
+        match self {
+            Pixels::Rgba(v) => PixelsRef::Rgba(&v[range]),
+            Pixels::Grayscale(v) => PixelsRef::Grayscale(&v[range]),
+            Pixels::Indexed(v) => PixelsRef::Indexed(&v[range]),
         }
     }
 
-    // Returns a Borrowed Cow if the Pixels struct already contains Rgba pixels.
-    // Otherwise clones them to create an Owned Cow.
+    pub(crate) fn all(&self) -> PixelsRef<'_> {
+        self.get(0..self.len())
+    }
+}
+
+impl<'a> PixelsRef<'a> {
+    #[cfg(feature = "std")]
+    pub(crate) fn to_pixels(self) -> Pixels {
+        match self {
+            PixelsRef::Rgba(v) => Pixels::Rgba(v.to_vec()),
+            PixelsRef::Grayscale(v) => Pixels::Grayscale(v.to_vec()),
+            PixelsRef::Indexed(v) => Pixels::Indexed(v.to_vec()),
+        }
+    }
+
+    // The pixels as stored in raw cels, i.e., the inverse of
+    // `Pixels::extend_from_bytes`.
+    #[cfg(feature = "std")]
+    pub(crate) fn to_bytes(self) -> Cow<'a, [u8]> {
+        match self {
+            PixelsRef::Rgba(v) => Cow::Owned(v.iter().flat_map(|px| px.0).collect()),
+            PixelsRef::Grayscale(v) => {
+                Cow::Owned(v.iter().flat_map(|px| [px.value, px.alpha]).collect())
+            }
+            PixelsRef::Indexed(v) => Cow::Borrowed(v),
+        }
+    }
+
+    // Feeds the pixel values (and the pixel format) into `state`. Only writes
+    // raw bytes so the result does not depend on the platform's word size or
+    // endianness.
+    pub(crate) fn write_hash<H: Hasher>(self, state: &mut H) {
+        match self {
+            PixelsRef::Rgba(v) => {
+                state.write(&[4]);
+                v.iter().for_each(|px| state.write(&px.0));
+            }
+            PixelsRef::Grayscale(v) => {
+                state.write(&[2]);
+                v.iter().for_each(|px| state.write(&[px.value, px.alpha]));
+            }
+            PixelsRef::Indexed(v) => {
+                state.write(&[1]);
+                state.write(v);
+            }
+        }
+    }
+
+    // Returns a Borrowed Cow if the pixels already are Rgba pixels. Otherwise
+    // converts them to create an Owned Cow.
     pub(crate) fn clone_as_image_rgba(
-        &self,
+        self,
         index_resolver_data: IndexResolverData<'_>,
-    ) -> Cow<Vec<image::Rgba<u8>>> {
+    ) -> Cow<'a, [crate::image::Rgba<u8>]> {
         match self {
-            Pixels::Rgba(rgba) => Cow::Borrowed(rgba),
-            Pixels::Grayscale(grayscale) => {
+            PixelsRef::Rgba(rgba) => Cow::Borrowed(rgba),
+            PixelsRef::Grayscale(grayscale) => {
                 Cow::Owned(grayscale.iter().map(|gs| gs.into_rgba()).collect())
             }
-            Pixels::Indexed(indexed) => {
+            PixelsRef::Indexed(indexed) => {
                 let IndexResolverData {
                     palette,
                     transparent_color_index,
@@ -154,8 +378,9 @@ impl Pixels {
                 let transparent_color_index = transparent_color_index.expect(
                     "Indexed tilemap pixels in non-indexed pixel format. Should have been caught in validation",
                 );
-                let resolver = |px: &Indexed| {
-                    px.as_rgba(palette, transparent_color_index, layer_is_background)
+                let resolver = |px: &u8| {
+                    Indexed(*px)
+                        .as_rgba(palette, transparent_color_index, layer_is_background)
                         .expect("Indexed pixel out of range. Should have been caught in validation")
                 };
                 Cow::Owned(indexed.iter().map(resolver).collect())
@@ -164,6 +389,386 @@ impl Pixels {
     }
 }
 
+// The pixel buffer of a frame, see `CelsData`. Files loaded with
+// `ReadOptions::lazy` keep the image data of the frame's cels as stored in the
+// file and decode all of them when one is first needed.
+#[derive(Debug)]
+pub(crate) enum FramePixels {
+    Decoded(Pixels),
+    #[cfg(feature = "std")]
+    Lazy(Box<LazyPixels>),
+}
+
+impl FramePixels {
+    // An empty buffer that decodes images as soon as they are added.
+    pub(crate) fn new(pixel_format: PixelFormat) -> Self {
+        FramePixels::Decoded(Pixels::new(pixel_format))
+    }
+
+    // An empty buffer that keeps images encoded until they are needed. With
+    // `shared` bytes, the images are not copied but refer to their position
+    // in that buffer, see `set_chunk`.
+    #[cfg(feature = "std")]
+    pub(crate) fn new_lazy(pixel_format: PixelFormat, shared: Option<SharedBytes>) -> Self {
+        FramePixels::Lazy(Box::new(LazyPixels {
+            pixel_format,
+            skip: false,
+            len: 0,
+            palette: None,
+            shared,
+            chunk: 0..0,
+            encoded: Mutex::new(Vec::new()),
+            decoded: OnceLock::new(),
+        }))
+    }
+
+    // An empty buffer that discards the data of images. Their pixels are
+    // transparent.
+    #[cfg(feature = "std")]
+    pub(crate) fn new_skipped(pixel_format: PixelFormat) -> Self {
+        let mut pixels = Self::new_lazy(pixel_format, None);
+        if let FramePixels::Lazy(lazy) = &mut pixels {
+            lazy.skip = true;
+        }
+        pixels
+    }
+
+    // The position in the shared buffer of the chunk whose images are added
+    // next.
+    #[cfg(feature = "std")]
+    pub(crate) fn set_chunk(&mut self, chunk: Range<usize>) {
+        if let FramePixels::Lazy(lazy) = self {
+            lazy.chunk = chunk;
+        }
+    }
+
+    // `reader` holds the rest of the current chunk.
+    pub(crate) fn read_raw(
+        &mut self,
+        reader: AseReader<&[u8]>,
+        expected_pixel_count: usize,
+    ) -> Result<Range<usize>> {
+        match self {
+            FramePixels::Decoded(pixels) => pixels.read_raw(reader, expected_pixel_count),
+            #[cfg(feature = "std")]
+            FramePixels::Lazy(lazy) => {
+                if lazy.skip {
+                    return Ok(lazy.add(
+                        EncodedData::Owned(Vec::new()),
+                        false,
+                        expected_pixel_count,
+                    ));
+                }
+                let bytes_per_pixel = Pixels::new(lazy.pixel_format).bytes_per_pixel();
+                let size = output_size(bytes_per_pixel, expected_pixel_count)?;
+                let data = if lazy.shared.is_some() {
+                    let remaining = reader.remaining();
+                    if remaining.len() < size {
+                        return Err(AsepriteParseError::InvalidInput(format!(
+                            "Invalid data size. Expected: {}, Actual: {}",
+                            size,
+                            remaining.len()
+                        )));
+                    }
+                    let start = lazy.chunk.end - remaining.len();
+                    EncodedData::Shared(start..start + size)
+                } else {
+                    EncodedData::Owned(reader.take_bytes(size)?)
+                };
+                Ok(lazy.add(data, false, expected_pixel_count))
+            }
+        }
+    }
+
+    // `reader` holds the rest of the current chunk.
+    pub(crate) fn read_compressed(
+        &mut self,
+        reader: AseReader<&[u8]>,
+        expected_pixel_count: usize,
+    ) -> Result<Range<usize>> {
+        match self {
+            FramePixels::Decoded(pixels) => pixels.read_compressed(reader, expected_pixel_count),
+            #[cfg(feature = "std")]
+            FramePixels::Lazy(lazy) => {
+                if lazy.skip {
+                    return Ok(lazy.add(
+                        EncodedData::Owned(Vec::new()),
+                        true,
+                        expected_pixel_count,
+                    ));
+                }
+                let data = if lazy.shared.is_some() {
+                    let start = lazy.chunk.end - reader.remaining().len();
+                    EncodedData::Shared(start..lazy.chunk.end)
+                } else {
+                    EncodedData::Owned(reader.rest()?)
+                };
+                Ok(lazy.add(data, true, expected_pixel_count))
+            }
+        }
+    }
+
+    // The pixels of the frame. Decodes them if necessary.
+    pub(crate) fn get(&self) -> &Pixels {
+        match self {
+            FramePixels::Decoded(pixels) => pixels,
+            #[cfg(feature = "std")]
+            FramePixels::Lazy(lazy) => &lazy.decoded().pixels,
+        }
+    }
+
+    // Like `get`, but fails if the pixels could not be decoded.
+    #[cfg(feature = "std")]
+    pub(crate) fn try_get(&self) -> Result<&Pixels> {
+        match self {
+            FramePixels::Decoded(pixels) => Ok(pixels),
+            #[cfg(feature = "std")]
+            FramePixels::Lazy(lazy) => {
+                let decoded = lazy.decoded();
+                match &decoded.error {
+                    None => Ok(&decoded.pixels),
+                    Some(err) => Err(AsepriteParseError::InvalidInput(err.clone())),
+                }
+            }
+        }
+    }
+
+    // The pixels of the frame, unless they still have to be decoded.
+    pub(crate) fn get_if_decoded(&self) -> Option<&Pixels> {
+        match self {
+            FramePixels::Decoded(pixels) => Some(pixels),
+            #[cfg(feature = "std")]
+            FramePixels::Lazy(lazy) => lazy.decoded.get().map(|decoded| &decoded.pixels),
+        }
+    }
+
+    // The pixels of the frame for modification. Decodes them if necessary.
+    #[cfg(feature = "std")]
+    pub(crate) fn get_mut(&mut self) -> &mut Pixels {
+        if let FramePixels::Lazy(lazy) = self {
+            lazy.decoded();
+            let pixels = lazy.decoded.take().expect("Pixels were decoded").pixels;
+            *self = FramePixels::Decoded(pixels);
+        }
+        match self {
+            FramePixels::Decoded(pixels) => pixels,
+            FramePixels::Lazy(_) => unreachable!("Pixels were decoded"),
+        }
+    }
+
+    // Lazily decoded indexed pixels are validated against the palette once
+    // they are decoded.
+    #[cfg(feature = "std")]
+    pub(crate) fn set_palette(&mut self, palette: Option<&ColorPalette>) {
+        if let FramePixels::Lazy(lazy) = self {
+            lazy.palette = palette.map(|palette| {
+                let mut valid = [false; 256];
+                for (index, valid) in valid.iter_mut().enumerate() {
+                    *valid = palette.color(index as u32).is_some();
+                }
+                valid
+            });
+        }
+    }
+
+    // Decodes lazily decoded pixels without storing them. The encoded images
+    // are kept, so they are released on the thread that drops the buffer.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn decode_encoded(&self) -> Option<Result<Pixels>> {
+        match self {
+            FramePixels::Decoded(_) => None,
+            FramePixels::Lazy(lazy) => {
+                let encoded = lazy.encoded.lock().unwrap_or_else(PoisonError::into_inner);
+                Some(lazy.decode(&encoded))
+            }
+        }
+    }
+
+    pub(crate) fn bytes_per_pixel(&self) -> usize {
+        match self {
+            FramePixels::Decoded(pixels) => pixels.bytes_per_pixel(),
+            #[cfg(feature = "std")]
+            FramePixels::Lazy(lazy) => lazy.pixel_format.bytes_per_pixel(),
+        }
+    }
+
+    pub(crate) fn is_indexed(&self) -> bool {
+        match self {
+            FramePixels::Decoded(pixels) => matches!(pixels, Pixels::Indexed(_)),
+            #[cfg(feature = "std")]
+            FramePixels::Lazy(lazy) => matches!(lazy.pixel_format, PixelFormat::Indexed { .. }),
+        }
+    }
+
+    // Frees all images. Images that were not decoded yet are dropped without
+    // decoding them.
+    #[cfg(feature = "image")]
+    pub(crate) fn clear(&mut self) {
+        let empty = match self {
+            FramePixels::Decoded(Pixels::Rgba(_)) => Pixels::Rgba(Vec::new()),
+            FramePixels::Decoded(Pixels::Grayscale(_)) => Pixels::Grayscale(Vec::new()),
+            FramePixels::Decoded(Pixels::Indexed(_)) => Pixels::Indexed(Vec::new()),
+            FramePixels::Lazy(lazy) => Pixels::new(lazy.pixel_format),
+        };
+        *self = FramePixels::Decoded(empty);
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        match self {
+            FramePixels::Decoded(pixels) => pixels.shrink_to_fit(),
+            #[cfg(feature = "std")]
+            FramePixels::Lazy(_) => {}
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub(crate) struct LazyPixels {
+    pixel_format: PixelFormat,
+    // Set for files loaded with `AsepriteFile::read_metadata`.
+    skip: bool,
+    // The number of pixels of all images.
+    len: usize,
+    // Which palette indices exist, for indexed files with a palette.
+    palette: Option<[bool; 256]>,
+    // Set for files loaded with `AsepriteFile::read_shared`.
+    shared: Option<SharedBytes>,
+    // The position of the current chunk in `shared`.
+    chunk: Range<usize>,
+    // Released once the images are decoded.
+    encoded: Mutex<Vec<EncodedImage>>,
+    decoded: OnceLock<DecodedPixels>,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct EncodedImage {
+    data: EncodedData,
+    compressed: bool,
+    pixel_count: usize,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+enum EncodedData {
+    Owned(Vec<u8>),
+    // A range of `LazyPixels::shared`.
+    Shared(Range<usize>),
+}
+
+// The contents of a file that parsed data can refer to instead of copying
+// them.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub(crate) struct SharedBytes(pub(crate) Arc<dyn AsRef<[u8]> + Send + Sync>);
+
+#[cfg(feature = "std")]
+impl SharedBytes {
+    pub(crate) fn get(&self) -> &[u8] {
+        (*self.0).as_ref()
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Debug for SharedBytes {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "SharedBytes({} bytes)", self.get().len())
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct DecodedPixels {
+    pixels: Pixels,
+    // If decoding failed, `pixels` are transparent.
+    error: Option<String>,
+}
+
+#[cfg(feature = "std")]
+impl LazyPixels {
+    fn add(&mut self, data: EncodedData, compressed: bool, pixel_count: usize) -> Range<usize> {
+        let start = self.len;
+        self.len += pixel_count;
+        if self.skip {
+            return start..self.len;
+        }
+        self.encoded
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(EncodedImage {
+                data,
+                compressed,
+                pixel_count,
+            });
+        start..self.len
+    }
+
+    fn decoded(&self) -> &DecodedPixels {
+        self.decoded.get_or_init(|| {
+            let encoded =
+                core::mem::take(&mut *self.encoded.lock().unwrap_or_else(PoisonError::into_inner));
+            let transparent = Rgba::from_channels(0, 0, 0, 0);
+            if self.skip {
+                return DecodedPixels {
+                    pixels: Pixels::Rgba(vec![transparent; self.len]),
+                    error: None,
+                };
+            }
+            match self.decode(&encoded) {
+                Ok(pixels) => DecodedPixels {
+                    pixels,
+                    error: None,
+                },
+                Err(err) => {
+                    log::warn!("Could not decode cel pixels: {}", err);
+                    DecodedPixels {
+                        pixels: Pixels::Rgba(vec![transparent; self.len]),
+                        error: Some(err.to_string()),
+                    }
+                }
+            }
+        })
+    }
+
+    fn decode(&self, encoded: &[EncodedImage]) -> Result<Pixels> {
+        let mut pixels = Pixels::new(self.pixel_format);
+        for image in encoded {
+            let data = match &image.data {
+                EncodedData::Owned(data) => data,
+                EncodedData::Shared(range) => {
+                    let shared = self.shared.as_ref().expect("Shared data was set");
+                    &shared.get()[range.clone()]
+                }
+            };
+            let reader = AseReader::new(data);
+            if image.compressed {
+                pixels.read_compressed(reader, image.pixel_count)?;
+            } else {
+                pixels.read_raw(reader, image.pixel_count)?;
+            }
+        }
+        // Like the validation of decoded cels, frames without cels need no
+        // palette.
+        if let Pixels::Indexed(indices) = &pixels {
+            if indices.is_empty() {
+                return Ok(pixels);
+            }
+            let valid = self.palette.as_ref().ok_or_else(|| {
+                AsepriteParseError::InvalidInput("No palette present for indexed pixel data".into())
+            })?;
+            if let Some(index) = indices.iter().find(|index| !valid[**index as usize]) {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Index out of range: {} (max: {})",
+                    index,
+                    valid.iter().filter(|valid| **valid).count()
+                )));
+            }
+        }
+        Ok(pixels)
+    }
+}
+
 pub(crate) struct IndexResolverData<'a> {
     pub(crate) palette: Option<&'a ColorPalette>,
     pub(crate) transparent_color_index: Option<u8>,
@@ -0,0 +1,162 @@
+// Building blocks for the PNG encoders, i.e. plain, animated and paletted
+// images. Writing PNG files this way works without the image crate.
+use crate::image::RgbaImage;
+use flate2::{write::ZlibEncoder, Compression, Crc};
+use std::io::Write;
+
+pub(crate) const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+pub(crate) const BIT_DEPTH: u8 = 8;
+pub(crate) const COLOR_TYPE_RGBA: u8 = 6;
+pub(crate) const BYTES_PER_PIXEL: usize = 4;
+
+// Row filters.
+const FILTER_NONE: u8 = 0;
+const FILTER_SUB: u8 = 1;
+const FILTER_UP: u8 = 2;
+const FILTER_AVERAGE: u8 = 3;
+const FILTER_PAETH: u8 = 4;
+
+// A complete PNG file with the image.
+pub(crate) fn encode(image: &RgbaImage) -> Vec<u8> {
+    let mut data = SIGNATURE.to_vec();
+    let (width, height) = image.dimensions();
+    push_chunk(&mut data, b"IHDR", &header(width, height, COLOR_TYPE_RGBA));
+    push_chunk(&mut data, b"IDAT", &compress(&filter_rows(image)));
+    push_chunk(&mut data, b"IEND", &[]);
+    data
+}
+
+// The payload of the IHDR chunk. Compression, filter and interlace method
+// are always the defaults.
+pub(crate) fn header(width: u32, height: u32, color_type: u8) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[BIT_DEPTH, color_type, 0, 0, 0]);
+    header
+}
+
+pub(crate) fn push_chunk(data: &mut Vec<u8>, chunk_type: &[u8; 4], payload: &[u8]) {
+    data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    data.extend_from_slice(chunk_type);
+    data.extend_from_slice(payload);
+    let mut crc = Crc::new();
+    crc.update(chunk_type);
+    crc.update(payload);
+    data.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+// Compress filtered image rows for an IDAT or fdAT chunk.
+pub(crate) fn compress(filtered: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(filtered).unwrap();
+    encoder.finish().unwrap()
+}
+
+// Each row uses the filter with the smallest sum of absolute differences,
+// the heuristic suggested by the PNG specification.
+pub(crate) fn filter_rows(image: &RgbaImage) -> Vec<u8> {
+    let stride = image.width() as usize * BYTES_PER_PIXEL;
+    let raw = image.as_raw();
+    let mut result = Vec::with_capacity((stride + 1) * image.height() as usize);
+    let zeros = vec![0; stride];
+    let mut candidates = vec![vec![0_u8; stride]; 5];
+    for (y, row) in raw.chunks(stride).enumerate() {
+        let above = if y == 0 {
+            &zeros[..]
+        } else {
+            &raw[(y - 1) * stride..y * stride]
+        };
+        for (filter, candidate) in candidates.iter_mut().enumerate() {
+            for i in 0..stride {
+                let predicted = predict(filter as u8, row, above, i);
+                candidate[i] = row[i].wrapping_sub(predicted);
+            }
+        }
+        let cost = |filtered: &Vec<u8>| -> u32 {
+            filtered
+                .iter()
+                .map(|&b| (b as i8).unsigned_abs() as u32)
+                .sum()
+        };
+        let best = (0..candidates.len())
+            .min_by_key(|&filter| cost(&candidates[filter]))
+            .unwrap();
+        result.push(best as u8);
+        result.extend_from_slice(&candidates[best]);
+    }
+    result
+}
+
+// The value the filter predicts for byte `i` of `row`, given the unfiltered
+// bytes of the row and of the one above it.
+pub(crate) fn predict(filter: u8, row: &[u8], above: &[u8], i: usize) -> u8 {
+    let left = if i >= BYTES_PER_PIXEL {
+        row[i - BYTES_PER_PIXEL]
+    } else {
+        0
+    };
+    let upper_left = if i >= BYTES_PER_PIXEL {
+        above[i - BYTES_PER_PIXEL]
+    } else {
+        0
+    };
+    let up = above[i];
+    match filter {
+        FILTER_NONE => 0,
+        FILTER_SUB => left,
+        FILTER_UP => up,
+        FILTER_AVERAGE => ((left as u16 + up as u16) / 2) as u8,
+        FILTER_PAETH => {
+            let estimate = left as i16 + up as i16 - upper_left as i16;
+            let distance_left = (estimate - left as i16).abs();
+            let distance_up = (estimate - up as i16).abs();
+            let distance_upper_left = (estimate - upper_left as i16).abs();
+            if distance_left <= distance_up && distance_left <= distance_upper_left {
+                left
+            } else if distance_up <= distance_upper_left {
+                up
+            } else {
+                upper_left
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+// Splits a PNG file into its chunks, used to test the encoders.
+#[cfg(all(test, feature = "image"))]
+pub(crate) mod decode {
+    use super::SIGNATURE;
+    use flate2::Crc;
+
+    pub(crate) fn u32_at(data: &[u8], position: usize) -> u32 {
+        let bytes = [
+            data[position],
+            data[position + 1],
+            data[position + 2],
+            data[position + 3],
+        ];
+        u32::from_be_bytes(bytes)
+    }
+
+    // The type and payload of each chunk, after checking its CRC.
+    pub(crate) fn chunks(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+        assert_eq!(data[0..8], SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut position = 8;
+        while position < data.len() {
+            let size = u32_at(data, position) as usize;
+            let chunk_type = &data[position + 4..position + 8];
+            let payload = &data[position + 8..position + 8 + size];
+            let mut crc = Crc::new();
+            crc.update(chunk_type);
+            crc.update(payload);
+            assert_eq!(crc.sum(), u32_at(data, position + 8 + size));
+            chunks.push((chunk_type, payload));
+            position += 12 + size;
+        }
+        assert_eq!(position, data.len());
+        chunks
+    }
+}
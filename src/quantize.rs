@@ -0,0 +1,114 @@
+use crate::image::RgbaImage;
+use crate::{palette::ColorDistance, pixel::Indexed, ColorPalette};
+use std::collections::HashMap;
+
+/// Options for [ColorPalette::quantize_with_options].
+///
+/// ```
+/// # use asefile::{ColorDistance, QuantizeOptions};
+/// let options = QuantizeOptions::new()
+///     .transparent_index(0)
+///     .alpha_threshold(128)
+///     .distance(ColorDistance::Rgb);
+/// ```
+#[derive(Debug, Clone)]
+pub struct QuantizeOptions {
+    transparent_index: u8,
+    alpha_threshold: u8,
+    distance: ColorDistance,
+}
+
+impl Default for QuantizeOptions {
+    fn default() -> Self {
+        Self {
+            transparent_index: 0,
+            alpha_threshold: 1,
+            distance: ColorDistance::Perceptual,
+        }
+    }
+}
+
+impl QuantizeOptions {
+    /// Default options: Fully transparent pixels become index 0, all other
+    /// pixels the perceptually closest color.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The index that transparent pixels are mapped to. Use the sprite's
+    /// [transparent_color_index](crate::AsepriteFile::transparent_color_index)
+    /// to get pixels that turn back into the same image. Other pixels never
+    /// map to this index. Defaults to `0`.
+    pub fn transparent_index(mut self, index: u8) -> Self {
+        self.transparent_index = index;
+        self
+    }
+
+    /// Pixels with an alpha value below the threshold count as transparent.
+    /// Defaults to `1`, so that only fully transparent pixels do. With `0`,
+    /// every pixel is matched against the palette.
+    pub fn alpha_threshold(mut self, threshold: u8) -> Self {
+        self.alpha_threshold = threshold;
+        self
+    }
+
+    /// How the closest palette color is found. Defaults to
+    /// [ColorDistance::Perceptual].
+    pub fn distance(mut self, distance: ColorDistance) -> Self {
+        self.distance = distance;
+        self
+    }
+}
+
+/// # Quantization
+impl ColorPalette {
+    /// Convert an image to indexed pixels, row by row, by picking the closest
+    /// palette color for each pixel. This is the inverse of how indexed cels
+    /// are turned into RGBA images. See [QuantizeOptions] for the defaults.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/indexed.aseprite")).unwrap();
+    /// let image = ase.frame(0).image();
+    /// let pixels = ase.palette().unwrap().quantize(&image);
+    /// assert_eq!(pixels.len(), (image.width() * image.height()) as usize);
+    /// ```
+    pub fn quantize(&self, image: &RgbaImage) -> Vec<Indexed> {
+        self.quantize_with_options(image, &QuantizeOptions::new())
+    }
+
+    /// Like [quantize](ColorPalette::quantize) but with custom
+    /// [QuantizeOptions].
+    ///
+    /// Only the first 256 palette entries can be addressed by indexed pixels.
+    /// If no other entry is left, all pixels become the transparent index.
+    pub fn quantize_with_options(
+        &self,
+        image: &RgbaImage,
+        options: &QuantizeOptions,
+    ) -> Vec<Indexed> {
+        let candidates: Vec<(u8, [u8; 4])> = self
+            .iter()
+            .filter(|(index, _)| *index <= u8::MAX as u32)
+            .map(|(index, entry)| (index as u8, entry.raw_rgba8()))
+            .filter(|(index, _)| *index != options.transparent_index)
+            .collect();
+        // Images tend to have few distinct colors.
+        let mut nearest: HashMap<[u8; 4], u8> = HashMap::new();
+        image
+            .pixels()
+            .map(|pixel| {
+                if pixel.0[3] < options.alpha_threshold {
+                    return Indexed::new(options.transparent_index);
+                }
+                let index = *nearest.entry(pixel.0).or_insert_with(|| {
+                    candidates
+                        .iter()
+                        .min_by_key(|(_, rgba)| options.distance.between(pixel.0, *rgba))
+                        .map_or(options.transparent_index, |(index, _)| *index)
+                });
+                Indexed::new(index)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,297 @@
+/*!
+Plain RGBA pixel buffers, available with or without the `image` feature.
+
+With the `image` feature, which is enabled by default, images are returned as
+an [`image::RgbaImage`] from the [image](https://docs.rs/image) library. The
+accessors named `raw_image`, such as
+[Frame::raw_image](crate::Frame::raw_image) and
+[Cel::raw_image](crate::Cel::raw_image), return the same pixels as an
+[RgbaImage] of this module instead, and are the only way to get images when
+the feature is disabled. [RgbaImage] holds the RGBA data in rows from top to
+bottom with 4 bytes per pixel, and offers a small subset of the API of its
+counterpart. The raw bytes can be passed on to a GPU or another library
+without copying:
+
+```
+# use asefile::AsepriteFile;
+# use std::path::Path;
+# let path = Path::new("./tests/data/basic-16x16.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let image = ase.frame(0).raw_image();
+let (width, height) = image.dimensions();
+let pixels: Vec<u8> = image.into_raw();
+assert_eq!(pixels.len(), (width * height * 4) as usize);
+```
+
+[`image::RgbaImage`]: https://docs.rs/image/0.23/image/type.RgbaImage.html
+*/
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::ops::{Deref, DerefMut, Index, IndexMut};
+
+const BYTES_PER_PIXEL: usize = 4;
+
+/// A pixel with red, green, blue and alpha channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(transparent)]
+pub struct Rgba<T>(pub [T; 4]);
+
+impl<T> Index<usize> for Rgba<T> {
+    type Output = T;
+
+    fn index(&self, channel: usize) -> &T {
+        &self.0[channel]
+    }
+}
+
+impl<T> IndexMut<usize> for Rgba<T> {
+    fn index_mut(&mut self, channel: usize) -> &mut T {
+        &mut self.0[channel]
+    }
+}
+
+/// Constructing pixels from their channels.
+pub trait Pixel {
+    /// The type of a single channel.
+    type Subpixel;
+
+    /// Create a pixel from its red, green, blue and alpha channels.
+    fn from_channels(
+        r: Self::Subpixel,
+        g: Self::Subpixel,
+        b: Self::Subpixel,
+        a: Self::Subpixel,
+    ) -> Self;
+
+    /// The channels of the pixel.
+    fn channels(&self) -> &[Self::Subpixel];
+}
+
+impl<T: Copy> Pixel for Rgba<T> {
+    type Subpixel = T;
+
+    fn from_channels(r: T, g: T, b: T, a: T) -> Self {
+        Rgba([r, g, b, a])
+    }
+
+    fn channels(&self) -> &[T] {
+        &self.0
+    }
+}
+
+/// An image with 8 bit RGBA pixels.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct RgbaImage {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// A transparent black image.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::from_pixel(width, height, Rgba([0; 4]))
+    }
+
+    /// An image filled with a single color.
+    pub fn from_pixel(width: u32, height: u32, pixel: Rgba<u8>) -> Self {
+        let len = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            data: pixel.0.repeat(len),
+        }
+    }
+
+    /// An image with the color `f(x, y)` at each position.
+    pub fn from_fn<F: FnMut(u32, u32) -> Rgba<u8>>(width: u32, height: u32, mut f: F) -> Self {
+        let mut image = Self::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.put_pixel(x, y, f(x, y));
+            }
+        }
+        image
+    }
+
+    /// Use an existing buffer of RGBA data. Returns `None` if its length does
+    /// not match the size of the image.
+    pub fn from_raw(width: u32, height: u32, data: Vec<u8>) -> Option<Self> {
+        if data.len() == width as usize * height as usize * BYTES_PER_PIXEL {
+            Some(Self {
+                width,
+                height,
+                data,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Width and height of the image in pixels.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The RGBA data of the image.
+    pub fn as_raw(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    /// The RGBA data of the image.
+    pub fn into_raw(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// The pixel at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside of the image.
+    pub fn get_pixel(&self, x: u32, y: u32) -> &Rgba<u8> {
+        let start = self.offset(x, y);
+        as_pixel(&self.data[start..start + BYTES_PER_PIXEL])
+    }
+
+    /// The pixel at the given position, for modification.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside of the image.
+    pub fn get_pixel_mut(&mut self, x: u32, y: u32) -> &mut Rgba<u8> {
+        let start = self.offset(x, y);
+        as_pixel_mut(&mut self.data[start..start + BYTES_PER_PIXEL])
+    }
+
+    /// Replace the pixel at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside of the image.
+    pub fn put_pixel(&mut self, x: u32, y: u32, pixel: Rgba<u8>) {
+        *self.get_pixel_mut(x, y) = pixel;
+    }
+
+    /// All pixels, in rows from top to bottom.
+    pub fn pixels(&self) -> impl Iterator<Item = &Rgba<u8>> + Clone {
+        self.data.chunks_exact(BYTES_PER_PIXEL).map(as_pixel)
+    }
+
+    /// All pixels for modification, in rows from top to bottom.
+    pub fn pixels_mut(&mut self) -> impl Iterator<Item = &mut Rgba<u8>> {
+        self.data
+            .chunks_exact_mut(BYTES_PER_PIXEL)
+            .map(as_pixel_mut)
+    }
+
+    /// All pixels together with their position.
+    pub fn enumerate_pixels(&self) -> impl Iterator<Item = (u32, u32, &Rgba<u8>)> + Clone {
+        let width = self.width.max(1);
+        self.pixels()
+            .enumerate()
+            .map(move |(i, pixel)| (i as u32 % width, i as u32 / width, pixel))
+    }
+
+    fn offset(&self, x: u32, y: u32) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "Pixel ({}, {}) is outside of the {}x{} image",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        (y as usize * self.width as usize + x as usize) * BYTES_PER_PIXEL
+    }
+}
+
+impl Deref for RgbaImage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl DerefMut for RgbaImage {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+// The images composited by this crate, which are `image` crate types when that
+// feature is enabled, as the type of this module. Moves the data without
+// copying it.
+pub(crate) fn from_image(image: crate::image::RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    RgbaImage::from_raw(width, height, image.into_raw()).unwrap()
+}
+
+// Rgba is a transparent wrapper around its channels, so four bytes have the
+// same layout as an Rgba<u8>.
+fn as_pixel(bytes: &[u8]) -> &Rgba<u8> {
+    let bytes: &[u8; BYTES_PER_PIXEL] = bytes.try_into().unwrap();
+    unsafe { &*(bytes as *const [u8; BYTES_PER_PIXEL] as *const Rgba<u8>) }
+}
+
+fn as_pixel_mut(bytes: &mut [u8]) -> &mut Rgba<u8> {
+    let bytes: &mut [u8; BYTES_PER_PIXEL] = bytes.try_into().unwrap();
+    unsafe { &mut *(bytes as *mut [u8; BYTES_PER_PIXEL] as *mut Rgba<u8>) }
+}
+
+/// Operations on whole images.
+pub mod imageops {
+    use super::RgbaImage;
+    use core::ops::Deref;
+
+    /// A copy of a rectangular part of an image, returned by [crop_imm].
+    #[derive(Debug, Clone)]
+    pub struct SubImage(RgbaImage);
+
+    impl SubImage {
+        /// The copied pixels.
+        pub fn to_image(&self) -> RgbaImage {
+            self.0.clone()
+        }
+    }
+
+    impl Deref for SubImage {
+        type Target = RgbaImage;
+
+        fn deref(&self) -> &RgbaImage {
+            &self.0
+        }
+    }
+
+    /// The part of the image at the given position and size. The part is
+    /// reduced to fit into the image.
+    pub fn crop_imm(image: &RgbaImage, x: u32, y: u32, width: u32, height: u32) -> SubImage {
+        let x = x.min(image.width());
+        let y = y.min(image.height());
+        let width = width.min(image.width() - x);
+        let height = height.min(image.height() - y);
+        SubImage(RgbaImage::from_fn(width, height, |column, row| {
+            *image.get_pixel(x + column, y + row)
+        }))
+    }
+
+    /// Copy the pixels of `top` onto `bottom` at the given position. Pixels
+    /// outside of `bottom` are ignored.
+    pub fn replace(bottom: &mut RgbaImage, top: &RgbaImage, x: u32, y: u32) {
+        for row in 0..top.height().min(bottom.height().saturating_sub(y)) {
+            for column in 0..top.width().min(bottom.width().saturating_sub(x)) {
+                bottom.put_pixel(x + column, y + row, *top.get_pixel(column, row));
+            }
+        }
+    }
+}
@@ -1,21 +1,64 @@
+use crate::io::Read;
 use crate::{AsepriteParseError, Result};
-use byteorder::{LittleEndian, ReadBytesExt};
+use alloc::{format, string::String, vec, vec::Vec};
+#[cfg(feature = "std")]
 use flate2::read::ZlibDecoder;
-use std::io::{Cursor, Read};
+#[cfg(feature = "std")]
+use std::io::Result as IoResult;
+#[cfg(not(feature = "std"))]
+use Result as IoResult;
 
-fn to_ase(e: std::io::Error) -> AsepriteParseError {
-    e.into()
+// Upper bound for buffers that are allocated up front based on a size read
+// from the file. Bigger buffers still work, but only grow as data actually
+// arrives, so a corrupt size field cannot trigger a huge allocation by itself.
+const MAX_PREALLOCATION: usize = 1 << 24;
+
+// Counts the bytes read from the underlying reader, so the parser knows at
+// which offset the file ends.
+pub(crate) struct CountingReader<R> {
+    input: R,
+    count: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub(crate) fn new(input: R) -> Self {
+        Self { input, count: 0 }
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let len = self.input.read(buf)?;
+        self.count += len as u64;
+        Ok(len)
+    }
 }
 
 pub(crate) struct AseReader<T: Read> {
     input: T,
 }
 
-impl AseReader<Cursor<&[u8]>> {
-    pub(crate) fn new(data: &[u8]) -> AseReader<Cursor<&[u8]>> {
-        let input = Cursor::new(data);
+impl<'a> AseReader<&'a [u8]> {
+    pub(crate) fn new(input: &'a [u8]) -> Self {
         AseReader { input }
     }
+
+    // The bytes that have not been read yet.
+    #[cfg(feature = "std")]
+    pub(crate) fn remaining(&self) -> &'a [u8] {
+        self.input
+    }
+}
+
+impl<R: Read> AseReader<&mut CountingReader<R>> {
+    // The number of bytes read so far.
+    pub(crate) fn position(&self) -> u64 {
+        self.input.count()
+    }
 }
 
 impl<T: Read> AseReader<T>
@@ -26,28 +69,51 @@ where
         Self { input }
     }
 
+    pub(crate) fn into_inner(self) -> T {
+        self.input
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.input.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
     pub(crate) fn byte(&mut self) -> Result<u8> {
-        self.input.read_u8().map_err(to_ase)
+        self.bytes().map(u8::from_le_bytes)
     }
 
     pub(crate) fn word(&mut self) -> Result<u16> {
-        self.input.read_u16::<LittleEndian>().map_err(to_ase)
+        self.bytes().map(u16::from_le_bytes)
     }
 
     pub(crate) fn short(&mut self) -> Result<i16> {
-        self.input.read_i16::<LittleEndian>().map_err(to_ase)
+        self.bytes().map(i16::from_le_bytes)
     }
 
     pub(crate) fn dword(&mut self) -> Result<u32> {
-        self.input.read_u32::<LittleEndian>().map_err(to_ase)
+        self.bytes().map(u32::from_le_bytes)
     }
 
     pub(crate) fn long(&mut self) -> Result<i32> {
-        self.input.read_i32::<LittleEndian>().map_err(to_ase)
+        self.bytes().map(i32::from_le_bytes)
+    }
+
+    pub(crate) fn qword(&mut self) -> Result<u64> {
+        self.bytes().map(u64::from_le_bytes)
+    }
+
+    pub(crate) fn long64(&mut self) -> Result<i64> {
+        self.bytes().map(i64::from_le_bytes)
+    }
+
+    // 16.16 fixed point value.
+    pub(crate) fn fixed(&mut self) -> Result<f64> {
+        self.long().map(|value| value as f64 / 65536.0)
     }
 
     pub(crate) fn string(&mut self) -> Result<String> {
-        let str_len = self.input.read_u16::<LittleEndian>()?;
+        let str_len = self.word()?;
         let mut str_bytes = vec![0_u8; str_len as usize];
         self.input.read_exact(&mut str_bytes)?;
         let s = String::from_utf8(str_bytes)?;
@@ -55,16 +121,37 @@ where
     }
 
     pub(crate) fn read_exact(&mut self, buffer: &mut [u8]) -> Result<()> {
-        self.input.read_exact(buffer).map_err(to_ase)
+        self.input.read_exact(buffer)?;
+        Ok(())
     }
 
     pub(crate) fn skip_reserved(&mut self, count: usize) -> Result<()> {
         let mut ignored = vec![0_u8; count];
-        self.input.read_exact(&mut ignored).map_err(to_ase)
+        self.read_exact(&mut ignored)
+    }
+
+    // Like `skip_reserved` but does not allocate.
+    pub(crate) fn skip(&mut self, count: u64) -> Result<()> {
+        let mut buffer = [0; 4096];
+        let mut remaining = count;
+        while remaining > 0 {
+            let len = remaining.min(buffer.len() as u64) as usize;
+            self.read_exact(&mut buffer[..len])?;
+            remaining -= len as u64;
+        }
+        Ok(())
+    }
+
+    // All remaining bytes.
+    #[cfg(feature = "std")]
+    pub(crate) fn rest(mut self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.input.read_to_end(&mut bytes)?;
+        Ok(bytes)
     }
 
     pub(crate) fn take_bytes(self, limit: usize) -> Result<Vec<u8>> {
-        let mut output = Vec::with_capacity(limit);
+        let mut output = Vec::with_capacity(limit.min(MAX_PREALLOCATION));
         self.input.take(limit as u64).read_to_end(&mut output)?;
         if output.len() != limit {
             Err(AsepriteParseError::InvalidInput(format!(
@@ -77,10 +164,57 @@ where
         }
     }
 
+    #[cfg(feature = "std")]
     pub(crate) fn unzip(self, expected_output_size: usize) -> Result<Vec<u8>> {
         let mut decoder = ZlibDecoder::new(self.input);
-        let mut buffer = Vec::with_capacity(expected_output_size);
-        decoder.read_to_end(&mut buffer)?;
+        let mut buffer = Vec::with_capacity(expected_output_size.min(MAX_PREALLOCATION));
+        // Reading one byte more than expected is enough to detect oversized
+        // data without decompressing all of it.
+        let limit = (expected_output_size as u64).saturating_add(1);
+        (&mut decoder).take(limit).read_to_end(&mut buffer)?;
+        trace_event!(
+            TRACE,
+            compressed_bytes = decoder.total_in(),
+            decompressed_bytes = decoder.total_out(),
+            "decompressed"
+        );
+        check_unzipped_size(buffer, expected_output_size)
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn unzip(mut self, expected_output_size: usize) -> Result<Vec<u8>> {
+        use miniz_oxide::inflate::{self, TINFLStatus};
+
+        let mut compressed = Vec::new();
+        self.input.read_to_end(&mut compressed)?;
+        let limit = expected_output_size.saturating_add(1);
+        let buffer = match inflate::decompress_to_vec_zlib_with_limit(&compressed, limit) {
+            Ok(buffer) => buffer,
+            Err(err) if err.status == TINFLStatus::HasMoreOutput => err.output,
+            Err(err) => {
+                return Err(AsepriteParseError::InvalidInput(format!(
+                    "Could not decompress data: {}",
+                    err
+                )))
+            }
+        };
+        check_unzipped_size(buffer, expected_output_size)
+    }
+}
+
+fn check_unzipped_size(buffer: Vec<u8>, expected_output_size: usize) -> Result<Vec<u8>> {
+    if buffer.len() > expected_output_size {
+        Err(AsepriteParseError::InvalidInput(format!(
+            "Invalid decompressed data size. Expected: {}, Actual: more than {}",
+            expected_output_size, expected_output_size
+        )))
+    } else if buffer.len() < expected_output_size {
+        Err(AsepriteParseError::InvalidInput(format!(
+            "Invalid decompressed data size. Expected: {}, Actual: {}",
+            expected_output_size,
+            buffer.len()
+        )))
+    } else {
         Ok(buffer)
     }
 }
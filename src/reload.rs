@@ -0,0 +1,198 @@
+//! Find out which layers, tags, and slices of a reloaded file correspond to
+//! the ones of a previous version.
+//!
+//! Layer, tag and slice ids are indices, so they change whenever an artist
+//! inserts or deletes an item. Tools that hot-reload files can use
+//! [match_ids()] to update references they hold into the old file.
+//!
+//! ```
+//! # use asefile::AsepriteFile;
+//! # use std::path::Path;
+//! # let path = Path::new("./tests/data/layers_and_tags.aseprite");
+//! let old = AsepriteFile::read_file(&path).unwrap();
+//! let new = AsepriteFile::read_file(&path).unwrap();
+//! let mapping = asefile::reload::match_ids(&old, &new);
+//! assert_eq!(mapping.layers.new_id(1), Some(1));
+//! assert!(mapping.is_identity());
+//! ```
+use std::mem;
+
+use crate::{diff::match_items, AsepriteFile, Tag};
+
+/// How the layers, tags and slices of two versions of a file correspond to
+/// each other. See [match_ids()].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IdMapping {
+    /// Layers, identified by [crate::Layer::id].
+    pub layers: ItemMapping,
+    /// Tags, identified by their index as in [AsepriteFile::tag].
+    pub tags: ItemMapping,
+    /// Slices, identified by their index in [AsepriteFile::slices].
+    pub slices: ItemMapping,
+}
+
+/// Pairs of ids of one kind of item. All lists are sorted by id.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ItemMapping {
+    /// Items that exist in both files, as `(old id, new id)`.
+    pub matched: Vec<(u32, u32)>,
+    /// The matched items whose name changed, as `(old id, new id)`. For
+    /// layers the [path](crate::Layer::path) is compared, so a layer that was
+    /// moved into a different group also counts as renamed.
+    pub renamed: Vec<(u32, u32)>,
+    /// Ids of items that only exist in the new file.
+    pub added: Vec<u32>,
+    /// Ids of items that only exist in the old file.
+    pub removed: Vec<u32>,
+}
+
+impl ItemMapping {
+    /// The id in the new file of the item with the given id in the old file,
+    /// if the item still exists.
+    pub fn new_id(&self, old_id: u32) -> Option<u32> {
+        self.matched
+            .binary_search_by_key(&old_id, |&(old, _)| old)
+            .ok()
+            .map(|index| self.matched[index].1)
+    }
+
+    /// The id in the old file of the item with the given id in the new file,
+    /// if the item existed before.
+    pub fn old_id(&self, new_id: u32) -> Option<u32> {
+        self.matched
+            .iter()
+            .find(|&&(_, new)| new == new_id)
+            .map(|&(old, _)| old)
+    }
+
+    /// Returns `true` if all items kept their ids and names.
+    pub fn is_identity(&self) -> bool {
+        self.renamed.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.matched.iter().all(|(old, new)| old == new)
+    }
+}
+
+impl IdMapping {
+    /// Returns `true` if all layers, tags and slices kept their ids and
+    /// names, so references into the old file are still valid.
+    pub fn is_identity(&self) -> bool {
+        self.layers.is_identity() && self.tags.is_identity() && self.slices.is_identity()
+    }
+}
+
+/// Pair up the layers, tags and slices of two versions of a file. `old` is
+/// the previously loaded file.
+///
+/// - Layers are matched by [UUID](crate::Layer::uuid) if both layers have
+///   one. Otherwise they are matched by path and layer type (image, group or
+///   tilemap).
+/// - Tags are matched by name. The remaining tags are matched by the overlap
+///   of their frame ranges, which finds most renamed tags.
+/// - Slices are matched by name.
+///
+/// Items with the same name (or path) are paired in the order in which they
+/// appear. The result only depends on the two files, so it can be stored and
+/// compared across runs.
+pub fn match_ids(old: &AsepriteFile, new: &AsepriteFile) -> IdMapping {
+    IdMapping {
+        layers: match_layers(old, new),
+        tags: match_tags(&old.tags, &new.tags),
+        slices: match_slices(old, new),
+    }
+}
+
+// Collects the result of `match_items`. `same_name` decides which of the
+// matched items count as renamed.
+fn item_mapping(
+    partners: Vec<Option<usize>>,
+    added: Vec<usize>,
+    same_name: impl Fn(usize, usize) -> bool,
+) -> ItemMapping {
+    let mut mapping = ItemMapping {
+        added: added.into_iter().map(|ib| ib as u32).collect(),
+        ..ItemMapping::default()
+    };
+    for (ia, partner) in partners.into_iter().enumerate() {
+        match partner {
+            Some(ib) => {
+                mapping.matched.push((ia as u32, ib as u32));
+                if !same_name(ia, ib) {
+                    mapping.renamed.push((ia as u32, ib as u32));
+                }
+            }
+            None => mapping.removed.push(ia as u32),
+        }
+    }
+    mapping
+}
+
+fn match_layers(a: &AsepriteFile, b: &AsepriteFile) -> ItemMapping {
+    let paths_a: Vec<String> = a.layers().map(|l| l.path()).collect();
+    let paths_b: Vec<String> = b.layers().map(|l| l.path()).collect();
+    let uuid_a = |ia: usize| a.layer(ia as u32).uuid();
+    let uuid_b = |ib: usize| b.layer(ib as u32).uuid();
+    let same_uuid = |ia: usize, ib: usize| uuid_a(ia).is_some() && uuid_a(ia) == uuid_b(ib);
+    let same_path = |ia: usize, ib: usize| {
+        // Layers with different UUIDs are different layers, even if they have
+        // the same path.
+        let comparable = uuid_a(ia).is_none() || uuid_b(ib).is_none();
+        comparable
+            && paths_a[ia] == paths_b[ib]
+            && mem::discriminant(&a.layer(ia as u32).layer_type())
+                == mem::discriminant(&b.layer(ib as u32).layer_type())
+    };
+    let (partners, added) = match_items(paths_a.len(), paths_b.len(), &[&same_uuid, &same_path]);
+    item_mapping(partners, added, |ia, ib| paths_a[ia] == paths_b[ib])
+}
+
+fn match_tags(a: &[Tag], b: &[Tag]) -> ItemMapping {
+    let same_name = |ia: usize, ib: usize| a[ia].name() == b[ib].name();
+    let (mut partners, added) = match_items(a.len(), b.len(), &[&same_name]);
+
+    // Pair the remaining tags whose ranges overlap, best match first. The
+    // similarity is the number of shared frames divided by the number of
+    // frames covered by either tag.
+    let mut candidates = Vec::new();
+    for (ia, _) in partners.iter().enumerate().filter(|(_, p)| p.is_none()) {
+        for &ib in &added {
+            if let Some(similarity) = range_similarity(&a[ia], &b[ib]) {
+                candidates.push((similarity, ia, ib));
+            }
+        }
+    }
+    candidates.sort_by(|(x, ia, ib), (y, ja, jb)| {
+        let (x, y) = (x.0 as u64 * y.1 as u64, y.0 as u64 * x.1 as u64);
+        y.cmp(&x).then(ia.cmp(ja)).then(ib.cmp(jb))
+    });
+    let mut used = vec![false; b.len()];
+    for (_, ia, ib) in candidates {
+        if partners[ia].is_none() && !used[ib] {
+            partners[ia] = Some(ib);
+            used[ib] = true;
+        }
+    }
+    let added = added.into_iter().filter(|&ib| !used[ib]).collect();
+    item_mapping(partners, added, same_name)
+}
+
+// Shared and total frames of two tags, if they have any frames in common.
+fn range_similarity(a: &Tag, b: &Tag) -> Option<(u32, u32)> {
+    let shared_from = a.from_frame().max(b.from_frame());
+    let shared_to = a.to_frame().min(b.to_frame());
+    if shared_from > shared_to {
+        return None;
+    }
+    let total = a.to_frame().max(b.to_frame()) - a.from_frame().min(b.from_frame()) + 1;
+    Some((shared_to - shared_from + 1, total))
+}
+
+fn match_slices(a: &AsepriteFile, b: &AsepriteFile) -> ItemMapping {
+    let (slices_a, slices_b) = (a.slices(), b.slices());
+    let same_name = |ia: usize, ib: usize| slices_a[ia].name == slices_b[ib].name;
+    let (partners, added) = match_items(slices_a.len(), slices_b.len(), &[&same_name]);
+    item_mapping(partners, added, same_name)
+}
@@ -0,0 +1,70 @@
+/*!
+Use sprites with [SDL2](https://github.com/Rust-SDL2/rust-sdl2).
+
+Requires the `sdl2` feature, which links against the SDL2 library. The
+helpers copy a composited frame, or any other [RgbaImage], into a [Surface]
+or a [Texture] with the [RGBA32](PixelFormatEnum::RGBA32) format, whose byte
+order matches the image on every platform:
+
+```no_run
+# use asefile::AsepriteFile;
+# use std::path::Path;
+# let path = Path::new("./tests/data/layers_and_tags.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let sdl = sdl2::init().unwrap();
+let window = sdl.video().unwrap().window("Player", 320, 240).build().unwrap();
+let mut canvas = window.into_canvas().build().unwrap();
+let textures = canvas.texture_creator();
+let texture = asefile::sdl2::to_texture(&ase.frame(0).image(), &textures).unwrap();
+canvas.copy(&texture, None, None).unwrap();
+canvas.present();
+```
+
+Textures use nearest-neighbor scaling and alpha blending, so pixel art stays
+sharp and transparent pixels are not drawn.
+*/
+use crate::image::RgbaImage;
+use ::sdl2::pixels::PixelFormatEnum;
+use ::sdl2::render::{BlendMode, ScaleMode, Texture, TextureCreator};
+use ::sdl2::surface::Surface;
+
+/// Copy an image into a new [Surface].
+///
+/// # Errors
+///
+/// Returns SDL's error message if the surface cannot be created.
+pub fn to_surface(image: &RgbaImage) -> Result<Surface<'static>, String> {
+    let (width, height) = image.dimensions();
+    let mut surface = Surface::new(width, height, PixelFormatEnum::RGBA32)?;
+    let pitch = surface.pitch() as usize;
+    let stride = width as usize * 4;
+    surface.with_lock_mut(|pixels| {
+        for (y, row) in image.as_raw().chunks(stride).enumerate() {
+            pixels[y * pitch..y * pitch + stride].copy_from_slice(row);
+        }
+    });
+    Ok(surface)
+}
+
+/// Copy an image into a new static [Texture] of the renderer that owns
+/// `creator`.
+///
+/// # Errors
+///
+/// Returns SDL's error message if the texture cannot be created, e.g.
+/// because it is larger than the renderer supports.
+pub fn to_texture<'a, T>(
+    image: &RgbaImage,
+    creator: &'a TextureCreator<T>,
+) -> Result<Texture<'a>, String> {
+    let (width, height) = image.dimensions();
+    let mut texture = creator
+        .create_texture_static(PixelFormatEnum::RGBA32, width, height)
+        .map_err(|err| err.to_string())?;
+    texture
+        .update(None, image.as_raw(), width as usize * 4)
+        .map_err(|err| err.to_string())?;
+    texture.set_blend_mode(BlendMode::Blend);
+    texture.set_scale_mode(ScaleMode::Nearest);
+    Ok(texture)
+}
@@ -0,0 +1,233 @@
+// SIMD versions of the normal, multiply and screen blend modes. They process
+// four pixels at a time and give exactly the same results as the functions in
+// `blend`, which handle all other modes and the pixels left over at the end of
+// a row.
+//
+// Only implemented with SSE2 on x86 and x86_64 so far. On other targets no
+// pixels are blended here.
+use crate::blend::Color8;
+use crate::BlendMode;
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse2"
+))]
+use sse2::{Lanes, Pixels};
+
+// Blends the first pixels of `src` onto `backdrop`, which holds the RGBA bytes
+// of as many pixels. Returns the number of blended pixels.
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse2"
+))]
+pub(crate) fn blend_row(
+    mode: BlendMode,
+    backdrop: &mut [u8],
+    src: &[Color8],
+    opacity: u8,
+) -> usize {
+    debug_assert_eq!(backdrop.len(), src.len() * 4);
+    let blend: fn(Pixels, Pixels, Lanes) -> Pixels = match mode {
+        BlendMode::Normal => sse2::normal,
+        BlendMode::Multiply => sse2::multiply,
+        BlendMode::Screen => sse2::screen,
+        _ => return 0,
+    };
+    let len = src.len() / 4 * 4;
+    let backdrop = backdrop[..len * 4].chunks_exact_mut(16);
+    for (back, src) in backdrop.zip(src[..len].chunks_exact(4)) {
+        let mut src_bytes = [0; 16];
+        for (bytes, pixel) in src_bytes.chunks_exact_mut(4).zip(src) {
+            bytes.copy_from_slice(&pixel.0);
+        }
+        let result = blend(
+            sse2::load(back),
+            sse2::load(&src_bytes),
+            sse2::splat(opacity as i32),
+        );
+        sse2::store(result, back);
+    }
+    len
+}
+
+#[cfg(not(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse2"
+)))]
+pub(crate) fn blend_row(
+    _mode: BlendMode,
+    _backdrop: &mut [u8],
+    _src: &[Color8],
+    _opacity: u8,
+) -> usize {
+    0
+}
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse2"
+))]
+mod sse2 {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::*;
+
+    // One channel of four pixels, or any other four values, as i32 lanes.
+    pub(super) type Lanes = __m128i;
+
+    // Four pixels, with one value per channel and pixel.
+    pub(super) type Pixels = [Lanes; 4];
+
+    pub(super) fn splat(value: i32) -> Lanes {
+        unsafe { _mm_set1_epi32(value) }
+    }
+
+    pub(super) fn load(bytes: &[u8]) -> Pixels {
+        assert_eq!(bytes.len(), 16);
+        unsafe {
+            let v = _mm_loadu_si128(bytes.as_ptr() as *const __m128i);
+            let mask = _mm_set1_epi32(0xff);
+            [
+                _mm_and_si128(v, mask),
+                _mm_and_si128(_mm_srli_epi32(v, 8), mask),
+                _mm_and_si128(_mm_srli_epi32(v, 16), mask),
+                _mm_srli_epi32(v, 24),
+            ]
+        }
+    }
+
+    pub(super) fn store([r, g, b, a]: Pixels, bytes: &mut [u8]) {
+        assert_eq!(bytes.len(), 16);
+        unsafe {
+            let v = _mm_or_si128(
+                _mm_or_si128(r, _mm_slli_epi32(g, 8)),
+                _mm_or_si128(_mm_slli_epi32(b, 16), _mm_slli_epi32(a, 24)),
+            );
+            _mm_storeu_si128(bytes.as_mut_ptr() as *mut __m128i, v);
+        }
+    }
+
+    fn add(a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm_add_epi32(a, b) }
+    }
+
+    fn sub(a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm_sub_epi32(a, b) }
+    }
+
+    // Both values must fit into an i16 and `b` must not be negative.
+    fn mul(a: Lanes, b: Lanes) -> Lanes {
+        // The upper halves of the lanes of `b` are zero, so this adds nothing
+        // to the product of the lower halves.
+        unsafe { _mm_madd_epi16(a, b) }
+    }
+
+    // Truncates `a / b` toward zero, like the division of i32 values. Exact
+    // as long as the magnitude of the quotient is at most 255.
+    fn div(a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm_cvttps_epi32(_mm_div_ps(_mm_cvtepi32_ps(a), _mm_cvtepi32_ps(b))) }
+    }
+
+    fn is_zero(a: Lanes) -> Lanes {
+        unsafe { _mm_cmpeq_epi32(a, _mm_setzero_si128()) }
+    }
+
+    // `a` in the lanes where `mask` is set, `b` in all others.
+    fn select(mask: Lanes, a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm_or_si128(_mm_and_si128(mask, a), _mm_andnot_si128(mask, b)) }
+    }
+
+    fn select_pixels(mask: Lanes, a: Pixels, b: Pixels) -> Pixels {
+        [
+            select(mask, a[0], b[0]),
+            select(mask, a[1], b[1]),
+            select(mask, a[2], b[2]),
+            select(mask, a[3], b[3]),
+        ]
+    }
+
+    fn mul_un8(a: Lanes, b: Lanes) -> Lanes {
+        unsafe {
+            let t = add(mul(a, b), _mm_set1_epi32(0x80));
+            _mm_srli_epi32(add(_mm_srli_epi32(t, 8), t), 8)
+        }
+    }
+
+    fn blend8(back: Lanes, src: Lanes, opacity: Lanes) -> Lanes {
+        unsafe {
+            let t = add(mul(sub(src, back), opacity), _mm_set1_epi32(0x80));
+            add(back, _mm_srai_epi32(add(_mm_srai_epi32(t, 8), t), 8))
+        }
+    }
+
+    // See `blend::merge`.
+    fn merge(back: Pixels, src: Pixels, opacity: Lanes) -> Pixels {
+        let back_empty = is_zero(back[3]);
+        let src_empty = is_zero(src[3]);
+        let channel = |c: usize| {
+            select(
+                back_empty,
+                src[c],
+                select(src_empty, back[c], blend8(back[c], src[c], opacity)),
+            )
+        };
+        let result = [
+            channel(0),
+            channel(1),
+            channel(2),
+            blend8(back[3], src[3], opacity),
+        ];
+        let transparent = [splat(0); 4];
+        select_pixels(is_zero(result[3]), transparent, result)
+    }
+
+    // See `blend::normal`.
+    pub(super) fn normal(back: Pixels, src: Pixels, opacity: Lanes) -> Pixels {
+        let src_alpha = mul_un8(src[3], opacity);
+        let res_alpha = sub(add(src_alpha, back[3]), mul_un8(back[3], src_alpha));
+        // Lanes with a zero `res_alpha` are replaced below.
+        let channel = |c: usize| {
+            add(
+                back[c],
+                div(mul(sub(src[c], back[c]), src_alpha), res_alpha),
+            )
+        };
+        let blended = [channel(0), channel(1), channel(2), res_alpha];
+        let over_empty = [src[0], src[1], src[2], src_alpha];
+        select_pixels(
+            is_zero(back[3]),
+            over_empty,
+            select_pixels(is_zero(src[3]), back, blended),
+        )
+    }
+
+    // See `blend::blender`.
+    fn blender<F: Fn(Lanes, Lanes) -> Lanes>(
+        back: Pixels,
+        src: Pixels,
+        opacity: Lanes,
+        f: F,
+    ) -> Pixels {
+        let norm = normal(back, src, opacity);
+        let src_blended = [
+            f(back[0], src[0]),
+            f(back[1], src[1]),
+            f(back[2], src[2]),
+            src[3],
+        ];
+        let blend = normal(back, src_blended, opacity);
+        let normal_to_blend_merge = merge(norm, blend, back[3]);
+        let src_total_alpha = mul_un8(src[3], opacity);
+        let composite_alpha = mul_un8(back[3], src_total_alpha);
+        let result = merge(normal_to_blend_merge, blend, composite_alpha);
+        select_pixels(is_zero(back[3]), norm, result)
+    }
+
+    pub(super) fn multiply(back: Pixels, src: Pixels, opacity: Lanes) -> Pixels {
+        blender(back, src, opacity, mul_un8)
+    }
+
+    pub(super) fn screen(back: Pixels, src: Pixels, opacity: Lanes) -> Pixels {
+        blender(back, src, opacity, |b, s| sub(add(b, s), mul_un8(b, s)))
+    }
+}
@@ -1,9 +1,11 @@
-use std::io::Read;
+use crate::io::Read;
+use alloc::{string::String, vec::Vec};
 
 use crate::{reader::AseReader, user_data::UserData, Result};
 
 /// A slice is a region of the sprite with a name and optional [UserData].
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Slice {
     /// The name of the slice. Not guaranteed to be unique.
     pub name: String,
@@ -13,8 +15,80 @@ pub struct Slice {
     pub user_data: Option<UserData>,
 }
 
+impl Slice {
+    /// The key that describes the slice at the given frame, i.e., the key
+    /// with the latest `from_frame` that is not after `frame`. Returns `None`
+    /// if the slice does not exist yet at that frame.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # let ase = AsepriteFile::read_file(std::path::Path::new("./tests/data/slice.aseprite")).unwrap();
+    /// let slice = &ase.slices()[0];
+    /// if let Some(key) = slice.key_for_frame(0) {
+    ///     println!("{} is at {:?}", slice.name, key.origin);
+    /// }
+    /// ```
+    pub fn key_for_frame(&self, frame: u32) -> Option<&SliceKey> {
+        self.keys
+            .iter()
+            .filter(|key| key.from_frame <= frame)
+            .max_by_key(|key| key.from_frame)
+    }
+
+    /// The [bounds](SliceKey::bounds) of the slice at the given frame.
+    /// Returns `None` if the slice does not exist yet or is hidden at that
+    /// frame, i.e., its key has a size of 0.
+    pub fn bounds_at(&self, frame: u32) -> Option<(i32, i32, u32, u32)> {
+        let key = self.key_for_frame(frame)?;
+        if key.size.width == 0 || key.size.height == 0 {
+            return None;
+        }
+        Some(key.bounds())
+    }
+}
+
+// The indices of all slices sorted by name, so that slices can be found by
+// their name or a prefix of it with a binary search. Slices with the same
+// name keep their order.
+#[derive(Debug, Default)]
+pub(crate) struct SliceNames(Vec<u32>);
+
+impl SliceNames {
+    pub(crate) fn new(slices: &[Slice]) -> Self {
+        let mut indices: Vec<u32> = (0..slices.len() as u32).collect();
+        indices.sort_by(|a, b| slices[*a as usize].name.cmp(&slices[*b as usize].name));
+        Self(indices)
+    }
+
+    // The first slice with the given name.
+    pub(crate) fn find<'a>(&self, slices: &'a [Slice], name: &str) -> Option<&'a Slice> {
+        let index = self.0.get(self.start(slices, name))?;
+        let slice = &slices[*index as usize];
+        Some(slice).filter(|slice| slice.name == name)
+    }
+
+    // The slices whose name starts with `prefix`, in the order of their names.
+    pub(crate) fn with_prefix<'a>(
+        &'a self,
+        slices: &'a [Slice],
+        prefix: &'a str,
+    ) -> impl Iterator<Item = &'a Slice> + 'a {
+        self.0[self.start(slices, prefix)..]
+            .iter()
+            .map(move |index| &slices[*index as usize])
+            .take_while(move |slice| slice.name.starts_with(prefix))
+    }
+
+    // The position of the first slice whose name is not less than `name`.
+    fn start(&self, slices: &[Slice], name: &str) -> usize {
+        self.0
+            .partition_point(|index| slices[*index as usize].name.as_str() < name)
+    }
+}
+
 /// A Slice9 divides a [Slice] into nine regions for 9-slice scaling.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Slice9 {
     /// Center X position (relative to slice bounds).
     pub center_x: i32,
@@ -41,7 +115,8 @@ impl Slice9 {
 }
 
 /// A SliceOrigin describes the position of a [Slice] within the sprite.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SliceOrigin {
     /// A [Slice]'s x origin coordinate in the sprite.
     pub x: i32,
@@ -57,7 +132,8 @@ impl SliceOrigin {
 }
 
 /// SliceSize describes the size of a [Slice] in pixels.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SliceSize {
     /// Slice width. This can be 0 if this slice is hidden in the animation from the given frame.
     pub width: u32,
@@ -73,7 +149,8 @@ impl SliceSize {
 }
 
 /// SlicePivot describes a [Slice]'s pivot position relative to the Slice's origin.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SlicePivot {
     /// Pivot X position (relative to the slice origin).
     pub x: i32,
@@ -89,7 +166,8 @@ impl SlicePivot {
 }
 
 /// SliceKey describes the position and shape of a [Slice], starting at a given frame.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SliceKey {
     /// Starting frame number for this slice key. (This slice is valid from this frame to the end of the animation.)
     pub from_frame: u32,
@@ -103,6 +181,23 @@ pub struct SliceKey {
     pub pivot: Option<SlicePivot>,
 }
 impl SliceKey {
+    /// The rectangle of the slice in sprite coordinates, as
+    /// `(x, y, width, height)`.
+    pub fn bounds(&self) -> (i32, i32, u32, u32) {
+        (
+            self.origin.x,
+            self.origin.y,
+            self.size.width,
+            self.size.height,
+        )
+    }
+
+    /// The position of the pivot in sprite coordinates, if the key has one.
+    pub fn pivot_position(&self) -> Option<(i32, i32)> {
+        self.pivot
+            .map(|pivot| (self.origin.x + pivot.x, self.origin.y + pivot.y))
+    }
+
     fn read<R: Read>(reader: &mut AseReader<R>, flags: u32) -> Result<Self> {
         let from_frame = reader.dword()?;
         let origin = SliceOrigin::read(reader)?;
@@ -135,13 +230,21 @@ pub(crate) fn parse_chunk(data: &[u8]) -> Result<Slice> {
     let flags = reader.dword()?;
     let _reserved = reader.dword()?;
     let name = reader.string()?;
-    let slice_keys: Result<Vec<SliceKey>> = (0..num_slice_keys)
-        .map(|_id| SliceKey::read(&mut reader, flags))
-        .collect();
 
+    trace_span!(DEBUG, "parse_slice", name = name.as_str(), num_slice_keys);
+
+    // Collecting from the range would reserve space for `num_slice_keys`
+    // entries up front, and that value comes straight from the file.
+    let mut keys = Vec::with_capacity((num_slice_keys as usize).min(data.len()));
+    for _id in 0..num_slice_keys {
+        keys.push(SliceKey::read(&mut reader, flags)?);
+    }
+
+    // The user data is stored in the chunk after the slice, which the parser
+    // attaches to the slice.
     Ok(Slice {
         name,
-        keys: slice_keys?,
+        keys,
         user_data: None,
     })
 }
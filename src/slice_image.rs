@@ -0,0 +1,237 @@
+use crate::image::{Rgba, RgbaImage};
+use crate::{AsepriteFile, AsepriteParseError, Result};
+
+/// How [AsepriteFile::nine_slice_image] fills the edges and the center of a
+/// 9-slice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NineSliceFill {
+    /// Scale the part to the new size, picking the nearest pixel.
+    Stretch,
+    /// Repeat the part, starting at its top left corner.
+    Tile,
+}
+
+/// Options for [AsepriteFile::nine_slice_image_with_options].
+///
+/// ```
+/// # use asefile::{NineSliceFill, NineSliceOptions};
+/// let options = NineSliceOptions::new()
+///     .edges(NineSliceFill::Tile)
+///     .center(NineSliceFill::Tile);
+/// ```
+#[derive(Debug, Clone)]
+pub struct NineSliceOptions {
+    edges: NineSliceFill,
+    center: NineSliceFill,
+}
+
+impl Default for NineSliceOptions {
+    fn default() -> Self {
+        Self {
+            edges: NineSliceFill::Stretch,
+            center: NineSliceFill::Stretch,
+        }
+    }
+}
+
+impl NineSliceOptions {
+    /// Default options: Edges and center are stretched.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How the four edges between the corners are filled. Defaults to
+    /// [NineSliceFill::Stretch].
+    pub fn edges(mut self, fill: NineSliceFill) -> Self {
+        self.edges = fill;
+        self
+    }
+
+    /// How the center is filled. Defaults to [NineSliceFill::Stretch].
+    pub fn center(mut self, fill: NineSliceFill) -> Self {
+        self.center = fill;
+        self
+    }
+}
+
+/// # Slice images
+impl AsepriteFile {
+    /// The part of a frame's [image](crate::Frame::image) that lies inside
+    /// the slice with the given name, e.g., one of several icons or UI pieces
+    /// that were drawn on one sprite. The slice's rectangle at that frame is
+    /// used, see [Slice::bounds_at](crate::Slice::bounds_at). Parts of the
+    /// slice that lie outside of the canvas are transparent.
+    ///
+    /// If several slices have the name, the first one is used, see
+    /// [slice](AsepriteFile::slice).
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # use std::path::Path;
+    /// # let path = Path::new("./tests/data/slice.aseprite");
+    /// let ase = AsepriteFile::read_file(&path).unwrap();
+    /// let image = ase.slice_image(0, "Slice 1").unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the frame or the slice
+    /// does not exist, or if the slice is hidden at that frame.
+    pub fn slice_image(&self, frame: u32, slice_name: &str) -> Result<RgbaImage> {
+        self.check_frame(frame)?;
+        let slice = self.slice(slice_name).ok_or_else(|| {
+            AsepriteParseError::InvalidInput(format!("Slice does not exist: {}", slice_name))
+        })?;
+        let (x, y, width, height) = slice.bounds_at(frame).ok_or_else(|| {
+            AsepriteParseError::InvalidInput(format!(
+                "Slice {} is not visible in frame {}",
+                slice_name, frame
+            ))
+        })?;
+        let frame_image = self.frame(frame).composite();
+        let (canvas_width, canvas_height) = frame_image.dimensions();
+        Ok(RgbaImage::from_fn(width, height, |dx, dy| {
+            let (sx, sy) = (x as i64 + dx as i64, y as i64 + dy as i64);
+            if sx < 0 || sy < 0 || sx >= canvas_width as i64 || sy >= canvas_height as i64 {
+                crate::image::Rgba([0, 0, 0, 0])
+            } else {
+                *frame_image.get_pixel(sx as u32, sy as u32)
+            }
+        }))
+    }
+
+    /// Render the slice with the given name at another size with 9-slice
+    /// scaling, e.g., to draw a UI panel of any size from a small sprite.
+    /// The slice's [Slice9](crate::Slice9) center splits it into nine parts:
+    /// The corners keep their size, the edges are stretched along the side
+    /// they belong to, and the center is stretched in both directions. Use
+    /// [NineSliceOptions] to tile them instead.
+    ///
+    /// If the target is smaller than the corners, the corners are scaled
+    /// down to fit.
+    ///
+    /// ```no_run
+    /// # use asefile::AsepriteFile;
+    /// # use std::path::Path;
+    /// let ase = AsepriteFile::read_file(Path::new("ui.aseprite")).unwrap();
+    /// let panel = ase.nine_slice_image(0, "panel", (64, 32)).unwrap();
+    /// assert_eq!(panel.dimensions(), (64, 32));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Like [slice_image](AsepriteFile::slice_image). Also returns
+    /// [AsepriteParseError::InvalidInput] if the slice has no 9-slice data
+    /// at that frame.
+    pub fn nine_slice_image(
+        &self,
+        frame: u32,
+        slice_name: &str,
+        size: (u32, u32),
+    ) -> Result<RgbaImage> {
+        self.nine_slice_image_with_options(frame, slice_name, size, &NineSliceOptions::new())
+    }
+
+    /// Like [nine_slice_image](AsepriteFile::nine_slice_image) but with
+    /// custom [NineSliceOptions].
+    pub fn nine_slice_image_with_options(
+        &self,
+        frame: u32,
+        slice_name: &str,
+        (width, height): (u32, u32),
+        options: &NineSliceOptions,
+    ) -> Result<RgbaImage> {
+        let source = self.slice_image(frame, slice_name)?;
+        let center = self
+            .slice(slice_name)
+            .and_then(|slice| slice.key_for_frame(frame))
+            .and_then(|key| key.slice9)
+            .ok_or_else(|| {
+                AsepriteParseError::InvalidInput(format!(
+                    "Slice {} has no 9-slice data in frame {}",
+                    slice_name, frame
+                ))
+            })?;
+        let columns = Axis::new(source.width(), center.center_x, center.center_width, width);
+        let rows = Axis::new(
+            source.height(),
+            center.center_y,
+            center.center_height,
+            height,
+        );
+        Ok(RgbaImage::from_fn(width, height, |x, y| {
+            let in_center = columns.in_middle(x) && rows.in_middle(y);
+            let fill = if in_center {
+                options.center
+            } else {
+                options.edges
+            };
+            match (columns.source(x, fill), rows.source(y, fill)) {
+                (Some(sx), Some(sy)) => *source.get_pixel(sx, sy),
+                _ => Rgba([0, 0, 0, 0]),
+            }
+        }))
+    }
+}
+
+// The columns or rows of a 9-slice: the start and end, which are corners,
+// and the middle part in between, in the source and the target image.
+struct Axis {
+    source_start: u32,
+    source_middle: u32,
+    source_end: u32,
+    target_start: u32,
+    target_middle: u32,
+    target_end: u32,
+}
+
+impl Axis {
+    fn new(source_len: u32, center_offset: i32, center_len: u32, target_len: u32) -> Self {
+        let source_start = (center_offset.max(0) as u32).min(source_len);
+        let source_middle = center_len.min(source_len - source_start);
+        let source_end = source_len - source_start - source_middle;
+        let corners = source_start + source_end;
+        let (target_start, target_end) = if target_len >= corners {
+            (source_start, source_end)
+        } else {
+            let start = (source_start as u64 * target_len as u64 / corners as u64) as u32;
+            (start, target_len - start)
+        };
+        Self {
+            source_start,
+            source_middle,
+            source_end,
+            target_start,
+            target_middle: target_len - target_start - target_end,
+            target_end,
+        }
+    }
+
+    fn in_middle(&self, t: u32) -> bool {
+        t >= self.target_start && t < self.target_start + self.target_middle
+    }
+
+    // The source position that the target position `t` shows. `None` if the
+    // middle has to be filled but the source has no middle.
+    fn source(&self, t: u32, fill: NineSliceFill) -> Option<u32> {
+        let scale =
+            |t: u32, target: u32, source: u32| (t as u64 * source as u64 / target as u64) as u32;
+        if t < self.target_start {
+            return Some(scale(t, self.target_start, self.source_start));
+        }
+        let t = t - self.target_start;
+        if t < self.target_middle {
+            if self.source_middle == 0 {
+                return None;
+            }
+            let offset = match fill {
+                NineSliceFill::Stretch => scale(t, self.target_middle, self.source_middle),
+                NineSliceFill::Tile => t % self.source_middle,
+            };
+            return Some(self.source_start + offset);
+        }
+        let t = t - self.target_middle;
+        let source_end_start = self.source_start + self.source_middle;
+        Some(source_end_start + scale(t, self.target_end, self.source_end))
+    }
+}
@@ -0,0 +1,339 @@
+/*!
+Lay out the frames of a sprite in a single sprite sheet image.
+
+[build] renders every frame, or the frames of a single tag, into one image
+and returns the position of each frame in it. Frames are placed in a single
+row, a single column, or a grid with a fixed number of columns. Frames can
+be trimmed to their non-transparent pixels, in which case each [SheetFrame]
+records where the trimmed image belongs in the untrimmed frame.
+
+```
+# use asefile::AsepriteFile;
+# use asefile::spritesheet::{self, SheetLayout, SpriteSheetOptions};
+# use std::path::Path;
+# let path = Path::new("./tests/data/layers_and_tags.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let options = SpriteSheetOptions::new()
+    .layout(SheetLayout::Grid { columns: 2 })
+    .spacing(1);
+let sheet = spritesheet::build(&ase, &options).unwrap();
+for frame in sheet.frames() {
+    println!(
+        "Frame {} at {},{} ({}x{})",
+        frame.frame(),
+        frame.x(),
+        frame.y(),
+        frame.width(),
+        frame.height()
+    );
+}
+// sheet.image().save("sheet.png").unwrap();
+```
+*/
+use crate::atlas::opaque_bounds;
+use crate::image::RgbaImage;
+use crate::{AsepriteFile, AsepriteParseError, Result};
+use std::collections::HashMap;
+
+/// How frames are arranged in a sprite sheet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SheetLayout {
+    /// All frames in a single row, from left to right.
+    #[default]
+    Horizontal,
+    /// All frames in a single column, from top to bottom.
+    Vertical,
+    /// Rows with the given number of frames, filled left to right and top to
+    /// bottom. Values less than 1 are treated as 1.
+    Grid {
+        /// Number of frames per row.
+        columns: u32,
+    },
+}
+
+/// Options for [build].
+///
+/// ```
+/// # use asefile::spritesheet::{SheetLayout, SpriteSheetOptions};
+/// let options = SpriteSheetOptions::new()
+///     .layout(SheetLayout::Vertical)
+///     .tag(0);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SpriteSheetOptions {
+    layout: SheetLayout,
+    tag: Option<u32>,
+    spacing: u32,
+    trim: bool,
+    merge_duplicates: bool,
+}
+
+impl SpriteSheetOptions {
+    /// Default options: All frames in a single row, without space between
+    /// them.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Arrange the frames in the given way.
+    pub fn layout(mut self, layout: SheetLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Only include the frames of the tag with the given id.
+    pub fn tag(mut self, tag_id: u32) -> Self {
+        self.tag = Some(tag_id);
+        self
+    }
+
+    /// Transparent pixels between neighboring frames. Defaults to 0.
+    pub fn spacing(mut self, pixels: u32) -> Self {
+        self.spacing = pixels;
+        self
+    }
+
+    /// Remove the fully transparent border of each frame. Frames then have
+    /// different sizes and are placed next to each other, with each row as
+    /// high as its highest frame and each column, in the
+    /// [Vertical](SheetLayout::Vertical) layout, as wide as its widest frame.
+    /// See [SheetFrame::trim_offset]. Defaults to `false`.
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Place frames with identical pixels only once. Their frames then share
+    /// the same position, and all but the first are marked as
+    /// [aliases](SheetFrame::alias_of). Defaults to `false`.
+    pub fn merge_duplicates(mut self, merge: bool) -> Self {
+        self.merge_duplicates = merge;
+        self
+    }
+}
+
+/// A sprite sheet created by [build].
+#[derive(Debug, Clone)]
+pub struct SpriteSheet {
+    pub(crate) image: RgbaImage,
+    frames: Vec<SheetFrame>,
+}
+
+impl SpriteSheet {
+    /// The sprite sheet image.
+    #[cfg(feature = "image")]
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// Take ownership of the sprite sheet image.
+    #[cfg(feature = "image")]
+    pub fn into_image(self) -> RgbaImage {
+        self.image
+    }
+
+    /// Like [into_image](SpriteSheet::into_image) but returns a
+    /// [raw_image::RgbaImage](crate::raw_image::RgbaImage). This is
+    /// available without the `image` feature.
+    pub fn into_raw_image(self) -> crate::raw_image::RgbaImage {
+        crate::raw_image::from_image(self.image)
+    }
+
+    /// The position of each frame in the image, in frame order.
+    pub fn frames(&self) -> &[SheetFrame] {
+        &self.frames
+    }
+}
+
+/// The source rectangle of a frame in a [SpriteSheet].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SheetFrame {
+    frame: u32,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    trim_offset: (u32, u32),
+    source_size: (u32, u32),
+    alias_of: Option<usize>,
+}
+
+impl SheetFrame {
+    /// The frame number in the sprite.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Left edge of the frame in the sprite sheet.
+    pub fn x(&self) -> u32 {
+        self.x
+    }
+
+    /// Top edge of the frame in the sprite sheet.
+    pub fn y(&self) -> u32 {
+        self.y
+    }
+
+    /// Width of the frame in the sprite sheet. This is the width of the
+    /// sprite unless the frame was trimmed.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the frame in the sprite sheet. This is the height of the
+    /// sprite unless the frame was trimmed.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Position of the trimmed image in the untrimmed frame. Always `(0, 0)`
+    /// if [trimming](SpriteSheetOptions::trim) is off.
+    pub fn trim_offset(&self) -> (u32, u32) {
+        self.trim_offset
+    }
+
+    /// Size of the untrimmed frame, which is the canvas size of the sprite.
+    pub fn source_size(&self) -> (u32, u32) {
+        self.source_size
+    }
+
+    /// Whether anything was removed by trimming.
+    pub fn is_trimmed(&self) -> bool {
+        (self.width, self.height) != self.source_size
+    }
+
+    /// If the frame's image is identical to an earlier frame and was merged
+    /// with it, the index of that frame in [SpriteSheet::frames]. Always
+    /// `None` if [merging duplicates](SpriteSheetOptions::merge_duplicates)
+    /// is off.
+    pub fn alias_of(&self) -> Option<usize> {
+        self.alias_of
+    }
+}
+
+/// Render the frames into a sprite sheet.
+///
+/// # Errors
+///
+/// Returns [AsepriteParseError::InvalidInput] if the tag does not exist.
+pub fn build(file: &AsepriteFile, options: &SpriteSheetOptions) -> Result<SpriteSheet> {
+    let frames: Vec<u32> = match options.tag {
+        Some(tag_id) if tag_id >= file.num_tags() => {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Tag does not exist: {}",
+                tag_id
+            )))
+        }
+        Some(tag_id) => {
+            let tag = file.tag(tag_id);
+            (tag.from_frame()..=tag.to_frame()).collect()
+        }
+        None => (0..file.num_frames()).collect(),
+    };
+    Ok(render(file, &frames, options))
+}
+
+pub(crate) fn render(
+    file: &AsepriteFile,
+    frames: &[u32],
+    options: &SpriteSheetOptions,
+) -> SpriteSheet {
+    let (width, height) = file.size();
+    let source_size = (width as u32, height as u32);
+
+    // The frames that get a cell, their images, and for each frame its cell
+    // and the frame it is an alias of. With merging, only the first frame
+    // with each image gets a cell.
+    let mut cells = Vec::new();
+    let mut cell_images = Vec::new();
+    let mut first_with_image: HashMap<RgbaImage, (usize, usize)> = HashMap::new();
+    let mut cell_of_frame = Vec::with_capacity(frames.len());
+    for (index, &frame) in frames.iter().enumerate() {
+        let image = file.frame(frame).composite();
+        if !options.merge_duplicates {
+            cells.push(frame);
+            cell_images.push(image);
+            cell_of_frame.push((cells.len() - 1, None));
+            continue;
+        }
+        let (first, cell) = *first_with_image.entry(image).or_insert_with_key(|image| {
+            cells.push(frame);
+            cell_images.push(image.clone());
+            (index, cells.len() - 1)
+        });
+        cell_of_frame.push((cell, Some(first).filter(|&first| first != index)));
+    }
+
+    let bounds: Vec<(u32, u32, u32, u32)> = cell_images
+        .iter()
+        .map(|image| {
+            if options.trim {
+                opaque_bounds(image)
+            } else {
+                (0, 0, source_size.0, source_size.1)
+            }
+        })
+        .collect();
+    let sizes: Vec<(u32, u32)> = bounds.iter().map(|&(_, _, w, h)| (w, h)).collect();
+    let (positions, (width, height)) = arrange(&sizes, options.layout, options.spacing);
+    let mut image = RgbaImage::new(width, height);
+    for ((cell_image, &(x, y, w, h)), &(cell_x, cell_y)) in
+        cell_images.iter().zip(&bounds).zip(&positions)
+    {
+        let trimmed = crate::image::imageops::crop_imm(cell_image, x, y, w, h);
+        crate::image::imageops::replace(&mut image, &trimmed, cell_x, cell_y);
+    }
+
+    let frames = frames
+        .iter()
+        .zip(cell_of_frame)
+        .map(|(&frame, (cell, alias_of))| {
+            let (x, y) = positions[cell];
+            let (offset_x, offset_y, width, height) = bounds[cell];
+            SheetFrame {
+                frame,
+                x,
+                y,
+                width,
+                height,
+                trim_offset: (offset_x, offset_y),
+                source_size,
+                alias_of,
+            }
+        })
+        .collect();
+    SpriteSheet { image, frames }
+}
+
+// Places rectangles of the given sizes row by row. Each row is as high as
+// its highest rectangle, each column of the vertical layout as wide as its
+// widest one.
+fn arrange(
+    sizes: &[(u32, u32)],
+    layout: SheetLayout,
+    spacing: u32,
+) -> (Vec<(u32, u32)>, (u32, u32)) {
+    let count = sizes.len() as u32;
+    let columns = match layout {
+        SheetLayout::Horizontal => count.max(1),
+        SheetLayout::Vertical => 1,
+        SheetLayout::Grid { columns } => columns.clamp(1, count.max(1)),
+    };
+    let mut positions = Vec::with_capacity(sizes.len());
+    let (mut width, mut height) = (0, 0);
+    for row in sizes.chunks(columns as usize) {
+        let y = if positions.is_empty() {
+            0
+        } else {
+            height + spacing
+        };
+        let mut x = 0;
+        for &(w, _) in row {
+            positions.push((x, y));
+            x += w + spacing;
+        }
+        width = width.max(x - spacing);
+        height = y + row.iter().map(|&(_, h)| h).max().unwrap_or(0);
+    }
+    (positions, (width, height))
+}
@@ -1,19 +1,46 @@
 use crate::{reader::AseReader, user_data::UserData, AsepriteParseError, Result};
+use alloc::{format, string::String, vec::Vec};
 
 /// A tag is a grouping of one or more frames.
 ///
 /// Tag ranges may overlap each other. Tag names are _not_ guaranteed to be
 /// unique.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tag {
     name: String,
     from_frame: u16,
     to_frame: u16,
     animation_direction: AnimationDirection,
+    repeat: u16,
     pub(crate) user_data: Option<UserData>,
 }
 
 impl Tag {
+    #[cfg(feature = "std")]
+    pub(crate) fn new(
+        name: String,
+        from_frame: u16,
+        to_frame: u16,
+        animation_direction: AnimationDirection,
+        repeat: u16,
+    ) -> Self {
+        Self {
+            name,
+            from_frame,
+            to_frame,
+            animation_direction,
+            repeat,
+            user_data: None,
+        }
+    }
+
+    // Ends the tag at the last of the first `num_frames` frames if it is
+    // longer. The tag must start before that frame.
+    pub(crate) fn clamp_to(&mut self, num_frames: u16) {
+        self.to_frame = self.to_frame.min(num_frames - 1);
+    }
+
     /// Tag name. May not be unique among all tags.
     pub fn name(&self) -> &str {
         &self.name
@@ -34,18 +61,41 @@ impl Tag {
         self.animation_direction
     }
 
+    /// How many times the tag's animation should be played. `0` means that
+    /// the animation loops forever.
+    ///
+    /// For [AnimationDirection::PingPong] each direction counts as one
+    /// repetition, i.e., a value of `2` plays once forward and once backward.
+    ///
+    /// Files written before Aseprite 1.3 always have `0` here.
+    pub fn repeat(&self) -> u16 {
+        self.repeat
+    }
+
     /// Returns the user data for the tag, if any exists.
     pub fn user_data(&self) -> Option<&UserData> {
         self.user_data.as_ref()
     }
 
+    #[cfg(feature = "std")]
+    pub(crate) fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
     pub(crate) fn set_user_data(&mut self, user_data: UserData) {
         self.user_data = Some(user_data);
     }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn set_range(&mut self, from_frame: u16, to_frame: u16) {
+        self.from_frame = from_frame;
+        self.to_frame = to_frame;
+    }
 }
 
 /// Describes how the tag's frames should be animated.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AnimationDirection {
     /// Start at `from_frame` and count up to `to_frame`.
     Forward,
@@ -56,18 +106,21 @@ pub enum AnimationDirection {
 }
 
 pub(crate) fn parse_chunk(data: &[u8]) -> Result<Vec<Tag>> {
-    let mut reader = AseReader::new(&data);
+    let mut reader = AseReader::new(data);
 
     let num_tags = reader.word()?;
     reader.skip_reserved(8)?;
 
+    trace_span!(DEBUG, "parse_tags", num_tags);
+
     let mut result = Vec::with_capacity(num_tags as usize);
 
     for _tag in 0..num_tags {
         let from_frame = reader.word()?;
         let to_frame = reader.word()?;
         let anim_dir = reader.byte()?;
-        reader.skip_reserved(8)?;
+        let repeat = reader.word()?;
+        reader.skip_reserved(6)?;
         let _color = reader.dword()?;
         let name = reader.string()?;
         let animation_direction = parse_animation_direction(anim_dir)?;
@@ -76,6 +129,7 @@ pub(crate) fn parse_chunk(data: &[u8]) -> Result<Vec<Tag>> {
             from_frame,
             to_frame,
             animation_direction,
+            repeat,
             user_data: None,
         });
     }
@@ -83,7 +137,7 @@ pub(crate) fn parse_chunk(data: &[u8]) -> Result<Vec<Tag>> {
     Ok(result)
 }
 
-fn parse_animation_direction(id: u8) -> Result<AnimationDirection> {
+pub(crate) fn parse_animation_direction(id: u8) -> Result<AnimationDirection> {
     match id {
         0 => Ok(AnimationDirection::Forward),
         1 => Ok(AnimationDirection::Reverse),
@@ -56,6 +56,220 @@ fn test_user_data(s: &str, c: [u8; 4]) -> UserData {
     UserData {
         text: Some(s.to_string()),
         color: Some(image::Rgba::from_channels(c[0], c[1], c[2], c[3])),
+        properties: Default::default(),
+    }
+}
+
+// Little-endian byte buffer for crafting chunk and file data in tests. Follows
+// the naming of the data types in the file format spec.
+#[derive(Default, Clone)]
+struct Bytes(Vec<u8>);
+
+impl Bytes {
+    fn byte(mut self, value: u8) -> Self {
+        self.0.push(value);
+        self
+    }
+    fn word(mut self, value: u16) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+    fn short(mut self, value: i16) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+    fn dword(mut self, value: u32) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+    fn string(self, value: &str) -> Self {
+        self.word(value.len() as u16).bytes(value.as_bytes())
+    }
+    fn bytes(mut self, value: &[u8]) -> Self {
+        self.0.extend_from_slice(value);
+        self
+    }
+    fn zeros(mut self, count: usize) -> Self {
+        self.0.resize(self.0.len() + count, 0);
+        self
+    }
+    fn zlib(self, value: &[u8]) -> Self {
+        use flate2::{write::ZlibEncoder, Compression};
+        use std::io::Write;
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(value).unwrap();
+        self.bytes(&encoder.finish().unwrap())
+    }
+}
+
+// A chunk for `craft_file`.
+fn chunk(chunk_type: u16, data: Bytes) -> Bytes {
+    Bytes::default()
+        .dword(data.0.len() as u32 + 6)
+        .word(chunk_type)
+        .bytes(&data.0)
+}
+
+// Builds a complete file in memory. Each frame is given as its list of chunks;
+// all frames last 100ms.
+fn craft_file(width: u16, height: u16, color_depth: u16, frames: &[Vec<Bytes>]) -> Vec<u8> {
+    let mut body = Bytes::default();
+    for chunks in frames {
+        let data: Vec<u8> = chunks.iter().flat_map(|c| c.0.clone()).collect();
+        body = body
+            .dword(data.len() as u32 + 16)
+            .word(0xF1FA)
+            .word(chunks.len() as u16)
+            .word(100)
+            .zeros(2)
+            .dword(chunks.len() as u32)
+            .bytes(&data);
+    }
+    Bytes::default()
+        .dword(body.0.len() as u32 + 128)
+        .word(0xA5E0)
+        .word(frames.len() as u16)
+        .word(width)
+        .word(height)
+        .word(color_depth)
+        .dword(1) // flags
+        .word(100) // speed
+        .zeros(8)
+        .byte(0) // transparent color index
+        .zeros(3)
+        .word(0) // number of colors
+        .byte(1) // pixel width
+        .byte(1) // pixel height
+        .short(0)
+        .short(0)
+        .word(16)
+        .word(16)
+        .zeros(84)
+        .bytes(&body.0)
+        .0
+}
+
+fn layer_chunk(name: &str) -> Bytes {
+    nested_layer_chunk(name, 0, 0)
+}
+
+// A visible layer of the given type (0: image, 1: group) inside the group
+// above it with a lower child level.
+fn nested_layer_chunk(name: &str, layer_type: u16, child_level: u16) -> Bytes {
+    let data = Bytes::default()
+        .word(1) // flags: visible
+        .word(layer_type)
+        .word(child_level)
+        .zeros(4)
+        .word(0) // blend mode
+        .byte(255)
+        .zeros(3)
+        .string(name);
+    chunk(0x2004, data)
+}
+
+// A raw (uncompressed) cel at position (0, 0).
+fn raw_cel_chunk(layer: u16, width: u16, height: u16, pixels: &[u8]) -> Bytes {
+    let data = Bytes::default()
+        .word(layer)
+        .short(0)
+        .short(0)
+        .byte(255)
+        .word(0) // raw image
+        .zeros(7)
+        .word(width)
+        .word(height)
+        .bytes(pixels);
+    chunk(0x2005, data)
+}
+
+fn palette_chunk(colors: &[[u8; 4]]) -> Bytes {
+    let mut data = Bytes::default()
+        .dword(colors.len() as u32)
+        .dword(0)
+        .dword(colors.len() as u32 - 1)
+        .zeros(8);
+    for color in colors {
+        data = data.word(0).bytes(color);
+    }
+    chunk(0x2019, data)
+}
+
+fn unlimited() -> limits::Budget {
+    limits::Budget::new(Default::default())
+}
+
+fn assert_limit_exceeded<T: std::fmt::Debug>(result: Result<T>) {
+    match result {
+        Err(AsepriteParseError::LimitExceeded(msg)) => println!("Expected error: {}", msg),
+        other => panic!("Expected LimitExceeded error, got: {:?}", other),
+    }
+}
+
+fn assert_invalid_input<T: std::fmt::Debug>(result: Result<T>) {
+    match result {
+        Err(AsepriteParseError::InvalidInput(msg)) => println!("Expected error: {}", msg),
+        other => panic!("Expected InvalidInput error, got: {:?}", other),
+    }
+}
+
+// Checks that two files have the same content, including everything that does
+// not affect rendering. Pixel buffers may be laid out differently.
+fn assert_semantic_eq(a: &AsepriteFile, b: &AsepriteFile) {
+    use std::hash::Hasher;
+
+    let changes = diff::diff(a, b);
+    assert!(changes.is_empty(), "{:?}", changes);
+    assert_eq!(format!("{:?}", a.layers), format!("{:?}", b.layers));
+    assert_eq!(format!("{:?}", a.tags), format!("{:?}", b.tags));
+    assert_eq!(a.frame_times, b.frame_times);
+    assert_eq!(a.slices, b.slices);
+    assert_eq!(a.sprite_user_data, b.sprite_user_data);
+    assert_eq!(a.color_profile, b.color_profile);
+    assert_eq!(a.warnings, b.warnings);
+    assert_eq!(a.trailing_data, b.trailing_data);
+
+    let colors = |f: &AsepriteFile| {
+        f.palette.as_ref().map(|palette| {
+            let mut entries: Vec<_> = palette
+                .entries
+                .values()
+                .map(|e| format!("{:?}", e))
+                .collect();
+            entries.sort();
+            entries
+        })
+    };
+    assert_eq!(colors(a), colors(b));
+
+    let tilesets = |f: &AsepriteFile| {
+        let mut tilesets: Vec<_> = f.tilesets.map().values().collect();
+        tilesets.sort_by_key(|tileset| tileset.id.0);
+        format!("{:?}", tilesets)
+    };
+    assert_eq!(tilesets(a), tilesets(b));
+    let external_files = |f: &AsepriteFile| {
+        let mut external_files: Vec<_> = f.external_files.map().values().collect();
+        external_files.sort_by_key(|external_file| *external_file.id().value());
+        format!("{:?}", external_files)
+    };
+    assert_eq!(external_files(a), external_files(b));
+    assert_eq!(a.grid, b.grid);
+    assert_eq!(a.pixel_ratio, b.pixel_ratio);
+    assert_eq!(a.raw_chunks, b.raw_chunks);
+
+    let cels = |f: &AsepriteFile, frame: u16| -> Vec<(String, u64)> {
+        f.framedata
+            .frame_cels(frame)
+            .map(|(_, cel)| {
+                let mut hasher = hash::Fnv1a::new();
+                cel.write_hash(&f.framedata, &mut hasher);
+                (format!("{:?}", cel), hasher.finish())
+            })
+            .collect()
+    };
+    for frame in 0..a.num_frames {
+        assert_eq!(cels(a, frame), cels(b, frame), "frame {}", frame);
     }
 }
 
@@ -128,6 +342,59 @@ fn cels_basic() {
     let _image = cel1.image();
 }
 
+#[test]
+fn raw_images() {
+    use crate::spritesheet::{self, SpriteSheetOptions};
+
+    let f = load_test_file("layers_and_tags");
+    for frame in 0..f.num_frames() {
+        let image = f.frame(frame).image();
+        let raw = f.frame(frame).raw_image();
+        assert_eq!(raw.dimensions(), image.dimensions());
+        assert_eq!(raw.as_raw(), image.as_raw());
+        let frame = f.frame(frame);
+        for layer in 0..f.num_layers() {
+            let cel = frame.layer(layer);
+            assert_eq!(cel.raw_image().into_raw(), cel.image().into_raw());
+        }
+    }
+    let options = ImageOptions::new().apply_pixel_ratio(true);
+    assert_eq!(
+        f.frame(1).raw_image_with_options(&options).into_raw(),
+        f.frame(1).image_with_options(&options).into_raw()
+    );
+    let sheet = spritesheet::build(&f, &SpriteSheetOptions::new()).unwrap();
+    let image = sheet.image().clone();
+    assert_eq!(sheet.into_raw_image().into_raw(), image.into_raw());
+
+    let f = load_test_file("tilemap");
+    let tileset = f.tilesets().get(&TilesetId::new(0)).unwrap();
+    for tile_id in 0..*tileset.tile_count() {
+        let image = tileset.tile_image(tile_id).unwrap();
+        let raw = tileset.raw_tile_image(tile_id).unwrap();
+        assert_eq!(raw.dimensions(), image.dimensions());
+        assert_eq!(raw.into_raw(), image.into_raw());
+    }
+    assert!(tileset.raw_tile_image(*tileset.tile_count()).is_none());
+
+    let user_data = test_user_data("test", [1, 2, 3, 4]);
+    assert_eq!(user_data.raw_color(), Some([1, 2, 3, 4]));
+}
+
+#[test]
+fn set_cel_raw_image() {
+    let mut f = load_test_file("basic-16x16");
+    let image = raw_image::RgbaImage::from_fn(3, 2, |x, y| {
+        raw_image::Rgba([x as u8 * 80, y as u8 * 80, 40, 255])
+    });
+    f.set_cel_raw_image(0, 0, &image, (4, 5)).unwrap();
+    let frame = f.frame(0).image();
+    for (x, y, pixel) in image.enumerate_pixels() {
+        assert_eq!(frame.get_pixel(x + 4, y + 5).0, pixel.0);
+    }
+    assert_invalid_input(f.set_cel_raw_image(0, 0, &raw_image::RgbaImage::new(0, 2), (0, 0)));
+}
+
 #[test]
 fn background() {
     let f = load_test_file("background");
@@ -328,6 +595,71 @@ fn indexed() {
     compare_with_reference_image(f.frame(0).image(), "indexed_01");
 }
 
+#[test]
+fn indexed_png() {
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    for name in &["indexed", "tilemap_indexed"] {
+        let f = load_test_file(name);
+        let mut data = Vec::new();
+        f.frame(0).write_indexed_png(&mut data).unwrap();
+
+        let chunks = png::decode::chunks(&data);
+        let types: Vec<&[u8]> = chunks.iter().map(|(chunk_type, _)| *chunk_type).collect();
+        assert_eq!(types[0], b"IHDR");
+        assert_eq!(chunks[0].1[8..10], [8, 3]);
+        assert_eq!(types[1], b"PLTE");
+        let palette = f.palette().unwrap();
+        let expected: Vec<u8> = (0..palette.num_colors())
+            .flat_map(|index| palette.color(index).unwrap().raw_rgba8()[..3].to_vec())
+            .collect();
+        assert_eq!(chunks[1].1, &expected[..]);
+
+        // The transparent index is the only transparent entry.
+        let transparent_index = f.pixel_format().transparent_color_index().unwrap();
+        let (_, trns) = chunks.iter().find(|(t, _)| *t == b"tRNS").unwrap();
+        assert_eq!(trns[transparent_index as usize], 0);
+
+        // Every pixel is an index into the palette.
+        let (_, compressed) = chunks.iter().find(|(t, _)| *t == b"IDAT").unwrap();
+        let mut filtered = Vec::new();
+        ZlibDecoder::new(*compressed)
+            .read_to_end(&mut filtered)
+            .unwrap();
+        let (width, height) = f.size();
+        assert_eq!(filtered.len(), (width + 1) * height);
+        assert!(filtered
+            .chunks(width + 1)
+            .all(|row| row[0] == 0 && row[1..].iter().all(|&i| (i as u32) < palette.num_colors())));
+
+        let decoded = image::load_from_memory(&data).unwrap().to_rgba8();
+        let image = f.frame(0).image();
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let other = decoded.get_pixel(x, y);
+            if pixel[3] == 0 {
+                assert_eq!(other[3], 0);
+            } else {
+                assert_eq!(pixel, other, "{} at ({}, {})", name, x, y);
+            }
+        }
+    }
+
+    let rgba = load_test_file("basic-16x16");
+    let err = rgba.frame(0).write_indexed_png(Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    let f = load_test_file("indexed");
+    let dir = std::env::temp_dir().join("asefile_indexed_export");
+    let paths = f
+        .export_frames_with_options(&dir, "frame.png", &FrameExportOptions::new().indexed(true))
+        .unwrap();
+    let mut original = Vec::new();
+    f.frame(0).write_indexed_png(&mut original).unwrap();
+    assert_eq!(std::fs::read(&paths[0]).unwrap(), original);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
 #[test]
 fn grayscale() {
     let f = load_test_file("grayscale");
@@ -336,6 +668,57 @@ fn grayscale() {
     compare_with_reference_image(f.frame(0).image(), "grayscale");
 }
 
+#[test]
+fn native_images() {
+    for name in &["indexed", "tilemap_indexed"] {
+        let f = load_test_file(name);
+        let palette = f.palette().unwrap();
+        let transparent = f.pixel_format().transparent_color_index().unwrap();
+        // Transparent pixels may have any color.
+        let resolve = |index: pixel::Indexed| {
+            let color = palette.color(index.value() as u32).unwrap().raw_rgba8();
+            if index.value() == transparent || color[3] == 0 {
+                None
+            } else {
+                Some(color)
+            }
+        };
+        let visible = |pixel: &image::Rgba<u8>| Some(pixel.0).filter(|pixel| pixel[3] != 0);
+        let frame = f.frame(0);
+        let cel = frame.layer(0);
+        let indexed = cel.indexed_image().unwrap();
+        assert_eq!(indexed.dimensions(), cel.image().dimensions());
+        for (x, y, pixel) in cel.image().enumerate_pixels() {
+            assert_eq!(resolve(indexed.get_pixel(x, y)), visible(pixel));
+        }
+        let indexed = f.frame(0).indexed_image().unwrap();
+        for (x, y, pixel) in f.frame(0).image().enumerate_pixels() {
+            assert_eq!(resolve(indexed.get_pixel(x, y)), visible(pixel));
+        }
+        assert!(cel.grayscale_image().is_none());
+        assert!(f.frame(0).grayscale_image().is_none());
+    }
+
+    for name in &["grayscale", "tilemap_grayscale"] {
+        let f = load_test_file(name);
+        let frame = f.frame(0);
+        let cel = frame.layer(0);
+        let grayscale = cel.grayscale_image().unwrap();
+        for (x, y, pixel) in cel.image().enumerate_pixels() {
+            let expected = pixel::Grayscale::new(pixel[0], pixel[3]);
+            assert_eq!(grayscale.get_pixel(x, y), expected);
+        }
+        let grayscale = f.frame(0).grayscale_image().unwrap();
+        assert_eq!(grayscale.dimensions(), f.frame(0).image().dimensions());
+        assert!(cel.indexed_image().is_none());
+        assert!(f.frame(0).indexed_image().is_none());
+    }
+
+    let f = load_test_file("basic-16x16");
+    assert!(f.frame(0).indexed_image().is_none());
+    assert!(f.frame(0).layer(0).grayscale_image().is_none());
+}
+
 #[test]
 fn palette() {
     let f = load_test_file("palette");
@@ -353,7 +736,7 @@ fn tilemap() {
     assert_eq!(f.size(), (32, 32));
     let ts = f
         .tilesets()
-        .get(&tileset::TilesetId::new(0))
+        .get(&TilesetId::new(0))
         .expect("No tileset found");
     assert_eq!(ts.name(), "test_tileset");
 
@@ -367,7 +750,7 @@ fn tilemap_indexed() {
     assert_eq!(f.size(), (32, 32));
     let ts = f
         .tilesets()
-        .get(&tileset::TilesetId::new(0))
+        .get(&TilesetId::new(0))
         .expect("No tileset found");
     assert_eq!(ts.name(), "test_tileset");
 
@@ -381,7 +764,7 @@ fn tilemap_grayscale() {
     assert_eq!(f.size(), (32, 32));
     let ts = f
         .tilesets()
-        .get(&tileset::TilesetId::new(0))
+        .get(&TilesetId::new(0))
         .expect("No tileset found");
     assert_eq!(ts.name(), "test_tileset");
 
@@ -393,13 +776,64 @@ fn tileset_export() {
     let f = load_test_file("tileset");
     let tileset = f
         .tilesets()
-        .get(&tileset::TilesetId::new(0))
+        .get(&TilesetId::new(0))
         .expect("No tileset found");
     let img = f.tileset_image(tileset.id()).unwrap();
 
     compare_with_reference_image(img, "tileset");
 }
 
+#[test]
+fn tileset_indexed_pixels() {
+    let f = load_test_file("tilemap_indexed");
+    let tileset = f.tilesets().get(&TilesetId::new(0)).unwrap();
+    let image = f.tileset_image(tileset.id()).unwrap();
+    let palette = f.palette().unwrap();
+    let transparent_color_index = f.pixel_format().transparent_color_index().unwrap();
+    let tile_width = *tileset.tile_size().width() as u32;
+    let tile_height = *tileset.tile_size().height() as u32;
+    let to_rgba = |index: u8| {
+        let c = palette.color(index as u32).unwrap();
+        if index == transparent_color_index {
+            [c.red(), c.green(), c.blue(), 0]
+        } else {
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        }
+    };
+
+    for tile_id in 0..*tileset.tile_count() {
+        let indices = tileset.tile_indexed_pixels(tile_id).unwrap();
+        assert_eq!(indices.len() as u32, tile_width * tile_height);
+        for (i, index) in indices.iter().enumerate() {
+            let x = i as u32 % tile_width;
+            let y = tile_id * tile_height + i as u32 / tile_width;
+            assert_eq!(to_rgba(*index), image.get_pixel(x, y).0);
+        }
+    }
+    assert!(tileset.tile_indexed_pixels(*tileset.tile_count()).is_none());
+
+    let columns = 3;
+    let (atlas, width, height) = tileset.indexed_atlas(columns).unwrap();
+    let rows = tileset.tile_count().div_ceil(columns);
+    assert_eq!((width, height), (columns * tile_width, rows * tile_height));
+    assert_eq!(atlas.len() as u32, width * height);
+    for tile_id in 0..*tileset.tile_count() {
+        let indices = tileset.tile_indexed_pixels(tile_id).unwrap();
+        let x0 = (tile_id % columns) * tile_width;
+        let y0 = (tile_id / columns) * tile_height;
+        for (i, index) in indices.iter().enumerate() {
+            let x = x0 + i as u32 % tile_width;
+            let y = y0 + i as u32 / tile_width;
+            assert_eq!(atlas[(y * width + x) as usize], *index);
+        }
+    }
+
+    let f = load_test_file("tileset");
+    let tileset = f.tilesets().get(&TilesetId::new(0)).unwrap();
+    assert!(tileset.tile_indexed_pixels(0).is_none());
+    assert!(tileset.indexed_atlas(4).is_none());
+}
+
 #[test]
 fn user_data_sprite() {
     let f = load_test_file("user_data");
@@ -440,6 +874,7 @@ fn user_data_tags() {
     let expected_second = UserData {
         text: None,
         color: Some(image::Rgba::from_channels(0, 0, 0, 255)),
+        properties: Default::default(),
     };
     assert_eq!(*second, expected_second);
 
@@ -455,25 +890,5335 @@ fn cel_overflow() {
     assert_eq!(file.width as u32, img.width());
     assert_eq!(file.height as u32, img.height());
 }
-/*
 #[test]
-fn gen_random_pixels() {
-    use rand::Rng;
-    use image::{Rgba};
-    use std::path::Path;
-    let mut rng = rand::thread_rng();
+fn diff_identical() {
+    let a = load_test_file("layers_and_tags");
+    let b = load_test_file("layers_and_tags");
+    let changes = diff::diff(&a, &b);
+    assert!(changes.is_empty());
+    assert_eq!(changes.to_string(), "no changes\n");
+}
 
-    let (width, height) = (256, 256);
-    let mut img = image::RgbaImage::new(width, height);
-    for y in 0..width {
-        for x in 0..height {
-            let r: u8 = rng.gen();
-            let g: u8 = rng.gen();
-            let b: u8 = rng.gen();
-            let a: u8 = rng.gen();
-            img.put_pixel(x, y, Rgba([r, g, b, a]));
+#[test]
+fn diff_perturbed() {
+    use diff::{Change, ChangeKind};
+
+    let a = load_test_file("layers_and_tags");
+    let mut b = load_test_file("layers_and_tags");
+    b.width = 32;
+    b.frame_times[1] = 250;
+    b.layers.layers[1].opacity = 128;
+    b.tags.remove(1);
+    let cel = b.framedata.cel(cel::CelId { frame: 1, layer: 1 }).unwrap();
+    if let cel::CelContent::Raw(content) = &cel.content {
+        let cel::PixelRange { frame, range } = content.pixels.clone();
+        if let pixel::Pixels::Rgba(pixels) = b.framedata.frame_pixels_mut(frame) {
+            pixels[range.start] = image::Rgba([1, 2, 3, 255]);
         }
     }
-    img.save(&Path::new("tests/data/random-256x256.png")).unwrap();
+
+    let changes = diff::diff(&a, &b);
+    assert_eq!(
+        changes.size,
+        Some(Change {
+            old: (16, 16),
+            new: (32, 16)
+        })
+    );
+    assert_eq!(changes.num_frames, None);
+    assert_eq!(changes.frame_durations.len(), 1);
+    assert_eq!(changes.frame_durations[0].frame, 1);
+    assert_eq!(changes.layers.len(), 1);
+    let layer = &changes.layers[0];
+    assert_eq!(layer.path, "Layer 1");
+    assert_eq!(layer.kind, ChangeKind::Modified);
+    assert_eq!(layer.opacity, Some(Change { old: 255, new: 128 }));
+    // Frames 2 and 3 link to the cel in frame 1.
+    assert_eq!(layer.changed_frames, vec![1, 2, 3]);
+    assert_eq!(changes.tags.len(), 1);
+    assert_eq!(changes.tags[0].name, "T3");
+    assert_eq!(changes.tags[0].kind, ChangeKind::Removed);
+    assert!(changes.slices.is_empty());
+    assert!(changes.palette.is_none());
+
+    let text = changes.to_string();
+    assert!(text.contains("canvas resized: 16x16 -> 32x16"));
+    assert!(
+        text.contains("layer \"Layer 1\": opacity 255 -> 128; pixels changed in frames 1, 2, 3")
+    );
+    assert!(text.contains("tag \"T3\" removed"));
+}
+
+#[test]
+fn diff_layer_moved_into_group() {
+    use diff::ChangeKind;
+
+    let a = load_test_file("layers_and_tags");
+    let mut b = load_test_file("layers_and_tags");
+    // Turn "Group 1/Layer 4" into a top level layer.
+    b.layers.layers[5].child_level = 0;
+    b.layers = layer::LayersData::from_vec(b.layers.layers).unwrap();
+
+    let changes = diff::diff(&a, &b);
+    assert_eq!(changes.layers.len(), 1);
+    let layer = &changes.layers[0];
+    assert_eq!(layer.kind, ChangeKind::Modified);
+    assert_eq!(layer.path, "Layer 4");
+    assert_eq!(layer.old_path.as_deref(), Some("Group 1/Layer 4"));
+}
+
+#[test]
+fn diff_slices_and_palette() {
+    use diff::ChangeKind;
+
+    let a = load_test_file("slice");
+    let mut b = load_test_file("slice");
+    b.slices[0].keys[0].origin.x += 1;
+    let changes = diff::diff(&a, &b);
+    assert_eq!(changes.slices.len(), 1);
+    assert_eq!(changes.slices[0].kind, ChangeKind::Modified);
+    assert!(changes.slices[0].keys_changed);
+
+    let a = load_test_file("indexed");
+    let b = load_test_file("palette");
+    let changes = diff::diff(&a, &b);
+    let palette = changes.palette.unwrap();
+    assert!(!palette.changed_colors.is_empty());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn diff_serialize() {
+    let a = load_test_file("layers_and_tags");
+    let mut b = load_test_file("layers_and_tags");
+    b.tags.remove(0);
+    let changes = diff::diff(&a, &b);
+    let json = serde_json::to_string(&changes).unwrap();
+    assert!(json.contains("\"kind\":\"Removed\""));
+    let parsed: diff::FileDiff = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, changes);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn reload_serialize() {
+    let a = load_test_file("layers_and_tags");
+    let mut b = load_test_file("layers_and_tags");
+    b.tags.remove(0);
+    let mapping = reload::match_ids(&a, &b);
+    let json = serde_json::to_string(&mapping).unwrap();
+    let parsed: reload::IdMapping = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, mapping);
+    assert_eq!(parsed.tags.removed, vec![0]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn metadata_serialize() {
+    let f = load_test_file("slice");
+    let json = serde_json::to_string(f.slices()).unwrap();
+    let slices: Vec<Slice> = serde_json::from_str(&json).unwrap();
+    assert_eq!(&slices, f.slices());
+
+    let f = load_test_file("user_data");
+    let json = serde_json::to_string(&f.tag(0)).unwrap();
+    let tag: Tag = serde_json::from_str(&json).unwrap();
+    assert_eq!(tag.name(), f.tag(0).name());
+    assert_eq!(tag.user_data(), f.tag(0).user_data());
+    assert!(json.contains("\"color\":[0,255,0,255]"));
+
+    let layer = serde_json::to_value(f.layer(0)).unwrap();
+    assert_eq!(layer["id"], 0);
+    assert_eq!(layer["parent"], serde_json::Value::Null);
+    assert_eq!(layer["user_data"]["text"], "test_user_data_layer");
+    let frame = serde_json::to_value(f.frame(0)).unwrap();
+    assert_eq!(frame["index"], 0);
+    assert_eq!(frame["duration"], f.frame(0).duration());
+}
+
+// The following tests use sizes and counts close to u32::MAX. They must fail
+// with an error rather than overflow or try to allocate huge buffers.
+
+#[test]
+fn overflow_palette_indices() {
+    let data = Bytes::default().dword(0).dword(0).dword(u32::MAX).zeros(8);
+    assert_invalid_input(palette::parse_chunk(&data.0));
+}
+
+#[test]
+fn overflow_slice_key_count() {
+    let data = Bytes::default()
+        .dword(u32::MAX)
+        .dword(0)
+        .dword(0)
+        .string("slice");
+    let result = slice::parse_chunk(&data.0);
+    assert!(matches!(result, Err(AsepriteParseError::IoError(_))));
+}
+
+#[test]
+fn overflow_external_file_count() {
+    let data = Bytes::default().dword(u32::MAX).zeros(8);
+    let result = external_file::ExternalFile::parse_chunk(&data.0);
+    assert!(matches!(result, Err(AsepriteParseError::IoError(_))));
+}
+
+#[test]
+fn overflow_tileset_size() {
+    let data = Bytes::default()
+        .dword(0) // id
+        .dword(2) // flags: includes tiles
+        .dword(u32::MAX) // number of tiles
+        .word(u16::MAX)
+        .word(u16::MAX)
+        .short(1)
+        .zeros(14)
+        .string("tileset")
+        .dword(0)
+        .zlib(&[0; 16]);
+    assert_invalid_input(tileset::Tileset::parse_chunk(
+        &data.0,
+        PixelFormat::Rgba,
+        &mut unlimited(),
+    ));
+}
+
+#[test]
+fn overflow_cel_size() {
+    // A 65535x65535 compressed cel with only 16 bytes of pixel data.
+    let data = Bytes::default()
+        .word(0) // layer index
+        .short(0)
+        .short(0)
+        .byte(255)
+        .word(2) // compressed image
+        .zeros(7)
+        .word(u16::MAX)
+        .word(u16::MAX)
+        .zlib(&[0; 16]);
+    assert_invalid_input(cel::parse_chunk(
+        &data.0,
+        0,
+        &mut pixel::FramePixels::new(PixelFormat::Rgba),
+        &mut unlimited(),
+    ));
+}
+
+#[test]
+fn resource_limits() {
+    let read = |name: &str, options: ReadOptions| {
+        let data = std::fs::read(format!("./tests/data/{}.aseprite", name)).unwrap();
+        AsepriteFile::read_with_options(&data[..], &options)
+    };
+    let options = ReadOptions::new;
+
+    assert!(read("basic-16x16", options().max_canvas_size(16, 16)).is_ok());
+    assert_limit_exceeded(read("basic-16x16", options().max_canvas_size(15, 100)));
+    assert_limit_exceeded(read("basic-16x16", options().max_canvas_size(100, 15)));
+
+    let num_frames = load_test_file("layers_and_tags").num_frames() as u16;
+    assert!(read("layers_and_tags", options().max_frames(num_frames)).is_ok());
+    assert_limit_exceeded(read(
+        "layers_and_tags",
+        options().max_frames(num_frames - 1),
+    ));
+
+    assert!(read("basic-16x16", options().max_image_bytes(16 * 16 * 4)).is_ok());
+    assert_limit_exceeded(read("basic-16x16", options().max_image_bytes(100)));
+    assert_limit_exceeded(read("tileset", options().max_image_bytes(100)));
+    assert_limit_exceeded(read("tilemap", options().max_image_bytes(10)));
+
+    assert!(read("big", options().max_total_bytes(u64::MAX)).is_ok());
+    assert_limit_exceeded(read("big", options().max_total_bytes(1000)));
+    assert_limit_exceeded(read("big", options().lazy(true).max_total_bytes(1000)));
+}
+
+#[test]
+fn limit_compressed_cel_size() {
+    // A 65535x65535 compressed cel with only 16 bytes of pixel data, which is
+    // rejected without decompressing it.
+    let data = Bytes::default()
+        .word(0)
+        .short(0)
+        .short(0)
+        .byte(255)
+        .word(2)
+        .zeros(7)
+        .word(u16::MAX)
+        .word(u16::MAX)
+        .zlib(&[0; 16]);
+    let limits = limits::Limits {
+        image_bytes: Some(1 << 20),
+        ..Default::default()
+    };
+    let result = cel::parse_chunk(
+        &data.0,
+        0,
+        &mut pixel::FramePixels::new(PixelFormat::Rgba),
+        &mut limits::Budget::new(limits),
+    );
+    assert_limit_exceeded(result);
+}
+
+#[test]
+fn cel_with_too_much_pixel_data() {
+    let data = Bytes::default()
+        .word(0)
+        .short(0)
+        .short(0)
+        .byte(255)
+        .word(2)
+        .zeros(7)
+        .word(2)
+        .word(2)
+        .zlib(&[0; 17]);
+    assert_invalid_input(cel::parse_chunk(
+        &data.0,
+        0,
+        &mut pixel::FramePixels::new(PixelFormat::Rgba),
+        &mut unlimited(),
+    ));
+}
+
+#[test]
+fn large_tile_size() {
+    let data = Bytes::default()
+        .dword(0)
+        .dword(0) // no tiles included
+        .dword(1)
+        .word(256)
+        .word(256)
+        .short(1)
+        .zeros(14)
+        .string("tileset");
+    let tileset =
+        tileset::Tileset::parse_chunk(&data.0, PixelFormat::Rgba, &mut unlimited()).unwrap();
+    assert_eq!(tileset.tile_size().pixels_per_tile(), 65536);
+}
+
+#[test]
+fn crafted_file() {
+    let pixels = [255, 0, 0, 255].repeat(4);
+    let bytes = craft_file(
+        2,
+        2,
+        32,
+        &[vec![layer_chunk("Layer"), raw_cel_chunk(0, 2, 2, &pixels)]],
+    );
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    assert_eq!(f.size(), (2, 2));
+    assert!(!f.frame(0).layer(0).is_empty());
+    assert_eq!(f.frame(0).image().get_pixel(1, 1).0, [255, 0, 0, 255]);
+}
+
+#[test]
+fn tag_repeat() {
+    let file_with_repeat = |repeat: u16| {
+        let tags = Bytes::default()
+            .word(1)
+            .zeros(8)
+            .word(0) // from frame
+            .word(1) // to frame
+            .byte(2) // ping-pong
+            .word(repeat)
+            .zeros(6)
+            .zeros(4) // color
+            .string("Walk");
+        let bytes = craft_file(
+            2,
+            2,
+            32,
+            &[vec![layer_chunk("Layer"), chunk(0x2018, tags)], vec![]],
+        );
+        AsepriteFile::read(&bytes[..]).unwrap()
+    };
+    let f = file_with_repeat(3);
+    assert_eq!(f.tag(0).name(), "Walk");
+    assert_eq!(f.tag(0).animation_direction(), AnimationDirection::PingPong);
+    assert_eq!(f.tag(0).repeat(), 3);
+    assert_eq!(load_test_file("layers_and_tags").tag(0).repeat(), 0);
+
+    let changes = diff::diff(&file_with_repeat(0), &f);
+    assert_eq!(
+        changes.tags[0].repeat,
+        Some(diff::Change { old: 0, new: 3 })
+    );
+    assert!(changes.to_string().contains("repeat 0 -> 3"));
+}
+
+#[test]
+fn degenerate_zero_frames() {
+    let bytes = craft_file(16, 16, 32, &[]);
+    assert_invalid_input(AsepriteFile::read(&bytes[..]));
+}
+
+#[test]
+fn degenerate_zero_size_canvas() {
+    let bytes = craft_file(0, 16, 32, &[vec![]]);
+    assert_invalid_input(AsepriteFile::read(&bytes[..]));
+    let bytes = craft_file(16, 0, 32, &[vec![]]);
+    assert_invalid_input(AsepriteFile::read(&bytes[..]));
+}
+
+#[test]
+fn degenerate_no_layers() {
+    let bytes = craft_file(4, 3, 32, &[vec![], vec![]]);
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    assert_eq!(f.num_layers(), 0);
+    assert_eq!(f.num_frames(), 2);
+    let img = f.frame(1).image();
+    assert_eq!(img.dimensions(), (4, 3));
+    assert!(img.pixels().all(is_transparent));
+}
+
+#[test]
+fn degenerate_no_cels() {
+    let bytes = craft_file(4, 4, 32, &[vec![layer_chunk("A"), layer_chunk("B")]]);
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    assert_eq!(f.num_layers(), 2);
+    assert!(f.frame(0).layer(1).is_empty());
+    assert!(f.frame(0).image().pixels().all(is_transparent));
+    assert!(f.layer(0).frame(0).image().pixels().all(is_transparent));
+}
+
+#[test]
+fn degenerate_cel_without_layer() {
+    let pixels = [0; 16];
+    let bytes = craft_file(2, 2, 32, &[vec![raw_cel_chunk(0, 2, 2, &pixels)]]);
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    assert_eq!(
+        f.warnings(),
+        &[ParseWarning::MissingLayer { frame: 0, layer: 0 }]
+    );
+    assert!(f.frame(0).image().pixels().all(is_transparent));
+    let strict = ReadOptions::new().strict(true);
+    assert_invalid_input(AsepriteFile::read_with_options(&bytes[..], &strict));
+}
+
+#[test]
+fn tile_id_out_of_range() {
+    // A copy of tilemap.aseprite in which a tile of the first cel refers to a
+    // deleted tile, as written by Aseprite after tiles are removed.
+    let f = load_test_file("tilemap_deleted_tile");
+    assert_eq!(
+        f.warnings(),
+        &[ParseWarning::TileIdOutOfRange {
+            frame: 0,
+            layer: 0,
+            tile_id: 7
+        }]
+    );
+    // The missing tile is drawn as tile 0, which is empty.
+    let expected = load_test_file("tilemap").frame(0).image();
+    let image = f.frame(0).image();
+    let mut changed = 0;
+    for (pixel, expected) in image.pixels().zip(expected.pixels()) {
+        if pixel != expected {
+            assert!(is_transparent(pixel));
+            changed += 1;
+        }
+    }
+    assert!(changed > 0);
+
+    let path = std::path::Path::new("./tests/data/tilemap_deleted_tile.aseprite");
+    let strict = ReadOptions::new().strict(true);
+    assert_invalid_input(AsepriteFile::read_file_with_options(path, &strict));
+}
+
+#[test]
+fn degenerate_indexed_without_palette() {
+    // Without any indexed pixels a missing palette is fine.
+    let bytes = craft_file(2, 2, 8, &[vec![layer_chunk("Layer")]]);
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    assert!(f.palette().is_none());
+    assert!(f.frame(0).image().pixels().all(is_transparent));
+
+    // Indexed pixels need a palette to be resolved.
+    let bytes = craft_file(
+        2,
+        2,
+        8,
+        &[vec![layer_chunk("Layer"), raw_cel_chunk(0, 2, 2, &[1; 4])]],
+    );
+    assert_invalid_input(AsepriteFile::read(&bytes[..]));
+}
+
+// Records the names of all spans and the fields of all events emitted while
+// running `f`.
+#[cfg(feature = "tracing")]
+fn capture_trace<F: FnOnce()>(f: F) -> (Vec<&'static str>, Vec<&'static str>) {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    #[derive(Default)]
+    struct Capture {
+        next_id: AtomicU64,
+        spans: Arc<Mutex<Vec<&'static str>>>,
+        event_fields: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.spans.lock().unwrap().push(span.metadata().name());
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = self.event_fields.lock().unwrap();
+            fields.extend(event.fields().map(|field| field.name()));
+        }
+        fn enter(&self, _span: &Id) {}
+        fn exit(&self, _span: &Id) {}
+    }
+
+    let capture = Capture::default();
+    let spans = capture.spans.clone();
+    let event_fields = capture.event_fields.clone();
+    tracing::subscriber::with_default(capture, f);
+    let spans = spans.lock().unwrap().clone();
+    let event_fields = event_fields.lock().unwrap().clone();
+    (spans, event_fields)
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_spans() {
+    let (spans, event_fields) = capture_trace(|| {
+        let f = load_test_file("layers_and_tags");
+        f.frame(1).image();
+        f.frame(0).layer(0).image();
+    });
+    let count = |name: &str| spans.iter().filter(|s| **s == name).count();
+    assert_eq!(count("read_aseprite"), 1);
+    assert_eq!(count("parse_frame"), 4);
+    assert!(count("parse_cel") > 0);
+    assert_eq!(count("parse_palette"), 1);
+    assert_eq!(count("parse_tags"), 1);
+    assert_eq!(count("composite_frame"), 1);
+    assert_eq!(count("composite_layer"), 1);
+    assert!(event_fields.contains(&"compressed_bytes"));
+    assert!(event_fields.contains(&"decompressed_bytes"));
+
+    let (spans, _) = capture_trace(|| {
+        load_test_file("slice");
+    });
+    assert!(spans.contains(&"parse_slice"));
+}
+
+#[test]
+fn builder() {
+    let mut builder = AsepriteFileBuilder::new(4, 2);
+    let bottom = builder.add_layer("Bottom");
+    let top = builder.add_layer("Top");
+    builder.set_layer_opacity(top, 128);
+    builder.set_layer_visible(bottom, false);
+    builder.add_frame(100);
+    let frame = builder.add_frame(50);
+    let red = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+    builder.add_cel(frame, bottom, (0, 0), &red);
+    builder.add_cel(frame, top, (3, 1), &red);
+    builder.add_tag("Walk", 0, 1, AnimationDirection::PingPong);
+    builder.set_palette(&[[0, 0, 0, 255], [255, 255, 255, 255]]);
+    let f = builder.build().unwrap();
+
+    assert_eq!(f.size(), (4, 2));
+    assert_eq!(f.num_frames(), 2);
+    assert_eq!(f.frame(1).duration(), 50);
+    assert_eq!(f.layer(1).name(), "Top");
+    assert_eq!(f.layer(1).opacity(), 128);
+    assert!(!f.layer(0).is_visible());
+    assert!(f.frame(0).layer(0).is_empty());
+    assert!(!f.frame(1).layer(0).is_empty());
+    assert_eq!(f.tag(0).name(), "Walk");
+    assert_eq!(f.tag(0).animation_direction(), AnimationDirection::PingPong);
+    assert_eq!(f.palette().unwrap().num_colors(), 2);
+
+    let img = f.frame(1).image();
+    assert!(is_transparent(img.get_pixel(0, 0)));
+    assert_eq!(img.get_pixel(3, 1).0, [255, 0, 0, 255]);
+}
+
+#[test]
+fn builder_errors() {
+    let image = image::RgbaImage::new(1, 1);
+
+    assert_invalid_input(AsepriteFileBuilder::new(4, 4).build());
+    let mut builder = AsepriteFileBuilder::new(0, 4);
+    builder.add_frame(100);
+    assert_invalid_input(builder.build());
+
+    let mut builder = AsepriteFileBuilder::new(4, 4);
+    builder.add_frame(100);
+    builder.add_cel(0, 0, (0, 0), &image);
+    assert_invalid_input(builder.build());
+
+    let mut builder = AsepriteFileBuilder::new(4, 4);
+    builder.add_layer("Layer");
+    builder.add_frame(100);
+    builder.add_cel(1, 0, (0, 0), &image);
+    assert_invalid_input(builder.build());
+
+    let mut builder = AsepriteFileBuilder::new(4, 4);
+    builder.add_layer("Layer");
+    builder.add_frame(100);
+    builder.add_cel(0, 0, (0, 0), &image);
+    builder.add_cel(0, 0, (1, 1), &image);
+    assert_invalid_input(builder.build());
+
+    let mut builder = AsepriteFileBuilder::new(4, 4);
+    builder.add_frame(100);
+    builder.add_tag("Tag", 0, 1, AnimationDirection::Forward);
+    assert_invalid_input(builder.build());
+}
+
+#[test]
+fn builder_pixel_formats() {
+    let mut builder = AsepriteFileBuilder::new(2, 1);
+    builder.set_pixel_format(PixelFormat::Grayscale);
+    let layer = builder.add_layer("Layer");
+    let frame = builder.add_frame(100);
+    builder.add_cel_pixels(frame, layer, (0, 0), (2, 1), &[200, 255, 0, 0]);
+    let f = builder.build().unwrap();
+    assert_eq!(f.pixel_format(), PixelFormat::Grayscale);
+    let img = f.frame(0).image();
+    assert_eq!(img.get_pixel(0, 0).0, [200, 200, 200, 255]);
+    assert!(is_transparent(img.get_pixel(1, 0)));
+    assert_semantic_eq(&f, &write_and_read(&f));
+
+    let indexed = PixelFormat::Indexed {
+        transparent_color_index: 2,
+    };
+    let mut builder = AsepriteFileBuilder::new(2, 2);
+    builder.set_pixel_format(indexed);
+    builder.set_palette(&[COLOR_RED, COLOR_GREEN, [0, 0, 0, 0]]);
+    let layer = builder.add_layer("Layer");
+    let frame = builder.add_frame(100);
+    builder.add_cel_pixels(frame, layer, (0, 1), (2, 1), &[1, 2]);
+    let f = builder.build().unwrap();
+    assert_eq!(f.pixel_format(), indexed);
+    let img = f.frame(0).image();
+    assert_eq!(img.get_pixel(0, 1).0, COLOR_GREEN);
+    assert!(is_transparent(img.get_pixel(1, 1)));
+    assert_semantic_eq(&f, &write_and_read(&f));
+
+    // RGBA images, wrong sizes, and missing palette entries are rejected.
+    let errors: &[&dyn Fn(&mut AsepriteFileBuilder)] = &[
+        &|b| b.add_cel(0, 0, (0, 0), &image::RgbaImage::new(1, 1)),
+        &|b| b.add_cel_pixels(0, 0, (0, 0), (2, 1), &[0]),
+        &|b| b.add_cel_pixels(0, 0, (0, 0), (0, 1), &[]),
+        &|b| b.add_cel_pixels(0, 0, (0, 0), (1, 1), &[3]),
+    ];
+    for add_cel in errors {
+        let mut builder = AsepriteFileBuilder::new(2, 2);
+        builder.set_pixel_format(indexed);
+        builder.set_palette(&[COLOR_RED, COLOR_GREEN, [0, 0, 0, 0]]);
+        builder.add_layer("Layer");
+        builder.add_frame(100);
+        add_cel(&mut builder);
+        assert_invalid_input(builder.build());
+    }
+}
+
+#[test]
+fn color_histogram_solid() {
+    let mut builder = AsepriteFileBuilder::new(4, 4);
+    let layer = builder.add_layer("Layer");
+    let frame = builder.add_frame(100);
+    let image = image::RgbaImage::from_pixel(4, 4, image::Rgba([200, 100, 50, 255]));
+    builder.add_cel(frame, layer, (0, 0), &image);
+    let f = builder.build().unwrap();
+
+    let histogram = f.frame(0).color_histogram(8);
+    assert_eq!(histogram.total(), 16);
+    assert_eq!(
+        histogram.buckets(),
+        &[HistogramBucket {
+            color: [200, 100, 50, 255],
+            count: 16
+        }]
+    );
+    assert_eq!(
+        f.frame(0).dominant_colors(3),
+        vec![([200, 100, 50, 255], 1.0)]
+    );
+}
+
+#[test]
+fn color_histogram_checker() {
+    let mut builder = AsepriteFileBuilder::new(4, 4);
+    let layer = builder.add_layer("Layer");
+    let frame = builder.add_frame(100);
+    let image = image::RgbaImage::from_fn(4, 2, |x, y| {
+        if (x + y) % 2 == 0 {
+            image::Rgba([255, 255, 255, 255])
+        } else {
+            image::Rgba([0, 0, 0, 255])
+        }
+    });
+    // The bottom half of the canvas stays transparent.
+    builder.add_cel(frame, layer, (0, 0), &image);
+    let f = builder.build().unwrap();
+
+    let histogram = f.frame(0).color_histogram(2);
+    assert_eq!(histogram.total(), 8);
+    let colors: Vec<_> = histogram.buckets().iter().map(|b| b.color).collect();
+    assert_eq!(colors, vec![[0, 0, 0, 255], [255, 255, 255, 255]]);
+    assert_eq!(f.frame(0).dominant_colors(1), vec![([0, 0, 0, 255], 0.5)]);
+    assert_eq!(f.frame(0).color_histogram(2), histogram);
+}
+
+#[test]
+fn color_histogram_quantization() {
+    let mut builder = AsepriteFileBuilder::new(2, 1);
+    let layer = builder.add_layer("Layer");
+    let frame = builder.add_frame(100);
+    let image = image::RgbaImage::from_fn(2, 1, |x, _| image::Rgba([100 + x as u8 * 2, 0, 0, 255]));
+    builder.add_cel(frame, layer, (0, 0), &image);
+    let f = builder.build().unwrap();
+
+    assert_eq!(f.frame(0).color_histogram(8).buckets().len(), 2);
+    let coarse = f.frame(0).color_histogram(4);
+    assert_eq!(
+        coarse.buckets(),
+        &[HistogramBucket {
+            color: [101, 0, 0, 255],
+            count: 2
+        }]
+    );
+}
+
+// Histogram computed from the composited image.
+fn composited_histogram(frame: &Frame, bits_per_channel: u8) -> Histogram {
+    let mut builder = crate::histogram::HistogramBuilder::new(bits_per_channel);
+    for pixel in frame.image().pixels() {
+        builder.add(*pixel, 1);
+    }
+    builder.build()
+}
+
+#[test]
+fn color_histogram_indexed() {
+    let palette = [[0, 0, 0, 255], [255, 0, 0, 255], [0, 0, 255, 128]];
+    let bytes = craft_file(
+        3,
+        2,
+        8,
+        &[vec![
+            palette_chunk(&palette),
+            layer_chunk("Layer"),
+            raw_cel_chunk(0, 3, 2, &[0, 1, 1, 2, 1, 0]),
+        ]],
+    );
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    let histogram = f.frame(0).color_histogram(8);
+    assert_eq!(histogram, composited_histogram(&f.frame(0), 8));
+    assert_eq!(
+        histogram.buckets(),
+        &[
+            HistogramBucket {
+                color: [255, 0, 0, 255],
+                count: 3
+            },
+            HistogramBucket {
+                color: [0, 0, 255, 128],
+                count: 1
+            },
+        ]
+    );
+
+    let f = load_test_file("indexed");
+    for frame in 0..f.num_frames() {
+        let frame = f.frame(frame);
+        assert_eq!(frame.color_histogram(5), composited_histogram(&frame, 5));
+    }
+}
+
+#[test]
+#[should_panic]
+fn color_histogram_invalid_bits() {
+    let f = load_test_file("basic-16x16");
+    f.frame(0).color_histogram(0);
+}
+
+#[test]
+fn render_ansi() {
+    use crate::ansi::{AnsiOptions, Transparency};
+
+    // Red top row, green middle row, half transparent blue bottom row.
+    let mut image = image::RgbaImage::from_pixel(2, 3, image::Rgba(COLOR_RED));
+    for x in 0..2 {
+        image.put_pixel(x, 1, image::Rgba(COLOR_GREEN));
+        image.put_pixel(x, 2, image::Rgba([0, 0, 255, 128]));
+    }
+    let mut builder = AsepriteFileBuilder::new(2, 3);
+    let layer = builder.add_layer("Layer");
+    let frame = builder.add_frame(100);
+    builder.add_cel(frame, layer, (0, 0), &image);
+    let f = builder.build().unwrap();
+
+    let options = AnsiOptions::new().transparency(Transparency::Solid([255, 255, 255]));
+    let art = f.frame(0).render_ansi(&options);
+    let expected = "\x1b[38;2;255;0;0m\x1b[48;2;0;255;0m▀▀\x1b[0m\n\
+                    \x1b[38;2;127;127;255m\x1b[49m▀▀\x1b[0m\n";
+    assert_eq!(art, expected);
+
+    // The bottom row lies on dark squares of the checkerboard.
+    let art = f.frame(0).render_ansi(&AnsiOptions::new());
+    assert!(art.ends_with("\x1b[38;2;76;76;204m\x1b[49m▀▀\x1b[0m\n"));
+
+    // Scaling down to one column.
+    let art = f.frame(0).render_ansi(&options.max_width(1));
+    assert_eq!(art, "\x1b[38;2;255;0;0m\x1b[48;2;0;255;0m▀\x1b[0m\n");
+}
+
+#[cfg(feature = "simd")]
+#[test]
+fn simd_blending_matches_scalar() {
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(283);
+    // Fully transparent and opaque pixels take separate paths.
+    let channel = |rng: &mut rand::rngs::StdRng| match rng.gen_range(0, 4) {
+        0 => 0,
+        1 => 255,
+        _ => rng.gen(),
+    };
+    let modes: [(BlendMode, fn(_, _, _) -> _); 3] = [
+        (BlendMode::Normal, blend::normal),
+        (BlendMode::Multiply, blend::multiply),
+        (BlendMode::Screen, blend::screen),
+    ];
+    for (mode, scalar) in modes.iter() {
+        for _ in 0..500 {
+            let opacity = channel(&mut rng);
+            let src: Vec<image::Rgba<u8>> = (0..64)
+                .map(|_| image::Rgba([0; 4].map(|_| channel(&mut rng))))
+                .collect();
+            let backdrop: Vec<u8> = (0..src.len() * 4).map(|_| channel(&mut rng)).collect();
+            let mut actual = backdrop.clone();
+            let done = simd::blend_row(*mode, &mut actual, &src, opacity);
+            for (i, src) in src[..done].iter().enumerate() {
+                let back = &backdrop[i * 4..i * 4 + 4];
+                let back = image::Rgba([back[0], back[1], back[2], back[3]]);
+                assert_eq!(
+                    &actual[i * 4..i * 4 + 4],
+                    &scalar(back, *src, opacity).0[..],
+                    "{:?} of {:?} onto {:?} with opacity {}",
+                    mode,
+                    src,
+                    back,
+                    opacity
+                );
+            }
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn arbitrary_sprites() {
+    use ::arbitrary::{Arbitrary, Unstructured};
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(205);
+    for _ in 0..200 {
+        let len = rng.gen_range(0, 4096);
+        let bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+        let mut u = Unstructured::new(&bytes);
+        let sprite = arbitrary::ArbitrarySprite::arbitrary(&mut u).unwrap();
+        let f = sprite.build();
+
+        assert_eq!(f.size(), (sprite.width as usize, sprite.height as usize));
+        assert_eq!(f.num_layers() as usize, sprite.layers.len());
+        assert_eq!(f.num_tags() as usize, sprite.tags.len());
+        assert_eq!(f.slices().len(), sprite.slices.len());
+        assert!(diff::diff(&f, &f).is_empty());
+        let copy = AsepriteFile::read(&sprite.to_bytes()[..]).unwrap();
+        assert_semantic_eq(&f, &copy);
+
+        for (frame_id, frame) in sprite.frames.iter().enumerate() {
+            let frame_id = frame_id as u32;
+            assert_eq!(f.frame(frame_id).duration(), frame.duration as u32);
+            assert_eq!(
+                f.frame(frame_id).image().dimensions(),
+                (sprite.width as u32, sprite.height as u32)
+            );
+            for (layer_id, cel) in frame.cels.iter().enumerate() {
+                let layer = &sprite.layers[layer_id];
+                let actual_layer = f.layer(layer_id as u32);
+                let actual = actual_layer.frame(frame_id);
+                assert_eq!(actual.is_empty(), cel.is_none());
+                let cel = match cel {
+                    Some(cel) if layer.blend_mode == BlendMode::Normal && layer.opacity == 255 => {
+                        cel
+                    }
+                    _ => continue,
+                };
+                // Fully opaque pixels are copied unchanged onto the canvas.
+                let canvas = actual.image();
+                let source = cel.image();
+                for (x, y, pixel) in source.enumerate_pixels() {
+                    let cx = cel.x as i32 + x as i32;
+                    let cy = cel.y as i32 + y as i32;
+                    let inside =
+                        cx >= 0 && cy >= 0 && cx < sprite.width as i32 && cy < sprite.height as i32;
+                    if inside && pixel.0[3] == 255 {
+                        assert_eq!(canvas.get_pixel(cx as u32, cy as u32), pixel);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn binary_reader() {
+    let data = Bytes::default()
+        .byte(0xFE)
+        .word(0xBEEF)
+        .short(-2)
+        .dword(0xDEAD_BEEF)
+        .bytes(&(-70_000_i32).to_le_bytes())
+        .bytes(&0x0001_8000_i32.to_le_bytes())
+        .bytes(&(-0x0000_4000_i32).to_le_bytes())
+        .0;
+    let mut reader = binary::Reader::new(&data[..]);
+    assert_eq!(reader.byte().unwrap(), 0xFE);
+    assert_eq!(reader.word().unwrap(), 0xBEEF);
+    assert_eq!(reader.short().unwrap(), -2);
+    assert_eq!(reader.dword().unwrap(), 0xDEAD_BEEF);
+    assert_eq!(reader.long().unwrap(), -70_000);
+    assert_eq!(reader.fixed().unwrap(), 1.5);
+    assert_eq!(reader.fixed().unwrap(), -0.25);
+    assert!(reader.into_inner().is_empty());
+}
+
+#[test]
+fn binary_reader_strings() {
+    // Strings are a WORD byte count followed by UTF-8 without terminator.
+    let data = [
+        0x07, 0x00, b'G', b'r', 0xC3, 0xBC, 0xC3, 0x9F, b'e', 0x00, 0x00,
+    ];
+    let mut reader = binary::Reader::new(&data[..]);
+    assert_eq!(reader.string().unwrap(), "Grüße");
+    assert_eq!(reader.string().unwrap(), "");
+
+    let invalid = [0x01, 0x00, 0xFF];
+    assert_invalid_input(binary::Reader::new(&invalid[..]).string());
+}
+
+#[test]
+fn binary_reader_eof() {
+    let is_eof = |err: AsepriteParseError| match err {
+        AsepriteParseError::IoError(err) => err.kind() == std::io::ErrorKind::UnexpectedEof,
+        _ => false,
+    };
+    let data = [0x01, 0x02, 0x03];
+    let mut reader = binary::Reader::new(std::io::Cursor::new(&data[..]));
+    assert!(is_eof(reader.dword().unwrap_err()));
+
+    // A string that is shorter than its length prefix.
+    let data = [0x05, 0x00, b'a', b'b'];
+    assert!(is_eof(binary::Reader::new(&data[..]).string().unwrap_err()));
+}
+
+#[test]
+fn into_frame_images() {
+    for name in &["linked_cels", "layers_and_tags", "tilemap", "indexed"] {
+        let f = load_test_file(name);
+        let expected: Vec<_> = (0..f.num_frames()).map(|i| f.frame(i).image()).collect();
+        let actual = f.into_frame_images(ImageOptions::default());
+        assert_eq!(actual, expected, "{}", name);
+    }
+
+    let f = load_test_file("linked_cels");
+    let iter = f.into_frame_images_iter(ImageOptions::default());
+    assert_eq!(iter.len(), 3);
+}
+
+#[test]
+fn release_schedule_keeps_link_sources() {
+    let f = load_test_file("linked_cels");
+    let schedule = f.framedata.release_schedule();
+    let freed_after = |frame: u16, layer: u16| {
+        schedule
+            .iter()
+            .position(|ids| ids.iter().any(|id| id.frame == frame && id.layer == layer))
+    };
+    let mut num_links = 0;
+    for frame in 0..f.num_frames() as u16 {
+        for layer in 0..f.num_layers() as u16 {
+            let cel = f.framedata.cel(cel::CelId { frame, layer });
+            match cel.map(|c| &c.content) {
+                None => assert_eq!(freed_after(frame, layer), None),
+                Some(cel::CelContent::Linked(source)) => {
+                    num_links += 1;
+                    assert_eq!(freed_after(frame, layer), Some(frame as usize));
+                    assert!(freed_after(*source, layer).unwrap() >= frame as usize);
+                }
+                Some(_) => assert!(freed_after(frame, layer).unwrap() >= frame as usize),
+            }
+        }
+    }
+    assert!(num_links > 0);
+}
+
+#[test]
+fn image_options_hidden_layers() {
+    let mut builder = AsepriteFileBuilder::new(1, 1);
+    let layer = builder.add_layer("Hidden");
+    builder.set_layer_visible(layer, false);
+    let frame = builder.add_frame(100);
+    let red = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 0, 0, 255]));
+    builder.add_cel(frame, layer, (0, 0), &red);
+    let f = builder.build().unwrap();
+
+    assert!(is_transparent(f.frame(0).image().get_pixel(0, 0)));
+    let options = ImageOptions::new().include_hidden_layers(true);
+    assert_eq!(f.frame(0).image_with_options(&options), red);
+    assert_eq!(f.into_frame_images(options), vec![red]);
+}
+
+/*
+#[test]
+fn gen_random_pixels() {
+    use rand::Rng;
+    use image::{Rgba};
+    use std::path::Path;
+    let mut rng = rand::thread_rng();
+
+    let (width, height) = (256, 256);
+    let mut img = image::RgbaImage::new(width, height);
+    for y in 0..width {
+        for x in 0..height {
+            let r: u8 = rng.gen();
+            let g: u8 = rng.gen();
+            let b: u8 = rng.gen();
+            let a: u8 = rng.gen();
+            img.put_pixel(x, y, Rgba([r, g, b, a]));
+        }
+    }
+    img.save(&Path::new("tests/data/random-256x256.png")).unwrap();
+}
+// */
+
+#[cfg(feature = "godot")]
+#[test]
+fn godot_sprite_frames() {
+    use crate::atlas::{self, AtlasOptions};
+    use crate::exporters::godot;
+
+    let mut builder = AsepriteFileBuilder::new(8, 8);
+    let layer = builder.add_layer("Layer");
+    for (index, duration) in [100, 100, 200, 50].iter().enumerate() {
+        let frame = builder.add_frame(*duration);
+        let color = image::Rgba([index as u8 * 60, 0, 0, 255]);
+        // The last frame only covers the center of the canvas.
+        let (position, size) = if index == 3 { ((2, 2), 4) } else { ((0, 0), 8) };
+        let image = image::RgbaImage::from_pixel(size, size, color);
+        builder.add_cel(frame, layer, position, &image);
+    }
+    builder.add_tag("Idle", 0, 1, AnimationDirection::Forward);
+    builder.add_tag("Jump", 1, 3, AnimationDirection::PingPong);
+    let mut f = builder.build().unwrap();
+    f.tags.push(Tag::new(
+        "Hit \"hard\"".to_owned(),
+        2,
+        3,
+        AnimationDirection::Reverse,
+        2,
+    ));
+
+    let options = AtlasOptions::new().trim(true);
+    let atlas = atlas::pack(&[("sprite", &f)], &options).unwrap();
+    let mut tres = Vec::new();
+    godot::write_sprite_frames(&atlas, "sprite", &f, "res://sprite.png", &mut tres).unwrap();
+    let tres = String::from_utf8(tres).unwrap();
+    let expected = std::fs::read_to_string("tests/data/sprite_frames.tres").unwrap();
+    assert_eq!(tres, expected);
+    // Each frame is the region of the atlas that it was packed into.
+    for frame in atlas.frames() {
+        let (x, y, width, height) = frame.rect();
+        let texture = format!(
+            "id=\"AtlasTexture_{}\"]\natlas = ExtResource(\"1\")\nregion = Rect2({}, {}, {}, {})\n",
+            frame.frame(),
+            x,
+            y,
+            width,
+            height
+        );
+        assert!(tres.contains(&texture), "{}", texture);
+    }
+    assert!(atlas.frames()[3].is_trimmed());
+    assert!(tres.contains("margin = Rect2(2, 2, 4, 4)"));
+
+    // Without tags there is a single looping animation.
+    f.tags.clear();
+    let mut tres = Vec::new();
+    godot::write_sprite_frames(&atlas, "sprite", &f, "res://sprite.png", &mut tres).unwrap();
+    let tres = String::from_utf8(tres).unwrap();
+    assert!(tres.contains("\"name\": &\"default\""));
+    assert!(tres.contains("id=\"AtlasTexture_3\""));
+
+    // The frames must be part of the atlas.
+    let err = godot::write_sprite_frames(&atlas, "other", &f, "res://sprite.png", Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    // Exporting writes the atlas image next to the resource.
+    let dir = std::env::temp_dir().join("asefile_godot_export");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("sprite.tres");
+    godot::export_sprite_frames(&f, &path, &options).unwrap();
+    let tres = std::fs::read_to_string(&path).unwrap();
+    assert!(tres.contains("path=\"sprite.png\""));
+    let image = image::open(dir.join("sprite.png")).unwrap().to_rgba8();
+    assert!(&image == atlas.image());
+    let err = godot::export_sprite_frames(&f, &dir.join("sprite.png"), &options).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "ico")]
+#[test]
+fn ico_export() {
+    use crate::exporters::ico;
+
+    let f = load_test_file("basic-16x16");
+    let frame = f.frame(0).image();
+    let mut data = Vec::new();
+    ico::write(&f, &[(0, 16), (0, 32), (0, 256)], &mut data).unwrap();
+    assert_eq!(&data[..6], &[0, 0, 1, 0, 3, 0]);
+    // Width and height of each entry; 256 is stored as 0.
+    let sizes: Vec<_> = (0..3)
+        .map(|i| (data[6 + i * 16], data[7 + i * 16]))
+        .collect();
+    assert_eq!(sizes, vec![(16, 16), (32, 32), (0, 0)]);
+
+    // The reader picks the largest image.
+    let icon = image::load_from_memory_with_format(&data, image::ImageFormat::Ico)
+        .unwrap()
+        .to_rgba8();
+    assert_eq!(icon.dimensions(), (256, 256));
+    for (x, y, pixel) in icon.enumerate_pixels() {
+        assert_eq!(pixel, frame.get_pixel(x / 16, y / 16));
+    }
+    let mut data = Vec::new();
+    ico::write(&f, &[(0, 16)], &mut data).unwrap();
+    let icon = image::load_from_memory_with_format(&data, image::ImageFormat::Ico).unwrap();
+    assert!(icon.to_rgba8() == frame);
+
+    // Sprites that are not square are centered.
+    let mut builder = AsepriteFileBuilder::new(4, 2);
+    let layer = builder.add_layer("Layer");
+    let frame = builder.add_frame(100);
+    let image = image::RgbaImage::from_pixel(4, 2, image::Rgba(COLOR_RED));
+    builder.add_cel(frame, layer, (0, 0), &image);
+    let wide = builder.build().unwrap();
+    let mut data = Vec::new();
+    ico::write(&wide, &[(0, 8)], &mut data).unwrap();
+    let icon = image::load_from_memory_with_format(&data, image::ImageFormat::Ico)
+        .unwrap()
+        .to_rgba8();
+    for (_, y, pixel) in icon.enumerate_pixels() {
+        let expected = if (2..6).contains(&y) {
+            COLOR_RED
+        } else {
+            [0; 4]
+        };
+        assert_eq!(pixel.0, expected);
+    }
+
+    for frames in &[&[][..], &[(0, 0)], &[(0, 257)], &[(1, 16)]] {
+        let err = ico::write(&f, frames, Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
+
+#[cfg(feature = "cur")]
+#[test]
+fn cur_export() {
+    use crate::exporters::cur;
+
+    // The pivot of "Slice 1" is at (7, 7) on the canvas.
+    let f = load_test_file("slice");
+    let hotspot = |slice: Option<&str>| {
+        let mut data = Vec::new();
+        cur::write(&f, 0, 32, slice, &mut data).unwrap();
+        assert_eq!(&data[..6], &[0, 0, 2, 0, 1, 0]);
+        let icon = image::load_from_memory_with_format(&data, image::ImageFormat::Ico)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(icon.dimensions(), (32, 32));
+        assert_eq!(icon.get_pixel(14, 14), f.frame(0).image().get_pixel(7, 7));
+        let x = u16::from_le_bytes([data[10], data[11]]);
+        let y = u16::from_le_bytes([data[12], data[13]]);
+        (x, y)
+    };
+    assert_eq!(hotspot(Some("Slice 1")), (14, 14));
+    assert_eq!(hotspot(Some("Missing")), (0, 0));
+    assert_eq!(hotspot(None), (0, 0));
+
+    let mut hidden = load_test_file("slice");
+    for key in &mut hidden.slices[0].keys {
+        key.size.width = 0;
+    }
+    let mut data = Vec::new();
+    cur::write(&hidden, 0, 32, Some("Slice 1"), &mut data).unwrap();
+    assert_eq!(&data[10..14], &[0, 0, 0, 0]);
+
+    let err = cur::write(&f, 0, 300, None, Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "apng")]
+#[test]
+fn apng_animation() {
+    use crate::exporters::apng::{self, decode, ApngOptions};
+    use rand::Rng;
+
+    for name in &["layers_and_tags", "transparency", "linked_cels", "big"] {
+        let f = load_test_file(name);
+        let mut data = Vec::new();
+        apng::write(&f, &mut data, &ApngOptions::new()).unwrap();
+        let animation = decode::animation(&data);
+        assert_eq!(animation.num_plays, 0);
+        assert_eq!(animation.frames.len(), f.num_frames() as usize);
+        for (index, (image, duration)) in animation.frames.iter().enumerate() {
+            let frame = f.frame(index as u32);
+            assert_eq!(*duration, frame.duration());
+            assert!(*image == frame.image(), "{}: frame {} differs", name, index);
+        }
+        // Viewers without APNG support show the first frame.
+        let default_image = image::load_from_memory(&data).unwrap().to_rgba8();
+        assert!(default_image == f.frame(0).image());
+    }
+
+    // Noise exercises all row filters.
+    let mut rng = rand::thread_rng();
+    let mut builder = AsepriteFileBuilder::new(40, 30);
+    let layer = builder.add_layer("Layer");
+    let mut images = Vec::new();
+    for _ in 0..4 {
+        let mut image = image::RgbaImage::new(40, 30);
+        for pixel in image.pixels_mut() {
+            let alpha = if rng.gen::<bool>() { 255 } else { rng.gen() };
+            *pixel = image::Rgba([rng.gen(), rng.gen_range(0, 8), rng.gen(), alpha]);
+        }
+        let frame = builder.add_frame(70);
+        builder.add_cel(frame, layer, (0, 0), &image);
+        images.push(image);
+    }
+    builder.add_tag("Bounce", 1, 3, AnimationDirection::PingPong);
+    let mut f = builder.build().unwrap();
+    f.tags.push(Tag::new(
+        "Twice".to_owned(),
+        0,
+        1,
+        AnimationDirection::Reverse,
+        2,
+    ));
+
+    let mut data = Vec::new();
+    apng::write(&f, &mut data, &ApngOptions::new().tag(0)).unwrap();
+    let animation = decode::animation(&data);
+    assert_eq!((animation.width, animation.height), (40, 30));
+    assert_eq!(animation.num_plays, 0);
+    let frames: Vec<_> = animation.frames.iter().map(|(image, _)| image).collect();
+    assert_eq!(frames, vec![&images[1], &images[2], &images[3], &images[2]]);
+
+    let mut data = Vec::new();
+    apng::write(&f, &mut data, &ApngOptions::new().tag(1)).unwrap();
+    let animation = decode::animation(&data);
+    assert_eq!(animation.num_plays, 1);
+    let frames: Vec<_> = animation.frames.iter().map(|(image, _)| image).collect();
+    assert_eq!(frames, vec![&images[1], &images[0], &images[1], &images[0]]);
+
+    let err = apng::write(&f, Vec::new(), &ApngOptions::new().tag(2)).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn edit_cel_image() {
+    let old = load_test_file("linked_cels");
+    let mut f = load_test_file("linked_cels");
+    // Find a cel that other cels link to.
+    let (source, layer) = (0..f.num_frames() as u16)
+        .flat_map(|frame| (0..f.num_layers() as u16).map(move |layer| (frame, layer)))
+        .find_map(|(frame, layer)| {
+            match f
+                .framedata
+                .cel(cel::CelId { frame, layer })
+                .map(|c| &c.content)
+            {
+                Some(cel::CelContent::Linked(source)) => Some((*source as u32, layer as u32)),
+                _ => None,
+            }
+        })
+        .expect("No linked cels");
+    let images: Vec<_> = (0..f.num_frames()).map(|i| f.frame(i).image()).collect();
+
+    let green = image::RgbaImage::from_pixel(2, 3, image::Rgba(COLOR_GREEN));
+    f.set_cel_image(source, layer, &green, (-1, 1)).unwrap();
+
+    let changes = diff::diff(&old, &f);
+    assert_eq!(changes.layers.len(), 1);
+    assert_eq!(changes.layers[0].kind, diff::ChangeKind::Modified);
+    assert_eq!(changes.layers[0].changed_frames, vec![source]);
+    assert_eq!(
+        diff::FileDiff {
+            layers: Vec::new(),
+            ..changes
+        },
+        diff::FileDiff::default()
+    );
+    let cel_image = f.frame(source).layer(layer).image();
+    assert_eq!(cel_image.get_pixel(0, 1).0, COLOR_GREEN);
+    assert_eq!(cel_image.get_pixel(0, 3).0, COLOR_GREEN);
+    assert!(is_transparent(cel_image.get_pixel(1, 1)));
+    for frame in 0..f.num_frames() {
+        if frame != source {
+            assert_eq!(f.frame(frame).image(), images[frame as usize]);
+        }
+    }
+    // All cels can still be released in order.
+    let frames = f.into_frame_images(ImageOptions::default());
+    assert_eq!(frames.len(), images.len());
+}
+
+#[test]
+fn edit_cel_image_errors() {
+    let image = image::RgbaImage::new(2, 2);
+    let mut f = load_test_file("linked_cels");
+    assert_invalid_input(f.set_cel_image(f.num_frames(), 0, &image, (0, 0)));
+    assert_invalid_input(f.set_cel_image(0, f.num_layers(), &image, (0, 0)));
+    assert_invalid_input(f.set_cel_image(0, 0, &image, (0, 40000)));
+    assert_invalid_input(f.set_cel_image(0, 0, &image::RgbaImage::new(0, 2), (0, 0)));
+
+    let mut f = load_test_file("indexed");
+    match f.set_cel_image(0, 0, &image, (0, 0)) {
+        Err(AsepriteParseError::UnsupportedFeature(_)) => {}
+        other => panic!("Expected UnsupportedFeature error, got: {:?}", other),
+    }
+}
+
+// The number of pixels in the buffer of `frame` that image cels refer to.
+fn referenced_pixels(f: &AsepriteFile, frame: u16) -> usize {
+    f.framedata
+        .frame_cels(frame)
+        .map(|(_, cel)| match &cel.content {
+            cel::CelContent::Raw(content) => content.pixels.range.len(),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[test]
+fn edit_cel_image_frees_old_pixels() {
+    let mut f = load_test_file("layers_and_tags");
+    let images: Vec<_> = (0..f.num_layers())
+        .map(|layer| f.frame(0).layer(layer).image())
+        .collect();
+    let layer = f
+        .layers()
+        .find(|layer| {
+            layer.layer_type() == layer::LayerType::Image
+                && !f.frame(0).layer(layer.id()).is_empty()
+        })
+        .unwrap()
+        .id();
+    let green = image::RgbaImage::from_pixel(8, 8, image::Rgba(COLOR_GREEN));
+    for _ in 0..3 {
+        f.set_cel_image(0, layer, &green, (0, 0)).unwrap();
+    }
+    assert_eq!(f.framedata.frame_pixels(0).len(), referenced_pixels(&f, 0));
+    for (other, image) in images.iter().enumerate() {
+        let cel_image = f.frame(0).layer(other as u32).image();
+        if other as u32 == layer {
+            assert_eq!(cel_image.get_pixel(7, 7).0, COLOR_GREEN);
+        } else {
+            assert_eq!(&cel_image, image);
+        }
+    }
+}
+
+#[test]
+fn edit_names_and_durations() {
+    let old = load_test_file("layers_and_tags");
+    let mut f = load_test_file("layers_and_tags");
+    let old_layer = f.layer(0).path();
+    let old_tag = f.tag(0).name().to_owned();
+    let old_duration = f.frame(1).duration();
+
+    f.rename_layer(0, "Renamed layer").unwrap();
+    f.rename_tag(0, "Renamed tag").unwrap();
+    f.set_frame_duration(1, 1234).unwrap();
+    assert_eq!(f.layer(0).name(), "Renamed layer");
+    assert_eq!(f.tag(0).name(), "Renamed tag");
+
+    let changes = diff::diff(&old, &f);
+    assert_eq!(
+        changes.frame_durations,
+        vec![diff::FrameDurationChange {
+            frame: 1,
+            duration: diff::Change {
+                old: old_duration,
+                new: 1234
+            }
+        }]
+    );
+    let mut layers: Vec<_> = changes
+        .layers
+        .iter()
+        .map(|l| (l.path.as_str(), l.kind))
+        .collect();
+    layers.sort_by_key(|(path, _)| *path);
+    assert_eq!(
+        layers,
+        vec![
+            (old_layer.as_str(), diff::ChangeKind::Removed),
+            ("Renamed layer", diff::ChangeKind::Added)
+        ]
+    );
+    let tags: Vec<_> = changes
+        .tags
+        .iter()
+        .map(|t| (t.name.as_str(), t.kind))
+        .collect();
+    assert!(tags.contains(&(old_tag.as_str(), diff::ChangeKind::Removed)));
+    assert!(tags.contains(&("Renamed tag", diff::ChangeKind::Added)));
+    assert_eq!(tags.len(), 2);
+    assert!(changes.size.is_none() && changes.palette.is_none() && changes.slices.is_empty());
+
+    assert_invalid_input(f.rename_layer(f.num_layers(), "x"));
+    assert_invalid_input(f.rename_tag(f.num_tags(), "x"));
+    assert_invalid_input(f.set_frame_duration(f.num_frames(), 100));
+}
+
+#[test]
+fn import_images() {
+    let red = image::RgbaImage::from_pixel(4, 2, image::Rgba(COLOR_RED));
+    let green = image::RgbaImage::from_pixel(4, 2, image::Rgba(COLOR_GREEN));
+    let f = import::from_images(vec![(red.clone(), 100), (green.clone(), 250)]).unwrap();
+    assert_eq!(f.size(), (4, 2));
+    assert_eq!(f.num_layers(), 1);
+    assert_eq!(f.num_tags(), 0);
+    assert_eq!(f.frame(0).image(), red);
+    assert_eq!(f.frame(1).image(), green);
+    assert_eq!(f.frame(1).duration(), 250);
+
+    let options = import::ImportOptions::new().duration(40).tag("All");
+    let f =
+        import::from_images_with_options(vec![(red.clone(), 100), (green.clone(), 250)], &options)
+            .unwrap();
+    assert_eq!(f.frame(0).duration(), 40);
+    assert_eq!(f.frame(1).duration(), 40);
+    assert_eq!(
+        (f.tag(0).name(), f.tag(0).from_frame(), f.tag(0).to_frame()),
+        ("All", 0, 1)
+    );
+
+    let small = image::RgbaImage::from_pixel(2, 2, image::Rgba(COLOR_GREEN));
+    assert_invalid_input(import::from_images(vec![
+        (red.clone(), 100),
+        (small.clone(), 100),
+    ]));
+    assert_invalid_input(import::from_images(vec![(red.clone(), 70000)]));
+    assert_invalid_input(import::from_images(Vec::new()));
+
+    // With a canvas size, images are centered.
+    let options = import::ImportOptions::new().canvas_size(4, 4);
+    let f = import::from_images_with_options(vec![(red, 100), (small, 100)], &options).unwrap();
+    assert_eq!(f.size(), (4, 4));
+    let img = f.frame(0).image();
+    assert!(is_transparent(img.get_pixel(0, 0)));
+    assert_eq!(img.get_pixel(0, 1).0, COLOR_RED);
+    assert_eq!(img.get_pixel(3, 2).0, COLOR_RED);
+    let img = f.frame(1).image();
+    assert!(is_transparent(img.get_pixel(0, 1)));
+    assert_eq!(img.get_pixel(1, 1).0, COLOR_GREEN);
+    assert_eq!(img.get_pixel(2, 2).0, COLOR_GREEN);
+    assert!(is_transparent(img.get_pixel(3, 2)));
+}
+
+#[test]
+fn import_directory() {
+    let mut dir = std::env::temp_dir();
+    dir.push(format!("asefile_import_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let colors = [COLOR_RED, COLOR_GREEN, [0, 0, 255, 255]];
+    // Written out of order; frame 10 must come last.
+    for (number, color) in [10, 1, 2].iter().zip(&colors) {
+        let image = image::RgbaImage::from_pixel(3, 3, image::Rgba(*color));
+        image
+            .save(dir.join(format!("walk_{}.png", number)))
+            .unwrap();
+    }
+    std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+
+    let f = import::from_directory(&dir, &import::ImportOptions::new());
+    std::fs::remove_dir_all(&dir).unwrap();
+    let f = f.unwrap();
+    assert_eq!(f.num_frames(), 3);
+    assert_eq!(f.frame(0).duration(), import::DEFAULT_FRAME_DURATION);
+    let centers: Vec<_> = (0..3)
+        .map(|i| f.frame(i).image().get_pixel(1, 1).0)
+        .collect();
+    assert_eq!(centers, vec![colors[1], colors[2], colors[0]]);
+}
+
+#[cfg(feature = "cache")]
+#[test]
+fn cache_round_trip() {
+    for name in &[
+        "layers_and_tags",
+        "linked_cels",
+        "indexed",
+        "grayscale",
+        "palette",
+        "slice",
+        "tilemap",
+        "tilemap_indexed",
+        "user_data",
+    ] {
+        let f = load_test_file(name);
+        let mut data = Vec::new();
+        f.to_cache(42, &mut data).unwrap();
+        let cached = AsepriteFile::from_cache(&data[..], 42).unwrap();
+        assert_semantic_eq(&f, &cached);
+
+        // Stale and truncated caches are rejected.
+        assert_invalid_input(AsepriteFile::from_cache(&data[..], 43));
+        assert!(AsepriteFile::from_cache(&data[..data.len() - 1], 42).is_err());
+    }
+}
+
+#[cfg(feature = "cache")]
+#[test]
+fn cache_skips_unused_pixels() {
+    let mut f = load_test_file("layers_and_tags");
+    let layer = f
+        .framedata
+        .frame_cels(0)
+        .find(|(_, cel)| matches!(cel.content, cel::CelContent::Raw(_)))
+        .unwrap()
+        .0;
+    // The pixels of the removed cel stay in the frame's buffer.
+    f.framedata.remove_cel(cel::CelId {
+        frame: 0,
+        layer: layer as u16,
+    });
+    assert!(referenced_pixels(&f, 0) > 0);
+    assert!(f.framedata.frame_pixels(0).len() > referenced_pixels(&f, 0));
+
+    let mut data = Vec::new();
+    f.to_cache(0, &mut data).unwrap();
+    let cached = AsepriteFile::from_cache(&data[..], 0).unwrap();
+    assert_semantic_eq(&f, &cached);
+    assert_eq!(
+        cached.framedata.frame_pixels(0).len(),
+        referenced_pixels(&cached, 0)
+    );
+}
+
+#[cfg(feature = "cache")]
+#[test]
+fn cache_version() {
+    let f = load_test_file("basic-16x16");
+    let mut data = Vec::new();
+    f.to_cache(0, &mut data).unwrap();
+    // The crate version follows the magic, the format version and its length.
+    let version = &mut data[16..16 + env!("CARGO_PKG_VERSION").len()];
+    version[0] = b'9';
+    assert_invalid_input(AsepriteFile::from_cache(&data[..], 0));
+    assert_invalid_input(AsepriteFile::from_cache(&b"not a cache"[..], 0));
+}
+
+#[cfg(feature = "cache")]
+#[test]
+fn cache_read_file_cached() {
+    let source = PathBuf::from("./tests/data/layers_and_tags.aseprite");
+    let mut path = std::env::temp_dir();
+    path.push(format!("asefile_cache_{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let parsed = cache::read_file_cached(&source, &path).unwrap();
+    assert!(path.exists());
+    let cached = cache::read_file_cached(&source, &path).unwrap();
+    assert_semantic_eq(&parsed, &cached);
+
+    // An invalid cache is replaced.
+    std::fs::write(&path, "garbage").unwrap();
+    let reparsed = cache::read_file_cached(&source, &path).unwrap();
+    assert_semantic_eq(&parsed, &reparsed);
+    let fingerprint = cache::fingerprint(&std::fs::read(&source).unwrap());
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(AsepriteFile::from_cache(&data[..], fingerprint).is_ok());
+}
+
+#[test]
+fn reload_match_ids() {
+    let f = load_test_file("layers_and_tags");
+    let mapping = reload::match_ids(&f, &f);
+    assert!(mapping.is_identity());
+    assert_eq!(mapping.layers.matched.len(), f.num_layers() as usize);
+
+    let red = image::RgbaImage::from_pixel(1, 1, image::Rgba(COLOR_RED));
+    let slice = |name: &str| Slice {
+        name: name.to_owned(),
+        keys: vec![SliceKey {
+            from_frame: 0,
+            origin: SliceOrigin { x: 0, y: 0 },
+            size: SliceSize {
+                width: 1,
+                height: 1,
+            },
+            slice9: None,
+            pivot: None,
+        }],
+        user_data: None,
+    };
+    let mut old = AsepriteFileBuilder::new(2, 2);
+    for name in &["Body", "Arm", "Arm"] {
+        old.add_layer(name);
+    }
+    for _ in 0..6 {
+        old.add_frame(100);
+    }
+    old.add_cel(0, 0, (0, 0), &red);
+    old.add_tag("Idle", 0, 1, AnimationDirection::Forward);
+    old.add_tag("Run", 2, 4, AnimationDirection::Forward);
+    old.add_tag("Jump", 5, 5, AnimationDirection::Forward);
+    old.add_slice(slice("Hitbox"));
+    old.add_slice(slice("Old"));
+    let old = old.build().unwrap();
+
+    // The artist inserted a layer at the bottom, renamed and extended "Run",
+    // removed "Jump", and replaced a slice.
+    let mut new = AsepriteFileBuilder::new(2, 2);
+    for name in &["Shadow", "Body", "Arm", "Arm"] {
+        new.add_layer(name);
+    }
+    for _ in 0..6 {
+        new.add_frame(100);
+    }
+    new.add_tag("Sprint", 1, 4, AnimationDirection::Forward);
+    new.add_tag("Idle", 0, 1, AnimationDirection::Forward);
+    new.add_slice(slice("New"));
+    new.add_slice(slice("Hitbox"));
+    let new = new.build().unwrap();
+
+    let mapping = reload::match_ids(&old, &new);
+    assert!(!mapping.is_identity());
+    let layers = &mapping.layers;
+    assert_eq!(layers.matched, vec![(0, 1), (1, 2), (2, 3)]);
+    assert_eq!(layers.added, vec![0]);
+    assert!(layers.removed.is_empty() && layers.renamed.is_empty());
+    assert_eq!(layers.new_id(2), Some(3));
+    assert_eq!(layers.old_id(0), None);
+
+    let tags = &mapping.tags;
+    assert_eq!(tags.matched, vec![(0, 1), (1, 0)]);
+    assert_eq!(tags.renamed, vec![(1, 0)]);
+    assert_eq!(tags.removed, vec![2]);
+    assert!(tags.added.is_empty());
+
+    let slices = &mapping.slices;
+    assert_eq!(slices.matched, vec![(0, 1)]);
+    assert_eq!(
+        (&slices.added[..], &slices.removed[..]),
+        (&[0][..], &[1][..])
+    );
+    assert_eq!(reload::match_ids(&old, &new), mapping);
+}
+
+#[test]
+fn reload_match_layers_by_uuid() {
+    let layer = |name: &str, uuid: u8| {
+        let data = Bytes::default()
+            .word(1)
+            .word(0)
+            .word(0)
+            .zeros(4)
+            .word(0)
+            .byte(255)
+            .zeros(3)
+            .string(name)
+            .bytes(&[uuid; 16]);
+        chunk(0x2004, data)
+    };
+    let file = |layers: Vec<Bytes>| {
+        let mut data = craft_file(1, 1, 32, &[layers]);
+        // Set the header flag for layer UUIDs.
+        data[14] |= 4;
+        AsepriteFile::read(&data[..]).unwrap()
+    };
+    let old = file(vec![layer("A", 1), layer("B", 2)]);
+    assert_eq!(old.layer(1).uuid(), Some([2; 16]));
+    // Both layers were renamed and swapped. A new layer reuses an old name.
+    let new = file(vec![layer("C", 2), layer("A", 3), layer("D", 1)]);
+    assert_eq!(write_and_read(&new).layer(2).uuid(), Some([1; 16]));
+
+    let layers = reload::match_ids(&old, &new).layers;
+    assert_eq!(layers.matched, vec![(0, 2), (1, 0)]);
+    assert_eq!(layers.renamed, vec![(0, 2), (1, 0)]);
+    assert_eq!(layers.added, vec![1]);
+
+    // Diffs report the renames instead of removed and added layers.
+    let changes = diff::diff(&old, &new);
+    let renamed: Vec<_> = changes
+        .layers
+        .iter()
+        .map(|layer| (layer.old_path.as_deref(), layer.path.as_str()))
+        .collect();
+    assert_eq!(
+        renamed,
+        vec![(Some("A"), "D"), (Some("B"), "C"), (None, "A")]
+    );
+    assert_eq!(changes.layers[2].kind, diff::ChangeKind::Added);
+
+    // Files saved without the header flag have no UUIDs.
+    assert_eq!(load_test_file("basic-16x16").layer(0).uuid(), None);
+}
+
+#[test]
+fn strict_fixtures() {
+    let options = ReadOptions::new().strict(true);
+    for entry in std::fs::read_dir("./tests/data").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map_or(true, |ext| ext != "aseprite") {
+            continue;
+        }
+        // Tested by tile_id_out_of_range.
+        if path
+            .file_stem()
+            .is_some_and(|stem| stem == "tilemap_deleted_tile")
+        {
+            continue;
+        }
+        // Some fixtures test unsupported features and never load.
+        if let Ok(f) = AsepriteFile::read_file(&path) {
+            assert!(f.warnings().is_empty() && f.trailing_data().is_empty());
+            assert!(AsepriteFile::read_file_with_options(&path, &options).is_ok());
+        }
+    }
+}
+
+#[test]
+fn lazy_fixtures() {
+    let options = ReadOptions::new().lazy(true);
+    for entry in std::fs::read_dir("./tests/data").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map_or(true, |ext| ext != "aseprite") {
+            continue;
+        }
+        let lazy = AsepriteFile::read_file_with_options(&path, &options);
+        match AsepriteFile::read_file(&path) {
+            Ok(f) => {
+                let lazy = lazy.unwrap();
+                for frame in (0..f.num_frames()).rev() {
+                    assert_eq!(lazy.frame(frame).image(), f.frame(frame).image());
+                }
+                assert!(lazy.decode_all().is_ok());
+            }
+            Err(_) => assert!(lazy.and_then(|f| f.decode_all()).is_err()),
+        }
+    }
+}
+
+#[test]
+fn frame_cache() {
+    let mut f = load_test_file("layers_and_tags");
+    let expected: Vec<_> = (0..f.num_frames()).map(|i| f.frame(i).image()).collect();
+    assert_eq!(f.frame_cache.len(), 0);
+
+    f.set_frame_cache_capacity(2);
+    assert_eq!(f.frame_cache_capacity(), 2);
+    for i in [0, 1, 0, 2, 0, 2] {
+        assert_eq!(f.frame(i).image(), expected[i as usize]);
+    }
+    assert_eq!(f.frame_cache.len(), 2);
+    let hidden = ImageOptions::new().include_hidden_layers(true);
+    assert_eq!(
+        f.frame(0).image_with_options(&hidden),
+        load_test_file("layers_and_tags")
+            .frame(0)
+            .image_with_options(&hidden)
+    );
+    f.set_frame_cache_capacity(1);
+    assert_eq!(f.frame_cache.len(), 1);
+
+    // Edits drop the cached images.
+    let red = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 0, 0, 255]));
+    let top = f.num_layers() - 1;
+    f.set_cel_image(0, top, &red, (0, 0)).unwrap();
+    assert_eq!(f.frame_cache.len(), 0);
+    assert_eq!(f.frame(0).image().get_pixel(0, 0).0, [255, 0, 0, 255]);
+}
+
+#[test]
+fn read_shared() {
+    for entry in std::fs::read_dir("./tests/data").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map_or(true, |ext| ext != "aseprite") {
+            continue;
+        }
+        let data = std::sync::Arc::new(std::fs::read(&path).unwrap());
+        let shared = AsepriteFile::read_shared(data);
+        match AsepriteFile::read_file(&path) {
+            Ok(f) => {
+                let shared = shared.unwrap();
+                for frame in 0..f.num_frames() {
+                    assert_eq!(shared.frame(frame).image(), f.frame(frame).image());
+                }
+            }
+            Err(_) => assert!(shared.and_then(|f| f.decode_all()).is_err()),
+        }
+    }
+}
+
+#[test]
+fn shared_raw_cel() {
+    // A 1x2 raw cel, preceded by unrelated data.
+    let cel = Bytes::default()
+        .word(0)
+        .short(0)
+        .short(0)
+        .byte(255)
+        .word(0)
+        .zeros(7)
+        .word(1)
+        .word(2)
+        .byte(1)
+        .byte(2)
+        .byte(3)
+        .byte(4)
+        .byte(5)
+        .byte(6)
+        .byte(7)
+        .byte(8);
+    let mut data = vec![0xff; 10];
+    data.extend_from_slice(&cel.0);
+    let shared = pixel::SharedBytes(std::sync::Arc::new(data));
+    let mut pixels = pixel::FramePixels::new_lazy(PixelFormat::Rgba, Some(shared));
+    pixels.set_chunk(10..10 + cel.0.len());
+    cel::parse_chunk(&cel.0, 0, &mut pixels, &mut unlimited()).unwrap();
+    assert!(pixels.get_if_decoded().is_none());
+    match pixels.try_get().unwrap() {
+        pixel::Pixels::Rgba(rgba) => assert_eq!(
+            rgba,
+            &[image::Rgba([1, 2, 3, 4]), image::Rgba([5, 6, 7, 8])]
+        ),
+        _ => panic!("Expected RGBA pixels"),
+    }
+
+    // Too little data.
+    let mut pixels = pixel::FramePixels::new_lazy(
+        PixelFormat::Rgba,
+        Some(pixel::SharedBytes(std::sync::Arc::new(
+            cel.0[..cel.0.len() - 1].to_vec(),
+        ))),
+    );
+    pixels.set_chunk(0..cel.0.len() - 1);
+    assert_invalid_input(cel::parse_chunk(
+        &cel.0[..cel.0.len() - 1],
+        0,
+        &mut pixels,
+        &mut unlimited(),
+    ));
+}
+
+#[test]
+fn read_metadata() {
+    let data = std::fs::read("./tests/data/layers_and_tags.aseprite").unwrap();
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    let metadata = AsepriteFile::read_metadata(&data[..]).unwrap();
+    assert_eq!(metadata.size(), f.size());
+    assert_eq!(metadata.num_frames(), f.num_frames());
+    assert_eq!(metadata.num_layers(), f.num_layers());
+    for frame in 0..f.num_frames() {
+        assert_eq!(metadata.frame(frame).duration(), f.frame(frame).duration());
+        let image = metadata.frame(frame).image();
+        assert!(image.pixels().all(|pixel| pixel[3] == 0));
+    }
+    for layer in 0..f.num_layers() {
+        assert_eq!(metadata.layer(layer).name(), f.layer(layer).name());
+    }
+    for tag in 0..f.num_tags() {
+        assert_eq!(metadata.tag(tag).name(), f.tag(tag).name());
+        assert_eq!(metadata.tag(tag).to_frame(), f.tag(tag).to_frame());
+    }
+    assert!(metadata.decode_all().is_ok());
+}
+
+#[test]
+fn read_metadata_cannot_be_saved() {
+    let data = std::fs::read("./tests/data/layers_and_tags.aseprite").unwrap();
+    let metadata = AsepriteFile::read_metadata(&data[..]).unwrap();
+    let err = metadata.write_to(Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    #[cfg(feature = "cache")]
+    {
+        let err = metadata.to_cache(0, Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn lazy_invalid_pixel_data() {
+    // A 2x2 cel with one pixel too many.
+    let data = Bytes::default()
+        .word(0)
+        .short(0)
+        .short(0)
+        .byte(255)
+        .word(2)
+        .zeros(7)
+        .word(2)
+        .word(2)
+        .zlib(&[0; 17]);
+    let mut pixels = pixel::FramePixels::new_lazy(PixelFormat::Rgba, None);
+    let cel = cel::parse_chunk(&data.0, 0, &mut pixels, &mut unlimited()).unwrap();
+    assert!(pixels.get_if_decoded().is_none());
+    assert_invalid_input(pixels.try_get());
+    assert_eq!(pixels.get().len(), 4);
+    match cel.content {
+        cel::CelContent::Raw(image) => assert_eq!(image.pixels.range, 0..4),
+        _ => panic!("Expected an image cel"),
+    }
+}
+
+#[test]
+fn clear_lazy_pixels() {
+    let data = Bytes::default()
+        .word(0)
+        .short(0)
+        .short(0)
+        .byte(255)
+        .word(2)
+        .zeros(7)
+        .word(2)
+        .word(2)
+        .zlib(&[0; 8]);
+    let mut pixels = pixel::FramePixels::new_lazy(PixelFormat::Grayscale, None);
+    cel::parse_chunk(&data.0, 0, &mut pixels, &mut unlimited()).unwrap();
+    pixels.clear();
+    match pixels.get_if_decoded() {
+        Some(pixel::Pixels::Grayscale(grayscale)) => assert!(grayscale.is_empty()),
+        _ => panic!("Expected empty grayscale pixels"),
+    }
+}
+
+#[test]
+fn lenient_parsing() {
+    let user_data = chunk(0x2020, Bytes::default().dword(0));
+    let mut layer = layer_chunk("Layer");
+    layer.0[6] |= 0x80; // unknown layer flag
+    let unknown = chunk(0x2099, Bytes::default().bytes(&[1, 2, 3]));
+    let pixels = [255, 0, 0, 255];
+    let bytes = craft_file(
+        1,
+        1,
+        32,
+        &[
+            vec![user_data.clone(), layer, unknown],
+            vec![
+                raw_cel_chunk(0, 1, 1, &pixels),
+                raw_cel_chunk(1, 1, 1, &pixels),
+            ],
+        ],
+    );
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    assert_eq!(
+        f.warnings(),
+        &[
+            ParseWarning::UnusedUserData { frame: 0 },
+            ParseWarning::UnknownLayerFlags {
+                layer: 0,
+                flags: 0x80
+            },
+            ParseWarning::UnknownChunk {
+                frame: 0,
+                chunk_type: 0x2099
+            },
+            ParseWarning::MissingLayer { frame: 1, layer: 1 },
+        ]
+    );
+    assert!(f.layer(0).is_visible());
+    assert_eq!(f.frame(1).image().get_pixel(0, 0).0, pixels);
+
+    let raw: Vec<_> = f
+        .frame(0)
+        .raw_chunks()
+        .map(|chunk| (chunk.chunk_type(), chunk.data()))
+        .collect();
+    assert_eq!(raw, [(0x2099, &[1, 2, 3][..])]);
+    assert_eq!(f.frame(1).raw_chunks().count(), 0);
+
+    // Unknown chunks are written back.
+    let mut written = Vec::new();
+    f.write_to(&mut written).unwrap();
+    let f = AsepriteFile::read(&written[..]).unwrap();
+    assert_eq!(
+        f.warnings(),
+        &[ParseWarning::UnknownChunk {
+            frame: 0,
+            chunk_type: 0x2099
+        }]
+    );
+
+    let strict = ReadOptions::new().strict(true);
+    assert_invalid_input(AsepriteFile::read_with_options(&bytes[..], &strict));
+}
+
+#[test]
+fn raw_chunk_position() {
+    // The types of the chunks in the first frame of a file.
+    let chunk_types = |data: &[u8]| {
+        let dword =
+            |at: usize| u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+        let mut offset = 144;
+        let mut types = Vec::new();
+        for _ in 0..dword(140) {
+            types.push(u16::from_le_bytes([data[offset + 4], data[offset + 5]]));
+            offset += dword(offset) as usize;
+        }
+        types
+    };
+    let unknown = chunk(0x2099, Bytes::default().bytes(&[1, 2, 3]));
+    let bytes = craft_file(
+        1,
+        1,
+        32,
+        &[vec![
+            layer_chunk("Bottom"),
+            unknown,
+            layer_chunk("Top"),
+            raw_cel_chunk(0, 1, 1, &COLOR_RED),
+        ]],
+    );
+    assert_eq!(chunk_types(&bytes), [0x2004, 0x2099, 0x2004, 0x2005]);
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    let mut written = Vec::new();
+    f.write_to(&mut written).unwrap();
+    assert_eq!(chunk_types(&written), [0x2004, 0x2099, 0x2004, 0x2005]);
+    let f = AsepriteFile::read(&written[..]).unwrap();
+    assert_eq!(f.layer(1).name(), "Top");
+    assert_eq!(f.frame(0).raw_chunks().count(), 1);
+}
+
+#[test]
+fn read_partial() {
+    let data = std::fs::read("./tests/data/layers_and_tags.aseprite").unwrap();
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    let images: Vec<_> = (0..f.num_frames()).map(|i| f.frame(i).image()).collect();
+    assert!(AsepriteFile::read_partial(&data[..])
+        .unwrap()
+        .warnings()
+        .is_empty());
+
+    let mut frames_seen = std::collections::BTreeSet::new();
+    for len in (0..data.len()).step_by(7) {
+        let cut_off = &data[..len];
+        let partial = match AsepriteFile::read_partial(cut_off) {
+            Ok(partial) => partial,
+            Err(_) => {
+                assert!(AsepriteFile::read(cut_off).is_err());
+                continue;
+            }
+        };
+        let frames = partial.num_frames();
+        assert!(frames < f.num_frames());
+        assert_eq!(
+            partial.warnings(),
+            &[ParseWarning::Truncated {
+                frames: frames as u16,
+                declared_frames: f.num_frames() as u16,
+            }]
+        );
+        for i in 0..frames {
+            assert_eq!(partial.frame(i).image(), images[i as usize]);
+        }
+        for i in 0..partial.num_tags() {
+            assert!(partial.tag(i).to_frame() < frames);
+        }
+        frames_seen.insert(frames);
+    }
+    assert_eq!(frames_seen.len() as u32, f.num_frames() - 1);
+
+    // A corrupted frame in the middle of the file.
+    let mut corrupted = data.clone();
+    let frame_size = u32::from_le_bytes([data[128], data[129], data[130], data[131]]) as usize;
+    corrupted[128 + frame_size + 4] = 0;
+    assert_invalid_input(AsepriteFile::read(&corrupted[..]));
+    let partial = AsepriteFile::read_partial(&corrupted[..]).unwrap();
+    assert_eq!(partial.num_frames(), 1);
+    assert!(partial.trailing_data().is_empty());
+}
+
+#[test]
+fn error_context() {
+    let layer = layer_chunk("Layer");
+    let bad_cel = chunk(
+        0x2005,
+        Bytes::default()
+            .word(0)
+            .short(0)
+            .short(0)
+            .byte(255)
+            .word(7) // unknown cel type
+            .zeros(7),
+    );
+    let bytes = craft_file(1, 1, 32, &[vec![layer.clone()], vec![bad_cel]]);
+    let offset = 128 + 16 + layer.0.len() + 16;
+    let err = AsepriteFile::read(&bytes[..]).unwrap_err();
+    assert!(matches!(err, AsepriteParseError::InvalidInput(_)));
+    assert!(
+        err.to_string()
+            .ends_with(&format!("(in Cel chunk of frame 1 at offset {})", offset)),
+        "{}",
+        err
+    );
+
+    // Cut off in the header of the second frame.
+    let err = AsepriteFile::read(&bytes[..offset - 10]).unwrap_err();
+    match &err {
+        AsepriteParseError::IoError(io) => assert_eq!(io.kind(), std::io::ErrorKind::UnexpectedEof),
+        other => panic!("Expected an I/O error, got: {:?}", other),
+    }
+    assert!(
+        err.to_string()
+            .ends_with(&format!("(in header of frame 1 at offset {})", offset - 16)),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn color_profile() {
+    let f = load_test_file("basic-16x16");
+    let profile = f.color_profile().unwrap();
+    assert_eq!(profile.profile_type(), ColorProfileType::Srgb);
+    assert_eq!(profile.fixed_gamma(), None);
+    assert_eq!(profile.icc_profile(), None);
+
+    let icc = b"not really an ICC profile";
+    let profile_chunk = chunk(
+        0x2007,
+        Bytes::default()
+            .word(2) // ICC
+            .word(1) // fixed gamma
+            .dword(0x0002_4000) // 2.25
+            .zeros(8)
+            .dword(icc.len() as u32)
+            .bytes(icc),
+    );
+    let bytes = craft_file(1, 1, 32, &[vec![profile_chunk, layer_chunk("Layer")]]);
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    let profile = f.color_profile().unwrap();
+    assert_eq!(profile.profile_type(), ColorProfileType::Icc);
+    assert_eq!(profile.fixed_gamma(), Some(2.25));
+    assert_eq!(profile.icc_profile(), Some(&icc[..]));
+    assert_eq!(f.frame(0).raw_chunks().count(), 0);
+
+    let copy = write_and_read(&f);
+    assert_eq!(copy.color_profile(), f.color_profile());
+
+    // The ICC data is cut off.
+    let profile_chunk = chunk(
+        0x2007,
+        Bytes::default().word(2).word(0).zeros(12).dword(100),
+    );
+    let bytes = craft_file(1, 1, 32, &[vec![profile_chunk]]);
+    assert_invalid_input(AsepriteFile::read(&bytes[..]));
+}
+
+#[test]
+fn convert_to_srgb() {
+    let sprite = |profile: Bytes| {
+        // A mid gray and a transparent pixel.
+        let pixels = [[128, 128, 128, 255], [128, 128, 128, 0]].concat();
+        let chunks = vec![
+            chunk(0x2007, profile),
+            layer_chunk("Layer"),
+            raw_cel_chunk(0, 2, 1, &pixels),
+        ];
+        AsepriteFile::read(&craft_file(2, 1, 32, &[chunks])[..]).unwrap()
+    };
+    let convert = ImageOptions::new().convert_to_srgb(true);
+    let gray = |f: &AsepriteFile, options: &ImageOptions| {
+        let image = f.frame(0).image_with_options(options);
+        (image.get_pixel(0, 0).0, image.get_pixel(1, 0).0)
+    };
+
+    // sRGB with a gamma of 1.0 stores linear values.
+    let linear = sprite(Bytes::default().word(1).word(1).dword(0x1_0000).zeros(8));
+    assert_eq!(
+        gray(&linear, &ImageOptions::new()),
+        ([128, 128, 128, 255], [128, 128, 128, 0])
+    );
+    assert_eq!(
+        gray(&linear, &convert),
+        ([188, 188, 188, 255], [128, 128, 128, 0])
+    );
+
+    // An ICC profile with the sRGB colorants and linear tone curves.
+    let mut icc = vec![0; 128];
+    icc[16..20].copy_from_slice(b"RGB ");
+    icc[20..24].copy_from_slice(b"XYZ ");
+    let colorants = [
+        (b"rXYZ", [0.4361, 0.2225, 0.0139]),
+        (b"gXYZ", [0.3851, 0.7169, 0.0971]),
+        (b"bXYZ", [0.1431, 0.0606, 0.7141]),
+    ];
+    let tags = colorants.len() + 3;
+    icc.extend_from_slice(&(tags as u32).to_be_bytes());
+    let mut data = Vec::new();
+    let data_start = 132 + tags * 12;
+    for (signature, xyz) in &colorants {
+        let offset = data_start + data.len();
+        icc.extend_from_slice(&signature[..]);
+        icc.extend_from_slice(&(offset as u32).to_be_bytes());
+        icc.extend_from_slice(&20_u32.to_be_bytes());
+        data.extend_from_slice(b"XYZ \0\0\0\0");
+        for value in xyz {
+            data.extend_from_slice(&((value * 65536.0) as i32).to_be_bytes());
+        }
+    }
+    // All channels share one curve with a gamma of 1.0.
+    let curve_offset = data_start + data.len();
+    for signature in [b"rTRC", b"gTRC", b"bTRC"] {
+        icc.extend_from_slice(&signature[..]);
+        icc.extend_from_slice(&(curve_offset as u32).to_be_bytes());
+        icc.extend_from_slice(&14_u32.to_be_bytes());
+    }
+    data.extend_from_slice(b"curv\0\0\0\0\0\0\0\x01\x01\0");
+    icc.extend_from_slice(&data);
+    let f = sprite(
+        Bytes::default()
+            .word(2)
+            .word(0)
+            .zeros(12)
+            .dword(icc.len() as u32)
+            .bytes(&icc),
+    );
+    let (converted, _) = gray(&f, &convert);
+    for channel in &converted[..3] {
+        assert!((187..=189).contains(channel), "{:?}", converted);
+    }
+
+    // Profiles that are not supported leave the colors unchanged.
+    icc[16..20].copy_from_slice(b"GRAY");
+    let f = sprite(
+        Bytes::default()
+            .word(2)
+            .word(0)
+            .zeros(12)
+            .dword(icc.len() as u32)
+            .bytes(&icc),
+    );
+    assert_eq!(gray(&f, &convert).0, [128, 128, 128, 255]);
+    let srgb = load_test_file("basic-16x16");
+    assert_eq!(
+        srgb.frame(0).image_with_options(&convert),
+        srgb.frame(0).image()
+    );
+}
+
+#[test]
+fn external_files() {
+    let mut entries = Bytes::default().dword(4).zeros(8);
+    for (id, file_type, name) in [
+        (1, 0, "palette.aseprite"),
+        (2, 1, "tiles.aseprite"),
+        (3, 3, "tile-manager"),
+        (4, 9, "future"),
+    ] {
+        entries = entries.dword(id).byte(file_type).zeros(7).string(name);
+    }
+    let bytes = craft_file(1, 1, 32, &[vec![chunk(0x2008, entries)]]);
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    assert_eq!(f.external_files().map().len(), 4);
+    let file = |id| f.external_file_by_id(&ExternalFileId::new(id)).unwrap();
+    assert_eq!(file(1).file_type(), ExternalFileType::Palette);
+    assert_eq!(file(1).name(), "palette.aseprite");
+    assert_eq!(file(2).file_type(), ExternalFileType::Tileset);
+    assert_eq!(
+        file(3).file_type(),
+        ExternalFileType::TileManagementExtension
+    );
+    assert_eq!(file(4).file_type(), ExternalFileType::Unknown(9));
+
+    let copy = write_and_read(&f);
+    let copy_file = |id| copy.external_file_by_id(&ExternalFileId::new(id)).unwrap();
+    assert_eq!(copy_file(4).file_type(), ExternalFileType::Unknown(9));
+    assert_eq!(copy_file(2).name(), "tiles.aseprite");
+}
+
+#[test]
+fn external_tileset() {
+    let source = load_test_file("tileset");
+    assert_eq!(source.pixel_format(), PixelFormat::Rgba);
+    let source_tileset = source.tilesets().get(&TilesetId::new(0)).unwrap();
+    let external_files = Bytes::default()
+        .dword(1)
+        .zeros(8)
+        .dword(7)
+        .byte(1) // tileset
+        .zeros(7)
+        .string("tileset.aseprite");
+    let tileset = Bytes::default()
+        .dword(3)
+        .dword(1 | 4) // external file, empty tile is zero
+        .dword(1)
+        .word(*source_tileset.tile_size().width())
+        .word(*source_tileset.tile_size().height())
+        .short(1)
+        .zeros(14)
+        .string("Linked")
+        .dword(7)
+        .dword(0);
+    let bytes = craft_file(
+        16,
+        16,
+        32,
+        &[vec![chunk(0x2008, external_files), chunk(0x2023, tileset)]],
+    );
+    let err = AsepriteFile::read(&bytes[..]).unwrap_err();
+    assert!(
+        matches!(err, AsepriteParseError::UnsupportedFeature(_)),
+        "{:?}",
+        err
+    );
+
+    let options = ReadOptions::new().external_resolver(|external| {
+        assert_eq!(external.file_type(), ExternalFileType::Tileset);
+        std::fs::read(format!("./tests/data/{}", external.name())).ok()
+    });
+    let f = AsepriteFile::read_with_options(&bytes[..], &options).unwrap();
+    let id = TilesetId::new(3);
+    let tileset = f.tilesets().get(&id).unwrap();
+    assert_eq!(tileset.tile_count(), source_tileset.tile_count());
+    assert_eq!(tileset.name(), "Linked");
+    assert_eq!(
+        f.tileset_image(&id).unwrap(),
+        source.tileset_image(&TilesetId::new(0)).unwrap()
+    );
+
+    let options = ReadOptions::new().external_resolver(|_| None);
+    assert!(AsepriteFile::read_with_options(&bytes[..], &options).is_err());
+    let options = ReadOptions::new().external_resolver(|_| Some(vec![1, 2, 3]));
+    let err = AsepriteFile::read_with_options(&bytes[..], &options).unwrap_err();
+    assert!(
+        err.to_string()
+            .ends_with("(in external file tileset.aseprite)"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn legacy_palette() {
+    // Two packets: colors 0 and 1, then color 3 after skipping one color.
+    let six_bit = Bytes::default()
+        .word(2)
+        .byte(0)
+        .byte(2)
+        .bytes(&[63, 0, 0, 0, 32, 63])
+        .byte(1)
+        .byte(1)
+        .bytes(&[10, 20, 30]);
+    let bytes = craft_file(1, 1, 8, &[vec![chunk(0x0011, six_bit)]]);
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    let palette = f.palette().unwrap();
+    assert_eq!(palette.num_colors(), 3);
+    assert_eq!(palette.color(0).unwrap().raw_rgba8(), [255, 0, 0, 255]);
+    assert_eq!(palette.color(1).unwrap().raw_rgba8(), [0, 130, 255, 255]);
+    assert!(palette.color(2).is_none());
+    assert_eq!(palette.color(3).unwrap().raw_rgba8(), [40, 81, 121, 255]);
+
+    // A count of zero means 256 colors.
+    let colors: Vec<u8> = (0..=255).flat_map(|i| [i, i, 255 - i]).collect();
+    let eight_bit = Bytes::default().word(1).byte(0).byte(0).bytes(&colors);
+    let bytes = craft_file(1, 1, 8, &[vec![chunk(0x0004, eight_bit.clone())]]);
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    let palette = f.palette().unwrap();
+    assert_eq!(palette.num_colors(), 256);
+    assert_eq!(palette.color(200).unwrap().raw_rgba8(), [200, 200, 55, 255]);
+
+    // The new palette chunk takes precedence.
+    let chunks = vec![palette_chunk(&[COLOR_GREEN]), chunk(0x0004, eight_bit)];
+    let f = AsepriteFile::read(&craft_file(1, 1, 8, &[chunks])[..]).unwrap();
+    assert_eq!(f.palette().unwrap().num_colors(), 1);
+    assert_eq!(
+        f.palette().unwrap().color(0).unwrap().raw_rgba8(),
+        COLOR_GREEN
+    );
+}
+
+#[test]
+fn user_data_properties() {
+    let external_files = Bytes::default()
+        .dword(1)
+        .zeros(8)
+        .dword(5)
+        .byte(2) // properties extension
+        .zeros(7)
+        .string("my-extension");
+    let user_properties = Bytes::default()
+        .dword(0) // user properties
+        .dword(4)
+        .string("hp")
+        .word(0x8)
+        .bytes(&(-5i64).to_le_bytes())
+        .string("name")
+        .word(0xD)
+        .string("knight")
+        .string("origin")
+        .word(0xE)
+        .dword(3)
+        .dword(-4i32 as u32)
+        .string("list")
+        .word(0x11)
+        .dword(2)
+        .word(0) // each element has its own type
+        .word(0x1)
+        .byte(1)
+        .word(0x3)
+        .byte(7);
+    let extension_properties = Bytes::default()
+        .dword(5)
+        .dword(1)
+        .string("nested")
+        .word(0x12)
+        .dword(1)
+        .string("speed")
+        .word(0xA)
+        .dword(0x18000); // 1.5
+    let properties_size = 8 + user_properties.0.len() + extension_properties.0.len();
+    let user_data_chunk = |properties: &Bytes| {
+        chunk(
+            0x2020,
+            Bytes::default()
+                .dword(1 | 4)
+                .string("text")
+                .dword(properties_size as u32)
+                .dword(2)
+                .bytes(&user_properties.0)
+                .bytes(&properties.0),
+        )
+    };
+    let data = craft_file(
+        1,
+        1,
+        32,
+        &[vec![
+            chunk(0x2008, external_files.clone()),
+            layer_chunk("Layer"),
+            user_data_chunk(&extension_properties),
+        ]],
+    );
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    let layer = f.layer(0);
+    let user_data = layer.user_data().unwrap();
+    assert_eq!(user_data.text.as_deref(), Some("text"));
+    let properties = &user_data.properties[""];
+    assert_eq!(properties["hp"], PropertyValue::I64(-5));
+    assert_eq!(
+        properties["name"],
+        PropertyValue::String("knight".to_string())
+    );
+    assert_eq!(properties["origin"], PropertyValue::Point { x: 3, y: -4 });
+    assert_eq!(
+        properties["list"],
+        PropertyValue::Vector(vec![PropertyValue::Bool(true), PropertyValue::U8(7)])
+    );
+    let mut nested = Properties::new();
+    nested.insert("speed".to_string(), PropertyValue::Fixed(1.5));
+    assert_eq!(
+        user_data.properties["my-extension"]["nested"],
+        PropertyValue::Map(nested)
+    );
+    assert_semantic_eq(&f, &write_and_read(&f));
+    #[cfg(feature = "cache")]
+    {
+        let mut cache = Vec::new();
+        f.to_cache(0, &mut cache).unwrap();
+        assert_semantic_eq(&f, &AsepriteFile::from_cache(&cache[..], 0).unwrap());
+    }
+
+    // Extensions must be declared in the external files.
+    let mut undeclared = AsepriteFile::read(&data[..]).unwrap();
+    let user_data = undeclared.layers.layers[0].user_data.as_mut().unwrap();
+    let properties = user_data.properties.remove("my-extension").unwrap();
+    user_data.properties.insert("other".to_string(), properties);
+    let err = undeclared.write_to(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    let unknown_type = Bytes::default()
+        .dword(5)
+        .dword(1)
+        .string("bad")
+        .word(0x99)
+        .byte(0);
+    let data = craft_file(
+        1,
+        1,
+        32,
+        &[vec![
+            chunk(0x2008, external_files),
+            layer_chunk("Layer"),
+            user_data_chunk(&unknown_type),
+        ]],
+    );
+    assert_invalid_input(AsepriteFile::read(&data[..]));
+}
+
+#[test]
+fn user_data_property_accessors() {
+    let mut user_data = test_user_data("text", COLOR_RED);
+    let mut nested = Properties::new();
+    nested.insert("speed".to_string(), PropertyValue::Fixed(1.5));
+    let properties = user_data.properties.entry("ext".to_string()).or_default();
+    properties.insert("hp".to_string(), PropertyValue::U16(300));
+    properties.insert("neg".to_string(), PropertyValue::I8(-1));
+    properties.insert("name".to_string(), PropertyValue::String("knight".into()));
+    properties.insert("nested".to_string(), PropertyValue::Map(nested));
+    user_data
+        .properties
+        .entry(String::new())
+        .or_default()
+        .insert("flag".to_string(), PropertyValue::Bool(true));
+
+    assert_eq!(user_data.property::<i64>("ext", "hp").unwrap(), 300);
+    assert_eq!(user_data.property::<u16>("ext", "hp").unwrap(), 300);
+    assert_eq!(user_data.property::<i32>("ext", "neg").unwrap(), -1);
+    assert_eq!(user_data.property::<&str>("ext", "name").unwrap(), "knight");
+    assert_eq!(
+        user_data.property::<String>("ext", "name").unwrap(),
+        "knight"
+    );
+    assert!(user_data.property::<bool>("", "flag").unwrap());
+    let nested = user_data.property::<&Properties>("ext", "nested").unwrap();
+    assert_eq!(nested["speed"], PropertyValue::Fixed(1.5));
+    assert_eq!(
+        user_data.property::<&PropertyValue>("ext", "hp").unwrap(),
+        &PropertyValue::U16(300)
+    );
+
+    // Values that do not fit are a type mismatch.
+    let err = user_data.property::<u8>("ext", "hp").unwrap_err();
+    assert_eq!(
+        err,
+        PropertyError::WrongType {
+            extension: "ext".to_string(),
+            name: "hp".to_string(),
+            expected: "u8",
+            found: "u16",
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "Property \"hp\" of extension \"ext\" is a u16, expected u8"
+    );
+    assert!(user_data.property::<u64>("ext", "neg").is_err());
+    assert!(user_data.property::<f64>("ext", "name").is_err());
+    let err = user_data.property::<bool>("", "missing").unwrap_err();
+    assert_eq!(err.to_string(), "Property \"missing\" not found");
+    assert!(matches!(
+        user_data.property::<bool>("other", "flag"),
+        Err(PropertyError::Missing { .. })
+    ));
+}
+
+#[test]
+fn cel_z_index() {
+    let cel = |layer: u16, z_index: i16, color: [u8; 4]| {
+        let data = Bytes::default()
+            .word(layer)
+            .short(0)
+            .short(0)
+            .byte(255)
+            .word(0) // raw image
+            .short(z_index)
+            .zeros(5)
+            .word(1)
+            .word(1)
+            .bytes(&color);
+        chunk(0x2005, data)
+    };
+    let file = |bottom_z_index: i16| {
+        let data = craft_file(
+            1,
+            1,
+            32,
+            &[vec![
+                layer_chunk("Bottom"),
+                layer_chunk("Middle"),
+                layer_chunk("Top"),
+                cel(0, bottom_z_index, COLOR_RED),
+                cel(1, 0, COLOR_GREEN),
+                cel(2, 0, [0, 0, 255, 255]),
+            ]],
+        );
+        AsepriteFile::read(&data[..]).unwrap()
+    };
+    let f = file(2);
+    assert_eq!(f.layer(0).frame(0).z_index(), 2);
+    assert_eq!(f.layer(1).frame(0).z_index(), 0);
+    // Moved above the top layer, since it has the higher z-index.
+    assert_eq!(f.frame(0).image().get_pixel(0, 0).0, COLOR_RED);
+    assert_eq!(write_and_read(&f).layer(0).frame(0).z_index(), 2);
+    // Only above the middle layer.
+    let f = file(1);
+    assert_eq!(f.frame(0).image().get_pixel(0, 0).0, [0, 0, 255, 255]);
+    let f = file(0);
+    assert_eq!(f.frame(0).image().get_pixel(0, 0).0, [0, 0, 255, 255]);
+}
+
+#[test]
+fn grid() {
+    let mut data = craft_file(1, 1, 32, &[vec![layer_chunk("Layer")]]);
+    data[36..38].copy_from_slice(&(-2i16).to_le_bytes());
+    data[38] = 3;
+    data[40] = 8;
+    data[42] = 4;
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    let grid = f.grid();
+    assert_eq!((grid.x(), grid.y()), (-2, 3));
+    assert_eq!((grid.width(), grid.height()), (8, 4));
+    assert_eq!(write_and_read(&f).grid(), grid);
+
+    let mut builder = AsepriteFileBuilder::new(1, 1);
+    builder.add_layer("Layer");
+    builder.add_frame(100);
+    let grid = builder.build().unwrap().grid();
+    assert_eq!(
+        (grid.x(), grid.y(), grid.width(), grid.height()),
+        (0, 0, 16, 16)
+    );
+}
+
+#[test]
+fn pixel_ratio() {
+    let pixels = [COLOR_RED, COLOR_GREEN].concat();
+    let mut data = craft_file(
+        2,
+        1,
+        32,
+        &[vec![layer_chunk("Layer"), raw_cel_chunk(0, 2, 1, &pixels)]],
+    );
+    data[34] = 2;
+    data[35] = 1;
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    assert_eq!(f.pixel_ratio(), (2, 1));
+    assert_eq!(f.frame(0).image().dimensions(), (2, 1));
+    let options = ImageOptions::new().apply_pixel_ratio(true);
+    let image = f.frame(0).image_with_options(&options);
+    assert_eq!(image.dimensions(), (4, 1));
+    let row: Vec<[u8; 4]> = image.pixels().map(|pixel| pixel.0).collect();
+    assert_eq!(row, vec![COLOR_RED, COLOR_RED, COLOR_GREEN, COLOR_GREEN]);
+    // The cache keeps stretched and unstretched images apart.
+    assert_eq!(f.frame(0).image().dimensions(), (2, 1));
+    assert_eq!(write_and_read(&f).pixel_ratio(), (2, 1));
+
+    // Old files leave the ratio zero.
+    data[34] = 0;
+    data[35] = 0;
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    assert_eq!(f.pixel_ratio(), (1, 1));
+}
+
+#[test]
+fn reference_layer() {
+    let reference_layer = Bytes::default()
+        .word(1 | 0x40) // visible, reference layer
+        .word(0)
+        .word(0)
+        .zeros(4)
+        .word(0)
+        .byte(255)
+        .zeros(3)
+        .string("Reference");
+    let bytes = craft_file(
+        1,
+        1,
+        32,
+        &[vec![
+            layer_chunk("Layer"),
+            chunk(0x2004, reference_layer),
+            raw_cel_chunk(0, 1, 1, &COLOR_RED),
+            raw_cel_chunk(1, 1, 1, &COLOR_GREEN),
+        ]],
+    );
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    assert!(!f.layer(0).is_reference());
+    assert!(f.layer(1).is_reference());
+    assert_eq!(f.layer(1).layer_type(), layer::LayerType::Image);
+    assert_eq!(f.frame(0).image().get_pixel(0, 0).0, COLOR_RED);
+    let options = ImageOptions::new().include_reference_layers(true);
+    let image = f.frame(0).image_with_options(&options);
+    assert_eq!(image.get_pixel(0, 0).0, COLOR_GREEN);
+    assert!(write_and_read(&f).layer(1).is_reference());
+}
+
+#[test]
+fn transparent_color_index() {
+    let file = |transparent_index: u8| {
+        let mut data = craft_file(
+            2,
+            1,
+            8,
+            &[vec![
+                palette_chunk(&[COLOR_RED, COLOR_GREEN]),
+                layer_chunk("Layer"),
+                raw_cel_chunk(0, 2, 1, &[0, 1]),
+            ]],
+        );
+        data[28] = transparent_index;
+        AsepriteFile::read(&data[..]).unwrap()
+    };
+    let f = file(1);
+    assert_eq!(f.transparent_color_index(), Some(1));
+    let image = f.frame(0).image();
+    assert_eq!(image.get_pixel(0, 0).0, COLOR_RED);
+    assert_eq!(image.get_pixel(1, 0)[3], 0);
+    assert_eq!(
+        load_test_file("basic-16x16").transparent_color_index(),
+        None
+    );
+
+    // The transparent index may lie beyond the end of the palette.
+    let f = file(5);
+    let image = f.frame(0).image();
+    assert_eq!(image.get_pixel(1, 0).0, COLOR_GREEN);
+    let mut data = Vec::new();
+    f.frame(0).write_indexed_png(&mut data).unwrap();
+    let chunks = png::decode::chunks(&data);
+    let (_, palette) = chunks.iter().find(|(t, _)| *t == b"PLTE").unwrap();
+    assert_eq!(palette.len(), 6 * 3);
+    let (_, trns) = chunks.iter().find(|(t, _)| *t == b"tRNS").unwrap();
+    assert_eq!(trns[5], 0);
+    let decoded = image::load_from_memory(&data).unwrap().to_rgba8();
+    assert_eq!(decoded.get_pixel(1, 0).0, COLOR_GREEN);
+}
+
+#[test]
+fn palette_iter() {
+    let f = load_test_file("palette");
+    let palette = f.palette().unwrap();
+    let iter = palette.iter();
+    assert_eq!(iter.len(), palette.num_colors() as usize);
+    let indices: Vec<u32> = iter.map(|(index, _)| index).collect();
+    assert_eq!(indices, (0..palette.num_colors()).collect::<Vec<_>>());
+    for (index, entry) in palette {
+        assert_eq!(entry.id(), index);
+        assert_eq!(entry.raw_rgba8(), palette.color(index).unwrap().raw_rgba8());
+    }
+    assert_eq!(
+        palette.iter().next_back().unwrap().0,
+        palette.num_colors() - 1
+    );
+}
+
+#[test]
+fn palette_gpl() {
+    let f = load_test_file("palette");
+    let palette = f.palette().unwrap();
+    let mut data = Vec::new();
+    palette.write_gpl(&mut data).unwrap();
+    let text = String::from_utf8(data).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "GIMP Palette");
+    let colors = &lines[lines.iter().position(|line| *line == "#").unwrap() + 1..];
+    assert_eq!(colors.len(), palette.num_colors() as usize);
+    let first = palette.color(0).unwrap().raw_rgba8();
+    assert!(colors[0].starts_with(&format!("{:3} {:3} {:3}", first[0], first[1], first[2])));
+
+    // Translucent colors add an alpha column, gaps are opaque black.
+    let mut palette = ColorPalette::from_colors(&[[255, 0, 0, 255], [0, 128, 255, 64]]);
+    palette.entries.get_mut(&0).unwrap().name = Some("Red\nand more".to_string());
+    let mut entry = palette.entries.remove(&1).unwrap();
+    entry.id = 2;
+    palette.entries.insert(2, entry);
+    let mut data = Vec::new();
+    palette.write_gpl(&mut data).unwrap();
+    assert_eq!(
+        String::from_utf8(data).unwrap(),
+        "GIMP Palette\nChannels: RGBA\n#\n\
+         255   0   0 255\tRed and more\n\
+         \x20 0   0   0 255\tUntitled\n\
+         \x20 0 128 255  64\tUntitled\n"
+    );
+}
+
+#[test]
+fn palette_act_jasc_hex() {
+    let palette = ColorPalette::from_colors(&[[255, 0, 0, 255], [0, 0, 0, 0], [1, 2, 3, 255]]);
+    let rgb = |palette: &ColorPalette| -> Vec<[u8; 4]> {
+        palette.iter().map(|(_, entry)| entry.raw_rgba8()).collect()
+    };
+
+    let mut act = Vec::new();
+    palette.write_act(&mut act).unwrap();
+    assert_eq!(act.len(), 772);
+    assert_eq!(&act[768..], &[0, 3, 0, 1]);
+    let read = ColorPalette::read_act(&act[..]).unwrap();
+    assert_eq!(rgb(&read), rgb(&palette));
+    let read = ColorPalette::read_act(&act[..768]).unwrap();
+    assert_eq!(read.num_colors(), 256);
+    assert_eq!(read.color(1).unwrap().raw_rgba8(), [0, 0, 0, 255]);
+    assert_invalid_input(ColorPalette::read_act(&act[..100]));
+    let large = ColorPalette::from_colors(&[[0, 0, 0, 255]; 257]);
+    assert!(large.write_act(Vec::new()).is_err());
+
+    let mut pal = Vec::new();
+    palette.write_jasc_pal(&mut pal).unwrap();
+    assert_eq!(
+        String::from_utf8(pal.clone()).unwrap(),
+        "JASC-PAL\r\n0100\r\n3\r\n255 0 0\r\n0 0 0\r\n1 2 3\r\n"
+    );
+    let read = ColorPalette::read_jasc_pal(&pal[..]).unwrap();
+    assert_eq!(read.color(1).unwrap().raw_rgba8(), [0, 0, 0, 255]);
+    assert_eq!(read.color(2).unwrap().raw_rgba8(), [1, 2, 3, 255]);
+    let read = ColorPalette::read_jasc_pal(&b"JASC-PAL\n0100\n1\n7 8 9\n"[..]).unwrap();
+    assert_eq!(rgb(&read), vec![[7, 8, 9, 255]]);
+    assert_invalid_input(ColorPalette::read_jasc_pal(
+        &b"JASC-PAL\n0100\n2\n7 8 9\n"[..],
+    ));
+    assert_invalid_input(ColorPalette::read_jasc_pal(&b"GIMP Palette\n"[..]));
+
+    let mut hex = Vec::new();
+    palette.write_hex(&mut hex).unwrap();
+    assert_eq!(
+        String::from_utf8(hex.clone()).unwrap(),
+        "ff0000\n00000000\n010203\n"
+    );
+    let read = ColorPalette::read_hex(&hex[..]).unwrap();
+    assert_eq!(rgb(&read), rgb(&palette));
+    let read = ColorPalette::read_hex(&b"#FFFFFF\r\n\n#00ff0080\n"[..]).unwrap();
+    assert_eq!(rgb(&read), vec![[255, 255, 255, 255], [0, 255, 0, 128]]);
+    assert_invalid_input(ColorPalette::read_hex(&b"fffff\n"[..]));
+    assert_invalid_input(ColorPalette::read_hex(&b"ggffff\n"[..]));
+}
+
+#[test]
+fn palette_nearest() {
+    let palette = ColorPalette::from_colors(&[
+        [0, 0, 0, 255],
+        [255, 0, 0, 255],
+        [0, 0, 0, 0],
+        [60, 0, 255, 255],
+        [0, 120, 0, 255],
+    ]);
+    assert_eq!(palette.nearest([250, 10, 10, 255]), Some(1));
+    assert_eq!(palette.nearest([5, 5, 5, 0]), Some(2));
+    assert_eq!(
+        palette.nearest_by([0, 0, 0, 255], ColorDistance::Rgb),
+        Some(0)
+    );
+    // Blue differences count for less than green ones in the perceptual
+    // metric.
+    let color = [0, 110, 160, 255];
+    assert_eq!(palette.nearest_by(color, ColorDistance::Rgb), Some(3));
+    assert_eq!(
+        palette.nearest_by(color, ColorDistance::Perceptual),
+        Some(4)
+    );
+    assert_eq!(ColorPalette::from_colors(&[]).nearest([0; 4]), None);
+}
+
+#[test]
+fn palette_quantize() {
+    let f = load_test_file("indexed");
+    let palette = f.palette().unwrap();
+    let transparent_index = f.transparent_color_index().unwrap();
+    let image = f.frame(0).image();
+    let options = QuantizeOptions::new().transparent_index(transparent_index);
+    let pixels = palette.quantize_with_options(&image, &options);
+    for (pixel, index) in image.pixels().zip(&pixels) {
+        if pixel.0[3] == 0 {
+            assert_eq!(index.value(), transparent_index);
+        } else {
+            let color = palette.color(index.value() as u32).unwrap().raw_rgba8();
+            assert_eq!(color, pixel.0);
+        }
+    }
+
+    let palette = ColorPalette::from_colors(&[[0, 0, 0, 0], [255, 0, 0, 255], [0, 0, 255, 255]]);
+    let image = image::RgbaImage::from_fn(4, 1, |x, _| match x {
+        0 => image::Rgba([0, 0, 0, 0]),
+        1 => image::Rgba([200, 0, 20, 100]),
+        2 => image::Rgba([10, 0, 240, 255]),
+        _ => image::Rgba([0, 0, 0, 255]),
+    });
+    let values = |pixels: Vec<pixel::Indexed>| -> Vec<u8> {
+        pixels.iter().map(|pixel| pixel.value()).collect()
+    };
+    assert_eq!(values(palette.quantize(&image)), vec![0, 1, 2, 1]);
+    let options = QuantizeOptions::new().alpha_threshold(128);
+    assert_eq!(
+        values(palette.quantize_with_options(&image, &options)),
+        vec![0, 0, 2, 1]
+    );
+    let options = QuantizeOptions::new().transparent_index(2);
+    assert_eq!(
+        values(palette.quantize_with_options(&image, &options)),
+        vec![2, 1, 1, 1]
+    );
+}
+
+#[test]
+fn palette_remap() {
+    let source = ColorPalette::from_colors(&[[0, 0, 0, 0], [255, 0, 0, 255], [0, 0, 255, 255]]);
+    let target = ColorPalette::from_colors(&[[0, 0, 0, 0], [0, 0, 250, 255], [255, 0, 0, 255]]);
+    let remap = source.remap_to(&target, RemapMatch::Exact);
+    assert_eq!(remap.get(0), Some(0));
+    assert_eq!(remap.get(1), Some(2));
+    assert_eq!(remap.get(2), None);
+    assert_eq!(remap.get(3), None);
+    assert!(!remap.is_complete());
+    assert_eq!(remap.map(pixel::Indexed::new(2)).value(), 2);
+    let remap = source.remap_to(&target, RemapMatch::Nearest(ColorDistance::Rgb));
+    assert_eq!(remap.get(2), Some(1));
+    assert!(remap.is_complete());
+
+    // Swapping two colors and their indices keeps the image.
+    let mut f = load_test_file("indexed");
+    let transparent_index = f.transparent_color_index().unwrap() as u32;
+    let before = f.frame(0).image();
+    let colors: Vec<[u8; 4]> = f
+        .palette()
+        .unwrap()
+        .iter()
+        .map(|(_, entry)| entry.raw_rgba8())
+        .collect();
+    let (a, b) = (0..colors.len() as u32)
+        .filter(|&index| index != transparent_index)
+        .zip((0..colors.len() as u32).rev())
+        .find(|&(a, b)| b != transparent_index && colors[a as usize] != colors[b as usize])
+        .unwrap();
+    let mut swapped = colors.clone();
+    swapped.swap(a as usize, b as usize);
+    let target = ColorPalette::from_colors(&swapped);
+    let remap = f.palette().unwrap().remap_to(&target, RemapMatch::Exact);
+    let indices = f.frame(0).indexed_image().unwrap();
+    f.apply_palette_remap(&remap).unwrap();
+    assert_ne!(f.frame(0).indexed_image().unwrap(), indices);
+    for (index, color) in swapped.iter().enumerate() {
+        f.set_palette_color(index as u32, *color).unwrap();
+    }
+    assert_eq!(f.frame(0).image(), before);
+    let f = write_and_read(&f);
+    assert_eq!(f.frame(0).image(), before);
+
+    let mut f = load_test_file("basic-16x16");
+    let palette = ColorPalette::from_colors(&[[0, 0, 0, 255]]);
+    let remap = palette.remap_to(&palette, RemapMatch::Exact);
+    assert!(matches!(
+        f.apply_palette_remap(&remap),
+        Err(AsepriteParseError::UnsupportedFeature(_))
+    ));
+}
+
+#[test]
+fn sorted_palette() {
+    let f = load_test_file("indexed");
+    let transparent_index = f.transparent_color_index().unwrap() as u32;
+    let before = f.frame(0).image();
+    let luminance =
+        |rgba: [u8; 4]| 2126 * rgba[0] as u32 + 7152 * rgba[1] as u32 + 722 * rgba[2] as u32;
+    for order in [
+        PaletteOrder::Hue,
+        PaletteOrder::Luminance,
+        PaletteOrder::Usage,
+    ] {
+        let mut f = load_test_file("indexed");
+        let (palette, remap) = f.sorted_palette(order).unwrap();
+        assert!(remap.is_complete());
+        assert_eq!(palette.num_colors(), f.palette().unwrap().num_colors());
+        assert_eq!(
+            remap.get(transparent_index as u8),
+            Some(transparent_index as u8)
+        );
+        f.apply_palette_remap(&remap).unwrap();
+        for (index, entry) in &palette {
+            f.set_palette_color(index, entry.raw_rgba8()).unwrap();
+        }
+        assert_eq!(f.frame(0).image(), before);
+
+        let colors: Vec<[u8; 4]> = palette
+            .iter()
+            .filter(|(index, _)| *index != transparent_index)
+            .map(|(_, entry)| entry.raw_rgba8())
+            .collect();
+        if order == PaletteOrder::Luminance {
+            assert!(colors
+                .windows(2)
+                .all(|w| luminance(w[0]) <= luminance(w[1])));
+        }
+        if order == PaletteOrder::Hue {
+            let is_gray = |rgba: &[u8; 4]| rgba[0] == rgba[1] && rgba[1] == rgba[2];
+            let grays = colors.iter().take_while(|rgba| is_gray(rgba)).count();
+            assert!(colors[grays..].iter().all(|rgba| !is_gray(rgba)));
+        }
+        if order == PaletteOrder::Usage {
+            let mut usage = vec![0; 256];
+            for frame in 0..f.num_frames() {
+                for layer in 0..f.num_layers() {
+                    let indices = f.frame(frame).layer(layer).indexed_image().unwrap();
+                    for pixel in indices.pixels() {
+                        usage[pixel.value() as usize] += 1;
+                    }
+                }
+            }
+            let used = |index: u32| usage[index as usize];
+            let first = (0..palette.num_colors())
+                .find(|&index| index != transparent_index)
+                .unwrap();
+            assert!((0..palette.num_colors())
+                .filter(|&index| index != transparent_index)
+                .all(|index| used(index) <= used(first)));
+        }
+    }
+
+    let f = load_test_file("basic-16x16");
+    assert!(f.sorted_palette(PaletteOrder::Luminance).is_ok());
+    assert!(matches!(
+        f.sorted_palette(PaletteOrder::Usage),
+        Err(AsepriteParseError::UnsupportedFeature(_))
+    ));
+}
+
+#[test]
+fn sorted_sparse_palette() {
+    let mut f = load_test_file("indexed");
+    let transparent_index = 10;
+    f.pixel_format = PixelFormat::Indexed {
+        transparent_color_index: transparent_index as u8,
+    };
+    let palette = f.palette.as_mut().unwrap();
+    palette
+        .entries
+        .retain(|index, _| *index < 2 || *index == transparent_index);
+    let (sorted, remap) = f.sorted_palette(PaletteOrder::Luminance).unwrap();
+    assert_eq!(sorted.num_colors(), 3);
+    assert!(sorted.color(2).is_none());
+    assert_eq!(
+        sorted
+            .color(transparent_index)
+            .map(|entry| entry.raw_rgba8()),
+        f.palette()
+            .unwrap()
+            .color(transparent_index)
+            .map(|entry| entry.raw_rgba8())
+    );
+    assert_eq!(
+        remap.get(transparent_index as u8),
+        Some(transparent_index as u8)
+    );
+    assert!(remap.is_complete());
+}
+
+#[test]
+fn palette_to_rgba_vec() {
+    let f = load_test_file("palette");
+    let palette = f.palette().unwrap();
+    let colors = palette.to_rgba_vec();
+    assert_eq!(colors.len(), palette.num_colors() as usize);
+    for (index, entry) in palette {
+        assert_eq!(colors[index as usize], entry.raw_rgba8());
+    }
+
+    let mut palette = ColorPalette::from_colors(&[[255, 0, 0, 255], [0, 255, 0, 255]]);
+    let mut entry = palette.entries.remove(&1).unwrap();
+    entry.id = 3;
+    palette.entries.insert(3, entry);
+    assert_eq!(
+        palette.to_rgba_vec(),
+        vec![[255, 0, 0, 255], [0; 4], [0; 4], [0, 255, 0, 255]]
+    );
+    assert!(ColorPalette::from_colors(&[]).to_rgba_vec().is_empty());
+}
+
+#[test]
+fn palette_index_out_of_range() {
+    // A single entry at an index far beyond any real palette.
+    let data = Bytes::default()
+        .dword(1)
+        .dword(0xFFFF_FFF0)
+        .dword(0xFFFF_FFF0)
+        .zeros(8)
+        .word(0)
+        .bytes(&COLOR_GREEN);
+    assert_invalid_input(palette::parse_chunk(&data.0));
+    let bytes = craft_file(1, 1, 32, &[vec![chunk(0x2019, data)]]);
+    assert_invalid_input(AsepriteFile::read(&bytes[..]));
+
+    // The last index that is still allowed.
+    let data = Bytes::default()
+        .dword(0x10000)
+        .dword(0xFFFF)
+        .dword(0xFFFF)
+        .zeros(8)
+        .word(0)
+        .bytes(&COLOR_GREEN);
+    let palette = palette::parse_chunk(&data.0).unwrap();
+    assert_eq!(palette.to_rgba_vec().len(), 0x10000);
+
+    // Old palette chunks that skip past the last index.
+    let skips = Bytes::default()
+        .word(257)
+        .bytes(&[255, 1, 0, 0, 0].repeat(257));
+    assert_invalid_input(palette::apply_old_chunk(&skips.0, false, &mut None));
+}
+
+#[test]
+fn slice_lookup_by_name() {
+    let slice = |name: &str, width: u32| Slice {
+        name: name.to_owned(),
+        keys: vec![SliceKey {
+            from_frame: 0,
+            origin: SliceOrigin { x: 0, y: 0 },
+            size: SliceSize { width, height: 1 },
+            slice9: None,
+            pivot: None,
+        }],
+        user_data: None,
+    };
+    let mut builder = AsepriteFileBuilder::new(4, 4);
+    builder.add_layer("Layer");
+    builder.add_frame(100);
+    builder.add_slice(slice("icon/b", 1));
+    builder.add_slice(slice("button", 2));
+    builder.add_slice(slice("icon/a", 3));
+    builder.add_slice(slice("icon/b", 4));
+    builder.add_slice(slice("icons", 5));
+    let f = write_and_read(&builder.build().unwrap());
+
+    let width = |slice: &Slice| slice.keys[0].size.width;
+    assert_eq!(f.slice("icon/b").map(width), Some(1));
+    assert_eq!(f.slice("button").map(width), Some(2));
+    assert!(f.slice("icon").is_none());
+    assert!(f.slice("zzz").is_none());
+    let widths: Vec<u32> = f.slices_named("icon/").map(width).collect();
+    assert_eq!(widths, vec![3, 1, 4]);
+    assert_eq!(f.slices_named("icon").count(), 4);
+    assert_eq!(f.slices_named("").count(), 5);
+    assert_eq!(f.slices_named("x").count(), 0);
+}
+
+#[test]
+fn slice_key_for_frame() {
+    let key = |from_frame: u32| SliceKey {
+        from_frame,
+        origin: SliceOrigin {
+            x: from_frame as i32,
+            y: 0,
+        },
+        size: SliceSize {
+            width: 1,
+            height: 1,
+        },
+        slice9: None,
+        pivot: None,
+    };
+    // Keys are not necessarily sorted.
+    let slice = Slice {
+        name: "moving".to_owned(),
+        keys: vec![key(3), key(0), key(1)],
+        user_data: None,
+    };
+    let from = |frame: u32| slice.key_for_frame(frame).map(|key| key.from_frame);
+    assert_eq!(from(0), Some(0));
+    assert_eq!(from(1), Some(1));
+    assert_eq!(from(2), Some(1));
+    assert_eq!(from(3), Some(3));
+    assert_eq!(from(100), Some(3));
+
+    let late = Slice {
+        name: "late".to_owned(),
+        keys: vec![key(2)],
+        user_data: None,
+    };
+    assert!(late.key_for_frame(1).is_none());
+    assert_eq!(late.key_for_frame(2).unwrap().origin.x, 2);
+}
+
+#[test]
+fn slice_bounds() {
+    let key = |from_frame: u32, width: u32| SliceKey {
+        from_frame,
+        origin: SliceOrigin { x: -2, y: 3 },
+        size: SliceSize { width, height: 4 },
+        slice9: None,
+        pivot: Some(SlicePivot { x: 1, y: 2 }),
+    };
+    let slice = Slice {
+        name: "hitbox".to_owned(),
+        keys: vec![key(1, 5), key(3, 0)],
+        user_data: None,
+    };
+    assert_eq!(slice.keys[0].bounds(), (-2, 3, 5, 4));
+    assert_eq!(slice.keys[0].pivot_position(), Some((-1, 5)));
+    assert_eq!(slice.bounds_at(0), None);
+    assert_eq!(slice.bounds_at(2), Some((-2, 3, 5, 4)));
+    // Hidden from frame 3 on.
+    assert_eq!(slice.bounds_at(3), None);
+    let mut unpivoted = key(0, 1);
+    unpivoted.pivot = None;
+    assert_eq!(unpivoted.pivot_position(), None);
+}
+
+#[test]
+fn slice_image() {
+    let f = load_test_file("slice");
+    let (x, y, width, height) = f.slice("Slice 1").unwrap().bounds_at(0).unwrap();
+    let image = f.slice_image(0, "Slice 1").unwrap();
+    assert_eq!(image.dimensions(), (width, height));
+    let frame = f.frame(0).image();
+    for (dx, dy, pixel) in image.enumerate_pixels() {
+        assert_eq!(pixel, frame.get_pixel(x as u32 + dx, y as u32 + dy));
+    }
+    assert_invalid_input(f.slice_image(0, "Missing"));
+    assert_invalid_input(f.slice_image(f.num_frames(), "Slice 1"));
+
+    // Slices may reach beyond the canvas and be hidden.
+    let red = image::RgbaImage::from_pixel(4, 4, image::Rgba(COLOR_RED));
+    let key = |from_frame: u32, size: u32| SliceKey {
+        from_frame,
+        origin: SliceOrigin { x: -1, y: 2 },
+        size: SliceSize {
+            width: size,
+            height: size,
+        },
+        slice9: None,
+        pivot: None,
+    };
+    let mut builder = AsepriteFileBuilder::new(4, 4);
+    builder.add_layer("Layer");
+    builder.add_frame(100);
+    builder.add_frame(100);
+    builder.add_cel(0, 0, (0, 0), &red);
+    builder.add_slice(Slice {
+        name: "edge".to_owned(),
+        keys: vec![key(0, 3), key(1, 0)],
+        user_data: None,
+    });
+    let f = builder.build().unwrap();
+    let image = f.slice_image(0, "edge").unwrap();
+    assert_eq!(image.dimensions(), (3, 3));
+    assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 0]);
+    assert_eq!(image.get_pixel(1, 1).0, COLOR_RED);
+    assert_eq!(image.get_pixel(2, 2).0, [0, 0, 0, 0]);
+    assert_invalid_input(f.slice_image(1, "edge"));
+}
+
+#[test]
+fn nine_slice_image() {
+    // Each pixel's color encodes its position.
+    let image = image::RgbaImage::from_fn(6, 6, |x, y| image::Rgba([x as u8, y as u8, 0, 255]));
+    let key = |slice9: Option<Slice9>| SliceKey {
+        from_frame: 0,
+        origin: SliceOrigin { x: 0, y: 0 },
+        size: SliceSize {
+            width: 6,
+            height: 6,
+        },
+        slice9,
+        pivot: None,
+    };
+    let center = Slice9 {
+        center_x: 2,
+        center_y: 2,
+        center_width: 2,
+        center_height: 2,
+    };
+    let mut builder = AsepriteFileBuilder::new(6, 6);
+    builder.add_layer("Layer");
+    builder.add_frame(100);
+    builder.add_cel(0, 0, (0, 0), &image);
+    builder.add_slice(Slice {
+        name: "panel".to_owned(),
+        keys: vec![key(Some(center))],
+        user_data: None,
+    });
+    builder.add_slice(Slice {
+        name: "plain".to_owned(),
+        keys: vec![key(None)],
+        user_data: None,
+    });
+    let f = builder.build().unwrap();
+    let source = |image: &image::RgbaImage, x: u32, y: u32| {
+        let pixel = image.get_pixel(x, y).0;
+        (pixel[0], pixel[1])
+    };
+
+    let panel = f.nine_slice_image(0, "panel", (10, 8)).unwrap();
+    assert_eq!(panel.dimensions(), (10, 8));
+    // Corners keep their pixels.
+    assert_eq!(source(&panel, 0, 0), (0, 0));
+    assert_eq!(source(&panel, 1, 1), (1, 1));
+    assert_eq!(source(&panel, 9, 7), (5, 5));
+    assert_eq!(source(&panel, 8, 0), (4, 0));
+    // The middle is stretched: 6 target columns show 2 source columns.
+    let row: Vec<u8> = (0..10).map(|x| source(&panel, x, 0).0).collect();
+    assert_eq!(row, vec![0, 1, 2, 2, 2, 3, 3, 3, 4, 5]);
+    assert_eq!(source(&panel, 4, 4), (2, 3));
+
+    let options = NineSliceOptions::new()
+        .edges(NineSliceFill::Tile)
+        .center(NineSliceFill::Stretch);
+    let panel = f
+        .nine_slice_image_with_options(0, "panel", (10, 8), &options)
+        .unwrap();
+    let row: Vec<u8> = (0..10).map(|x| source(&panel, x, 0).0).collect();
+    assert_eq!(row, vec![0, 1, 2, 3, 2, 3, 2, 3, 4, 5]);
+    let row: Vec<u8> = (0..10).map(|x| source(&panel, x, 2).0).collect();
+    assert_eq!(row, vec![0, 1, 2, 2, 2, 3, 3, 3, 4, 5]);
+    let options = NineSliceOptions::new().center(NineSliceFill::Tile);
+    let panel = f
+        .nine_slice_image_with_options(0, "panel", (10, 8), &options)
+        .unwrap();
+    assert_eq!(source(&panel, 4, 2), (2, 2));
+    assert_eq!(source(&panel, 5, 5), (3, 3));
+    assert_eq!(source(&panel, 5, 0), (3, 0));
+
+    // Targets smaller than the corners shrink them.
+    let small = f.nine_slice_image(0, "panel", (2, 2)).unwrap();
+    assert_eq!(source(&small, 0, 0), (0, 0));
+    assert_eq!(source(&small, 1, 1), (4, 4));
+
+    assert_invalid_input(f.nine_slice_image(0, "plain", (10, 10)));
+    assert_invalid_input(f.nine_slice_image(0, "missing", (10, 10)));
+}
+
+#[test]
+fn slices_at() {
+    let f = load_test_file("slice");
+    let names = |x: i32, y: i32| -> Vec<&str> {
+        f.slices_at(0, x, y)
+            .map(|slice| slice.name.as_str())
+            .collect()
+    };
+    // "Slice 1" covers (2, 2) to (13, 13).
+    assert_eq!(names(2, 2), vec!["Slice 1"]);
+    assert_eq!(names(13, 13), vec!["Slice 1"]);
+    assert!(names(1, 5).is_empty());
+    assert!(names(14, 5).is_empty());
+    assert!(names(-5, -5).is_empty());
+
+    let key = |from_frame: u32, x: i32, size: u32| SliceKey {
+        from_frame,
+        origin: SliceOrigin { x, y: 0 },
+        size: SliceSize {
+            width: size,
+            height: size,
+        },
+        slice9: None,
+        pivot: None,
+    };
+    let mut builder = AsepriteFileBuilder::new(4, 4);
+    builder.add_layer("Layer");
+    for _ in 0..3 {
+        builder.add_frame(100);
+    }
+    builder.add_slice(Slice {
+        name: "big".to_owned(),
+        keys: vec![key(0, -2, 4), key(1, 0, 0)],
+        user_data: None,
+    });
+    builder.add_slice(Slice {
+        name: "late".to_owned(),
+        keys: vec![key(2, 1, 1)],
+        user_data: None,
+    });
+    let f = builder.build().unwrap();
+    let names = |frame: u32, x: i32, y: i32| -> Vec<&str> {
+        f.slices_at(frame, x, y)
+            .map(|slice| slice.name.as_str())
+            .collect()
+    };
+    assert_eq!(names(0, -1, 1), vec!["big"]);
+    assert!(names(0, 2, 1).is_empty());
+    // "big" is hidden from frame 1 on, "late" only exists from frame 2.
+    assert!(names(1, -1, 1).is_empty());
+    assert!(names(1, 1, 0).is_empty());
+    assert_eq!(names(2, 1, 0), vec!["late"]);
+}
+
+#[test]
+fn user_data_slices() {
+    let slice_chunk = |name: &str| {
+        let data = Bytes::default()
+            .dword(1) // number of keys
+            .dword(0) // flags
+            .dword(0)
+            .string(name)
+            .dword(0) // frame
+            .dword(1u32) // x
+            .dword(2u32) // y
+            .dword(3) // width
+            .dword(4); // height
+        chunk(0x2022, data)
+    };
+    let text_and_color = Bytes::default()
+        .dword(1 | 2)
+        .string("hitbox")
+        .bytes(&[10, 20, 30, 255]);
+    let color = Bytes::default().dword(2).bytes(&[0, 0, 255, 128]);
+    let data = craft_file(
+        4,
+        4,
+        32,
+        &[vec![
+            layer_chunk("Layer"),
+            slice_chunk("Text"),
+            chunk(0x2020, text_and_color),
+            slice_chunk("Plain"),
+            slice_chunk("Color"),
+            chunk(0x2020, color),
+        ]],
+    );
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    let user_data = |name: &str| f.slice(name).unwrap().user_data.clone();
+    let text = user_data("Text").unwrap();
+    assert_eq!(text.text.as_deref(), Some("hitbox"));
+    assert_eq!(text.color, Some(image::Rgba([10, 20, 30, 255])));
+    assert!(user_data("Plain").is_none());
+    let color = user_data("Color").unwrap();
+    assert_eq!(color.text, None);
+    assert_eq!(color.color, Some(image::Rgba([0, 0, 255, 128])));
+    // The layer keeps no user data of its own.
+    assert!(f.layer(0).user_data().is_none());
+
+    let f = write_and_read(&f);
+    assert_eq!(
+        f.slice("Text")
+            .unwrap()
+            .user_data
+            .as_ref()
+            .unwrap()
+            .text
+            .as_deref(),
+        Some("hitbox")
+    );
+    assert!(f.slice("Plain").unwrap().user_data.is_none());
+    assert_eq!(
+        f.slice("Color").unwrap().user_data.as_ref().unwrap().color,
+        Some(image::Rgba([0, 0, 255, 128]))
+    );
+
+    // The test file's slice has a color.
+    let f = load_test_file("slice");
+    let user_data = f.slice("Slice 1").unwrap().user_data.as_ref().unwrap();
+    assert_eq!(user_data.color, Some(image::Rgba([0, 0, 255, 255])));
+}
+
+#[test]
+fn layer_tree() {
+    let ids = |layers: Vec<Layer>| -> Vec<u32> { layers.iter().map(|l| l.id()).collect() };
+    let f = load_test_file("layers_and_tags");
+    assert_eq!(ids(f.root_layers().collect()), vec![0, 1, 2, 3]);
+    let group = f.layer(3);
+    assert_eq!(group.layer_type(), layer::LayerType::Group);
+    assert_eq!(ids(group.children().collect()), vec![4, 5]);
+    assert!(f.layer(1).children().next().is_none());
+    assert_eq!(f.layer(5).parent().map(|p| p.id()), Some(3));
+
+    // Group "A" holds "B", which holds "C"; "D" is at the top level again.
+    let data = craft_file(
+        1,
+        1,
+        32,
+        &[vec![
+            nested_layer_chunk("A", 1, 0),
+            nested_layer_chunk("B", 1, 1),
+            nested_layer_chunk("C", 0, 2),
+            nested_layer_chunk("A2", 0, 1),
+            nested_layer_chunk("D", 0, 0),
+        ]],
+    );
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    assert_eq!(ids(f.root_layers().collect()), vec![0, 4]);
+    assert_eq!(ids(f.layer(0).children().collect()), vec![1, 3]);
+    assert_eq!(ids(f.layer(1).children().collect()), vec![2]);
+    assert_eq!(f.layer(2).path(), "A/B/C");
+    let children: Vec<Layer> = f.layer(0).children().collect();
+    assert_eq!(children[0].parent().unwrap().name(), "A");
+}
+
+#[test]
+fn layer_by_path() {
+    let data = craft_file(
+        1,
+        1,
+        32,
+        &[vec![
+            nested_layer_chunk("UI", 1, 0),
+            nested_layer_chunk("Buttons", 1, 1),
+            nested_layer_chunk("Hover", 0, 2),
+            nested_layer_chunk("Buttons/Hover", 0, 1),
+            nested_layer_chunk("Hover", 0, 0),
+            nested_layer_chunk("UI", 1, 0),
+            nested_layer_chunk("Icons", 0, 1),
+        ]],
+    );
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    let id = |path: &str| f.layer_by_path(path).map(|layer| layer.id());
+    assert_eq!(id("UI/Buttons/Hover"), Some(2));
+    assert_eq!(id("UI/Buttons"), Some(1));
+    assert_eq!(id("Hover"), Some(4));
+    assert_eq!(id("UI"), Some(0));
+    // The second group with the same name is searched too.
+    assert_eq!(id("UI/Icons"), Some(6));
+    assert_eq!(id("UI/Buttons/Hover/X"), None);
+    assert_eq!(id("UI/Butt"), None);
+    assert_eq!(id("Buttons"), None);
+    assert_eq!(id(""), None);
+    for layer in f.layers() {
+        let found = f.layer_by_path(&layer.path()).unwrap();
+        assert_eq!(found.path(), layer.path());
+    }
+
+    let images: Vec<u32> = f
+        .layers_matching(|layer| layer.layer_type() == layer::LayerType::Image)
+        .map(|layer| layer.id())
+        .collect();
+    assert_eq!(images, vec![2, 3, 4, 6]);
+    assert_eq!(f.layers_matching(|layer| layer.name() == "UI").count(), 2);
+}
+
+#[test]
+fn image_with_layers() {
+    let (red, green, blue) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]);
+    let row = |pixels: [[u8; 4]; 3]| -> Vec<u8> { pixels.concat() };
+    let clear = [0, 0, 0, 0];
+    let data = craft_file(
+        3,
+        1,
+        32,
+        &[vec![
+            nested_layer_chunk("Body", 1, 0),
+            nested_layer_chunk("Arm", 0, 1),
+            nested_layer_chunk("guide", 0, 0),
+            nested_layer_chunk("Leg", 0, 0),
+            raw_cel_chunk(1, 3, 1, &row([red, clear, clear])),
+            raw_cel_chunk(2, 3, 1, &row([clear, green, clear])),
+            raw_cel_chunk(3, 3, 1, &row([clear, clear, blue])),
+        ]],
+    );
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    let pixels =
+        |filter: LayerFilter| -> Vec<u8> { f.frame(0).image_with_layers(&filter).into_raw() };
+    assert_eq!(
+        pixels(LayerFilter::predicate(|_| true)),
+        f.frame(0).image().into_raw()
+    );
+    // Selecting a group selects the layers inside of it.
+    assert_eq!(pixels(LayerFilter::name("Body")), row([red, clear, clear]));
+    assert_eq!(
+        pixels(LayerFilter::path("Body/Arm")),
+        row([red, clear, clear])
+    );
+    assert_eq!(pixels(LayerFilter::path("Arm")), row([clear; 3]));
+    assert_eq!(pixels(LayerFilter::index(3)), row([clear, clear, blue]));
+    assert_eq!(
+        pixels(LayerFilter::name("Leg").or(LayerFilter::index(2))),
+        row([clear, green, blue])
+    );
+    assert_eq!(
+        pixels(LayerFilter::excluding(LayerFilter::name("guide"))),
+        row([red, clear, blue])
+    );
+    assert_eq!(
+        pixels(LayerFilter::predicate(|layer| layer
+            .name()
+            .starts_with('A'))),
+        row([red, clear, clear])
+    );
+}
+
+#[test]
+fn group_image() {
+    let layer = |name: &str, flags: u16, layer_type: u16, level: u16| {
+        let data = Bytes::default()
+            .word(flags)
+            .word(layer_type)
+            .word(level)
+            .zeros(4)
+            .word(0) // blend mode
+            .byte(255)
+            .zeros(3)
+            .string(name);
+        chunk(0x2004, data)
+    };
+    let cel = |layer: u16, z_index: i16, color: [u8; 4]| {
+        let data = Bytes::default()
+            .word(layer)
+            .short(0)
+            .short(0)
+            .byte(255)
+            .word(0) // raw image
+            .short(z_index)
+            .zeros(5)
+            .word(1)
+            .word(1)
+            .bytes(&color);
+        chunk(0x2005, data)
+    };
+    let data = craft_file(
+        1,
+        1,
+        32,
+        &[vec![
+            layer("Gear", 0, 1, 0),
+            layer("Base", 1, 0, 1),
+            layer("Shade", 1, 0, 1),
+            layer("Hidden", 0, 0, 1),
+            cel(1, 1, COLOR_RED),
+            cel(2, 0, [0, 0, 255, 255]),
+            cel(3, 0, COLOR_GREEN),
+        ]],
+    );
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    // The group is hidden, so it is not part of the frame.
+    assert_eq!(f.frame(0).image().into_raw(), vec![0, 0, 0, 0]);
+    // "Base" is moved above "Shade" by its z-index, and "Hidden" is skipped.
+    assert_eq!(f.frame(0).group_image(0).get_pixel(0, 0).0, COLOR_RED);
+}
+
+#[test]
+fn group_image_matches_frame_image() {
+    let f = load_test_file("layers_and_tags");
+    let group = f.layer_by_name("Group 1").unwrap().id();
+    assert!(!f.frame(1).group_image(group).pixels().all(is_transparent));
+    let split = f.split_layers();
+    for frame in 0..f.num_frames() {
+        let image = f.frame(frame).group_image(group);
+        let only_group = f.frame(frame).image_with_layers(&LayerFilter::index(group));
+        assert_eq!(image, only_group);
+        let (_, _, split_image) = split
+            .iter()
+            .find(|(name, split_frame, _)| *name == "Group 1" && *split_frame == frame)
+            .unwrap();
+        assert_eq!(&image, split_image);
+    }
+}
+
+#[test]
+#[should_panic(expected = "not a group")]
+fn group_image_of_image_layer() {
+    let f = load_test_file("layers_and_tags");
+    f.frame(0).group_image(1);
+}
+
+#[test]
+fn split_layers() {
+    let f = load_test_file("layers_and_tags");
+    let images = f.split_layers();
+    let names: Vec<(&str, u32)> = images
+        .iter()
+        .map(|(name, frame, _)| (*name, *frame))
+        .collect();
+    let expected: Vec<(&str, u32)> = (0..f.num_frames())
+        .flat_map(|frame| vec![("Layer 1", frame), ("Group 1", frame)])
+        .collect();
+    assert_eq!(names, expected);
+    for (name, frame, image) in &images {
+        let expected = match *name {
+            "Layer 1" => f.frame(*frame).layer(1).image(),
+            _ => f.frame(*frame).group_image(3),
+        };
+        assert_eq!(image, &expected);
+    }
+}
+
+#[test]
+fn composite_options() {
+    let data = craft_file(
+        1,
+        1,
+        32,
+        &[vec![
+            layer_chunk("Back"),
+            layer_chunk("Cape"),
+            raw_cel_chunk(0, 1, 1, &[0, 0, 255, 255]),
+            raw_cel_chunk(1, 1, 1, &[255, 255, 255, 255]),
+        ]],
+    );
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    let pixel = |options: CompositeOptions| -> Vec<u8> {
+        f.frame(0).image_with_composite_options(&options).into_raw()
+    };
+    assert_eq!(
+        pixel(CompositeOptions::new()),
+        f.frame(0).image().into_raw()
+    );
+    assert_eq!(
+        pixel(CompositeOptions::new().layer_tint(1, [255, 0, 0])),
+        vec![255, 0, 0, 255]
+    );
+    assert_eq!(
+        pixel(CompositeOptions::new().layer_opacity(1, 0)),
+        vec![0, 0, 255, 255]
+    );
+    assert_eq!(
+        pixel(CompositeOptions::new().layer_opacity(1, 128)),
+        vec![128, 128, 255, 255]
+    );
+    assert_eq!(
+        pixel(
+            CompositeOptions::new()
+                .layer_opacity(1, 128)
+                .layer_tint(1, [0, 0, 0])
+        ),
+        vec![0, 0, 127, 255]
+    );
+    // The file is unchanged.
+    assert_eq!(f.frame(0).image().into_raw(), vec![255, 255, 255, 255]);
+}
+
+#[test]
+fn composite_blend_fn() {
+    let data = craft_file(
+        1,
+        1,
+        32,
+        &[vec![
+            layer_chunk("Back"),
+            layer_chunk("Glow"),
+            raw_cel_chunk(0, 1, 1, &[0, 0, 255, 255]),
+            raw_cel_chunk(1, 1, 1, &[10, 20, 30, 255]),
+        ]],
+    );
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    // Records the backdrop's blue, the source's green and the opacity.
+    let options = CompositeOptions::new()
+        .layer_blend_fn(1, |backdrop, src, opacity| {
+            image::Rgba([backdrop[2], src[1], opacity, 255])
+        })
+        .layer_opacity(1, 128);
+    assert_eq!(
+        f.frame(0).image_with_composite_options(&options).into_raw(),
+        vec![255, 20, 128, 255]
+    );
+    // Other layers keep their blend mode.
+    let options = CompositeOptions::new().layer_blend_fn(0, |_, _, _| image::Rgba([1, 2, 3, 4]));
+    assert_eq!(
+        f.frame(0).image_with_composite_options(&options).into_raw(),
+        vec![10, 20, 30, 255]
+    );
+    let options = options.layer_opacity(1, 0);
+    assert_eq!(
+        f.frame(0).image_with_composite_options(&options).into_raw(),
+        vec![1, 2, 3, 4]
+    );
+}
+
+#[test]
+fn background_color() {
+    let data = craft_file(
+        2,
+        1,
+        32,
+        &[vec![
+            layer_chunk("Layer"),
+            raw_cel_chunk(0, 2, 1, &[255, 0, 0, 128, 0, 0, 0, 0]),
+        ]],
+    );
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    let options = ImageOptions::new().background_color([0, 0, 255]);
+    let image = f.frame(0).image_with_options(&options);
+    assert_eq!(image.get_pixel(0, 0).0, [128, 0, 127, 255]);
+    assert_eq!(image.get_pixel(1, 0).0, [0, 0, 255, 255]);
+    // Images with and without a background are cached separately.
+    assert_eq!(f.frame(0).image().get_pixel(1, 0).0, [0, 0, 0, 0]);
+
+    let f = load_test_file("indexed");
+    let palette = f.palette().unwrap();
+    let [r, g, b, _] = palette.color(1).unwrap().raw_rgba8();
+    let options = ImageOptions::new().background_index(1);
+    let plain = f.frame(0).image();
+    let image = f.frame(0).image_with_options(&options);
+    for (plain, pixel) in plain.pixels().zip(image.pixels()) {
+        assert_eq!(pixel.0[3], 255);
+        if plain.0[3] == 0 {
+            assert_eq!(pixel.0, [r, g, b, 255]);
+        }
+    }
+    // Without such a palette entry, there is no background.
+    assert!(palette.num_colors() < 256 && palette.color(255).is_none());
+    let options = ImageOptions::new().background_index(255);
+    assert_eq!(f.frame(0).image_with_options(&options), plain);
+}
+
+#[test]
+fn premultiply_alpha() {
+    let data = craft_file(
+        2,
+        1,
+        32,
+        &[vec![
+            layer_chunk("Layer"),
+            raw_cel_chunk(0, 2, 1, &[255, 100, 0, 128, 40, 50, 60, 255]),
+        ]],
+    );
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    let expected = vec![128, 50, 0, 128, 40, 50, 60, 255];
+    let options = ImageOptions::new().premultiply_alpha(true);
+    assert_eq!(f.frame(0).image_with_options(&options).into_raw(), expected);
+    assert_eq!(
+        f.frame(0).layer(0).image_premultiplied().into_raw(),
+        expected
+    );
+    assert_eq!(
+        f.frame(0).image().into_raw(),
+        vec![255, 100, 0, 128, 40, 50, 60, 255]
+    );
+}
+
+#[test]
+fn cel_is_empty() {
+    // Regression test: `Cel::is_empty` used to return `true` for cels with
+    // content and `false` for empty ones.
+    let f = load_test_file("layers_and_tags");
+    let frame = f.frame(1);
+    assert!(!frame.layer(4).is_empty());
+    assert!(frame.layer(5).is_empty());
+    // Group layers never have cels.
+    assert!(frame.layer(3).is_empty());
+    for frame in 0..f.num_frames() {
+        let frame = f.frame(frame);
+        for layer in 0..f.num_layers() {
+            let cel = frame.layer(layer);
+            let has_pixels = cel.image().pixels().any(|pixel| pixel.0[3] != 0);
+            assert_eq!(cel.is_empty(), !has_pixels);
+        }
+    }
+}
+
+#[test]
+fn frame_duration_from_header_speed() {
+    let mut bytes = craft_file(2, 2, 32, &[vec![layer_chunk("Layer")], vec![]]);
+    // Set the speed in the header to 50ms and the duration of the first
+    // frame to 0.
+    bytes[18..20].copy_from_slice(&50u16.to_le_bytes());
+    bytes[136..138].copy_from_slice(&0u16.to_le_bytes());
+    let f = AsepriteFile::read(&bytes[..]).unwrap();
+    assert_eq!(f.frame(0).duration(), 50);
+    assert_eq!(f.frame(1).duration(), 100);
+}
+
+#[test]
+fn trailing_data() {
+    let mut data = std::fs::read("./tests/data/basic-16x16.aseprite").unwrap();
+    let end = data.len() as u64;
+    let garbage: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+    data.extend_from_slice(&garbage);
+
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    assert_eq!(
+        f.warnings(),
+        &[ParseWarning::TrailingData {
+            offset: end,
+            len: 3000
+        }]
+    );
+    assert_eq!(f.trailing_data(), &garbage[..]);
+    assert_eq!(
+        f.frame(0).image(),
+        load_test_file("basic-16x16").frame(0).image()
+    );
+
+    let strict = ReadOptions::new().strict(true);
+    assert_invalid_input(AsepriteFile::read_with_options(&data[..], &strict));
+}
+
+#[test]
+fn understated_file_size() {
+    let mut data = std::fs::read("./tests/data/basic-16x16.aseprite").unwrap();
+    let end = data.len() as u64;
+    let declared = end as u32 - 100;
+    data[..4].copy_from_slice(&declared.to_le_bytes());
+
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    assert_eq!(
+        f.warnings(),
+        &[ParseWarning::FileSizeMismatch {
+            declared,
+            actual: end
+        }]
+    );
+    assert!(f.trailing_data().is_empty());
+
+    let strict = ReadOptions::new().strict(true);
+    assert_invalid_input(AsepriteFile::read_with_options(&data[..], &strict));
+}
+
+#[test]
+fn read_next_concatenated() {
+    let mut first = std::fs::read("./tests/data/layers_and_tags.aseprite").unwrap();
+    let second = std::fs::read("./tests/data/tilemap.aseprite").unwrap();
+    // The header size of the first file is wrong, which must not matter.
+    let declared = first.len() as u32 + 50;
+    first[..4].copy_from_slice(&declared.to_le_bytes());
+    let bundle = [&first[..], &second[..], b"rest"].concat();
+
+    let mut input = &bundle[..];
+    let (a, len_a) = AsepriteFile::read_next(&mut input).unwrap();
+    assert_eq!(len_a, first.len() as u64);
+    assert_eq!(
+        a.warnings(),
+        &[ParseWarning::FileSizeMismatch {
+            declared,
+            actual: len_a
+        }]
+    );
+    let (b, len_b) = AsepriteFile::read_next(&mut input).unwrap();
+    assert_eq!(len_b, second.len() as u64);
+    assert!(b.warnings().is_empty() && b.trailing_data().is_empty());
+    assert_eq!(input, b"rest");
+
+    assert_eq!(
+        a.num_layers(),
+        load_test_file("layers_and_tags").num_layers()
+    );
+    assert_eq!(
+        b.frame(0).image(),
+        load_test_file("tilemap").frame(0).image()
+    );
+}
+
+#[test]
+fn frame_padding() {
+    use std::convert::TryInto;
+
+    let frame = vec![layer_chunk("Layer"), raw_cel_chunk(0, 1, 1, &COLOR_RED)];
+    let mut data = craft_file(1, 1, 32, &[frame.clone(), frame]);
+    // Grow the first frame by four unused bytes.
+    let frame_start = 128;
+    let frame_size = u32::from_le_bytes(data[frame_start..frame_start + 4].try_into().unwrap());
+    let frame_end = frame_start + frame_size as usize;
+    data[frame_start..frame_start + 4].copy_from_slice(&(frame_size + 4).to_le_bytes());
+    data.splice(frame_end..frame_end, [0xFF; 4]);
+    let file_size = data.len() as u32;
+    data[..4].copy_from_slice(&file_size.to_le_bytes());
+
+    let (f, len) = AsepriteFile::read_next(&data[..]).unwrap();
+    assert_eq!(len, data.len() as u64);
+    assert!(f.warnings().is_empty());
+    assert_eq!(f.frame(1).image().get_pixel(0, 0).0, COLOR_RED);
+}
+
+#[test]
+fn user_data_tileset() {
+    let user_data = |text: &str| chunk(0x2020, Bytes::default().dword(1).string(text));
+    let pixels: Vec<u8> = [COLOR_RED, COLOR_GREEN].concat();
+    let compressed = Bytes::default().zlib(&pixels);
+    let tileset_chunk = Bytes::default()
+        .dword(0) // id
+        .dword(2 | 4) // tiles in this file, empty tile is 0
+        .dword(2) // number of tiles
+        .word(1)
+        .word(1)
+        .short(1)
+        .zeros(14)
+        .string("Tiles")
+        .dword(compressed.0.len() as u32)
+        .bytes(&compressed.0);
+    let data = craft_file(
+        1,
+        1,
+        32,
+        &[vec![
+            layer_chunk("Bottom"),
+            chunk(0x2023, tileset_chunk.clone()),
+            user_data("tileset"),
+            user_data("tile 0"),
+            user_data("tile 1"),
+            layer_chunk("Top"),
+            user_data("layer"),
+        ]],
+    );
+    let f = AsepriteFile::read(&data[..]).unwrap();
+    let tileset = f.tilesets().get(&TilesetId::new(0)).unwrap();
+    let text = |user_data: Option<&UserData>| user_data.and_then(|u| u.text.clone());
+    assert_eq!(text(tileset.user_data()).as_deref(), Some("tileset"));
+    assert_eq!(text(tileset.tile_user_data(0)).as_deref(), Some("tile 0"));
+    assert_eq!(text(tileset.tile_user_data(1)).as_deref(), Some("tile 1"));
+    assert!(tileset.tile_user_data(2).is_none());
+    assert!(f.layer(0).user_data().is_none());
+    assert_eq!(text(f.layer(1).user_data()).as_deref(), Some("layer"));
+    assert_semantic_eq(&f, &write_and_read(&f));
+
+    // Only the tileset's user data.
+    let tileset_only = craft_file(
+        1,
+        1,
+        32,
+        &[vec![chunk(0x2023, tileset_chunk), user_data("tileset")]],
+    );
+    let f = AsepriteFile::read(&tileset_only[..]).unwrap();
+    let tileset = f.tilesets().get(&TilesetId::new(0)).unwrap();
+    assert!(tileset.user_data().is_some() && tileset.tile_user_data(0).is_none());
+}
+
+#[test]
+fn layer_to_tileset() {
+    // Tiles of 2x2 pixels: A, A mirrored horizontally, empty, B, A.
+    let a = image::RgbaImage::from_fn(2, 2, |x, y| {
+        image::Rgba([x as u8 * 100, y as u8 * 100, 7, 255])
+    });
+    let a_flipped = image::imageops::flip_horizontal(&a);
+    let b = image::RgbaImage::from_pixel(2, 2, image::Rgba(COLOR_GREEN));
+    let mut builder = AsepriteFileBuilder::new(10, 2);
+    let layer = builder.add_layer("Level");
+    builder.add_frame(100);
+    let mut level = image::RgbaImage::new(10, 2);
+    for (i, image) in [(0, &a), (1, &a_flipped), (3, &b), (4, &a)].iter() {
+        image::imageops::replace(&mut level, *image, i * 2, 0);
+    }
+    builder.add_cel(0, layer, (0, 0), &level);
+    let f = builder.build().unwrap();
+
+    // Draws the grid with the tileset, applying flips.
+    let reconstruct = |tileset: &Tileset, grid: &TileGrid| {
+        let mut canvas = image::RgbaImage::new(10, 2);
+        for (i, tile) in grid.tiles().iter().enumerate() {
+            let mut image = tileset.tile_image(tile.id()).unwrap();
+            if tile.flip_x() {
+                image = image::imageops::flip_horizontal(&image);
+            }
+            if tile.flip_y() {
+                image = image::imageops::flip_vertical(&image);
+            }
+            image::imageops::replace(&mut canvas, &image, i as u32 * 2, 0);
+        }
+        canvas
+    };
+
+    let (tileset, grid) = f.layer_to_tileset(layer, 0, (2, 2)).unwrap();
+    assert_eq!((grid.width(), grid.height()), (5, 1));
+    assert_eq!(*tileset.tile_count(), 4);
+    assert!(*tileset.empty_tile_is_id_zero());
+    assert_eq!(tileset.name(), "Level");
+    let ids: Vec<u32> = grid.tiles().iter().map(|t| t.id()).collect();
+    assert_eq!(ids, vec![1, 2, 0, 3, 1]);
+    assert_eq!(
+        reconstruct(&tileset, &grid),
+        f.frame(0).layer(layer).image()
+    );
+
+    let options = TileDedupOptions::new().detect_flips(true);
+    let (tileset, grid) = f
+        .layer_to_tileset_with_options(layer, 0, (2, 2), &options)
+        .unwrap();
+    assert_eq!(*tileset.tile_count(), 3);
+    let tile = grid.tile(1, 0).unwrap();
+    assert_eq!((tile.id(), tile.flip_x(), tile.flip_y()), (1, true, false));
+    assert!(grid.tile(5, 0).is_none());
+    assert_eq!(
+        reconstruct(&tileset, &grid),
+        f.frame(0).layer(layer).image()
+    );
+
+    assert_invalid_input(f.layer_to_tileset(layer, 0, (3, 2)));
+    assert_invalid_input(f.layer_to_tileset(layer, 0, (0, 2)));
+    assert_invalid_input(f.layer_to_tileset(1, 0, (2, 2)));
+    assert_invalid_input(f.layer_to_tileset(layer, 1, (2, 2)));
+}
+
+fn write_and_read(f: &AsepriteFile) -> AsepriteFile {
+    let mut data = Vec::new();
+    f.write_to(&mut data).unwrap();
+    let options = ReadOptions::new().strict(f.warnings().is_empty());
+    AsepriteFile::read_with_options(&data[..], &options).unwrap()
+}
+
+#[test]
+fn write_fixtures() {
+    for entry in std::fs::read_dir("./tests/data").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map_or(true, |ext| ext != "aseprite") {
+            continue;
+        }
+        if let Ok(f) = AsepriteFile::read_file(&path) {
+            println!("Writing: {}", path.display());
+            let copy = write_and_read(&f);
+            assert_semantic_eq(&f, &copy);
+            for frame in 0..f.num_frames() {
+                assert_eq!(copy.frame(frame).image(), f.frame(frame).image());
+            }
+        }
+    }
+}
+
+#[test]
+fn write_edited_file() {
+    let mut f = load_test_file("linked_cels");
+    let green = image::RgbaImage::from_pixel(2, 3, image::Rgba(COLOR_GREEN));
+    f.set_cel_image(0, 0, &green, (-1, 1)).unwrap();
+    f.rename_layer(0, "Renamed layer").unwrap();
+    f.set_frame_duration(1, 1234).unwrap();
+
+    let path = std::env::temp_dir().join("asefile_write_edited_file.aseprite");
+    f.write_file(&path).unwrap();
+    let copy = AsepriteFile::read_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_semantic_eq(&f, &copy);
+    assert_eq!(copy.layer(0).name(), "Renamed layer");
+    assert_eq!(copy.frame(0).layer(0).image(), f.frame(0).layer(0).image());
+}
+
+#[test]
+fn write_built_file() {
+    let mut builder = AsepriteFileBuilder::new(4, 2);
+    let layer = builder.add_layer("Layer");
+    let frame = builder.add_frame(100);
+    builder.add_frame(50);
+    let red = image::RgbaImage::from_pixel(2, 2, image::Rgba(COLOR_RED));
+    builder.add_cel(frame, layer, (3, 1), &red);
+    builder.add_tag("First", 0, 0, AnimationDirection::Forward);
+    builder.add_tag("Second", 1, 1, AnimationDirection::Reverse);
+    builder.set_palette(&[[0, 0, 0, 255], [255, 255, 255, 128]]);
+    let mut f = builder.build().unwrap();
+    f.tags[1].set_user_data(test_user_data("second", COLOR_GREEN));
+    f.sprite_user_data = Some(test_user_data("sprite", COLOR_RED));
+
+    let copy = write_and_read(&f);
+    assert_eq!(copy.frame(0).image(), f.frame(0).image());
+    assert_eq!(copy.palette().unwrap().color(1).unwrap().alpha(), 128);
+    assert_eq!(copy.sprite_user_data(), f.sprite_user_data());
+    // Tags are matched with user data chunks by position, so the first tag
+    // gets an empty one.
+    let empty = UserData {
+        text: None,
+        color: None,
+        properties: Default::default(),
+    };
+    assert_eq!(copy.tag(0).user_data(), Some(&empty));
+    assert_eq!(copy.tag(1).user_data(), f.tag(1).user_data());
+
+    let long_name = "x".repeat(70000);
+    f.rename_layer(layer, &long_name).unwrap();
+    let err = f.write_to(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn edit_and_save() {
+    let color_profile = chunk(0x2007, Bytes::default().word(0).word(0).zeros(12));
+    let mask = chunk(0x2016, Bytes::default().bytes(&[1, 2, 3]));
+    let external_files = Bytes::default()
+        .dword(1)
+        .zeros(8)
+        .dword(5)
+        .byte(2) // extension
+        .zeros(7)
+        .string("ext");
+    let tags = Bytes::default()
+        .word(1)
+        .zeros(8)
+        .word(0)
+        .word(0)
+        .byte(0)
+        .word(0)
+        .zeros(10)
+        .string("Tag");
+    let cel = Bytes::default()
+        .word(0)
+        .short(0)
+        .short(0)
+        .byte(255)
+        .word(0) // raw image
+        .short(3) // z-index
+        .zeros(5)
+        .word(2)
+        .word(1)
+        .bytes(&[COLOR_RED, COLOR_GREEN].concat());
+    let cel_extra = Bytes::default().dword(1).zeros(16);
+    let mut data = craft_file(
+        2,
+        1,
+        32,
+        &[
+            vec![
+                color_profile,
+                mask.clone(),
+                chunk(0x2008, external_files),
+                palette_chunk(&[COLOR_RED, COLOR_GREEN]),
+                layer_chunk("Layer"),
+                chunk(0x2018, tags),
+                chunk(0x2005, cel),
+                chunk(0x2006, cel_extra.clone()),
+            ],
+            vec![mask],
+        ],
+    );
+    // Grid width and height.
+    data[40] = 8;
+    data[42] = 4;
+    let mut f = AsepriteFile::read(&data[..]).unwrap();
+
+    f.rename_layer(0, "Renamed").unwrap();
+    f.set_tag_range(0, 0, 1).unwrap();
+    f.set_palette_color(1, [0, 0, 255, 255]).unwrap();
+    assert_eq!(f.tag(0).to_frame(), 1);
+    assert_eq!(f.palette().unwrap().color(1).unwrap().blue(), 255);
+
+    let copy = write_and_read(&f);
+    assert_semantic_eq(&f, &copy);
+    assert_eq!(copy.layer(0).name(), "Renamed");
+    assert_eq!(copy.tag(0).to_frame(), 1);
+    assert_eq!(copy.palette().unwrap().color(1).unwrap().blue(), 255);
+    // Data that is not interpreted by this crate survives.
+    assert_eq!((copy.grid().width(), copy.grid().height()), (8, 4));
+    assert_eq!(
+        copy.color_profile().unwrap().profile_type(),
+        ColorProfileType::None
+    );
+    assert_eq!(copy.raw_chunks.len(), 2);
+    assert_eq!(copy.raw_chunks[0].chunk_type, 0x2016);
+    assert_eq!(copy.raw_chunks[1].frame, 1);
+    let copy_cel = copy
+        .framedata
+        .cel(cel::CelId { frame: 0, layer: 0 })
+        .unwrap();
+    assert_eq!(copy_cel.data.z_index, 3);
+    assert_eq!(copy_cel.extra.as_deref(), Some(&cel_extra.0[..]));
+    let external_file = copy.external_files().get(&ExternalFileId::new(5)).unwrap();
+    assert_eq!(
+        external_file.file_type(),
+        ExternalFileType::PropertiesExtension
+    );
+
+    // A new image invalidates the cel's extra data.
+    let image = image::RgbaImage::from_pixel(1, 1, image::Rgba(COLOR_RED));
+    f.set_cel_image(0, 0, &image, (0, 0)).unwrap();
+    let cel = f.framedata.cel(cel::CelId { frame: 0, layer: 0 }).unwrap();
+    assert!(cel.extra.is_none());
+    assert_eq!(cel.data.z_index, 3);
+
+    assert_invalid_input(f.set_tag_range(1, 0, 0));
+    assert_invalid_input(f.set_tag_range(0, 1, 0));
+    assert_invalid_input(f.set_tag_range(0, 0, 2));
+    assert_invalid_input(f.set_palette_color(2, COLOR_RED));
+    let mut builder = AsepriteFileBuilder::new(1, 1);
+    builder.add_frame(100);
+    let mut no_palette = builder.build().unwrap();
+    assert_invalid_input(no_palette.set_palette_color(0, COLOR_RED));
+}
+
+#[test]
+fn export_frames() {
+    let f = load_test_file("layers_and_tags");
+    let dir = std::env::temp_dir().join("asefile_export_frames");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let options = FrameExportOptions::new().title("walk").tag(1);
+    let tag = f.tag(1);
+    let paths = f
+        .export_frames_with_options(&dir, "{title}/{tag}_{tagframe01}_{frame}.png", &options)
+        .unwrap();
+    assert_eq!(
+        paths.len(),
+        (tag.to_frame() - tag.from_frame() + 1) as usize
+    );
+    for (index, path) in paths.iter().enumerate() {
+        let frame = tag.from_frame() + index as u32;
+        let name = format!("{}_{:02}_{}.png", tag.name(), index + 1, frame);
+        assert_eq!(*path, dir.join("walk").join(name));
+        let image = image::open(path).unwrap().to_rgba8();
+        assert!(image == f.frame(frame).image());
+    }
+
+    // Frames outside of tags have an empty tag name.
+    let paths = f.export_frames(&dir, "{tag}-{frame}-{duration}").unwrap();
+    assert_eq!(paths.len(), f.num_frames() as usize);
+    for (frame, path) in paths.iter().enumerate() {
+        let frame = frame as u32;
+        let tag = (0..f.num_tags())
+            .map(|id| f.tag(id))
+            .find(|tag| tag.from_frame() <= frame && frame <= tag.to_frame());
+        let name = format!(
+            "{}-{}-{}",
+            tag.map_or("", |tag| tag.name()),
+            frame,
+            f.frame(frame).duration()
+        );
+        assert_eq!(*path, dir.join(name));
+        assert!(path.exists());
+    }
+
+    // Without a frame number, one is appended before the extension.
+    let paths = f.export_frames(&dir, "sheet.v2/sprite.png").unwrap();
+    assert_eq!(paths[0], dir.join("sheet.v2/sprite1.png"));
+    assert_eq!(paths[1], dir.join("sheet.v2/sprite2.png"));
+    let paths = f.export_frames(&dir, "sheet.v2/sprite").unwrap();
+    assert_eq!(paths[0], dir.join("sheet.v2/sprite1"));
+
+    let error = |pattern: &str, options: &FrameExportOptions| {
+        let err = f
+            .export_frames_with_options(&dir, pattern, options)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    };
+    let options = FrameExportOptions::new();
+    error("{title}_{frame}.png", &options);
+    error("{layer}_{frame}.png", &options);
+    error("{frame.png", &options);
+    error("{framex}.png", &options);
+    // Each tag starts counting at 0.
+    error("{tagframe}.png", &options);
+    error("{frame}.png", &options.clone().tag(f.num_tags()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sprite_sheet_layouts() {
+    use crate::spritesheet::{self, SheetLayout, SpriteSheetOptions};
+
+    let f = load_test_file("layers_and_tags");
+    let (width, height) = (f.width() as u32, f.height() as u32);
+    let check = |options: &SpriteSheetOptions, expected: &[(u32, u32, u32)], size: (u32, u32)| {
+        let sheet = spritesheet::build(&f, options).unwrap();
+        assert_eq!(sheet.image().dimensions(), size);
+        assert_eq!(sheet.frames().len(), expected.len());
+        for (rect, &(frame, x, y)) in sheet.frames().iter().zip(expected) {
+            assert_eq!((rect.frame(), rect.x(), rect.y()), (frame, x, y));
+            assert_eq!((rect.width(), rect.height()), (width, height));
+            let image = f.frame(frame).image();
+            let view = image::imageops::crop_imm(sheet.image(), x, y, width, height).to_image();
+            assert!(view == image, "frame {} differs", frame);
+        }
+    };
+
+    let (w, h) = (width, height);
+    check(
+        &SpriteSheetOptions::new(),
+        &[(0, 0, 0), (1, w, 0), (2, 2 * w, 0), (3, 3 * w, 0)],
+        (4 * w, h),
+    );
+    check(
+        &SpriteSheetOptions::new().layout(SheetLayout::Vertical),
+        &[(0, 0, 0), (1, 0, h), (2, 0, 2 * h), (3, 0, 3 * h)],
+        (w, 4 * h),
+    );
+    check(
+        &SpriteSheetOptions::new()
+            .layout(SheetLayout::Grid { columns: 3 })
+            .spacing(2),
+        &[(0, 0, 0), (1, w + 2, 0), (2, 2 * w + 4, 0), (3, 0, h + 2)],
+        (3 * w + 4, 2 * h + 2),
+    );
+    // More columns than frames, and too few.
+    let tag = f.tag(1);
+    let tag_frames: Vec<u32> = (tag.from_frame()..=tag.to_frame()).collect();
+    let single_row: Vec<_> = tag_frames
+        .iter()
+        .enumerate()
+        .map(|(index, &frame)| (frame, index as u32 * w, 0))
+        .collect();
+    check(
+        &SpriteSheetOptions::new()
+            .layout(SheetLayout::Grid { columns: 10 })
+            .tag(1),
+        &single_row,
+        (tag_frames.len() as u32 * w, h),
+    );
+    check(
+        &SpriteSheetOptions::new().layout(SheetLayout::Grid { columns: 0 }),
+        &[(0, 0, 0), (1, 0, h), (2, 0, 2 * h), (3, 0, 3 * h)],
+        (w, 4 * h),
+    );
+
+    let err = spritesheet::build(&f, &SpriteSheetOptions::new().tag(f.num_tags())).unwrap_err();
+    assert!(matches!(err, AsepriteParseError::InvalidInput(_)));
+    // Held frames share a single cell.
+    let mut builder = AsepriteFileBuilder::new(4, 4);
+    let layer = builder.add_layer("Layer");
+    for value in [10, 10, 20, 10] {
+        let frame = builder.add_frame(100);
+        let image = image::RgbaImage::from_pixel(4, 4, image::Rgba([value, 0, 0, 255]));
+        builder.add_cel(frame, layer, (0, 0), &image);
+    }
+    let held = builder.build().unwrap();
+    let options = SpriteSheetOptions::new().merge_duplicates(true).spacing(1);
+    let sheet = spritesheet::build(&held, &options).unwrap();
+    assert_eq!(sheet.image().dimensions(), (9, 4));
+    let frames: Vec<_> = sheet
+        .frames()
+        .iter()
+        .map(|frame| (frame.frame(), frame.x(), frame.alias_of()))
+        .collect();
+    assert_eq!(
+        frames,
+        [(0, 0, None), (1, 0, Some(0)), (2, 5, None), (3, 0, Some(0))]
+    );
+    assert_eq!(sheet.image().get_pixel(5, 0).0, [20, 0, 0, 255]);
+    let sheet = spritesheet::build(&held, &SpriteSheetOptions::new()).unwrap();
+    assert_eq!(sheet.image().dimensions(), (16, 4));
+    assert!(sheet
+        .frames()
+        .iter()
+        .all(|frame| frame.alias_of().is_none()));
+
+    // Trimmed frames are placed next to each other, rows are as high as
+    // their highest frame.
+    let mut builder = AsepriteFileBuilder::new(8, 8);
+    let layer = builder.add_layer("Layer");
+    let red = image::Rgba([255, 0, 0, 255]);
+    for (x, y, w, h) in [(1, 2, 3, 2), (0, 0, 8, 8), (4, 4, 2, 4), (7, 7, 1, 1)] {
+        let frame = builder.add_frame(100);
+        builder.add_cel(
+            frame,
+            layer,
+            (x, y),
+            &image::RgbaImage::from_pixel(w, h, red),
+        );
+    }
+    builder.add_frame(100);
+    let sprite = builder.build().unwrap();
+    let options = SpriteSheetOptions::new()
+        .layout(SheetLayout::Grid { columns: 2 })
+        .spacing(1)
+        .trim(true);
+    let sheet = spritesheet::build(&sprite, &options).unwrap();
+    let frames: Vec<_> = sheet
+        .frames()
+        .iter()
+        .map(|frame| {
+            (
+                (frame.x(), frame.y(), frame.width(), frame.height()),
+                frame.trim_offset(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        frames,
+        [
+            ((0, 0, 3, 2), (1, 2)),
+            ((4, 0, 8, 8), (0, 0)),
+            ((0, 9, 2, 4), (4, 4)),
+            ((3, 9, 1, 1), (7, 7)),
+            ((0, 14, 1, 1), (0, 0)),
+        ]
+    );
+    assert_eq!(sheet.image().dimensions(), (12, 15));
+    for frame in sheet.frames() {
+        assert_eq!(frame.source_size(), (8, 8));
+        let mut image = image::RgbaImage::new(8, 8);
+        let trimmed = image::imageops::crop_imm(
+            sheet.image(),
+            frame.x(),
+            frame.y(),
+            frame.width(),
+            frame.height(),
+        );
+        let (offset_x, offset_y) = frame.trim_offset();
+        image::imageops::replace(&mut image, &trimmed.to_image(), offset_x, offset_y);
+        assert!(image == sprite.frame(frame.frame()).image());
+    }
+    assert!(!sheet.frames()[1].is_trimmed());
+    assert!(sheet.frames()[0].is_trimmed());
+    let options = options.layout(SheetLayout::Vertical);
+    let sheet = spritesheet::build(&sprite, &options).unwrap();
+    assert_eq!(sheet.image().dimensions(), (8, 2 + 8 + 4 + 1 + 1 + 4));
+}
+
+#[test]
+fn atlas_packing() {
+    use crate::atlas::{self, Atlas, AtlasOptions};
+    use rand::Rng;
+
+    // Frames stay within the atlas, keep their padding, and contain the
+    // pixels of the (trimmed) frame.
+    let check = |atlas: &Atlas, sprites: &[(&str, &AsepriteFile)], padding: u32| {
+        let (atlas_width, atlas_height) = atlas.image().dimensions();
+        let mut rects = Vec::new();
+        for frame in atlas.frames() {
+            let file = sprites.iter().find(|s| s.0 == frame.sprite()).unwrap().1;
+            let (x, y, width, height) = frame.rect();
+            assert!(x + width <= atlas_width && y + height <= atlas_height);
+            assert_eq!(
+                frame.source_size(),
+                (file.width() as u32, file.height() as u32)
+            );
+            assert_eq!(frame.duration(), file.frame(frame.frame()).duration());
+            let (offset_x, offset_y) = frame.trim_offset();
+            let mut image = image::RgbaImage::new(file.width() as u32, file.height() as u32);
+            let packed = image::imageops::crop_imm(atlas.image(), x, y, width, height).to_image();
+            image::imageops::replace(&mut image, &packed, offset_x, offset_y);
+            assert!(image == file.frame(frame.frame()).image());
+            if !rects.contains(&frame.rect()) {
+                rects.push(frame.rect());
+            }
+        }
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                let apart = a.0 + a.2 + padding <= b.0
+                    || b.0 + b.2 + padding <= a.0
+                    || a.1 + a.3 + padding <= b.1
+                    || b.1 + b.3 + padding <= a.1;
+                assert!(apart, "{:?} and {:?} overlap", a, b);
+            }
+        }
+    };
+
+    let tags = load_test_file("layers_and_tags");
+    let linked = load_test_file("linked_cels");
+    let basic = load_test_file("basic-16x16");
+    let sprites = [("tags", &tags), ("linked", &linked), ("basic", &basic)];
+    let num_frames = (tags.num_frames() + linked.num_frames() + basic.num_frames()) as usize;
+
+    let atlas = atlas::pack(&sprites, &AtlasOptions::new()).unwrap();
+    assert_eq!(atlas.frames().len(), num_frames);
+    assert!(atlas.frames().iter().all(|frame| !frame.is_trimmed()));
+    check(&atlas, &sprites, 0);
+    assert_eq!(atlas.frames()[0].sprite(), "tags");
+    assert_eq!(atlas.frames()[1].frame(), 1);
+
+    let options = AtlasOptions::new().trim(true).padding(2);
+    let trimmed = atlas::pack(&sprites, &options).unwrap();
+    assert!(trimmed.frames().iter().any(|frame| frame.is_trimmed()));
+    check(&trimmed, &sprites, 2);
+
+    let with_copy = [("tags", &tags), ("basic", &basic), ("copy", &basic)];
+    let options = AtlasOptions::new()
+        .merge_duplicates(true)
+        .power_of_two(true);
+    let merged = atlas::pack(&with_copy, &options).unwrap();
+    check(&merged, &with_copy, 0);
+    let (width, height) = merged.image().dimensions();
+    assert!(width.is_power_of_two() && height.is_power_of_two());
+    let frames = merged.frames();
+    let copies = &frames[frames.len() - basic.num_frames() as usize..];
+    for copy in copies {
+        assert_eq!(copy.sprite(), "copy");
+        let original = frames
+            .iter()
+            .find(|f| f.sprite() == "basic" && f.frame() == copy.frame())
+            .unwrap();
+        assert_eq!(copy.rect(), original.rect());
+        let first = copy.alias_of().unwrap();
+        assert_eq!(frames[first].rect(), copy.rect());
+        assert_eq!(frames[first].alias_of(), None);
+    }
+    assert!(atlas
+        .frames()
+        .iter()
+        .all(|frame| frame.alias_of().is_none()));
+
+    let err = atlas::pack(&sprites, &AtlasOptions::new().max_size(100, 20)).unwrap_err();
+    assert!(matches!(err, AsepriteParseError::InvalidInput(_)));
+
+    // Frames of random sizes are packed densely.
+    let mut rng = rand::thread_rng();
+    let files: Vec<AsepriteFile> = (0..40)
+        .map(|_| {
+            let (width, height) = (rng.gen_range(4, 40), rng.gen_range(4, 40));
+            let mut builder = AsepriteFileBuilder::new(width, height);
+            let layer = builder.add_layer("Layer");
+            let frame = builder.add_frame(100);
+            let image = image::RgbaImage::from_pixel(
+                width as u32,
+                height as u32,
+                image::Rgba([rng.gen(), rng.gen(), rng.gen(), 255]),
+            );
+            builder.add_cel(frame, layer, (0, 0), &image);
+            builder.build().unwrap()
+        })
+        .collect();
+    let names: Vec<String> = (0..files.len()).map(|i| i.to_string()).collect();
+    let sprites: Vec<_> = names.iter().map(String::as_str).zip(&files).collect();
+    let atlas = atlas::pack(&sprites, &AtlasOptions::new()).unwrap();
+    check(&atlas, &sprites, 0);
+    let used: u32 = files
+        .iter()
+        .map(|f| f.width() as u32 * f.height() as u32)
+        .sum();
+    let (width, height) = atlas.image().dimensions();
+    let density = used as f64 / (width * height) as f64;
+    assert!(density > 0.75, "density {}", density);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_metadata() {
+    use crate::atlas::{self, AtlasOptions};
+    use crate::exporters::json::{self, JsonFormat, JsonOptions};
+    use crate::spritesheet::{self, SpriteSheetOptions};
+    use serde_json::{json, Value};
+
+    let parse = |data: Vec<u8>| -> Value { serde_json::from_slice(&data).unwrap() };
+
+    let f = load_test_file("user_data");
+    let sheet = spritesheet::build(&f, &SpriteSheetOptions::new()).unwrap();
+    let mut data = Vec::new();
+    let options = JsonOptions::new().image("user_data.png");
+    json::write_sheet(&sheet, "user_data", &f, &mut data, &options).unwrap();
+    let doc = parse(data);
+    let frames = doc["frames"].as_object().unwrap();
+    assert_eq!(frames.len(), 8);
+    assert_eq!(
+        doc["frames"]["user_data 3.aseprite"],
+        json!({
+            "frame": { "x": 48, "y": 0, "w": 16, "h": 16 },
+            "rotated": false,
+            "trimmed": false,
+            "spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 16 },
+            "sourceSize": { "w": 16, "h": 16 },
+            "duration": 100,
+            "slices": [{ "name": "Slice 1", "bounds": { "x": 49, "y": 1, "w": 2, "h": 2 } }]
+        })
+    );
+    let meta = &doc["meta"];
+    assert_eq!(meta["image"], "user_data.png");
+    assert_eq!(meta["format"], "RGBA8888");
+    assert_eq!(meta["size"], json!({ "w": 128, "h": 16 }));
+    assert_eq!(
+        meta["frameTags"],
+        json!([
+            { "name": "Tag 0", "from": 0, "to": 1, "direction": "forward",
+              "color": "#00ff00ff", "data": "test_user_data_tag_0" },
+            { "name": "Tag 1", "from": 3, "to": 4, "direction": "forward",
+              "color": "#000000ff" },
+            { "name": "Tag 2", "from": 6, "to": 7, "direction": "forward",
+              "color": "#ff0000ff", "data": "test_user_data_tag_2" }
+        ])
+    );
+    assert_eq!(
+        meta["layers"],
+        json!([{ "name": "Layer 1", "opacity": 255, "blendMode": "normal",
+                 "color": "#ff0000ff", "data": "test_user_data_layer" }])
+    );
+    assert_eq!(
+        meta["slices"],
+        json!([{
+            "name": "Slice 1",
+            "color": "#0000ffff",
+            "data": "test_user_data_slice",
+            "keys": [{ "frame": 0, "bounds": { "x": 1, "y": 1, "w": 2, "h": 2 } }]
+        }])
+    );
+
+    // Single frame names don't have a number. Pivots are included.
+    let f = load_test_file("slice");
+    let sheet = spritesheet::build(&f, &SpriteSheetOptions::new()).unwrap();
+    let mut data = Vec::new();
+    json::write_sheet(&sheet, "slice", &f, &mut data, &JsonOptions::new()).unwrap();
+    let doc = parse(data);
+    assert!(doc["frames"]["slice.aseprite"].is_object());
+    assert_eq!(
+        doc["meta"]["slices"][0]["keys"][0]["pivot"],
+        json!({ "x": 5, "y": 5 })
+    );
+
+    // Array format for the frames of one tag. Tags that are not completely
+    // included are left out, the others refer to positions in the array.
+    let f = load_test_file("layers_and_tags");
+    let sheet = spritesheet::build(&f, &SpriteSheetOptions::new().tag(1)).unwrap();
+    let mut data = Vec::new();
+    let options = JsonOptions::new()
+        .format(JsonFormat::Array)
+        .filename_format("{tag}_{tagframe}");
+    json::write_sheet(&sheet, "player", &f, &mut data, &options).unwrap();
+    let doc = parse(data);
+    let names: Vec<&str> = doc["frames"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|frame| frame["filename"].as_str().unwrap())
+        .collect();
+    // {tag} is the first tag that contains the frame.
+    assert_eq!(names, ["T1_1", "T3_1", "T3_2"]);
+    assert_eq!(
+        doc["meta"]["frameTags"],
+        json!([
+            { "name": "T3", "from": 0, "to": 2, "direction": "forward" },
+            { "name": "T2", "from": 2, "to": 2, "direction": "forward" }
+        ])
+    );
+    assert_eq!(
+        doc["meta"]["layers"][2],
+        json!({ "name": "Layer 5", "group": "Group 1", "opacity": 255, "blendMode": "normal" })
+    );
+    assert_eq!(doc["meta"]["layers"][1], json!({ "name": "Group 1" }));
+
+    // Trimmed sheets record where each frame belongs in the source frame.
+    let sheet = spritesheet::build(&f, &SpriteSheetOptions::new().trim(true)).unwrap();
+    let mut data = Vec::new();
+    json::write_sheet(&sheet, "player", &f, &mut data, &JsonOptions::new()).unwrap();
+    let doc = parse(data);
+    for frame in sheet.frames() {
+        let entry = &doc["frames"][format!("player {}.aseprite", frame.frame())];
+        let (w, h) = (frame.width(), frame.height());
+        let (offset_x, offset_y) = frame.trim_offset();
+        assert_eq!(entry["trimmed"], frame.is_trimmed());
+        assert_eq!(
+            entry["spriteSourceSize"],
+            json!({ "x": offset_x, "y": offset_y, "w": w, "h": h })
+        );
+        assert_eq!(entry["sourceSize"], json!({ "w": 16, "h": 16 }));
+    }
+    assert!(sheet.frames().iter().any(|frame| frame.is_trimmed()));
+
+    // Atlases with trimmed frames of several sprites.
+    let basic = load_test_file("basic-16x16");
+    let sprites = [("player", &f), ("coin", &basic)];
+    let atlas = atlas::pack(&sprites, &AtlasOptions::new().trim(true)).unwrap();
+    let mut data = Vec::new();
+    json::write_atlas(&atlas, &sprites, &mut data, &JsonOptions::new()).unwrap();
+    let doc = parse(data);
+    assert_eq!(
+        doc["frames"].as_object().unwrap().len(),
+        atlas.frames().len()
+    );
+    for frame in atlas.frames() {
+        let name = if frame.sprite() == "coin" {
+            "coin.aseprite".to_owned()
+        } else {
+            format!("player {}.aseprite", frame.frame())
+        };
+        let entry = &doc["frames"][&name];
+        let (x, y, w, h) = frame.rect();
+        let (offset_x, offset_y) = frame.trim_offset();
+        assert_eq!(entry["frame"], json!({ "x": x, "y": y, "w": w, "h": h }));
+        assert_eq!(entry["trimmed"], frame.is_trimmed());
+        assert_eq!(
+            entry["spriteSourceSize"],
+            json!({ "x": offset_x, "y": offset_y, "w": w, "h": h })
+        );
+    }
+    let (width, height) = atlas.image().dimensions();
+    assert_eq!(doc["meta"]["size"], json!({ "w": width, "h": height }));
+
+    // Names must be unique in the hash format, and every sprite is required.
+    let err = json::write_atlas(
+        &atlas,
+        &sprites,
+        &mut Vec::new(),
+        &JsonOptions::new().filename_format("{title}"),
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    let options = JsonOptions::new()
+        .format(JsonFormat::Array)
+        .filename_format("{title}");
+    assert!(json::write_atlas(&atlas, &sprites, &mut Vec::new(), &options).is_ok());
+    let err = json::write_atlas(&atlas, &sprites[..1], &mut Vec::new(), &options).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "texturepacker")]
+#[test]
+fn texturepacker_metadata() {
+    use crate::atlas::{self, AtlasOptions};
+    use crate::exporters::texturepacker::{
+        self, decode, TexturePackerFormat, TexturePackerOptions,
+    };
+    use serde_json::{json, Value};
+
+    let player = load_test_file("layers_and_tags");
+    let coin = load_test_file("basic-16x16");
+    let sprites = [("player", &player), ("coin", &coin)];
+    let atlas = atlas::pack(&sprites, &AtlasOptions::new().trim(true)).unwrap();
+    let name = |sprite: &str, frame: u32| {
+        if sprite == "coin" {
+            "coin.png".to_owned()
+        } else {
+            format!("player_{}.png", frame)
+        }
+    };
+
+    let mut data = Vec::new();
+    let options = TexturePackerOptions::new().image("atlas.png");
+    texturepacker::write(&atlas, &sprites, &mut data, &options).unwrap();
+    let doc: Value = serde_json::from_slice(&data).unwrap();
+    let (width, height) = atlas.image().dimensions();
+    assert_eq!(doc["meta"]["image"], "atlas.png");
+    assert_eq!(doc["meta"]["size"], json!({ "w": width, "h": height }));
+    assert_eq!(
+        doc["frames"].as_object().unwrap().len(),
+        atlas.frames().len()
+    );
+    for frame in atlas.frames() {
+        let (x, y, w, h) = frame.rect();
+        let (offset_x, offset_y) = frame.trim_offset();
+        let (source_w, source_h) = frame.source_size();
+        assert_eq!(
+            doc["frames"][name(frame.sprite(), frame.frame())],
+            json!({
+                "frame": { "x": x, "y": y, "w": w, "h": h },
+                "rotated": false,
+                "trimmed": frame.is_trimmed(),
+                "spriteSourceSize": { "x": offset_x, "y": offset_y, "w": w, "h": h },
+                "sourceSize": { "w": source_w, "h": source_h }
+            })
+        );
+    }
+
+    let mut data = Vec::new();
+    let options = TexturePackerOptions::new().format(TexturePackerFormat::JsonArray);
+    texturepacker::write(&atlas, &sprites, &mut data, &options).unwrap();
+    let doc: Value = serde_json::from_slice(&data).unwrap();
+    let frames = doc["frames"].as_array().unwrap();
+    assert_eq!(frames.len(), atlas.frames().len());
+    for (entry, frame) in frames.iter().zip(atlas.frames()) {
+        assert_eq!(entry["filename"], name(frame.sprite(), frame.frame()));
+    }
+    assert!(doc["meta"].get("image").is_none());
+
+    // Plist offsets are measured between the centers of the trimmed and the
+    // source frame, with y pointing up.
+    let mut data = Vec::new();
+    let options = TexturePackerOptions::new()
+        .format(TexturePackerFormat::Plist)
+        .image("a&b.png");
+    texturepacker::write(&atlas, &sprites, &mut data, &options).unwrap();
+    let (frames, meta) = decode::plist(std::str::from_utf8(&data).unwrap());
+    assert_eq!(frames.len(), atlas.frames().len());
+    for (decoded, frame) in frames.iter().zip(atlas.frames()) {
+        assert_eq!(decoded.name, name(frame.sprite(), frame.frame()));
+        let (x, y, w, h) = frame.rect();
+        assert_eq!(decoded.rect, [x as i64, y as i64, w as i64, h as i64]);
+        let (offset_x, offset_y) = frame.trim_offset();
+        let (source_w, source_h) = frame.source_size();
+        assert_eq!(decoded.source_size, (source_w as i64, source_h as i64));
+        let expected_x = offset_x as f64 + w as f64 / 2.0 - source_w as f64 / 2.0;
+        let expected_y = source_h as f64 / 2.0 - (offset_y as f64 + h as f64 / 2.0);
+        assert_eq!(decoded.offset, (expected_x, expected_y));
+        assert!(decoded.aliases.is_empty());
+    }
+    let meta_value = |key: &str| meta.iter().find(|(k, _)| k == key).unwrap().1.clone();
+    assert_eq!(meta_value("format"), "3");
+    assert_eq!(meta_value("textureFileName"), "a&b.png");
+    assert_eq!(meta_value("size"), format!("{{{},{}}}", width, height));
+
+    // Merged duplicates are listed as aliases of the first frame.
+    let with_copy = [("coin", &coin), ("copy", &coin)];
+    let merged = atlas::pack(&with_copy, &AtlasOptions::new().merge_duplicates(true)).unwrap();
+    let mut data = Vec::new();
+    let options = TexturePackerOptions::new()
+        .format(TexturePackerFormat::Plist)
+        .filename_format("{title}");
+    texturepacker::write(&merged, &with_copy, &mut data, &options).unwrap();
+    let (frames, _) = decode::plist(std::str::from_utf8(&data).unwrap());
+    assert_eq!(frames[0].aliases, ["copy"]);
+    assert!(frames[1].aliases.is_empty());
+    assert_eq!(frames[0].rect, frames[1].rect);
+
+    // Only the array format allows duplicate names.
+    for (format, ok) in [
+        (TexturePackerFormat::JsonHash, false),
+        (TexturePackerFormat::JsonArray, true),
+        (TexturePackerFormat::Plist, false),
+    ] {
+        let options = TexturePackerOptions::new()
+            .format(format)
+            .filename_format("{title}");
+        let result = texturepacker::write(&atlas, &sprites, &mut Vec::new(), &options);
+        assert_eq!(result.is_ok(), ok);
+    }
+}
+
+#[cfg(feature = "libgdx")]
+#[test]
+fn libgdx_atlas() {
+    use crate::atlas::{self, AtlasOptions};
+    use crate::exporters::libgdx::{self, decode, LibgdxOptions, TextureFilter};
+
+    let player = load_test_file("layers_and_tags");
+    let coin = load_test_file("basic-16x16");
+    let sprites = [("player", &player), ("coin", &coin)];
+    let atlas = atlas::pack(&sprites, &AtlasOptions::new().trim(true).padding(1)).unwrap();
+    let mut data = Vec::new();
+    libgdx::write(
+        &atlas,
+        &sprites,
+        "atlas.png",
+        &mut data,
+        &LibgdxOptions::new(),
+    )
+    .unwrap();
+    let (header, regions) = decode::atlas(std::str::from_utf8(&data).unwrap());
+    let (width, height) = atlas.image().dimensions();
+    assert_eq!(
+        header,
+        [
+            "atlas.png".to_owned(),
+            format!("size: {}, {}", width, height),
+            "format: RGBA8888".to_owned(),
+            "filter: Nearest,Nearest".to_owned(),
+            "repeat: none".to_owned(),
+        ]
+    );
+
+    // Tags T1 (0-1), T3 (1-3) and T2 (3), then the untagged coin frame.
+    let names: Vec<&str> = regions.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        ["T1_0", "T1_1", "T3_0", "T3_1", "T3_2", "T2_0", "coin_0"]
+    );
+    let frames = [
+        ("player", 0),
+        ("player", 1),
+        ("player", 1),
+        ("player", 2),
+        ("player", 3),
+        ("player", 3),
+        ("coin", 0),
+    ];
+    for ((_, fields), (sprite, frame_id)) in regions.iter().zip(frames) {
+        let frame = atlas
+            .frames()
+            .iter()
+            .find(|frame| frame.sprite() == sprite && frame.frame() == frame_id)
+            .unwrap();
+        let (x, y, w, h) = frame.rect();
+        let (offset_x, offset_y) = frame.trim_offset();
+        let (source_w, source_h) = frame.source_size();
+        assert_eq!(fields["xy"], [x as i64, y as i64]);
+        assert_eq!(fields["size"], [w as i64, h as i64]);
+        assert_eq!(fields["orig"], [source_w as i64, source_h as i64]);
+        assert_eq!(
+            fields["offset"],
+            [offset_x as i64, (source_h - offset_y - h) as i64]
+        );
+        assert_eq!(fields["index"], [-1]);
+    }
+
+    let mut data = Vec::new();
+    let options = LibgdxOptions::new()
+        .filter(TextureFilter::Linear)
+        .prefix_sprite(true);
+    libgdx::write(&atlas, &sprites, "atlas.png", &mut data, &options).unwrap();
+    let (header, regions) = decode::atlas(std::str::from_utf8(&data).unwrap());
+    assert_eq!(header[3], "filter: Linear,Linear");
+    assert_eq!(regions[0].0, "player/T1_0");
+    assert_eq!(regions[6].0, "coin_0");
+
+    let err = libgdx::write(
+        &atlas,
+        &sprites[1..],
+        "atlas.png",
+        &mut Vec::new(),
+        &LibgdxOptions::new(),
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn atlas_slices() {
+    use crate::atlas::{self, AtlasOptions};
+
+    let mut builder = AsepriteFileBuilder::new(16, 16);
+    let layer = builder.add_layer("Layer");
+    for _ in 0..3 {
+        let frame = builder.add_frame(100);
+        let image = image::RgbaImage::from_pixel(6, 4, image::Rgba([0, 0, 255, 255]));
+        builder.add_cel(frame, layer, (5, 8), &image);
+    }
+    let key = |from_frame, x, y, width| SliceKey {
+        from_frame,
+        origin: SliceOrigin { x, y },
+        size: SliceSize { width, height: 6 },
+        slice9: Some(Slice9 {
+            center_x: 1,
+            center_y: 2,
+            center_width: 3,
+            center_height: 2,
+        }),
+        pivot: Some(SlicePivot { x: 2, y: 5 }),
+    };
+    builder.add_slice(Slice {
+        name: "hitbox".to_owned(),
+        // Moves in frame 1 and is hidden from frame 2 on.
+        keys: vec![key(0, 4, 7, 8), key(1, -2, 3, 8), key(2, 0, 0, 0)],
+        user_data: None,
+    });
+    let f = builder.build().unwrap();
+
+    let sprites = [("sprite", &f)];
+    let atlas = atlas::pack(&sprites, &AtlasOptions::new().trim(true).padding(1)).unwrap();
+    let frames = atlas.frames();
+    for (frame, (origin_x, origin_y)) in frames.iter().zip([(4, 7), (-2, 3)]) {
+        let (x, y, _, _) = frame.rect();
+        assert_eq!(frame.trim_offset(), (5, 8));
+        let left = x as i32 + origin_x - 5;
+        let top = y as i32 + origin_y - 8;
+        let slices = frame.slices();
+        assert_eq!(slices.len(), 1);
+        assert_eq!(slices[0].name(), "hitbox");
+        assert_eq!(slices[0].bounds(), (left, top, 8, 6));
+        assert_eq!(slices[0].center(), Some((left + 1, top + 2, 3, 2)));
+        assert_eq!(slices[0].pivot(), Some((left + 2, top + 5)));
+    }
+    assert!(frames[2].slices().is_empty());
+
+    #[cfg(feature = "json")]
+    {
+        use crate::exporters::json::{self, JsonOptions};
+
+        let mut data = Vec::new();
+        json::write_atlas(&atlas, &sprites, &mut data, &JsonOptions::new()).unwrap();
+        let doc: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        let (x, y, w, h) = frames[0].slices()[0].bounds();
+        let (pivot_x, pivot_y) = frames[0].slices()[0].pivot().unwrap();
+        assert_eq!(
+            doc["frames"]["sprite 0.aseprite"]["slices"][0],
+            serde_json::json!({
+                "name": "hitbox",
+                "bounds": { "x": x, "y": y, "w": w, "h": h },
+                "center": { "x": x + 1, "y": y + 2, "w": 3, "h": 2 },
+                "pivot": { "x": pivot_x, "y": pivot_y }
+            })
+        );
+        assert!(doc["frames"]["sprite 2.aseprite"].get("slices").is_none());
+        // The sprite's own slice data is unchanged.
+        assert_eq!(
+            doc["meta"]["slices"][0]["keys"][1]["bounds"],
+            serde_json::json!({ "x": -2, "y": 3, "w": 8, "h": 6 })
+        );
+    }
+}
+
+#[cfg(feature = "bevy")]
+#[test]
+fn bevy_loader() {
+    use crate::bevy::{Aseprite, AsepritePlugin};
+    use bevy_app::{App, TaskPoolPlugin};
+    use bevy_asset::{AssetPlugin, AssetServer, Assets, Handle, LoadState};
+    use bevy_image::{Image, ImagePlugin, TextureAtlasLayout, TextureAtlasPlugin};
+    use std::time::Duration;
+
+    let mut app = App::new();
+    app.add_plugins((
+        TaskPoolPlugin::default(),
+        AssetPlugin {
+            file_path: "tests/data".to_owned(),
+            ..Default::default()
+        },
+        ImagePlugin::default(),
+        TextureAtlasPlugin,
+        AsepritePlugin,
+    ));
+    let handle: Handle<Aseprite> = app
+        .world()
+        .resource::<AssetServer>()
+        .load("layers_and_tags.aseprite");
+    for _ in 0..500 {
+        app.update();
+        let server = app.world().resource::<AssetServer>();
+        match server.load_state(&handle) {
+            LoadState::Loaded if server.is_loaded_with_dependencies(&handle) => break,
+            LoadState::Failed(err) => panic!("{}", err),
+            _ => std::thread::sleep(Duration::from_millis(10)),
+        }
+    }
+
+    let f = load_test_file("layers_and_tags");
+    let sprite = app
+        .world()
+        .resource::<Assets<Aseprite>>()
+        .get(&handle)
+        .unwrap();
+    let images = app.world().resource::<Assets<Image>>();
+    let layouts = app.world().resource::<Assets<TextureAtlasLayout>>();
+    let atlas = images.get(sprite.atlas()).unwrap();
+    let layout = layouts.get(sprite.atlas_layout()).unwrap();
+    assert_eq!(layout.len(), f.num_frames() as usize);
+    assert_eq!(sprite.frames().len(), f.num_frames() as usize);
+    let (width, height) = f.size();
+    for (frame, rect) in layout.textures.iter().enumerate() {
+        assert_eq!(rect.size().to_array(), [width as u32, height as u32]);
+        let expected = f.frame(frame as u32).image();
+        let image = images.get(&sprite.frames()[frame]).unwrap();
+        assert_eq!(image.data.as_deref(), Some(expected.as_raw().as_slice()));
+        let data = atlas.data.as_deref().unwrap();
+        let stride = atlas.width() as usize * 4;
+        for (x, y, pixel) in expected.enumerate_pixels() {
+            let start = (rect.min.y + y) as usize * stride + (rect.min.x + x) as usize * 4;
+            assert_eq!(data[start..start + 4], pixel.0);
+        }
+    }
+
+    assert_eq!(sprite.animations().len(), f.num_tags() as usize);
+    let tag = f.tag(0);
+    let animation = sprite.animation(tag.name()).unwrap();
+    let frames: Vec<usize> = (tag.from_frame()..=tag.to_frame())
+        .map(|frame| frame as usize)
+        .collect();
+    assert_eq!(animation.frames(), &frames[..]);
+    assert!(animation.is_looping());
+    let first = animation.durations()[0];
+    assert_eq!(animation.frame_at(Duration::ZERO), frames[0]);
+    assert_eq!(animation.frame_at(first), frames[1]);
+    assert_eq!(animation.frame_at(animation.duration()), frames[0]);
+    assert!(sprite.animation("missing").is_none());
+}
+
+#[cfg(feature = "macroquad")]
+#[test]
+fn macroquad_animation_sheet() {
+    use crate::macroquad::AnimationSheet;
+
+    let f = load_test_file("layers_and_tags");
+    let image = f.frame(0).image();
+    let converted = crate::macroquad::image(&image);
+    assert_eq!(
+        (converted.width as u32, converted.height as u32),
+        image.dimensions()
+    );
+    assert_eq!(&converted.bytes, image.as_raw());
+
+    let sheet = AnimationSheet::new(&f);
+    let (width, height) = f.size();
+    let (width, height) = (width as u32, height as u32);
+    assert_eq!(sheet.frame_size(), (width, height));
+    assert_eq!(sheet.animations().len(), f.num_tags() as usize);
+    let mut columns = 0;
+    for (row, animation) in sheet.animations().iter().enumerate() {
+        let tag = f.tag(row as u32);
+        let frames = crate::exporters::playback_frames(tag);
+        assert_eq!(animation.name, tag.name());
+        assert_eq!(animation.row, row as u32);
+        assert_eq!(animation.frames, frames.len() as u32);
+        let total: u32 = frames.iter().map(|&frame| f.frame(frame).duration()).sum();
+        let fps = (frames.len() as f64 * 1000.0 / total as f64).round() as u32;
+        assert_eq!(animation.fps, fps);
+        for (column, &frame) in frames.iter().enumerate() {
+            let cell = image::imageops::crop_imm(
+                sheet.image(),
+                column as u32 * width,
+                row as u32 * height,
+                width,
+                height,
+            )
+            .to_image();
+            assert_eq!(cell, f.frame(frame).image());
+        }
+        columns = columns.max(frames.len() as u32);
+    }
+    assert_eq!(
+        sheet.image().dimensions(),
+        (columns * width, f.num_tags() * height)
+    );
+
+    let mut sprite = sheet.animated_sprite(false);
+    sprite.set_animation(1);
+    sprite.set_frame(1);
+    let source = sprite.frame().source_rect;
+    assert_eq!(
+        (source.x, source.y, source.w, source.h),
+        (width as f32, height as f32, width as f32, height as f32)
+    );
+
+    let untagged = load_test_file("basic-16x16");
+    let sheet = AnimationSheet::new(&untagged);
+    assert!(sheet.animations().is_empty());
+    assert_eq!(sheet.image().dimensions(), (0, 0));
+}
+
+#[cfg(feature = "ggez")]
+#[test]
+fn ggez_source_rect() {
+    use crate::atlas::{self, AtlasOptions};
+
+    let f = load_test_file("layers_and_tags");
+    let sprites = [("player", &f)];
+    let atlas = atlas::pack(&sprites, &AtlasOptions::new().padding(1)).unwrap();
+    let (width, height) = atlas.image().dimensions();
+    for frame in atlas.frames() {
+        let rect = crate::ggez::source_rect(&atlas, frame);
+        let (x, y, w, h) = frame.rect();
+        assert_eq!(
+            (rect.x, rect.y, rect.w, rect.h),
+            (
+                x as f32 / width as f32,
+                y as f32 / height as f32,
+                w as f32 / width as f32,
+                h as f32 / height as f32
+            )
+        );
+        assert!(rect.x + rect.w <= 1.0 && rect.y + rect.h <= 1.0);
+    }
+}
+
+#[cfg(feature = "sdl2")]
+#[test]
+fn sdl2_surface() {
+    let f = load_test_file("layers_and_tags");
+    let image = f.frame(0).image();
+    let surface = crate::sdl2::to_surface(&image).unwrap();
+    assert_eq!((surface.width(), surface.height()), image.dimensions());
+    let pitch = surface.pitch() as usize;
+    let stride = image.width() as usize * 4;
+    surface.with_lock(|pixels| {
+        for (y, row) in image.as_raw().chunks(stride).enumerate() {
+            assert_eq!(&pixels[y * pitch..y * pitch + stride], row);
+        }
+    });
+}
+
+#[cfg(feature = "wgpu")]
+#[test]
+fn wgpu_padded_rows() {
+    use image::RgbaImage;
+
+    let f = load_test_file("layers_and_tags");
+    let image = f.frame(0).image();
+    let (data, bytes_per_row) = crate::wgpu::padded_rows(&image);
+    assert_eq!(bytes_per_row % ::wgpu::COPY_BYTES_PER_ROW_ALIGNMENT, 0);
+    assert!(bytes_per_row >= image.width() * 4);
+    assert_eq!(data.len(), (bytes_per_row * image.height()) as usize);
+    let stride = image.width() as usize * 4;
+    for (row, pixels) in data
+        .chunks(bytes_per_row as usize)
+        .zip(image.as_raw().chunks(stride))
+    {
+        assert_eq!(&row[..stride], pixels);
+        assert!(row[stride..].iter().all(|&byte| byte == 0));
+    }
+
+    // Rows that are already aligned are not padded.
+    let aligned = RgbaImage::new(64, 2);
+    assert_eq!(crate::wgpu::padded_rows(&aligned), (vec![0; 512], 256));
+    let empty = RgbaImage::new(0, 0);
+    assert_eq!(crate::wgpu::padded_rows(&empty), (Vec::new(), 256));
+}
+
+#[cfg(feature = "capi")]
+#[test]
+fn capi_read() {
+    use crate::capi::*;
+    use std::ffi::{CStr, CString};
+
+    let data = std::fs::read("tests/data/layers_and_tags.aseprite").unwrap();
+    let f = load_test_file("layers_and_tags");
+    unsafe {
+        let file = asefile_read(data.as_ptr(), data.len());
+        assert!(!file.is_null());
+        assert_eq!(asefile_num_frames(file), f.num_frames());
+        let (width, height) = (asefile_width(file), asefile_height(file));
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        assert_eq!(
+            asefile_frame_rgba(file, 1, pixels.as_mut_ptr(), pixels.len()),
+            0
+        );
+        assert_eq!(pixels, f.frame(1).image().into_raw());
+        assert_eq!(asefile_frame_rgba(file, 1, pixels.as_mut_ptr(), 4), -1);
+        assert_eq!(asefile_frame_duration(file, 1), f.frame(1).duration());
+
+        assert_eq!(asefile_num_tags(file), f.num_tags());
+        let mut tag = std::mem::zeroed();
+        assert_eq!(asefile_tag(file, 0, &mut tag), 0);
+        assert_eq!(CStr::from_ptr(tag.name).to_str().unwrap(), f.tag(0).name());
+        assert_eq!(tag.to_frame, f.tag(0).to_frame());
+        assert_eq!(asefile_tag(file, f.num_tags(), &mut tag), -1);
+        let error = CStr::from_ptr(asefile_last_error()).to_str().unwrap();
+        assert!(error.starts_with("Tag does not exist"));
+        asefile_free(file);
+
+        let path = CString::new("tests/data/slice.aseprite").unwrap();
+        let file = asefile_open(path.as_ptr());
+        assert_eq!(asefile_num_slices(file), 1);
+        let mut slice = std::mem::zeroed();
+        assert_eq!(asefile_slice(file, 0, 0, &mut slice), 0);
+        let key = load_test_file("slice").slices()[0].keys[0];
+        assert_eq!((slice.x, slice.width), (key.origin.x, key.size.width));
+        asefile_free(file);
+
+        let path = CString::new("tests/data/missing.aseprite").unwrap();
+        assert!(asefile_open(path.as_ptr()).is_null());
+        assert!(!asefile_last_error().is_null());
+
+        let null = std::ptr::null();
+        assert_eq!(asefile_width(null), 0);
+        assert_eq!(asefile_num_tags(null), 0);
+        assert_eq!(asefile_frame_rgba(null, 0, pixels.as_mut_ptr(), 4), -1);
+        assert_eq!(asefile_tag(null, 0, &mut tag), -1);
+        assert_eq!(asefile_slice(null, 0, 0, &mut slice), -1);
+        let error = CStr::from_ptr(asefile_last_error()).to_str().unwrap();
+        assert_eq!(error, "File is null");
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn wasm_sprite() {
+    use crate::wasm::Sprite;
+
+    let data = std::fs::read("tests/data/layers_and_tags.aseprite").unwrap();
+    let f = load_test_file("layers_and_tags");
+    let sprite = Sprite::new(&data).unwrap();
+    assert_eq!(sprite.num_frames(), f.num_frames());
+    assert_eq!(sprite.width(), f.width() as u32);
+    assert_eq!(sprite.frame_rgba(1), Some(f.frame(1).image().into_raw()));
+    assert_eq!(sprite.frame_rgba(f.num_frames()), None);
+    assert_eq!(sprite.layer_name(0).as_deref(), Some(f.layer(0).name()));
+    assert_eq!(sprite.tag_name(0).as_deref(), Some(f.tag(0).name()));
+    assert_eq!(sprite.tag_to_frame(0), Some(f.tag(0).to_frame()));
+    assert_eq!(sprite.tag_name(f.num_tags()), None);
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn read_async() {
+    use std::future::Future;
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    // Returns a few bytes at a time and is not ready every other time.
+    struct SlowReader {
+        data: Vec<u8>,
+        position: usize,
+        ready: bool,
+    }
+
+    impl futures_io::AsyncRead for SlowReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let start = self.position;
+            let len = buf.len().min(100).min(self.data.len() - start);
+            buf[..len].copy_from_slice(&self.data[start..start + len]);
+            self.position += len;
+            Poll::Ready(Ok(len))
+        }
+    }
+
+    let reader = SlowReader {
+        data: std::fs::read("tests/data/layers_and_tags.aseprite").unwrap(),
+        position: 0,
+        ready: false,
+    };
+    let mut future = Box::pin(AsepriteFile::read_async(reader));
+    let mut cx = Context::from_waker(Waker::noop());
+    let f = loop {
+        if let Poll::Ready(result) = future.as_mut().poll(&mut cx) {
+            break result.unwrap();
+        }
+    };
+    let expected = load_test_file("layers_and_tags");
+    assert_eq!(f.num_frames(), expected.num_frames());
+    assert_eq!(f.frame(1).image(), expected.frame(1).image());
 }
-// */
@@ -1,18 +1,47 @@
-use crate::{reader::AseReader, tilemap::TileBitmaskHeader, Result};
-use std::{io::Read, ops::Index};
+use crate::io::Read;
+use crate::{reader::AseReader, tilemap::TileBitmaskHeader, AsepriteParseError, Result};
+use alloc::{format, vec::Vec};
+use core::ops::Index;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) struct TileId(pub(crate) u32);
 
-#[derive(Debug)]
-pub(crate) struct Tile {
-    pub id: TileId,
-    pub flip_x: bool,
-    pub flip_y: bool,
-    pub rotate_90cw: bool,
+/// A reference to a tile of a [Tileset](crate::Tileset), as stored in a
+/// tilemap.
+///
+/// The flags describe how the tile is transformed when it is drawn. Flips are
+/// applied before the rotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub(crate) id: TileId,
+    pub(crate) flip_x: bool,
+    pub(crate) flip_y: bool,
+    pub(crate) rotate_90cw: bool,
 }
 
 impl Tile {
+    /// The id of the tile in the tileset. If the tileset's
+    /// [empty_tile_is_id_zero](crate::Tileset::empty_tile_is_id_zero) is set,
+    /// id 0 is the empty tile.
+    pub fn id(&self) -> u32 {
+        self.id.0
+    }
+
+    /// The tile is mirrored horizontally.
+    pub fn flip_x(&self) -> bool {
+        self.flip_x
+    }
+
+    /// The tile is mirrored vertically.
+    pub fn flip_y(&self) -> bool {
+        self.flip_y
+    }
+
+    /// The tile is rotated by 90 degrees clockwise.
+    pub fn rotate_90cw(&self) -> bool {
+        self.rotate_90cw
+    }
+
     pub(crate) fn new(chunk: &[u8], header: &TileBitmaskHeader) -> Result<Self> {
         AseReader::new(chunk)
             .dword()
@@ -30,16 +59,25 @@ impl Tile {
 }
 
 #[derive(Debug)]
-pub(crate) struct Tiles(Vec<Tile>);
+pub(crate) struct Tiles(pub(crate) Vec<Tile>);
 
 impl Tiles {
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Tile> {
+        self.0.iter()
+    }
+
     pub(crate) fn unzip<T: Read>(
         reader: AseReader<T>,
         expected_tile_count: usize,
         header: &TileBitmaskHeader,
     ) -> Result<Self> {
         // Only 32-bit tiles supported for now
-        let expected_output_size = 4 * expected_tile_count;
+        let expected_output_size = expected_tile_count.checked_mul(4).ok_or_else(|| {
+            AsepriteParseError::InvalidInput(format!(
+                "Tilemap too large: {} tiles",
+                expected_tile_count
+            ))
+        })?;
         let bytes = reader.unzip(expected_output_size)?;
         let tiles: Result<Vec<Tile>> = bytes
             .chunks_exact(4)
@@ -60,3 +98,37 @@ impl Index<usize> for Tiles {
 fn as_bool(bitwise_and: u32) -> bool {
     bitwise_and != 0
 }
+
+/// A grid of [Tile] references in row-major order. See
+/// [AsepriteFile::layer_to_tileset](crate::AsepriteFile::layer_to_tileset).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileGrid {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) tiles: Vec<Tile>,
+}
+
+impl TileGrid {
+    /// Number of tiles per row.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Number of rows.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The tile at the given column and row, if it is inside the grid.
+    pub fn tile(&self, x: u32, y: u32) -> Option<&Tile> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.tiles.get((y * self.width + x) as usize)
+    }
+
+    /// All tiles, row by row.
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+}
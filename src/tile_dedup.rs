@@ -0,0 +1,211 @@
+use crate::image::RgbaImage;
+use crate::{
+    pixel::Pixels,
+    tile::{Tile, TileGrid, TileId},
+    tileset::{TileSize, Tileset, TilesetId},
+    AsepriteFile, AsepriteParseError, Result,
+};
+use std::collections::HashMap;
+
+/// Options for [AsepriteFile::layer_to_tileset_with_options].
+///
+/// ```
+/// # use asefile::TileDedupOptions;
+/// let options = TileDedupOptions::new().detect_flips(true);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TileDedupOptions {
+    detect_flips: bool,
+}
+
+impl TileDedupOptions {
+    /// Default options: Only identical tiles are merged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also merge tiles that are mirror images of each other, horizontally,
+    /// vertically, or both. The grid records the flips in the tile's
+    /// [flip_x](Tile::flip_x) and [flip_y](Tile::flip_y) flags. Defaults to
+    /// `false`.
+    pub fn detect_flips(mut self, detect: bool) -> Self {
+        self.detect_flips = detect;
+        self
+    }
+}
+
+/// # Tilesets from image layers
+impl AsepriteFile {
+    /// Cut the image of a layer into tiles and merge identical ones. Returns
+    /// the merged tiles as a [Tileset] and the grid that places them on the
+    /// canvas.
+    ///
+    /// Tile 0 of the tileset is the empty tile, as in tilesets created by
+    /// Aseprite, and fully transparent tiles refer to it. The other tiles are
+    /// numbered in the order in which they first appear, row by row. The
+    /// tileset's pixels are always RGBA.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # use std::path::Path;
+    /// # let path = Path::new("./tests/data/basic-16x16.aseprite");
+    /// let ase = AsepriteFile::read_file(&path).unwrap();
+    /// let (tileset, grid) = ase.layer_to_tileset(0, 0, (8, 8)).unwrap();
+    /// assert_eq!((grid.width(), grid.height()), (2, 2));
+    /// let first = grid.tile(0, 0).unwrap();
+    /// let image = tileset.tile_image(first.id()).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [AsepriteParseError::InvalidInput] if the layer or frame does
+    /// not exist, or if the canvas size is not a multiple of the tile size.
+    pub fn layer_to_tileset(
+        &self,
+        layer_id: u32,
+        frame: u32,
+        tile_size: (u32, u32),
+    ) -> Result<(Tileset, TileGrid)> {
+        self.layer_to_tileset_with_options(layer_id, frame, tile_size, &TileDedupOptions::new())
+    }
+
+    /// Like [layer_to_tileset](AsepriteFile::layer_to_tileset) but with custom
+    /// [TileDedupOptions].
+    pub fn layer_to_tileset_with_options(
+        &self,
+        layer_id: u32,
+        frame: u32,
+        tile_size: (u32, u32),
+        options: &TileDedupOptions,
+    ) -> Result<(Tileset, TileGrid)> {
+        self.check_layer(layer_id)?;
+        self.check_frame(frame)?;
+        let (tile_width, tile_height) = tile_size;
+        let (width, height) = (self.width as u32, self.height as u32);
+        if tile_width == 0
+            || tile_height == 0
+            || width % tile_width != 0
+            || height % tile_height != 0
+        {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Canvas size {}x{} is not a multiple of the tile size {}x{}",
+                width, height, tile_width, tile_height
+            )));
+        }
+        let image = self.layer_image(frame as u16, layer_id as usize);
+
+        let pixels_per_tile = (tile_width * tile_height) as usize;
+        let mut dedup = Dedup {
+            // The empty tile.
+            pixels: vec![crate::image::Rgba([0, 0, 0, 0]); pixels_per_tile],
+            ids: HashMap::new(),
+            tile_count: 1,
+        };
+        let (columns, rows) = (width / tile_width, height / tile_height);
+        let mut tiles = Vec::with_capacity((columns * rows) as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                let tile = cut_tile(&image, column * tile_width, row * tile_height, tile_size);
+                tiles.push(dedup.tile(tile, tile_size, options));
+            }
+        }
+
+        let name = self.layer(layer_id).name().to_owned();
+        let tileset = Tileset {
+            id: TilesetId::new(0),
+            empty_tile_is_id_zero: true,
+            tile_count: dedup.tile_count,
+            tile_size: TileSize {
+                width: tile_width as u16,
+                height: tile_height as u16,
+            },
+            base_index: 1,
+            name,
+            external_file: None,
+            pixels: Some(Pixels::Rgba(dedup.pixels)),
+            user_data: None,
+            tile_user_data: Vec::new(),
+        };
+        let grid = TileGrid {
+            width: columns,
+            height: rows,
+            tiles,
+        };
+        Ok((tileset, grid))
+    }
+}
+
+// Tiles found so far.
+struct Dedup {
+    pixels: Vec<crate::image::Rgba<u8>>,
+    ids: HashMap<Vec<crate::image::Rgba<u8>>, u32>,
+    tile_count: u32,
+}
+
+impl Dedup {
+    fn tile(
+        &mut self,
+        pixels: Vec<crate::image::Rgba<u8>>,
+        tile_size: (u32, u32),
+        options: &TileDedupOptions,
+    ) -> Tile {
+        let tile = |id: u32, flip_x: bool, flip_y: bool| Tile {
+            id: TileId(id),
+            flip_x,
+            flip_y,
+            rotate_90cw: false,
+        };
+        if pixels.iter().all(|px| px.0[3] == 0) {
+            return tile(0, false, false);
+        }
+        if let Some(&id) = self.ids.get(&pixels) {
+            return tile(id, false, false);
+        }
+        if options.detect_flips {
+            for &(flip_x, flip_y) in &[(true, false), (false, true), (true, true)] {
+                let flipped = flip(&pixels, tile_size, flip_x, flip_y);
+                if let Some(&id) = self.ids.get(&flipped) {
+                    return tile(id, flip_x, flip_y);
+                }
+            }
+        }
+        let id = self.tile_count;
+        self.tile_count += 1;
+        self.pixels.extend_from_slice(&pixels);
+        self.ids.insert(pixels, id);
+        tile(id, false, false)
+    }
+}
+
+fn cut_tile(
+    image: &RgbaImage,
+    x: u32,
+    y: u32,
+    (width, height): (u32, u32),
+) -> Vec<crate::image::Rgba<u8>> {
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for ty in y..y + height {
+        for tx in x..x + width {
+            pixels.push(*image.get_pixel(tx, ty));
+        }
+    }
+    pixels
+}
+
+fn flip(
+    pixels: &[crate::image::Rgba<u8>],
+    (width, height): (u32, u32),
+    flip_x: bool,
+    flip_y: bool,
+) -> Vec<crate::image::Rgba<u8>> {
+    let (width, height) = (width as usize, height as usize);
+    let mut result = Vec::with_capacity(pixels.len());
+    for y in 0..height {
+        let y = if flip_y { height - 1 - y } else { y };
+        for x in 0..width {
+            let x = if flip_x { width - 1 - x } else { x };
+            result.push(pixels[y * width + x]);
+        }
+    }
+    result
+}
@@ -1,7 +1,9 @@
-use std::io::Read;
+use crate::io::Read;
+use alloc::format;
 
-use crate::{reader::AseReader, tile, AsepriteParseError, Result};
+use crate::{limits::Budget, reader::AseReader, tile, AsepriteParseError, Result};
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub(crate) struct Tilemap {
     pub width: u16,  // width in number of tiles
@@ -12,7 +14,10 @@ pub(crate) struct Tilemap {
 }
 
 impl Tilemap {
-    pub(crate) fn parse_chunk<R: Read>(mut reader: AseReader<R>) -> Result<Self> {
+    pub(crate) fn parse_chunk<R: Read>(
+        mut reader: AseReader<R>,
+        budget: &mut Budget,
+    ) -> Result<Self> {
         let width = reader.word()?;
         let height = reader.word()?;
         let bits_per_tile = reader.word()?;
@@ -25,6 +30,7 @@ impl Tilemap {
         let bitmask_header = TileBitmaskHeader::parse(&mut reader)?;
         reader.skip_reserved(10)?;
         let expected_tile_count = width as usize * height as usize;
+        budget.reserve("Tilemap", expected_tile_count as u64 * 4)?;
         let tiles = tile::Tiles::unzip(reader, expected_tile_count, &bitmask_header)?;
         Ok(Self {
             width,
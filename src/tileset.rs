@@ -1,13 +1,23 @@
-use std::{collections::HashMap, fmt, io::Read};
-
-use crate::{pixel::Pixels, AsepriteParseError, ColorPalette, PixelFormat, Result};
+use crate::collections::Map;
+use crate::io::Read;
+use alloc::{format, string::String, vec, vec::Vec};
+use core::fmt;
+
+#[cfg(feature = "image")]
+use crate::image::{Rgba, RgbaImage};
+use crate::{
+    pixel::{IndexResolverData, Pixels},
+    raw_image,
+    user_data::UserData,
+    AsepriteParseError, ColorPalette, PixelFormat, Result,
+};
 use bitflags::bitflags;
-use image::{Rgba, RgbaImage};
 
-use crate::{external_file::ExternalFileId, reader::AseReader};
+use crate::{external_file::ExternalFileId, limits::Budget, reader::AseReader};
 
 /// An id for a [Tileset].
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TilesetId(pub(crate) u32);
 
 impl TilesetId {
@@ -44,8 +54,8 @@ bitflags! {
 /// A [Tileset] reference to an [ExternalFile].
 #[derive(Debug)]
 pub struct ExternalTilesetReference {
-    external_file_id: ExternalFileId,
-    tileset_id: TilesetId,
+    pub(crate) external_file_id: ExternalFileId,
+    pub(crate) tileset_id: TilesetId,
 }
 
 impl ExternalTilesetReference {
@@ -70,8 +80,8 @@ impl ExternalTilesetReference {
 /// The size of a tile in pixels.
 #[derive(Debug)]
 pub struct TileSize {
-    width: u16,
-    height: u16,
+    pub(crate) width: u16,
+    pub(crate) height: u16,
 }
 
 impl TileSize {
@@ -85,8 +95,8 @@ impl TileSize {
         &self.height
     }
 
-    pub(crate) fn pixels_per_tile(&self) -> u16 {
-        self.width * self.height
+    pub(crate) fn pixels_per_tile(&self) -> u32 {
+        self.width as u32 * self.height as u32
     }
 }
 
@@ -101,6 +111,9 @@ pub struct Tileset {
     pub(crate) name: String,
     pub(crate) external_file: Option<ExternalTilesetReference>,
     pub(crate) pixels: Option<Pixels>,
+    pub(crate) user_data: Option<UserData>,
+    // Indexed by tile id. May be shorter than the number of tiles.
+    pub(crate) tile_user_data: Vec<Option<UserData>>,
 }
 
 impl Tileset {
@@ -142,6 +155,128 @@ impl Tileset {
         self.external_file.as_ref()
     }
 
+    /// Returns a reference to the tileset's [UserData], if any exists. This
+    /// does not include the user data of individual tiles, see
+    /// [tile_user_data](Tileset::tile_user_data).
+    pub fn user_data(&self) -> Option<&UserData> {
+        self.user_data.as_ref()
+    }
+
+    /// Returns a reference to the [UserData] of the tile with the given id,
+    /// if any exists.
+    pub fn tile_user_data(&self, tile_id: u32) -> Option<&UserData> {
+        self.tile_user_data
+            .get(tile_id as usize)
+            .and_then(Option::as_ref)
+    }
+
+    // The tile that is drawn for `tile_id`. Aseprite keeps the ids of deleted
+    // tiles in tilemaps, and draws them as tile 0.
+    pub(crate) fn drawn_tile_id(&self, tile_id: u32) -> u32 {
+        if tile_id < self.tile_count {
+            tile_id
+        } else {
+            0
+        }
+    }
+
+    /// The image of a tile.
+    ///
+    /// Returns `None` if the tileset is indexed (see
+    /// [AsepriteFile::tileset_image](crate::AsepriteFile::tileset_image)
+    /// for resolving indices), contains no pixel data, or has no tile with
+    /// the given id.
+    #[cfg(feature = "image")]
+    pub fn tile_image(&self, tile_id: u32) -> Option<RgbaImage> {
+        let image = self.raw_tile_image(tile_id)?;
+        let (width, height) = image.dimensions();
+        RgbaImage::from_raw(width, height, image.into_raw())
+    }
+
+    /// Like [tile_image](Tileset::tile_image) but returns a
+    /// [raw_image::RgbaImage]. This is available without the `image`
+    /// feature.
+    pub fn raw_tile_image(&self, tile_id: u32) -> Option<raw_image::RgbaImage> {
+        if tile_id >= self.tile_count {
+            return None;
+        }
+        let pixels = match &self.pixels {
+            Some(Pixels::Indexed(_)) | None => return None,
+            Some(pixels) => pixels,
+        };
+        let pixels_per_tile = self.tile_size.pixels_per_tile() as usize;
+        let start = tile_id as usize * pixels_per_tile;
+        let resolver_data = IndexResolverData {
+            palette: None,
+            transparent_color_index: None,
+            layer_is_background: false,
+        };
+        let rgba = pixels
+            .get(start..start + pixels_per_tile)
+            .clone_as_image_rgba(resolver_data);
+        let Self { tile_size, .. } = self;
+        raw_image::RgbaImage::from_raw(
+            tile_size.width as u32,
+            tile_size.height as u32,
+            rgba.iter().flat_map(|px| px.0).collect(),
+        )
+    }
+
+    /// The palette indices of a tile's pixels in row-major order.
+    ///
+    /// Returns `None` if the tileset is not indexed, contains no pixel data,
+    /// or has no tile with the given id.
+    ///
+    /// Indices are returned verbatim. In particular, the file's
+    /// [transparent color index](PixelFormat::transparent_color_index) is
+    /// not treated specially; handling transparency is up to the renderer.
+    pub fn tile_indexed_pixels(&self, tile_id: u32) -> Option<&[u8]> {
+        if tile_id >= self.tile_count {
+            return None;
+        }
+        match &self.pixels {
+            Some(Pixels::Indexed(indices)) => {
+                let pixels_per_tile = self.tile_size.pixels_per_tile() as usize;
+                let start = tile_id as usize * pixels_per_tile;
+                Some(&indices[start..start + pixels_per_tile])
+            }
+            _ => None,
+        }
+    }
+
+    /// The palette indices of all tiles arranged in a grid with `columns`
+    /// tiles per row, in the same order as their ids. Returns the indices in
+    /// row-major order together with the width and height of the grid in
+    /// pixels. Unused cells of the last row are filled with index 0.
+    ///
+    /// Returns `None` in the same cases as
+    /// [tile_indexed_pixels](Tileset::tile_indexed_pixels), and indices are
+    /// also returned verbatim. A `columns` value of 0 is treated as 1.
+    pub fn indexed_atlas(&self, columns: u32) -> Option<(Vec<u8>, u32, u32)> {
+        if !matches!(self.pixels, Some(Pixels::Indexed(_))) {
+            return None;
+        }
+        let tile_width = self.tile_size.width as usize;
+        let tile_height = self.tile_size.height as usize;
+        let columns = columns.clamp(1, self.tile_count.max(1));
+        let rows = self.tile_count.div_ceil(columns);
+        let width = columns as usize * tile_width;
+        let height = rows as usize * tile_height;
+        let mut atlas = vec![0_u8; width * height];
+        for tile_id in 0..self.tile_count {
+            let tile = self.tile_indexed_pixels(tile_id)?;
+            let x = (tile_id % columns) as usize * tile_width;
+            let y = (tile_id / columns) as usize * tile_height;
+            for row in 0..tile_height {
+                let start = (y + row) * width + x;
+                atlas[start..start + tile_width]
+                    .copy_from_slice(&tile[row * tile_width..(row + 1) * tile_width]);
+            }
+        }
+        Some((atlas, width as u32, height as u32))
+    }
+
+    #[cfg(feature = "image")]
     pub(crate) fn write_to_image(&self, image_pixels: &[Rgba<u8>]) -> RgbaImage {
         let Tileset {
             tile_size,
@@ -151,7 +286,7 @@ impl Tileset {
         let TileSize { width, height } = tile_size;
         let tile_width = *width as u32;
         let tile_height = *height as u32;
-        let pixels_per_tile = tile_size.pixels_per_tile() as u32;
+        let pixels_per_tile = tile_size.pixels_per_tile();
         let image_height = tile_count * tile_height;
         let mut image = RgbaImage::new(tile_width, image_height);
         for tile_idx in 0..*tile_count {
@@ -173,7 +308,11 @@ impl Tileset {
         image
     }
 
-    pub(crate) fn parse_chunk(data: &[u8], pixel_format: PixelFormat) -> Result<Tileset> {
+    pub(crate) fn parse_chunk(
+        data: &[u8],
+        pixel_format: PixelFormat,
+        budget: &mut Budget,
+    ) -> Result<Tileset> {
         let mut reader = AseReader::new(data);
         let id = reader.dword().map(TilesetId)?;
         let flags = reader.dword().map(|val| TilesetFlags { bits: val })?;
@@ -201,9 +340,23 @@ impl Tileset {
                 None
             } else {
                 let _compressed_length = reader.dword()?;
-                let expected_pixel_count =
-                    (tile_count * (tile_height as u32) * (tile_width as u32)) as usize;
-                Pixels::from_compressed(reader, pixel_format, expected_pixel_count).map(Some)?
+                // Also guarantees that `write_to_image` can't overflow.
+                let expected_pixel_count = tile_count
+                    .checked_mul(tile_height as u32)
+                    .and_then(|n| n.checked_mul(tile_width as u32))
+                    .ok_or_else(|| {
+                        AsepriteParseError::InvalidInput(format!(
+                            "Tileset too large: {} tiles of size {}x{}",
+                            tile_count, tile_width, tile_height
+                        ))
+                    })? as usize;
+                budget.reserve(
+                    "Tileset image",
+                    expected_pixel_count as u64 * pixel_format.bytes_per_pixel() as u64,
+                )?;
+                let mut pixels = Pixels::new(pixel_format);
+                pixels.read_compressed(reader, expected_pixel_count)?;
+                Some(pixels)
             }
         };
         Ok(Tileset {
@@ -215,17 +368,19 @@ impl Tileset {
             name,
             external_file,
             pixels,
+            user_data: None,
+            tile_user_data: Vec::new(),
         })
     }
 }
 
 /// A map of [TilesetId] values to [Tileset] instances.
 #[derive(Debug)]
-pub struct TilesetsById(HashMap<TilesetId, Tileset>);
+pub struct TilesetsById(Map<TilesetId, Tileset>);
 
 impl TilesetsById {
     pub(crate) fn new() -> Self {
-        Self(HashMap::new())
+        Self(Map::new())
     }
 
     pub(crate) fn add(&mut self, tileset: Tileset) {
@@ -233,7 +388,7 @@ impl TilesetsById {
     }
 
     /// Returns a reference to the underlying HashMap value.
-    pub fn map(&self) -> &HashMap<TilesetId, Tileset> {
+    pub fn map(&self) -> &Map<TilesetId, Tileset> {
         &self.0
     }
 
@@ -242,6 +397,15 @@ impl TilesetsById {
         self.0.get(id)
     }
 
+    pub(crate) fn get_mut(&mut self, id: &TilesetId) -> Option<&mut Tileset> {
+        self.0.get_mut(id)
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn map_mut(&mut self) -> &mut Map<TilesetId, Tileset> {
+        &mut self.0
+    }
+
     pub(crate) fn validate(
         &self,
         pixel_format: &PixelFormat,
@@ -251,10 +415,10 @@ impl TilesetsById {
             // Validates that all Tilesets contain their own pixel data.
             // External file references currently not supported.
             let pixels = tileset.pixels.as_ref().ok_or_else(|| {
-                AsepriteParseError::UnsupportedFeature(
-                    "Expected Tileset data to contain pixels. External file Tilesets not supported"
-                        .into(),
-                )
+                AsepriteParseError::UnsupportedFeature(format!(
+                    "Tileset {} has no pixels. Load the tiles of external tilesets with ReadOptions::external_resolver",
+                    tileset.id.0
+                ))
             })?;
 
             if let Pixels::Indexed(indexed_pixels) = pixels {
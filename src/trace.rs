@@ -0,0 +1,18 @@
+// Thin wrappers around the `tracing` macros. With the `tracing` feature
+// disabled they expand to nothing, so instrumentation has no cost in normal
+// builds. Arguments are only evaluated when the feature is enabled.
+
+// Enters a span that stays open until the end of the enclosing block.
+macro_rules! trace_span {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::span!(tracing::Level::$level, $($arg)+).entered();
+    };
+}
+
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::event!(tracing::Level::$level, $($arg)+);
+    };
+}
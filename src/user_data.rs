@@ -1,16 +1,386 @@
-use crate::{reader::AseReader, Result};
-use image::Pixel;
+use crate::external_file::{ExternalFileId, ExternalFilesById};
+use crate::image::Pixel;
+use crate::io::Read;
+use crate::{reader::AseReader, AsepriteParseError, Result};
+use alloc::{borrow::ToOwned, collections::BTreeMap, format, string::String, vec::Vec};
+use core::{convert::TryFrom, fmt};
+
+// Nested maps and vectors deeper than this are rejected, so that corrupt
+// files cannot overflow the stack.
+const MAX_PROPERTY_DEPTH: u32 = 64;
 
 /// UserData contains user-provided metadata which describes some other data in the sprite.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UserData {
     /// User-provided string data.
     pub text: Option<String>,
     /// User-provided color.
-    pub color: Option<image::Rgba<u8>>,
+    #[cfg(feature = "image")]
+    #[cfg_attr(feature = "serde", serde(with = "color_serde"))]
+    pub color: Option<crate::image::Rgba<u8>>,
+    // Without the `image` feature the color is only available through
+    // `raw_color`, so that enabling the feature does not change the type of a
+    // public field.
+    #[cfg(not(feature = "image"))]
+    #[cfg_attr(feature = "serde", serde(with = "color_serde"))]
+    pub(crate) color: Option<crate::image::Rgba<u8>>,
+    /// Custom properties from Aseprite 1.3, grouped by the extension that
+    /// defines them. The key of the properties that users edit in Aseprite
+    /// is the empty string, that of an extension's properties is the
+    /// [name](crate::ExternalFile::name) of its
+    /// [ExternalFileType::PropertiesExtension](crate::ExternalFileType::PropertiesExtension)
+    /// entry.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub properties: BTreeMap<String, Properties>,
+}
+
+impl UserData {
+    /// The user-provided color as red, green, blue and alpha channels. This
+    /// is available without the `image` feature.
+    pub fn raw_color(&self) -> Option<[u8; 4]> {
+        self.color.map(|color| color.0)
+    }
+
+    /// The property `name` of `extension`, converted to `T`. Use `""` as
+    /// `extension` for the properties that users edit in Aseprite.
+    ///
+    /// Integers convert to any integer type that can hold their value, since
+    /// Aseprite stores them with the smallest type that fits. Numbers convert
+    /// to `f64`, and to `f32` if they are not `F64` values. Use
+    /// [PropertyValue] as `T` to get the value as it is.
+    ///
+    /// ```
+    /// # use asefile::{PropertyError, PropertyValue, UserData};
+    /// let mut user_data = UserData {
+    ///     text: None,
+    ///     color: None,
+    ///     properties: Default::default(),
+    /// };
+    /// let properties = user_data.properties.entry("my_ext".to_string()).or_default();
+    /// properties.insert("hp".to_string(), PropertyValue::U8(12));
+    ///
+    /// assert_eq!(user_data.property::<i64>("my_ext", "hp").unwrap(), 12);
+    /// assert!(matches!(
+    ///     user_data.property::<&str>("my_ext", "hp"),
+    ///     Err(PropertyError::WrongType { .. })
+    /// ));
+    /// assert!(matches!(
+    ///     user_data.property::<i64>("my_ext", "mp"),
+    ///     Err(PropertyError::Missing { .. })
+    /// ));
+    /// ```
+    pub fn property<'a, T: FromPropertyValue<'a>>(
+        &'a self,
+        extension: &str,
+        name: &str,
+    ) -> core::result::Result<T, PropertyError> {
+        let value = self
+            .properties
+            .get(extension)
+            .and_then(|properties| properties.get(name))
+            .ok_or_else(|| PropertyError::Missing {
+                extension: extension.to_owned(),
+                name: name.to_owned(),
+            })?;
+        T::from_property_value(value).ok_or_else(|| PropertyError::WrongType {
+            extension: extension.to_owned(),
+            name: name.to_owned(),
+            expected: core::any::type_name::<T>(),
+            found: value.type_name(),
+        })
+    }
+}
+
+/// An error occured while looking up a property with [UserData::property].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropertyError {
+    /// The user data has no property with this name.
+    Missing {
+        /// The extension of the property.
+        extension: String,
+        /// The name of the property.
+        name: String,
+    },
+    /// The property's value cannot be converted to the requested type.
+    WrongType {
+        /// The extension of the property.
+        extension: String,
+        /// The name of the property.
+        name: String,
+        /// The requested type.
+        expected: &'static str,
+        /// The type of the value, see [PropertyValue::type_name].
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for PropertyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let describe = |f: &mut fmt::Formatter<'_>, extension: &str, name: &str| {
+            if extension.is_empty() {
+                write!(f, "Property {:?}", name)
+            } else {
+                write!(f, "Property {:?} of extension {:?}", name, extension)
+            }
+        };
+        match self {
+            PropertyError::Missing { extension, name } => {
+                describe(f, extension, name)?;
+                write!(f, " not found")
+            }
+            PropertyError::WrongType {
+                extension,
+                name,
+                expected,
+                found,
+            } => {
+                describe(f, extension, name)?;
+                write!(f, " is a {}, expected {}", found, expected)
+            }
+        }
+    }
+}
+
+impl core::error::Error for PropertyError {}
+
+/// Conversion of property values for [UserData::property].
+pub trait FromPropertyValue<'a>: Sized {
+    /// The converted value, or `None` if `value` has an incompatible type.
+    fn from_property_value(value: &'a PropertyValue) -> Option<Self>;
+}
+
+impl<'a> FromPropertyValue<'a> for &'a PropertyValue {
+    fn from_property_value(value: &'a PropertyValue) -> Option<Self> {
+        Some(value)
+    }
 }
 
-pub(crate) fn parse_userdata_chunk(data: &[u8]) -> Result<UserData> {
+impl<'a> FromPropertyValue<'a> for PropertyValue {
+    fn from_property_value(value: &'a PropertyValue) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl<'a> FromPropertyValue<'a> for bool {
+    fn from_property_value(value: &'a PropertyValue) -> Option<Self> {
+        match value {
+            PropertyValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! integer_from_property_value {
+    ($($int:ty),*) => {
+        $(
+            impl<'a> FromPropertyValue<'a> for $int {
+                fn from_property_value(value: &'a PropertyValue) -> Option<Self> {
+                    match *value {
+                        PropertyValue::I8(value) => Self::try_from(value).ok(),
+                        PropertyValue::U8(value) => Self::try_from(value).ok(),
+                        PropertyValue::I16(value) => Self::try_from(value).ok(),
+                        PropertyValue::U16(value) => Self::try_from(value).ok(),
+                        PropertyValue::I32(value) => Self::try_from(value).ok(),
+                        PropertyValue::U32(value) => Self::try_from(value).ok(),
+                        PropertyValue::I64(value) => Self::try_from(value).ok(),
+                        PropertyValue::U64(value) => Self::try_from(value).ok(),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+integer_from_property_value!(i8, u8, i16, u16, i32, u32, i64, u64);
+
+impl<'a> FromPropertyValue<'a> for f64 {
+    fn from_property_value(value: &'a PropertyValue) -> Option<Self> {
+        match *value {
+            PropertyValue::Fixed(value) | PropertyValue::F64(value) => Some(value),
+            PropertyValue::F32(value) => Some(value as f64),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> FromPropertyValue<'a> for f32 {
+    fn from_property_value(value: &'a PropertyValue) -> Option<Self> {
+        match *value {
+            PropertyValue::Fixed(value) => Some(value as f32),
+            PropertyValue::F32(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> FromPropertyValue<'a> for &'a str {
+    fn from_property_value(value: &'a PropertyValue) -> Option<Self> {
+        match value {
+            PropertyValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> FromPropertyValue<'a> for String {
+    fn from_property_value(value: &'a PropertyValue) -> Option<Self> {
+        <&str>::from_property_value(value).map(ToOwned::to_owned)
+    }
+}
+
+impl<'a> FromPropertyValue<'a> for &'a [PropertyValue] {
+    fn from_property_value(value: &'a PropertyValue) -> Option<Self> {
+        match value {
+            PropertyValue::Vector(elements) => Some(elements),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> FromPropertyValue<'a> for &'a Properties {
+    fn from_property_value(value: &'a PropertyValue) -> Option<Self> {
+        match value {
+            PropertyValue::Map(properties) => Some(properties),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> FromPropertyValue<'a> for [u8; 16] {
+    fn from_property_value(value: &'a PropertyValue) -> Option<Self> {
+        match value {
+            PropertyValue::Uuid(uuid) => Some(*uuid),
+            _ => None,
+        }
+    }
+}
+
+/// Custom properties by name. See [UserData::properties].
+pub type Properties = BTreeMap<String, PropertyValue>;
+
+/// The value of a custom property, see [UserData::properties].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(missing_docs)]
+pub enum PropertyValue {
+    Bool(bool),
+    I8(i8),
+    U8(u8),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    /// A 16.16 fixed point number.
+    Fixed(f64),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Point {
+        x: i32,
+        y: i32,
+    },
+    Size {
+        width: i32,
+        height: i32,
+    },
+    Rect {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    },
+    /// A list of values, which may have different types.
+    Vector(Vec<PropertyValue>),
+    /// Nested properties.
+    Map(Properties),
+    Uuid([u8; 16]),
+}
+
+impl PropertyValue {
+    /// A short name of the value's type, e.g., `"i32"` or `"string"`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            PropertyValue::Bool(_) => "bool",
+            PropertyValue::I8(_) => "i8",
+            PropertyValue::U8(_) => "u8",
+            PropertyValue::I16(_) => "i16",
+            PropertyValue::U16(_) => "u16",
+            PropertyValue::I32(_) => "i32",
+            PropertyValue::U32(_) => "u32",
+            PropertyValue::I64(_) => "i64",
+            PropertyValue::U64(_) => "u64",
+            PropertyValue::Fixed(_) => "fixed",
+            PropertyValue::F32(_) => "f32",
+            PropertyValue::F64(_) => "f64",
+            PropertyValue::String(_) => "string",
+            PropertyValue::Point { .. } => "point",
+            PropertyValue::Size { .. } => "size",
+            PropertyValue::Rect { .. } => "rect",
+            PropertyValue::Vector(_) => "vector",
+            PropertyValue::Map(_) => "map",
+            PropertyValue::Uuid(_) => "uuid",
+        }
+    }
+
+    // The type code of the value in files.
+    #[cfg(feature = "std")]
+    pub(crate) fn type_code(&self) -> u16 {
+        match self {
+            PropertyValue::Bool(_) => 0x0001,
+            PropertyValue::I8(_) => 0x0002,
+            PropertyValue::U8(_) => 0x0003,
+            PropertyValue::I16(_) => 0x0004,
+            PropertyValue::U16(_) => 0x0005,
+            PropertyValue::I32(_) => 0x0006,
+            PropertyValue::U32(_) => 0x0007,
+            PropertyValue::I64(_) => 0x0008,
+            PropertyValue::U64(_) => 0x0009,
+            PropertyValue::Fixed(_) => 0x000A,
+            PropertyValue::F32(_) => 0x000B,
+            PropertyValue::F64(_) => 0x000C,
+            PropertyValue::String(_) => 0x000D,
+            PropertyValue::Point { .. } => 0x000E,
+            PropertyValue::Size { .. } => 0x000F,
+            PropertyValue::Rect { .. } => 0x0010,
+            PropertyValue::Vector(_) => 0x0011,
+            PropertyValue::Map(_) => 0x0012,
+            PropertyValue::Uuid(_) => 0x0013,
+        }
+    }
+}
+
+// image::Rgba has no serde support, store its channels as an array instead.
+#[cfg(feature = "serde")]
+mod color_serde {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        color: &Option<crate::image::Rgba<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        color.map(|color| color.0).serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<crate::image::Rgba<u8>>, D::Error> {
+        Ok(Option::<[u8; 4]>::deserialize(deserializer)?.map(crate::image::Rgba))
+    }
+}
+
+// Extension properties are stored with the id of the extension's entry in
+// `external_files`.
+pub(crate) fn parse_userdata_chunk(
+    data: &[u8],
+    external_files: &ExternalFilesById,
+) -> Result<UserData> {
     let mut reader = AseReader::new(data);
 
     let flags = reader.dword()?;
@@ -25,11 +395,122 @@ pub(crate) fn parse_userdata_chunk(data: &[u8]) -> Result<UserData> {
         let green = reader.byte()?;
         let blue = reader.byte()?;
         let alpha = reader.byte()?;
-        let rgba = image::Rgba::from_channels(red, green, blue, alpha);
+        let rgba = crate::image::Rgba::from_channels(red, green, blue, alpha);
         Some(rgba)
     } else {
         None
     };
+    let mut properties = BTreeMap::new();
+    if flags & 4 != 0 {
+        let _size = reader.dword()?;
+        let num_maps = reader.dword()?;
+        for _ in 0..num_maps {
+            let key = reader.dword()?;
+            let extension = if key == 0 {
+                String::new()
+            } else {
+                let external_file = external_files.get(&ExternalFileId::new(key));
+                external_file
+                    .map(|external_file| external_file.name().to_owned())
+                    .ok_or_else(|| {
+                        AsepriteParseError::InvalidInput(format!(
+                            "Properties of unknown extension: {}",
+                            key
+                        ))
+                    })?
+            };
+            properties.insert(extension, parse_properties(&mut reader, 0)?);
+        }
+    }
+
+    Ok(UserData {
+        text,
+        color,
+        properties,
+    })
+}
 
-    Ok(UserData { text, color })
+pub(crate) fn parse_properties<R: Read>(
+    reader: &mut AseReader<R>,
+    depth: u32,
+) -> Result<Properties> {
+    let num_properties = reader.dword()?;
+    let mut properties = Properties::new();
+    for _ in 0..num_properties {
+        let name = reader.string()?;
+        let value_type = reader.word()?;
+        let value = parse_property_value(reader, value_type, depth)?;
+        properties.insert(name, value);
+    }
+    Ok(properties)
+}
+
+fn parse_property_value<R: Read>(
+    reader: &mut AseReader<R>,
+    value_type: u16,
+    depth: u32,
+) -> Result<PropertyValue> {
+    if depth > MAX_PROPERTY_DEPTH {
+        return Err(AsepriteParseError::InvalidInput(format!(
+            "Properties nested deeper than {} levels",
+            MAX_PROPERTY_DEPTH
+        )));
+    }
+    let value = match value_type {
+        0x0001 => PropertyValue::Bool(reader.byte()? != 0),
+        0x0002 => PropertyValue::I8(reader.byte()? as i8),
+        0x0003 => PropertyValue::U8(reader.byte()?),
+        0x0004 => PropertyValue::I16(reader.short()?),
+        0x0005 => PropertyValue::U16(reader.word()?),
+        0x0006 => PropertyValue::I32(reader.long()?),
+        0x0007 => PropertyValue::U32(reader.dword()?),
+        0x0008 => PropertyValue::I64(reader.long64()?),
+        0x0009 => PropertyValue::U64(reader.qword()?),
+        0x000A => PropertyValue::Fixed(reader.fixed()?),
+        0x000B => PropertyValue::F32(f32::from_bits(reader.dword()?)),
+        0x000C => PropertyValue::F64(f64::from_bits(reader.qword()?)),
+        0x000D => PropertyValue::String(reader.string()?),
+        0x000E => PropertyValue::Point {
+            x: reader.long()?,
+            y: reader.long()?,
+        },
+        0x000F => PropertyValue::Size {
+            width: reader.long()?,
+            height: reader.long()?,
+        },
+        0x0010 => PropertyValue::Rect {
+            x: reader.long()?,
+            y: reader.long()?,
+            width: reader.long()?,
+            height: reader.long()?,
+        },
+        0x0011 => {
+            let num_elements = reader.dword()?;
+            // Zero for vectors whose elements each have their own type.
+            let element_type = reader.word()?;
+            let mut elements = Vec::new();
+            for _ in 0..num_elements {
+                let value_type = if element_type == 0 {
+                    reader.word()?
+                } else {
+                    element_type
+                };
+                elements.push(parse_property_value(reader, value_type, depth + 1)?);
+            }
+            PropertyValue::Vector(elements)
+        }
+        0x0012 => PropertyValue::Map(parse_properties(reader, depth + 1)?),
+        0x0013 => {
+            let mut uuid = [0; 16];
+            reader.read_exact(&mut uuid)?;
+            PropertyValue::Uuid(uuid)
+        }
+        _ => {
+            return Err(AsepriteParseError::InvalidInput(format!(
+                "Unknown property type: {}",
+                value_type
+            )))
+        }
+    };
+    Ok(value)
 }
@@ -0,0 +1,135 @@
+/*!
+Inspect sprites from JavaScript.
+
+Requires the `wasm` feature. The parser itself needs no file system and
+builds for `wasm32-unknown-unknown` without this feature; read sprites from
+bytes with [AsepriteFile::read]. This module adds a [Sprite] class for
+[wasm-bindgen](https://rustwasm.github.io/docs/wasm-bindgen/), so web tools
+can use the crate without writing bindings of their own:
+
+```js
+const response = await fetch("player.aseprite");
+const sprite = new Sprite(new Uint8Array(await response.arrayBuffer()));
+const pixels = new Uint8ClampedArray(sprite.frameRgba(0));
+const image = new ImageData(pixels, sprite.width, sprite.height);
+canvas.getContext("2d").putImageData(image, 0, 0);
+```
+
+Methods taking a frame, layer or tag index return `undefined` if it does not
+exist.
+*/
+use crate::AsepriteFile;
+use wasm_bindgen::prelude::*;
+
+/// A parsed sprite.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct Sprite {
+    file: AsepriteFile,
+}
+
+#[wasm_bindgen]
+impl Sprite {
+    /// Parse the contents of an Aseprite file. Throws an `Error` if the data
+    /// is not a valid sprite.
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: &[u8]) -> Result<Sprite, JsError> {
+        let file = AsepriteFile::read(data)?;
+        Ok(Sprite { file })
+    }
+
+    /// Width in pixels.
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.file.width() as u32
+    }
+
+    /// Height in pixels.
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.file.height() as u32
+    }
+
+    /// Number of frames.
+    #[wasm_bindgen(getter, js_name = numFrames)]
+    pub fn num_frames(&self) -> u32 {
+        self.file.num_frames()
+    }
+
+    /// Number of layers.
+    #[wasm_bindgen(getter, js_name = numLayers)]
+    pub fn num_layers(&self) -> u32 {
+        self.file.num_layers()
+    }
+
+    /// Number of tags.
+    #[wasm_bindgen(getter, js_name = numTags)]
+    pub fn num_tags(&self) -> u32 {
+        self.file.num_tags()
+    }
+
+    /// Duration of a frame in milliseconds.
+    #[wasm_bindgen(js_name = frameDuration)]
+    pub fn frame_duration(&self, frame: u32) -> Option<u32> {
+        self.has_frame(frame)
+            .then(|| self.file.frame(frame).duration())
+    }
+
+    /// The composited frame as RGBA data, 4 bytes per pixel in rows from top
+    /// to bottom.
+    #[wasm_bindgen(js_name = frameRgba)]
+    pub fn frame_rgba(&self, frame: u32) -> Option<Vec<u8>> {
+        self.has_frame(frame)
+            .then(|| self.file.frame(frame).raw_image().into_raw())
+    }
+
+    /// Name of a layer.
+    #[wasm_bindgen(js_name = layerName)]
+    pub fn layer_name(&self, layer: u32) -> Option<String> {
+        (layer < self.file.num_layers()).then(|| self.file.layer(layer).name().to_owned())
+    }
+
+    /// Name of a tag.
+    #[wasm_bindgen(js_name = tagName)]
+    pub fn tag_name(&self, tag: u32) -> Option<String> {
+        self.has_tag(tag)
+            .then(|| self.file.tag(tag).name().to_owned())
+    }
+
+    /// First frame of a tag.
+    #[wasm_bindgen(js_name = tagFromFrame)]
+    pub fn tag_from_frame(&self, tag: u32) -> Option<u32> {
+        self.has_tag(tag).then(|| self.file.tag(tag).from_frame())
+    }
+
+    /// Last frame of a tag.
+    #[wasm_bindgen(js_name = tagToFrame)]
+    pub fn tag_to_frame(&self, tag: u32) -> Option<u32> {
+        self.has_tag(tag).then(|| self.file.tag(tag).to_frame())
+    }
+
+    /// Names of all slices.
+    #[wasm_bindgen(js_name = sliceNames)]
+    pub fn slice_names(&self) -> Vec<String> {
+        self.file
+            .slices()
+            .iter()
+            .map(|slice| slice.name.clone())
+            .collect()
+    }
+}
+
+impl Sprite {
+    /// The parsed file.
+    pub fn file(&self) -> &AsepriteFile {
+        &self.file
+    }
+
+    fn has_frame(&self, frame: u32) -> bool {
+        frame < self.file.num_frames()
+    }
+
+    fn has_tag(&self, tag: u32) -> bool {
+        tag < self.file.num_tags()
+    }
+}
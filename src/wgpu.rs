@@ -0,0 +1,100 @@
+/*!
+Upload images to [wgpu](https://wgpu.rs) textures.
+
+Requires the `wgpu` feature. [create_texture] uploads any [RgbaImage], such
+as a composited [frame](crate::Frame::image), the image of a single layer
+([Cel::image](crate::Cel::image)), or a packed [atlas](crate::atlas), into
+a new texture and returns it together with a view for binding it:
+
+```no_run
+# use asefile::AsepriteFile;
+# use std::path::Path;
+# fn upload(device: &wgpu::Device, queue: &wgpu::Queue) {
+# let path = Path::new("./tests/data/layers_and_tags.aseprite");
+let ase = AsepriteFile::read_file(&path).unwrap();
+let (texture, view) =
+    asefile::wgpu::create_texture(device, queue, &ase.frame(0).image(), Some("player"));
+# }
+```
+
+Textures use the [Rgba8UnormSrgb](wgpu::TextureFormat::Rgba8UnormSrgb)
+format, which matches the pixel data of an [RgbaImage]. For copies through a
+buffer, which require each row to start at a multiple of
+[COPY_BYTES_PER_ROW_ALIGNMENT] bytes, [padded_rows] lays out the pixel data
+accordingly.
+*/
+use crate::image::RgbaImage;
+use ::wgpu::{
+    Device, Extent3d, Origin3d, Queue, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture,
+    TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+    TextureViewDescriptor, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+const BYTES_PER_PIXEL: u32 = 4;
+
+/// Create a texture with the image and a view of the whole texture. The
+/// texture can be sampled in shaders and used as the destination of copies.
+///
+/// # Panics
+///
+/// wgpu panics or reports a validation error if the image is larger than
+/// the device's texture size limit.
+pub fn create_texture(
+    device: &Device,
+    queue: &Queue,
+    image: &RgbaImage,
+    label: Option<&str>,
+) -> (Texture, TextureView) {
+    let (width, height) = image.dimensions();
+    let size = Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&TextureDescriptor {
+        label,
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8UnormSrgb,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let (data, bytes_per_row) = padded_rows(image);
+    queue.write_texture(
+        TexelCopyTextureInfo {
+            texture: &texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        &data,
+        TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(bytes_per_row),
+            rows_per_image: Some(height),
+        },
+        size,
+    );
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    (texture, view)
+}
+
+/// The pixel data of the image with each row padded to a multiple of
+/// [COPY_BYTES_PER_ROW_ALIGNMENT] bytes, and the padded size of a row in
+/// bytes. This is the layout wgpu requires for copying a buffer into a
+/// texture.
+pub fn padded_rows(image: &RgbaImage) -> (Vec<u8>, u32) {
+    let stride = image.width() * BYTES_PER_PIXEL;
+    let alignment = COPY_BYTES_PER_ROW_ALIGNMENT;
+    let bytes_per_row = stride.div_ceil(alignment).max(1) * alignment;
+    let mut data = vec![0; bytes_per_row as usize * image.height() as usize];
+    for (row, pixels) in data
+        .chunks_mut(bytes_per_row as usize)
+        .zip(image.as_raw().chunks(stride.max(1) as usize))
+    {
+        row[..pixels.len()].copy_from_slice(pixels);
+    }
+    (data, bytes_per_row)
+}
@@ -0,0 +1,786 @@
+use crate::{
+    cel::{CelContent, CelsData, ImageContent, RawCel},
+    color_profile::{ColorProfile, ColorProfileType},
+    external_file::{ExternalFile, ExternalFileType, ExternalFilesById},
+    layer::{LayerData, LayerType},
+    palette::ColorPalette,
+    slice::Slice,
+    tags::Tag,
+    tileset::{Tileset, TilesetId},
+    user_data::{Properties, PropertyValue, UserData},
+    AsepriteFile, PixelFormat, RawChunk,
+};
+use byteorder::{LittleEndian, WriteBytesExt};
+use flate2::{write::ZlibEncoder, Compression};
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+const HEADER_SIZE: usize = 128;
+const FRAME_HEADER_SIZE: usize = 16;
+const CHUNK_HEADER_SIZE: usize = 6;
+
+// Header flags: layer opacity and group opacity are valid, layers have UUIDs.
+const HEADER_FLAG_LAYER_OPACITY: u32 = 1;
+const HEADER_FLAG_GROUP_OPACITY: u32 = 2;
+const HEADER_FLAG_LAYER_UUID: u32 = 4;
+
+// Tileset flags.
+const TILESET_FLAG_EXTERNAL_FILE: u32 = 1;
+const TILESET_FLAG_TILES_IN_FILE: u32 = 2;
+const TILESET_FLAG_EMPTY_TILE_IS_ID_ZERO: u32 = 4;
+
+// Slice flags.
+const SLICE_FLAG_9_SLICE: u32 = 1;
+const SLICE_FLAG_PIVOT: u32 = 2;
+
+const CHUNK_OLD_PALETTE: u16 = 0x0004;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_CEL_EXTRA: u16 = 0x2006;
+const CHUNK_COLOR_PROFILE: u16 = 0x2007;
+const CHUNK_EXTERNAL_FILES: u16 = 0x2008;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_PALETTE: u16 = 0x2019;
+const CHUNK_USER_DATA: u16 = 0x2020;
+const CHUNK_SLICE: u16 = 0x2022;
+const CHUNK_TILESET: u16 = 0x2023;
+
+/// # Writing
+impl AsepriteFile {
+    /// Save the sprite in the Aseprite file format.
+    ///
+    /// Everything this crate reads from a file is written back: layers, cels,
+    /// palette, tags, slices, tilesets, external files, the color profile, and
+    /// all user data. Chunks that this crate does not interpret, such as masks
+    /// and cel extras, are kept when reading and written back unchanged. So a
+    /// file can be loaded, edited, and saved without losing data. Reading the
+    /// output with [AsepriteFile::read] returns an equivalent file. Cels are
+    /// always stored compressed, so the output is usually not byte for byte
+    /// identical to the original file.
+    ///
+    /// ```
+    /// # use asefile::AsepriteFile;
+    /// # use std::path::Path;
+    /// # let path = Path::new("./tests/data/basic-16x16.aseprite");
+    /// let ase = AsepriteFile::read_file(&path).unwrap();
+    /// let mut data = Vec::new();
+    /// ase.write_to(&mut data).unwrap();
+    ///
+    /// let copy = AsepriteFile::read(&data[..]).unwrap();
+    /// assert_eq!(copy.frame(0).image(), ase.frame(0).image());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [io::ErrorKind::InvalidInput] if a value does
+    /// not fit into its field, e.g., a name longer than 65535 bytes, or if the
+    /// file was loaded with [read_metadata](AsepriteFile::read_metadata) and
+    /// has no pixels. Also returns any error of the underlying writer.
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        if self.metadata_only {
+            return Err(invalid_input(
+                "Cannot write a file loaded without its pixels".to_owned(),
+            ));
+        }
+        let mut frames = Vec::with_capacity(self.num_frames as usize);
+        for frame in 0..self.num_frames {
+            frames.push(self.encode_frame(frame)?);
+        }
+        let size = HEADER_SIZE + frames.iter().map(Vec::len).sum::<usize>();
+
+        let mut header = ChunkWriter::new();
+        header.dword(to_u32(size, "File")?);
+        header.word(0xA5E0);
+        header.word(self.num_frames);
+        header.word(self.width);
+        header.word(self.height);
+        let (color_depth, transparent_color_index) = match self.pixel_format {
+            PixelFormat::Rgba => (32, 0),
+            PixelFormat::Grayscale => (16, 0),
+            PixelFormat::Indexed {
+                transparent_color_index,
+            } => (8, transparent_color_index),
+        };
+        header.word(color_depth);
+        let mut flags = HEADER_FLAG_LAYER_OPACITY | HEADER_FLAG_GROUP_OPACITY;
+        if self.layers_have_uuid() {
+            flags |= HEADER_FLAG_LAYER_UUID;
+        }
+        header.dword(flags);
+        // Deprecated speed field, superseded by the frame durations. Frames
+        // with a duration of 0 are read with this speed, so it has to be 0
+        // for them to keep their duration.
+        let speed = if self.frame_times.contains(&0) {
+            0
+        } else {
+            self.frame_times[0]
+        };
+        header.word(speed);
+        header.zeros(8);
+        header.byte(transparent_color_index);
+        header.zeros(3);
+        let num_colors = self.palette.as_ref().map_or(0, palette_size);
+        header.word(u16::try_from(num_colors).unwrap_or(0));
+        header.byte(self.pixel_ratio.0);
+        header.byte(self.pixel_ratio.1);
+        header.short(self.grid.x);
+        header.short(self.grid.y);
+        header.word(self.grid.width);
+        header.word(self.grid.height);
+        header.zeros(84);
+
+        w.write_all(&header.0)?;
+        for frame in &frames {
+            w.write_all(frame)?;
+        }
+        Ok(())
+    }
+
+    /// Save the sprite to a file. See [write_to](AsepriteFile::write_to).
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    // UUIDs are stored either for all layers or for none.
+    fn layers_have_uuid(&self) -> bool {
+        let layers = &self.layers.layers;
+        !layers.is_empty() && layers.iter().all(|layer| layer.uuid.is_some())
+    }
+
+    // The frame header followed by all chunks of the frame. Sprite-wide data
+    // goes into the first frame, in the same order as in files written by
+    // Aseprite.
+    fn encode_frame(&self, frame: u16) -> io::Result<Vec<u8>> {
+        let raw_chunks = self
+            .raw_chunks
+            .iter()
+            .filter(|chunk| chunk.frame == frame)
+            .collect();
+        let mut chunks = Chunks::new(&self.external_files, raw_chunks);
+        if frame == 0 {
+            if let Some(color_profile) = &self.color_profile {
+                chunks.push(CHUNK_COLOR_PROFILE, color_profile_chunk(color_profile)?);
+            }
+            if !self.external_files.map().is_empty() {
+                chunks.push(
+                    CHUNK_EXTERNAL_FILES,
+                    external_files_chunk(&self.external_files)?,
+                );
+            }
+            if let Some(palette) = &self.palette {
+                if palette_size(palette) > 0 {
+                    chunks.push(CHUNK_PALETTE, palette_chunk(palette)?);
+                }
+            }
+            // The sprite's user data follows the old palette chunk.
+            if self.palette.is_some() || self.sprite_user_data.is_some() {
+                chunks.push(CHUNK_OLD_PALETTE, old_palette_chunk(self.palette.as_ref()));
+                chunks.user_data(self.sprite_user_data.as_ref())?;
+            }
+
+            let mut tilesets: Vec<&Tileset> = self.tilesets.map().values().collect();
+            tilesets.sort_by_key(|tileset| tileset.id.0);
+            for tileset in tilesets {
+                chunks.push(CHUNK_TILESET, tileset_chunk(tileset)?);
+                let tile_user_data = &tileset.tile_user_data;
+                let num_tiles = tile_user_data
+                    .iter()
+                    .rposition(Option::is_some)
+                    .map_or(0, |last| last + 1);
+                if tileset.user_data.is_some() || num_tiles > 0 {
+                    chunks.user_data_or_empty(tileset.user_data.as_ref())?;
+                }
+                for user_data in &tile_user_data[..num_tiles] {
+                    chunks.user_data_or_empty(user_data.as_ref())?;
+                }
+            }
+
+            let has_uuid = self.layers_have_uuid();
+            for layer in &self.layers.layers {
+                chunks.push(CHUNK_LAYER, layer_chunk(layer, has_uuid)?);
+                chunks.user_data(layer.user_data.as_ref())?;
+            }
+
+            if !self.tags.is_empty() {
+                chunks.push(CHUNK_TAGS, tags_chunk(&self.tags)?);
+                // One user data chunk per tag, in order. Trailing tags without
+                // user data can be left out.
+                let num_tags = self
+                    .tags
+                    .iter()
+                    .rposition(|tag| tag.user_data().is_some())
+                    .map_or(0, |last| last + 1);
+                for tag in &self.tags[..num_tags] {
+                    chunks.user_data_or_empty(tag.user_data())?;
+                }
+            }
+
+            for slice in &self.slices {
+                chunks.push(CHUNK_SLICE, slice_chunk(slice)?);
+                chunks.user_data(slice.user_data.as_ref())?;
+            }
+        }
+
+        for (_, cel) in self.framedata.frame_cels(frame) {
+            chunks.push(CHUNK_CEL, cel_chunk(cel, &self.framedata)?);
+            if let Some(extra) = &cel.extra {
+                chunks.push(CHUNK_CEL_EXTRA, ChunkWriter(extra.clone()));
+            }
+            chunks.user_data(cel.user_data.as_ref())?;
+        }
+
+        chunks.finish();
+        let num_chunks = chunks.count;
+        let size = FRAME_HEADER_SIZE + chunks.data.len();
+        let mut w = ChunkWriter(Vec::with_capacity(size));
+        w.dword(to_u32(size, "Frame")?);
+        w.word(0xF1FA);
+        w.word(u16::try_from(num_chunks).unwrap_or(0xFFFF));
+        w.word(self.frame_times[frame as usize]);
+        w.zeros(2);
+        w.dword(num_chunks);
+        w.0.extend_from_slice(&chunks.data);
+        Ok(w.0)
+    }
+}
+
+// The chunks of one frame.
+struct Chunks<'a> {
+    data: Vec<u8>,
+    count: u32,
+    // To look up the ids of extensions with user data properties.
+    external_files: &'a ExternalFilesById,
+    // Chunks this crate does not interpret, in their original order. Each
+    // one is written when the frame has as many chunks before it as in the
+    // file it was read from.
+    raw_chunks: Vec<&'a RawChunk>,
+    next_raw_chunk: usize,
+}
+
+impl<'a> Chunks<'a> {
+    fn new(external_files: &'a ExternalFilesById, raw_chunks: Vec<&'a RawChunk>) -> Self {
+        Self {
+            data: Vec::new(),
+            count: 0,
+            external_files,
+            raw_chunks,
+            next_raw_chunk: 0,
+        }
+    }
+
+    fn push(&mut self, chunk_type: u16, chunk: ChunkWriter) {
+        while let Some(raw) = self.raw_chunks.get(self.next_raw_chunk).copied() {
+            if raw.index > self.count {
+                break;
+            }
+            self.next_raw_chunk += 1;
+            self.write(raw.chunk_type, &raw.data);
+        }
+        self.write(chunk_type, &chunk.0);
+    }
+
+    // Writes the raw chunks that go after all other chunks.
+    fn finish(&mut self) {
+        while let Some(raw) = self.raw_chunks.get(self.next_raw_chunk).copied() {
+            self.next_raw_chunk += 1;
+            self.write(raw.chunk_type, &raw.data);
+        }
+    }
+
+    fn write(&mut self, chunk_type: u16, data: &[u8]) {
+        // Chunks are smaller than the whole file, whose size is checked to
+        // fit into a DWORD.
+        let size = (CHUNK_HEADER_SIZE + data.len()) as u32;
+        self.data.extend_from_slice(&size.to_le_bytes());
+        self.data.extend_from_slice(&chunk_type.to_le_bytes());
+        self.data.extend_from_slice(data);
+        self.count += 1;
+    }
+
+    // Attaches user data to the previous chunk, if there is any.
+    fn user_data(&mut self, user_data: Option<&UserData>) -> io::Result<()> {
+        if let Some(user_data) = user_data {
+            let chunk = user_data_chunk(user_data, self.external_files)?;
+            self.push(CHUNK_USER_DATA, chunk);
+        }
+        Ok(())
+    }
+
+    // Like `user_data()` but writes an empty chunk if there is no user data.
+    // Used where user data chunks are assigned by position, e.g., for tags.
+    fn user_data_or_empty(&mut self, user_data: Option<&UserData>) -> io::Result<()> {
+        let empty = UserData {
+            text: None,
+            color: None,
+            properties: Default::default(),
+        };
+        let chunk = user_data_chunk(user_data.unwrap_or(&empty), self.external_files)?;
+        self.push(CHUNK_USER_DATA, chunk);
+        Ok(())
+    }
+}
+
+// Encodes values the same way as `AseReader` decodes them.
+struct ChunkWriter(Vec<u8>);
+
+impl ChunkWriter {
+    fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    // Writing to a Vec cannot fail.
+    fn byte(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn word(&mut self, value: u16) {
+        self.0.write_u16::<LittleEndian>(value).unwrap();
+    }
+
+    fn short(&mut self, value: i16) {
+        self.0.write_i16::<LittleEndian>(value).unwrap();
+    }
+
+    fn dword(&mut self, value: u32) {
+        self.0.write_u32::<LittleEndian>(value).unwrap();
+    }
+
+    fn long(&mut self, value: i32) {
+        self.0.write_i32::<LittleEndian>(value).unwrap();
+    }
+
+    // A 16.16 fixed point number. Values read from files convert back
+    // exactly.
+    fn fixed(&mut self, value: f64) {
+        self.long((value * 65536.0) as i32);
+    }
+
+    fn zeros(&mut self, count: usize) {
+        self.0.resize(self.0.len() + count, 0);
+    }
+
+    fn string(&mut self, value: &str) -> io::Result<()> {
+        let len = u16::try_from(value.len()).map_err(|_| {
+            invalid_input(format!(
+                "String too long: {} bytes, at most 65535 are allowed",
+                value.len()
+            ))
+        })?;
+        self.word(len);
+        self.0.extend_from_slice(value.as_bytes());
+        Ok(())
+    }
+
+    // Appends the zlib-compressed `data`.
+    fn compressed(&mut self, data: &[u8]) {
+        let mut encoder = ZlibEncoder::new(&mut self.0, Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap();
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn to_u32(size: usize, what: &str) -> io::Result<u32> {
+    u32::try_from(size).map_err(|_| invalid_input(format!("{} too large: {} bytes", what, size)))
+}
+
+fn color_profile_chunk(color_profile: &ColorProfile) -> io::Result<ChunkWriter> {
+    let mut w = ChunkWriter::new();
+    w.word(match color_profile.profile_type() {
+        ColorProfileType::None => 0,
+        ColorProfileType::Srgb => 1,
+        ColorProfileType::Icc => 2,
+    });
+    w.word(color_profile.fixed_gamma().is_some() as u16);
+    w.fixed(color_profile.fixed_gamma().unwrap_or(0.0));
+    w.zeros(8);
+    if let Some(icc_profile) = color_profile.icc_profile() {
+        w.dword(to_u32(icc_profile.len(), "ICC profile")?);
+        w.0.extend_from_slice(icc_profile);
+    }
+    Ok(w)
+}
+
+fn external_files_chunk(external_files: &ExternalFilesById) -> io::Result<ChunkWriter> {
+    let mut external_files: Vec<&ExternalFile> = external_files.map().values().collect();
+    external_files.sort_by_key(|external_file| *external_file.id().value());
+    let mut w = ChunkWriter::new();
+    w.dword(external_files.len() as u32);
+    w.zeros(8);
+    for external_file in external_files {
+        w.dword(*external_file.id().value());
+        w.byte(external_file.file_type);
+        w.zeros(7);
+        w.string(external_file.name())?;
+    }
+    Ok(w)
+}
+
+// Number of entries needed to store the palette, including gaps.
+fn palette_size(palette: &ColorPalette) -> usize {
+    palette
+        .entries
+        .keys()
+        .max()
+        .map_or(0, |&max_id| max_id as usize + 1)
+}
+
+// Gaps between palette entries are filled with opaque black, like in
+// palettes that were resized in Aseprite.
+fn palette_chunk(palette: &ColorPalette) -> io::Result<ChunkWriter> {
+    let size = palette_size(palette) as u32;
+    let mut w = ChunkWriter::new();
+    w.dword(size);
+    w.dword(0);
+    w.dword(size - 1);
+    w.zeros(8);
+    for id in 0..size {
+        match palette.entries.get(&id) {
+            Some(entry) => {
+                w.word(entry.name.is_some() as u16);
+                w.0.extend_from_slice(&entry.rgba8);
+                if let Some(name) = &entry.name {
+                    w.string(name)?;
+                }
+            }
+            None => {
+                w.word(0);
+                w.0.extend_from_slice(&[0, 0, 0, 255]);
+            }
+        }
+    }
+    Ok(w)
+}
+
+// Older readers only understand this chunk. It cannot store more than 256
+// colors or alpha values, so larger palettes are left out; readers that
+// know the new palette chunk ignore this one.
+fn old_palette_chunk(palette: Option<&ColorPalette>) -> ChunkWriter {
+    let mut w = ChunkWriter::new();
+    let size = palette.map_or(0, palette_size);
+    match palette {
+        Some(palette) if (1..=256).contains(&size) => {
+            // One packet with all colors. A count of 0 means 256.
+            w.word(1);
+            w.byte(0);
+            w.byte(size as u8);
+            for id in 0..size as u32 {
+                let rgb = palette.entries.get(&id).map_or([0, 0, 0], |entry| {
+                    [entry.rgba8[0], entry.rgba8[1], entry.rgba8[2]]
+                });
+                w.0.extend_from_slice(&rgb);
+            }
+        }
+        _ => w.word(0),
+    }
+    w
+}
+
+fn user_data_chunk(
+    user_data: &UserData,
+    external_files: &ExternalFilesById,
+) -> io::Result<ChunkWriter> {
+    let mut w = ChunkWriter::new();
+    let flags = user_data.text.is_some() as u32
+        | (user_data.color.is_some() as u32) << 1
+        | (!user_data.properties.is_empty() as u32) << 2;
+    w.dword(flags);
+    if let Some(text) = &user_data.text {
+        w.string(text)?;
+    }
+    if let Some(color) = &user_data.color {
+        w.0.extend_from_slice(&color.0);
+    }
+    if !user_data.properties.is_empty() {
+        let start = w.0.len();
+        // The size is patched in below.
+        w.dword(0);
+        w.dword(to_u32(user_data.properties.len(), "Property maps")?);
+        for (extension, properties) in &user_data.properties {
+            w.dword(extension_id(extension, external_files)?);
+            write_properties(&mut w, properties)?;
+        }
+        let size = to_u32(w.0.len() - start, "User data properties")?;
+        w.0[start..start + 4].copy_from_slice(&size.to_le_bytes());
+    }
+    Ok(w)
+}
+
+// The key of an extension's property map: the id of its entry in the
+// external files, or zero for user properties.
+fn extension_id(extension: &str, external_files: &ExternalFilesById) -> io::Result<u32> {
+    if extension.is_empty() {
+        return Ok(0);
+    }
+    external_files
+        .map()
+        .values()
+        .find(|external_file| {
+            external_file.file_type() == ExternalFileType::PropertiesExtension
+                && external_file.name() == extension
+        })
+        .map(|external_file| *external_file.id().value())
+        .ok_or_else(|| {
+            invalid_input(format!(
+                "Properties of extension {:?} without an external file entry",
+                extension
+            ))
+        })
+}
+
+// Properties in the encoding of files, also used by the cache.
+#[cfg(feature = "cache")]
+pub(crate) fn encode_properties(properties: &Properties) -> io::Result<Vec<u8>> {
+    let mut w = ChunkWriter::new();
+    write_properties(&mut w, properties)?;
+    Ok(w.0)
+}
+
+fn write_properties(w: &mut ChunkWriter, properties: &Properties) -> io::Result<()> {
+    w.dword(to_u32(properties.len(), "Properties")?);
+    for (name, value) in properties {
+        w.string(name)?;
+        w.word(value.type_code());
+        write_property_value(w, value)?;
+    }
+    Ok(())
+}
+
+fn write_property_value(w: &mut ChunkWriter, value: &PropertyValue) -> io::Result<()> {
+    match value {
+        PropertyValue::Bool(value) => w.byte(*value as u8),
+        PropertyValue::I8(value) => w.byte(*value as u8),
+        PropertyValue::U8(value) => w.byte(*value),
+        PropertyValue::I16(value) => w.short(*value),
+        PropertyValue::U16(value) => w.word(*value),
+        PropertyValue::I32(value) => w.long(*value),
+        PropertyValue::U32(value) => w.dword(*value),
+        PropertyValue::I64(value) => w.0.extend_from_slice(&value.to_le_bytes()),
+        PropertyValue::U64(value) => w.0.extend_from_slice(&value.to_le_bytes()),
+        PropertyValue::Fixed(value) => w.fixed(*value),
+        PropertyValue::F32(value) => w.dword(value.to_bits()),
+        PropertyValue::F64(value) => w.0.extend_from_slice(&value.to_bits().to_le_bytes()),
+        PropertyValue::String(value) => w.string(value)?,
+        PropertyValue::Point { x, y } => {
+            w.long(*x);
+            w.long(*y);
+        }
+        PropertyValue::Size { width, height } => {
+            w.long(*width);
+            w.long(*height);
+        }
+        PropertyValue::Rect {
+            x,
+            y,
+            width,
+            height,
+        } => {
+            w.long(*x);
+            w.long(*y);
+            w.long(*width);
+            w.long(*height);
+        }
+        PropertyValue::Vector(elements) => {
+            w.dword(to_u32(elements.len(), "Property vector")?);
+            // Elements of the same type share it, otherwise each element
+            // is preceded by its own type.
+            let element_type = elements.first().map_or(0, PropertyValue::type_code);
+            let uniform = elements
+                .iter()
+                .all(|element| element.type_code() == element_type);
+            if uniform {
+                w.word(element_type);
+            } else {
+                w.word(0);
+            }
+            for element in elements {
+                if !uniform {
+                    w.word(element.type_code());
+                }
+                write_property_value(w, element)?;
+            }
+        }
+        PropertyValue::Map(properties) => write_properties(w, properties)?,
+        PropertyValue::Uuid(uuid) => w.0.extend_from_slice(uuid),
+    }
+    Ok(())
+}
+
+fn tileset_chunk(tileset: &Tileset) -> io::Result<ChunkWriter> {
+    let mut flags = 0;
+    if tileset.external_file.is_some() {
+        flags |= TILESET_FLAG_EXTERNAL_FILE;
+    }
+    if tileset.pixels.is_some() {
+        flags |= TILESET_FLAG_TILES_IN_FILE;
+    }
+    if tileset.empty_tile_is_id_zero {
+        flags |= TILESET_FLAG_EMPTY_TILE_IS_ID_ZERO;
+    }
+    let mut w = ChunkWriter::new();
+    w.dword(tileset.id.0);
+    w.dword(flags);
+    w.dword(tileset.tile_count);
+    w.word(tileset.tile_size.width);
+    w.word(tileset.tile_size.height);
+    w.short(tileset.base_index);
+    w.zeros(14);
+    w.string(&tileset.name)?;
+    if let Some(reference) = &tileset.external_file {
+        w.dword(*reference.external_file_id.value());
+        w.dword(reference.tileset_id.0);
+    }
+    if let Some(pixels) = &tileset.pixels {
+        let mut data = ChunkWriter::new();
+        data.compressed(&pixels.all().to_bytes());
+        w.dword(to_u32(data.0.len(), "Tileset")?);
+        w.0.extend_from_slice(&data.0);
+    }
+    Ok(w)
+}
+
+fn layer_chunk(layer: &LayerData, has_uuid: bool) -> io::Result<ChunkWriter> {
+    let mut w = ChunkWriter::new();
+    w.word(layer.flags.bits() as u16);
+    let layer_type = match layer.layer_type {
+        LayerType::Image => 0,
+        LayerType::Group => 1,
+        LayerType::Tilemap(_) => 2,
+    };
+    w.word(layer_type);
+    w.word(layer.child_level);
+    // Default width and height, ignored by Aseprite.
+    w.word(0);
+    w.word(0);
+    w.word(layer.blend_mode as u16);
+    w.byte(layer.opacity);
+    w.zeros(3);
+    w.string(&layer.name)?;
+    if let LayerType::Tilemap(TilesetId(tileset_id)) = layer.layer_type {
+        w.dword(tileset_id);
+    }
+    if has_uuid {
+        w.0.extend_from_slice(&layer.uuid.unwrap_or_default());
+    }
+    Ok(w)
+}
+
+fn tags_chunk(tags: &[Tag]) -> io::Result<ChunkWriter> {
+    let mut w = ChunkWriter::new();
+    let num_tags = u16::try_from(tags.len())
+        .map_err(|_| invalid_input(format!("Too many tags: {}", tags.len())))?;
+    w.word(num_tags);
+    w.zeros(8);
+    for tag in tags {
+        w.word(tag.from_frame() as u16);
+        w.word(tag.to_frame() as u16);
+        w.byte(tag.animation_direction() as u8);
+        w.word(tag.repeat());
+        w.zeros(6);
+        // Deprecated tag color, superseded by the user data color.
+        w.zeros(4);
+        w.string(tag.name())?;
+    }
+    Ok(w)
+}
+
+// The flags are shared by all keys, so keys without 9-slice or pivot data
+// get zeros if any other key has them.
+fn slice_chunk(slice: &Slice) -> io::Result<ChunkWriter> {
+    let mut flags = 0;
+    if slice.keys.iter().any(|key| key.slice9.is_some()) {
+        flags |= SLICE_FLAG_9_SLICE;
+    }
+    if slice.keys.iter().any(|key| key.pivot.is_some()) {
+        flags |= SLICE_FLAG_PIVOT;
+    }
+    let mut w = ChunkWriter::new();
+    w.dword(slice.keys.len() as u32);
+    w.dword(flags);
+    w.dword(0);
+    w.string(&slice.name)?;
+    for key in &slice.keys {
+        w.dword(key.from_frame);
+        w.long(key.origin.x);
+        w.long(key.origin.y);
+        w.dword(key.size.width);
+        w.dword(key.size.height);
+        if flags & SLICE_FLAG_9_SLICE != 0 {
+            let slice9 = key.slice9.as_ref();
+            w.long(slice9.map_or(0, |s| s.center_x));
+            w.long(slice9.map_or(0, |s| s.center_y));
+            w.dword(slice9.map_or(0, |s| s.center_width));
+            w.dword(slice9.map_or(0, |s| s.center_height));
+        }
+        if flags & SLICE_FLAG_PIVOT != 0 {
+            let pivot = key.pivot.as_ref();
+            w.long(pivot.map_or(0, |p| p.x));
+            w.long(pivot.map_or(0, |p| p.y));
+        }
+    }
+    Ok(w)
+}
+
+fn cel_chunk(cel: &RawCel, framedata: &CelsData) -> io::Result<ChunkWriter> {
+    let mut w = ChunkWriter::new();
+    w.word(cel.data.layer_index);
+    w.short(cel.data.x);
+    w.short(cel.data.y);
+    w.byte(cel.data.opacity);
+    let z_index = cel.data.z_index;
+    match &cel.content {
+        CelContent::Raw(ImageContent { size, pixels }) => {
+            w.word(2);
+            w.short(z_index);
+            w.zeros(5);
+            w.word(size.width);
+            w.word(size.height);
+            w.compressed(&framedata.pixels(pixels).to_bytes());
+        }
+        CelContent::Linked(frame) => {
+            w.word(1);
+            w.short(z_index);
+            w.zeros(5);
+            w.word(*frame);
+        }
+        CelContent::Tilemap(tilemap) => {
+            w.word(3);
+            w.short(z_index);
+            w.zeros(5);
+            w.word(tilemap.width);
+            w.word(tilemap.height);
+            w.word(32);
+            let header = &tilemap.bitmask_header;
+            w.dword(header.tile_id);
+            w.dword(header.x_flip);
+            w.dword(header.y_flip);
+            w.dword(header.rotate_90cw);
+            w.zeros(10);
+            let mut tiles = ChunkWriter(Vec::with_capacity(tilemap.tiles.0.len() * 4));
+            for tile in tilemap.tiles.iter() {
+                let mut value = tile.id.0 & header.tile_id;
+                if tile.flip_x {
+                    value |= header.x_flip;
+                }
+                if tile.flip_y {
+                    value |= header.y_flip;
+                }
+                if tile.rotate_90cw {
+                    value |= header.rotate_90cw;
+                }
+                tiles.dword(value);
+            }
+            w.compressed(&tiles.0);
+        }
+    }
+    Ok(w)
+}
@@ -0,0 +1,289 @@
+// Reads exported animations back with independent decoders, so that the
+// encoders are not only checked against their own understanding of the
+// format.
+
+#![cfg(all(feature = "image", any(feature = "gif", feature = "webp")))]
+
+use asefile::{AnimationDirection, AsepriteFile, AsepriteFileBuilder};
+use image::{Rgba, RgbaImage};
+use std::path::PathBuf;
+
+fn load_test_file(name: &str) -> AsepriteFile {
+    let mut path = PathBuf::new();
+    path.push("tests");
+    path.push("data");
+    path.push(format!("{}.aseprite", name));
+    AsepriteFile::read_file(&path).unwrap()
+}
+
+// Four frames of a gradient with far more than 256 colors on a noisy alpha
+// channel, with the durations 5, 35, 65 and 95 ms. Tag 0 plays frames 1 to
+// 3 in ping-pong order, tag 1 plays frames 0 and 1 in reverse, twice.
+fn noise_animation() -> (AsepriteFile, Vec<RgbaImage>) {
+    use rand::Rng;
+
+    let mut rng = rand::thread_rng();
+    let mut builder = AsepriteFileBuilder::new(64, 64);
+    let layer = builder.add_layer("Layer");
+    let mut images = Vec::new();
+    for i in 0..4 {
+        let image = RgbaImage::from_fn(64, 64, |x, y| {
+            let alpha = if rng.gen::<bool>() { 255 } else { rng.gen() };
+            Rgba([x as u8 * 4, y as u8 * 4, i * 60, alpha])
+        });
+        let frame = builder.add_frame(5 + 30 * i as u16);
+        builder.add_cel(frame, layer, (0, 0), &image);
+        images.push(image);
+    }
+    builder.add_tag("Bounce", 1, 3, AnimationDirection::PingPong);
+    builder.add_tag("Twice", 0, 1, AnimationDirection::Reverse);
+    let f = builder.build().unwrap();
+    (with_tag_repeat(&f, "Twice", 2), images)
+}
+
+// The builder only creates tags that repeat forever. Patch the repeat count
+// in the tags chunk of the written file instead.
+fn with_tag_repeat(f: &AsepriteFile, tag_name: &str, repeat: u16) -> AsepriteFile {
+    let mut data = Vec::new();
+    f.write_to(&mut data).unwrap();
+    let mut name = (tag_name.len() as u16).to_le_bytes().to_vec();
+    name.extend_from_slice(tag_name.as_bytes());
+    let start = data
+        .windows(name.len())
+        .position(|window| window == name.as_slice())
+        .unwrap();
+    // The name follows the repeat count, 6 reserved bytes, and the color.
+    data[start - 12..start - 10].copy_from_slice(&repeat.to_le_bytes());
+    let f = AsepriteFile::read(data.as_slice()).unwrap();
+    assert_eq!(f.tag_by_name(tag_name).unwrap().repeat(), repeat);
+    f
+}
+
+#[cfg(feature = "gif")]
+mod gif_export {
+    use super::*;
+    use asefile::exporters::gif::{self, GifOptions};
+
+    struct Animation {
+        width: u16,
+        height: u16,
+        palette_len: usize,
+        repeat: ::gif::Repeat,
+        // Images and delays in hundredths of a second.
+        frames: Vec<(RgbaImage, u16)>,
+    }
+
+    fn decode(data: &[u8]) -> Animation {
+        let mut options = ::gif::DecodeOptions::new();
+        options.set_color_output(::gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(data).unwrap();
+        let (width, height) = (decoder.width(), decoder.height());
+        let palette_len = decoder.global_palette().unwrap().len() / 3;
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!((frame.left, frame.top), (0, 0));
+            assert_eq!((frame.width, frame.height), (width, height));
+            let image =
+                RgbaImage::from_raw(width as u32, height as u32, frame.buffer.to_vec()).unwrap();
+            frames.push((image, frame.delay));
+        }
+        Animation {
+            width,
+            height,
+            palette_len,
+            repeat: decoder.repeat(),
+            frames,
+        }
+    }
+
+    // GIF only supports fully transparent or fully opaque pixels. The
+    // transparent palette entry is black.
+    fn binary_alpha(image: &RgbaImage) -> RgbaImage {
+        let mut image = image.clone();
+        for pixel in image.pixels_mut() {
+            *pixel = if pixel.0[3] < 128 {
+                Rgba([0, 0, 0, 0])
+            } else {
+                Rgba([pixel.0[0], pixel.0[1], pixel.0[2], 255])
+            };
+        }
+        image
+    }
+
+    #[test]
+    fn gif_animation() {
+        for name in &["layers_and_tags", "transparency", "linked_cels", "indexed"] {
+            let f = load_test_file(name);
+            let mut data = Vec::new();
+            gif::write(&f, &mut data, &GifOptions::new()).unwrap();
+            let animation = decode(&data);
+            assert_eq!(animation.repeat, ::gif::Repeat::Infinite);
+            assert_eq!(
+                (animation.width, animation.height),
+                (f.width() as u16, f.height() as u16)
+            );
+            assert_eq!(animation.frames.len(), f.num_frames() as usize);
+            for (index, (image, delay)) in animation.frames.iter().enumerate() {
+                let frame = f.frame(index as u32);
+                assert_eq!(*delay as u32, ((frame.duration() + 5) / 10).max(2));
+                assert!(
+                    *image == binary_alpha(&frame.image()),
+                    "{}: frame {} differs",
+                    name,
+                    index
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gif_quantized_animation() {
+        let (f, images) = noise_animation();
+        let mut data = Vec::new();
+        gif::write(&f, &mut data, &GifOptions::new()).unwrap();
+        let animation = decode(&data);
+        assert_eq!(animation.palette_len, 256);
+        let delays: Vec<_> = animation.frames.iter().map(|(_, delay)| *delay).collect();
+        assert_eq!(delays, vec![2, 4, 7, 10]);
+        for ((image, _), expected) in animation.frames.iter().zip(&images) {
+            let mut total_error = 0;
+            for (actual, expected) in image.pixels().zip(binary_alpha(expected).pixels()) {
+                assert_eq!(actual.0[3], expected.0[3]);
+                for channel in 0..3 {
+                    total_error += (actual.0[channel] as i32 - expected.0[channel] as i32).abs();
+                }
+            }
+            // 256 colors are spaced about 36 apart in this color range.
+            let mean_error = total_error as f64 / (64.0 * 64.0 * 3.0);
+            assert!(mean_error < 16.0, "mean error {}", mean_error);
+        }
+    }
+
+    #[test]
+    fn gif_tag_animation() {
+        let (f, _) = noise_animation();
+        let mut data = Vec::new();
+        gif::write(&f, &mut data, &GifOptions::new().tag(0)).unwrap();
+        let animation = decode(&data);
+        assert_eq!(animation.repeat, ::gif::Repeat::Infinite);
+        let delays: Vec<_> = animation.frames.iter().map(|(_, delay)| *delay).collect();
+        assert_eq!(delays, vec![4, 7, 10, 7]);
+        assert!(animation.frames[1].0 == animation.frames[3].0);
+
+        let mut data = Vec::new();
+        gif::write(&f, &mut data, &GifOptions::new().tag(1)).unwrap();
+        let animation = decode(&data);
+        assert_eq!(animation.repeat, ::gif::Repeat::Finite(0));
+        let delays: Vec<_> = animation.frames.iter().map(|(_, delay)| *delay).collect();
+        assert_eq!(delays, vec![4, 2, 4, 2]);
+
+        let err = gif::write(&f, Vec::new(), &GifOptions::new().tag(2)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
+
+#[cfg(feature = "webp")]
+mod webp_export {
+    use super::*;
+    use asefile::exporters::webp::{self, WebpOptions};
+    use image_webp::{LoopCount, WebPDecoder};
+    use std::io::Cursor;
+    use std::num::NonZeroU16;
+
+    struct Animation {
+        width: u32,
+        height: u32,
+        loop_count: LoopCount,
+        // Images and durations in milliseconds.
+        frames: Vec<(RgbaImage, u32)>,
+    }
+
+    fn decode(data: &[u8]) -> Animation {
+        let mut decoder = WebPDecoder::new(Cursor::new(data)).unwrap();
+        assert!(decoder.is_animated());
+        assert!(decoder.has_alpha());
+        let (width, height) = decoder.dimensions();
+        let mut frames = Vec::new();
+        for _ in 0..decoder.num_frames() {
+            let mut buffer = vec![0; decoder.output_buffer_size().unwrap()];
+            let duration = decoder.read_frame(&mut buffer).unwrap();
+            frames.push((
+                RgbaImage::from_raw(width, height, buffer).unwrap(),
+                duration,
+            ));
+        }
+        Animation {
+            width,
+            height,
+            loop_count: decoder.loop_count(),
+            frames,
+        }
+    }
+
+    #[test]
+    fn webp_animation() {
+        for name in &["layers_and_tags", "transparency", "linked_cels", "big"] {
+            let f = load_test_file(name);
+            let mut data = Vec::new();
+            webp::write(&f, &mut data, &WebpOptions::new()).unwrap();
+            let animation = decode(&data);
+            assert_eq!(animation.loop_count, LoopCount::Forever);
+            assert_eq!(
+                (animation.width, animation.height),
+                (f.width() as u32, f.height() as u32)
+            );
+            assert_eq!(animation.frames.len(), f.num_frames() as usize);
+            for (index, (image, duration)) in animation.frames.iter().enumerate() {
+                let frame = f.frame(index as u32);
+                assert_eq!(*duration, frame.duration());
+                assert!(*image == frame.image(), "{}: frame {} differs", name, index);
+            }
+        }
+    }
+
+    #[test]
+    fn webp_noise_animation() {
+        // Noise exercises codes with many symbols.
+        let (f, images) = noise_animation();
+        let mut data = Vec::new();
+        webp::write(&f, &mut data, &WebpOptions::new()).unwrap();
+        let animation = decode(&data);
+        assert_eq!(animation.frames.len(), images.len());
+        for (index, ((image, duration), expected)) in
+            animation.frames.iter().zip(&images).enumerate()
+        {
+            assert_eq!(*duration, f.frame(index as u32).duration());
+            assert!(image == expected, "frame {} differs", index);
+        }
+    }
+
+    #[test]
+    fn webp_tag_animation() {
+        let (f, images) = noise_animation();
+        let mut data = Vec::new();
+        webp::write(&f, &mut data, &WebpOptions::new().tag(0)).unwrap();
+        let animation = decode(&data);
+        assert_eq!(animation.loop_count, LoopCount::Forever);
+        let frames: Vec<_> = animation.frames.iter().map(|(image, _)| image).collect();
+        assert_eq!(frames, vec![&images[1], &images[2], &images[3], &images[2]]);
+        let durations: Vec<_> = animation
+            .frames
+            .iter()
+            .map(|(_, duration)| *duration)
+            .collect();
+        assert_eq!(durations, vec![35, 65, 95, 65]);
+
+        let mut data = Vec::new();
+        webp::write(&f, &mut data, &WebpOptions::new().tag(1)).unwrap();
+        let animation = decode(&data);
+        assert_eq!(
+            animation.loop_count,
+            LoopCount::Times(NonZeroU16::new(1).unwrap())
+        );
+        let frames: Vec<_> = animation.frames.iter().map(|(image, _)| image).collect();
+        assert_eq!(frames, vec![&images[1], &images[0], &images[1], &images[0]]);
+
+        let err = webp::write(&f, Vec::new(), &WebpOptions::new().tag(2)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,174 @@
+// Approximates memory use with a counting global allocator. This lives in its
+// own test binary so the allocator does not slow down the other tests. Counts
+// are kept per thread, so tests running in parallel do not affect each other.
+
+#![cfg(feature = "image")]
+
+use asefile::{AsepriteFile, AsepriteFileBuilder, ImageOptions};
+use image::{Rgba, RgbaImage};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAllocator;
+
+thread_local! {
+    static CURRENT: Cell<usize> = const { Cell::new(0) };
+    static PEAK: Cell<usize> = const { Cell::new(0) };
+    static LIVE_ALLOCATIONS: Cell<isize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = CURRENT.with(|c| {
+                c.set(c.get().wrapping_add(layout.size()));
+                c.get()
+            });
+            PEAK.with(|p| p.set(p.get().max(current)));
+            LIVE_ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.with(|c| c.set(c.get().wrapping_sub(layout.size())));
+        LIVE_ALLOCATIONS.with(|n| n.set(n.get() - 1));
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Runs `f` and returns by how much the peak exceeded the memory in use before.
+fn peak_increase<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = CURRENT.with(Cell::get);
+    PEAK.with(|p| p.set(before));
+    let result = f();
+    (result, PEAK.with(Cell::get).wrapping_sub(before))
+}
+
+// Runs `f` and returns how many more allocations are alive afterwards.
+fn live_allocations_increase<T>(f: impl FnOnce() -> T) -> (T, isize) {
+    let before = LIVE_ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, LIVE_ALLOCATIONS.with(Cell::get) - before)
+}
+
+const SIZE: u32 = 128;
+const FRAMES: u32 = 32;
+const FRAME_BYTES: usize = (SIZE * SIZE * 4) as usize;
+
+fn sprite() -> AsepriteFile {
+    let mut builder = AsepriteFileBuilder::new(SIZE as u16, SIZE as u16);
+    let layer = builder.add_layer("Layer");
+    for i in 0..FRAMES {
+        let frame = builder.add_frame(100);
+        let image = RgbaImage::from_pixel(SIZE, SIZE, Rgba([i as u8, 0, 0, 255]));
+        builder.add_cel(frame, layer, (0, 0), &image);
+    }
+    builder.build().unwrap()
+}
+
+#[test]
+fn into_frame_images_frees_cels() {
+    let cel_bytes = FRAME_BYTES * FRAMES as usize;
+
+    // Without freeing cels the peak would grow by `cel_bytes`, as all images
+    // would exist alongside all cels.
+    let f = sprite();
+    let (images, increase) = peak_increase(|| f.into_frame_images(ImageOptions::default()));
+    assert_eq!(images.len(), FRAMES as usize);
+    assert!(
+        increase < 4 * FRAME_BYTES,
+        "peak grew by {} bytes, cels use {}",
+        increase,
+        cel_bytes
+    );
+    drop(images);
+
+    let f = sprite();
+    let (_, increase) = peak_increase(|| {
+        for (i, image) in f
+            .into_frame_images_iter(ImageOptions::default())
+            .enumerate()
+        {
+            assert_eq!(image.get_pixel(0, 0).0[0], i as u8);
+        }
+    });
+    assert!(
+        increase < 2 * FRAME_BYTES,
+        "peak grew by {} bytes",
+        increase
+    );
+}
+
+fn chunk(out: &mut Vec<u8>, chunk_type: u16, data: &[u8]) {
+    out.extend((data.len() as u32 + 6).to_le_bytes());
+    out.extend(chunk_type.to_le_bytes());
+    out.extend(data);
+}
+
+// An RGBA file where every frame has one uncompressed 4x4 cel per layer.
+fn file_with_many_cels(num_frames: u16, num_layers: u16) -> Vec<u8> {
+    let mut frames = Vec::new();
+    for frame in 0..num_frames {
+        let mut chunks = Vec::new();
+        let mut num_chunks = 0;
+        if frame == 0 {
+            for _ in 0..num_layers {
+                let mut layer = vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 0, 0, 0];
+                layer.extend([1, 0, b'L']);
+                chunk(&mut chunks, 0x2004, &layer);
+                num_chunks += 1;
+            }
+        }
+        for layer in 0..num_layers {
+            let mut cel = Vec::new();
+            cel.extend(layer.to_le_bytes());
+            cel.extend([0; 4]); // position
+            cel.push(255); // opacity
+            cel.extend([0; 9]); // raw cel, reserved
+            cel.extend([4, 0, 4, 0]);
+            cel.extend([frame as u8; 64]);
+            chunk(&mut chunks, 0x2005, &cel);
+            num_chunks += 1;
+        }
+        frames.extend((chunks.len() as u32 + 16).to_le_bytes());
+        frames.extend(0xF1FA_u16.to_le_bytes());
+        frames.extend((num_chunks as u16).to_le_bytes());
+        frames.extend(100_u16.to_le_bytes());
+        frames.extend([0; 2]);
+        frames.extend((num_chunks as u32).to_le_bytes());
+        frames.extend(chunks);
+    }
+    let mut file = Vec::new();
+    file.extend((frames.len() as u32 + 128).to_le_bytes());
+    file.extend(0xA5E0_u16.to_le_bytes());
+    file.extend(num_frames.to_le_bytes());
+    file.extend([16, 0, 16, 0, 32, 0]); // size, color depth
+    file.extend([1, 0, 0, 0, 100, 0]); // flags, speed
+    file.extend([0; 12]);
+    file.extend([0, 0, 1, 1]); // number of colors, pixel ratio
+    file.extend([0, 0, 0, 0, 16, 0, 16, 0]); // grid
+    file.extend([0; 84]);
+    file.extend(frames);
+    file
+}
+
+#[test]
+fn allocations_do_not_scale_with_cels() {
+    let (frames, layers) = (64, 8);
+    let num_cels = frames as isize * layers as isize;
+    let bytes = file_with_many_cels(frames, layers);
+
+    let (f, live) = live_allocations_increase(|| AsepriteFile::read(&bytes[..]).unwrap());
+    assert_eq!(f.frame(63).layer(7).image().get_pixel(0, 0).0, [63; 4]);
+    assert!(
+        live < num_cels / 2,
+        "{} allocations for {} cels",
+        live,
+        num_cels
+    );
+}